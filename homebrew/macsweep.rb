@@ -10,6 +10,10 @@ class Macsweep < Formula
 
   def install
     system "cargo", "install", *std_cargo_args
+
+    # Man pages are generated from the CLI definitions
+    system bin/"macsweep", "generate-man", buildpath/"man"
+    man1.install Dir[buildpath/"man/*.1"]
   end
 
   def caveats
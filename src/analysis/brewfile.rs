@@ -0,0 +1,250 @@
+// Brewfiles read for `macsweep compare --brewfile` and `clean --not-in-brewfile`, and how
+// the installed Homebrew packages differ from one
+use crate::analysis::recommendations::{CleanupAction, Recommendation, RecommendationSeverity};
+use crate::scanner::{Package, PackageSource};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+
+/// What a Brewfile line installs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    Tap,
+    Brew,
+    Cask,
+    /// A Mac App Store app, installed with `mas`
+    Mas,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrewfileEntry {
+    pub kind: EntryKind,
+    /// As written, e.g. `hashicorp/tap/terraform` or, for `mas`, the app's name
+    pub name: String,
+    /// The App Store id of a `mas` line
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Brewfile {
+    pub entries: Vec<BrewfileEntry>,
+}
+
+impl Brewfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Cannot read Brewfile {}", path.display()))?;
+        Ok(Self::parse(&text))
+    }
+
+    /// Read the `tap`, `brew`, `cask` and `mas` lines, ignoring their options (`args:`,
+    /// `greedy:`, `restart_service:` and so on). Other lines, such as `vscode` extensions,
+    /// comments and Ruby conditionals, are skipped.
+    pub fn parse(text: &str) -> Self {
+        let entries = text.lines().filter_map(parse_line).collect();
+        Self { entries }
+    }
+
+    fn contains(&self, kind: EntryKind, package: &str) -> bool {
+        self.entries.iter().any(|e| e.kind == kind && same_package(&e.name, package))
+    }
+}
+
+fn parse_line(line: &str) -> Option<BrewfileEntry> {
+    let line = line.trim_start();
+    let (keyword, rest) = line.split_once(|c: char| c.is_whitespace() || c == '(')?;
+    let kind = match keyword {
+        "tap" => EntryKind::Tap,
+        "brew" => EntryKind::Brew,
+        "cask" => EntryKind::Cask,
+        "mas" => EntryKind::Mas,
+        _ => return None,
+    };
+    let (name, rest) = quoted(rest.trim_start())?;
+    let id = if kind == EntryKind::Mas {
+        let (_, id) = rest.split_once("id:")?;
+        Some(id.trim().trim_end_matches(')').trim().parse().ok()?)
+    } else {
+        None
+    };
+    Some(BrewfileEntry { kind, name, id })
+}
+
+/// The string literal `text` starts with, and what follows it
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut value = String::new();
+    let mut chars = text[1..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+            c if c == quote => return Some((value, &text[1 + i + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// Brewfiles may name a formula by its tap (`hashicorp/tap/terraform`) where brew records
+/// just `terraform`, or the other way round
+fn same_package(brewfile_name: &str, installed: &str) -> bool {
+    let short = |name: &str| name.rsplit('/').next().unwrap_or(name).to_lowercase();
+    brewfile_name.eq_ignore_ascii_case(installed) || short(brewfile_name) == short(installed)
+}
+
+/// How the installed Homebrew packages and App Store apps differ from a Brewfile
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BrewfileComparison<'a> {
+    /// Installed on request but not in the Brewfile: candidates for removal
+    pub extra: Vec<&'a Package>,
+    /// In the Brewfile but not installed
+    pub missing: Vec<BrewfileEntry>,
+    /// Brewfile entries that are installed
+    pub matched: usize,
+}
+
+impl BrewfileComparison<'_> {
+    /// The extra packages as removals for `clean --not-in-brewfile`. They are only Review: a
+    /// package missing from the Brewfile may just not have been added to it yet.
+    pub fn to_recommendations(&self) -> Vec<Recommendation> {
+        self.extra
+            .iter()
+            .map(|package| Recommendation {
+                package: package.name.clone(),
+                source: Some(package.source.clone()),
+                reason: "Not in the Brewfile".to_string(),
+                severity: RecommendationSeverity::Review,
+                size_recoverable: package.size_bytes.unwrap_or(0),
+                action: CleanupAction::Remove,
+            })
+            .collect()
+    }
+}
+
+/// Compare the formulae, casks and App Store apps in `packages` with `brewfile`. Formulae
+/// installed as dependencies are never extra, since the Brewfile's formulae bring them in;
+/// taps aren't recorded, so they're not compared.
+pub fn compare<'a>(brewfile: &Brewfile, packages: &'a [Package]) -> BrewfileComparison<'a> {
+    let kind_of = |package: &Package| match package.source {
+        PackageSource::Homebrew => Some(EntryKind::Brew),
+        PackageSource::HomebrewCask => Some(EntryKind::Cask),
+        PackageSource::MacAppStore => Some(EntryKind::Mas),
+        _ => None,
+    };
+    let installed = |entry: &BrewfileEntry| match entry.kind {
+        // App Store apps may have been scanned as applications
+        EntryKind::Mas => packages.iter().any(|p| {
+            matches!(p.source, PackageSource::MacAppStore | PackageSource::Applications) && p.name.eq_ignore_ascii_case(&entry.name)
+        }),
+        kind => packages.iter().any(|p| kind_of(p) == Some(kind) && same_package(&entry.name, &p.name)),
+    };
+
+    let mut comparison = BrewfileComparison::default();
+    for entry in brewfile.entries.iter().filter(|e| e.kind != EntryKind::Tap) {
+        if installed(entry) {
+            comparison.matched += 1;
+        } else {
+            comparison.missing.push(entry.clone());
+        }
+    }
+    comparison.extra = packages
+        .iter()
+        .filter(|p| !p.is_dependency)
+        .filter(|p| kind_of(p).is_some_and(|kind| !brewfile.contains(kind, &p.name)))
+        .collect();
+    comparison.extra.sort_by(|a, b| (a.source.as_str(), &a.name).cmp(&(b.source.as_str(), &b.name)));
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BREWFILE: &str = r#"
+tap "homebrew/bundle"
+tap "hashicorp/tap"
+# Command line tools
+brew "jq"
+brew "hashicorp/tap/terraform"
+brew "postgresql@16", restart_service: :changed
+brew("wget")
+brew 'ffmpeg', args: ["with-fdk-aac"]
+cask "firefox", greedy: true
+cask "visual-studio-code"
+mas "Xcode", id: 497799835
+mas "Things 3", id: 904280696
+vscode "rust-lang.rust-analyzer"
+if OS.mac?
+  brew "gnu-sed" # comment after
+end
+"#;
+
+    fn package(name: &str, source: PackageSource) -> Package {
+        Package::new(name.to_string(), source)
+    }
+
+    #[test]
+    fn test_parse_brewfile() {
+        let brewfile = Brewfile::parse(BREWFILE);
+        let names: Vec<(EntryKind, &str)> = brewfile.entries.iter().map(|e| (e.kind, e.name.as_str())).collect();
+        assert_eq!(
+            names,
+            [
+                (EntryKind::Tap, "homebrew/bundle"),
+                (EntryKind::Tap, "hashicorp/tap"),
+                (EntryKind::Brew, "jq"),
+                (EntryKind::Brew, "hashicorp/tap/terraform"),
+                (EntryKind::Brew, "postgresql@16"),
+                (EntryKind::Brew, "wget"),
+                (EntryKind::Brew, "ffmpeg"),
+                (EntryKind::Cask, "firefox"),
+                (EntryKind::Cask, "visual-studio-code"),
+                (EntryKind::Mas, "Xcode"),
+                (EntryKind::Mas, "Things 3"),
+                (EntryKind::Brew, "gnu-sed"),
+            ]
+        );
+        assert_eq!(brewfile.entries[9].id, Some(497799835));
+        assert_eq!(quoted(r#""say \"hi\"", x"#), Some(("say \"hi\"".to_string(), ", x")));
+        assert!(Brewfile::parse("brew jq\nmas \"Pages\"\nbrew \"unterminated").entries.is_empty());
+    }
+
+    #[test]
+    fn test_compare_with_installed_packages() {
+        let brewfile = Brewfile::parse(BREWFILE);
+        let mut openssl = package("openssl@3", PackageSource::Homebrew);
+        openssl.is_dependency = true;
+        let packages = [
+            package("jq", PackageSource::Homebrew),
+            package("terraform", PackageSource::Homebrew),
+            package("wget", PackageSource::Homebrew),
+            package("ffmpeg", PackageSource::Homebrew),
+            package("gnu-sed", PackageSource::Homebrew),
+            package("htop", PackageSource::Homebrew),
+            openssl,
+            package("firefox", PackageSource::HomebrewCask),
+            package("slack", PackageSource::HomebrewCask),
+            package("Xcode", PackageSource::Applications),
+            // Not installed by Homebrew, so never extra
+            package("Figma", PackageSource::Applications),
+            package("left-pad", PackageSource::Npm),
+        ];
+
+        let comparison = compare(&brewfile, &packages);
+        let extra: Vec<&str> = comparison.extra.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(extra, ["htop", "slack"]);
+        let missing: Vec<&str> = comparison.missing.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(missing, ["postgresql@16", "visual-studio-code", "Things 3"]);
+        assert_eq!(comparison.matched, 7);
+        let recommendations = comparison.to_recommendations();
+        assert_eq!(recommendations[1].package, "slack");
+        assert_eq!(recommendations[1].severity, RecommendationSeverity::Review);
+
+        // A formula in the Brewfile says nothing about a cask of the same name
+        let packages = [package("docker", PackageSource::Homebrew), package("docker", PackageSource::HomebrewCask)];
+        let recommendations = compare(&Brewfile::parse("brew \"docker\""), &packages).to_recommendations();
+        assert_eq!(recommendations.len(), 1);
+        assert_eq!(recommendations[0].find_package(&packages).map(|p| &p.source), Some(&PackageSource::HomebrewCask));
+    }
+}
@@ -0,0 +1,163 @@
+// Broken symlinks in bin directories, left behind when packages are removed outside macsweep
+use crate::scanner::{Package, PackageSource};
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Bin directories package managers link executables into, relative to the home directory
+/// when not absolute
+const BIN_DIRS: &[&str] = &[
+    "/opt/homebrew/bin",
+    "/opt/homebrew/sbin",
+    "/usr/local/bin",
+    "/usr/local/sbin",
+    ".local/bin",
+    ".cargo/bin",
+    ".npm-global/bin",
+];
+
+/// A symlink whose target no longer exists
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokenLink {
+    pub path: PathBuf,
+    /// Where the link points, as stored in the link (possibly relative)
+    pub target: PathBuf,
+    /// Package the link belonged to, when its target or name gives it away
+    pub package: Option<String>,
+    pub source: Option<PackageSource>,
+}
+
+/// The bin directories that exist on this machine
+pub fn bin_dirs() -> Vec<PathBuf> {
+    let home = dirs::home_dir();
+    BIN_DIRS
+        .iter()
+        .filter_map(|dir| match Path::new(dir) {
+            dir if dir.is_absolute() => Some(dir.to_path_buf()),
+            dir => home.as_ref().map(|home| home.join(dir)),
+        })
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// Dangling symlinks directly inside the known bin directories
+pub fn find_broken_links(packages: &[Package]) -> Vec<BrokenLink> {
+    find_broken_links_in(&bin_dirs(), packages)
+}
+
+pub fn find_broken_links_in(dirs: &[PathBuf], packages: &[Package]) -> Vec<BrokenLink> {
+    let mut links = Vec::new();
+
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if let Some(target) = dangling_target(&path) {
+                let (package, source) = attribute(&path, &target, packages).unzip();
+                links.push(BrokenLink { path, target, package, source });
+            }
+        }
+    }
+
+    links.sort_by(|a, b| a.path.cmp(&b.path));
+    links
+}
+
+/// The target of `path` if it is a symlink that points nowhere
+pub fn dangling_target(path: &Path) -> Option<PathBuf> {
+    let metadata = path.symlink_metadata().ok()?;
+    if !metadata.file_type().is_symlink() || path.exists() {
+        return None;
+    }
+    fs::read_link(path).ok()
+}
+
+/// The package a dead link belonged to: from the install location its target names, or else
+/// from a recorded package that installs a binary of the same name
+fn attribute(path: &Path, target: &Path, packages: &[Package]) -> Option<(String, PackageSource)> {
+    if let Some(found) = installed_by(target) {
+        return Some(found);
+    }
+
+    let name = path.file_name()?.to_str()?;
+    packages
+        .iter()
+        .find(|p| {
+            p.binaries.iter().any(|b| b == name) || p.binary_path.as_ref().is_some_and(|b| b.file_name().is_some_and(|f| f == name))
+        })
+        .map(|p| (p.name.clone(), p.source.clone()))
+}
+
+/// The package whose install location `path` is in, going by the location's layout:
+/// `Cellar/<formula>`, `Caskroom/<cask>`, `pipx/venvs/<app>` or `node_modules/<package>`
+pub fn installed_by(path: &Path) -> Option<(String, PackageSource)> {
+    let components: Vec<&str> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => part.to_str(),
+            _ => None,
+        })
+        .collect();
+
+    for (idx, pair) in components.windows(2).enumerate() {
+        let next = components.get(idx + 2).copied();
+        let found = match (pair[0], pair[1], next) {
+            ("Cellar", name, _) => Some((name.to_string(), PackageSource::Homebrew)),
+            ("Caskroom", name, _) => Some((name.to_string(), PackageSource::HomebrewCask)),
+            ("venvs", name, _) if idx > 0 && components[idx - 1] == "pipx" => Some((name.to_string(), PackageSource::Pipx)),
+            ("node_modules", scope, Some(name)) if scope.starts_with('@') => Some((format!("{}/{}", scope, name), PackageSource::Npm)),
+            ("node_modules", name, _) => Some((name.to_string(), PackageSource::Npm)),
+            _ => None,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_find_and_attribute_broken_links() {
+        let root = tempfile::TempDir::new().unwrap();
+        let bin = root.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let live = root.path().join("Cellar/jq/1.7.1/bin/jq");
+        fs::create_dir_all(live.parent().unwrap()).unwrap();
+        fs::write(&live, b"").unwrap();
+
+        symlink(&live, bin.join("jq")).unwrap();
+        symlink("../Cellar/node/21.7.1/bin/node", bin.join("node")).unwrap();
+        symlink("/Users/a/.local/pipx/venvs/httpie/bin/http", bin.join("http")).unwrap();
+        symlink("../lib/node_modules/@nestjs/cli/bin/nest.js", bin.join("nest")).unwrap();
+        symlink("/opt/old/protoc-25/bin/protoc", bin.join("protoc")).unwrap();
+        symlink("/nowhere/at/all", bin.join("mystery")).unwrap();
+        fs::write(bin.join("script"), b"#!/bin/sh").unwrap();
+
+        let mut protobuf = Package::new("protobuf".to_string(), PackageSource::Homebrew);
+        protobuf.binaries = vec!["protoc".to_string()];
+
+        let links = find_broken_links_in(&[bin.clone(), root.path().join("missing")], &[protobuf]);
+        let found: Vec<_> = links
+            .iter()
+            .map(|l| (l.path.file_name().unwrap().to_str().unwrap(), l.package.as_deref(), l.source.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("http", Some("httpie"), Some(PackageSource::Pipx)),
+                ("mystery", None, None),
+                ("nest", Some("@nestjs/cli"), Some(PackageSource::Npm)),
+                ("node", Some("node"), Some(PackageSource::Homebrew)),
+                ("protoc", Some("protobuf"), Some(PackageSource::Homebrew)),
+            ]
+        );
+        assert_eq!(links[3].target, PathBuf::from("../Cellar/node/21.7.1/bin/node"));
+    }
+}
@@ -2,13 +2,233 @@
 use crate::scanner::Package;
 use super::DependencyAnalysis;
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
 
+/// Find the leaves and orphans of each source's dependency graph; a package's dependencies
+/// are looked up among installed packages of its own source. Orphans are found by walking
+/// down from every explicitly installed package, so dependencies that only need each other
+/// (a cycle) are orphaned together and an orphan's own dependencies are orphaned with it.
 pub fn analyze_dependency_tree(packages: &[Package]) -> Result<DependencyAnalysis> {
-    // TODO: Build dependency graph
-    // Find packages with no dependents (leaves)
-    // Find packages that are deps but whose parent is uninstalled
-    Ok(DependencyAnalysis {
-        leaves: Vec::new(),
-        orphans: Vec::new(),
-    })
+    let key = |name: &str, p: &Package| (name.to_string(), p.source.to_string());
+    let by_key: HashMap<(String, String), &Package> = packages.iter().map(|p| (key(&p.name, p), p)).collect();
+    let installed_dependencies = |p: &Package| {
+        p.dependencies.iter()
+            .filter(move |d| **d != p.name)
+            .filter_map(|d| by_key.get(&key(d, p)).copied())
+            .collect::<Vec<_>>()
+    };
+
+    let required: HashSet<(String, String)> = packages.iter()
+        .flat_map(&installed_dependencies)
+        .map(|d| key(&d.name, d))
+        .collect();
+
+    // Everything explicitly installed packages need, directly or indirectly
+    let mut needed: HashSet<(String, String)> = HashSet::new();
+    let mut stack: Vec<&Package> = packages.iter().filter(|p| !p.is_dependency).collect();
+    while let Some(package) = stack.pop() {
+        if needed.insert(key(&package.name, package)) {
+            stack.extend(installed_dependencies(package));
+        }
+    }
+
+    let mut analysis = DependencyAnalysis::default();
+    for package in packages {
+        let package_key = key(&package.name, package);
+        let counts = analysis.by_source.entry(package_key.1.clone()).or_default();
+        counts.packages += 1;
+
+        if !required.contains(&package_key) {
+            counts.leaves += 1;
+            analysis.leaves.push(package_key.clone());
+        }
+        if package.is_dependency && !needed.contains(&package_key) {
+            counts.orphans += 1;
+            analysis.orphans.push(package_key);
+        }
+    }
+
+    Ok(analysis)
+}
+
+/// Packages to remove in a safe order, and the ones that have to stay
+#[derive(Debug)]
+pub struct RemovalOrder<'a> {
+    /// Dependents come before the packages they depend on
+    pub ordered: Vec<&'a Package>,
+    /// Packages still required by installed packages that aren't being removed, with those packages
+    pub blocked: Vec<(&'a Package, Vec<String>)>,
+}
+
+/// Drop candidates that installed packages outside the removal set still depend on (which
+/// can in turn free their own dependencies from the set), and order the rest so dependents
+/// are removed before their dependencies. Dependency cycles are broken in candidate order.
+pub fn plan_removals<'a>(installed: &[Package], candidates: &[&'a Package]) -> RemovalOrder<'a> {
+    let key = |p: &Package| (p.name.clone(), p.source.to_string());
+
+    // Reverse edges, within a source: (source, dependency) -> dependents
+    let mut dependents: HashMap<(String, String), Vec<&str>> = HashMap::new();
+    for package in installed.iter().chain(candidates.iter().copied()) {
+        for dependency in &package.dependencies {
+            let entry = dependents.entry((dependency.clone(), package.source.to_string())).or_default();
+            if !entry.contains(&package.name.as_str()) {
+                entry.push(&package.name);
+            }
+        }
+    }
+    let dependents_of = |p: &Package| dependents.get(&key(p)).map(Vec::as_slice).unwrap_or(&[]);
+
+    let mut removing: HashSet<(String, String)> = candidates.iter().map(|p| key(p)).collect();
+    let mut blocked = Vec::new();
+    loop {
+        let newly_blocked: Vec<(&Package, Vec<String>)> = candidates.iter()
+            .filter(|p| removing.contains(&key(p)))
+            .filter_map(|p| {
+                let source = p.source.to_string();
+                let staying: Vec<String> = dependents_of(p).iter()
+                    .filter(|d| !removing.contains(&(d.to_string(), source.clone())))
+                    .map(|d| d.to_string())
+                    .collect();
+                (!staying.is_empty()).then_some((*p, staying))
+            })
+            .collect();
+        if newly_blocked.is_empty() {
+            break;
+        }
+        for (package, staying) in newly_blocked {
+            removing.remove(&key(package));
+            blocked.push((package, staying));
+        }
+    }
+
+    // Kahn's algorithm over dependent -> dependency edges, preferring candidate order
+    let mut remaining: Vec<&Package> = candidates.iter().copied().filter(|p| removing.contains(&key(p))).collect();
+    let mut ordered = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let pending: HashSet<(String, String)> = remaining.iter().map(|p| key(p)).collect();
+        let source_of = |p: &Package| p.source.to_string();
+        let next = remaining.iter()
+            .position(|p| !dependents_of(p).iter().any(|d| pending.contains(&(d.to_string(), source_of(p)))))
+            .unwrap_or(0);
+        ordered.push(remaining.remove(next));
+    }
+
+    RemovalOrder { ordered, blocked }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::PackageSource;
+
+    fn package(name: &str, dependencies: &[&str]) -> Package {
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+        package
+    }
+
+    fn names<'a>(packages: impl IntoIterator<Item = &'a Package>) -> Vec<&'a str> {
+        packages.into_iter().map(|p| p.name.as_str()).collect()
+    }
+
+    fn dependency(name: &str, source: PackageSource, dependencies: &[&str]) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+        package.is_dependency = true;
+        package
+    }
+
+    fn keys(keys: &[(String, String)]) -> Vec<&str> {
+        keys.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_analyze_leaves_and_orphans() {
+        // black needs click; httpie was uninstalled, leaving requests and its dependencies
+        let packages = vec![
+            Package { dependencies: vec!["click".to_string()], ..Package::new("black".to_string(), PackageSource::Pip) },
+            dependency("click", PackageSource::Pip, &[]),
+            dependency("requests", PackageSource::Pip, &["idna", "urllib3"]),
+            dependency("idna", PackageSource::Pip, &[]),
+            dependency("urllib3", PackageSource::Pip, &[]),
+            package("jq", &["oniguruma"]),
+            dependency("oniguruma", PackageSource::Homebrew, &[]),
+        ];
+
+        let analysis = analyze_dependency_tree(&packages).unwrap();
+        assert_eq!(keys(&analysis.leaves), ["black", "requests", "jq"]);
+        assert_eq!(keys(&analysis.orphans), ["requests", "idna", "urllib3"]);
+        assert!(analysis.orphans.iter().all(|(_, source)| source == "pip"));
+
+        let pip = &analysis.by_source["pip"];
+        assert_eq!((pip.packages, pip.leaves, pip.orphans), (5, 2, 3));
+        let brew = &analysis.by_source["homebrew"];
+        assert_eq!((brew.packages, brew.leaves, brew.orphans), (2, 1, 0));
+    }
+
+    #[test]
+    fn test_analyze_cycles_and_sources() {
+        let packages = vec![
+            // A dependency cycle nothing installed on request uses
+            dependency("a", PackageSource::Pip, &["b"]),
+            dependency("b", PackageSource::Pip, &["a"]),
+            // A cycle kept alive by a top-level package, and a package that lists itself
+            Package { dependencies: vec!["c".to_string()], ..Package::new("tool".to_string(), PackageSource::Pip) },
+            dependency("c", PackageSource::Pip, &["d", "c"]),
+            dependency("d", PackageSource::Pip, &["c"]),
+            // An npm package with the same name doesn't keep the pip one
+            Package { dependencies: vec!["e".to_string()], ..Package::new("cli".to_string(), PackageSource::Npm) },
+            dependency("e", PackageSource::Pip, &[]),
+        ];
+
+        let analysis = analyze_dependency_tree(&packages).unwrap();
+        assert_eq!(keys(&analysis.orphans), ["a", "b", "e"]);
+        assert_eq!(keys(&analysis.leaves), ["tool", "cli", "e"]);
+    }
+
+    #[test]
+    fn test_dependents_are_removed_first() {
+        // ffmpeg -> x264 -> nasm, and ffmpeg -> nasm directly
+        let installed = vec![package("nasm", &[]), package("x264", &["nasm"]), package("ffmpeg", &["x264", "nasm"])];
+        let candidates: Vec<&Package> = installed.iter().collect();
+
+        let order = plan_removals(&installed, &candidates);
+        assert_eq!(names(order.ordered), ["ffmpeg", "x264", "nasm"]);
+        assert!(order.blocked.is_empty());
+    }
+
+    #[test]
+    fn test_required_packages_are_blocked_transitively() {
+        // curl stays, so openssl@3 stays, so ca-certificates stays
+        let installed = vec![
+            package("ca-certificates", &[]),
+            package("openssl@3", &["ca-certificates"]),
+            package("curl", &["openssl@3"]),
+            package("wget", &["openssl@3"]),
+            package("jq", &[]),
+        ];
+        let candidates = vec![&installed[0], &installed[1], &installed[3], &installed[4]];
+
+        let order = plan_removals(&installed, &candidates);
+        assert_eq!(names(order.ordered), ["wget", "jq"]);
+        let blocked: Vec<_> = order.blocked.iter().map(|(p, by)| (p.name.as_str(), by.clone())).collect();
+        assert_eq!(blocked, [
+            ("openssl@3", vec!["curl".to_string()]),
+            ("ca-certificates", vec!["openssl@3".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn test_cycles_and_other_sources() {
+        let installed = vec![package("a", &["b"]), package("b", &["a"]), package("c", &[])];
+        let mut npm_user = Package::new("tool".to_string(), PackageSource::Npm);
+        npm_user.dependencies = vec!["c".to_string()];
+        let candidates: Vec<&Package> = installed.iter().collect();
+
+        // A cycle is broken only once nothing else is ready, and still yields every package
+        // once; an npm package doesn't hold back a formula
+        let order = plan_removals(&[installed.clone(), vec![npm_user]].concat(), &candidates);
+        assert_eq!(names(order.ordered), ["c", "a", "b"]);
+        assert!(order.blocked.is_empty());
+    }
 }
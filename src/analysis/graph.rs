@@ -0,0 +1,195 @@
+// Dependency subgraphs rendered as DOT or Mermaid for `macsweep deps`
+use crate::scanner::Package;
+use crate::utils::date::days_since;
+use crate::utils::size::format_size;
+use std::collections::{HashMap, VecDeque};
+
+/// Text format of a rendered graph
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GraphFormat {
+    /// Graphviz, e.g. `macsweep deps ffmpeg | dot -Tsvg > ffmpeg.svg`
+    #[default]
+    Dot,
+    /// Mermaid flowchart, for Markdown files and GitHub comments
+    Mermaid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphNode {
+    pub name: String,
+    /// Size and days unused, or why they are unknown
+    pub annotation: String,
+    /// Referenced by an installed package but not installed itself
+    pub missing: bool,
+}
+
+/// The packages reachable from one package, each listed once; edges point from a package
+/// to what it depends on, whichever way the graph was walked
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    /// (from, to) indices into `nodes`
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    /// Walk `root`'s dependencies (or with `reverse`, its dependents) within its source, at
+    /// most `depth` levels deep; packages already in the graph aren't walked again, so cycles end
+    pub fn build(packages: &[Package], root: &Package, depth: Option<usize>, reverse: bool) -> Self {
+        let installed: HashMap<&str, &Package> = packages
+            .iter()
+            .filter(|p| p.source == root.source)
+            .map(|p| (p.name.as_str(), p))
+            .collect();
+
+        let mut graph = Self::default();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut queue = VecDeque::from([(root.name.clone(), 0)]);
+        index.insert(root.name.clone(), 0);
+        graph.nodes.push(node(&root.name, Some(root)));
+
+        while let Some((name, level)) = queue.pop_front() {
+            let Some(package) = installed.get(name.as_str()) else {
+                continue;
+            };
+            if depth.is_some_and(|depth| level >= depth) {
+                continue;
+            }
+
+            let neighbours = if reverse { &package.dependents } else { &package.dependencies };
+            for neighbour in neighbours {
+                let idx = match index.get(neighbour) {
+                    Some(&idx) => idx,
+                    None => {
+                        let idx = graph.nodes.len();
+                        index.insert(neighbour.clone(), idx);
+                        graph.nodes.push(node(neighbour, installed.get(neighbour.as_str()).copied()));
+                        queue.push_back((neighbour.clone(), level + 1));
+                        idx
+                    }
+                };
+                let from = index[&name];
+                graph.edges.push(if reverse { (idx, from) } else { (from, idx) });
+            }
+        }
+
+        graph
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::from("digraph dependencies {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for node in &self.nodes {
+            let label = quote(&format!("{}\n{}", node.name, node.annotation)).replace('\n', "\\n");
+            let style = if node.missing { ", style=dashed" } else { "" };
+            out.push_str(&format!("    {} [label={}{}];\n", quote(&node.name), label, style));
+        }
+        for &(from, to) in &self.edges {
+            out.push_str(&format!("    {} -> {};\n", quote(&self.nodes[from].name), quote(&self.nodes[to].name)));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("flowchart LR\n");
+
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let label = format!("{}<br/>{}", node.name, node.annotation).replace('"', "#quot;");
+            out.push_str(&format!("    n{}[\"{}\"]\n", idx, label));
+        }
+        for &(from, to) in &self.edges {
+            out.push_str(&format!("    n{} --> n{}\n", from, to));
+        }
+
+        let missing: Vec<String> = self.nodes.iter().enumerate().filter(|(_, n)| n.missing).map(|(idx, _)| format!("n{}", idx)).collect();
+        if !missing.is_empty() {
+            out.push_str("    classDef missing stroke-dasharray: 5 5\n");
+            out.push_str(&format!("    class {} missing\n", missing.join(",")));
+        }
+        out
+    }
+}
+
+fn node(name: &str, package: Option<&Package>) -> GraphNode {
+    let Some(package) = package else {
+        return GraphNode { name: name.to_string(), annotation: "not installed".to_string(), missing: true };
+    };
+
+    let size = package.size_bytes.map(format_size).unwrap_or_else(|| "size unknown".to_string());
+    let usage = match package.last_used {
+        Some(last_used) => format!("{} days unused", days_since(&last_used)),
+        None => "no usage data".to_string(),
+    };
+    GraphNode { name: name.to_string(), annotation: format!("{}, {}", size, usage), missing: false }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::PackageSource;
+
+    fn formula(name: &str, dependencies: &[&str], dependents: &[&str]) -> Package {
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+        package.dependents = dependents.iter().map(|d| d.to_string()).collect();
+        package.size_bytes = Some(2 * 1024 * 1024);
+        package
+    }
+
+    fn names(graph: &DependencyGraph) -> Vec<&str> {
+        graph.nodes.iter().map(|n| n.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_graph_with_cycle_depth_and_missing_dependency() {
+        let packages = vec![
+            formula("ffmpeg", &["x264", "lame"], &[]),
+            formula("x264", &["libfoo"], &["ffmpeg"]),
+            formula("libfoo", &["x264", "gone"], &["x264"]),
+            formula("lame", &[], &["ffmpeg"]),
+        ];
+
+        let graph = DependencyGraph::build(&packages, &packages[0], None, false);
+        assert_eq!(names(&graph), ["ffmpeg", "x264", "lame", "libfoo", "gone"]);
+        // The libfoo -> x264 edge closes the cycle without walking it again
+        assert_eq!(graph.edges, [(0, 1), (0, 2), (1, 3), (3, 1), (3, 4)]);
+        assert!(graph.nodes[4].missing);
+        assert_eq!(graph.nodes[2].annotation, "2.0 MB, no usage data");
+
+        let shallow = DependencyGraph::build(&packages, &packages[0], Some(1), false);
+        assert_eq!(names(&shallow), ["ffmpeg", "x264", "lame"]);
+
+        // Reverse edges still point from dependent to dependency
+        let reverse = DependencyGraph::build(&packages, &packages[2], None, true);
+        assert_eq!(names(&reverse), ["libfoo", "x264", "ffmpeg"]);
+        assert_eq!(reverse.edges, [(1, 0), (2, 1)]);
+    }
+
+    #[test]
+    fn test_render_dot_and_mermaid() {
+        let packages = vec![formula("wget", &["openssl@3", "gone"], &[])];
+        let graph = DependencyGraph::build(&packages, &packages[0], None, false);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    \"wget\" [label=\"wget\\n2.0 MB, no usage data\"];\n"));
+        assert!(dot.contains("    \"gone\" [label=\"gone\\nnot installed\", style=dashed];\n"));
+        assert!(dot.contains("    \"wget\" -> \"openssl@3\";\n"));
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR\n    n0[\"wget<br/>2.0 MB, no usage data\"]\n"));
+        assert!(mermaid.contains("    n0 --> n2\n"));
+        assert!(mermaid.ends_with("    class n1,n2 missing\n"));
+    }
+}
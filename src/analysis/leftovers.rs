@@ -0,0 +1,400 @@
+// Leftover application data detection (Application Support, Caches, Preferences, ...)
+use crate::analysis::recommendations::{CleanupAction, Recommendation, RecommendationSeverity};
+use crate::scanner::{Package, PackageSource};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Library subdirectories that commonly hold per-app data
+const LEFTOVER_LOCATIONS: &[&str] = &[
+    "Application Support",
+    "Caches",
+    "Preferences",
+    "Containers",
+    "Saved Application State",
+    "HTTPStorages",
+    "WebKit",
+    "Logs",
+];
+
+/// Where an app keeps its support files, as (Library subdirectory, suffix after the bundle
+/// identifier); Application Support and Logs are also searched by the app's name
+const SUPPORT_LOCATIONS: &[(&str, &str)] = &[
+    ("Application Support", ""),
+    ("Preferences", ".plist"),
+    ("Caches", ""),
+    ("Containers", ""),
+    ("Saved Application State", ".savedState"),
+    ("HTTPStorages", ""),
+    ("HTTPStorages", ".binarycookies"),
+    ("WebKit", ""),
+    ("Logs", ""),
+];
+
+/// Locations holding an app's settings, kept by `clean --keep-config`
+const CONFIG_LOCATIONS: &[&str] = &["Application Support", "Preferences"];
+
+/// Entries that are shared between apps or owned by the system and must never be reported
+const SHARED_ENTRIES: &[&str] = &[
+    "group containers",
+    "addressbook",
+    "callhistorydb",
+    "callhistorytransactions",
+    "clouddocs",
+    "crashreporter",
+    "diagnosticreports",
+    "dock",
+    "familysettings",
+    "fileprovider",
+    "icdd",
+    "knowledge",
+    "mobilesync",
+    "syncservices",
+    "ubiquity",
+    "homebrew",
+    "macsweep",
+    "pip",
+    "node-gyp",
+    "typescript",
+];
+
+/// A directory or file left behind by an application that is no longer installed
+#[derive(Debug, Clone, Serialize)]
+pub struct Leftover {
+    /// Entry name with any `.plist` / `.savedState` suffix removed
+    pub name: String,
+    pub path: PathBuf,
+    /// Library subdirectory the entry was found in (e.g. "Caches")
+    pub location: String,
+    /// Set when the entry is named after a bundle identifier
+    pub bundle_id: Option<String>,
+    pub size_bytes: u64,
+}
+
+impl Leftover {
+    /// Leftovers are never "Safe": the owning app is only inferred, so the user should review them
+    pub fn to_recommendation(&self) -> Recommendation {
+        Recommendation {
+            package: self.name.clone(),
+            source: None,
+            reason: format!("Leftover data from uninstalled app ({})", self.location),
+            severity: RecommendationSeverity::Review,
+            size_recoverable: self.size_bytes,
+            action: CleanupAction::Remove,
+        }
+    }
+}
+
+/// Find leftover app data in the current user's ~/Library
+pub fn find_leftovers(packages: &[Package]) -> Result<Vec<Leftover>> {
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    Ok(find_leftovers_in(&home.join("Library"), packages))
+}
+
+/// Find leftover app data under `library`, using `packages` as the set of installed software
+pub fn find_leftovers_in(library: &Path, packages: &[Package]) -> Vec<Leftover> {
+    let installed = InstalledSet::from_packages(packages);
+    let mut leftovers = Vec::new();
+
+    for location in LEFTOVER_LOCATIONS {
+        let dir = library.join(location);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let name = strip_entry_suffix(&file_name);
+
+            if name.starts_with('.') || is_shared_entry(name) {
+                continue;
+            }
+
+            let bundle_id = if looks_like_bundle_id(name) {
+                Some(name.to_string())
+            } else {
+                None
+            };
+
+            // Only bundle-id named entries are trusted in locations keyed by bundle id;
+            // plain names there are usually tools or frameworks, not apps
+            if bundle_id.is_none() && !matches!(*location, "Application Support" | "Logs") {
+                continue;
+            }
+
+            if installed.owns(name, bundle_id.is_some()) {
+                continue;
+            }
+
+            let size_bytes = if path.is_dir() {
+                crate::utils::size::calculate_directory_size(&path).unwrap_or(0)
+            } else {
+                fs::metadata(&path).map(|m| m.len()).unwrap_or(0)
+            };
+
+            leftovers.push(Leftover {
+                name: name.to_string(),
+                path,
+                location: location.to_string(),
+                bundle_id,
+                size_bytes,
+            });
+        }
+    }
+
+    leftovers.sort_by_key(|l| std::cmp::Reverse(l.size_bytes));
+    leftovers
+}
+
+/// Support files in the current user's ~/Library that belong to an app or cask being removed.
+/// The bundle identifier is read from the app's Info.plist while it is still installed,
+/// falling back to the one stored by the last scan; `defaults` is given `timeout` to read it.
+pub fn support_files(package: &Package, keep_config: bool, timeout: Duration) -> Result<Vec<Leftover>> {
+    if !matches!(package.source, PackageSource::Applications | PackageSource::HomebrewCask) {
+        return Ok(Vec::new());
+    }
+
+    let bundle_id = package.binary_path.as_deref()
+        .and_then(|app| crate::scanner::applications::read_bundle_id(app, timeout))
+        .or_else(|| package.bundle_id.clone());
+    let Some(bundle_id) = bundle_id else {
+        return Ok(Vec::new());
+    };
+    let app_name = package.binary_path.as_ref()
+        .and_then(|p| p.file_stem())
+        .map(|s| s.to_string_lossy().to_string());
+
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    Ok(support_files_in(&home.join("Library"), &bundle_id, app_name.as_deref(), keep_config))
+}
+
+/// Support files under `library` named after `bundle_id`, or after `app_name` where apps use
+/// their display name; `keep_config` leaves Application Support and Preferences alone
+pub fn support_files_in(library: &Path, bundle_id: &str, app_name: Option<&str>, keep_config: bool) -> Vec<Leftover> {
+    let mut files: Vec<Leftover> = Vec::new();
+    if is_shared_entry(bundle_id) {
+        return files;
+    }
+
+    for (location, suffix) in SUPPORT_LOCATIONS {
+        if keep_config && CONFIG_LOCATIONS.contains(location) {
+            continue;
+        }
+
+        let mut names = vec![(format!("{}{}", bundle_id, suffix), Some(bundle_id))];
+        if let Some(app_name) = app_name.filter(|n| !is_shared_entry(n)) {
+            if suffix.is_empty() && matches!(*location, "Application Support" | "Logs") {
+                names.push((app_name.to_string(), None));
+            }
+        }
+
+        for (file_name, bundle_id) in names {
+            let path = library.join(location).join(&file_name);
+            // symlink_metadata so a link is trashed rather than what it points to
+            if path.symlink_metadata().is_err() || files.iter().any(|f| f.path == path) {
+                continue;
+            }
+
+            let size_bytes = if path.is_dir() {
+                crate::utils::size::calculate_directory_size(&path).unwrap_or(0)
+            } else {
+                fs::metadata(&path).map(|m| m.len()).unwrap_or(0)
+            };
+            files.push(Leftover {
+                name: strip_entry_suffix(&file_name).to_string(),
+                path,
+                location: location.to_string(),
+                bundle_id: bundle_id.map(str::to_string),
+                size_bytes,
+            });
+        }
+    }
+
+    files
+}
+
+/// Bundle identifiers and normalized names of everything currently installed
+struct InstalledSet {
+    bundle_ids: Vec<String>,
+    names: HashSet<String>,
+}
+
+impl InstalledSet {
+    fn from_packages(packages: &[Package]) -> Self {
+        let mut bundle_ids = Vec::new();
+        let mut names = HashSet::new();
+
+        for package in packages {
+            if let Some(ref id) = package.bundle_id {
+                bundle_ids.push(id.to_lowercase());
+            }
+
+            names.insert(normalize_name(&package.name));
+
+            // Casks are named by token; the .app name is what shows up in ~/Library
+            if matches!(package.source, PackageSource::Applications | PackageSource::HomebrewCask) {
+                if let Some(stem) = package.binary_path.as_ref().and_then(|p| p.file_stem()) {
+                    names.insert(normalize_name(&stem.to_string_lossy()));
+                }
+            }
+        }
+
+        Self { bundle_ids, names }
+    }
+
+    /// Whether an entry belongs to installed software, by bundle id first and name as fallback
+    fn owns(&self, entry_name: &str, is_bundle_id: bool) -> bool {
+        let lower = entry_name.to_lowercase();
+
+        if is_bundle_id {
+            // Helpers and extensions use the parent id as a prefix (com.foo.app.helper)
+            if self.bundle_ids.iter().any(|id| lower == *id || lower.starts_with(&format!("{}.", id))) {
+                return true;
+            }
+
+            // Fall back to the last component of the id (com.tinyspeck.slackmacgap -> slackmacgap)
+            return lower
+                .rsplit('.')
+                .next()
+                .map(|last| self.names.contains(&normalize_name(last)))
+                .unwrap_or(false);
+        }
+
+        self.names.contains(&normalize_name(entry_name))
+    }
+}
+
+fn strip_entry_suffix(file_name: &str) -> &str {
+    file_name
+        .strip_suffix(".plist")
+        .or_else(|| file_name.strip_suffix(".savedState"))
+        .unwrap_or(file_name)
+}
+
+fn is_shared_entry(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    lower.starts_with("com.apple.") || SHARED_ENTRIES.contains(&lower.as_str())
+}
+
+/// Reverse-DNS style names such as `com.example.App`
+fn looks_like_bundle_id(name: &str) -> bool {
+    let parts: Vec<&str> = name.split('.').collect();
+    parts.len() >= 3
+        && parts.iter().all(|p| !p.is_empty())
+        && !name.contains(char::is_whitespace)
+}
+
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn app(name: &str, bundle_id: &str) -> Package {
+        let mut package = Package::new(name.to_string(), PackageSource::Applications);
+        package.bundle_id = Some(bundle_id.to_string());
+        package.binary_path = Some(PathBuf::from(format!("/Applications/{}.app", name)));
+        package
+    }
+
+    fn touch(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"data").unwrap();
+    }
+
+    #[test]
+    fn test_find_leftovers_by_bundle_id() {
+        let library = TempDir::new().unwrap();
+        let root = library.path();
+
+        touch(&root.join("Caches/com.example.Installed/cache.db"));
+        touch(&root.join("Caches/com.example.Installed.helper/cache.db"));
+        touch(&root.join("Caches/com.example.Removed/cache.db"));
+        touch(&root.join("Preferences/com.example.Removed.plist"));
+        touch(&root.join("Preferences/com.apple.dock.plist"));
+
+        let packages = vec![app("Installed", "com.example.Installed")];
+        let leftovers = find_leftovers_in(root, &packages);
+
+        let mut names: Vec<_> = leftovers.iter().map(|l| (l.location.as_str(), l.name.as_str())).collect();
+        names.sort();
+        assert_eq!(names, vec![("Caches", "com.example.Removed"), ("Preferences", "com.example.Removed")]);
+        assert!(leftovers.iter().all(|l| l.size_bytes > 0));
+    }
+
+    #[test]
+    fn test_find_leftovers_name_fallback_and_shared() {
+        let library = TempDir::new().unwrap();
+        let root = library.path();
+
+        touch(&root.join("Application Support/Visual Studio Code/state"));
+        touch(&root.join("Application Support/Old App/state"));
+        touch(&root.join("Application Support/CrashReporter/x"));
+        touch(&root.join("Group Containers/group.com.example.Removed/x"));
+        // Plain names in bundle-id keyed locations are ignored
+        touch(&root.join("Caches/SomeTool/x"));
+
+        let mut cask = Package::new("visual-studio-code".to_string(), PackageSource::HomebrewCask);
+        cask.binary_path = Some(PathBuf::from("/Applications/Visual Studio Code.app"));
+
+        let leftovers = find_leftovers_in(root, &[cask]);
+        assert_eq!(leftovers.len(), 1);
+        assert_eq!(leftovers[0].name, "Old App");
+        assert_eq!(leftovers[0].to_recommendation().severity, RecommendationSeverity::Review);
+    }
+
+    #[test]
+    fn test_support_files_for_removed_app() {
+        let library = TempDir::new().unwrap();
+        let root = library.path();
+
+        touch(&root.join("Application Support/Foo/settings.json"));
+        touch(&root.join("Caches/com.foo.app/cache.db"));
+        touch(&root.join("Preferences/com.foo.app.plist"));
+        touch(&root.join("Saved Application State/com.foo.app.savedState/windows.plist"));
+        touch(&root.join("Logs/Foo/foo.log"));
+        touch(&root.join("Caches/com.foo.other/cache.db"));
+        touch(&root.join("Caches/Foo/cache.db"));
+
+        let files = support_files_in(root, "com.foo.app", Some("Foo"), false);
+        let mut found: Vec<_> = files.iter().map(|f| (f.location.as_str(), f.name.as_str())).collect();
+        found.sort();
+        assert_eq!(found, vec![
+            ("Application Support", "Foo"),
+            ("Caches", "com.foo.app"),
+            ("Logs", "Foo"),
+            ("Preferences", "com.foo.app"),
+            ("Saved Application State", "com.foo.app"),
+        ]);
+        assert!(files.iter().all(|f| f.size_bytes > 0));
+
+        // --keep-config leaves settings for a reinstall
+        let kept = support_files_in(root, "com.foo.app", Some("Foo"), true);
+        assert_eq!(kept.len(), 3);
+        assert!(kept.iter().all(|f| f.location != "Preferences" && f.location != "Application Support"));
+
+        // Shared and system names are never claimed by an app
+        touch(&root.join("Application Support/CrashReporter/x"));
+        assert!(support_files_in(root, "com.apple.Safari", Some("Safari"), false).is_empty());
+        assert!(support_files_in(root, "com.crash.app", Some("CrashReporter"), false).is_empty());
+    }
+
+    #[test]
+    fn test_looks_like_bundle_id() {
+        assert!(looks_like_bundle_id("com.tinyspeck.slackmacgap"));
+        assert!(!looks_like_bundle_id("Slack"));
+        assert!(!looks_like_bundle_id("node.js"));
+        assert!(!looks_like_bundle_id("Google Chrome.app.x"));
+    }
+}
@@ -0,0 +1,175 @@
+// Reinstall manifests written from the recorded packages for `macsweep export`: a Brewfile,
+// a pip requirements file and shell scripts of install commands
+use crate::scanner::{Package, PackageSource};
+
+/// Which manifest to write
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ManifestKind {
+    /// `brew` and `cask` lines for `brew bundle`
+    Brewfile,
+    /// pip packages pinned to their versions, for `pip install -r`
+    Requirements,
+    /// A shell script of `npm install -g` commands
+    NpmGlobals,
+    /// A shell script reinstalling everything a package manager can
+    InstallScript,
+}
+
+/// Packages installed only as another package's dependency are left out; installing what
+/// needed them brings them back.
+pub fn render(kind: ManifestKind, packages: &[Package]) -> String {
+    let mut packages: Vec<&Package> = packages.iter().filter(|p| !p.is_dependency).collect();
+    packages.sort_by_key(|p| p.name.to_lowercase());
+    let from = |source: PackageSource| packages.iter().copied().filter(move |p| p.source == source);
+
+    let mut out = String::new();
+    match kind {
+        ManifestKind::Brewfile => {
+            for package in from(PackageSource::Homebrew) {
+                out.push_str(&format!("brew {}\n", ruby_string(&package.name)));
+            }
+            for package in from(PackageSource::HomebrewCask) {
+                out.push_str(&format!("cask {}\n", ruby_string(&package.name)));
+            }
+        }
+        ManifestKind::Requirements => {
+            for package in from(PackageSource::Pip) {
+                out.push_str(&format!("{}\n", pinned(package, "==")));
+            }
+        }
+        ManifestKind::NpmGlobals => {
+            out.push_str("#!/bin/sh\n# npm global packages recorded by macsweep\n");
+            for package in from(PackageSource::Npm) {
+                out.push_str(&format!("npm install -g {}\n", shell_quote(&pinned(package, "@"))));
+            }
+        }
+        ManifestKind::InstallScript => {
+            out.push_str("#!/bin/sh\n# Packages recorded by macsweep; each command runs even if an earlier one fails\n");
+            let mut section = |title: &str, lines: Vec<String>| {
+                if !lines.is_empty() {
+                    out.push_str(&format!("\n# {}\n", title));
+                    for line in lines {
+                        out.push_str(&line);
+                        out.push('\n');
+                    }
+                }
+            };
+            let commands = |source, command: &str, version_separator: Option<&str>| -> Vec<String> {
+                from(source)
+                    .map(|p| {
+                        let spec = match version_separator {
+                            Some(separator) => pinned(p, separator),
+                            None => p.name.clone(),
+                        };
+                        format!("{} {}", command, shell_quote(&spec))
+                    })
+                    .collect()
+            };
+            section("Homebrew formulae", commands(PackageSource::Homebrew, "brew install", None));
+            section("Homebrew casks", commands(PackageSource::HomebrewCask, "brew install --cask", None));
+            section("pip", commands(PackageSource::Pip, "python3 -m pip install", Some("==")));
+            section("pipx", commands(PackageSource::Pipx, "pipx install", None));
+            section("npm", commands(PackageSource::Npm, "npm install -g", Some("@")));
+            section("cargo", commands(PackageSource::Cargo, "cargo install", None));
+            section("gem", commands(PackageSource::Gem, "gem install", None));
+
+            // Nothing records where these came from, so they can only be listed
+            let by_hand: Vec<String> = packages
+                .iter()
+                .filter(|p| matches!(p.source, PackageSource::Applications | PackageSource::MacAppStore))
+                .map(|p| format!("#   {}", p.name))
+                .collect();
+            section("Applications to reinstall by hand", by_hand);
+        }
+    }
+    out
+}
+
+/// `name==1.2.0` or `name@1.2.0`, or just the name when no version is recorded
+fn pinned(package: &Package, separator: &str) -> String {
+    match package.version.as_deref() {
+        Some(version) if !version.is_empty() => format!("{}{}{}", package.name, separator, version),
+        _ => package.name.clone(),
+    }
+}
+
+/// A double-quoted Ruby string, as Brewfiles use
+fn ruby_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('#', "\\#"))
+}
+
+/// `text` as one shell word, quoted only when it needs to be
+fn shell_quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c);
+    if !text.is_empty() && text.chars().all(plain) {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, source: PackageSource, version: Option<&str>) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.version = version.map(str::to_string);
+        package
+    }
+
+    fn packages() -> Vec<Package> {
+        let mut openssl = package("openssl@3", PackageSource::Homebrew, Some("3.3.1"));
+        openssl.is_dependency = true;
+        vec![
+            package("wget", PackageSource::Homebrew, Some("1.24.5")),
+            openssl,
+            package("hashicorp/tap/terraform", PackageSource::Homebrew, None),
+            package("firefox", PackageSource::HomebrewCask, Some("128.0")),
+            package("Jq", PackageSource::Homebrew, Some("1.7.1")),
+            package("requests", PackageSource::Pip, Some("2.32.3")),
+            package("black", PackageSource::Pip, None),
+            package("httpie", PackageSource::Pipx, Some("3.2.2")),
+            package("@vue/cli", PackageSource::Npm, Some("5.0.8")),
+            package("ripgrep", PackageSource::Cargo, Some("14.1.0")),
+            package("Slack", PackageSource::Applications, Some("4.39")),
+        ]
+    }
+
+    #[test]
+    fn test_brewfile_is_brew_bundle_syntax() {
+        // Formulae (taps by their full name) before casks, as `brew bundle dump` writes them
+        assert_eq!(
+            render(ManifestKind::Brewfile, &packages()),
+            "brew \"hashicorp/tap/terraform\"\nbrew \"Jq\"\nbrew \"wget\"\ncask \"firefox\"\n"
+        );
+        assert_eq!(ruby_string("odd\"#{name}\\"), "\"odd\\\"\\#{name}\\\\\"");
+    }
+
+    #[test]
+    fn test_requirements_and_npm_globals() {
+        assert_eq!(render(ManifestKind::Requirements, &packages()), "black\nrequests==2.32.3\n");
+        assert_eq!(
+            render(ManifestKind::NpmGlobals, &packages()),
+            "#!/bin/sh\n# npm global packages recorded by macsweep\nnpm install -g @vue/cli@5.0.8\n"
+        );
+        assert_eq!(render(ManifestKind::Requirements, &[]), "");
+    }
+
+    #[test]
+    fn test_install_script() {
+        let script = render(ManifestKind::InstallScript, &packages());
+        assert!(script.starts_with("#!/bin/sh\n"));
+        assert!(script.contains("\n# Homebrew formulae\nbrew install hashicorp/tap/terraform\nbrew install Jq\nbrew install wget\n"));
+        assert!(script.contains("\n# Homebrew casks\nbrew install --cask firefox\n"));
+        assert!(script.contains("\npython3 -m pip install black\npython3 -m pip install requests==2.32.3\n"));
+        assert!(script.contains("\npipx install httpie\n"));
+        assert!(script.contains("\ncargo install ripgrep\n"));
+        assert!(script.contains("\n# Applications to reinstall by hand\n#   Slack\n"));
+        assert!(!script.contains("openssl"));
+        assert!(!script.contains("# gem"));
+
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+        assert_eq!(shell_quote("a b"), "'a b'");
+    }
+}
@@ -0,0 +1,148 @@
+// Prometheus metrics of the recorded packages and what the recommendations would free, for
+// `macsweep export --metrics` and node_exporter's textfile collector
+use crate::analysis::recommendations::{Recommendation, RecommendationSeverity};
+use crate::scanner::Package;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+const SEVERITIES: [(RecommendationSeverity, &str); 3] = [
+    (RecommendationSeverity::Safe, "safe"),
+    (RecommendationSeverity::Review, "review"),
+    (RecommendationSeverity::Warning, "warning"),
+];
+
+/// The metrics in the Prometheus text format. Every severity gets a sample, so a reclaimable
+/// total that drops to nothing reads as 0 rather than vanishing; the last scan's timestamp is
+/// left out when no scan is recorded.
+pub fn render(packages: &[Package], recommendations: &[Recommendation], last_scan: Option<DateTime<Utc>>) -> String {
+    let mut by_source: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for package in packages {
+        let (count, bytes) = by_source.entry(package.source.as_str()).or_default();
+        *count += 1;
+        *bytes += package.size_bytes.unwrap_or(0);
+    }
+    let reclaimable = |severity| recommendations.iter().filter(move |r| r.severity == severity);
+
+    let mut out = String::new();
+    let mut gauge = |name: &str, help: &str, samples: Vec<(Option<(&str, &str)>, u64)>| {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+        for (label, value) in samples {
+            match label {
+                Some((key, text)) => {
+                    let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, key, label_value(text), value);
+                }
+                None => {
+                    let _ = writeln!(out, "{} {}", name, value);
+                }
+            }
+        }
+    };
+
+    gauge(
+        "macsweep_packages_total",
+        "Packages recorded by the last scan of each source.",
+        by_source.iter().map(|(source, (count, _))| (Some(("source", *source)), *count)).collect(),
+    );
+    gauge(
+        "macsweep_package_size_bytes_total",
+        "Disk space used by the recorded packages of each source.",
+        by_source.iter().map(|(source, (_, bytes))| (Some(("source", *source)), *bytes)).collect(),
+    );
+    gauge(
+        "macsweep_reclaimable_bytes",
+        "Space removing the recommended packages of each severity would free.",
+        SEVERITIES.iter().map(|(severity, name)| (Some(("severity", *name)), reclaimable(*severity).map(|r| r.size_recoverable).sum())).collect(),
+    );
+    gauge(
+        "macsweep_reclaimable_packages",
+        "Packages recommended for removal, by severity.",
+        SEVERITIES.iter().map(|(severity, name)| (Some(("severity", *name)), reclaimable(*severity).count() as u64)).collect(),
+    );
+    if let Some(scanned_at) = last_scan {
+        gauge(
+            "macsweep_last_scan_timestamp_seconds",
+            "When the last scan ran, in seconds since the Unix epoch.",
+            vec![(None, scanned_at.timestamp().max(0) as u64)],
+        );
+    }
+    out
+}
+
+/// `text` escaped for a label value: backslashes, double quotes and newlines are escaped as the
+/// text format requires, and other control characters, which would garble the line, become `_`
+fn label_value(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => value.push_str("\\\\"),
+            '"' => value.push_str("\\\""),
+            '\n' => value.push_str("\\n"),
+            c if c.is_control() => value.push('_'),
+            c => value.push(c),
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::recommendations::CleanupAction;
+    use crate::scanner::PackageSource;
+
+    fn package(name: &str, source: PackageSource, size: Option<u64>) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.size_bytes = size;
+        package
+    }
+
+    fn recommendation(package: &str, severity: RecommendationSeverity, size: u64) -> Recommendation {
+        Recommendation {
+            package: package.to_string(),
+            source: None,
+            reason: "Unused".to_string(),
+            severity,
+            size_recoverable: size,
+            action: CleanupAction::Remove,
+        }
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let packages = [
+            package("wget", PackageSource::Homebrew, Some(4096)),
+            package("jq", PackageSource::Homebrew, None),
+            package("typescript", PackageSource::Npm, Some(1000)),
+        ];
+        let recommendations = [
+            recommendation("wget", RecommendationSeverity::Safe, 4096),
+            recommendation("typescript", RecommendationSeverity::Review, 1000),
+        ];
+        let now = DateTime::parse_from_rfc3339("2025-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        let text = render(&packages, &recommendations, Some(now));
+        assert!(text.starts_with(
+            "# HELP macsweep_packages_total Packages recorded by the last scan of each source.\n\
+             # TYPE macsweep_packages_total gauge\n\
+             macsweep_packages_total{source=\"homebrew\"} 2\n\
+             macsweep_packages_total{source=\"npm\"} 1\n"
+        ));
+        assert!(text.contains("macsweep_package_size_bytes_total{source=\"homebrew\"} 4096\n"));
+        assert!(text.contains(
+            "macsweep_reclaimable_bytes{severity=\"safe\"} 4096\n\
+             macsweep_reclaimable_bytes{severity=\"review\"} 1000\n\
+             macsweep_reclaimable_bytes{severity=\"warning\"} 0\n"
+        ));
+        assert!(text.contains("macsweep_reclaimable_packages{severity=\"review\"} 1\n"));
+        assert!(text.ends_with("# TYPE macsweep_last_scan_timestamp_seconds gauge\nmacsweep_last_scan_timestamp_seconds 1740830400\n"));
+
+        assert!(!render(&[], &[], None).contains("macsweep_last_scan_timestamp_seconds"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        assert_eq!(label_value("homebrew"), "homebrew");
+        assert_eq!(label_value("a\"b\\c\nd\te"), "a\\\"b\\\\c\\nd_e");
+    }
+}
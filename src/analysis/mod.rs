@@ -2,10 +2,34 @@
 pub mod orphans;
 pub mod dependencies;
 pub mod recommendations;
+pub mod leftovers;
+pub mod protected;
+pub mod broken_links;
+pub mod owners;
+pub mod graph;
+pub mod manifests;
+pub mod brewfile;
+pub mod sbom;
+pub mod metrics;
+pub mod trends;
 
-use crate::scanner::Package;
+use std::collections::BTreeMap;
 
+/// Leaves and orphans of the installed packages' dependency graphs, as (name, source) keys
+#[derive(Debug, Clone, Default)]
 pub struct DependencyAnalysis {
-    pub leaves: Vec<String>,
-    pub orphans: Vec<String>,
+    /// Packages no other installed package of their source depends on
+    pub leaves: Vec<(String, String)>,
+    /// Packages installed as dependencies that no explicitly installed package needs any more
+    pub orphans: Vec<(String, String)>,
+    /// Counts for each source, keyed like the sources in `leaves` and `orphans`
+    pub by_source: BTreeMap<String, SourceDependencies>,
+}
+
+/// One source's share of a `DependencyAnalysis`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceDependencies {
+    pub packages: usize,
+    pub leaves: usize,
+    pub orphans: usize,
 }
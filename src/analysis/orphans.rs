@@ -1,13 +1,16 @@
 // Orphan detection for packages
+use super::DependencyAnalysis;
+use crate::scanner::{Package, PackageSource};
+use crate::utils::process::output;
 use anyhow::{Context, Result};
+use std::collections::HashSet;
 use std::process::Command;
+use std::time::Duration;
 
 /// Get orphaned Homebrew packages that can be safely removed
 /// Uses `brew autoremove --dry-run` to find packages no longer needed
-pub fn get_orphaned_brew_packages() -> Result<Vec<String>> {
-    let output = Command::new("brew")
-        .args(["autoremove", "--dry-run"])
-        .output()
+pub fn get_orphaned_brew_packages(timeout: Duration) -> Result<Vec<String>> {
+    let output = output(Command::new("brew").args(["autoremove", "--dry-run"]), timeout)
         .context("Failed to run brew autoremove")?;
 
     let stdout = String::from_utf8(output.stdout)
@@ -33,12 +36,68 @@ pub fn get_orphaned_brew_packages() -> Result<Vec<String>> {
     Ok(orphans)
 }
 
+/// Orphaned packages of every source as (name, source) keys. For Homebrew these are what
+/// `brew autoremove` would remove, falling back to the dependency graph when brew can't be
+/// asked within `timeout`; other sources only have the graph.
+pub fn find_orphans(packages: &[Package], timeout: Duration) -> HashSet<(String, String)> {
+    let analysis = crate::analysis::dependencies::analyze_dependency_tree(packages).unwrap_or_default();
+    let brew_orphans = if packages.iter().any(|p| p.source == PackageSource::Homebrew) {
+        get_orphaned_brew_packages(timeout)
+            .inspect_err(|e| tracing::warn!(command = "brew autoremove", error = %e, "Failed to ask Homebrew for orphans, using the dependency graph"))
+            .ok()
+    } else {
+        None
+    };
+    merge_orphans(&analysis, brew_orphans)
+}
+
+fn merge_orphans(analysis: &DependencyAnalysis, brew_orphans: Option<Vec<String>>) -> HashSet<(String, String)> {
+    let homebrew = PackageSource::Homebrew.to_string();
+    match brew_orphans {
+        Some(names) => analysis.orphans.iter()
+            .filter(|(_, source)| *source != homebrew)
+            .cloned()
+            .chain(names.into_iter().map(|name| (name, homebrew.clone())))
+            .collect(),
+        None => analysis.orphans.iter().cloned().collect(),
+    }
+}
+
+/// Formulae `brew autoremove` would uninstall once `removed` are gone that it wouldn't have
+/// before: dependencies that nothing left installed needs, following the chain until nothing
+/// changes, less those that were unneeded already
+pub fn predict_autoremove<'a>(packages: &'a [Package], removed: &[&Package]) -> Vec<&'a Package> {
+    let unneeded_already: HashSet<&str> = unneeded_once_gone(packages, HashSet::new()).iter().map(|p| p.name.as_str()).collect();
+    let gone = removed.iter()
+        .filter(|p| p.source == PackageSource::Homebrew)
+        .map(|p| p.name.as_str())
+        .collect();
+    unneeded_once_gone(packages, gone).into_iter().filter(|p| !unneeded_already.contains(p.name.as_str())).collect()
+}
+
+/// Dependencies nothing installed needs once the formulae `gone` are removed
+fn unneeded_once_gone<'a: 'b, 'b>(packages: &'a [Package], mut gone: HashSet<&'b str>) -> Vec<&'a Package> {
+    let mut cascade = Vec::new();
+
+    loop {
+        let orphaned: Vec<&Package> = packages.iter()
+            .filter(|p| p.source == PackageSource::Homebrew && p.is_dependency && !gone.contains(p.name.as_str()))
+            .filter(|p| p.dependents.iter().all(|d| gone.contains(d.as_str())))
+            .collect();
+        if orphaned.is_empty() {
+            return cascade;
+        }
+        for package in orphaned {
+            gone.insert(&package.name);
+            cascade.push(package);
+        }
+    }
+}
+
 /// Get top-level Homebrew packages (leaves) that are not dependencies
 /// Uses `brew leaves` to find packages explicitly installed by the user
-pub fn get_brew_leaves() -> Result<Vec<String>> {
-    let output = Command::new("brew")
-        .arg("leaves")
-        .output()
+pub fn get_brew_leaves(timeout: Duration) -> Result<Vec<String>> {
+    let output = output(Command::new("brew").arg("leaves"), timeout)
         .context("Failed to run brew leaves")?;
 
     let stdout = String::from_utf8(output.stdout)
@@ -57,28 +116,78 @@ pub fn get_brew_leaves() -> Result<Vec<String>> {
 mod tests {
     use super::*;
 
+    fn formula(name: &str, is_dependency: bool, dependents: &[&str]) -> Package {
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.is_dependency = is_dependency;
+        package.dependents = dependents.iter().map(|d| d.to_string()).collect();
+        package
+    }
+
+    #[test]
+    fn test_predict_autoremove_follows_chain() {
+        let packages = vec![
+            formula("ffmpeg", false, &[]),
+            formula("x264", true, &["ffmpeg"]),
+            formula("lame", true, &["ffmpeg", "sox"]),
+            formula("sox", false, &[]),
+            formula("libvorbis", true, &["x264"]),
+            formula("openssl@3", true, &["curl"]),
+            formula("curl", false, &[]),
+            // Already unneeded, so not the removals' doing
+            formula("libpng", true, &[]),
+            formula("zlib", true, &["libpng"]),
+        ];
+
+        let removed = [&packages[0]];
+        let cascade: Vec<_> = predict_autoremove(&packages, &removed).iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(cascade, ["x264", "libvorbis"]);
+
+        // Removing both users of lame frees it too
+        let removed = [&packages[0], &packages[3]];
+        let mut cascade: Vec<_> = predict_autoremove(&packages, &removed).iter().map(|p| p.name.as_str()).collect();
+        cascade.sort();
+        assert_eq!(cascade, ["lame", "libvorbis", "x264"]);
+
+        // Packages of other sources never start a cascade
+        let npm_curl = Package::new("curl".to_string(), PackageSource::Npm);
+        assert!(predict_autoremove(&packages, &[&npm_curl]).is_empty());
+    }
+
+    #[test]
+    fn test_merge_orphans_prefers_brew_for_homebrew() {
+        let key = |name: &str, source: &str| (name.to_string(), source.to_string());
+        let analysis = DependencyAnalysis {
+            orphans: vec![key("idna", "pip"), key("libyaml", "homebrew")],
+            ..Default::default()
+        };
+
+        let mut merged: Vec<_> = merge_orphans(&analysis, Some(vec!["x264".to_string()])).into_iter().collect();
+        merged.sort();
+        assert_eq!(merged, [key("idna", "pip"), key("x264", "homebrew")]);
+
+        let mut fallback: Vec<_> = merge_orphans(&analysis, None).into_iter().collect();
+        fallback.sort();
+        assert_eq!(fallback, [key("idna", "pip"), key("libyaml", "homebrew")]);
+    }
+
     #[test]
     #[ignore] // Requires Homebrew to be installed
     fn test_get_orphaned_brew_packages() {
         // This test requires Homebrew to be installed
-        let result = get_orphaned_brew_packages();
+        let result = get_orphaned_brew_packages(Duration::from_secs(60));
         assert!(result.is_ok());
 
-        let orphans = result.unwrap();
         // Can't assert exact count, but should return a Vec
-        assert!(orphans.len() >= 0);
     }
 
     #[test]
     #[ignore] // Requires Homebrew to be installed
     fn test_get_brew_leaves() {
         // This test requires Homebrew to be installed
-        let result = get_brew_leaves();
+        let result = get_brew_leaves(Duration::from_secs(60));
         assert!(result.is_ok());
 
-        let leaves = result.unwrap();
         // Should have at least some top-level packages
         // Can't assert exact count as it varies by system
-        assert!(leaves.len() >= 0);
     }
 }
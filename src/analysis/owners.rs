@@ -0,0 +1,132 @@
+// Which package a file on disk came from, for `macsweep which`
+use super::broken_links::installed_by;
+use crate::scanner::{Package, PackageSource};
+use serde::Serialize;
+use std::path::Path;
+
+/// How a file was tied to its package
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OwnerMatch {
+    /// It is the package's recorded binary or app bundle, or inside it
+    BinaryPath,
+    /// It is inside the directory the package's size is measured from
+    InstallDir,
+    /// It resolves into a Cellar, Caskroom, pipx venv or node_modules directory
+    InstallLocation,
+    /// It is in a bin directory and named like one of the package's executables
+    BinaryName,
+}
+
+impl OwnerMatch {
+    pub fn describe(self) -> &'static str {
+        match self {
+            OwnerMatch::BinaryPath => "the package's recorded binary",
+            OwnerMatch::InstallDir => "inside the package's install directory",
+            OwnerMatch::InstallLocation => "links into the package's install location",
+            OwnerMatch::BinaryName => "named like one of the package's executables",
+        }
+    }
+}
+
+/// The package a file belongs to. `package` is None when the install location names a package
+/// no scan has recorded.
+#[derive(Debug, Clone)]
+pub struct Owner<'a> {
+    pub name: String,
+    pub source: PackageSource,
+    pub package: Option<&'a Package>,
+    pub matched_by: OwnerMatch,
+}
+
+impl<'a> Owner<'a> {
+    fn recorded(package: &'a Package, matched_by: OwnerMatch) -> Self {
+        Self { name: package.name.clone(), source: package.source.clone(), package: Some(package), matched_by }
+    }
+}
+
+/// The package that installed `path`, checking the path itself and, when it is a symlink, where
+/// it leads: recorded binaries and app bundles first, then install directories, then the
+/// install location's layout, and last an executable of the same name in a bin directory
+pub fn find_owner<'a>(path: &Path, packages: &'a [Package]) -> Option<Owner<'a>> {
+    let resolved = path.canonicalize().ok();
+    let candidates: Vec<&Path> = std::iter::once(path).chain(resolved.as_deref()).collect();
+
+    for candidate in &candidates {
+        if let Some(package) = packages.iter().find(|p| p.binary_path.as_ref().is_some_and(|b| candidate.starts_with(b))) {
+            return Some(Owner::recorded(package, OwnerMatch::BinaryPath));
+        }
+    }
+    for candidate in &candidates {
+        if let Some(package) = packages.iter().find(|p| p.size_path.as_ref().is_some_and(|dir| candidate.starts_with(dir))) {
+            return Some(Owner::recorded(package, OwnerMatch::InstallDir));
+        }
+    }
+    for candidate in candidates.iter().rev() {
+        if let Some((name, source)) = installed_by(candidate) {
+            let package = packages.iter().find(|p| p.name == name && p.source == source);
+            return Some(Owner { name, source, package, matched_by: OwnerMatch::InstallLocation });
+        }
+    }
+
+    let in_bin_dir = path.parent().and_then(|dir| dir.file_name()).is_some_and(|dir| dir == "bin" || dir == "sbin");
+    let name = path.file_name()?.to_str()?;
+    packages
+        .iter()
+        .filter(|_| in_bin_dir)
+        .find(|p| p.binaries.iter().any(|b| b == name))
+        .map(|package| Owner::recorded(package, OwnerMatch::BinaryName))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_find_owner() {
+        let root = tempfile::TempDir::new().unwrap();
+        let root = root.path().canonicalize().unwrap();
+        let bin = root.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+
+        let terraform = root.join("Cellar/terraform/1.7.0/bin/terraform");
+        fs::create_dir_all(terraform.parent().unwrap()).unwrap();
+        fs::write(&terraform, b"").unwrap();
+        symlink(&terraform, bin.join("terraform")).unwrap();
+        let packer = root.join("Cellar/packer/1.10.0/bin/packer");
+        fs::create_dir_all(packer.parent().unwrap()).unwrap();
+        fs::write(&packer, b"").unwrap();
+        symlink(&packer, bin.join("packer")).unwrap();
+        fs::write(bin.join("rg"), b"").unwrap();
+        fs::write(bin.join("mystery"), b"").unwrap();
+        let app = root.join("Applications/Slack.app");
+        fs::create_dir_all(app.join("Contents/MacOS")).unwrap();
+
+        let mut formula = Package::new("terraform".to_string(), PackageSource::Homebrew);
+        formula.size_path = Some(root.join("Cellar/terraform"));
+        let mut ripgrep = Package::new("ripgrep".to_string(), PackageSource::Cargo);
+        ripgrep.binaries = vec!["rg".to_string()];
+        let mut slack = Package::new("Slack".to_string(), PackageSource::Applications);
+        slack.binary_path = Some(app.clone());
+        let packages = [formula, ripgrep, slack];
+
+        let owner = find_owner(&bin.join("terraform"), &packages).unwrap();
+        assert_eq!((owner.name.as_str(), owner.matched_by), ("terraform", OwnerMatch::InstallDir));
+        assert!(owner.package.is_some());
+
+        // Not recorded by any scan, but its Cellar path says where it came from
+        let owner = find_owner(&bin.join("packer"), &packages).unwrap();
+        assert_eq!((owner.name.as_str(), owner.source, owner.matched_by), ("packer", PackageSource::Homebrew, OwnerMatch::InstallLocation));
+        assert!(owner.package.is_none());
+
+        let owner = find_owner(&bin.join("rg"), &packages).unwrap();
+        assert_eq!((owner.name.as_str(), owner.matched_by), ("ripgrep", OwnerMatch::BinaryName));
+        let owner = find_owner(&app.join("Contents/MacOS"), &packages).unwrap();
+        assert_eq!((owner.name.as_str(), owner.matched_by), ("Slack", OwnerMatch::BinaryPath));
+
+        assert!(find_owner(&bin.join("mystery"), &packages).is_none());
+        assert!(find_owner(&root.join("rg"), &packages).is_none());
+    }
+}
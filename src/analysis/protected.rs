@@ -0,0 +1,147 @@
+// Packages that cleanup never removes: system-critical tools and the toolchains other sources need
+use crate::scanner::{Package, PackageSource};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+
+/// Tools the system and most workflows rely on, whatever their recorded usage
+const CRITICAL: &[&str] = &[
+    "git", "openssh", "coreutils", "findutils", "python", "bash", "zsh", "fish", "curl",
+    "openssl", "ca-certificates", "gnupg", "xcode", "terminal",
+];
+
+/// Formulae (and the package managers themselves) needed to manage packages of each source
+fn toolchain(source: &PackageSource) -> &'static [&'static str] {
+    match source {
+        PackageSource::Npm => &["node", "npm"],
+        PackageSource::Pip => &["python", "pip"],
+        PackageSource::Pipx => &["python", "pipx"],
+        PackageSource::Cargo => &["rust", "rustup", "rustup-init"],
+        PackageSource::Gem => &["ruby"],
+        PackageSource::Go => &["go"],
+        PackageSource::Composer => &["php", "composer"],
+        _ => &[],
+    }
+}
+
+/// Protected names, lowercased and without `@version`, with why each is protected
+#[derive(Debug, Clone, Default)]
+pub struct ProtectedPackages {
+    reasons: HashMap<String, String>,
+    /// Names protected for one source only, from `source:name` entries in the config
+    source_reasons: HashMap<(PackageSource, String), String>,
+}
+
+/// A `protected_packages` entry: a bare name protects that name from every source, and
+/// `source:name` (e.g. `homebrew:node`) only the package from that source
+pub fn parse_entry(entry: &str) -> Result<(Option<PackageSource>, &str)> {
+    match entry.rsplit_once(':') {
+        Some((source, name)) => {
+            let source = source.parse().with_context(|| format!("Invalid protected package '{}'", entry))?;
+            Ok((Some(source), name))
+        }
+        None => Ok((None, entry)),
+    }
+}
+
+impl ProtectedPackages {
+    /// The built-in list, names from the config file, and the toolchains of the sources that
+    /// have packages installed
+    pub fn new<'a>(configured: &[String], installed_sources: impl IntoIterator<Item = &'a PackageSource>) -> Self {
+        let mut protected = Self::default();
+
+        for name in CRITICAL {
+            protected.add(name, "system-critical".to_string());
+        }
+        for entry in configured {
+            match parse_entry(entry) {
+                Ok((Some(source), name)) => {
+                    protected.source_reasons.entry((source, base_name(name))).or_insert_with(|| "protected in config".to_string());
+                }
+                // Entries are checked when the config is loaded
+                _ => protected.add(entry, "protected in config".to_string()),
+            }
+        }
+        for source in installed_sources {
+            for name in toolchain(source) {
+                protected.add(name, format!("needed by {} packages", source.as_str()));
+            }
+        }
+
+        protected
+    }
+
+    fn add(&mut self, name: &str, reason: String) {
+        self.reasons.entry(base_name(name)).or_insert(reason);
+    }
+
+    /// Why `package` is protected, if it is
+    pub fn reason(&self, package: &Package) -> Option<&str> {
+        let name = base_name(&package.name);
+        self.reasons
+            .get(&name)
+            .or_else(|| self.source_reasons.get(&(package.source.clone(), name)))
+            .map(String::as_str)
+    }
+
+    pub fn is_protected(&self, package: &Package) -> bool {
+        self.reason(package).is_some()
+    }
+}
+
+/// `python@3.12` -> `python`, `OpenSSL@3` -> `openssl`; scoped npm names keep their scope
+fn base_name(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.char_indices().skip(1).find(|(_, c)| *c == '@') {
+        Some((i, _)) => name[..i].to_string(),
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, source: PackageSource) -> Package {
+        Package::new(name.to_string(), source)
+    }
+
+    #[test]
+    fn test_builtin_and_configured() {
+        let protected = ProtectedPackages::new(&["Postgresql@16".to_string()], []);
+
+        assert_eq!(protected.reason(&package("git", PackageSource::Homebrew)), Some("system-critical"));
+        assert!(protected.is_protected(&package("python@3.12", PackageSource::Homebrew)));
+        assert!(protected.is_protected(&package("openssl@3", PackageSource::Homebrew)));
+        assert!(protected.is_protected(&package("Xcode", PackageSource::Applications)));
+        assert_eq!(protected.reason(&package("postgresql@14", PackageSource::Homebrew)), Some("protected in config"));
+        assert!(!protected.is_protected(&package("gitui", PackageSource::Homebrew)));
+        assert!(!protected.is_protected(&package("@angular/cli", PackageSource::Npm)));
+    }
+
+    #[test]
+    fn test_configured_for_one_source() {
+        let protected = ProtectedPackages::new(&["homebrew:node".to_string(), "npm:Typescript".to_string()], []);
+
+        assert_eq!(protected.reason(&package("node@20", PackageSource::Homebrew)), Some("protected in config"));
+        assert!(!protected.is_protected(&package("node", PackageSource::Npm)));
+        assert!(protected.is_protected(&package("typescript", PackageSource::Npm)));
+        assert!(!protected.is_protected(&package("typescript", PackageSource::Homebrew)));
+
+        assert_eq!(parse_entry("@vue/cli").unwrap(), (None, "@vue/cli"));
+        assert_eq!(parse_entry("plugin:asdf:nodejs").unwrap(), (Some(PackageSource::plugin("asdf")), "nodejs"));
+        assert!(parse_entry("brw:node").is_err());
+    }
+
+    #[test]
+    fn test_toolchains_of_installed_sources() {
+        let node = package("node@20", PackageSource::Homebrew);
+        let ruby = package("ruby", PackageSource::Homebrew);
+
+        let without_npm = ProtectedPackages::new(&[], [&PackageSource::Homebrew]);
+        assert!(!without_npm.is_protected(&node));
+
+        let protected = ProtectedPackages::new(&[], [&PackageSource::Homebrew, &PackageSource::Npm]);
+        assert_eq!(protected.reason(&node), Some("needed by npm packages"));
+        assert!(!protected.is_protected(&ruby));
+    }
+}
@@ -1,92 +1,239 @@
 // Cleanup recommendations engine
-use crate::scanner::Package;
-use anyhow::Result;
-use chrono::Utc;
+use super::protected::ProtectedPackages;
+use super::trends::TrendKind;
+use crate::scanner::{Architecture, Package, PackageSource};
+use crate::usage::processes::ProcessSnapshot;
+use crate::usage::UsageConfidence;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Recommendation {
     pub package: String,
+    /// Source of the package, which a formula and a cask of the same name tell apart; None
+    /// for leftovers, which aren't installed packages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PackageSource>,
     pub reason: String,
     pub severity: RecommendationSeverity,
     pub size_recoverable: u64,
+    pub action: CleanupAction,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Recommendation {
+    /// Whether it's about `package`: the same name, and the same source when it has one
+    pub fn is_for(&self, package: &Package) -> bool {
+        self.package == package.name && self.source.as_ref().is_none_or(|source| *source == package.source)
+    }
+
+    /// The package in `packages` it's about
+    pub fn find_package<'a>(&self, packages: &'a [Package]) -> Option<&'a Package> {
+        packages.iter().find(|p| self.is_for(p))
+    }
+}
+
+/// What `clean` does with a recommended package
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CleanupAction {
+    /// Uninstall it
+    #[default]
+    Remove,
+    /// Keep it, removing only the versions left behind by upgrades (`brew cleanup <formula>`)
+    BrewCleanup,
+}
+
+impl CleanupAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CleanupAction::Remove => "remove",
+            CleanupAction::BrewCleanup => "brew-cleanup",
+        }
+    }
+
+    /// Unknown values (from a newer version) are treated as a removal
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "brew-cleanup" => CleanupAction::BrewCleanup,
+            _ => CleanupAction::Remove,
+        }
+    }
+}
+
+/// Ordered from most to least certain, so `Review` as a cap keeps `Safe` and `Review`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 pub enum RecommendationSeverity {
-    Safe,      // Orphaned dependencies - can be removed safely
-    Review,    // Unused 90+ days - should review before removing
-    Warning,   // Unused 30-90 days - check if still needed
+    Safe,      // Orphaned dependencies (or past `safe_days`) - can be removed safely
+    Review,    // Unused 90+ days by default - should review before removing
+    Warning,   // Unused 30-90 days by default - check if still needed
 }
 
-pub fn generate_recommendations(packages: &[Package]) -> Result<Vec<Recommendation>> {
-    let mut recommendations = Vec::new();
-    let now = Utc::now();
+/// Cleanup status of a package as shown by `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PackageStatus {
+    Safe,
+    Review,
+    Warning,
+    /// Protected by the keep tag, never recommended
+    Pinned,
+    /// No longer installed (only shown by `list --include-removed`)
+    Removed,
+}
 
-    // Get orphaned packages from Homebrew
-    let orphan_names = crate::analysis::orphans::get_orphaned_brew_packages()
-        .unwrap_or_else(|_| Vec::new());
-    let orphan_set: std::collections::HashSet<_> = orphan_names.iter()
-        .map(|s| s.as_str())
-        .collect();
+impl RecommendationSeverity {
+    /// One level less urgent, for recommendations built on weak evidence
+    pub fn downgraded(self) -> Self {
+        match self {
+            RecommendationSeverity::Safe => RecommendationSeverity::Review,
+            RecommendationSeverity::Review | RecommendationSeverity::Warning => RecommendationSeverity::Warning,
+        }
+    }
 
-    for package in packages {
-        // Check if package is orphaned
-        if orphan_set.contains(package.name.as_str()) {
-            recommendations.push(Recommendation {
-                package: package.name.clone(),
-                reason: format!("Orphaned dependency - no longer required by any installed package"),
-                severity: RecommendationSeverity::Safe,
-                size_recoverable: package.size_bytes.unwrap_or(0),
-            });
-            continue; // Don't double-count orphans
+    /// One level more urgent
+    pub fn upgraded(self) -> Self {
+        match self {
+            RecommendationSeverity::Safe | RecommendationSeverity::Review => RecommendationSeverity::Safe,
+            RecommendationSeverity::Warning => RecommendationSeverity::Review,
         }
+    }
+}
 
-        // Check if package is unused for extended period
-        if let Some(last_used) = package.last_used {
-            let days_since_use = (now - last_used).num_days();
-
-            if days_since_use >= 180 {
-                // 6+ months unused
-                recommendations.push(Recommendation {
-                    package: package.name.clone(),
-                    reason: format!("Not used in {} days (~{} months)",
-                        days_since_use, days_since_use / 30),
-                    severity: RecommendationSeverity::Review,
-                    size_recoverable: package.size_bytes.unwrap_or(0),
-                });
-            } else if days_since_use >= 90 {
-                // 3-6 months unused
-                recommendations.push(Recommendation {
-                    package: package.name.clone(),
-                    reason: format!("Not used in {} days (~{} months)",
-                        days_since_use, days_since_use / 30),
-                    severity: RecommendationSeverity::Review,
-                    size_recoverable: package.size_bytes.unwrap_or(0),
-                });
-            } else if days_since_use >= 30 {
-                // 1-3 months unused
-                recommendations.push(Recommendation {
-                    package: package.name.clone(),
-                    reason: format!("Not used in {} days", days_since_use),
-                    severity: RecommendationSeverity::Warning,
-                    size_recoverable: package.size_bytes.unwrap_or(0),
-                });
-            }
-        } else {
-            // Never used (no usage data)
-            // Only recommend if it's also large (>100MB)
-            if let Some(size) = package.size_bytes {
-                if size > 100 * 1024 * 1024 { // 100 MB
-                    recommendations.push(Recommendation {
-                        package: package.name.clone(),
-                        reason: format!("No usage data found - {} in size", format_size(size)),
-                        severity: RecommendationSeverity::Review,
-                        size_recoverable: size,
-                    });
+/// When unused packages are recommended, from the `[recommendations]` table of the config file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RecommendationConfig {
+    /// Days unused before a package is worth checking
+    pub warning_days: u32,
+    /// Days unused before a package should be reviewed for removal
+    pub review_days: u32,
+    /// Days unused before a package is safe to remove; unset means age alone never makes it safe
+    pub safe_days: Option<u32>,
+    /// Packages without usage data at least this large (in MB) are recommended for review
+    pub large_size_mb: u64,
+    /// Whether size alone, without usage data, can lead to a recommendation
+    pub size_only: bool,
+    /// Raise the severity for never-used applications that only run under Rosetta
+    pub boost_intel_only: bool,
+}
+
+impl Default for RecommendationConfig {
+    fn default() -> Self {
+        Self { warning_days: 30, review_days: 90, safe_days: None, large_size_mb: 100, size_only: true, boost_intel_only: false }
+    }
+}
+
+impl RecommendationConfig {
+    /// Apply `--thresholds`, e.g. `warning=60,review=180,safe=365,large=500,size-only=false,intel-only=true`
+    pub fn apply_overrides(&mut self, spec: &str) -> Result<()> {
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .with_context(|| format!("Expected KEY=VALUE in --thresholds, got '{}'", pair))?;
+            let value = value.trim();
+            let days = || value.parse::<u32>().with_context(|| format!("Invalid number of days for {}: '{}'", key, value));
+            match key.trim() {
+                "warning" => self.warning_days = days()?,
+                "review" => self.review_days = days()?,
+                "safe" => self.safe_days = if value == "none" { None } else { Some(days()?) },
+                "large" => {
+                    self.large_size_mb = value
+                        .trim_end_matches("MB")
+                        .parse()
+                        .with_context(|| format!("Invalid size in MB for large: '{}'", value))?
+                }
+                "size-only" => {
+                    self.size_only = value.parse().with_context(|| format!("Expected true or false for size-only, got '{}'", value))?
+                }
+                "intel-only" => {
+                    self.boost_intel_only =
+                        value.parse().with_context(|| format!("Expected true or false for intel-only, got '{}'", value))?
                 }
+                other => anyhow::bail!("Unknown threshold '{}' (expected warning, review, safe, large, size-only or intel-only)", other),
             }
         }
+        self.validate()
+    }
+
+    /// Thresholds must rise with certainty: warning < review < safe
+    pub fn validate(&self) -> Result<()> {
+        if self.warning_days >= self.review_days {
+            anyhow::bail!("Warning threshold ({} days) must be below the review threshold ({} days)", self.warning_days, self.review_days);
+        }
+        if let Some(safe_days) = self.safe_days.filter(|&safe_days| safe_days <= self.review_days) {
+            anyhow::bail!("Safe threshold ({} days) must be above the review threshold ({} days)", safe_days, self.review_days);
+        }
+        Ok(())
+    }
+
+    /// (minimum days unused, severity), most certain first
+    fn buckets(&self) -> Vec<(i64, RecommendationSeverity)> {
+        self.safe_days
+            .map(|days| (days, RecommendationSeverity::Safe))
+            .into_iter()
+            .chain([(self.review_days, RecommendationSeverity::Review), (self.warning_days, RecommendationSeverity::Warning)])
+            .map(|(days, severity)| (days as i64, severity))
+            .collect()
+    }
+
+    /// Severity for a package unused for `days_since_use` days, if it's past any threshold
+    fn severity_for(&self, days_since_use: i64) -> Option<RecommendationSeverity> {
+        self.buckets().into_iter().find(|&(days, _)| days_since_use >= days).map(|(_, severity)| severity)
+    }
+}
+
+impl From<RecommendationSeverity> for PackageStatus {
+    fn from(severity: RecommendationSeverity) -> Self {
+        match severity {
+            RecommendationSeverity::Safe => PackageStatus::Safe,
+            RecommendationSeverity::Review => PackageStatus::Review,
+            RecommendationSeverity::Warning => PackageStatus::Warning,
+        }
     }
+}
+
+/// Recommendations for every package that isn't protected or running; brew and `ps` are
+/// given `timeout` to list the orphans and running processes
+pub fn generate_recommendations(
+    packages: &[Package],
+    protected: &ProtectedPackages,
+    config: &RecommendationConfig,
+    timeout: Duration,
+) -> Result<Vec<Recommendation>> {
+    let orphan_set = crate::analysis::orphans::find_orphans(packages, timeout);
+
+    // Never propose removing something that is running right now
+    let running = ProcessSnapshot::capture(timeout).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "Failed to list running processes");
+        ProcessSnapshot::default()
+    });
+
+    Ok(recommend_all(packages.iter().filter(|p| !protected.is_protected(p) && !running.is_running(p)), &orphan_set, config))
+}
+
+/// Like `generate_recommendations`, with the orphan lookup (`orphans::find_orphans`) done by the caller
+/// and no check for running processes
+pub fn generate_recommendations_with_orphans(
+    packages: &[Package],
+    orphans: &HashSet<(String, String)>,
+    protected: &ProtectedPackages,
+    config: &RecommendationConfig,
+) -> Result<Vec<Recommendation>> {
+    Ok(recommend_all(packages.iter().filter(|p| !protected.is_protected(p)), orphans, config))
+}
+
+fn recommend_all<'a>(
+    packages: impl Iterator<Item = &'a Package>,
+    orphans: &HashSet<(String, String)>,
+    config: &RecommendationConfig,
+) -> Vec<Recommendation> {
+    let now = Utc::now();
+    let mut recommendations: Vec<Recommendation> = packages
+        .filter_map(|package| recommend_package(package, orphans, config, now).or_else(|| stale_versions_recommendation(package)))
+        .collect();
 
     // Sort by size (largest first) within each severity level
     recommendations.sort_by(|a, b| {
@@ -111,7 +258,206 @@ pub fn generate_recommendations(packages: &[Package]) -> Result<Vec<Recommendati
         }
     });
 
-    Ok(recommendations)
+    recommendations
+}
+
+/// The recommendation for a single package, if any; `orphans` holds the (name, source) of orphaned packages
+pub fn recommend_package(
+    package: &Package,
+    orphans: &HashSet<(String, String)>,
+    config: &RecommendationConfig,
+    now: DateTime<Utc>,
+) -> Option<Recommendation> {
+    let mut recommendation = base_recommendation(package, orphans, config, now)?;
+
+    // Names like `libyaml` say little on their own
+    if let Some(ref description) = package.description {
+        recommendation.reason = format!("{} ({})", recommendation.reason, description);
+    }
+
+    Some(recommendation)
+}
+
+/// `brew cleanup` for a formula that is kept but has old versions left behind by upgrades
+pub fn stale_versions_recommendation(package: &Package) -> Option<Recommendation> {
+    let size = package.stale_bytes.filter(|&size| size > 0 && !package.stale_versions.is_empty())?;
+    Some(Recommendation {
+        package: package.name.clone(),
+        source: Some(package.source.clone()),
+        reason: format!(
+            "Stale versions {} - run brew cleanup to reclaim {}",
+            package.stale_versions.join(", "),
+            format_size(size)
+        ),
+        severity: RecommendationSeverity::Safe,
+        size_recoverable: size,
+        action: CleanupAction::BrewCleanup,
+    })
+}
+
+fn base_recommendation(
+    package: &Package,
+    orphans: &HashSet<(String, String)>,
+    config: &RecommendationConfig,
+    now: DateTime<Utc>,
+) -> Option<Recommendation> {
+    // Login items and LaunchAgents are in use whenever the Mac is
+    if package.launches_at_login {
+        return None;
+    }
+
+    // Check if package is orphaned
+    if orphans.contains(&(package.name.clone(), package.source.to_string())) {
+        return Some(Recommendation {
+            package: package.name.clone(),
+            source: Some(package.source.clone()),
+            reason: "Orphaned dependency - no longer required by any installed package".to_string(),
+            severity: RecommendationSeverity::Safe,
+            size_recoverable: package.size_bytes.unwrap_or(0),
+            action: CleanupAction::Remove,
+        });
+    }
+
+    // Removing a package other installed packages rely on would break them
+    if !package.dependents.is_empty() {
+        return None;
+    }
+
+    // Check if package is unused for extended period
+    if let Some(last_used) = package.last_used {
+        let days_since_use = (now - last_used).num_days();
+
+        let mut severity = config.severity_for(days_since_use)?;
+        let mut reason = if severity != RecommendationSeverity::Warning {
+            format!("Not used in {} days (~{} months)", days_since_use, days_since_use / 30)
+        } else {
+            format!("Not used in {} days", days_since_use)
+        };
+
+        // An old access time alone may just mean macOS stopped updating it
+        if package.usage_confidence == UsageConfidence::Low {
+            severity = severity.downgraded();
+            reason.push_str(", based on file access time only");
+        }
+        // A habit that stopped says more than the same gap in a tool only used now and then;
+        // the trend alone never makes a package Safe
+        if let Some(trend) = package.usage_trend {
+            match trend.kind() {
+                Some(TrendKind::Dropped) if severity == RecommendationSeverity::Warning => severity = RecommendationSeverity::Review,
+                Some(TrendKind::Occasional) => severity = severity.downgraded(),
+                _ => {}
+            }
+            if let Some(description) = trend.describe() {
+                reason = format!("{} - {}", reason, description);
+            }
+        }
+        if let Some(origin) = install_origin(package) {
+            reason = format!("{} - {}", reason, origin);
+        }
+
+        return Some(Recommendation {
+            package: package.name.clone(),
+            source: Some(package.source.clone()),
+            reason,
+            severity,
+            size_recoverable: package.size_bytes.unwrap_or(0),
+            action: CleanupAction::Remove,
+        });
+    }
+
+    // Never used (no usage data): only recommend if it's also large, or with
+    // `boost_intel_only`, an app that needs Rosetta
+    let large = package.size_bytes.filter(|&size| config.size_only && size > config.large_size_mb * 1024 * 1024);
+    let intel_only = config.boost_intel_only && package.arch == Some(Architecture::X86_64);
+
+    let (mut severity, mut reason) = match (large, intel_only) {
+        (Some(size), _) => (RecommendationSeverity::Review, format!("No usage data found - {} in size", format_size(size))),
+        (None, true) => (RecommendationSeverity::Warning, "No usage data found".to_string()),
+        (None, false) => return None,
+    };
+    if intel_only {
+        if large.is_some() {
+            severity = severity.upgraded();
+        }
+        reason.push_str(" - Intel-only, runs under Rosetta");
+    }
+    if let Some(origin) = install_origin(package) {
+        reason = format!("{} - {}", reason, origin);
+    }
+    Some(Recommendation {
+        package: package.name.clone(),
+        source: Some(package.source.clone()),
+        reason,
+        severity,
+        size_recoverable: package.size_bytes.unwrap_or(0),
+        action: CleanupAction::Remove,
+    })
+}
+
+/// Never-used packages the large-package rule skipped because their size was never measured
+/// (e.g. after `scan --no-size`), though they have a directory `size --missing` could measure
+pub fn unmeasured_never_used(packages: &[Package], protected: &ProtectedPackages, config: &RecommendationConfig) -> usize {
+    if !config.size_only {
+        return 0;
+    }
+    packages
+        .iter()
+        .filter(|p| p.last_used.is_none() && p.size_bytes.is_none() && p.size_path.is_some())
+        .filter(|p| !p.launches_at_login && p.dependents.is_empty() && !protected.is_protected(p))
+        .count()
+}
+
+/// How a formula came to be installed, which Homebrew records; other sources don't say
+fn install_origin(package: &Package) -> Option<&'static str> {
+    (package.source == PackageSource::Homebrew).then_some(if package.is_dependency {
+        "installed as a dependency, never asked for"
+    } else {
+        "installed on request"
+    })
+}
+
+/// Narrows recommendations for `clean --source/--unused/--only-severity/--exclude`
+#[derive(Debug, Clone, Default)]
+pub struct RecommendationFilter {
+    /// Only packages from these sources, as `ScannerRegistry::sources` resolves `--source`
+    pub sources: Option<Vec<PackageSource>>,
+    /// Only packages unused for at least this many days, or never used
+    pub unused_days: Option<u32>,
+    /// Least certain severity to include
+    pub max_severity: Option<RecommendationSeverity>,
+    /// Package name patterns (`python*`) to leave out
+    pub exclude: Vec<String>,
+}
+
+impl RecommendationFilter {
+    /// Keep the recommendations that pass every filter; those for packages not in `packages`
+    /// only survive when no filter needs the package
+    pub fn apply(&self, mut recommendations: Vec<Recommendation>, packages: &[Package], now: DateTime<Utc>) -> Vec<Recommendation> {
+        let cutoff = self.unused_days.map(|days| now - chrono::Duration::days(days as i64));
+
+        recommendations.retain(|r| {
+            if self.max_severity.is_some_and(|max| r.severity > max) {
+                return false;
+            }
+            if self.exclude.iter().any(|pattern| crate::utils::glob::glob_match(pattern, &r.package)) {
+                return false;
+            }
+            if self.sources.is_none() && cutoff.is_none() {
+                return true;
+            }
+
+            let Some(package) = r.find_package(packages) else {
+                return false;
+            };
+            if self.sources.as_ref().is_some_and(|sources| !sources.contains(&package.source)) {
+                return false;
+            }
+            // Never-used packages are past any threshold
+            cutoff.is_none_or(|cutoff| package.last_used.is_none_or(|last_used| last_used < cutoff))
+        });
+
+        recommendations
+    }
 }
 
 fn format_size(bytes: u64) -> String {
@@ -129,6 +475,7 @@ fn format_size(bytes: u64) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::analysis::trends::UsageTrend;
     use chrono::{Duration, Utc};
 
     #[test]
@@ -139,7 +486,7 @@ mod tests {
         package.size_bytes = Some(100 * 1024 * 1024); // 100 MB
 
         let packages = vec![package];
-        let recommendations = generate_recommendations(&packages).unwrap();
+        let recommendations = generate_recommendations(&packages, &ProtectedPackages::default(), &RecommendationConfig::default(), std::time::Duration::from_secs(60)).unwrap();
 
         assert_eq!(recommendations.len(), 1);
         assert_eq!(recommendations[0].package, "old-package");
@@ -154,7 +501,7 @@ mod tests {
         package.size_bytes = Some(50 * 1024 * 1024);
 
         let packages = vec![package];
-        let recommendations = generate_recommendations(&packages).unwrap();
+        let recommendations = generate_recommendations(&packages, &ProtectedPackages::default(), &RecommendationConfig::default(), std::time::Duration::from_secs(60)).unwrap();
 
         // Should not recommend removal for recently used packages
         assert_eq!(recommendations.len(), 0);
@@ -176,7 +523,7 @@ mod tests {
         warning_pkg.size_bytes = Some(50 * 1024 * 1024);
 
         let packages = vec![warning_pkg, review_pkg, safe_pkg];
-        let recommendations = generate_recommendations(&packages).unwrap();
+        let recommendations = generate_recommendations(&packages, &ProtectedPackages::default(), &RecommendationConfig::default(), std::time::Duration::from_secs(60)).unwrap();
 
         // Should be ordered by severity: Safe first, then Review, then Warning
         // Within same severity, ordered by size (largest first)
@@ -191,6 +538,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_protected_packages_are_not_recommended() {
+        let now = Utc::now();
+        let unused = |name: &str, source| {
+            let mut package = crate::scanner::Package::new(name.to_string(), source);
+            package.last_used = Some(now - Duration::days(400));
+            package.size_bytes = Some(300 * 1024 * 1024);
+            package
+        };
+        let packages = vec![
+            unused("git", crate::scanner::PackageSource::Homebrew),
+            unused("node", crate::scanner::PackageSource::Homebrew),
+            unused("left-pad", crate::scanner::PackageSource::Npm),
+            unused("terraform", crate::scanner::PackageSource::Homebrew),
+            unused("sqlite", crate::scanner::PackageSource::Homebrew),
+        ];
+        let protected = ProtectedPackages::new(&["terraform".to_string()], packages.iter().map(|p| &p.source));
+
+        let orphans: HashSet<_> = ["sqlite", "git"].into_iter().map(|name| (name.to_string(), "homebrew".to_string())).collect();
+        let recommendations = generate_recommendations_with_orphans(&packages, &orphans, &protected, &RecommendationConfig::default()).unwrap();
+        let names: Vec<_> = recommendations.iter().map(|r| r.package.as_str()).collect();
+        assert_eq!(names, ["sqlite", "left-pad"]);
+    }
+
+    #[test]
+    fn test_stale_versions_of_kept_formulae() {
+        let now = Utc::now();
+        let formula = |name: &str, last_used_days: i64, stale: &[&str]| {
+            let mut package = crate::scanner::Package::new(name.to_string(), crate::scanner::PackageSource::Homebrew);
+            package.last_used = Some(now - Duration::days(last_used_days));
+            package.size_bytes = Some(80 * 1024 * 1024);
+            package.stale_versions = stale.iter().map(|v| v.to_string()).collect();
+            package.stale_bytes = Some(stale.len() as u64 * 40 * 1024 * 1024);
+            package
+        };
+        let packages = vec![
+            formula("node", 2, &["21.7.1", "22.2.0"]),
+            formula("jq", 2, &[]),
+            // Recommended for removal anyway, which takes the old versions too
+            formula("php", 120, &["8.2.0"]),
+        ];
+
+        let recommendations = generate_recommendations_with_orphans(&packages, &HashSet::new(), &ProtectedPackages::default(), &RecommendationConfig::default()).unwrap();
+        assert_eq!(recommendations.len(), 2);
+        assert_eq!(recommendations[0].package, "node");
+        assert_eq!(recommendations[0].action, CleanupAction::BrewCleanup);
+        assert_eq!(recommendations[0].severity, RecommendationSeverity::Safe);
+        assert_eq!(recommendations[0].size_recoverable, 80 * 1024 * 1024);
+        assert_eq!(recommendations[0].reason, "Stale versions 21.7.1, 22.2.0 - run brew cleanup to reclaim 80.0 MB");
+        assert_eq!(recommendations[1].package, "php");
+        assert_eq!(recommendations[1].action, CleanupAction::Remove);
+
+        assert_eq!(CleanupAction::parse(CleanupAction::BrewCleanup.as_str()), CleanupAction::BrewCleanup);
+        assert_eq!(CleanupAction::parse("remove"), CleanupAction::Remove);
+    }
+
     #[test]
     fn test_large_unused_package_recommendation() {
         let mut package = crate::scanner::Package::new("large-unused".to_string(), crate::scanner::PackageSource::Homebrew);
@@ -198,7 +601,7 @@ mod tests {
         package.size_bytes = Some(150 * 1024 * 1024); // 150 MB
 
         let packages = vec![package];
-        let recommendations = generate_recommendations(&packages).unwrap();
+        let recommendations = generate_recommendations(&packages, &ProtectedPackages::default(), &RecommendationConfig::default(), std::time::Duration::from_secs(60)).unwrap();
 
         // Large packages without usage data should be recommended for review
         assert_eq!(recommendations.len(), 1);
@@ -206,6 +609,232 @@ mod tests {
         assert!(recommendations[0].reason.contains("No usage data"));
     }
 
+    #[test]
+    fn test_custom_thresholds() {
+        let now = Utc::now();
+        let config = RecommendationConfig { warning_days: 60, review_days: 180, safe_days: Some(365), ..Default::default() };
+        let unused = |days: i64| {
+            let mut package = crate::scanner::Package::new("old-tool".to_string(), crate::scanner::PackageSource::Pip);
+            package.last_used = Some(now - Duration::days(days));
+            package
+        };
+        let severity = |package: &Package| recommend_package(package, &HashSet::new(), &config, now).map(|r| r.severity);
+
+        assert_eq!(severity(&unused(45)), None);
+        assert_eq!(severity(&unused(100)), Some(RecommendationSeverity::Warning));
+        assert_eq!(severity(&unused(200)), Some(RecommendationSeverity::Review));
+        assert_eq!(severity(&unused(400)), Some(RecommendationSeverity::Safe));
+        assert_eq!(recommend_package(&unused(100), &HashSet::new(), &config, now).unwrap().reason, "Not used in 100 days");
+
+        // Weak evidence still costs a level
+        let mut package = unused(400);
+        package.usage_confidence = UsageConfidence::Low;
+        assert_eq!(severity(&package), Some(RecommendationSeverity::Review));
+
+        // The defaults never call an unused package safe
+        assert_eq!(
+            recommend_package(&unused(400), &HashSet::new(), &RecommendationConfig::default(), now).unwrap().severity,
+            RecommendationSeverity::Review
+        );
+    }
+
+    #[test]
+    fn test_size_cutoff_and_size_only() {
+        let now = Utc::now();
+        let mut package = crate::scanner::Package::new("large-unused".to_string(), crate::scanner::PackageSource::Npm);
+        package.size_bytes = Some(300 * 1024 * 1024);
+
+        let config = RecommendationConfig { large_size_mb: 500, ..Default::default() };
+        assert!(recommend_package(&package, &HashSet::new(), &config, now).is_none());
+        package.size_bytes = Some(600 * 1024 * 1024);
+        assert_eq!(recommend_package(&package, &HashSet::new(), &config, now).unwrap().severity, RecommendationSeverity::Review);
+
+        let config = RecommendationConfig { size_only: false, ..Default::default() };
+        assert!(recommend_package(&package, &HashSet::new(), &config, now).is_none());
+    }
+
+    #[test]
+    fn test_unmeasured_packages_skip_the_size_rule() {
+        let now = Utc::now();
+        let mut app = crate::scanner::Package::new("Blender".to_string(), crate::scanner::PackageSource::Applications);
+        app.size_path = Some(std::path::PathBuf::from("/Applications/Blender.app"));
+        let config = RecommendationConfig::default();
+        let protected = ProtectedPackages::default();
+        assert!(recommend_package(&app, &HashSet::new(), &config, now).is_none());
+        assert_eq!(unmeasured_never_used(std::slice::from_ref(&app), &protected, &config), 1);
+
+        let without_size_rule = RecommendationConfig { size_only: false, ..Default::default() };
+        assert_eq!(unmeasured_never_used(std::slice::from_ref(&app), &protected, &without_size_rule), 0);
+        let blender_protected = ProtectedPackages::new(&["Blender".to_string()], [&PackageSource::Applications]);
+        assert_eq!(unmeasured_never_used(std::slice::from_ref(&app), &blender_protected, &config), 0);
+        app.size_bytes = Some(12 * 1024 * 1024 * 1024);
+        assert_eq!(unmeasured_never_used(&[app], &protected, &config), 0);
+    }
+
+    #[test]
+    fn test_never_used_intel_only_apps_are_boosted() {
+        let now = Utc::now();
+        let mut app = crate::scanner::Package::new("Old Game".to_string(), crate::scanner::PackageSource::Applications);
+        app.arch = Some(Architecture::X86_64);
+        app.size_bytes = Some(20 * 1024 * 1024);
+        let boost = RecommendationConfig { boost_intel_only: true, ..Default::default() };
+
+        // Off by default
+        assert!(recommend_package(&app, &HashSet::new(), &RecommendationConfig::default(), now).is_none());
+
+        let recommendation = recommend_package(&app, &HashSet::new(), &boost, now).unwrap();
+        assert_eq!(recommendation.severity, RecommendationSeverity::Warning);
+        assert_eq!(recommendation.reason, "No usage data found - Intel-only, runs under Rosetta");
+
+        app.size_bytes = Some(500 * 1024 * 1024);
+        assert_eq!(recommend_package(&app, &HashSet::new(), &boost, now).unwrap().severity, RecommendationSeverity::Safe);
+
+        // Universal apps and apps that have been used are left alone
+        app.arch = Some(Architecture::Universal);
+        assert_eq!(recommend_package(&app, &HashSet::new(), &boost, now).unwrap().severity, RecommendationSeverity::Review);
+        app.arch = Some(Architecture::X86_64);
+        app.last_used = Some(now - Duration::days(5));
+        assert!(recommend_package(&app, &HashSet::new(), &boost, now).is_none());
+    }
+
+    #[test]
+    fn test_usage_trend_adjusts_severity() {
+        let now = Utc::now();
+        let config = RecommendationConfig { review_days: 120, ..Default::default() };
+        let mut package = crate::scanner::Package::new("kubectl".to_string(), crate::scanner::PackageSource::Homebrew);
+        package.last_used = Some(now - Duration::days(95));
+        let recommend = |package: &Package| recommend_package(package, &HashSet::new(), &config, now).unwrap();
+
+        assert_eq!(recommend(&package).severity, RecommendationSeverity::Warning);
+
+        package.usage_trend = Some(UsageTrend { last_30_days: 0, last_90_days: 0, last_365_days: 180 });
+        let recommendation = recommend(&package);
+        assert_eq!(recommendation.severity, RecommendationSeverity::Review);
+        assert_eq!(
+            recommendation.reason,
+            "Not used in 95 days - usage dropped from ~20×/month to 0 in the last quarter - installed on request"
+        );
+
+        // Used once a year: the same gap is ordinary
+        package.last_used = Some(now - Duration::days(200));
+        package.usage_trend = Some(UsageTrend { last_30_days: 0, last_90_days: 0, last_365_days: 1 });
+        assert_eq!(recommend(&package).severity, RecommendationSeverity::Warning);
+    }
+
+    #[test]
+    fn test_login_items_are_never_recommended() {
+        let now = Utc::now();
+        let mut app = crate::scanner::Package::new("Rectangle".to_string(), crate::scanner::PackageSource::Applications);
+        app.size_bytes = Some(500 * 1024 * 1024);
+        app.last_used = Some(now - Duration::days(400));
+        assert!(recommend_package(&app, &HashSet::new(), &RecommendationConfig::default(), now).is_some());
+
+        app.launches_at_login = true;
+        assert!(recommend_package(&app, &HashSet::new(), &RecommendationConfig::default(), now).is_none());
+        app.last_used = None;
+        assert!(recommend_package(&app, &HashSet::new(), &RecommendationConfig::default(), now).is_none());
+    }
+
+    #[test]
+    fn test_threshold_overrides() {
+        let mut config = RecommendationConfig::default();
+        config.apply_overrides("warning=60, review=180,safe=365,large=500MB,size-only=false,intel-only=true").unwrap();
+        assert_eq!(
+            config,
+            RecommendationConfig {
+                warning_days: 60,
+                review_days: 180,
+                safe_days: Some(365),
+                large_size_mb: 500,
+                size_only: false,
+                boost_intel_only: true,
+            }
+        );
+        config.apply_overrides("safe=none").unwrap();
+        assert_eq!(config.safe_days, None);
+
+        let mut config = RecommendationConfig::default();
+        assert!(config.apply_overrides("review=20").unwrap_err().to_string().contains("must be below"));
+        let mut config = RecommendationConfig::default();
+        assert!(config.apply_overrides("safe=60").unwrap_err().to_string().contains("must be above"));
+        assert!(RecommendationConfig::default().apply_overrides("stale=3").unwrap_err().to_string().contains("Unknown threshold"));
+        assert!(RecommendationConfig::default().apply_overrides("warning").unwrap_err().to_string().contains("KEY=VALUE"));
+    }
+
+    #[test]
+    fn test_reason_includes_description() {
+        let now = Utc::now();
+        let mut package = crate::scanner::Package::new("libyaml".to_string(), crate::scanner::PackageSource::Homebrew);
+        package.description = Some("YAML Parser".to_string());
+
+        let orphans: HashSet<_> = [("libyaml".to_string(), "homebrew".to_string())].into_iter().collect();
+        let recommendation = recommend_package(&package, &orphans, &RecommendationConfig::default(), now).unwrap();
+        assert!(recommendation.reason.starts_with("Orphaned dependency"));
+        assert!(recommendation.reason.ends_with("(YAML Parser)"));
+    }
+
+    #[test]
+    fn test_orphans_match_name_and_source() {
+        let now = Utc::now();
+        let pip_six = crate::scanner::Package::new("six".to_string(), crate::scanner::PackageSource::Pip);
+        let brew_six = crate::scanner::Package::new("six".to_string(), crate::scanner::PackageSource::Homebrew);
+        let orphans: HashSet<_> = [("six".to_string(), "pip".to_string())].into_iter().collect();
+
+        let recommendation = recommend_package(&pip_six, &orphans, &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(recommendation.severity, RecommendationSeverity::Safe);
+        assert!(recommend_package(&brew_six, &orphans, &RecommendationConfig::default(), now).is_none());
+    }
+
+    #[test]
+    fn test_low_confidence_usage_is_downgraded() {
+        let now = Utc::now();
+        let mut package = crate::scanner::Package::new("old-tool".to_string(), crate::scanner::PackageSource::Homebrew);
+        package.last_used = Some(now - Duration::days(200));
+
+        package.usage_confidence = UsageConfidence::High;
+        let rec = recommend_package(&package, &HashSet::new(), &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(rec.severity, RecommendationSeverity::Review);
+        assert!(!rec.reason.contains("access time"));
+
+        package.usage_confidence = UsageConfidence::Low;
+        let rec = recommend_package(&package, &HashSet::new(), &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(rec.severity, RecommendationSeverity::Warning);
+        assert_eq!(rec.reason, "Not used in 200 days (~6 months), based on file access time only - installed on request");
+    }
+
+    #[test]
+    fn test_reason_says_how_formulae_were_installed() {
+        let now = Utc::now();
+        let mut package = crate::scanner::Package::new("protobuf".to_string(), crate::scanner::PackageSource::Homebrew);
+        package.last_used = Some(now - Duration::days(100));
+        package.is_dependency = true;
+        let rec = recommend_package(&package, &HashSet::new(), &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(rec.reason, "Not used in 100 days (~3 months) - installed as a dependency, never asked for");
+
+        package.last_used = None;
+        package.size_bytes = Some(200 * 1024 * 1024);
+        package.is_dependency = false;
+        let rec = recommend_package(&package, &HashSet::new(), &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(rec.reason, "No usage data found - 200.0 MB in size - installed on request");
+
+        // Other sources don't record why a package was installed
+        let mut black = crate::scanner::Package::new("black".to_string(), crate::scanner::PackageSource::Pip);
+        black.last_used = Some(now - Duration::days(100));
+        let rec = recommend_package(&black, &HashSet::new(), &RecommendationConfig::default(), now).unwrap();
+        assert_eq!(rec.reason, "Not used in 100 days (~3 months)");
+    }
+
+    #[test]
+    fn test_packages_with_dependents_are_not_recommended() {
+        let now = Utc::now();
+        let mut package = crate::scanner::Package::new("openssl@3".to_string(), crate::scanner::PackageSource::Homebrew);
+        package.last_used = Some(now - Duration::days(200));
+        package.size_bytes = Some(50 * 1024 * 1024);
+        package.dependents = vec!["wget".to_string()];
+
+        assert!(recommend_package(&package, &HashSet::new(), &RecommendationConfig::default(), now).is_none());
+    }
+
     #[test]
     fn test_format_size() {
         assert_eq!(format_size(0), "0 bytes");
@@ -216,4 +845,70 @@ mod tests {
         assert_eq!(format_size(1024 * 1024 * 1024), "1.0 GB");
         assert_eq!(format_size(1536 * 1024 * 1024), "1.5 GB");
     }
+
+    #[test]
+    fn test_recommendation_filter() {
+        use crate::scanner::{Package, PackageSource};
+
+        let now = Utc::now();
+        let package = |name: &str, source: PackageSource, days: Option<i64>| {
+            let mut package = Package::new(name.to_string(), source);
+            package.last_used = days.map(|d| now - Duration::days(d));
+            package
+        };
+        let recommendation = |name: &str, severity| Recommendation {
+            package: name.to_string(),
+            source: None,
+            reason: String::new(),
+            severity,
+            size_recoverable: 0,
+            action: CleanupAction::Remove,
+        };
+
+        let packages = vec![
+            package("libyaml", PackageSource::Homebrew, None),
+            package("python@3.11", PackageSource::Homebrew, Some(400)),
+            package("node", PackageSource::Homebrew, Some(200)),
+            package("typescript", PackageSource::Npm, Some(100)),
+            package("Zoom", PackageSource::Applications, Some(45)),
+        ];
+        let recommendations = vec![
+            recommendation("libyaml", RecommendationSeverity::Safe),
+            recommendation("python@3.11", RecommendationSeverity::Review),
+            recommendation("node", RecommendationSeverity::Review),
+            recommendation("typescript", RecommendationSeverity::Review),
+            recommendation("Zoom", RecommendationSeverity::Warning),
+            recommendation("not-in-db", RecommendationSeverity::Review),
+        ];
+        let names = |filter: RecommendationFilter| -> Vec<String> {
+            filter.apply(recommendations.clone(), &packages, now).into_iter().map(|r| r.package).collect()
+        };
+
+        assert_eq!(names(RecommendationFilter::default()).len(), 6);
+        assert_eq!(
+            names(RecommendationFilter { unused_days: Some(180), ..Default::default() }),
+            ["libyaml", "python@3.11", "node"]
+        );
+        assert_eq!(
+            names(RecommendationFilter { max_severity: Some(RecommendationSeverity::Safe), ..Default::default() }),
+            ["libyaml"]
+        );
+        assert_eq!(
+            names(RecommendationFilter { max_severity: Some(RecommendationSeverity::Review), ..Default::default() }).len(),
+            5
+        );
+        assert_eq!(
+            names(RecommendationFilter { exclude: vec!["python*".to_string(), "NODE".to_string()], ..Default::default() }),
+            ["libyaml", "typescript", "Zoom", "not-in-db"]
+        );
+
+        // Everything composes, with --source as before
+        let filter = RecommendationFilter {
+            sources: Some(vec![PackageSource::Homebrew, PackageSource::HomebrewCask]),
+            unused_days: Some(180),
+            max_severity: Some(RecommendationSeverity::Review),
+            exclude: vec!["python*".to_string()],
+        };
+        assert_eq!(names(filter), ["libyaml", "node"]);
+    }
 }
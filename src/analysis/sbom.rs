@@ -0,0 +1,224 @@
+// CycloneDX 1.5 inventory of the recorded packages, for `macsweep export --sbom`
+use crate::scanner::{Package, PackageSource};
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bom {
+    pub bom_format: &'static str,
+    pub spec_version: &'static str,
+    pub version: u32,
+    pub metadata: Metadata,
+    pub components: Vec<Component>,
+    pub dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Metadata {
+    pub timestamp: String,
+    pub tools: Tools,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tools {
+    pub components: Vec<Component>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Component {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    #[serde(rename = "bom-ref", skip_serializing_if = "Option::is_none")]
+    pub bom_ref: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Property {
+    pub name: &'static str,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Dependency {
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub depends_on: Vec<String>,
+}
+
+/// The inventory as pretty-printed JSON, every package included
+pub fn render(packages: &[Package], now: DateTime<Utc>) -> Result<String> {
+    let json = serde_json::to_string_pretty(&bom(packages, now)).context("Failed to serialize the SBOM")?;
+    Ok(json + "\n")
+}
+
+/// Every package as a component with its purl, and for each one the installed packages of
+/// its source it depends on. Components are referred to as `source:name`, which unlike purls
+/// can't collide (a pip and a pipx package of the same name share one).
+pub fn bom(packages: &[Package], now: DateTime<Utc>) -> Bom {
+    let bom_ref = |name: &str, source: &PackageSource| format!("{}:{}", source, name);
+    let installed: HashSet<(&str, &PackageSource)> = packages.iter().map(|p| (p.name.as_str(), &p.source)).collect();
+    let components = packages
+        .iter()
+        .map(|p| Component {
+            kind: component_type(&p.source),
+            bom_ref: Some(bom_ref(&p.name, &p.source)),
+            name: p.name.clone(),
+            version: p.version.clone(),
+            description: p.description.clone(),
+            purl: Some(purl(p)),
+            properties: vec![Property { name: "macsweep:source", value: p.source.to_string() }],
+        })
+        .collect();
+    let dependencies = packages
+        .iter()
+        .map(|p| Dependency {
+            reference: bom_ref(&p.name, &p.source),
+            // Dependencies that aren't installed have no component to point at
+            depends_on: p
+                .dependencies
+                .iter()
+                .filter(|d| installed.contains(&(d.as_str(), &p.source)))
+                .map(|d| bom_ref(d, &p.source))
+                .collect(),
+        })
+        .collect();
+
+    Bom {
+        bom_format: "CycloneDX",
+        spec_version: "1.5",
+        version: 1,
+        metadata: Metadata {
+            timestamp: now.to_rfc3339_opts(SecondsFormat::Secs, true),
+            tools: Tools {
+                components: vec![Component {
+                    kind: "application",
+                    bom_ref: None,
+                    name: "macsweep".to_string(),
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
+                    description: None,
+                    purl: None,
+                    properties: Vec::new(),
+                }],
+            },
+        },
+        components,
+        dependencies,
+    }
+}
+
+/// Language packages are libraries; everything else installs something to run
+fn component_type(source: &PackageSource) -> &'static str {
+    match source {
+        PackageSource::Npm | PackageSource::Pip | PackageSource::Gem | PackageSource::Composer => "library",
+        _ => "application",
+    }
+}
+
+/// The package URL, e.g. `pkg:npm/%40vue/cli@5.0.8`. Applications, which no package
+/// ecosystem names, are `pkg:generic/` with their bundle identifier when one is recorded, and
+/// plugins' packages are `pkg:generic/` too, qualified by the plugin.
+pub fn purl(package: &Package) -> String {
+    let name = package.name.as_str();
+    let plugin_qualifier;
+    let (kind, path, qualifiers) = match package.source {
+        PackageSource::Homebrew => ("brew", encode(name), ""),
+        PackageSource::HomebrewCask => ("brew", encode(name), "?type=cask"),
+        PackageSource::Npm => match name.strip_prefix('@').and_then(|scoped| scoped.split_once('/')) {
+            Some((scope, name)) => ("npm", format!("%40{}/{}", encode(scope), encode(name)), ""),
+            None => ("npm", encode(name), ""),
+        },
+        // PyPI names compare case-insensitively with `-`, `_` and `.` alike
+        PackageSource::Pip | PackageSource::Pipx => ("pypi", encode(&name.to_lowercase().replace(['_', '.'], "-")), ""),
+        PackageSource::Cargo => ("cargo", encode(name), ""),
+        PackageSource::Gem => ("gem", encode(name), ""),
+        PackageSource::Go => ("golang", name.split('/').map(encode).collect::<Vec<_>>().join("/"), ""),
+        PackageSource::Composer => ("composer", name.split('/').map(encode).collect::<Vec<_>>().join("/"), ""),
+        PackageSource::Applications | PackageSource::MacAppStore | PackageSource::LocalBin => {
+            ("generic", encode(package.bundle_id.as_deref().unwrap_or(name)), "")
+        }
+        PackageSource::Plugin(_) => {
+            plugin_qualifier = format!("?macsweep_plugin={}", encode(package.source.plugin_name().unwrap_or_default()));
+            ("generic", encode(name), plugin_qualifier.as_str())
+        }
+    };
+    match package.version.as_deref().filter(|v| !v.is_empty()) {
+        Some(version) => format!("pkg:{}/{}@{}{}", kind, path, encode(version), qualifiers),
+        None => format!("pkg:{}/{}{}", kind, path, qualifiers),
+    }
+}
+
+/// Percent-encode everything but the characters purl leaves as they are
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b".-_~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, source: PackageSource, version: Option<&str>) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.version = version.map(str::to_string);
+        package
+    }
+
+    #[test]
+    fn test_purls() {
+        assert_eq!(purl(&package("jq", PackageSource::Homebrew, Some("1.7.1"))), "pkg:brew/jq@1.7.1");
+        assert_eq!(purl(&package("openssl@3", PackageSource::Homebrew, Some("3.3.1_1"))), "pkg:brew/openssl%403@3.3.1_1");
+        assert_eq!(purl(&package("firefox", PackageSource::HomebrewCask, Some("128.0"))), "pkg:brew/firefox@128.0?type=cask");
+        assert_eq!(purl(&package("@vue/cli", PackageSource::Npm, Some("5.0.8"))), "pkg:npm/%40vue/cli@5.0.8");
+        assert_eq!(purl(&package("Django_Extensions", PackageSource::Pip, None)), "pkg:pypi/django-extensions");
+        assert_eq!(purl(&package("httpie", PackageSource::Pipx, Some("3.2.2"))), "pkg:pypi/httpie@3.2.2");
+        assert_eq!(purl(&package("ripgrep", PackageSource::Cargo, Some("14.1.0+local"))), "pkg:cargo/ripgrep@14.1.0%2Blocal");
+
+        let mut slack = package("Slack", PackageSource::Applications, Some("4.39.95"));
+        assert_eq!(purl(&slack), "pkg:generic/Slack@4.39.95");
+        slack.bundle_id = Some("com.tinyspeck.slackmacgap".to_string());
+        assert_eq!(purl(&slack), "pkg:generic/com.tinyspeck.slackmacgap@4.39.95");
+        assert_eq!(purl(&package("Visual Studio Code", PackageSource::Applications, None)), "pkg:generic/Visual%20Studio%20Code");
+        assert_eq!(
+            purl(&package("sdk-java", PackageSource::plugin("corp-sdk"), Some("21"))),
+            "pkg:generic/sdk-java@21?macsweep_plugin=corp-sdk"
+        );
+    }
+
+    #[test]
+    fn test_bom_links_installed_dependencies() {
+        let mut wget = package("wget", PackageSource::Homebrew, Some("1.24.5"));
+        wget.dependencies = vec!["openssl@3".to_string(), "libidn2".to_string()];
+        let openssl = package("openssl@3", PackageSource::Homebrew, Some("3.3.1"));
+        // Same name, other source: not what wget depends on
+        let libidn2 = package("libidn2", PackageSource::Npm, Some("1.0.0"));
+        let now = DateTime::parse_from_rfc3339("2025-03-01T12:00:00Z").unwrap().with_timezone(&Utc);
+
+        let bom = bom(&[wget, openssl, libidn2], now);
+        assert_eq!(bom.metadata.timestamp, "2025-03-01T12:00:00Z");
+        assert_eq!(bom.components.iter().map(|c| c.kind).collect::<Vec<_>>(), ["application", "application", "library"]);
+        assert_eq!(bom.components[1].purl.as_deref(), Some("pkg:brew/openssl%403@3.3.1"));
+        assert_eq!(bom.dependencies[0].reference, "homebrew:wget");
+        assert_eq!(bom.dependencies[0].depends_on, ["homebrew:openssl@3"]);
+        assert!(bom.dependencies[1].depends_on.is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+// Usage trends over trailing windows, from the usage events recorded by scheduled scans
+use chrono::{DateTime, Datelike, Months, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Event types that mean the package was actually run, by hand or by a crontab entry or
+/// LaunchAgent; file access times and manual marks say too little about how often
+pub const USE_EVENT_TYPES: &[&str] = &["shell", "spotlight", "process", "scheduled"];
+
+/// Uses per month a package needs before the last quarter for a stop to count as a drop
+const HEAVY_USES_PER_MONTH: f64 = 4.0;
+
+/// Days with a recorded use in the trailing 30, 90 and 365 days
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageTrend {
+    pub last_30_days: u32,
+    pub last_90_days: u32,
+    pub last_365_days: u32,
+}
+
+/// What a package's trend says about a long gap since its last use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendKind {
+    /// Used heavily until the last quarter, then not at all: the habit ended
+    Dropped,
+    /// Used only a few times a year: a long gap is normal for it
+    Occasional,
+}
+
+impl UsageTrend {
+    /// Average uses per month in the nine months before the last quarter
+    pub fn earlier_per_month(&self) -> f64 {
+        self.last_365_days.saturating_sub(self.last_90_days) as f64 / 9.0
+    }
+
+    pub fn kind(&self) -> Option<TrendKind> {
+        let earlier = self.last_365_days.saturating_sub(self.last_90_days);
+        if self.last_90_days > 0 || earlier == 0 {
+            return None;
+        }
+        if self.earlier_per_month() >= HEAVY_USES_PER_MONTH {
+            Some(TrendKind::Dropped)
+        } else if earlier < 9 {
+            Some(TrendKind::Occasional)
+        } else {
+            None
+        }
+    }
+
+    /// Why the trend matters, for a recommendation's reason
+    pub fn describe(&self) -> Option<String> {
+        let earlier = self.last_365_days - self.last_90_days;
+        match self.kind()? {
+            TrendKind::Dropped => Some(format!(
+                "usage dropped from ~{}×/month to 0 in the last quarter",
+                self.earlier_per_month().round()
+            )),
+            TrendKind::Occasional => Some(format!(
+                "used only {} {} in the nine months before, so long gaps are usual",
+                earlier,
+                if earlier == 1 { "time" } else { "times" }
+            )),
+        }
+    }
+}
+
+/// Uses recorded in one calendar month
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MonthlyUsage {
+    /// `YYYY-MM`
+    pub month: String,
+    pub uses: u32,
+}
+
+/// The twelve months up to and including `now`'s, oldest first, with the counts from
+/// `counts` (as returned by `database::get_monthly_usage`) and zero for months without uses
+pub fn last_twelve_months(counts: &[MonthlyUsage], now: DateTime<Utc>) -> Vec<MonthlyUsage> {
+    let this_month = now.date_naive().with_day(1).unwrap_or(now.date_naive());
+    (0..12)
+        .rev()
+        .filter_map(|back| this_month.checked_sub_months(Months::new(back)))
+        .map(|date| {
+            let month = date.format("%Y-%m").to_string();
+            let uses = counts.iter().find(|c| c.month == month).map_or(0, |c| c.uses);
+            MonthlyUsage { month, uses }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn trend(last_30_days: u32, last_90_days: u32, last_365_days: u32) -> UsageTrend {
+        UsageTrend { last_30_days, last_90_days, last_365_days }
+    }
+
+    #[test]
+    fn test_trend_kinds() {
+        // ~20 uses a month until three months ago
+        let dropped = trend(0, 0, 180);
+        assert_eq!(dropped.kind(), Some(TrendKind::Dropped));
+        assert_eq!(dropped.describe().unwrap(), "usage dropped from ~20×/month to 0 in the last quarter");
+
+        let yearly = trend(0, 0, 1);
+        assert_eq!(yearly.kind(), Some(TrendKind::Occasional));
+        assert_eq!(yearly.describe().unwrap(), "used only 1 time in the nine months before, so long gaps are usual");
+
+        // Still in use, never seen, or too regular to call either way
+        assert_eq!(trend(2, 10, 60).kind(), None);
+        assert_eq!(trend(0, 0, 0).kind(), None);
+        assert_eq!(trend(0, 0, 20).kind(), None);
+    }
+
+    #[test]
+    fn test_last_twelve_months_fills_gaps() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 31, 12, 0, 0).unwrap();
+        let counts = vec![
+            MonthlyUsage { month: "2024-04".to_string(), uses: 3 },
+            MonthlyUsage { month: "2025-01".to_string(), uses: 21 },
+        ];
+
+        let months = last_twelve_months(&counts, now);
+        assert_eq!(months.len(), 12);
+        assert_eq!(months[0], MonthlyUsage { month: "2024-04".to_string(), uses: 3 });
+        assert_eq!(months[9], MonthlyUsage { month: "2025-01".to_string(), uses: 21 });
+        assert_eq!(months[11], MonthlyUsage { month: "2025-03".to_string(), uses: 0 });
+    }
+}
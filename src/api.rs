@@ -0,0 +1,182 @@
+// The few calls an embedding app needs: scan into the database, read the packages back, and
+// ask what could be removed. Nothing here prints; the CLI's scan, list, stats and clean
+// commands go through the same functions.
+use crate::analysis::protected::ProtectedPackages;
+use crate::analysis::recommendations::{generate_recommendations, Recommendation, RecommendationConfig};
+use crate::config::Config;
+use crate::progress::{NoProgress, ProgressReporter};
+use crate::scan::{ScanEvent, ScanResult};
+use crate::scanner::registry::ScannerRegistry;
+use crate::scanner::Package;
+use crate::storage::{database, Database};
+use crate::error::Result;
+use crate::utils::process::{CommandKind, CommandTimeouts};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// The tag that keeps a package from being recommended, unless the CLI's `--keep-tag` says otherwise
+pub const KEEP_TAG: &str = "keep";
+
+/// What to scan and where to keep the results
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Names `--source` accepts, e.g. "brew" or "npm"; empty scans every source
+    pub sources: Vec<String>,
+    /// Skip the usage lookups, keeping the usage recorded by earlier scans
+    pub quick: bool,
+    /// Measure every directory again instead of reusing sizes of unmodified ones
+    pub recalculate_sizes: bool,
+    /// Measure no sizes at all, keeping the sizes recorded by earlier scans
+    pub skip_sizes: bool,
+    /// Skip sources whose change markers (e.g. Homebrew's Cellar) are unmodified since their
+    /// last scan, keeping their packages as recorded
+    pub changed_only: bool,
+    /// The database to use; None for `Database::default_path`
+    pub db_path: Option<PathBuf>,
+    /// Usage sources and recommendation thresholds, e.g. from `Config::load`
+    pub config: Config,
+}
+
+impl ScanOptions {
+    /// Open (creating if needed) the database the options point at
+    pub fn open_database(&self) -> Result<Database> {
+        let path = match &self.db_path {
+            Some(path) => path.clone(),
+            None => Database::default_path()?,
+        };
+        Database::open(&path)
+    }
+}
+
+/// A scan and what it leaves to clean up
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+    pub scan: ScanResult,
+    /// Every installed package in the database after the scan, not only the scanned sources'
+    pub packages: Vec<Package>,
+    pub recommendations: Vec<Recommendation>,
+}
+
+/// Scan the packages `options` asks for and save them to its database.
+///
+/// ```
+/// use macsweep::api::{load_packages, scan_and_store, ScanOptions};
+///
+/// let dir = tempfile::tempdir()?;
+/// let options = ScanOptions {
+///     sources: vec!["cargo".to_string()],
+///     quick: true,
+///     db_path: Some(dir.path().join("macsweep.db")),
+///     ..Default::default()
+/// };
+/// let result = scan_and_store(&options)?;
+/// assert_eq!(result.sources.len(), 1);
+/// assert_eq!(load_packages(&options.open_database()?)?.len(), result.packages.len());
+/// # Ok::<(), macsweep::error::MacsweepError>(())
+/// ```
+pub fn scan_and_store(options: &ScanOptions) -> Result<ScanResult> {
+    scan_and_store_with(&mut options.open_database()?, options, &NoProgress, &|_| {})
+}
+
+/// `scan_and_store` into an open database, reporting progress and events as `scan::scan` does;
+/// `options.db_path` is not used
+pub fn scan_and_store_with(
+    db: &mut Database,
+    options: &ScanOptions,
+    progress: &dyn ProgressReporter,
+    on_event: &(dyn Fn(ScanEvent) + Sync),
+) -> Result<ScanResult> {
+    // A mistyped source fails before anything is scanned
+    ScannerRegistry::default().select_all(&options.sources)?;
+    Ok(crate::scan::scan(db, options, progress, on_event)?)
+}
+
+/// The installed packages recorded by earlier scans
+///
+/// ```
+/// use macsweep::api::load_packages;
+/// use macsweep::storage::Database;
+///
+/// let dir = tempfile::tempdir()?;
+/// let db = Database::open(&dir.path().join("macsweep.db"))?;
+/// assert!(load_packages(&db)?.is_empty());
+/// # Ok::<(), macsweep::error::MacsweepError>(())
+/// ```
+pub fn load_packages(db: &Database) -> Result<Vec<Package>> {
+    database::get_packages(db.conn())
+}
+
+/// (name, source) of the packages tagged `tag`, for `recommendations_excluding`
+pub fn kept_packages(db: &Database, tag: &str) -> Result<HashSet<(String, String)>> {
+    database::get_tagged_packages(db.conn(), tag)
+}
+
+/// What could be removed from `packages`, safest and largest first. Packages macsweep always
+/// protects (and anything running right now) are left out. brew and `ps` get the default
+/// scanner timeout; `recommendations_excluding` takes the configured ones.
+///
+/// ```
+/// use chrono::{Duration, Utc};
+/// use macsweep::analysis::recommendations::{RecommendationConfig, RecommendationSeverity};
+/// use macsweep::api::recommendations_for;
+/// use macsweep::scanner::{Package, PackageSource};
+///
+/// let mut unused = Package::new("left-pad".to_string(), PackageSource::Npm);
+/// unused.last_used = Some(Utc::now() - Duration::days(400));
+/// unused.size_bytes = Some(4096);
+///
+/// let recommendations = recommendations_for(&[unused], &RecommendationConfig::default())?;
+/// assert_eq!(recommendations[0].package, "left-pad");
+/// assert_eq!(recommendations[0].severity, RecommendationSeverity::Review);
+/// # Ok::<(), macsweep::error::MacsweepError>(())
+/// ```
+pub fn recommendations_for(packages: &[Package], config: &RecommendationConfig) -> Result<Vec<Recommendation>> {
+    recommendations_excluding(packages, config, &[], &HashSet::new(), &CommandTimeouts::default())
+}
+
+/// `recommendations_for`, also leaving out the `protected` names (as in the config file's
+/// `protected_packages`) and the packages in `kept`, by (name, source), e.g. those tagged `keep`,
+/// with the scanner timeout of `timeouts` for brew and `ps`
+pub fn recommendations_excluding(
+    packages: &[Package],
+    config: &RecommendationConfig,
+    protected: &[String],
+    kept: &HashSet<(String, String)>,
+    timeouts: &CommandTimeouts,
+) -> Result<Vec<Recommendation>> {
+    let protected = ProtectedPackages::new(protected, packages.iter().map(|p| &p.source));
+    let mut recommendations = generate_recommendations(packages, &protected, config, timeouts.get(CommandKind::Scanner))?;
+    recommendations.retain(|r| !r.source.as_ref().is_some_and(|source| kept.contains(&(r.package.clone(), source.to_string()))));
+    Ok(recommendations)
+}
+
+/// Scan, then recommend from everything in the database with `options.config`'s thresholds and
+/// protected packages, leaving out packages tagged `keep`.
+///
+/// ```
+/// use macsweep::api::{scan_and_recommend, ScanOptions};
+///
+/// let dir = tempfile::tempdir()?;
+/// let options = ScanOptions {
+///     sources: vec!["cargo".to_string()],
+///     quick: true,
+///     db_path: Some(dir.path().join("macsweep.db")),
+///     ..Default::default()
+/// };
+/// let report = scan_and_recommend(&options)?;
+/// assert!(report.recommendations.len() <= report.packages.len());
+/// # Ok::<(), macsweep::error::MacsweepError>(())
+/// ```
+pub fn scan_and_recommend(options: &ScanOptions) -> Result<ScanReport> {
+    let mut db = options.open_database()?;
+    let scan = scan_and_store_with(&mut db, options, &NoProgress, &|_| {})?;
+    let packages = load_packages(&db)?;
+    let recommendations = recommendations_excluding(
+        &packages,
+        &options.config.recommendations,
+        &options.config.protected_packages,
+        &kept_packages(&db, KEEP_TAG)?,
+        &options.config.timeouts,
+    )?;
+    Ok(ScanReport { scan, packages, recommendations })
+}
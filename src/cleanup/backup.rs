@@ -1,17 +1,38 @@
 // Backup and undo support for cleanup operations
-use anyhow::{Context, Result};
+use crate::error::{Context, MacsweepError, Result};
+use crate::cleanup::log::CommandRecord;
 use crate::scanner::{Package, PackageSource};
+use crate::utils::process::{run_command_with_timeout, CommandKind, CommandTimeouts};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BackupManifest {
     pub backup_id: String,
     pub created_at: String,
     pub packages: Vec<BackupPackage>,
+    /// Broken symlinks removed by `doctor --fix`, recreated by `undo`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<RemovedLink>,
+}
+
+/// A dead symlink that was removed, and what it pointed to
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RemovedLink {
+    pub path: String,
+    pub target: String,
+}
+
+impl RemovedLink {
+    /// Whether something has been put at the link's path since, so it can't be recreated
+    pub fn path_in_use(&self) -> bool {
+        std::path::Path::new(&self.path).symlink_metadata().is_ok()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,10 +42,28 @@ pub struct BackupPackage {
     pub version: Option<String>,
     pub binary_path: Option<String>,
     pub size_bytes: Option<u64>,
+    /// Name and version `undo` actually installed, when they differ from the backup's
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_as: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_version: Option<String>,
+    /// Where an application was moved in the Trash
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trashed_path: Option<String>,
+    /// Support files (caches, preferences, ...) moved to the Trash along with an app or cask
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub support_files: Vec<TrashedFile>,
+}
+
+/// A file or directory moved to the Trash, and where it came from
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TrashedFile {
+    pub original: String,
+    pub trashed: String,
 }
 
-/// Get the backup directory path
-fn get_backup_dir() -> Result<PathBuf> {
+/// Directory holding the backup manifests, created if missing
+pub fn backup_dir() -> Result<PathBuf> {
     let home = dirs::home_dir().context("Failed to get home directory")?;
     let backup_dir = home.join("Library/Application Support/macsweep/backups");
 
@@ -35,169 +74,754 @@ fn get_backup_dir() -> Result<PathBuf> {
     Ok(backup_dir)
 }
 
-/// Create a backup manifest before removing packages
-pub fn create_backup(packages: &[Package]) -> Result<String> {
-    let backup_id = format!("cleanup_{}", Utc::now().format("%Y%m%d_%H%M%S"));
-
-    let backup_packages: Vec<BackupPackage> = packages.iter().map(|p| {
+impl From<&Package> for BackupPackage {
+    fn from(p: &Package) -> Self {
         BackupPackage {
             name: p.name.clone(),
-            source: format!("{:?}", p.source),
+            source: p.source.to_string(),
             version: p.version.clone(),
             binary_path: p.binary_path.as_ref().map(|pb| pb.to_string_lossy().to_string()),
             size_bytes: p.size_bytes,
+            restored_as: None,
+            restored_version: None,
+            trashed_path: None,
+            support_files: Vec::new(),
         }
-    }).collect();
+    }
+}
+
+/// Create a backup manifest before removing packages
+pub fn create_backup(packages: &[Package]) -> Result<String> {
+    let backup_id = format!("cleanup_{}", Utc::now().format("%Y%m%d_%H%M%S"));
+
+    let backup_packages: Vec<BackupPackage> = packages.iter().map(BackupPackage::from).collect();
 
     let manifest = BackupManifest {
         backup_id: backup_id.clone(),
         created_at: Utc::now().to_rfc3339(),
         packages: backup_packages,
+        links: Vec::new(),
     };
 
-    let backup_dir = get_backup_dir()?;
-    let manifest_path = backup_dir.join(format!("{}.json", backup_id));
+    write_new_manifest(&manifest)
+}
 
-    let json = serde_json::to_string_pretty(&manifest)?;
-    fs::write(&manifest_path, json)?;
+/// Create a backup manifest recording the broken symlinks about to be removed
+pub fn create_link_backup(links: &[RemovedLink]) -> Result<String> {
+    let manifest = BackupManifest {
+        backup_id: format!("links_{}", Utc::now().format("%Y%m%d_%H%M%S")),
+        created_at: Utc::now().to_rfc3339(),
+        packages: Vec::new(),
+        links: links.to_vec(),
+    };
 
-    println!("  📋 Backup created: {}", backup_id);
-    println!("     Manifest: {}", manifest_path.display());
+    write_new_manifest(&manifest)
+}
+
+fn write_new_manifest(manifest: &BackupManifest) -> Result<String> {
+    let manifest_path = backup_dir()?.join(format!("{}.json", manifest.backup_id));
+
+    let json = serde_json::to_string_pretty(manifest)?;
+    fs::write(&manifest_path, json)?;
 
     Ok(manifest_path.to_string_lossy().to_string())
 }
 
-/// Restore packages from a backup manifest
-pub fn restore_backup(backup_id: &str) -> Result<()> {
-    let backup_dir = get_backup_dir()?;
+/// Add packages removed as a side effect of the cleanup (e.g. by `brew autoremove`) or by
+/// resuming it to its manifest, so `undo` reinstalls them as well; packages already in the
+/// manifest are skipped
+pub fn add_to_backup(manifest_path: &str, packages: &[Package]) -> Result<()> {
+    let mut manifest = read_manifest(manifest_path)?;
+
+    for package in packages.iter().map(BackupPackage::from) {
+        if !manifest.packages.iter().any(|p| p.name == package.name && p.source == package.source) {
+            manifest.packages.push(package);
+        }
+    }
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+/// Take packages an interrupted cleanup never got to out of its manifest, so `undo` only
+/// reinstalls what was removed; `packages` are (name, source) pairs
+pub fn remove_from_backup(manifest_path: &str, packages: &[(&str, &str)]) -> Result<()> {
+    let mut manifest = read_manifest(manifest_path)?;
+
+    manifest.packages.retain(|p| !packages.contains(&(p.name.as_str(), p.source.as_str())));
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+/// Take links that couldn't be removed out of its manifest, so `undo` doesn't try to recreate
+/// links that are still there; `paths` are the links' paths
+pub fn remove_links_from_backup(manifest_path: &str, paths: &[&str]) -> Result<()> {
+    let mut manifest = read_manifest(manifest_path)?;
+
+    manifest.links.retain(|l| !paths.contains(&l.path.as_str()));
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+/// Record where a removed application ended up in the Trash, so `undo` can put it back
+pub fn record_trashed(manifest_path: &str, package: &Package, trashed: &std::path::Path) -> Result<()> {
+    let mut manifest = read_manifest(manifest_path)?;
+    let source = package.source.to_string();
+
+    for pkg in manifest.packages.iter_mut().filter(|p| p.name == package.name && p.source == source) {
+        pkg.trashed_path = Some(trashed.to_string_lossy().to_string());
+    }
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+/// Record the support files trashed along with a removed app or cask, so `undo` puts them back
+pub fn record_support_files(manifest_path: &str, package: &Package, files: &[TrashedFile]) -> Result<()> {
+    let mut manifest = read_manifest(manifest_path)?;
+    let source = package.source.to_string();
+
+    for pkg in manifest.packages.iter_mut().filter(|p| p.name == package.name && p.source == source) {
+        pkg.support_files.extend_from_slice(files);
+    }
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
+}
+
+fn read_manifest(manifest_path: &str) -> Result<BackupManifest> {
+    let contents = fs::read_to_string(manifest_path)
+        .with_context(|| format!("Cannot read backup manifest {}", manifest_path))?;
+    parse_manifest(&contents).with_context(|| format!("Invalid backup manifest {}", manifest_path))
+}
+
+/// Manifests written before package sources had stable names hold the variant names
+/// (`HomebrewCask`); read them as the stable ones so lookups by source still match
+fn parse_manifest(json: &str) -> serde_json::Result<BackupManifest> {
+    let mut manifest: BackupManifest = serde_json::from_str(json)?;
+    for pkg in &mut manifest.packages {
+        if let Ok(source) = pkg.source.parse::<PackageSource>() {
+            pkg.source = source.to_string();
+        }
+    }
+    Ok(manifest)
+}
+
+/// Whether a backed-up package can be restored right now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RestoreStatus {
+    Restorable,
+    AlreadyInstalled,
+    Impossible(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageVerification {
+    pub package: BackupPackage,
+    pub status: RestoreStatus,
+}
+
+#[derive(Debug)]
+pub struct BackupVerification {
+    pub backup_id: String,
+    pub created_at: String,
+    pub manifest_path: PathBuf,
+    pub packages: Vec<PackageVerification>,
+    pub links: Vec<RemovedLink>,
+}
+
+impl BackupVerification {
+    pub fn count(&self, pred: impl Fn(&RestoreStatus) -> bool) -> usize {
+        self.packages.iter().filter(|p| pred(&p.status)).count()
+    }
+}
+
+/// A package as reinstalled by `undo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reinstalled {
+    /// Stable name of the source (`homebrew`, `npm`, ...), which manifests are read as
+    pub source: String,
+    /// Name in the backup manifest
+    pub backup_name: String,
+    /// Differs from the backup's name when an older `name@major` formula was installed instead
+    pub name: String,
+    /// `None` when the installed version couldn't be determined
+    pub version: Option<String>,
+}
+
+/// Which entries of a backup `undo` restores, and whether it only says what it would do
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    /// Names of the manifest entries to restore; `None` restores all of them
+    pub packages: Option<Vec<String>>,
+    /// Print what would be reinstalled and from where, without installing anything
+    pub dry_run: bool,
+    /// How long an install may run before it is killed
+    pub timeouts: CommandTimeouts,
+}
+
+/// Outcome of a restore, recorded on the matching cleanup row
+#[derive(Debug, Default)]
+pub struct RestoreSummary {
+    pub manifest_path: String,
+    /// Every package that was reinstalled
+    pub reinstalled: Vec<Reinstalled>,
+    /// Restored, or that would be in a dry run
+    pub restored: usize,
+    pub already_installed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Entries left out of a selective restore
+    pub not_selected: usize,
+    /// Every install command that ran, with the package it was for
+    pub commands: Vec<(String, CommandRecord)>,
+}
+
+impl RestoreSummary {
+    /// Whether everything that was selected is installed again
+    pub fn succeeded(&self) -> bool {
+        self.restored + self.already_installed > 0 && self.skipped == 0 && self.failed == 0
+    }
+
+    /// Short status stored in `cleanups.restore_status`
+    pub fn outcome(&self) -> &'static str {
+        if self.restored + self.already_installed == 0 {
+            "failed"
+        } else if self.skipped > 0 || self.failed > 0 || self.not_selected > 0 {
+            "partial"
+        } else {
+            "restored"
+        }
+    }
+}
+
+/// What `restore_backup` is doing, delivered as it happens
+#[derive(Debug)]
+pub enum RestoreEvent<'a> {
+    /// The backup is verified; `selected` of its packages and `links` of its links are about
+    /// to be restored
+    Started { verification: &'a BackupVerification, selected: usize, links: usize },
+    PackageStarted { package: &'a BackupPackage },
+    PackageFinished { package: &'a BackupPackage, outcome: &'a RestoreOutcome },
+    LinkFinished { link: &'a RemovedLink, outcome: &'a LinkOutcome },
+}
+
+/// How restoring one package went
+#[derive(Debug)]
+pub enum RestoreOutcome {
+    /// Verification found it can't be restored, and why
+    Skipped(String),
+    AlreadyInstalled,
+    /// In a dry run, how it would be restored
+    WouldRestore(String),
+    /// Reinstalled; `warnings` are about its version and support files
+    Restored { reinstalled: Reinstalled, elapsed: Duration, warnings: Vec<String> },
+    /// The install command failed; `error` is the last line of its output
+    InstallFailed { elapsed: Duration, error: Option<String> },
+    Failed { elapsed: Duration, error: String },
+}
+
+/// How recreating one removed link went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkOutcome {
+    /// Something else is at its path now
+    PathInUse,
+    /// In a dry run, it would be recreated
+    WouldRecreate,
+    Recreated,
+    Failed(String),
+}
+
+/// Load a backup manifest by ID
+fn load_manifest(backup_id: &str) -> Result<(PathBuf, BackupManifest)> {
+    let backup_dir = backup_dir()?;
     let manifest_path = backup_dir.join(format!("{}.json", backup_id));
 
     if !manifest_path.exists() {
-        anyhow::bail!("Backup not found: {}", backup_id);
+        return Err(MacsweepError::Other(format!("Backup not found: {}", backup_id)));
     }
 
     let json = fs::read_to_string(&manifest_path)?;
-    let manifest: BackupManifest = serde_json::from_str(&json)?;
+    let manifest = parse_manifest(&json)
+        .context(format!("Backup manifest is corrupt or truncated: {}", manifest_path.display()))?;
 
-    println!("🔄 Restoring from backup: {}", manifest.backup_id);
-    println!("   Created: {}", manifest.created_at);
-    println!("   Packages: {}\n", manifest.packages.len());
+    Ok((manifest_path, manifest))
+}
 
-    let mut success_count = 0;
-    let mut failed_count = 0;
+/// The packages in a backup, in manifest order
+pub fn backup_packages(backup_id: &str) -> Result<Vec<BackupPackage>> {
+    Ok(load_manifest(backup_id)?.1.packages)
+}
 
-    for pkg in &manifest.packages {
-        print!("  Restoring {} ({})... ", pkg.name, pkg.source);
+/// Check that every package in a backup can actually be restored
+pub fn verify_backup(backup_id: &str) -> Result<BackupVerification> {
+    let (manifest_path, manifest) = load_manifest(backup_id)?;
 
-        match restore_package(pkg) {
-            Ok(true) => {
-                println!("✓");
-                success_count += 1;
-            }
-            Ok(false) => {
-                println!("⚠ Already installed");
-                success_count += 1;
-            }
-            Err(e) => {
-                println!("✗ {}", e);
-                failed_count += 1;
-            }
+    Ok(BackupVerification {
+        backup_id: manifest.backup_id.clone(),
+        created_at: manifest.created_at.clone(),
+        manifest_path,
+        packages: verify_manifest(&manifest),
+        links: manifest.links,
+    })
+}
+
+fn verify_manifest(manifest: &BackupManifest) -> Vec<PackageVerification> {
+    let mut reachable: HashMap<&'static str, bool> = HashMap::new();
+
+    manifest.packages.iter().map(|pkg| {
+        let status = verify_package(pkg, &mut reachable);
+        PackageVerification { package: pkg.clone(), status }
+    }).collect()
+}
+
+fn verify_package(pkg: &BackupPackage, reachable: &mut HashMap<&'static str, bool>) -> RestoreStatus {
+    let source = match pkg.source.parse::<PackageSource>().ok() {
+        Some(source) => source,
+        None => return RestoreStatus::Impossible(format!("unsupported source {}", pkg.source)),
+    };
+
+    let binary_exists = pkg.binary_path.as_ref()
+        .map(|p| std::path::Path::new(p).exists())
+        .unwrap_or(false);
+
+    if source == PackageSource::Applications {
+        if binary_exists {
+            return RestoreStatus::AlreadyInstalled;
         }
+        return match trashed_app_path(pkg) {
+            Some(_) => RestoreStatus::Restorable,
+            None => RestoreStatus::Impossible("moved to Trash and the Trash was emptied".to_string()),
+        };
     }
 
-    println!("\n📊 Restore Summary:");
-    println!("   Restored: {}", success_count);
-    if failed_count > 0 {
-        println!("   Failed: {}", failed_count);
+    if binary_exists {
+        return RestoreStatus::AlreadyInstalled;
     }
 
-    Ok(())
-}
+    let (manager, host) = match installer_for(&source) {
+        Some(pair) => pair,
+        None => return RestoreStatus::Impossible(format!("cannot restore {:?} packages", source)),
+    };
+
+    if which::which(manager).is_err() {
+        return RestoreStatus::Impossible(format!("{} is not on PATH", manager));
+    }
 
-fn restore_package(pkg: &BackupPackage) -> Result<bool> {
-    let source = parse_package_source(&pkg.source);
+    if !*reachable.entry(host).or_insert_with(|| is_reachable(host)) {
+        return RestoreStatus::Impossible(format!("{} is unreachable", host));
+    }
 
+    RestoreStatus::Restorable
+}
+
+/// Package manager binary and registry host used to reinstall a source
+fn installer_for(source: &PackageSource) -> Option<(&'static str, &'static str)> {
     match source {
-        PackageSource::Homebrew | PackageSource::HomebrewCask => {
-            restore_homebrew_package(&pkg.name)
+        PackageSource::Homebrew | PackageSource::HomebrewCask => Some(("brew", "formulae.brew.sh")),
+        PackageSource::Npm => Some(("npm", "registry.npmjs.org")),
+        PackageSource::Pip => Some(("pip3", "pypi.org")),
+        PackageSource::Pipx => Some(("pipx", "pypi.org")),
+        PackageSource::Cargo => Some(("cargo", "index.crates.io")),
+        _ => None,
+    }
+}
+
+fn is_reachable(host: &str) -> bool {
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    let addrs = match (host, 443).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(_) => return false,
+    };
+
+    addrs.into_iter().any(|addr| TcpStream::connect_timeout(&addr, Duration::from_secs(3)).is_ok())
+}
+
+/// Where a trashed application is, if the Trash hasn't been emptied. Backups made before the
+/// trashed path was recorded fall back to the app's name in `~/.Trash`.
+fn trashed_app_path(pkg: &BackupPackage) -> Option<PathBuf> {
+    if let Some(ref trashed) = pkg.trashed_path {
+        let trashed = PathBuf::from(trashed);
+        return trashed.exists().then_some(trashed);
+    }
+
+    let file_name = pkg.binary_path.as_ref()
+        .and_then(|p| std::path::Path::new(p).file_name().map(|f| f.to_os_string()))?;
+    let trashed = dirs::home_dir()?.join(".Trash").join(file_name);
+    trashed.exists().then_some(trashed)
+}
+
+/// Restore packages from a backup manifest
+///
+/// The backup is verified first; impossible entries are skipped with a reason
+/// instead of aborting the whole restore. `on_event` hears about each package as soon as it
+/// is done, so reinstalled ones can be recorded before a later one hangs.
+pub fn restore_backup(backup_id: &str, options: &RestoreOptions, mut on_event: impl FnMut(RestoreEvent)) -> Result<RestoreSummary> {
+    let verification = verify_backup(backup_id)?;
+    let selected = select_entries(&verification, options.packages.as_deref())?;
+
+    // Links belong to no package, so selecting packages leaves them out
+    let links: &[RemovedLink] = if options.packages.is_some() { &[] } else { &verification.links };
+    on_event(RestoreEvent::Started { verification: &verification, selected: selected.len(), links: links.len() });
+
+    let mut summary = RestoreSummary {
+        manifest_path: verification.manifest_path.to_string_lossy().to_string(),
+        not_selected: verification.packages.len() - selected.len() + verification.links.len() - links.len(),
+        ..Default::default()
+    };
+
+    for entry in selected {
+        let pkg = &entry.package;
+        on_event(RestoreEvent::PackageStarted { package: pkg });
+        let outcome = restore_entry(entry, options, &mut summary);
+        match outcome {
+            RestoreOutcome::Skipped(_) => summary.skipped += 1,
+            RestoreOutcome::AlreadyInstalled => summary.already_installed += 1,
+            RestoreOutcome::WouldRestore(_) | RestoreOutcome::Restored { .. } => summary.restored += 1,
+            RestoreOutcome::InstallFailed { .. } | RestoreOutcome::Failed { .. } => summary.failed += 1,
         }
-        PackageSource::Npm => {
-            restore_npm_package(&pkg.name)
+        on_event(RestoreEvent::PackageFinished { package: pkg, outcome: &outcome });
+        if let RestoreOutcome::Restored { reinstalled, .. } = outcome {
+            summary.reinstalled.push(reinstalled);
+        }
+    }
+
+    for link in links {
+        let outcome = restore_link(link, options.dry_run);
+        match outcome {
+            LinkOutcome::PathInUse => summary.already_installed += 1,
+            LinkOutcome::WouldRecreate | LinkOutcome::Recreated => summary.restored += 1,
+            LinkOutcome::Failed(_) => summary.failed += 1,
+        }
+        on_event(RestoreEvent::LinkFinished { link, outcome: &outcome });
+    }
+
+    if !summary.reinstalled.is_empty() {
+        if let Err(e) = record_reinstalled(&verification.manifest_path, &summary.reinstalled) {
+            tracing::warn!(manifest = %verification.manifest_path.display(), error = %e, "Failed to record restored versions in the backup manifest");
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Restore one verified package, adding the install commands that ran to `summary`
+fn restore_entry(entry: &PackageVerification, options: &RestoreOptions, summary: &mut RestoreSummary) -> RestoreOutcome {
+    let pkg = &entry.package;
+    match &entry.status {
+        RestoreStatus::Impossible(reason) => return RestoreOutcome::Skipped(reason.clone()),
+        RestoreStatus::AlreadyInstalled => return RestoreOutcome::AlreadyInstalled,
+        RestoreStatus::Restorable => {}
+    }
+
+    if options.dry_run {
+        return RestoreOutcome::WouldRestore(restore_method(pkg, options.timeouts.get(CommandKind::Scanner)));
+    }
+
+    let started = std::time::Instant::now();
+    let mut commands = Vec::new();
+    let result = restore_package(pkg, &options.timeouts, &mut commands);
+    let elapsed = started.elapsed();
+    let last_error = commands.last()
+        .filter(|c| !c.success())
+        .and_then(|c| c.stderr_summary().lines().last().map(str::to_string));
+    summary.commands.extend(commands.into_iter().map(|c| (pkg.name.clone(), c)));
+
+    match result {
+        Ok(Some(reinstalled)) => {
+            let mut warnings: Vec<String> = version_mismatch(pkg, &reinstalled).into_iter().collect();
+            warnings.extend(restore_support_files(pkg));
+            RestoreOutcome::Restored { reinstalled, elapsed, warnings }
+        }
+        Ok(None) => RestoreOutcome::InstallFailed { elapsed, error: last_error },
+        Err(e) => RestoreOutcome::Failed { elapsed, error: e.to_string() },
+    }
+}
+
+/// Recreate a removed symlink, unless something else has taken its path since
+fn restore_link(link: &RemovedLink, dry_run: bool) -> LinkOutcome {
+    if link.path_in_use() {
+        LinkOutcome::PathInUse
+    } else if dry_run {
+        LinkOutcome::WouldRecreate
+    } else {
+        match std::os::unix::fs::symlink(&link.target, &link.path) {
+            Ok(()) => LinkOutcome::Recreated,
+            Err(e) => LinkOutcome::Failed(e.to_string()),
+        }
+    }
+}
+
+/// The entries of a backup named in `packages` (case-insensitively), or all of them
+fn select_entries<'a>(verification: &'a BackupVerification, packages: Option<&[String]>) -> Result<Vec<&'a PackageVerification>> {
+    let Some(packages) = packages else {
+        return Ok(verification.packages.iter().collect());
+    };
+
+    let unknown: Vec<_> = packages.iter()
+        .filter(|name| !verification.packages.iter().any(|e| e.package.name.eq_ignore_ascii_case(name)))
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        let available: Vec<_> = verification.packages.iter().map(|e| e.package.name.as_str()).collect();
+        return Err(MacsweepError::Other(format!(
+            "Not in backup {}: {} (it contains: {})",
+            verification.backup_id,
+            unknown.join(", "),
+            available.join(", ")
+        )));
+    }
+
+    Ok(verification.packages.iter()
+        .filter(|e| packages.iter().any(|name| e.package.name.eq_ignore_ascii_case(name)))
+        .collect())
+}
+
+/// How a package would be restored, for `undo --dry-run`; brew is given `lookup_timeout` to
+/// say which version it would install
+fn restore_method(pkg: &BackupPackage, lookup_timeout: Duration) -> String {
+    let method = match pkg.source.parse::<PackageSource>().ok() {
+        Some(PackageSource::Homebrew) => homebrew_restore_formulae(pkg, lookup_timeout).iter()
+            .map(|formula| format!("brew install --formula {}", formula))
+            .collect::<Vec<_>>()
+            .join(", falling back to "),
+        Some(PackageSource::HomebrewCask) => format!("brew install --cask {}", pkg.name),
+        Some(PackageSource::Applications) => match (trashed_app_path(pkg), pkg.binary_path.as_ref()) {
+            (Some(trashed), Some(original)) => format!("move {} back to {}", trashed.display(), original),
+            _ => "check the Trash".to_string(),
+        },
+        Some(source) => match pinned_install_command(&source, &pkg.name, pkg.version.as_deref()) {
+            Some((program, args)) => format!("{} {}", program, args.join(" ")),
+            None => format!("cannot restore {:?} packages", source),
+        },
+        None => format!("unsupported source {}", pkg.source),
+    };
+
+    match pkg.support_files.len() {
+        0 => method,
+        1 => format!("{}, and put back 1 support file", method),
+        n => format!("{}, and put back {} support files", method, n),
+    }
+}
+
+/// Reinstall a package, at its backed-up version where the package manager allows it,
+/// adding the install commands that ran to `commands`; an install still running after the
+/// uninstall timeout is killed, and the version lookups get the scanner timeout. `None` means
+/// the install command failed.
+fn restore_package(pkg: &BackupPackage, timeouts: &CommandTimeouts, commands: &mut Vec<CommandRecord>) -> Result<Option<Reinstalled>> {
+    let (timeout, lookup_timeout) = (timeouts.get(CommandKind::Uninstall), timeouts.get(CommandKind::Scanner));
+    let source = pkg.source.parse::<PackageSource>().ok()
+        .with_context(|| format!("Cannot restore packages from source: {}", pkg.source))?;
+    let reinstalled = |name: &str, version: Option<String>| Reinstalled {
+        source: pkg.source.clone(),
+        backup_name: pkg.name.clone(),
+        name: name.to_string(),
+        version,
+    };
+
+    match source {
+        PackageSource::Homebrew => {
+            restore_homebrew_formula(pkg, timeout, lookup_timeout, commands)
+                .map(|installed| installed.map(|(name, version)| reinstalled(&name, version)))
         }
-        PackageSource::Pip | PackageSource::Pipx => {
-            restore_pip_package(&pkg.name, &source)
+        PackageSource::HomebrewCask => {
+            if !run_install("brew", &["install", "--cask", &pkg.name], timeout, commands)? {
+                return Ok(None);
+            }
+            Ok(Some(reinstalled(&pkg.name, brew_installed_version(&pkg.name, true, lookup_timeout))))
         }
-        PackageSource::Cargo => {
-            restore_cargo_package(&pkg.name)
+        PackageSource::Npm | PackageSource::Pip | PackageSource::Pipx | PackageSource::Cargo => {
+            let (program, args) = pinned_install_command(&source, &pkg.name, pkg.version.as_deref())
+                .ok_or_else(|| MacsweepError::Other(format!("No installer for packages from source: {}", source)))?;
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            if !run_install(program, &args, timeout, commands)? {
+                return Ok(None);
+            }
+            Ok(Some(reinstalled(&pkg.name, installed_version(&source, &pkg.name, lookup_timeout))))
         }
         PackageSource::Applications => {
-            // Applications can't be auto-restored - they were moved to trash
-            println!("(check Trash)");
-            Ok(false)
+            // Put the app back from the Trash
+            let (Some(trashed), Some(original)) = (trashed_app_path(pkg), pkg.binary_path.as_ref()) else {
+                return Err(MacsweepError::Other("Nothing to move back; check the Trash".to_string()));
+            };
+            fs::rename(&trashed, original)
+                .with_context(|| format!("Failed to move {} back to {}", trashed.display(), original))?;
+            Ok(Some(reinstalled(&pkg.name, pkg.version.clone())))
         }
         _ => {
-            anyhow::bail!("Cannot restore packages from source: {:?}", source)
+            Err(MacsweepError::Other(format!("Cannot restore packages from source: {:?}", source)))
+        }
+    }
+}
+
+/// Move an app's trashed support files back, leaving any the reinstall already recreated;
+/// returns a warning for each one that couldn't be restored
+fn restore_support_files(pkg: &BackupPackage) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for file in &pkg.support_files {
+        let (trashed, original) = (std::path::Path::new(&file.trashed), std::path::Path::new(&file.original));
+        if original.symlink_metadata().is_ok() {
+            continue;
+        }
+        if trashed.symlink_metadata().is_err() {
+            warnings.push(format!("{} is no longer in the Trash", file.original));
+            continue;
+        }
+
+        let moved = original.parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(trashed, original));
+        if let Err(e) = moved {
+            warnings.push(format!("Failed to move {} back to {}: {}", file.trashed, file.original, e));
+        }
+    }
+
+    warnings
+}
+
+/// Install command pinned to `version` when known: `npm install -g name@1.2.3`,
+/// `pip3 install name==1.2.3`, `cargo install name --version 1.2.3`
+fn pinned_install_command(source: &PackageSource, name: &str, version: Option<&str>) -> Option<(&'static str, Vec<String>)> {
+    let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let pip_spec = || version.map_or(name.to_string(), |v| format!("{}=={}", name, v));
+
+    Some(match source {
+        PackageSource::Npm => ("npm", args(&["install", "-g", &version.map_or(name.to_string(), |v| format!("{}@{}", name, v))])),
+        PackageSource::Pip => ("pip3", args(&["install", &pip_spec()])),
+        PackageSource::Pipx => ("pipx", args(&["install", &pip_spec()])),
+        PackageSource::Cargo => match version {
+            Some(v) => ("cargo", args(&["install", name, "--version", v])),
+            None => ("cargo", args(&["install", name])),
+        },
+        _ => return None,
+    })
+}
+
+/// Run an install command, keeping its record for the cleanup log; `false` means it failed
+fn run_install(program: &str, args: &[&str], timeout: Duration, commands: &mut Vec<CommandRecord>) -> Result<bool> {
+    let record = CommandRecord::run(Command::new(program).args(args), timeout)
+        .with_context(|| format!("Failed to execute {} install", program))?;
+    let success = record.success();
+    commands.push(record);
+    Ok(success)
+}
+
+/// Homebrew only installs a formula's current version, so when that is a different major
+/// version than the backup's, try the versioned `name@major` formula first
+fn restore_homebrew_formula(
+    pkg: &BackupPackage,
+    timeout: Duration,
+    lookup_timeout: Duration,
+    commands: &mut Vec<CommandRecord>,
+) -> Result<Option<(String, Option<String>)>> {
+    for formula in homebrew_restore_formulae(pkg, lookup_timeout) {
+        if run_install("brew", &["install", "--formula", &formula], timeout, commands)? {
+            let version = brew_installed_version(&formula, false, lookup_timeout);
+            return Ok(Some((formula, version)));
         }
     }
+    Ok(None)
 }
 
-fn restore_homebrew_package(name: &str) -> Result<bool> {
-    let output = Command::new("brew")
-        .args(["install", name])
-        .output()
-        .context("Failed to execute brew install")?;
+/// Formulae to try in turn: `name@major` when the backup's major version is no longer the
+/// current one, then the formula itself
+fn homebrew_restore_formulae(pkg: &BackupPackage, lookup_timeout: Duration) -> Vec<String> {
+    let wanted_major = pkg.version.as_deref().and_then(major_version);
+    let current = brew_stable_version(&pkg.name, lookup_timeout);
+    let current_major = current.as_deref().and_then(major_version);
 
-    Ok(output.status.success())
+    let mut formulae = Vec::new();
+    if let (Some(wanted), Some(current)) = (wanted_major, current_major) {
+        if wanted != current && !pkg.name.contains('@') {
+            formulae.push(format!("{}@{}", pkg.name, wanted));
+        }
+    }
+    formulae.push(pkg.name.clone());
+    formulae
 }
 
-fn restore_npm_package(name: &str) -> Result<bool> {
-    let output = Command::new("npm")
-        .args(["install", "-g", name])
-        .output()
-        .context("Failed to execute npm install")?;
+/// The version `brew install` would install now
+fn brew_stable_version(name: &str, timeout: Duration) -> Option<String> {
+    let output = run_command_with_timeout("brew", &["info", "--json=v2", "--formula", name], timeout).ok()?;
+    let json: serde_json::Value = serde_json::from_str(&output).ok()?;
+    json["formulae"][0]["versions"]["stable"].as_str().map(str::to_string)
+}
 
-    Ok(output.status.success())
+/// The newest installed version, from `brew list --versions`
+fn brew_installed_version(name: &str, cask: bool, timeout: Duration) -> Option<String> {
+    let kind = if cask { "--cask" } else { "--formula" };
+    let output = run_command_with_timeout("brew", &["list", kind, "--versions", name], timeout).ok()?;
+    output.split_whitespace().skip(1).last().map(str::to_string)
 }
 
-fn restore_pip_package(name: &str, source: &PackageSource) -> Result<bool> {
-    let command = match source {
-        PackageSource::Pipx => "pipx",
-        _ => "pip3",
+/// The version of `name` the package manager lists as installed, for npm, pip, pipx and cargo
+fn installed_version(source: &PackageSource, name: &str, timeout: Duration) -> Option<String> {
+    let listed = match source {
+        PackageSource::Npm => run_command_with_timeout("npm", &["list", "-g", "--depth=0", "--json", name], timeout),
+        PackageSource::Pip => run_command_with_timeout("pip3", &["show", name], timeout),
+        PackageSource::Pipx => run_command_with_timeout("pipx", &["list", "--short"], timeout),
+        PackageSource::Cargo => run_command_with_timeout("cargo", &["install", "--list"], timeout),
+        _ => return None,
     };
+    parse_installed_version(source, name, &listed.ok()?)
+}
 
-    let output = Command::new(command)
-        .args(["install", name])
-        .output()
-        .context(format!("Failed to execute {} install", command))?;
+/// The version of `name` in what `installed_version` ran: npm's JSON, `Version:` from
+/// `pip3 show`, `black 24.4.2` from `pipx list --short`, `ripgrep v14.1.0:` from `cargo install --list`
+fn parse_installed_version(source: &PackageSource, name: &str, output: &str) -> Option<String> {
+    match source {
+        PackageSource::Npm => {
+            let json: serde_json::Value = serde_json::from_str(output).ok()?;
+            json["dependencies"][name]["version"].as_str().map(str::to_string)
+        }
+        PackageSource::Pip => output.lines().find_map(|line| line.strip_prefix("Version:")).map(|v| v.trim().to_string()),
+        PackageSource::Pipx | PackageSource::Cargo => output.lines().find_map(|line| {
+            let mut parts = line.split_whitespace();
+            (parts.next()? == name).then_some(())?;
+            let version = parts.next()?.trim_end_matches(':');
+            Some(version.strip_prefix('v').unwrap_or(version).to_string())
+        }),
+        _ => None,
+    }
+}
 
-    Ok(output.status.success())
+/// `3.11.9` -> `3`, `v20.1.0` -> `20`, `1.7.1_1` -> `1`
+fn major_version(version: &str) -> Option<&str> {
+    let major = version.trim_start_matches('v').split(['.', '_', '-']).next()?;
+    (!major.is_empty() && major.chars().all(|c| c.is_ascii_digit())).then_some(major)
 }
 
-fn restore_cargo_package(name: &str) -> Result<bool> {
-    let output = Command::new("cargo")
-        .args(["install", name])
-        .output()
-        .context("Failed to execute cargo install")?;
+/// A warning when `undo` installed something other than what was backed up
+fn version_mismatch(pkg: &BackupPackage, reinstalled: &Reinstalled) -> Option<String> {
+    let wanted = pkg.version.as_deref()?;
+    let got = reinstalled.version.as_deref().unwrap_or("an unknown version");
+    let got_name = if reinstalled.name == pkg.name { String::new() } else { format!("{} ", reinstalled.name) };
+
+    let same = reinstalled.version.as_deref() == Some(wanted)
+        // Homebrew revisions (`1.7.1_1`) are rebuilds of the same version
+        || reinstalled.version.as_deref().is_some_and(|v| v.split('_').next() == wanted.split('_').next());
+    (!same).then(|| format!("backup had {} {}, restored {}{}", pkg.name, wanted, got_name, got))
+}
 
-    Ok(output.status.success())
+/// Write what was actually reinstalled back into the manifest
+fn record_reinstalled(manifest_path: &std::path::Path, reinstalled: &[Reinstalled]) -> Result<()> {
+    let mut manifest = read_manifest(&manifest_path.to_string_lossy())?;
+    apply_reinstalled(&mut manifest, reinstalled);
+    fs::write(manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(())
 }
 
-fn parse_package_source(source_str: &str) -> PackageSource {
-    match source_str {
-        "Homebrew" => PackageSource::Homebrew,
-        "HomebrewCask" => PackageSource::HomebrewCask,
-        "Npm" => PackageSource::Npm,
-        "Pip" => PackageSource::Pip,
-        "Pipx" => PackageSource::Pipx,
-        "Cargo" => PackageSource::Cargo,
-        "Applications" => PackageSource::Applications,
-        _ => PackageSource::Homebrew, // Default fallback
+fn apply_reinstalled(manifest: &mut BackupManifest, reinstalled: &[Reinstalled]) {
+    for pkg in &mut manifest.packages {
+        if let Some(installed) = reinstalled.iter().find(|r| r.source == pkg.source && r.backup_name == pkg.name) {
+            pkg.restored_as = (installed.name != pkg.name).then(|| installed.name.clone());
+            pkg.restored_version = installed.version.clone();
+        }
     }
 }
 
 /// List all available backups
 pub fn list_backups() -> Result<Vec<String>> {
-    let backup_dir = get_backup_dir()?;
+    let backup_dir = backup_dir()?;
 
     if !backup_dir.exists() {
         return Ok(Vec::new());
@@ -221,3 +845,266 @@ pub fn list_backups() -> Result<Vec<String>> {
 
     Ok(backups)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backup_package(name: &str, source: &str, binary_path: Option<&str>) -> BackupPackage {
+        BackupPackage {
+            name: name.to_string(),
+            source: source.to_string(),
+            version: None,
+            binary_path: binary_path.map(|p| p.to_string()),
+            size_bytes: None,
+            restored_as: None,
+            restored_version: None,
+            trashed_path: None,
+            support_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_pinned_install_command() {
+        let command = |source, version| {
+            let (program, args) = pinned_install_command(&source, "black", version).unwrap();
+            format!("{} {}", program, args.join(" "))
+        };
+
+        assert_eq!(command(PackageSource::Npm, Some("1.2.3")), "npm install -g black@1.2.3");
+        assert_eq!(command(PackageSource::Npm, None), "npm install -g black");
+        assert_eq!(command(PackageSource::Pip, Some("24.1.0")), "pip3 install black==24.1.0");
+        assert_eq!(command(PackageSource::Pipx, Some("24.1.0")), "pipx install black==24.1.0");
+        assert_eq!(command(PackageSource::Cargo, Some("0.9.0")), "cargo install black --version 0.9.0");
+        assert_eq!(command(PackageSource::Cargo, None), "cargo install black");
+        assert!(pinned_install_command(&PackageSource::Homebrew, "black", None).is_none());
+    }
+
+    #[test]
+    fn test_parse_installed_version() {
+        let npm = r#"{"dependencies": {"typescript": {"version": "5.4.5", "overridden": false}}}"#;
+        assert_eq!(parse_installed_version(&PackageSource::Npm, "typescript", npm).as_deref(), Some("5.4.5"));
+        assert_eq!(parse_installed_version(&PackageSource::Npm, "eslint", npm), None);
+
+        let pip = "Name: black\nVersion: 24.4.2\nSummary: The uncompromising code formatter.\n";
+        assert_eq!(parse_installed_version(&PackageSource::Pip, "black", pip).as_deref(), Some("24.4.2"));
+
+        let pipx = "httpie 3.2.2\nblack 24.4.2\n";
+        assert_eq!(parse_installed_version(&PackageSource::Pipx, "black", pipx).as_deref(), Some("24.4.2"));
+
+        let cargo = "bat v0.24.0:\n    bat\nripgrep v14.1.0:\n    rg\n";
+        assert_eq!(parse_installed_version(&PackageSource::Cargo, "ripgrep", cargo).as_deref(), Some("14.1.0"));
+        assert_eq!(parse_installed_version(&PackageSource::Cargo, "rg", cargo), None);
+    }
+
+    #[test]
+    fn test_major_version() {
+        assert_eq!(major_version("3.11.9"), Some("3"));
+        assert_eq!(major_version("v20.1.0"), Some("20"));
+        assert_eq!(major_version("1.7.1_1"), Some("1"));
+        assert_eq!(major_version("latest"), None);
+    }
+
+    #[test]
+    fn test_reinstalled_versions_are_recorded() {
+        let mut node = backup_package("node", "homebrew", None);
+        node.version = Some("18.19.0".to_string());
+        let mut jq = backup_package("jq", "homebrew", None);
+        jq.version = Some("1.7.1".to_string());
+        let mut manifest = BackupManifest {
+            backup_id: "cleanup_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: Vec::new(),
+            packages: vec![node.clone(), jq.clone(), backup_package("jq", "npm", None)],
+        };
+
+        let reinstalled = |name: &str, backup_name: &str, version: &str| Reinstalled {
+            source: "homebrew".to_string(),
+            backup_name: backup_name.to_string(),
+            name: name.to_string(),
+            version: Some(version.to_string()),
+        };
+        let node_18 = reinstalled("node@18", "node", "18.20.4");
+        let jq_rebuilt = reinstalled("jq", "jq", "1.7.1_1");
+
+        assert_eq!(version_mismatch(&node, &node_18).unwrap(), "backup had node 18.19.0, restored node@18 18.20.4");
+        assert_eq!(version_mismatch(&jq, &jq_rebuilt), None);
+
+        apply_reinstalled(&mut manifest, &[node_18, jq_rebuilt]);
+        assert_eq!(manifest.packages[0].restored_as.as_deref(), Some("node@18"));
+        assert_eq!(manifest.packages[0].restored_version.as_deref(), Some("18.20.4"));
+        assert_eq!(manifest.packages[1].restored_as, None);
+        assert_eq!(manifest.packages[1].restored_version.as_deref(), Some("1.7.1_1"));
+        assert_eq!(manifest.packages[2].restored_version, None);
+    }
+
+    #[test]
+    fn test_reinstalls_are_recorded_in_legacy_manifests() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut manifest = BackupManifest {
+            backup_id: "cleanup_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: Vec::new(),
+            packages: vec![backup_package("jq", "homebrew", None)],
+        };
+        // Written before sources had stable names
+        manifest.packages[0].source = "Homebrew".to_string();
+        fs::write(file.path(), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let jq = Reinstalled {
+            source: "homebrew".to_string(),
+            backup_name: "jq".to_string(),
+            name: "jq".to_string(),
+            version: Some("1.7.1".to_string()),
+        };
+        record_reinstalled(file.path(), &[jq]).unwrap();
+        let manifest = read_manifest(&file.path().to_string_lossy()).unwrap();
+        assert_eq!(manifest.packages[0].restored_version.as_deref(), Some("1.7.1"));
+    }
+
+    #[test]
+    fn test_verify_manifest_statuses() {
+        let installed = tempfile::NamedTempFile::new().unwrap();
+        let manifest = BackupManifest {
+            backup_id: "cleanup_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: Vec::new(),
+            packages: vec![
+                backup_package("Gone", "applications", Some("/nonexistent/macsweep-test/Gone.app")),
+                backup_package("tool", "LocalBin", None),
+                backup_package("jq", "homebrew", installed.path().to_str()),
+            ],
+        };
+
+        let results = verify_manifest(&manifest);
+        assert!(matches!(results[0].status, RestoreStatus::Impossible(_)));
+        assert!(matches!(results[1].status, RestoreStatus::Impossible(_)));
+        assert_eq!(results[2].status, RestoreStatus::AlreadyInstalled);
+    }
+
+    #[test]
+    fn test_add_to_backup() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let manifest = BackupManifest {
+            backup_id: "cleanup_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: Vec::new(),
+            packages: vec![backup_package("ffmpeg", "Homebrew", None)],  // written before sources had stable names
+        };
+        fs::write(file.path(), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let mut x264 = Package::new("x264".to_string(), PackageSource::Homebrew);
+        x264.size_bytes = Some(4096);
+        add_to_backup(file.path().to_str().unwrap(), &[x264.clone()]).unwrap();
+        // Resuming adds the same packages again; they are only listed once
+        add_to_backup(file.path().to_str().unwrap(), &[x264]).unwrap();
+
+        let manifest: BackupManifest = serde_json::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        let names: Vec<_> = manifest.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["ffmpeg", "x264"]);
+        assert_eq!(manifest.packages[1].size_bytes, Some(4096));
+
+        remove_from_backup(file.path().to_str().unwrap(), &[("ffmpeg", "homebrew"), ("x264", "npm")]).unwrap();
+        let manifest: BackupManifest = serde_json::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        let names: Vec<_> = manifest.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["x264"]);
+    }
+
+    #[test]
+    fn test_remove_links_from_backup() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let link = |path: &str| RemovedLink { path: path.to_string(), target: "/gone".to_string() };
+        let manifest = BackupManifest {
+            backup_id: "links_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: vec![link("/usr/local/bin/http"), link("/usr/local/bin/https")],
+            packages: Vec::new(),
+        };
+        fs::write(file.path(), serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        remove_links_from_backup(file.path().to_str().unwrap(), &["/usr/local/bin/https"]).unwrap();
+        let manifest: BackupManifest = serde_json::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        let paths: Vec<_> = manifest.links.iter().map(|l| l.path.as_str()).collect();
+        assert_eq!(paths, ["/usr/local/bin/http"]);
+    }
+
+    #[test]
+    fn test_support_files_are_recorded_and_restored() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file = dir.path().join("manifest.json");
+        let manifest = BackupManifest {
+            backup_id: "cleanup_test".to_string(),
+            created_at: Utc::now().to_rfc3339(),
+            links: Vec::new(),
+            packages: vec![backup_package("Foo", "applications", None)],
+        };
+        fs::write(&file, serde_json::to_string(&manifest).unwrap()).unwrap();
+
+        let original = dir.path().join("Library/Caches/com.foo.app");
+        let trashed = dir.path().join(".Trash/com.foo.app");
+        let recreated = dir.path().join("Library/Preferences/com.foo.app.plist");
+        fs::create_dir_all(&trashed).unwrap();
+        fs::create_dir_all(recreated.parent().unwrap()).unwrap();
+        fs::write(&recreated, b"new").unwrap();
+
+        let files = [
+            TrashedFile { original: original.to_string_lossy().to_string(), trashed: trashed.to_string_lossy().to_string() },
+            TrashedFile {
+                original: recreated.to_string_lossy().to_string(),
+                trashed: dir.path().join(".Trash/com.foo.app.plist").to_string_lossy().to_string(),
+            },
+            TrashedFile {
+                original: dir.path().join("Library/Logs/Foo").to_string_lossy().to_string(),
+                trashed: dir.path().join(".Trash/Foo").to_string_lossy().to_string(),
+            },
+        ];
+        let foo = Package::new("Foo".to_string(), PackageSource::Applications);
+        record_support_files(file.to_str().unwrap(), &foo, &files).unwrap();
+
+        let manifest = read_manifest(file.to_str().unwrap()).unwrap();
+        assert_eq!(manifest.packages[0].support_files, files);
+
+        // Files the reinstall recreated are left alone; emptied ones are reported
+        let warnings = restore_support_files(&manifest.packages[0]);
+        assert!(original.is_dir());
+        assert!(!trashed.exists());
+        assert_eq!(fs::read(&recreated).unwrap(), b"new");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("no longer in the Trash"));
+    }
+
+    #[test]
+    fn test_restore_summary_outcome() {
+        let mut summary = RestoreSummary { restored: 2, ..Default::default() };
+        assert_eq!(summary.outcome(), "restored");
+        summary.skipped = 1;
+        assert_eq!(summary.outcome(), "partial");
+        let summary = RestoreSummary { failed: 1, ..Default::default() };
+        assert_eq!(summary.outcome(), "failed");
+    }
+
+    #[test]
+    fn test_removed_links_are_recreated() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let link = dir.path().join("node");
+        std::os::unix::fs::symlink("../Cellar/node/21.7.1/bin/node", &link).unwrap();
+
+        crate::cleanup::executor::remove_broken_link(&link).unwrap();
+        assert!(link.symlink_metadata().is_err());
+        // A file, or a link that works again, is left alone
+        fs::write(&link, b"").unwrap();
+        assert!(crate::cleanup::executor::remove_broken_link(&link).is_err());
+        fs::remove_file(&link).unwrap();
+
+        let removed = RemovedLink {
+            path: link.to_string_lossy().to_string(),
+            target: "../Cellar/node/21.7.1/bin/node".to_string(),
+        };
+        assert_eq!(restore_link(&removed, true), LinkOutcome::WouldRecreate);
+        assert!(link.symlink_metadata().is_err());
+
+        assert_eq!(restore_link(&removed, false), LinkOutcome::Recreated);
+        assert_eq!(fs::read_link(&link).unwrap(), PathBuf::from("../Cellar/node/21.7.1/bin/node"));
+        assert_eq!(restore_link(&removed, false), LinkOutcome::PathInUse);
+    }
+}
@@ -1,136 +1,636 @@
 // Execute package removal commands
-use anyhow::{Context, Result};
+use crate::error::{Context, MacsweepError, Result};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use crate::analysis::protected::ProtectedPackages;
+use crate::analysis::recommendations::CleanupAction;
+use crate::cleanup::log::CommandRecord;
+use crate::scanner::homebrew::active_prefix;
+use crate::utils::process::{CommandKind, CommandTimeouts, SystemRunner};
+use std::time::Duration;
 use crate::scanner::{Package, PackageSource};
 
-pub fn remove_package(package: &Package, dry_run: bool) -> Result<bool> {
-    if dry_run {
+/// How `remove_package` removes packages
+#[derive(Debug, Clone, Default)]
+pub struct RemovalOptions {
+    pub dry_run: bool,
+    /// Also remove casks' preferences, caches and other files (`brew uninstall --zap`)
+    pub zap: bool,
+    /// Packages that are refused unless `force_protected` is set
+    pub protected: ProtectedPackages,
+    pub force_protected: bool,
+    /// How long an uninstaller may run before it is killed
+    pub timeouts: CommandTimeouts,
+}
+
+impl RemovalOptions {
+    fn timeout(&self) -> Duration {
+        self.timeouts.get(CommandKind::Uninstall)
+    }
+}
+
+/// Result of trying to remove one package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemovalOutcome {
+    Removed,
+    /// Moved to the Trash, at this path when the file manager said where
+    Trashed(Option<PathBuf>),
+    /// The uninstaller ran but refused; holds its error output
+    Failed(String),
+}
+
+/// A removal attempt: its outcome and the uninstaller that ran, if any
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub outcome: RemovalOutcome,
+    pub command: Option<CommandRecord>,
+}
+
+impl From<RemovalOutcome> for Removal {
+    fn from(outcome: RemovalOutcome) -> Self {
+        Self { outcome, command: None }
+    }
+}
+
+/// Turn an uninstaller's exit status and stderr into an outcome
+fn command_outcome(record: CommandRecord) -> Removal {
+    let outcome = if record.success() {
+        RemovalOutcome::Removed
+    } else {
+        RemovalOutcome::Failed(record.stderr_summary())
+    };
+    Removal { outcome, command: Some(record) }
+}
+
+/// Run an uninstaller to completion; errors mean it could not be started at all
+fn run_uninstaller(program: &str, args: &[&str], timeout: Duration) -> Result<CommandRecord> {
+    CommandRecord::run(Command::new(program).args(args), timeout)
+        .with_context(|| format!("Failed to execute {} {}", program, args.first().copied().unwrap_or_default()))
+}
+
+/// Remove a package; errors mean the uninstaller could not be run at all
+pub fn remove_package(package: &Package, options: &RemovalOptions) -> Result<Removal> {
+    if !options.force_protected {
+        if let Some(reason) = options.protected.reason(package) {
+            return Ok(RemovalOutcome::Failed(format!(
+                "{} is protected ({}); use --force-protected to remove it anyway",
+                package.name, reason
+            )).into());
+        }
+    }
+
+    if options.dry_run {
         // In dry-run mode, silently succeed (progress bar shows package name)
-        return Ok(true);
+        return Ok(RemovalOutcome::Removed.into());
     }
 
     // Progress bar shows the package name being removed
 
+    let timeout = options.timeout();
     match package.source {
-        PackageSource::Homebrew | PackageSource::HomebrewCask => {
-            remove_homebrew_package(&package.name)
+        PackageSource::Homebrew => {
+            remove_homebrew_package(&package.name, false, false, timeout)
+        }
+        PackageSource::HomebrewCask => {
+            remove_homebrew_package(&package.name, true, options.zap, timeout)
         }
         PackageSource::Npm => {
-            remove_npm_package(&package.name)
+            remove_npm_package(&package.name, timeout)
         }
         PackageSource::Pip | PackageSource::Pipx => {
-            remove_pip_package(&package.name, &package.source)
+            remove_pip_package(&package.name, &package.source, timeout)
         }
         PackageSource::Cargo => {
-            remove_cargo_package(&package.name)
+            remove_cargo_package(&package.name, timeout)
         }
         PackageSource::Applications => {
             remove_application(package)
         }
-        _ => {
-            eprintln!("  ⚠️  Cannot remove package from source: {:?}", package.source);
-            Ok(false)
+        PackageSource::Plugin(_) => {
+            remove_plugin_package(package, timeout)
         }
+        _ => Ok(RemovalOutcome::Failed(format!("Cannot remove packages from source {:?}", package.source)).into()),
     }
 }
 
-fn remove_homebrew_package(name: &str) -> Result<bool> {
-    let output = Command::new("brew")
-        .args(["uninstall", name])
-        .output()
-        .context("Failed to execute brew uninstall")?;
+/// Remove the versions of a formula left behind by upgrades with `brew cleanup`, keeping the
+/// formula itself
+pub fn cleanup_stale_versions(package: &Package, options: &RemovalOptions) -> Result<Removal> {
+    if package.source != PackageSource::Homebrew {
+        return Ok(RemovalOutcome::Failed(format!(
+            "brew cleanup only applies to Homebrew formulae, not {:?}",
+            package.source
+        )).into());
+    }
+    if options.dry_run {
+        return Ok(RemovalOutcome::Removed.into());
+    }
+    Ok(command_outcome(run_uninstaller("brew", &["cleanup", &package.name], options.timeout())?))
+}
 
-    if output.status.success() {
-        Ok(true)
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("    ✗ Failed to remove {}: {}", name, stderr.trim());
-        Ok(false)
+/// Carry out `action` on `package`
+pub fn run_action(package: &Package, action: CleanupAction, options: &RemovalOptions) -> Result<Removal> {
+    match action {
+        CleanupAction::Remove => remove_package(package, options),
+        CleanupAction::BrewCleanup => cleanup_stale_versions(package, options),
     }
 }
 
-fn remove_npm_package(name: &str) -> Result<bool> {
-    let output = Command::new("npm")
-        .args(["uninstall", "-g", name])
-        .output()
-        .context("Failed to execute npm uninstall")?;
+/// Most removals run at once by `remove_packages`
+pub const MAX_PARALLEL_REMOVALS: usize = 4;
 
-    if output.status.success() {
-        Ok(true)
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("    ✗ Failed to remove {}: {}", name, stderr.trim());
-        Ok(false)
+/// Progress of `remove_packages`; `worker` identifies the thread, `index` the package
+#[derive(Debug)]
+pub enum RemovalEvent {
+    Started { worker: usize, index: usize },
+    Finished {
+        worker: usize,
+        index: usize,
+        removal: Result<Removal>,
+        /// What `remaining_after` found after a reported success
+        remaining: Option<PathBuf>,
+    },
+}
+
+/// Whether removals from `source` can run alongside other removals from the same source;
+/// brew takes a global lock, so Homebrew formulae and casks go one at a time
+fn parallel_safe(source: &PackageSource) -> bool {
+    !matches!(source, PackageSource::Homebrew | PackageSource::HomebrewCask)
+}
+
+/// Carry out each package's action with up to `MAX_PARALLEL_REMOVALS` running at once.
+/// Homebrew packages are handled one at a time in the order given (dependents before
+/// dependencies), everything else in parallel. `on_event` runs on the calling thread as
+/// removals start and finish; no new removal starts once `stop` returns true.
+pub fn remove_packages(
+    packages: &[(&Package, CleanupAction)],
+    options: &RemovalOptions,
+    stop: &(dyn Fn() -> bool + Sync),
+    mut on_event: impl FnMut(RemovalEvent),
+) {
+    use std::collections::VecDeque;
+    use std::sync::{mpsc, Mutex};
+
+    let (serial, parallel): (VecDeque<usize>, VecDeque<usize>) =
+        (0..packages.len()).partition(|&i| !parallel_safe(&packages[i].0.source));
+    let workers = match (serial.is_empty(), parallel.len()) {
+        (true, n) => n.min(MAX_PARALLEL_REMOVALS),
+        (false, n) => 1 + n.min(MAX_PARALLEL_REMOVALS - 1),
+    };
+    let homebrew_prefix = (!serial.is_empty()).then(|| active_prefix(&SystemRunner));
+    let serial = Mutex::new(serial);
+    let parallel = Mutex::new(parallel);
+    let pop = |queue: &Mutex<VecDeque<usize>>| queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for worker in 0..workers {
+            let tx = tx.clone();
+            let (serial, parallel, pop, homebrew_prefix) = (&serial, &parallel, &pop, homebrew_prefix.as_deref());
+            scope.spawn(move || {
+                loop {
+                    if stop() {
+                        break;
+                    }
+                    // Only worker 0 takes Homebrew removals, so they never overlap
+                    let next = if worker == 0 { pop(serial).or_else(|| pop(parallel)) } else { pop(parallel) };
+                    let Some(index) = next else {
+                        break;
+                    };
+
+                    let (package, action) = packages[index];
+                    let _ = tx.send(RemovalEvent::Started { worker, index });
+                    let removal = run_action(package, action, options);
+                    let remaining = match removal {
+                        Ok(Removal { outcome: RemovalOutcome::Removed | RemovalOutcome::Trashed(_), .. }) if !options.dry_run => {
+                            remaining_after(package, action, homebrew_prefix, options.timeout())
+                        }
+                        _ => None,
+                    };
+                    let _ = tx.send(RemovalEvent::Finished { worker, index, removal, remaining });
+                }
+            });
+        }
+        drop(tx);
+
+        for event in rx {
+            on_event(event);
+        }
+    });
+}
+
+/// System directories no package manager installs into; a binary found there by name is
+/// not the removed package's
+const SYSTEM_BIN_DIRS: &[&str] = &["/bin", "/sbin", "/usr/bin", "/usr/sbin"];
+
+/// Something `action` should have taken away but is still there: for a removal, the
+/// package's binary or app bundle, or its Cellar/Caskroom directory under the active
+/// Homebrew prefix or brew still listing it; for `brew cleanup`, one of its stale versions
+pub fn remaining_after(package: &Package, action: CleanupAction, homebrew_prefix: Option<&Path>, timeout: Duration) -> Option<PathBuf> {
+    match (action, homebrew_prefix) {
+        (CleanupAction::Remove, Some(prefix)) => remaining_after_removal_in(package, Some(prefix))
+            .or_else(|| brew_still_lists(package, timeout).then(|| keg_path(package, prefix)).flatten()),
+        (CleanupAction::Remove, None) => remaining_after_removal_in(package, None),
+        (CleanupAction::BrewCleanup, Some(prefix)) => stale_versions_remaining_in(package, prefix),
+        (CleanupAction::BrewCleanup, None) => None,
+    }
+}
+
+/// A stale version's Cellar directory that `brew cleanup` left in place
+fn stale_versions_remaining_in(package: &Package, homebrew_prefix: &Path) -> Option<PathBuf> {
+    package.stale_versions.iter()
+        .map(|version| homebrew_prefix.join("Cellar").join(&package.name).join(version))
+        .find(|path| path.symlink_metadata().is_ok())
+}
+
+/// Where a Homebrew formula's keg or a cask's Caskroom entry lives under `homebrew_prefix`
+fn keg_path(package: &Package, homebrew_prefix: &Path) -> Option<PathBuf> {
+    let keg_dir = match package.source {
+        PackageSource::Homebrew => "Cellar",
+        PackageSource::HomebrewCask => "Caskroom",
+        _ => return None,
+    };
+    Some(homebrew_prefix.join(keg_dir).join(&package.name))
+}
+
+/// Only the active prefix counts: an Intel install left under /usr/local says nothing
+/// about whether the Apple Silicon one was removed
+fn remaining_after_removal_in(package: &Package, homebrew_prefix: Option<&Path>) -> Option<PathBuf> {
+    package.binary_path.iter()
+        .filter(|path| !SYSTEM_BIN_DIRS.iter().any(|dir| path.parent() == Some(Path::new(dir))))
+        .cloned()
+        .chain(homebrew_prefix.and_then(|prefix| keg_path(package, prefix)))
+        .find(|path| path.symlink_metadata().is_ok())
+}
+
+/// Whether `brew list --versions` still reports the formula or cask
+fn brew_still_lists(package: &Package, timeout: Duration) -> bool {
+    let kind = match package.source {
+        PackageSource::Homebrew => "--formula",
+        PackageSource::HomebrewCask => "--cask",
+        _ => return false,
+    };
+    crate::utils::process::run_command_with_timeout("brew", &["list", kind, "--versions", &package.name], timeout)
+        .is_ok_and(|output| !output.trim().is_empty())
+}
+
+fn remove_homebrew_package(name: &str, cask: bool, zap: bool, timeout: Duration) -> Result<Removal> {
+    let mut removal = command_outcome(run_uninstaller("brew", &homebrew_uninstall_args(name, cask, zap), timeout)?);
+    if let RemovalOutcome::Failed(ref stderr) = removal.outcome {
+        removal.outcome = RemovalOutcome::Failed(homebrew_failure(name, stderr));
+    }
+    Ok(removal)
+}
+
+/// Ask the plugin that reported the package to remove it with `<plugin> remove <name>`
+fn remove_plugin_package(package: &Package, timeout: Duration) -> Result<Removal> {
+    let name = package.source.plugin_name().unwrap_or_default();
+    let Some(plugin) = crate::scanner::plugin::find(name) else {
+        return Ok(RemovalOutcome::Failed(format!("The {} plugin is no longer installed", name)).into());
+    };
+    let record = CommandRecord::run(Command::new(&plugin.path).args(["remove", &package.name]), timeout)
+        .with_context(|| format!("Failed to execute {} remove", plugin.path.display()))?;
+    Ok(command_outcome(record))
+}
+
+/// Uninstall the formulae `names` together, e.g. the dependencies a cleanup left unneeded
+pub fn remove_formulae(names: &[String], options: &RemovalOptions) -> Result<CommandRecord> {
+    let mut args = vec!["uninstall", "--formula"];
+    args.extend(names.iter().map(String::as_str));
+    run_uninstaller("brew", &args, options.timeout())
+}
+
+/// `brew uninstall` arguments; `--formula`/`--cask` keep a formula and a cask with the same
+/// name (e.g. wireshark) apart
+fn homebrew_uninstall_args(name: &str, cask: bool, zap: bool) -> Vec<&str> {
+    match (cask, zap) {
+        (true, true) => vec!["uninstall", "--cask", "--zap", name],
+        (true, false) => vec!["uninstall", "--cask", name],
+        (false, _) => vec!["uninstall", "--formula", name],
+    }
+}
+
+/// Explain a failed `brew uninstall`, naming the packages that still depend on it
+fn homebrew_failure(name: &str, stderr: &str) -> String {
+    // "Refusing to uninstall /opt/homebrew/Cellar/openssl@3/3.2.1
+    //  because it is required by curl and wget, which are currently installed."
+    let flat = stderr.split_whitespace().collect::<Vec<_>>().join(" ");
+    let dependents = flat
+        .split_once("required by ")
+        .map(|(_, rest)| rest.split(", which ").next().unwrap_or(rest).trim_end_matches('.'));
+
+    match dependents {
+        Some(dependents) => format!(
+            "{} is required by {}; remove those first or run `brew uninstall --ignore-dependencies {}`",
+            name, dependents, name
+        ),
+        None => stderr.to_string(),
     }
 }
 
-fn remove_pip_package(name: &str, source: &PackageSource) -> Result<bool> {
+fn remove_npm_package(name: &str, timeout: Duration) -> Result<Removal> {
+    Ok(command_outcome(run_uninstaller("npm", &["uninstall", "-g", name], timeout)?))
+}
+
+fn remove_pip_package(name: &str, source: &PackageSource, timeout: Duration) -> Result<Removal> {
     let command = match source {
         PackageSource::Pipx => "pipx",
         _ => "pip3",
     };
 
-    let output = Command::new(command)
-        .args(["uninstall", "-y", name])
-        .output()
-        .context(format!("Failed to execute {} uninstall", command))?;
+    Ok(command_outcome(run_uninstaller(command, &["uninstall", "-y", name], timeout)?))
+}
+
+fn remove_cargo_package(name: &str, timeout: Duration) -> Result<Removal> {
+    Ok(command_outcome(run_uninstaller("cargo", &["uninstall", name], timeout)?))
+}
 
-    if output.status.success() {
-        Ok(true)
+fn remove_application(package: &Package) -> Result<Removal> {
+    let outcome = if let Some(ref path) = package.binary_path {
+        // Move to trash instead of deleting directly (safer)
+        match move_to_trash(path) {
+            Ok(trashed) => RemovalOutcome::Trashed(trashed),
+            Err(e) => RemovalOutcome::Failed(crate::error::chain(&e)),
+        }
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("    ✗ Failed to remove {}: {}", name, stderr.trim());
-        Ok(false)
+        RemovalOutcome::Failed("No binary path recorded".to_string())
+    };
+    Ok(outcome.into())
+}
+
+/// Delete a dead symlink, refusing anything that is no longer a dangling link (a reinstall
+/// may have made it live again); the target it pointed to is never touched
+pub fn remove_broken_link(path: &Path) -> Result<()> {
+    if crate::analysis::broken_links::dangling_target(path).is_none() {
+        return Err(MacsweepError::Other(format!("{} is no longer a broken symlink", path.display())));
     }
+    std::fs::remove_file(path).with_context(|| format!("Cannot remove {}", path.display()))
 }
 
-fn remove_cargo_package(name: &str) -> Result<bool> {
-    let output = Command::new("cargo")
-        .args(["uninstall", name])
-        .output()
-        .context("Failed to execute cargo uninstall")?;
+/// Move a file or directory to the Trash (never deletes directly), returning where it went,
+/// or `None` when the file manager trashed it without saying where
+pub fn move_to_trash(path: &Path) -> Result<Option<PathBuf>> {
+    if path.symlink_metadata().is_err() {
+        return Err(MacsweepError::Other(format!("{} does not exist", path.display())));
+    }
+
+    #[cfg(target_os = "macos")]
+    let trashed = file_manager_trash(path)?;
+    #[cfg(not(target_os = "macos"))]
+    let trashed = None;
 
-    if output.status.success() {
-        Ok(true)
-    } else {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("    ✗ Failed to remove {}: {}", name, stderr.trim());
-        Ok(false)
+    let trashed = match trashed {
+        Some(trashed) => trashed,
+        None => {
+            let home = dirs::home_dir().context("Failed to get home directory")?;
+            Some(move_into_trash_dir(path, &home.join(".Trash"))?)
+        }
+    };
+
+    // Never report success for something that is still in place
+    if path.symlink_metadata().is_ok() {
+        return Err(MacsweepError::Other(format!("{} is still there after moving it to the Trash", path.display())));
     }
+    Ok(trashed)
 }
 
-fn remove_application(package: &Package) -> Result<bool> {
-    if let Some(ref path) = package.binary_path {
-        // Move to trash instead of deleting directly (safer)
-        let output = Command::new("osascript")
-            .args([
-                "-e",
-                &format!("tell application \"Finder\" to delete POSIX file \"{}\"", path.display())
-            ])
-            .output()
-            .context("Failed to move application to trash")?;
-
-        if output.status.success() {
-            Ok(true)
-        } else {
-            eprintln!("    ✗ Failed to move {} to trash", package.name);
-            Ok(false)
+/// Trash with `NSFileManager`, which needs neither Finder nor an automation permission and
+/// uses the Trash of the volume the item is on. `None` means fall back to `~/.Trash`; a
+/// trashed item whose new location NSFileManager didn't report is `Some(None)`.
+#[cfg(target_os = "macos")]
+fn file_manager_trash(path: &Path) -> Result<Option<Option<PathBuf>>> {
+    use objc2_foundation::{NSFileManager, NSURL};
+
+    /// NSFileReadNoPermissionError and NSFileWriteNoPermissionError
+    const NO_PERMISSION: [isize; 2] = [257, 513];
+
+    let Some(url) = NSURL::from_file_path(path) else {
+        return Ok(None);
+    };
+    let mut resulting = None;
+    match NSFileManager::defaultManager().trashItemAtURL_resultingItemURL_error(&url, Some(&mut resulting)) {
+        Ok(()) => Ok(Some(resulting.and_then(|url| url.to_file_path()))),
+        Err(error) if NO_PERMISSION.contains(&error.code()) => Err(permission_denied(
+            path,
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, error.localizedDescription().to_string()),
+        )),
+        Err(error) => {
+            tracing::debug!(path = %path.display(), error = %error.localizedDescription(), "NSFileManager could not trash the file");
+            Ok(None)
         }
-    } else {
-        eprintln!("    ✗ No binary path found for {}", package.name);
-        Ok(false)
     }
 }
 
-fn format_source(source: &PackageSource) -> String {
-    match source {
-        PackageSource::Homebrew => "Homebrew".to_string(),
-        PackageSource::HomebrewCask => "Homebrew Cask".to_string(),
-        PackageSource::Npm => "npm".to_string(),
-        PackageSource::Pip => "pip".to_string(),
-        PackageSource::Pipx => "pipx".to_string(),
-        PackageSource::Cargo => "cargo".to_string(),
-        PackageSource::Applications => "Applications".to_string(),
-        _ => format!("{:?}", source),
+/// An I/O error callers can check for `PermissionDenied`, explaining why trashing `path` failed
+fn permission_denied(path: &Path, source: std::io::Error) -> MacsweepError {
+    MacsweepError::Io {
+        context: Some(format!(
+            "Permission denied moving {} to the Trash; it was probably installed by another user or needs admin rights",
+            path.display()
+        )),
+        source,
+    }
+}
+
+/// Move `path` into `trash_dir`, adding a number to the name when the Trash already has an
+/// item with the same name (`Foo.app`, `Foo 2.app`, ...)
+fn move_into_trash_dir(path: &Path, trash_dir: &Path) -> Result<PathBuf> {
+    let file_name = path.file_name().with_context(|| format!("{} has no file name", path.display()))?;
+    std::fs::create_dir_all(trash_dir).with_context(|| format!("Cannot create {}", trash_dir.display()))?;
+
+    let original = Path::new(file_name);
+    let stem = original.file_stem().unwrap_or(file_name).to_string_lossy();
+    let extension = original.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+
+    let mut target = trash_dir.join(file_name);
+    let mut n = 2;
+    while target.symlink_metadata().is_ok() {
+        target = trash_dir.join(format!("{} {}{}", stem, n, extension));
+        n += 1;
+    }
+
+    match std::fs::rename(path, &target) {
+        Ok(()) => Ok(target),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => Err(permission_denied(path, e)),
+        // EXDEV: the Trash is on another volume
+        Err(e) if e.raw_os_error() == Some(18) => Err(MacsweepError::Other(format!(
+            "{} is on a different volume than {}; move it to the Trash in Finder",
+            path.display(),
+            trash_dir.display()
+        ))),
+        Err(e) => Err(e).with_context(|| format!("Failed to move {} to {}", path.display(), trash_dir.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_packages_keeps_homebrew_serial_and_in_order() {
+        let packages: Vec<Package> = [
+            ("ffmpeg", PackageSource::Homebrew),
+            ("typescript", PackageSource::Npm),
+            ("x264", PackageSource::Homebrew),
+            ("black", PackageSource::Pip),
+            ("Zoom", PackageSource::HomebrewCask),
+            ("ripgrep", PackageSource::Cargo),
+        ]
+        .into_iter()
+        .map(|(name, source)| Package::new(name.to_string(), source))
+        .collect();
+        let refs: Vec<_> = packages.iter().map(|p| (p, CleanupAction::Remove)).collect();
+        let options = RemovalOptions { dry_run: true, ..Default::default() };
+
+        let mut finished = Vec::new();
+        let mut homebrew_workers = std::collections::HashSet::new();
+        remove_packages(&refs, &options, &|| false, |event| {
+            if let RemovalEvent::Finished { worker, index, removal, remaining } = event {
+                assert_eq!(removal.unwrap().outcome, RemovalOutcome::Removed);
+                assert!(remaining.is_none());
+                if !parallel_safe(&packages[index].source) {
+                    homebrew_workers.insert(worker);
+                }
+                finished.push(index);
+            }
+        });
+
+        assert_eq!(finished.len(), packages.len());
+        let homebrew: Vec<_> = finished.iter().copied().filter(|&i| !parallel_safe(&packages[i].source)).collect();
+        assert_eq!(homebrew, [0, 2, 4]);
+        assert_eq!(homebrew_workers.len(), 1);
+
+        let mut started = 0;
+        remove_packages(&refs, &options, &|| true, |_| started += 1);
+        assert_eq!(started, 0);
+    }
+
+    #[test]
+    fn test_remaining_after_removal() {
+        let prefix = tempfile::TempDir::new().unwrap();
+        let prefix_path = Some(prefix.path());
+        let bin = prefix.path().join("bin/jq");
+        std::fs::create_dir_all(bin.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(prefix.path().join("Cellar/jq/1.7.1")).unwrap();
+        std::fs::write(&bin, b"").unwrap();
+
+        let mut jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        jq.binary_path = Some(bin.clone());
+        assert_eq!(remaining_after_removal_in(&jq, prefix_path), Some(bin.clone()));
+
+        // Unlinked but the keg is still in the Cellar
+        std::fs::remove_file(&bin).unwrap();
+        assert_eq!(remaining_after_removal_in(&jq, prefix_path), Some(prefix.path().join("Cellar/jq")));
+
+        std::fs::remove_dir_all(prefix.path().join("Cellar/jq")).unwrap();
+        assert_eq!(remaining_after_removal_in(&jq, prefix_path), None);
+
+        // A cask's Caskroom entry, not a formula's Cellar, is what matters
+        std::fs::create_dir_all(prefix.path().join("Cellar/jq")).unwrap();
+        let cask = Package::new("jq".to_string(), PackageSource::HomebrewCask);
+        assert_eq!(remaining_after_removal_in(&cask, prefix_path), None);
+
+        // Binaries in system directories were never the package's
+        let mut black = Package::new("black".to_string(), PackageSource::Pip);
+        black.binary_path = Some(PathBuf::from("/bin/sh"));
+        assert_eq!(remaining_after_removal_in(&black, prefix_path), None);
+
+        // A keg left under another prefix, e.g. an old Intel install, isn't this removal's
+        let other = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir_all(other.path().join("Cellar/jq/1.7.1")).unwrap();
+        std::fs::remove_dir_all(prefix.path().join("Cellar/jq")).unwrap();
+        assert_eq!(remaining_after_removal_in(&jq, prefix_path), None);
+        // ... though it is when that prefix is the active one
+        assert_eq!(remaining_after_removal_in(&jq, Some(other.path())), Some(other.path().join("Cellar/jq")));
+    }
+
+    #[test]
+    fn test_stale_versions_remaining() {
+        let prefix = tempfile::TempDir::new().unwrap();
+        for version in ["21.7.1", "22.2.0", "22.3.0"] {
+            std::fs::create_dir_all(prefix.path().join("Cellar/node").join(version)).unwrap();
+        }
+
+        let mut node = Package::new("node".to_string(), PackageSource::Homebrew);
+        node.stale_versions = vec!["21.7.1".to_string(), "22.2.0".to_string()];
+        assert_eq!(stale_versions_remaining_in(&node, prefix.path()), Some(prefix.path().join("Cellar/node/21.7.1")));
+
+        // The active version staying is the point of `brew cleanup`
+        std::fs::remove_dir_all(prefix.path().join("Cellar/node/21.7.1")).unwrap();
+        std::fs::remove_dir_all(prefix.path().join("Cellar/node/22.2.0")).unwrap();
+        assert_eq!(stale_versions_remaining_in(&node, prefix.path()), None);
+        assert!(remaining_after_removal_in(&node, Some(prefix.path())).is_some());
+
+        let options = RemovalOptions { dry_run: true, ..Default::default() };
+        assert_eq!(run_action(&node, CleanupAction::BrewCleanup, &options).unwrap().outcome, RemovalOutcome::Removed);
+        let npm = Package::new("typescript".to_string(), PackageSource::Npm);
+        assert!(matches!(
+            run_action(&npm, CleanupAction::BrewCleanup, &options).unwrap().outcome,
+            RemovalOutcome::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_move_into_trash_dir_renames_collisions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let trash = dir.path().join(".Trash");
+        let app = dir.path().join("Applications/Foo.app");
+        let tool = dir.path().join("bin/foo");
+
+        for expected in ["Foo.app", "Foo 2.app", "Foo 3.app"] {
+            std::fs::create_dir_all(app.join("Contents")).unwrap();
+            assert_eq!(move_into_trash_dir(&app, &trash).unwrap(), trash.join(expected));
+            assert!(!app.exists());
+        }
+        assert!(trash.join("Foo 2.app/Contents").is_dir());
+
+        std::fs::create_dir_all(tool.parent().unwrap()).unwrap();
+        std::fs::write(&tool, b"").unwrap();
+        assert_eq!(move_into_trash_dir(&tool, &trash).unwrap(), trash.join("foo"));
+
+        let err = move_to_trash(&dir.path().join("Applications/Missing.app")).unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn test_homebrew_uninstall_args() {
+        assert_eq!(homebrew_uninstall_args("wireshark", false, false), ["uninstall", "--formula", "wireshark"]);
+        assert_eq!(homebrew_uninstall_args("wireshark", false, true), ["uninstall", "--formula", "wireshark"]);
+        assert_eq!(homebrew_uninstall_args("wireshark", true, false), ["uninstall", "--cask", "wireshark"]);
+        assert_eq!(homebrew_uninstall_args("wireshark", true, true), ["uninstall", "--cask", "--zap", "wireshark"]);
+    }
+
+    #[test]
+    fn test_protected_packages_are_refused() {
+        let git = Package::new("git".to_string(), PackageSource::Homebrew);
+        let mut options = RemovalOptions {
+            dry_run: true,
+            protected: ProtectedPackages::new(&[], []),
+            ..Default::default()
+        };
+
+        match remove_package(&git, &options).unwrap().outcome {
+            RemovalOutcome::Failed(message) => assert!(message.contains("--force-protected"), "{}", message),
+            outcome => panic!("git was not refused: {:?}", outcome),
+        }
+        let jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        assert_eq!(remove_package(&jq, &options).unwrap().outcome, RemovalOutcome::Removed);
+
+        options.force_protected = true;
+        assert_eq!(remove_package(&git, &options).unwrap().outcome, RemovalOutcome::Removed);
+    }
+
+    #[test]
+    fn test_homebrew_failure_names_dependents() {
+        let stderr = "Error: Refusing to uninstall /opt/homebrew/Cellar/openssl@3/3.2.1\n\
+                      because it is required by curl and wget, which are currently installed.\n\
+                      You can override this and force removal with:\n  brew uninstall --ignore-dependencies openssl@3";
+        assert_eq!(
+            homebrew_failure("openssl@3", stderr),
+            "openssl@3 is required by curl and wget; remove those first or run `brew uninstall --ignore-dependencies openssl@3`"
+        );
+
+        let stderr = "Error: No such keg: /opt/homebrew/Cellar/jq";
+        assert_eq!(homebrew_failure("jq", stderr), stderr);
     }
 }
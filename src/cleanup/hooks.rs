@@ -0,0 +1,182 @@
+// `[hooks]` run after a cleanup: a command given the cleanup report on stdin, and a webhook
+// the report is posted to
+use crate::cleanup::runner::CleanupReport;
+use crate::config::HookConfig;
+use crate::error::{MacsweepError, Result};
+use crate::utils::process::output_with_input;
+use std::fmt;
+use std::process::Command;
+use std::time::Duration;
+
+/// A hook that failed, named by its config key
+#[derive(Debug)]
+pub struct HookFailure {
+    pub hook: &'static str,
+    pub error: MacsweepError,
+}
+
+impl fmt::Display for HookFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.hook, self.error)
+    }
+}
+
+/// Run the post-clean command and post to the webhook, each with `report` as JSON. The webhook
+/// is posted to even if the command failed.
+pub fn run_post_clean(hooks: &HookConfig, report: &CleanupReport) -> Vec<HookFailure> {
+    run_hooks(hooks, report, |variable| std::env::var(variable).ok())
+}
+
+/// `run_post_clean`, reading the webhook token through `env`
+fn run_hooks(hooks: &HookConfig, report: &CleanupReport, env: impl Fn(&str) -> Option<String>) -> Vec<HookFailure> {
+    let payload = serde_json::to_vec(report).map_err(|e| format!("Failed to serialize the cleanup report: {}", e));
+    let timeout = Duration::from_secs(hooks.timeout);
+    let mut failures = Vec::new();
+    // Without a report to send, every configured hook fails the same way
+    let with_payload = |run: &dyn Fn(&[u8]) -> Result<()>| match &payload {
+        Ok(payload) => run(payload),
+        Err(e) => Err(MacsweepError::Other(e.clone())),
+    };
+
+    if let Some(command) = &hooks.post_clean_command {
+        if let Err(e) = with_payload(&|payload| run_command_hook(command, payload, timeout)) {
+            failures.push(HookFailure { hook: "post_clean_command", error: e });
+        }
+    }
+    if let Some(url) = &hooks.post_clean_webhook {
+        let token = hooks.webhook_token_env.as_deref().map(|variable| {
+            env(variable).ok_or_else(|| format!("{} isn't set; it should hold the webhook's bearer token", variable))
+        });
+        let post = |payload: &[u8]| {
+            let token = token.clone().transpose().map_err(MacsweepError::Other)?;
+            post_webhook(url, token.as_deref(), payload, timeout)
+        };
+        if let Err(e) = with_payload(&post) {
+            failures.push(HookFailure { hook: "post_clean_webhook", error: e });
+        }
+    }
+    failures
+}
+
+fn run_command_hook(command: &str, payload: &[u8], timeout: Duration) -> Result<()> {
+    tracing::debug!(command, "Running post-clean command");
+    let output = output_with_input(Command::new("sh").args(["-c", command]), payload, timeout)
+        .map_err(|e| MacsweepError::spawn("sh", format!("Failed to run {}", command), e))?;
+    match output.status {
+        None => Err(MacsweepError::TimedOut { program: command.to_string(), timeout }),
+        Some(status) if !status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            let stderr = if stderr.trim().is_empty() { status.to_string() } else { stderr };
+            Err(MacsweepError::CommandFailed { program: command.to_string(), stderr })
+        }
+        Some(_) => Ok(()),
+    }
+}
+
+/// POST `payload` to `url`, with `token` as the bearer token. Only a 2xx answer counts as
+/// delivered.
+fn post_webhook(url: &str, token: Option<&str>, payload: &[u8], timeout: Duration) -> Result<()> {
+    tracing::debug!(url, "Posting cleanup report");
+    let mut request = ureq::post(url)
+        .timeout(timeout)
+        .set("Content-Type", "application/json")
+        .set("User-Agent", concat!("macsweep/", env!("CARGO_PKG_VERSION")));
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    match request.send_bytes(payload) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(code, response)) => {
+            Err(MacsweepError::Other(format!("{} answered {} {}", url, code, response.status_text())))
+        }
+        Err(e) => Err(MacsweepError::Other(format!("Failed to reach {}: {}", url, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use tempfile::TempDir;
+
+    fn report() -> CleanupReport {
+        CleanupReport { attempted: 2, removed: 1, failed: 1, bytes_recovered: 4096, ..Default::default() }
+    }
+
+    /// Answer one request with `status`, sending back its headers and body
+    fn serve_once(status: &'static str) -> (String, mpsc::Receiver<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_string());
+            }
+            let length = headers
+                .iter()
+                .find_map(|h| h.to_lowercase().strip_prefix("content-length:").map(|n| n.trim().parse::<usize>().unwrap()))
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            sender.send((headers, String::from_utf8(body).unwrap())).unwrap();
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_command_gets_the_report_on_stdin() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("report.json");
+        let hooks = HookConfig { post_clean_command: Some(format!("cat > '{}'", out.display())), ..Default::default() };
+        assert!(run_post_clean(&hooks, &report()).is_empty());
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+        assert_eq!((json["removed"].as_u64(), json["failed"].as_u64()), (Some(1), Some(1)));
+
+        let hooks = HookConfig { post_clean_command: Some("echo 'inventory is down' >&2; exit 3".to_string()), ..Default::default() };
+        let failures = run_post_clean(&hooks, &report());
+        assert_eq!(failures[0].to_string(), "post_clean_command: echo 'inventory is down' >&2; exit 3 failed: inventory is down");
+
+        let hooks = HookConfig { post_clean_command: Some("sleep 5".to_string()), timeout: 1, ..Default::default() };
+        assert!(run_post_clean(&hooks, &report())[0].to_string().contains("timed out after 1s"));
+    }
+
+    #[test]
+    fn test_webhook_posts_the_report() {
+        let (url, received) = serve_once("204 No Content");
+        let env = |variable: &str| (variable == "MACSWEEP_TEST_HOOK_TOKEN").then(|| "s3cret".to_string());
+        let hooks = HookConfig {
+            post_clean_webhook: Some(url),
+            webhook_token_env: Some("MACSWEEP_TEST_HOOK_TOKEN".to_string()),
+            ..Default::default()
+        };
+        assert!(run_hooks(&hooks, &report(), env).is_empty());
+        let (headers, body) = received.recv().unwrap();
+        assert!(headers[0].starts_with("POST /hook "));
+        assert!(headers.iter().any(|h| h == "Authorization: Bearer s3cret"));
+        assert!(headers.iter().any(|h| h.eq_ignore_ascii_case("content-type: application/json")));
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["bytes_recovered"], 4096);
+
+        let (url, _received) = serve_once("503 Service Unavailable");
+        let hooks = HookConfig { post_clean_webhook: Some(url.clone()), ..Default::default() };
+        assert_eq!(run_post_clean(&hooks, &report())[0].to_string(), format!("post_clean_webhook: {} answered 503 Service Unavailable", url));
+
+        let hooks = HookConfig {
+            post_clean_webhook: Some(url),
+            webhook_token_env: Some("MACSWEEP_TEST_HOOK_TOKEN_UNSET".to_string()),
+            ..Default::default()
+        };
+        assert!(run_hooks(&hooks, &report(), env)[0].to_string().contains("MACSWEEP_TEST_HOOK_TOKEN_UNSET isn't set"));
+    }
+}
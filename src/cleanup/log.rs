@@ -0,0 +1,213 @@
+// Full output of the uninstall and install commands a cleanup or undo runs
+use crate::error::{Context, Result};
+use crate::utils::process::{command_line, output_within};
+use chrono::Utc;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Most stderr lines kept in a cleanup item's error message; the log has all of it
+const STDERR_SUMMARY_LINES: usize = 20;
+
+/// An external command that ran to completion or was stopped at its timeout, whatever its
+/// exit status
+#[derive(Debug, Clone)]
+pub struct CommandRecord {
+    /// Program and arguments, space-separated
+    pub command_line: String,
+    /// `None` when the command was killed by a signal or for running too long
+    pub exit_code: Option<i32>,
+    /// The timeout the command was killed at, if it ran that long
+    pub timed_out: Option<Duration>,
+    pub duration: Duration,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandRecord {
+    /// Run `command` to completion, capturing its output, and kill it if it outlasts `timeout`;
+    /// errors mean it could not be started
+    pub fn run(command: &mut Command, timeout: Duration) -> std::io::Result<Self> {
+        let command_line = command_line(command);
+        let started = Instant::now();
+        let output = output_within(command, timeout)?;
+        Ok(Self {
+            command_line,
+            exit_code: output.status.and_then(|status| status.code()),
+            timed_out: output.status.is_none().then_some(timeout),
+            duration: started.elapsed(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// The last lines of stderr, for error messages; falls back to the exit status when the
+    /// command printed nothing, and ends with the timeout when it was killed for running too long
+    pub fn stderr_summary(&self) -> String {
+        if let Some(timeout) = self.timed_out {
+            let timed_out = format!("{} timed out after {}s", self.command_line, timeout.as_secs());
+            return match self.stderr.trim() {
+                "" => timed_out,
+                _ => format!("{}\n{}", self.printed_summary(), timed_out),
+            };
+        }
+        if self.stderr.trim().is_empty() {
+            return match self.exit_code {
+                Some(code) => format!("{} exited with status {}", self.command_line, code),
+                None => format!("{} was killed by a signal", self.command_line),
+            };
+        }
+        self.printed_summary()
+    }
+
+    fn printed_summary(&self) -> String {
+        let lines: Vec<&str> = self.stderr.trim().lines().collect();
+        let kept = &lines[lines.len().saturating_sub(STDERR_SUMMARY_LINES)..];
+        if kept.len() < lines.len() {
+            format!("... ({} earlier lines in the cleanup log)\n{}", lines.len() - kept.len(), kept.join("\n"))
+        } else {
+            kept.join("\n")
+        }
+    }
+}
+
+/// Directory holding the cleanup logs
+pub fn log_dir() -> Result<PathBuf> {
+    let home = dirs::home_dir().context("Failed to get home directory")?;
+    Ok(home.join("Library/Logs/macsweep"))
+}
+
+/// Log of one cleanup, `~/Library/Logs/macsweep/cleanup_<id>.log`; a resumed cleanup or an
+/// undo appends to the same file
+pub struct CleanupLog {
+    path: PathBuf,
+    file: File,
+}
+
+impl CleanupLog {
+    /// Open the log of the cleanup whose backup manifest is `manifest_path`, named like the
+    /// backup (`cleanup_20250101_120000.log`); a cleanup without a backup gets a new name
+    pub fn for_backup(manifest_path: Option<&str>) -> Result<Self> {
+        let name = manifest_path
+            .and_then(|p| Path::new(p).file_stem())
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("cleanup_{}", Utc::now().format("%Y%m%d_%H%M%S")));
+        Self::open_in(&log_dir()?, &name)
+    }
+
+    pub fn open_in(dir: &Path, name: &str) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+        let path = dir.join(format!("{}.log", name));
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Cannot open cleanup log {}", path.display()))?;
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a command and everything it printed
+    pub fn record(&mut self, package: &str, record: &CommandRecord) -> Result<()> {
+        let status = match (record.exit_code, record.timed_out) {
+            (Some(code), _) => format!("exit status {}", code),
+            (None, Some(timeout)) => format!("timed out after {}s, killed", timeout.as_secs()),
+            (None, None) => "killed by a signal".to_string(),
+        };
+        let mut entry = format!(
+            "[{}] {}: {}\n{} after {:.1}s\n",
+            Utc::now().to_rfc3339(),
+            package,
+            record.command_line,
+            status,
+            record.duration.as_secs_f64()
+        );
+        for (name, output) in [("stdout", &record.stdout), ("stderr", &record.stderr)] {
+            if !output.trim().is_empty() {
+                entry.push_str(&format!("--- {} ---\n{}\n", name, output.trim_end()));
+            }
+        }
+        entry.push('\n');
+        self.write(&entry)
+    }
+
+    /// Append a failure that didn't come from a command, such as a Trash error
+    pub fn note(&mut self, package: &str, message: &str) -> Result<()> {
+        self.write(&format!("[{}] {}: {}\n\n", Utc::now().to_rfc3339(), package, message))
+    }
+
+    fn write(&mut self, entry: &str) -> Result<()> {
+        self.file
+            .write_all(entry.as_bytes())
+            .with_context(|| format!("Cannot write cleanup log {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_record_and_log() {
+        let record = CommandRecord::run(
+            Command::new("sh").args(["-c", "echo out; for i in $(seq 1 30); do echo err $i >&2; done; exit 3"]),
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert!(!record.success());
+        assert_eq!(record.exit_code, Some(3));
+        assert_eq!(record.command_line, "sh -c echo out; for i in $(seq 1 30); do echo err $i >&2; done; exit 3");
+
+        let summary = record.stderr_summary();
+        assert!(summary.starts_with("... (10 earlier lines in the cleanup log)\nerr 11\n"));
+        assert!(summary.ends_with("err 30"));
+
+        let quiet = CommandRecord::run(Command::new("sh").args(["-c", "exit 1"]), Duration::from_secs(10)).unwrap();
+        assert_eq!(quiet.stderr_summary(), "sh -c exit 1 exited with status 1");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut log = CleanupLog::open_in(&dir.path().join("logs"), "cleanup_7").unwrap();
+        log.record("jq", &record).unwrap();
+        log.note("Foo", "Permission denied moving /Applications/Foo.app to the Trash").unwrap();
+
+        // Reopening appends
+        CleanupLog::open_in(&dir.path().join("logs"), "cleanup_7").unwrap().note("jq", "again").unwrap();
+
+        let contents = std::fs::read_to_string(dir.path().join("logs/cleanup_7.log")).unwrap();
+        assert!(contents.contains("jq: sh -c echo out;"));
+        assert!(contents.contains("exit status 3 after "));
+        assert!(contents.contains("--- stdout ---\nout\n--- stderr ---\nerr 1\n"));
+        assert!(contents.contains("err 30\n"));
+        assert!(contents.contains("Foo: Permission denied"));
+        assert!(contents.trim_end().ends_with("jq: again"));
+    }
+
+    #[test]
+    fn test_command_record_times_out() {
+        let record = CommandRecord::run(
+            Command::new("sh").args(["-c", "echo stuck >&2; exec sleep 30"]),
+            Duration::from_secs(1),
+        )
+        .unwrap();
+        assert!(!record.success());
+        assert_eq!(record.exit_code, None);
+        assert_eq!(record.timed_out, Some(Duration::from_secs(1)));
+        assert!(record.duration < Duration::from_secs(10));
+        assert_eq!(record.stderr_summary(), "stuck\nsh -c echo stuck >&2; exec sleep 30 timed out after 1s");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut log = CleanupLog::open_in(dir.path(), "cleanup_8").unwrap();
+        log.record("jq", &record).unwrap();
+        let contents = std::fs::read_to_string(log.path()).unwrap();
+        assert!(contents.contains("timed out after 1s, killed after 1."));
+    }
+}
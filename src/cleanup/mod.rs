@@ -1,14 +1,22 @@
 // Cleanup module - safe package removal
 pub mod executor;
 pub mod backup;
-
-use anyhow::Result;
+pub mod hooks;
+pub mod plan;
+pub mod log;
+pub mod runner;
 
 pub struct CleanupPlan {
     pub packages_to_remove: Vec<String>,
     pub size_to_recover: u64,
 }
 
+impl Default for CleanupPlan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CleanupPlan {
     pub fn new() -> Self {
         Self {
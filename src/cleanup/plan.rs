@@ -0,0 +1,223 @@
+// Cleanup plans saved by `clean --dry-run --save-plan` and applied later with `clean --plan`
+use crate::analysis::recommendations::{CleanupAction, Recommendation, RecommendationSeverity};
+use crate::scanner::{Package, PackageSource};
+use crate::error::{Context, MacsweepError, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Bumped whenever the plan format changes incompatibly
+pub const PLAN_SCHEMA_VERSION: u32 = 1;
+
+/// A reviewed list of removals, applied exactly as written
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedPlan {
+    pub schema_version: u32,
+    /// Version of macsweep that wrote the plan
+    pub macsweep_version: String,
+    pub created_at: DateTime<Utc>,
+    pub packages: Vec<PlanEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub package: String,
+    pub source: PackageSource,
+    pub version: Option<String>,
+    pub size_bytes: u64,
+    pub reason: String,
+    pub severity: RecommendationSeverity,
+    /// Absent from plans written before stale-version cleanups existed
+    #[serde(default)]
+    pub action: CleanupAction,
+}
+
+/// How an installed package differs from its plan entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// No longer installed
+    Missing,
+    /// Upgraded or downgraded since the plan was written
+    VersionChanged { planned: Option<String>, installed: Option<String> },
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::Missing => write!(f, "no longer installed"),
+            Drift::VersionChanged { planned, installed } => write!(
+                f,
+                "version changed from {} to {}",
+                planned.as_deref().unwrap_or("unknown"),
+                installed.as_deref().unwrap_or("unknown")
+            ),
+        }
+    }
+}
+
+impl SavedPlan {
+    /// A plan for `recommendations`; those without a stored package are left out, since
+    /// they can't be checked when the plan is applied
+    pub fn new(recommendations: &[Recommendation], packages: &[Package]) -> Self {
+        let entries = recommendations.iter()
+            .filter_map(|r| {
+                let package = r.find_package(packages)?;
+                Some(PlanEntry {
+                    package: r.package.clone(),
+                    source: package.source.clone(),
+                    version: package.version.clone(),
+                    size_bytes: r.size_recoverable,
+                    reason: r.reason.clone(),
+                    severity: r.severity,
+                    action: r.action,
+                })
+            })
+            .collect();
+
+        Self {
+            schema_version: PLAN_SCHEMA_VERSION,
+            macsweep_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: Utc::now(),
+            packages: entries,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Cannot write plan {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read plan {}", path.display()))?;
+        let plan: SavedPlan = serde_json::from_str(&contents)
+            .with_context(|| format!("Invalid plan {}", path.display()))?;
+
+        if plan.schema_version > PLAN_SCHEMA_VERSION {
+            return Err(MacsweepError::Other(format!(
+                "Plan {} was written by macsweep {} (plan format {}); this version only reads format {}",
+                path.display(),
+                plan.macsweep_version,
+                plan.schema_version,
+                PLAN_SCHEMA_VERSION
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Compare each entry with what is installed now
+    pub fn check<'a>(&'a self, packages: &[Package]) -> Vec<(&'a PlanEntry, Option<Drift>)> {
+        self.packages.iter()
+            .map(|entry| {
+                let installed = packages.iter().find(|p| p.name == entry.package && p.source == entry.source);
+                let drift = match installed {
+                    None => Some(Drift::Missing),
+                    Some(p) if p.version != entry.version => Some(Drift::VersionChanged {
+                        planned: entry.version.clone(),
+                        installed: p.version.clone(),
+                    }),
+                    Some(_) => None,
+                };
+                (entry, drift)
+            })
+            .collect()
+    }
+}
+
+impl From<&PlanEntry> for Recommendation {
+    fn from(entry: &PlanEntry) -> Self {
+        Recommendation {
+            package: entry.package.clone(),
+            source: Some(entry.source.clone()),
+            reason: entry.reason.clone(),
+            severity: entry.severity,
+            size_recoverable: entry.size_bytes,
+            action: entry.action,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, source: PackageSource, version: &str) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.version = Some(version.to_string());
+        package
+    }
+
+    #[test]
+    fn test_saved_plan_detects_drift() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("plan.json");
+        let recommendation = |name: &str| Recommendation {
+            package: name.to_string(),
+            source: None,
+            reason: "Not used in 200 days".to_string(),
+            severity: RecommendationSeverity::Review,
+            size_recoverable: 1024,
+            action: CleanupAction::Remove,
+        };
+
+        let packages = vec![
+            package("jq", PackageSource::Homebrew, "1.7.1"),
+            package("node", PackageSource::Homebrew, "18.19.0"),
+            package("typescript", PackageSource::Npm, "5.3.3"),
+        ];
+        let plan = SavedPlan::new(
+            &[recommendation("jq"), recommendation("node"), recommendation("typescript"), recommendation("not-in-db")],
+            &packages,
+        );
+        plan.save(&path).unwrap();
+
+        let plan = SavedPlan::load(&path).unwrap();
+        assert_eq!(plan.schema_version, PLAN_SCHEMA_VERSION);
+        assert_eq!(plan.packages.len(), 3);
+        assert_eq!(plan.packages[1].version.as_deref(), Some("18.19.0"));
+
+        // node was upgraded and typescript removed since
+        let now = vec![package("jq", PackageSource::Homebrew, "1.7.1"), package("node", PackageSource::Homebrew, "22.1.0")];
+        let drift: Vec<_> = plan.check(&now).into_iter().map(|(entry, drift)| (entry.package.as_str(), drift)).collect();
+        assert_eq!(drift[0], ("jq", None));
+        assert_eq!(drift[1].1.as_ref().unwrap().to_string(), "version changed from 18.19.0 to 22.1.0");
+        assert_eq!(drift[2], ("typescript", Some(Drift::Missing)));
+
+        // Entries of plans written before `brew cleanup` entries existed are removals
+        let entry: PlanEntry = serde_json::from_str(
+            r#"{"package": "jq", "source": "homebrew", "version": "1.7.1", "size_bytes": 1024, "reason": "", "severity": "Review"}"#
+        ).unwrap();
+        assert_eq!(entry.action, CleanupAction::Remove);
+    }
+
+    #[test]
+    fn test_plan_entries_keep_their_source() {
+        let packages = vec![package("docker", PackageSource::Homebrew, "27.0.3"), package("docker", PackageSource::HomebrewCask, "4.31.0")];
+        let cask = Recommendation {
+            package: "docker".to_string(),
+            source: Some(PackageSource::HomebrewCask),
+            reason: "Not used in 200 days".to_string(),
+            severity: RecommendationSeverity::Review,
+            size_recoverable: 1024,
+            action: CleanupAction::Remove,
+        };
+
+        let plan = SavedPlan::new(&[cask], &packages);
+        assert_eq!(plan.packages[0].source, PackageSource::HomebrewCask);
+        assert_eq!(plan.packages[0].version.as_deref(), Some("4.31.0"));
+        let applied = Recommendation::from(&plan.packages[0]);
+        assert_eq!(applied.find_package(&packages).map(|p| &p.source), Some(&PackageSource::HomebrewCask));
+    }
+
+    #[test]
+    fn test_newer_plan_format_is_refused() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("plan.json");
+        let mut plan = SavedPlan::new(&[], &[]);
+        plan.schema_version = PLAN_SCHEMA_VERSION + 1;
+        plan.save(&path).unwrap();
+
+        let err = SavedPlan::load(&path).unwrap_err();
+        assert!(err.to_string().contains("this version only reads format"));
+    }
+}
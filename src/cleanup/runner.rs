@@ -0,0 +1,646 @@
+// Carry out a cleanup plan: back up, remove, record and autoremove, reporting what happens
+// through `CleanupEvent`s and the outcome as a `CleanupReport` rather than printing
+use crate::analysis::leftovers::Leftover;
+use crate::analysis::recommendations::{CleanupAction, Recommendation};
+use crate::cleanup::backup;
+use crate::cleanup::executor::{self, RemovalEvent, RemovalOptions, RemovalOutcome};
+use crate::cleanup::log::CleanupLog;
+use crate::progress::{ItemProgress, Phase, ProgressReporter};
+use crate::scanner::{Package, PackageSource};
+use crate::storage::database::{self, CleanupItem, IncompleteCleanup};
+use crate::utils::process::CommandKind;
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+/// What to clean up
+pub struct CleanupRequest<'a> {
+    /// The actions to carry out, dependents before their dependencies
+    pub recommendations: &'a [Recommendation],
+    /// Every recorded package, which the recommendations name
+    pub packages: &'a [Package],
+    /// Support files to move to the Trash along with each removed package, by package name and
+    /// source
+    pub support_files: &'a HashMap<(&'a str, &'a PackageSource), Vec<Leftover>>,
+    pub options: &'a RemovalOptions,
+    /// Remove the dependencies Homebrew formulae removed leave unneeded, as `brew autoremove`
+    /// would, without those that were unneeded before
+    pub autoremove: bool,
+    /// The interrupted cleanup this finishes, whose backup and record it carries on
+    pub resuming: Option<&'a IncompleteCleanup>,
+}
+
+/// How one planned action turned out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemOutcome {
+    Removed,
+    /// `brew cleanup` removed the stale versions
+    Cleaned,
+    /// The uninstaller reported success, but this is why it didn't look like it
+    Unverified(String),
+    /// The uninstaller ran but refused; holds its error output
+    Failed(String),
+    /// The uninstaller could not be run at all
+    Error(String),
+}
+
+impl ItemOutcome {
+    /// Status recorded for the item in the cleanup history
+    pub fn status(&self) -> &'static str {
+        match self {
+            ItemOutcome::Removed => "removed",
+            ItemOutcome::Cleaned => "cleaned",
+            ItemOutcome::Unverified(_) => "unverified",
+            ItemOutcome::Failed(_) | ItemOutcome::Error(_) => "failed",
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            ItemOutcome::Removed | ItemOutcome::Cleaned => None,
+            ItemOutcome::Unverified(e) | ItemOutcome::Failed(e) | ItemOutcome::Error(e) => Some(e),
+        }
+    }
+}
+
+/// Something that went wrong without stopping the cleanup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum CleanupWarning {
+    /// No backup could be written, so the cleanup can't be undone
+    NoBackup(String),
+    /// The cleanup history couldn't be updated
+    Database(String),
+    Other(String),
+}
+
+impl fmt::Display for CleanupWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CleanupWarning::NoBackup(message) | CleanupWarning::Database(message) | CleanupWarning::Other(message) => {
+                f.write_str(message)
+            }
+        }
+    }
+}
+
+/// What a cleanup did
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CleanupReport {
+    /// Actions started; an interrupted cleanup leaves the rest unattempted
+    pub attempted: usize,
+    /// Packages removed, including the dependencies they left unneeded (`--autoremove`)
+    pub removed: usize,
+    /// Formulae whose stale versions were cleaned up
+    pub cleaned: usize,
+    pub failed: usize,
+    /// Reported removed by the uninstaller but still on disk
+    pub unverified: usize,
+    /// Actions never started because the cleanup was interrupted
+    pub not_attempted: usize,
+    pub support_files_trashed: usize,
+    pub bytes_recovered: u64,
+    /// Dependencies removed because the cleanup left them unneeded (or in a dry run, that
+    /// would be)
+    pub autoremoved: Vec<String>,
+    pub backup_manifest_path: Option<String>,
+    /// Full output of every uninstaller that ran
+    pub log_path: Option<PathBuf>,
+    /// Something the log recorded failed besides the removals counted above
+    pub log_has_failures: bool,
+    pub warnings: Vec<CleanupWarning>,
+    /// How each attempted action turned out, in the order they finished, followed by the
+    /// dependencies removed because the cleanup left them unneeded
+    pub items: Vec<CleanupItem>,
+}
+
+impl CleanupReport {
+    pub fn interrupted(&self) -> bool {
+        self.not_attempted > 0
+    }
+
+    /// Whether anything failed, couldn't be verified or was left undone
+    pub fn is_partial(&self) -> bool {
+        self.failed > 0 || self.unverified > 0 || self.interrupted()
+    }
+}
+
+/// What `execute_cleanup` is doing, delivered on the calling thread; how far the removals
+/// have got goes to its `ProgressReporter` instead
+#[derive(Debug)]
+pub enum CleanupEvent<'a> {
+    CreatingBackup,
+    BackupCreated { manifest_path: &'a str },
+    /// Removals are about to start
+    Started,
+    RemovalFinished { package: &'a Package, action: CleanupAction, outcome: &'a ItemOutcome },
+    /// Dependencies the cleanup left unneeded are about to be removed
+    AutoremoveStarted,
+    /// Dependencies removed because the cleanup left them unneeded, or in a dry run, predicted
+    /// to be
+    Autoremoved { packages: &'a [Package] },
+    Warning(&'a CleanupWarning),
+}
+
+/// The report being built, and where its progress goes
+struct Reporter<F> {
+    report: CleanupReport,
+    on_event: F,
+}
+
+impl<F: FnMut(CleanupEvent)> Reporter<F> {
+    fn emit(&mut self, event: CleanupEvent) {
+        (self.on_event)(event);
+    }
+
+    fn warn(&mut self, warning: CleanupWarning) {
+        (self.on_event)(CleanupEvent::Warning(&warning));
+        self.report.warnings.push(warning);
+    }
+}
+
+fn planned_item(package: &Package, size_bytes: u64, action: CleanupAction) -> CleanupItem {
+    CleanupItem {
+        package: package.name.clone(),
+        source: package.source.to_string(),
+        size_bytes: Some(size_bytes),
+        status: "pending".to_string(),
+        error_message: None,
+        exit_code: None,
+        duration_ms: None,
+        action: action.as_str().to_string(),
+    }
+}
+
+/// Carry out `request`, recording it in `conn` so an interrupted cleanup can be resumed and
+/// undone. No new removal starts once `stop` returns true. Problems along the way (a backup
+/// that couldn't be written, a failed removal) end up in the report rather than as errors.
+pub fn execute_cleanup(
+    conn: &Connection,
+    request: &CleanupRequest,
+    stop: &(dyn Fn() -> bool + Sync),
+    progress: &dyn ProgressReporter,
+    on_event: impl FnMut(CleanupEvent),
+) -> CleanupReport {
+    let CleanupRequest { recommendations, packages, support_files, options, autoremove, resuming } = *request;
+    let dry_run = options.dry_run;
+    let mut reporter = Reporter { report: CleanupReport::default(), on_event };
+
+    // Stale versions `brew cleanup` removes can't be reinstalled, so only removals are backed up
+    let packages_to_remove: Vec<_> = recommendations.iter()
+        .filter(|r| r.action == CleanupAction::Remove)
+        .filter_map(|r| r.find_package(packages))
+        .cloned()
+        .collect();
+
+    // Create backup before cleanup; a resumed cleanup keeps the backup it started with
+    let backup_manifest_path = if dry_run {
+        None
+    } else if let Some(cleanup) = resuming.filter(|c| !c.backup_manifest_path.is_empty()) {
+        // The packages were taken out of the backup when the cleanup was interrupted
+        if let Err(e) = backup::add_to_backup(&cleanup.backup_manifest_path, &packages_to_remove) {
+            reporter.warn(CleanupWarning::Other(format!("Failed to add the remaining packages to the backup: {}", e)));
+        }
+        Some(cleanup.backup_manifest_path.clone())
+    } else {
+        reporter.emit(CleanupEvent::CreatingBackup);
+        match backup::create_backup(&packages_to_remove) {
+            Ok(path) => {
+                reporter.emit(CleanupEvent::BackupCreated { manifest_path: &path });
+                Some(path)
+            }
+            Err(e) => {
+                reporter.warn(CleanupWarning::NoBackup(format!("Failed to create backup: {}", e)));
+                None
+            }
+        }
+    };
+
+    // Record the plan before removing anything, so an interrupted cleanup can be resumed
+    let cleanup_id = if dry_run {
+        None
+    } else {
+        let recorded = match resuming {
+            Some(cleanup) => Ok(cleanup.id),
+            None => {
+                let planned: Vec<_> = recommendations.iter()
+                    .filter_map(|r| r.find_package(packages).map(|p| planned_item(p, r.size_recoverable, r.action)))
+                    .collect();
+                database::start_cleanup(conn, backup_manifest_path.as_deref().unwrap_or(""), &planned)
+            }
+        };
+        match recorded {
+            Ok(id) => Some(id),
+            Err(e) => {
+                reporter.warn(CleanupWarning::Database(format!("Failed to record cleanup in database: {}", e)));
+                None
+            }
+        }
+    };
+    let record_item = |reporter: &mut Reporter<_>, item: &CleanupItem| {
+        reporter.report.items.push(item.clone());
+        if let Some(id) = cleanup_id {
+            if let Err(e) = database::update_cleanup_item(conn, id, item) {
+                reporter.warn(CleanupWarning::Database(format!("Failed to record removal of {} in database: {}", item.package, e)));
+            }
+        }
+    };
+
+    // Full output of every uninstaller, which the outcomes only summarize
+    let mut log = if dry_run {
+        None
+    } else {
+        match CleanupLog::for_backup(backup_manifest_path.as_deref()) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                reporter.warn(CleanupWarning::Other(crate::error::chain(&e)));
+                None
+            }
+        }
+    };
+
+    let to_remove: Vec<_> = recommendations.iter()
+        .filter_map(|r| r.find_package(packages).map(|p| (r, p)))
+        .collect();
+    let targets: Vec<_> = to_remove.iter().map(|(r, p)| (*p, r.action)).collect();
+
+    // What `brew autoremove` would remove before the cleanup isn't the cleanup's doing, so
+    // only formulae orphaned since then are removed afterwards
+    let removes_formulae = targets.iter().any(|(p, action)| p.source == PackageSource::Homebrew && *action == CleanupAction::Remove);
+    let orphans_before = if autoremove && !dry_run && removes_formulae {
+        match crate::analysis::orphans::get_orphaned_brew_packages(options.timeouts.get(CommandKind::Scanner)) {
+            Ok(orphans) => Some(orphans),
+            Err(e) => {
+                reporter.warn(CleanupWarning::Other(format!("Skipping --autoremove, unneeded dependencies couldn't be listed: {:#}", e)));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    reporter.emit(CleanupEvent::Started);
+    progress.on_phase_start(Phase::Cleanup, to_remove.len() as u64);
+
+    // Outcome of each removal by position in the plan, so the results don't depend on
+    // which worker finished first
+    let mut statuses: Vec<Option<&str>> = vec![None; to_remove.len()];
+
+    executor::remove_packages(&targets, options, stop, |event| {
+        let (worker, index, removal, remaining) = match event {
+            RemovalEvent::Started { worker, index } => {
+                progress.on_item(Phase::Cleanup, ItemProgress::Started { name: &to_remove[index].1.name, worker });
+                return;
+            }
+            RemovalEvent::Finished { worker, index, removal, remaining } => (worker, index, removal, remaining),
+        };
+        let (rec, package) = to_remove[index];
+        let (removal, command) = match removal {
+            Ok(removal) => (Ok(removal.outcome), removal.command),
+            Err(e) => (Err(e), None),
+        };
+
+        let outcome = match removal {
+            Ok(outcome @ (RemovalOutcome::Removed | RemovalOutcome::Trashed(_))) => {
+                if let (RemovalOutcome::Trashed(Some(trashed)), Some(manifest_path)) = (&outcome, &backup_manifest_path) {
+                    if let Err(e) = backup::record_trashed(manifest_path, package, trashed) {
+                        reporter.warn(CleanupWarning::Other(format!("Failed to record where {} was trashed: {}", package.name, e)));
+                    }
+                }
+
+                // Trust what is on disk rather than the uninstaller's exit code
+                match remaining {
+                    Some(remaining) => {
+                        reporter.report.unverified += 1;
+                        ItemOutcome::Unverified(format!("uninstaller reported success but {} is still there", remaining.display()))
+                    }
+                    None if rec.action == CleanupAction::BrewCleanup => {
+                        reporter.report.cleaned += 1;
+                        reporter.report.bytes_recovered += rec.size_recoverable;
+                        ItemOutcome::Cleaned
+                    }
+                    None => {
+                        reporter.report.removed += 1;
+                        reporter.report.bytes_recovered += rec.size_recoverable;
+                        if let Some(files) = support_files.get(&(package.name.as_str(), &package.source)) {
+                            let (trashed, freed) = trash_support_files(package, files, dry_run, backup_manifest_path.as_deref(), &mut reporter);
+                            reporter.report.support_files_trashed += trashed;
+                            reporter.report.bytes_recovered += freed;
+                        }
+                        ItemOutcome::Removed
+                    }
+                }
+            }
+            Ok(RemovalOutcome::Failed(stderr)) => {
+                reporter.report.failed += 1;
+                ItemOutcome::Failed(stderr)
+            }
+            Err(e) => {
+                reporter.report.failed += 1;
+                ItemOutcome::Error(e.to_string())
+            }
+        };
+        reporter.emit(CleanupEvent::RemovalFinished { package, action: rec.action, outcome: &outcome });
+
+        if let Some(ref mut log) = log {
+            let logged = match (&command, outcome.error()) {
+                (Some(command), _) => log.record(&package.name, command),
+                (None, Some(error)) => log.note(&package.name, error),
+                (None, None) => Ok(()),
+            };
+            if let Err(e) = logged {
+                reporter.warn(CleanupWarning::Other(crate::error::chain(&e)));
+            }
+        }
+
+        record_item(&mut reporter, &CleanupItem {
+            status: outcome.status().to_string(),
+            error_message: outcome.error().map(str::to_string),
+            exit_code: command.as_ref().and_then(|c| c.exit_code),
+            duration_ms: command.as_ref().map(|c| c.duration.as_millis() as u64),
+            ..planned_item(package, rec.size_recoverable, rec.action)
+        });
+        statuses[index] = Some(outcome.status());
+        progress.on_item(Phase::Cleanup, ItemProgress::Finished { name: &package.name, worker });
+    });
+
+    progress.on_phase_done(Phase::Cleanup);
+
+    let removed_packages: Vec<_> = targets.iter().zip(&statuses)
+        .filter(|(_, status)| **status == Some("removed"))
+        .map(|((p, _), _)| *p)
+        .collect();
+    let remaining: Vec<_> = targets.iter().zip(&statuses)
+        .filter(|(_, status)| status.is_none())
+        .map(|((p, _), _)| *p)
+        .collect();
+    reporter.report.attempted = targets.len() - remaining.len();
+    reporter.report.not_attempted = remaining.len();
+    let interrupted = !remaining.is_empty();
+
+    // Dependencies left without a user by the removals
+    if autoremove && !interrupted && removed_packages.iter().any(|p| p.source == PackageSource::Homebrew) {
+        let cascade: Vec<Package> = if dry_run {
+            crate::analysis::orphans::predict_autoremove(packages, &removed_packages).into_iter().cloned().collect()
+        } else if let Some(ref orphans_before) = orphans_before {
+            reporter.emit(CleanupEvent::AutoremoveStarted);
+            remove_newly_orphaned(orphans_before, packages, options, &mut log, &mut reporter)
+        } else {
+            Vec::new()
+        };
+
+        if !cascade.is_empty() {
+            reporter.emit(CleanupEvent::Autoremoved { packages: &cascade });
+
+            reporter.report.removed += cascade.len();
+            reporter.report.bytes_recovered += cascade.iter().filter_map(|p| p.size_bytes).sum::<u64>();
+            reporter.report.autoremoved = cascade.iter().map(|p| p.name.clone()).collect();
+
+            if !dry_run {
+                if let Some(ref manifest_path) = backup_manifest_path {
+                    if let Err(e) = backup::add_to_backup(manifest_path, &cascade) {
+                        reporter.warn(CleanupWarning::Other(format!("Failed to add removed dependencies to the backup: {}", e)));
+                    }
+                }
+                for package in &cascade {
+                    record_item(&mut reporter, &CleanupItem {
+                        size_bytes: package.size_bytes,
+                        status: "removed".to_string(),
+                        ..planned_item(package, 0, CleanupAction::Remove)
+                    });
+                }
+            }
+        }
+    }
+
+    if interrupted {
+        // Leave the packages that were never attempted out of the backup until the cleanup resumes
+        if let Some(ref manifest_path) = backup_manifest_path {
+            let sources: Vec<_> = remaining.iter().map(|p| p.source.to_string()).collect();
+            let pairs: Vec<_> = remaining.iter().zip(&sources).map(|(p, s)| (p.name.as_str(), s.as_str())).collect();
+            if let Err(e) = backup::remove_from_backup(manifest_path, &pairs) {
+                reporter.warn(CleanupWarning::Other(format!("Failed to update the backup: {}", e)));
+            }
+        }
+    }
+
+    if let Some(id) = cleanup_id {
+        if let Err(e) = database::finish_cleanup(conn, id, if interrupted { "interrupted" } else { "complete" }) {
+            reporter.warn(CleanupWarning::Database(format!("Failed to record cleanup in database: {}", e)));
+        }
+    }
+
+    reporter.report.backup_manifest_path = backup_manifest_path;
+    reporter.report.log_path = log.map(|log| log.path().to_path_buf());
+    reporter.report
+}
+
+/// Uninstall the formulae `brew autoremove` would remove now but wouldn't have before the
+/// cleanup, `orphans_before`; those it would have are left alone, as the cleanup didn't
+/// orphan them
+fn remove_newly_orphaned<F: FnMut(CleanupEvent)>(
+    orphans_before: &[String],
+    packages: &[Package],
+    options: &RemovalOptions,
+    log: &mut Option<CleanupLog>,
+    reporter: &mut Reporter<F>,
+) -> Vec<Package> {
+    let orphaned: Vec<String> = match crate::analysis::orphans::get_orphaned_brew_packages(options.timeouts.get(CommandKind::Scanner)) {
+        Ok(orphans) => orphans.into_iter().filter(|name| !orphans_before.contains(name)).collect(),
+        Err(e) => {
+            reporter.warn(CleanupWarning::Other(format!("Unneeded dependencies couldn't be listed: {:#}", e)));
+            return Vec::new();
+        }
+    };
+    if orphaned.is_empty() {
+        return Vec::new();
+    }
+
+    let record = match executor::remove_formulae(&orphaned, options) {
+        Ok(record) => record,
+        Err(e) => {
+            reporter.warn(CleanupWarning::Other(e.to_string()));
+            return Vec::new();
+        }
+    };
+    if let Some(log) = log {
+        if let Err(e) = log.record("brew autoremove", &record) {
+            reporter.warn(CleanupWarning::Other(crate::error::chain(&e)));
+        }
+    }
+    if !record.success() {
+        reporter.warn(CleanupWarning::Other(format!("Removing unneeded dependencies failed: {}", record.stderr_summary())));
+        reporter.report.log_has_failures = true;
+        return Vec::new();
+    }
+
+    orphaned.into_iter()
+        .map(|name| {
+            packages.iter()
+                .find(|p| p.name == name && p.source == PackageSource::Homebrew)
+                .cloned()
+                .unwrap_or_else(|| Package::new(name, PackageSource::Homebrew))
+        })
+        .collect()
+}
+
+/// Move a removed app's support files to the Trash and record where they went in the backup;
+/// returns how many were moved (or would be, in a dry run) and the bytes they held
+fn trash_support_files<F: FnMut(CleanupEvent)>(
+    package: &Package,
+    files: &[Leftover],
+    dry_run: bool,
+    manifest_path: Option<&str>,
+    reporter: &mut Reporter<F>,
+) -> (usize, u64) {
+    if dry_run {
+        return (files.len(), files.iter().map(|f| f.size_bytes).sum());
+    }
+
+    let mut trashed = Vec::new();
+    let mut freed = 0;
+    for file in files {
+        // The uninstaller (e.g. a cask's zap stanza) may have removed it already
+        if file.path.symlink_metadata().is_err() {
+            continue;
+        }
+        match executor::move_to_trash(&file.path) {
+            Ok(Some(path)) => {
+                freed += file.size_bytes;
+                trashed.push(backup::TrashedFile {
+                    original: file.path.to_string_lossy().to_string(),
+                    trashed: path.to_string_lossy().to_string(),
+                });
+            }
+            Ok(None) => {
+                freed += file.size_bytes;
+                reporter.warn(CleanupWarning::Other(format!("Moved {} to the Trash, but undo can't put it back: the Trash didn't say where it went", file.path.display())));
+            }
+            Err(e) => reporter.warn(CleanupWarning::Other(format!("Failed to move {} to the Trash: {}", file.path.display(), crate::error::chain(&e)))),
+        }
+    }
+
+    if let (Some(manifest_path), false) = (manifest_path, trashed.is_empty()) {
+        if let Err(e) = backup::record_support_files(manifest_path, package, &trashed) {
+            reporter.warn(CleanupWarning::Other(format!("Failed to record {}'s support files in the backup: {}", package.name, e)));
+        }
+    }
+    (trashed.len(), freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::recommendations::RecommendationSeverity;
+    use crate::progress::NoProgress;
+    use crate::storage::Database;
+    use tempfile::NamedTempFile;
+
+    fn recommendation(package: &Package) -> Recommendation {
+        Recommendation {
+            package: package.name.clone(),
+            source: Some(package.source.clone()),
+            reason: "Not used in 200 days".to_string(),
+            severity: RecommendationSeverity::Review,
+            size_recoverable: package.size_bytes.unwrap_or(0),
+            action: CleanupAction::Remove,
+        }
+    }
+
+    #[test]
+    fn test_dry_run_reports_without_recording() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut packages = Vec::new();
+        for (name, size) in [("left-pad", 4096), ("is-odd", 1024)] {
+            let mut package = Package::new(name.to_string(), PackageSource::Npm);
+            package.size_bytes = Some(size);
+            packages.push(package);
+        }
+        let recommendations: Vec<_> = packages.iter().map(recommendation).collect();
+        let leftover = Leftover {
+            name: "left-pad".to_string(),
+            path: PathBuf::from("/nonexistent/left-pad"),
+            location: "Caches".to_string(),
+            bundle_id: None,
+            size_bytes: 512,
+        };
+        let support_files = HashMap::from([(("left-pad", &PackageSource::Npm), vec![leftover])]);
+        let options = RemovalOptions { dry_run: true, ..Default::default() };
+        let request = CleanupRequest {
+            recommendations: &recommendations,
+            packages: &packages,
+            support_files: &support_files,
+            options: &options,
+            autoremove: true,
+            resuming: None,
+        };
+
+        let mut events = Vec::new();
+        let report = execute_cleanup(db.conn(), &request, &|| false, &NoProgress, |event| {
+            events.push(match event {
+                CleanupEvent::Started => "started".to_string(),
+                CleanupEvent::RemovalFinished { package, outcome, .. } => format!("{} {}", package.name, outcome.status()),
+                _ => return,
+            })
+        });
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0], "started");
+        assert!(events.contains(&"left-pad removed".to_string()) && events.contains(&"is-odd removed".to_string()));
+        assert_eq!((report.attempted, report.removed, report.failed, report.not_attempted), (2, 2, 0, 0));
+        assert_eq!(report.support_files_trashed, 1);
+        assert_eq!(report.bytes_recovered, 4096 + 1024 + 512);
+        assert!(report.backup_manifest_path.is_none() && report.log_path.is_none() && report.warnings.is_empty());
+        assert!(!report.is_partial());
+        let mut items: Vec<_> = report.items.iter().map(|i| (i.package.as_str(), i.status.as_str())).collect();
+        items.sort();
+        assert_eq!(items, [("is-odd", "removed"), ("left-pad", "removed")]);
+        // A dry run leaves no cleanup to resume
+        assert!(database::get_incomplete_cleanup(db.conn()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_removals_are_resolved_by_source() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        // Formulae sort before casks, so a lookup by name alone finds the formula
+        let packages = vec![
+            Package::new("docker".to_string(), PackageSource::Homebrew),
+            Package::new("docker".to_string(), PackageSource::HomebrewCask),
+        ];
+        let recommendations = vec![recommendation(&packages[1])];
+        // Only the formula has support files, which the cask's removal leaves alone
+        let leftover = Leftover {
+            name: "docker".to_string(),
+            path: PathBuf::from("/nonexistent/docker"),
+            location: "Caches".to_string(),
+            bundle_id: None,
+            size_bytes: 512,
+        };
+        let support_files = HashMap::from([(("docker", &packages[0].source), vec![leftover])]);
+        let options = RemovalOptions { dry_run: true, ..Default::default() };
+        let request = CleanupRequest {
+            recommendations: &recommendations,
+            packages: &packages,
+            support_files: &support_files,
+            options: &options,
+            autoremove: false,
+            resuming: None,
+        };
+
+        let mut removed = Vec::new();
+        let report = execute_cleanup(db.conn(), &request, &|| false, &NoProgress, |event| {
+            if let CleanupEvent::RemovalFinished { package, .. } = event {
+                removed.push(package.source.clone());
+            }
+        });
+        assert_eq!(removed, [PackageSource::HomebrewCask]);
+        assert_eq!(report.support_files_trashed, 0);
+    }
+}
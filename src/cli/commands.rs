@@ -1,152 +1,235 @@
 // Command implementations
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::path::PathBuf;
-use std::time::Instant;
-use super::{OutputFormat, SortField};
-use crate::scanner::{Scanner, homebrew::HomebrewScanner, npm::NpmScanner, pip::PipScanner, cargo::CargoScanner, applications::ApplicationsScanner};
+use super::{output, Outcome, OutputFormat, SortField};
+use crate::api;
+use crate::error;
+use crate::scanner::PackageSource;
+use crate::scanner::registry::ScannerRegistry;
 use crate::storage::{Database, database};
+use crate::utils::date::{days_since, format_date, format_days_ago, DateDisplay};
+use crate::utils::process::{CommandKind, CommandTimeouts};
+use crate::analysis::recommendations::{CleanupAction, PackageStatus, RecommendationConfig};
 use colored::Colorize;
 
-pub fn scan(source: Option<String>, quick: bool) -> Result<()> {
-    let start = Instant::now();
-    println!("🔍 Scanning packages...");
-
-    let mut all_packages = Vec::new();
-
-    // Scan Homebrew
-    if source.is_none() || source.as_deref() == Some("homebrew") || source.as_deref() == Some("brew") {
-        let scanner = HomebrewScanner::new();
-        if scanner.is_available() {
-            print!("  {} Homebrew... ", "✓".green());
-            match scanner.scan() {
-                Ok(packages) => {
-                    println!("{} packages", packages.len().to_string().cyan());
-                    all_packages.extend(packages);
-                }
-                Err(e) => {
-                    println!("{}", format!("Error: {}", e).red());
-                }
-            }
-        } else {
-            println!("  {} Homebrew (not installed)", "✗".yellow());
+/// Structured result of `macsweep scan`, printed for machine-readable formats
+#[derive(serde::Serialize)]
+struct ScanSummary<'a> {
+    sources: &'a [crate::scan::SourceScan],
+    source_counts: &'a std::collections::BTreeMap<String, usize>,
+    total_packages: usize,
+    total_size_bytes: u64,
+    duration_ms: u128,
+    scan_duration_ms: u128,
+    sizes_reused: usize,
+    sizes_measured: usize,
+    usage_duration_ms: Option<u128>,
+    /// Time the usage lookups took combined, divided by the wall-clock time they took in parallel
+    usage_speedup: Option<f64>,
+    saved: bool,
+    /// Previously recorded packages the scan no longer found
+    removed: usize,
+    warnings: &'a [String],
+}
+
+impl<'a> From<&'a crate::scan::ScanResult> for ScanSummary<'a> {
+    fn from(result: &'a crate::scan::ScanResult) -> Self {
+        Self {
+            sources: &result.sources,
+            source_counts: &result.per_source_counts,
+            total_packages: result.packages.len(),
+            total_size_bytes: result.total_size_bytes(),
+            duration_ms: result.duration.as_millis(),
+            scan_duration_ms: result.scan_duration.as_millis(),
+            sizes_reused: result.sizes_reused,
+            sizes_measured: result.sizes_measured,
+            usage_duration_ms: result.usage_duration.map(|d| d.as_millis()),
+            usage_speedup: result.usage_speedup,
+            saved: result.saved,
+            removed: result.removed,
+            warnings: &result.warnings,
         }
     }
+}
+
+/// Shows what a scan is doing on stderr, and the per-source breakdown on stdout for people
+struct ScanProgress {
+    human: bool,
+}
 
-    // Scan npm
-    if source.is_none() || source.as_deref() == Some("npm") {
-        let scanner = NpmScanner::new();
-        if scanner.is_available() {
-            print!("  {} npm (global)... ", "✓".green());
-            match scanner.scan() {
-                Ok(packages) => {
-                    println!("{} packages", packages.len().to_string().cyan());
-                    all_packages.extend(packages);
+impl ScanProgress {
+    fn show(&self, event: crate::scan::ScanEvent) {
+        use crate::scan::ScanEvent;
+
+        match event {
+            ScanEvent::SourceUnavailable { label } => status!("  {} {} (not installed)", "✗".yellow(), label),
+            ScanEvent::SourceStarted { label } => status_inline!("  {} {}... ", "✓".green(), label),
+            ScanEvent::SourceScanned { unit, count, .. } => status!("{} {}", count.to_string().cyan(), unit),
+            ScanEvent::SourceFailed { error, .. } => status!("{}", format!("Error: {}", error).red()),
+            ScanEvent::SourceSkipped { label, reason } => status!("  {} {} (skipped: {})", "-".dimmed(), label, reason),
+            ScanEvent::SourceTimedOut { error, .. } => {
+                status!("{}", format!("Warning: {}, keeping its packages from the last scan", error).yellow())
+            }
+            ScanEvent::SourcesScanned { duration, sizes_reused, sizes_measured } => {
+                if sizes_reused + sizes_measured > 0 {
+                    status!(
+                        "  Scanned in {:.2}s ({} sizes unchanged since the last scan, {} measured)",
+                        duration.as_secs_f64(),
+                        sizes_reused,
+                        sizes_measured
+                    );
+                } else {
+                    status!("  Scanned in {:.2}s", duration.as_secs_f64());
                 }
-                Err(e) => {
-                    println!("{}", format!("Error: {}", e).red());
+            }
+            ScanEvent::PackagesFound { packages } => {
+                if self.human {
+                    print_scan_breakdown(packages);
                 }
             }
-        } else {
-            println!("  {} npm (not installed)", "✗".yellow());
-        }
-    }
-
-    // Scan pip
-    if source.is_none() || source.as_deref() == Some("pip") || source.as_deref() == Some("python") {
-        let scanner = PipScanner::new();
-        if scanner.is_available() {
-            print!("  {} pip/pipx... ", "✓".green());
-            match scanner.scan() {
-                Ok(packages) => {
-                    println!("{} packages", packages.len().to_string().cyan());
-                    all_packages.extend(packages);
+            ScanEvent::UsageStarted => status!("\n🔎 Gathering usage information..."),
+            ScanEvent::UsageFinished { duration, speedup } => {
+                match speedup {
+                    Some(speedup) => status!(
+                        "  Usage tracking complete in {:.2}s ({:.1}x faster than one at a time)",
+                        duration.as_secs_f64(),
+                        speedup
+                    ),
+                    None => status!("  Usage tracking complete in {:.2}s", duration.as_secs_f64()),
                 }
-                Err(e) => {
-                    println!("{}", format!("Error: {}", e).red());
+            }
+            ScanEvent::Saving => status_inline!("\n💾 Saving to database... "),
+            ScanEvent::Saved { removed } => {
+                status!("{}", "done".green());
+                if removed > 0 {
+                    status!("   {} packages are no longer installed (see {})", removed, "macsweep list --include-removed".cyan());
                 }
             }
-        } else {
-            println!("  {} pip (not installed)", "✗".yellow());
+            ScanEvent::SaveFailed { error } => status!("{}", format!("Error: {}", error).red()),
         }
     }
+}
 
-    // Scan cargo
-    if source.is_none() || source.as_deref() == Some("cargo") || source.as_deref() == Some("rust") {
-        let scanner = CargoScanner::new();
-        if scanner.is_available() {
-            print!("  {} cargo... ", "✓".green());
-            match scanner.scan() {
-                Ok(packages) => {
-                    println!("{} packages", packages.len().to_string().cyan());
-                    all_packages.extend(packages);
-                }
-                Err(e) => {
-                    println!("{}", format!("Error: {}", e).red());
-                }
+/// `notify` posts a notification when the scan finds much more to clean up than the last one;
+/// packages tagged `keep_tag` don't count
+pub fn scan(
+    db: &mut Database,
+    options: &api::ScanOptions,
+    if_stale: Option<u32>,
+    notify: bool,
+    keep_tag: &str,
+    format: OutputFormat,
+) -> Result<Outcome> {
+    // A mistyped source fails here rather than scanning nothing
+    ScannerRegistry::default().select_all(&options.sources)?;
+
+    if let Some(max_age) = if_stale {
+        if let Some(days) = days_since_last_scan(db, Some(&crate::scan::scan_type(&options.sources)))? {
+            if days < max_age {
+                status!("Last scan was {}; skipping (--if-stale {})", format_days_ago(days).to_lowercase(), max_age);
+                return Ok(Outcome::NothingToDo);
             }
-        } else {
-            println!("  {} cargo (not installed)", "✗".yellow());
         }
     }
 
-    // Scan Applications
-    if source.is_none() || source.as_deref() == Some("applications") || source.as_deref() == Some("apps") {
-        let scanner = ApplicationsScanner::new();
-        if scanner.is_available() {
-            print!("  {} Applications... ", "✓".green());
-            match scanner.scan() {
-                Ok(packages) => {
-                    println!("{} apps", packages.len().to_string().cyan());
-                    all_packages.extend(packages);
-                }
-                Err(e) => {
-                    println!("{}", format!("Error: {}", e).red());
-                }
+    status!("🔍 Scanning packages...");
+
+    // Machine-readable formats get a single structured document on stdout
+    let progress = ScanProgress { human: !format.is_structured() };
+    let bars = output::TerminalProgress::default();
+    let result = api::scan_and_store_with(db, options, &bars, &|event| progress.show(event))?;
+    output::print_serialized(&ScanSummary::from(&result), format)?;
+    print_usage_failures(&result.usage_failures);
+    if result.saved {
+        // The scan is saved either way; only the comparison with the next one is lost
+        if notify && options.config.notifications.enabled {
+            if let Err(e) = notify_reclaimable(db, &options.config, keep_tag) {
+                status!("{}", format!("Warning: couldn't work out the reclaimable space: {:#}", e).yellow());
+            }
+        }
+        if let Some(ref path) = options.config.metrics.textfile {
+            let config = &options.config;
+            if let Err(e) = write_metrics(db, path, keep_tag, &config.protected_packages, &config.recommendations, &config.timeouts) {
+                status!("{}", format!("Warning: couldn't update the metrics file: {:#}", e).yellow());
             }
         }
     }
 
-    let duration = start.elapsed();
-
-    println!("\n📊 Scan complete: {} packages found", all_packages.len().to_string().cyan().bold());
+    if result.packages.is_empty() {
+        Ok(Outcome::NothingToDo)
+    } else if result.has_failures() {
+        Ok(Outcome::PartialFailure)
+    } else {
+        Ok(Outcome::Success)
+    }
+}
 
-    // Display some statistics
-    let formulae_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Homebrew))
-        .count();
-    let casks_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::HomebrewCask))
-        .count();
-    let npm_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Npm))
-        .count();
-    let pip_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Pip) || matches!(p.source, crate::scanner::PackageSource::Pipx))
-        .count();
-    let cargo_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Cargo))
-        .count();
-    let apps_count = all_packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Applications))
-        .count();
+/// Record what the recommendations after the latest scan would free, and post a notification
+/// when that is at least the `[notifications]` threshold and more than at the last scan that
+/// recorded it. A notification that can't be posted is only a warning.
+fn notify_reclaimable(db: &Database, config: &crate::config::Config, keep_tag: &str) -> Result<()> {
+    let Some(scan) = database::get_last_scan(db.conn(), None)? else {
+        return Ok(());
+    };
+    let packages = api::load_packages(db)?;
+    let kept = api::kept_packages(db, keep_tag)?;
+    let recommendations = api::recommendations_excluding(&packages, &config.recommendations, &config.protected_packages, &kept, &config.timeouts)?;
+    let reclaimable = database::Reclaimable {
+        bytes: recommendations.iter().map(|r| r.size_recoverable).sum::<u64>() as i64,
+        packages: recommendations.len() as i64,
+    };
+    let previous = database::get_previous_reclaimable(db.conn(), scan.id)?;
+    database::set_scan_reclaimable(db.conn(), scan.id, reclaimable)?;
 
-    if formulae_count > 0 {
-        println!("   └── {} Homebrew formulae", formulae_count);
+    let growth = reclaimable.bytes - previous.map_or(0, |p| p.bytes);
+    if growth <= 0 || (reclaimable.bytes as u64) < config.notifications.threshold {
+        return Ok(());
     }
-    if casks_count > 0 {
-        println!("   └── {} Homebrew casks", casks_count);
+    let noun = if reclaimable.packages == 1 { "package" } else { "packages" };
+    let mut message = format!(
+        "{} reclaimable across {} {}",
+        crate::utils::size::format_size(reclaimable.bytes as u64),
+        reclaimable.packages,
+        noun
+    );
+    if previous.is_some() {
+        message.push_str(&format!(" (+{} since the last scan)", crate::utils::size::format_size(growth as u64)));
     }
-    if npm_count > 0 {
-        println!("   └── {} npm global packages", npm_count);
+    if let Err(e) = crate::utils::notify::post("macsweep", &message, config.timeouts.get(CommandKind::Scanner)) {
+        status!("{}", format!("Warning: couldn't post a notification: {}", e).yellow());
     }
-    if pip_count > 0 {
-        println!("   └── {} pip/pipx packages", pip_count);
+    Ok(())
+}
+
+/// Summarize the packages whose usage couldn't be looked up, once the scan is over, so the
+/// warning doesn't scroll away with the progress output
+fn print_usage_failures(failures: &std::collections::BTreeMap<String, Vec<String>>) {
+    let failed: usize = failures.values().map(Vec::len).sum();
+    if failed == 0 {
+        return;
     }
-    if cargo_count > 0 {
-        println!("   └── {} cargo binaries", cargo_count);
+    status!(
+        "{}",
+        format!("\n⚠️  Usage lookup failed for {} packages; they keep the usage recorded by earlier scans:", failed).yellow()
+    );
+    for (error, packages) in failures {
+        let names = match packages.len() {
+            1..=3 => packages.join(", "),
+            n => format!("{} and {} more", packages[..3].join(", "), n - 3),
+        };
+        status!("   {} ({})", error, names.dimmed());
     }
-    if apps_count > 0 {
-        println!("   └── {} Applications", apps_count);
+}
+
+/// Print the per-source package counts after a scan
+fn print_scan_breakdown(all_packages: &[crate::scanner::Package]) {
+    println!("\n📊 Scan complete: {} packages found", all_packages.len().to_string().cyan().bold());
+
+    for group in ScannerRegistry::default().groups() {
+        let count = all_packages.iter().filter(|p| group.sources.contains(&p.source)).count();
+        if count > 0 {
+            println!("   └── {} {}", count, group.label);
+        }
     }
 
     // Calculate total size
@@ -157,390 +240,1498 @@ pub fn scan(source: Option<String>, quick: bool) -> Result<()> {
     if total_size > 0 {
         println!("   └── {} total", crate::utils::size::format_size(total_size).cyan());
     }
+}
 
-    // Gather usage information
-    if !quick {
-        println!("\n🔎 Gathering usage information...");
-        let start_usage = Instant::now();
+/// Whole days since the latest scan (of `scan_type` if given), or None if there wasn't one
+fn days_since_last_scan(db: &Database, scan_type: Option<&str>) -> Result<Option<u32>> {
+    let scan = database::get_last_scan(db.conn(), scan_type)?;
+    Ok(scan.and_then(|s| s.scanned_at()).map(|dt| days_since(&dt)))
+}
 
-        use indicatif::{ProgressBar, ProgressStyle};
+/// Report how old the scan data is, warning once it's older than `stale_after` days
+fn print_scan_freshness(db: &Database, stale_after: u32) -> Result<()> {
+    let Some(days) = days_since_last_scan(db, None)? else {
+        return Ok(());
+    };
 
-        let pb = ProgressBar::new(all_packages.len() as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("  [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-                .unwrap()
-                .progress_chars("━━╺")
-        );
+    let age = format_days_ago(days).to_lowercase();
+    if days >= stale_after {
+        status!("{}", format!("⚠️  Data from scan {} — consider rescanning with `macsweep scan`", age).yellow());
+    } else {
+        status!("{}", format!("Data from scan {}", age).dimmed());
+    }
+    Ok(())
+}
 
-        for package in &mut all_packages {
-            pb.set_message(package.name.clone());
+/// Filters and ordering for `macsweep list`
+pub struct ListOptions {
+    /// `--source` values; empty lists every source
+    pub source: Vec<String>,
+    pub unused: Option<u32>,
+    /// Only packages without any recorded use
+    pub never_used: bool,
+    /// Only packages installed before this time
+    pub installed_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub orphaned: bool,
+    pub large: bool,
+    pub sort: SortField,
+    pub limit: Option<usize>,
+    /// Skip this many packages, for paging with `limit`
+    pub offset: usize,
+    /// Only packages whose name contains this text, or matches it if it has `*` or `?`
+    pub name: Option<String>,
+    pub tag: Option<String>,
+    /// Columns for the table, Markdown and CSV views; empty means the defaults
+    pub columns: Vec<output::PackageColumn>,
+    pub dates: DateDisplay,
+    /// Field separator of the CSV and TSV output
+    pub delimiter: u8,
+    /// Write the formatted list to this file instead of stdout
+    pub output: Option<PathBuf>,
+    /// Overwrite `output` if it already exists
+    pub force: bool,
+    /// Only show packages with a cleanup recommendation
+    pub recommended_only: bool,
+    /// Packages with this tag are shown as Pinned
+    pub keep_tag: String,
+    /// Extra protected packages from the config file, which never get a status
+    pub protected_packages: Vec<String>,
+    /// Thresholds deciding each package's status
+    pub thresholds: RecommendationConfig,
+    /// Also show packages that a scan found to be uninstalled
+    pub include_removed: bool,
+    /// Warn when the last scan is at least this many days old
+    pub stale_after: u32,
+    /// Sort by size growth since the previous scan
+    pub growing: bool,
+    /// Only show applications of this architecture
+    pub arch: Option<crate::scanner::Architecture>,
+    /// How long brew and `ps` may take to list orphans and running processes
+    pub timeouts: CommandTimeouts,
+}
 
-            match crate::usage::aggregate_usage(package) {
-                Ok(usage_info) => {
-                    package.last_used = usage_info.last_used;
-                    package.usage_count = usage_info.usage_count;
-                }
-                Err(e) => {
-                    // Don't fail the scan if usage tracking fails
-                    pb.println(format!("  Warning: Failed to get usage for {}: {}", package.name, e));
+/// The `list` filters, resolved once: what the database can select itself, and the orphans,
+/// which come from brew and are checked per package
+struct PackageFilter {
+    query: database::PackageQuery,
+    /// (name, source) of orphaned packages, for `--orphaned`
+    orphans: Option<std::collections::HashSet<(String, String)>>,
+}
+
+impl PackageFilter {
+    fn new(db: &Database, options: &ListOptions) -> Result<Self> {
+        let orphans = if options.orphaned {
+            Some(crate::analysis::orphans::find_orphans(&api::load_packages(db)?, options.timeouts.get(CommandKind::Scanner)))
+        } else {
+            None
+        };
+
+        let query = database::PackageQuery {
+            include_removed: options.include_removed,
+            sources: ScannerRegistry::default().sources_all(&options.source)?,
+            min_size: options.min_size,
+            max_size: options.max_size,
+            unused_before: options.unused.map(|days| chrono::Utc::now() - chrono::Duration::days(days as i64)),
+            never_used: options.never_used,
+            installed_before: options.installed_before,
+            name_like: options.name.as_deref().map(|name| {
+                if name.contains(['*', '?']) {
+                    database::like_glob(name)
+                } else {
+                    database::like_containing(name)
                 }
-            }
+            }),
+            tag: options.tag.clone(),
+            arch: options.arch,
+            order: if options.large { database::PackageOrder::Size } else { options.sort.into() },
+            ..Default::default()
+        };
+        Ok(Self { query, orphans })
+    }
 
-            pb.inc(1);
+    fn matches(&self, p: &crate::scanner::Package) -> bool {
+        match self.orphans {
+            Some(ref orphans) => orphans.contains(&(p.name.clone(), p.source.to_string())),
+            None => true,
         }
+    }
+}
 
-        pb.finish_and_clear();
+/// Works out the Status column of `list`
+struct StatusResolver {
+    orphans: std::collections::HashSet<(String, String)>,
+    kept: std::collections::HashSet<(String, String)>,
+    protected: crate::analysis::protected::ProtectedPackages,
+    running: crate::usage::processes::ProcessSnapshot,
+    thresholds: RecommendationConfig,
+    now: chrono::DateTime<chrono::Utc>,
+}
 
-        let usage_duration = start_usage.elapsed();
-        println!("  Usage tracking complete in {:.2}s", usage_duration.as_secs_f64());
+impl StatusResolver {
+    /// Orphans come from the `--orphaned` lookup only; `brew autoremove` is too slow to run on every list
+    fn new(
+        db: &Database,
+        filter: &PackageFilter,
+        keep_tag: &str,
+        protected_packages: &[String],
+        thresholds: &RecommendationConfig,
+        timeouts: &CommandTimeouts,
+    ) -> Result<Self> {
+        Ok(Self {
+            thresholds: thresholds.clone(),
+            orphans: filter.orphans.clone().unwrap_or_default(),
+            kept: database::get_tagged_packages(db.conn(), keep_tag)?,
+            protected: crate::analysis::protected::ProtectedPackages::new(
+                protected_packages,
+                &database::get_installed_sources(db.conn())?,
+            ),
+            running: crate::usage::processes::ProcessSnapshot::capture(timeouts.get(CommandKind::Scanner)).unwrap_or_default(),
+            now: chrono::Utc::now(),
+        })
     }
 
-    // Save to database
-    if !all_packages.is_empty() {
-        print!("\n💾 Saving to database... ");
-        match save_packages_to_db(&all_packages, &source, duration.as_millis() as i64) {
-            Ok(_) => println!("{}", "done".green()),
-            Err(e) => println!("{}", format!("Error: {}", e).red()),
+    fn status(&self, package: &crate::scanner::Package) -> Option<PackageStatus> {
+        if package.removed_at.is_some() {
+            return Some(PackageStatus::Removed);
         }
-    }
 
-    Ok(())
-}
+        if self.kept.contains(&(package.name.clone(), package.source.to_string())) {
+            return Some(PackageStatus::Pinned);
+        }
+
+        if self.protected.is_protected(package) || self.running.is_running(package) {
+            return None;
+        }
 
-fn save_packages_to_db(packages: &[crate::scanner::Package], source: &Option<String>, duration_ms: i64) -> Result<()> {
-    let db = Database::default()?;
-    db.init()?;
+        crate::analysis::recommendations::recommend_package(package, &self.orphans, &self.thresholds, self.now)
+            .map(|r| r.severity.into())
+    }
 
-    let conn = db.conn();
+    fn is_recommended(&self, row: &output::ListedPackage) -> bool {
+        matches!(row.status, Some(PackageStatus::Safe | PackageStatus::Review | PackageStatus::Warning))
+    }
+}
 
-    // Save all packages
-    for package in packages {
-        database::upsert_package(conn, package)?;
+pub fn list(db: &Database, options: ListOptions, format: OutputFormat) -> Result<()> {
+    if let Some(ref path) = options.output {
+        if path.exists() && !options.force {
+            anyhow::bail!("{} already exists; use --force to overwrite it", path.display());
+        }
     }
 
-    // Record the scan
-    let scan_type = source.as_deref().unwrap_or("full");
-    database::insert_scan(conn, scan_type, packages.len() as i64, duration_ms)?;
+    print_scan_freshness(db, options.stale_after)?;
+
+    let mut filter = PackageFilter::new(db, &options)?;
+    let statuses = StatusResolver::new(db, &filter, &options.keep_tag, &options.protected_packages, &options.thresholds, &options.timeouts)?;
+    let ListOptions { orphaned, limit, offset, columns, dates, delimiter, recommended_only, include_removed, growing, output: output_path, .. } =
+        options;
+    let default_columns: &[output::PackageColumn] =
+        if format.is_delimited() { &output::DEFAULT_CSV_PACKAGE_COLUMNS } else { &output::DEFAULT_PACKAGE_COLUMNS };
+    let columns = match (columns.is_empty(), growing) {
+        (false, _) => columns,
+        (true, false) => default_columns.to_vec(),
+        (true, true) => [default_columns, &[output::PackageColumn::Growth]].concat(),
+    };
+    let growth = if growing || columns.contains(&output::PackageColumn::Growth) {
+        database::get_size_growth(db.conn())?
+    } else {
+        std::collections::HashMap::new()
+    };
+    let listed = |package: crate::scanner::Package| {
+        let status = statuses.status(&package);
+        let size_growth = growth.get(&(package.source.to_string(), package.name.clone())).copied();
+        output::ListedPackage { package, status, size_growth }
+    };
 
-    Ok(())
-}
+    // The database pages through the results itself unless some packages are left out after
+    // loading, or they're sorted by growth
+    let (mut skip, mut take) = (offset, limit.unwrap_or(usize::MAX));
+    if !orphaned && !recommended_only && !growing {
+        filter.query.limit = limit;
+        filter.query.offset = offset;
+        (skip, take) = (0, usize::MAX);
+    }
 
-pub fn list(
-    source: Option<String>,
-    unused: Option<u32>,
-    orphaned: bool,
-    large: bool,
-    sort: SortField,
-    limit: Option<usize>,
-    format: OutputFormat,
-) -> Result<()> {
-    // Load packages from database
-    let db = Database::default()?;
-    db.init()?;
+    // JSON Lines streams rows straight from the database, which already returns them in order
+    if format == OutputFormat::Jsonl && !growing && output_path.is_none() {
+        let mut writer = output::JsonLinesWriter::stdout();
+        database::for_each_package::<anyhow::Error>(db.conn(), &filter.query, |pkg| {
+            if take > 0 && filter.matches(&pkg) {
+                let row = listed(pkg);
+                if !recommended_only || statuses.is_recommended(&row) {
+                    if skip > 0 {
+                        skip -= 1;
+                    } else {
+                        writer.write(&row)?;
+                        take -= 1;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        return Ok(());
+    }
 
-    let mut packages = database::get_packages(db.conn())?;
+    let mut packages = database::query_packages(db.conn(), &filter.query)?;
 
-    if packages.is_empty() {
+    if packages.is_empty() && !database::has_packages(db.conn(), include_removed)? {
         println!("No packages found. Run {} first.", "macsweep scan".cyan());
         return Ok(());
     }
 
     // Apply filters
-    if let Some(source_filter) = source {
-        packages.retain(|p| {
-            let source_str = format!("{:?}", p.source).to_lowercase();
-            source_str.contains(&source_filter.to_lowercase())
-        });
+    packages.retain(|p| filter.matches(p));
+    let mut packages: Vec<_> = packages.into_iter().map(listed).collect();
+    if recommended_only {
+        packages.retain(|row| statuses.is_recommended(row));
     }
 
-    if let Some(unused_days) = unused {
-        let cutoff = chrono::Utc::now() - chrono::Duration::days(unused_days as i64);
-        packages.retain(|p| {
-            p.last_used.is_none() || p.last_used.unwrap() < cutoff
-        });
-    }
-
-    if orphaned {
-        // Get orphaned packages from Homebrew
-        match crate::analysis::orphans::get_orphaned_brew_packages() {
-            Ok(orphan_names) => {
-                let orphan_set: std::collections::HashSet<_> = orphan_names.iter()
-                    .map(|s| s.as_str())
-                    .collect();
-                packages.retain(|p| orphan_set.contains(p.name.as_str()));
-
-                if packages.is_empty() {
-                    println!("No orphaned packages found.");
-                    return Ok(());
-                }
-            }
-            Err(e) => {
-                eprintln!("Warning: Failed to detect orphaned packages: {}", e);
-                // Fall back to dependency-based detection
-                packages.retain(|p| p.is_dependency);
-            }
-        }
+    if orphaned && packages.is_empty() {
+        println!("No orphaned packages found.");
+        return Ok(());
     }
 
-    // Apply sorting
-    match sort {
-        SortField::Name => packages.sort_by(|a, b| a.name.cmp(&b.name)),
-        SortField::Size => packages.sort_by(|a, b| {
-            b.size_bytes.unwrap_or(0).cmp(&a.size_bytes.unwrap_or(0))
-        }),
-        SortField::LastUsed => packages.sort_by(|a, b| {
-            match (a.last_used, b.last_used) {
-                (Some(a_time), Some(b_time)) => b_time.cmp(&a_time), // Most recent first
-                (Some(_), None) => std::cmp::Ordering::Less,
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                (None, None) => std::cmp::Ordering::Equal,
-            }
-        }),
-        SortField::InstallDate => packages.sort_by(|a, b| {
-            match (a.install_date, b.install_date) {
-                (Some(a_time), Some(b_time)) => b_time.cmp(&a_time),
-                (Some(_), None) => std::cmp::Ordering::Less,
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                (None, None) => std::cmp::Ordering::Equal,
-            }
-        }),
-        SortField::UsageCount => packages.sort_by(|a, b| b.usage_count.cmp(&a.usage_count)),
+    // Fastest growing first; packages without two recorded sizes go last
+    if growing {
+        packages.sort_by_key(|p| std::cmp::Reverse(p.size_growth.map(|g| (1, g)).unwrap_or((0, 0))));
     }
 
-    // Apply large flag (sort by size descending)
-    if large {
-        packages.sort_by(|a, b| {
-            b.size_bytes.unwrap_or(0).cmp(&a.size_bytes.unwrap_or(0))
-        });
-    }
+    let packages: Vec<_> = packages.into_iter().skip(skip).take(take).collect();
 
-    // Apply limit
-    if let Some(lim) = limit {
-        packages.truncate(lim);
+    if let Some(path) = output_path {
+        write_packages_file(&packages, &path, format, &columns, dates, delimiter)?;
+        status!("Wrote {} packages to {}", packages.len(), path.display());
+        return Ok(());
     }
 
     // Display packages
     match format {
-        OutputFormat::Table => display_packages_table(&packages),
+        OutputFormat::Table => display_packages_table(&packages, &columns, dates),
         OutputFormat::Json => display_packages_json(&packages)?,
-        OutputFormat::Csv => display_packages_csv(&packages)?,
+        OutputFormat::Jsonl => {
+            let mut writer = output::JsonLinesWriter::stdout();
+            for pkg in &packages {
+                writer.write(pkg)?;
+            }
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => display_packages_csv(&packages, &columns, delimiter)?,
+        OutputFormat::Yaml => display_packages_yaml(&packages)?,
+        OutputFormat::Markdown => display_packages_markdown(&packages, &columns, dates),
+        OutputFormat::Plist => {
+            output::print_serialized(&packages, format)?;
+        }
     }
 
     Ok(())
 }
 
-use chrono;
-use crate::cli::output;
+/// Write the list to `path` in `format`, without colors or the summary footer
+fn write_packages_file(
+    packages: &[output::ListedPackage],
+    path: &std::path::Path,
+    format: OutputFormat,
+    columns: &[output::PackageColumn],
+    dates: DateDisplay,
+    delimiter: u8,
+) -> Result<()> {
+    use anyhow::Context;
+    use std::io::Write;
+
+    let contents = match format {
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut wtr = output::csv_writer(file, delimiter);
+            write_packages_csv(&mut wtr, packages, columns)?;
+            return Ok(());
+        }
+        OutputFormat::Plist => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            output::write_plist(std::io::BufWriter::new(file), packages)?;
+            return Ok(());
+        }
+        OutputFormat::Jsonl => {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let mut writer = output::JsonLinesWriter::new(std::io::BufWriter::new(file));
+            for pkg in packages {
+                writer.write(pkg)?;
+            }
+            writer.into_inner().flush()?;
+            return Ok(());
+        }
+        OutputFormat::Table => {
+            let mut table = packages_table(packages, columns, dates);
+            table.force_no_tty();
+            format!("{}\n", table)
+        }
+        OutputFormat::Json => format!("{}\n", serde_json::to_string_pretty(packages)?),
+        OutputFormat::Yaml => serde_yaml::to_string(packages)?,
+        OutputFormat::Markdown => {
+            let headers: Vec<_> = columns.iter().map(|c| c.header()).collect();
+            let rows: Vec<_> = packages.iter().map(|p| output::package_table_row(p, columns, dates)).collect();
+            output::markdown_table(&headers, &rows)
+        }
+    };
+
+    std::fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
 
-fn display_packages_table(packages: &[crate::scanner::Package]) {
-    use comfy_table::{Table, Cell, Color, Attribute, ContentArrangement};
+fn packages_table(
+    packages: &[output::ListedPackage],
+    columns: &[output::PackageColumn],
+    dates: DateDisplay,
+) -> comfy_table::Table {
+    use comfy_table::{Cell, Color, Attribute, ContentArrangement};
 
-    let mut table = Table::new();
-    table.load_preset(comfy_table::presets::UTF8_FULL);
+    let mut table = output::create_table();
     table.set_content_arrangement(ContentArrangement::Dynamic);
 
     // Set headers
-    table.set_header(vec![
-        Cell::new("Package").add_attribute(Attribute::Bold),
-        Cell::new("Source").add_attribute(Attribute::Bold),
-        Cell::new("Version").add_attribute(Attribute::Bold),
-        Cell::new("Size").add_attribute(Attribute::Bold),
-        Cell::new("Install Date").add_attribute(Attribute::Bold),
-        Cell::new("Last Used").add_attribute(Attribute::Bold),
-    ]);
+    table.set_header(
+        columns.iter()
+            .map(|c| Cell::new(c.header()).add_attribute(Attribute::Bold))
+            .collect::<Vec<_>>()
+    );
 
-    // Add rows
+    // Add rows (source and status columns highlighted)
     for pkg in packages {
-        let source_str = format!("{:?}", pkg.source);
-        let version_str = pkg.version.as_deref().unwrap_or("-");
-        let size_str = pkg.size_bytes
-            .map(|s| crate::utils::size::format_size(s))
-            .unwrap_or_else(|| "-".to_string());
-
-        let install_date_str = pkg.install_date
-            .map(|dt| dt.format("%Y-%m-%d").to_string())
-            .unwrap_or_else(|| "-".to_string());
-
-        let last_used_str = if let Some(last_used) = pkg.last_used {
-            let days = crate::utils::date::days_since(&last_used);
-            crate::utils::date::format_days_ago(days)
-        } else {
-            "Never".to_string()
-        };
-
-        table.add_row(vec![
-            Cell::new(&pkg.name),
-            Cell::new(source_str).fg(Color::Cyan),
-            Cell::new(version_str),
-            Cell::new(size_str),
-            Cell::new(install_date_str),
-            Cell::new(last_used_str),
-        ]);
+        let cells: Vec<Cell> = columns.iter()
+            .map(|&column| {
+                let cell = Cell::new(column.cell(pkg, dates));
+                match column {
+                    output::PackageColumn::Source => cell.fg(Color::Cyan),
+                    output::PackageColumn::Status => match pkg.status {
+                        Some(status) => cell.fg(output::status_color(status)),
+                        None => cell,
+                    },
+                    _ => cell,
+                }
+            })
+            .collect();
+        table.add_row(cells);
     }
 
-    println!("\n{}", table);
+    table
+}
+
+fn display_packages_table(packages: &[output::ListedPackage], columns: &[output::PackageColumn], dates: DateDisplay) {
+    println!("\n{}", packages_table(packages, columns, dates));
     println!("\nTotal: {} packages", packages.len().to_string().cyan().bold());
 
     // Show total size
     let total_size: u64 = packages.iter()
-        .filter_map(|p| p.size_bytes)
+        .filter_map(|p| p.package.size_bytes)
         .sum();
     if total_size > 0 {
         println!("Total size: {}", crate::utils::size::format_size(total_size).cyan().bold());
     }
 }
 
-fn display_packages_json(packages: &[crate::scanner::Package]) -> Result<()> {
+fn display_packages_json(packages: &[output::ListedPackage]) -> Result<()> {
     let json = serde_json::to_string_pretty(packages)?;
     println!("{}", json);
     Ok(())
 }
 
-fn display_packages_csv(packages: &[crate::scanner::Package]) -> Result<()> {
-    use std::io;
-    let mut wtr = csv::Writer::from_writer(io::stdout());
+fn display_packages_markdown(packages: &[output::ListedPackage], columns: &[output::PackageColumn], dates: DateDisplay) {
+    let headers: Vec<_> = columns.iter().map(|c| c.header()).collect();
+    let rows: Vec<_> = packages.iter().map(|p| output::package_table_row(p, columns, dates)).collect();
+    print!("{}", output::markdown_table(&headers, &rows));
+
+    let total_size: u64 = packages.iter().filter_map(|p| p.package.size_bytes).sum();
+    println!("\n**Total:** {} packages, {}", packages.len(), crate::utils::size::format_size(total_size));
+}
+
+fn display_packages_yaml(packages: &[output::ListedPackage]) -> Result<()> {
+    print!("{}", serde_yaml::to_string(packages)?);
+    Ok(())
+}
+
+fn display_packages_csv(packages: &[output::ListedPackage], columns: &[output::PackageColumn], delimiter: u8) -> Result<()> {
+    let mut wtr = output::csv_writer(std::io::stdout(), delimiter);
+    write_packages_csv(&mut wtr, packages, columns)
+}
 
+fn write_packages_csv<W: std::io::Write>(
+    wtr: &mut csv::Writer<W>,
+    packages: &[output::ListedPackage],
+    columns: &[output::PackageColumn],
+) -> Result<()> {
     // Write headers
-    wtr.write_record(&["name", "source", "version", "size_bytes", "install_date", "last_used"])?;
+    wtr.write_record(columns.iter().map(|c| c.csv_header()))?;
 
     // Write data
     for pkg in packages {
-        wtr.write_record(&[
-            &pkg.name,
-            &format!("{:?}", pkg.source),
-            pkg.version.as_deref().unwrap_or(""),
-            &pkg.size_bytes.map(|s| s.to_string()).unwrap_or_default(),
-            &pkg.install_date.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
-            &pkg.last_used.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
-        ])?;
+        wtr.write_record(columns.iter().map(|c| c.csv_value(pkg)))?;
     }
 
     wtr.flush()?;
     Ok(())
 }
 
-pub fn info(package: &str) -> Result<()> {
-    println!("📦 Package info for: {}", package);
-    // TODO: Implement info logic
-    Ok(())
+#[derive(serde::Serialize)]
+struct PackageDetails<'a> {
+    #[serde(flatten)]
+    package: &'a crate::scanner::Package,
+    tags: Vec<String>,
+    notes: Vec<database::PackageNote>,
+    size_history: Vec<database::SizeRecord>,
+    /// Latest `macsweep mark`, when the last-used value comes from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manual_mark: Option<crate::usage::ManualMark>,
 }
 
-pub fn clean(dry_run: bool, yes: bool, source: Option<String>, interactive: bool) -> Result<()> {
-    println!("🧹 MacSweep Cleanup\n");
+/// Scans shown in the size trend of `info`
+const SIZE_TREND_SCANS: usize = 5;
 
-    // Load packages from database
-    let db = Database::default()?;
-    db.init()?;
-
-    let packages = database::get_packages(db.conn())?;
+pub fn info(db: &Database, package: &str, format: OutputFormat, dates: DateDisplay) -> Result<()> {
+    let matches: Vec<_> = api::load_packages(db)?
+        .into_iter()
+        .filter(|p| p.name == package)
+        .collect();
 
-    if packages.is_empty() {
-        println!("No packages found. Run {} first.", "macsweep scan".cyan());
+    if format.is_structured() {
+        let mut details = Vec::new();
+        for pkg in &matches {
+            let (tags, notes, size_history, manual_mark) = match database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+                Some(id) => (
+                    database::get_package_tags(db.conn(), id)?,
+                    database::get_package_notes(db.conn(), id)?,
+                    database::get_size_history(db.conn(), id, SIZE_TREND_SCANS)?,
+                    database::get_manual_mark(db.conn(), id)?.filter(|mark| mark.explains(pkg)),
+                ),
+                None => (Vec::new(), Vec::new(), Vec::new(), None),
+            };
+            details.push(PackageDetails { package: pkg, tags, notes, size_history, manual_mark });
+        }
+        output::print_serialized(&details, format)?;
         return Ok(());
     }
 
-    // Generate recommendations
-    let recommendations = crate::analysis::recommendations::generate_recommendations(&packages)?;
-
-    if recommendations.is_empty() {
-        println!("{}", "No cleanup recommendations at this time. ✨".green());
+    if matches.is_empty() {
+        println!("Package {} not found. Run {} first.", package.cyan(), "macsweep scan".cyan());
         return Ok(());
     }
 
-    // Filter by source if specified
-    let mut recommendations = recommendations;
-    if let Some(ref source_filter) = source {
-        recommendations.retain(|r| {
-            if let Some(pkg) = packages.iter().find(|p| p.name == r.package) {
-                let source_str = format!("{:?}", pkg.source).to_lowercase();
-                source_str.contains(&source_filter.to_lowercase())
-            } else {
-                false
+    for pkg in &matches {
+        println!("📦 {} ({:?})", pkg.name.cyan().bold(), pkg.source);
+        if let Some(ref description) = pkg.description {
+            println!("  {}", description.dimmed());
+        }
+        println!("  Version: {}", pkg.version.as_deref().unwrap_or("-"));
+        if !pkg.stale_versions.is_empty() {
+            println!(
+                "  Stale versions: {} ({})",
+                pkg.stale_versions.join(", "),
+                crate::utils::size::format_size(pkg.stale_bytes.unwrap_or(0))
+            );
+        }
+        if let Some(size) = pkg.size_bytes {
+            println!("  Size: {}", crate::utils::size::format_size(size));
+        }
+        if let Some(ref path) = pkg.binary_path {
+            println!("  Path: {}", path.display());
+        }
+        if let Some(arch) = pkg.arch {
+            let rosetta = if arch == crate::scanner::Architecture::X86_64 { " (runs under Rosetta)" } else { "" };
+            println!("  Architecture: {}{}", arch.as_str(), rosetta);
+        }
+        if !pkg.binaries.is_empty() {
+            println!("  Binaries: {}", pkg.binaries.join(", "));
+        }
+        if let Some(install_date) = pkg.install_date {
+            println!("  Installed: {}", format_date(&install_date, dates));
+        }
+        let manual_mark = match database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+            Some(id) => database::get_manual_mark(db.conn(), id)?,
+            None => None,
+        };
+        let marked = if manual_mark.is_some_and(|mark| mark.explains(pkg)) {
+            format!(" {}", "(marked manually)".dimmed())
+        } else {
+            String::new()
+        };
+        match pkg.last_used {
+            Some(last_used) => println!(
+                "  Last used: {} ({} uses){}",
+                format_date(&last_used, dates),
+                pkg.usage_count,
+                marked
+            ),
+            None => println!("  Last used: Never{}", marked),
+        }
+        if pkg.launches_at_login {
+            println!("  Startup: launches at login, so never recommended for removal");
+        }
+        if pkg.last_used.is_some() {
+            println!("  Confidence: {}", pkg.usage_confidence.as_str());
+        }
+        if !pkg.dependencies.is_empty() {
+            println!("  Dependencies: {}", pkg.dependencies.join(", "));
+        }
+        if !pkg.dependents.is_empty() {
+            println!("  Required by: {}", pkg.dependents.join(", "));
+        }
+
+        if let Some(id) = database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+            let history = database::get_size_history(db.conn(), id, SIZE_TREND_SCANS)?;
+            if history.len() > 1 {
+                println!("  Size trend:");
+                let mut previous: Option<i64> = None;
+                for record in &history {
+                    let date = record.scanned_at()
+                        .map(|dt| format_date(&dt, dates))
+                        .unwrap_or_else(|| record.scan_date.clone());
+                    let delta = previous
+                        .map(|p| format!(" ({})", crate::utils::size::format_size_delta(record.size_bytes - p)))
+                        .unwrap_or_default();
+                    println!("    {:<16} {}{}", date, crate::utils::size::format_size(record.size_bytes.max(0) as u64), delta.dimmed());
+                    previous = Some(record.size_bytes);
+                }
             }
-        });
 
-        if recommendations.is_empty() {
-            println!("No cleanup recommendations for source: {}", source_filter);
-            return Ok(());
+            let tags = database::get_package_tags(db.conn(), id)?;
+            if !tags.is_empty() {
+                println!("  Tags: {}", tags.join(", ").yellow());
+            }
+
+            let notes = database::get_package_notes(db.conn(), id)?;
+            if !notes.is_empty() {
+                println!("  Notes:");
+                for note in notes {
+                    println!("    • {} ({})", note.note, note.created_at.dimmed());
+                }
+            }
         }
+        println!();
     }
 
-    // Summary
-    let total_recoverable: u64 = recommendations.iter()
-        .map(|r| r.size_recoverable)
-        .sum();
-
-    println!("{}", "Packages to remove:".bold());
-    println!("  Total: {}", recommendations.len().to_string().yellow());
-    println!("  Potential space savings: {}\n", crate::utils::size::format_size(total_recoverable).green().bold());
+    Ok(())
+}
 
-    // Show what will be removed
-    for (idx, rec) in recommendations.iter().enumerate() {
-        let severity_icon = match rec.severity {
-            crate::analysis::recommendations::RecommendationSeverity::Safe => "✓",
-            crate::analysis::recommendations::RecommendationSeverity::Review => "⚠",
-            crate::analysis::recommendations::RecommendationSeverity::Warning => "•",
-        };
-        let size_str = crate::utils::size::format_size(rec.size_recoverable);
-        println!("  {} {} - {} ({})",
-            severity_icon,
-            rec.package.cyan(),
-            rec.reason,
-            size_str.yellow()
-        );
+/// Everything behind a package's recommendation (or lack of one), for `macsweep why`
+#[derive(serde::Serialize)]
+struct Explanation<'a> {
+    name: &'a str,
+    source: String,
+    last_used: Option<chrono::DateTime<chrono::Utc>>,
+    usage_confidence: crate::usage::UsageConfidence,
+    /// Usage sources the latest scan consulted, including those that found nothing
+    usage_checks: Vec<crate::usage::UsageCheck>,
+    checked_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Latest `macsweep mark`, when the last-used value comes from it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manual_mark: Option<crate::usage::ManualMark>,
+    is_dependency: bool,
+    dependencies: &'a [String],
+    dependents: &'a [String],
+    orphaned: bool,
+    /// Tagged with the keep tag
+    pinned: bool,
+    protected: bool,
+    running: bool,
+    launches_at_login: bool,
+    /// None when no scan has measured it, e.g. after `scan --no-size`
+    size_bytes: Option<u64>,
+    thresholds: &'a RecommendationConfig,
+    recommendation: Option<crate::analysis::recommendations::Recommendation>,
+}
 
-        // Limit display to prevent overwhelming output
-        if idx >= 19 && recommendations.len() > 20 {
-            println!("  ... and {} more", recommendations.len() - 20);
-            break;
+impl Explanation<'_> {
+    /// Why there is no recommendation, when there isn't one
+    fn no_recommendation_reason(&self) -> &'static str {
+        if self.pinned {
+            "pinned with the keep tag"
+        } else if self.protected {
+            "on the protected list"
+        } else if self.running {
+            "running right now"
+        } else if self.launches_at_login {
+            "launches at login"
+        } else if !self.dependents.is_empty() {
+            "other installed packages depend on it"
+        } else if self.last_used.is_some() {
+            "used within the warning threshold"
+        } else if self.size_bytes.is_none() && self.thresholds.size_only {
+            "no usage data, and no recorded size to judge it by; run macsweep size --missing"
+        } else {
+            "no usage data, but not large enough to recommend on size alone"
         }
     }
-    println!();
+}
 
-    if dry_run {
-        println!("{}", "[DRY RUN MODE] - No packages will be removed".yellow().bold());
-        println!("Run without --dry-run to actually remove packages.\n");
+/// Explain the recommendation for `package`: what each usage source found, its dependency
+/// situation, the thresholds applied, and the resulting severity
+pub fn why(
+    db: &Database,
+    package: &str,
+    keep_tag: &str,
+    config: &crate::config::Config,
+    thresholds: &RecommendationConfig,
+    format: OutputFormat,
+    dates: DateDisplay,
+) -> Result<()> {
+    let packages = api::load_packages(db)?;
+    let matches: Vec<_> = packages.iter().filter(|p| p.name == package).collect();
+
+    if matches.is_empty() && !format.is_structured() {
+        println!("Package {} not found. Run {} first.", package.cyan(), "macsweep scan".cyan());
+        return Ok(());
     }
 
-    // Interactive mode - let user select packages
-    if interactive && !dry_run {
-        use dialoguer::{theme::ColorfulTheme, MultiSelect};
+    let orphans = if matches.is_empty() {
+        std::collections::HashSet::new()
+    } else {
+        crate::analysis::orphans::find_orphans(&packages, config.timeouts.get(CommandKind::Scanner))
+    };
+    let kept = database::get_tagged_packages(db.conn(), keep_tag)?;
+    let protected = crate::analysis::protected::ProtectedPackages::new(&config.protected_packages, &database::get_installed_sources(db.conn())?);
+    let processes = crate::usage::processes::ProcessSnapshot::capture(config.timeouts.get(CommandKind::Scanner)).unwrap_or_default();
+    let now = chrono::Utc::now();
+
+    let mut explanations = Vec::new();
+    for pkg in matches {
+        let key = (pkg.name.clone(), pkg.source.to_string());
+        let (usage_checks, checked_at, manual_mark) = match database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+            Some(id) => {
+                let (checks, checked_at) = database::get_usage_checks(db.conn(), id)?;
+                (checks, checked_at, database::get_manual_mark(db.conn(), id)?.filter(|mark| mark.explains(pkg)))
+            }
+            None => (Vec::new(), None, None),
+        };
+        let pinned = kept.contains(&key);
+        let is_protected = protected.is_protected(pkg);
+        let running = processes.is_running(pkg);
+        let recommendation = if pinned || is_protected || running {
+            None
+        } else {
+            crate::analysis::recommendations::recommend_package(pkg, &orphans, thresholds, now)
+                .or_else(|| crate::analysis::recommendations::stale_versions_recommendation(pkg))
+        };
 
-        println!("{}", "Select packages to remove (Space to select, Enter to confirm):".bold());
-        println!();
+        explanations.push(Explanation {
+            name: &pkg.name,
+            source: key.1.clone(),
+            last_used: pkg.last_used,
+            usage_confidence: pkg.usage_confidence,
+            usage_checks,
+            checked_at,
+            manual_mark,
+            is_dependency: pkg.is_dependency,
+            dependencies: &pkg.dependencies,
+            dependents: &pkg.dependents,
+            orphaned: orphans.contains(&key),
+            pinned,
+            protected: is_protected,
+            running,
+            launches_at_login: pkg.launches_at_login,
+            size_bytes: pkg.size_bytes,
+            thresholds,
+            recommendation,
+        });
+    }
 
-        let items: Vec<String> = recommendations.iter().map(|r| {
-            let severity_icon = match r.severity {
-                crate::analysis::recommendations::RecommendationSeverity::Safe => "✓",
-                crate::analysis::recommendations::RecommendationSeverity::Review => "⚠",
-                crate::analysis::recommendations::RecommendationSeverity::Warning => "•",
-            };
-            let size_str = crate::utils::size::format_size(r.size_recoverable);
-            format!("{} {} - {} ({})", severity_icon, r.package, r.reason, size_str)
-        }).collect();
+    if format.is_structured() {
+        output::print_serialized(&explanations, format)?;
+        return Ok(());
+    }
 
-        let selected = MultiSelect::with_theme(&ColorfulTheme::default())
-            .items(&items)
-            .interact()?;
+    for explanation in &explanations {
+        print_explanation(explanation, dates);
+    }
+    Ok(())
+}
 
-        if selected.is_empty() {
-            println!("No packages selected. Cleanup cancelled.");
-            return Ok(());
-        }
+fn print_explanation(explanation: &Explanation, dates: DateDisplay) {
+    let list_or = |items: &[String], empty: &str| if items.is_empty() { empty.to_string() } else { items.join(", ") };
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
 
-        // Filter recommendations to only selected ones
-        let selected_recs: Vec<_> = selected.iter()
-            .map(|&idx| recommendations[idx].clone())
-            .collect();
-        recommendations = selected_recs;
+    println!("🔍 {} ({})", explanation.name.cyan().bold(), explanation.source);
 
-        let new_total: u64 = recommendations.iter()
-            .map(|r| r.size_recoverable)
-            .sum();
+    match explanation.checked_at {
+        Some(checked_at) => println!("  Usage sources (last checked: {}):", format_date(&checked_at, dates)),
+        None => println!("  Usage sources: {}", "not recorded; run macsweep scan without --quick".dimmed()),
+    }
+    for check in &explanation.usage_checks {
+        let mark = if check.found { "✓".green() } else { "✗".red() };
+        println!("    {} {}: {}", mark, check.label(), check.result);
+    }
+    if let Some(mark) = explanation.manual_mark {
+        let verb = if mark.used { "used" } else { "never used" };
+        println!("    {} Manual mark: {} on {}", "✓".green(), verb, format_date(&mark.date, dates));
+    }
 
-        println!("\n{} packages selected", recommendations.len().to_string().green().bold());
-        println!("Space to recover: {}\n", crate::utils::size::format_size(new_total).green().bold());
+    match explanation.last_used {
+        Some(last_used) => println!(
+            "  Last used: {} (confidence: {})",
+            format_date(&last_used, dates),
+            explanation.usage_confidence.as_str()
+        ),
+        None => println!("  Last used: Never"),
+    }
+    println!("  Installed as a dependency: {}", yes_no(explanation.is_dependency));
+    println!("  Dependencies: {}", list_or(explanation.dependencies, "none"));
+    println!("  Required by: {}", list_or(explanation.dependents, "nothing"));
+    println!("  Orphaned: {}", yes_no(explanation.orphaned));
+
+    let thresholds = explanation.thresholds;
+    let safe = thresholds.safe_days.map(|days| format!(", Safe after {}", days)).unwrap_or_default();
+    let size = if thresholds.size_only {
+        format!("without usage data, Review above {} MB", thresholds.large_size_mb)
+    } else {
+        "never on size alone".to_string()
+    };
+    let intel = if thresholds.boost_intel_only { "; never-used Intel-only apps boosted" } else { "" };
+    println!(
+        "  Thresholds: Warning after {} days unused, Review after {}{}; {}{}",
+        thresholds.warning_days, thresholds.review_days, safe, size, intel
+    );
+
+    match explanation.recommendation {
+        Some(ref recommendation) => println!(
+            "  Result: {} - {}",
+            format!("{:?}", recommendation.severity).yellow().bold(),
+            recommendation.reason
+        ),
+        None => println!("  Result: {} ({})", "no recommendation".green().bold(), explanation.no_recommendation_reason()),
+    }
+    println!();
+}
+
+pub fn tag(db: &Database, package: Option<String>, tags: Vec<String>, remove: bool) -> Result<()> {
+    // No package: list every tag
+    let package = match package {
+        Some(package) => package,
+        None => {
+            let all_tags = database::get_all_tags(db.conn())?;
+            if all_tags.is_empty() {
+                println!("No tags yet. Add one with {}", "macsweep tag <package> <tag>".cyan());
+                return Ok(());
+            }
+
+            let mut current: Option<&str> = None;
+            for (tag, name) in &all_tags {
+                if current != Some(tag.as_str()) {
+                    println!("{}", tag.yellow().bold());
+                    current = Some(tag.as_str());
+                }
+                println!("  • {}", name);
+            }
+            return Ok(());
+        }
+    };
+
+    let ids = database::find_package_ids(db.conn(), &package)?;
+    if ids.is_empty() {
+        anyhow::bail!("Package not found: {} (run `macsweep scan` first)", package);
+    }
+
+    for id in &ids {
+        for tag in &tags {
+            if remove {
+                database::remove_tag(db.conn(), *id, tag)?;
+            } else {
+                database::add_tag(db.conn(), *id, tag)?;
+            }
+        }
+    }
+
+    let current_tags = database::get_package_tags(db.conn(), ids[0])?;
+    if current_tags.is_empty() {
+        println!("{} has no tags", package.cyan());
+    } else {
+        println!("{}: {}", package.cyan(), current_tags.join(", ").yellow());
+    }
+
+    Ok(())
+}
+
+pub fn note(db: &Database, package: &str, text: Option<String>, remove: bool) -> Result<()> {
+    let ids = database::find_package_ids(db.conn(), package)?;
+    if ids.is_empty() {
+        anyhow::bail!("Package not found: {} (run `macsweep scan` first)", package);
+    }
+
+    if remove {
+        let removed: usize = ids.iter()
+            .map(|id| database::remove_notes(db.conn(), *id))
+            .sum::<crate::error::Result<usize>>()?;
+        println!("Removed {} notes from {}", removed, package.cyan());
+        return Ok(());
+    }
+
+    if let Some(text) = text {
+        for id in &ids {
+            database::add_note(db.conn(), *id, &text)?;
+        }
+        println!("{} Note added to {}", "✓".green(), package.cyan());
+        return Ok(());
+    }
+
+    let notes = database::get_package_notes(db.conn(), ids[0])?;
+    if notes.is_empty() {
+        println!("{} has no notes", package.cyan());
+    }
+    for note in notes {
+        println!("  • {} ({})", note.note, note.created_at.dimmed());
+    }
+
+    Ok(())
+}
+
+/// Record by hand that a package was used on `date` (default: now) or never is, for usage
+/// macsweep can't observe
+pub fn mark(db: &Database, package: &str, used: bool, date: Option<chrono::NaiveDate>) -> Result<()> {
+    let date = match date {
+        Some(date) => {
+            let midnight = date.and_time(chrono::NaiveTime::MIN);
+            let date = chrono::TimeZone::from_local_datetime(&chrono::Local, &midnight)
+                .earliest()
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+                .unwrap_or_else(|| midnight.and_utc());
+            if date > chrono::Utc::now() {
+                anyhow::bail!("--date can't be in the future");
+            }
+            date
+        }
+        None => chrono::Utc::now(),
+    };
+    let mark = crate::usage::ManualMark { used, date };
+
+    let packages: Vec<_> = api::load_packages(db)?
+        .into_iter()
+        .filter(|p| p.name == package)
+        .collect();
+    if packages.is_empty() {
+        anyhow::bail!("Package not found: {} (run `macsweep scan` first)", package);
+    }
+
+    for mut pkg in packages {
+        let Some(id) = database::get_package_id(db.conn(), &pkg.name, &pkg.source)? else {
+            continue;
+        };
+        database::record_manual_mark(db.conn(), id, &mark)?;
+        mark.apply(&mut pkg);
+        database::update_package_usage(db.conn(), id, pkg.last_used, pkg.usage_count, pkg.usage_confidence)?;
+    }
+
+    if used {
+        println!("{} Marked {} as used {}", "✓".green(), package.cyan(), date.format("%Y-%m-%d"));
+    } else {
+        println!("{} Marked {} as never used", "✓".green(), package.cyan());
+    }
+    Ok(())
+}
+
+/// Generate recommendations, leaving out protected packages and those tagged with `keep_tag`
+fn load_recommendations(
+    db: &Database,
+    packages: &[crate::scanner::Package],
+    keep_tag: &str,
+    protected_packages: &[String],
+    thresholds: &RecommendationConfig,
+    timeouts: &CommandTimeouts,
+) -> Result<Vec<crate::analysis::recommendations::Recommendation>> {
+    let protected = crate::analysis::protected::ProtectedPackages::new(protected_packages, packages.iter().map(|p| &p.source));
+    let unmeasured = crate::analysis::recommendations::unmeasured_never_used(packages, &protected, thresholds);
+    if unmeasured > 0 {
+        status!(
+            "{}",
+            format!(
+                "Note: {} never-used packages have no recorded size, so the large-package rule skipped them; run {} to measure them",
+                unmeasured,
+                "macsweep size --missing"
+            )
+            .dimmed()
+        );
+    }
+    Ok(api::recommendations_excluding(packages, thresholds, protected_packages, &api::kept_packages(db, keep_tag)?, timeouts)?)
+}
+
+/// Recommendations for the removals that failed in the most recent cleanup
+fn failed_removals(
+    db: &Database,
+    packages: &[crate::scanner::Package],
+) -> Result<Vec<crate::analysis::recommendations::Recommendation>> {
+    let Some(cleanup_id) = database::get_last_cleanup_id(db.conn())? else {
+        return Ok(Vec::new());
+    };
+
+    let recommendations = database::get_cleanup_items(db.conn(), cleanup_id)?
+        .into_iter()
+        .filter(|item| item.failed() || item.unverified())
+        // Skip anything that has since been removed some other way
+        .filter(|item| packages.iter().any(|p| item.is_for(p)))
+        .map(|item| crate::analysis::recommendations::Recommendation {
+            reason: format!(
+                "Retry failed removal: {}",
+                item.error_message.as_deref().and_then(|e| e.lines().next()).unwrap_or("unknown error")
+            ),
+            source: item.source.parse().ok(),
+            package: item.package,
+            severity: crate::analysis::recommendations::RecommendationSeverity::Review,
+            size_recoverable: item.size_bytes.unwrap_or(0),
+            action: CleanupAction::parse(&item.action),
+        })
+        .collect();
+
+    Ok(recommendations)
+}
+
+/// Recommendations for the Homebrew packages the Brewfile at `path` doesn't list, leaving out
+/// protected packages and those tagged `keep_tag` as recommendations do
+fn brewfile_removals(
+    db: &Database,
+    path: &std::path::Path,
+    packages: &[crate::scanner::Package],
+    keep_tag: &str,
+    protected_packages: &[String],
+) -> Result<Vec<crate::analysis::recommendations::Recommendation>> {
+    let brewfile = crate::analysis::brewfile::Brewfile::load(path)?;
+    let protected = crate::analysis::protected::ProtectedPackages::new(protected_packages, packages.iter().map(|p| &p.source));
+    let kept = api::kept_packages(db, keep_tag)?;
+    let mut comparison = crate::analysis::brewfile::compare(&brewfile, packages);
+    comparison.extra.retain(|p| !protected.is_protected(p) && !kept.contains(&(p.name.clone(), p.source.to_string())));
+    Ok(comparison.to_recommendations())
+}
+
+/// Recommendations for the entries of a saved plan, leaving out (and reporting) packages that
+/// are gone or, unless `allow_drift`, changed version since the plan was written
+fn saved_plan_removals(
+    path: &std::path::Path,
+    packages: &[crate::scanner::Package],
+    allow_drift: bool,
+) -> Result<Vec<crate::analysis::recommendations::Recommendation>> {
+    use crate::cleanup::plan::{Drift, SavedPlan};
+
+    let plan = SavedPlan::load(path)?;
+    status!(
+        "Applying plan {} ({} packages, written {} by macsweep {})\n",
+        path.display(),
+        plan.packages.len(),
+        crate::utils::format_datetime(&plan.created_at),
+        plan.macsweep_version
+    );
+
+    let mut recommendations = Vec::new();
+    for (entry, drift) in plan.check(packages) {
+        match drift {
+            None => {}
+            Some(drift @ Drift::VersionChanged { .. }) if allow_drift => {
+                eprintln!("  ⚠ {} ({:?}): {}, removing anyway", entry.package, entry.source, drift);
+            }
+            Some(drift) => {
+                let hint = if matches!(drift, Drift::Missing) { "" } else { " (use --allow-drift to remove it anyway)" };
+                eprintln!("  ✗ {} ({:?}): {}, skipped{}", entry.package, entry.source, drift, hint);
+                continue;
+            }
+        }
+        recommendations.push(entry.into());
+    }
+
+    Ok(recommendations)
+}
+
+/// Write the plan `clean --dry-run --save-plan` shows
+fn save_plan_file(
+    path: &std::path::Path,
+    recommendations: &[crate::analysis::recommendations::Recommendation],
+    packages: &[crate::scanner::Package],
+) -> Result<()> {
+    let plan = crate::cleanup::plan::SavedPlan::new(recommendations, packages);
+    plan.save(path)?;
+    status!("📝 Saved a plan of {} packages to {}\n", plan.packages.len(), path.display());
+    Ok(())
+}
+
+/// Recommendations for the removals an interrupted cleanup never got to
+fn pending_removals(
+    cleanup: &database::IncompleteCleanup,
+    packages: &[crate::scanner::Package],
+) -> Vec<crate::analysis::recommendations::Recommendation> {
+    cleanup.pending.iter()
+        // Skip anything that has since been removed some other way
+        .filter(|item| packages.iter().any(|p| item.is_for(p)))
+        .map(|item| crate::analysis::recommendations::Recommendation {
+            reason: "Left by an interrupted cleanup".to_string(),
+            package: item.package.clone(),
+            source: item.source.parse().ok(),
+            severity: crate::analysis::recommendations::RecommendationSeverity::Review,
+            size_recoverable: item.size_bytes.unwrap_or(0),
+            action: CleanupAction::parse(&item.action),
+        })
+        .collect()
+}
+
+/// Drop recommendations for packages that installed packages outside the plan still need,
+/// returning them with a "required by" reason, and order the rest so dependents are
+/// removed before their dependencies; `brew cleanup`s keep their packages and go last
+fn order_removals(
+    recommendations: Vec<crate::analysis::recommendations::Recommendation>,
+    packages: &[crate::scanner::Package],
+) -> (Vec<crate::analysis::recommendations::Recommendation>, Vec<crate::analysis::recommendations::Recommendation>) {
+    let (cleanups, recommendations): (Vec<_>, Vec<_>) = recommendations.into_iter()
+        .partition(|r| r.action == CleanupAction::BrewCleanup);
+    let candidates: Vec<_> = recommendations.iter()
+        .filter_map(|r| r.find_package(packages))
+        .collect();
+    let order = crate::analysis::dependencies::plan_removals(packages, &candidates);
+    let find = |package: &crate::scanner::Package| recommendations.iter().find(|r| r.is_for(package)).cloned();
+
+    let mut ordered: Vec<_> = order.ordered.iter().filter_map(|p| find(p)).collect();
+    // Recommendations without a stored package can't be ordered; they stay at the end
+    ordered.extend(recommendations.iter().filter(|r| r.find_package(packages).is_none()).cloned());
+    ordered.extend(cleanups);
+
+    let blocked = order.blocked.iter()
+        .filter_map(|(p, required_by)| {
+            let mut rec = find(p)?;
+            rec.reason = format!("required by {}", required_by.join(", "));
+            Some(rec)
+        })
+        .collect();
+
+    (ordered, blocked)
+}
+
+/// Print the recommendations `order_removals` held back
+fn print_blocked(blocked: &[crate::analysis::recommendations::Recommendation]) {
+    if blocked.is_empty() {
+        return;
+    }
+    println!("{}", "Kept because other packages need them:".bold());
+    for rec in blocked {
+        println!("  ⛔ {} - {}", rec.package.cyan(), rec.reason);
+    }
+    println!();
+}
+
+/// A package the cleanup plan would remove
+#[derive(serde::Serialize)]
+struct PlannedRemoval {
+    package: String,
+    source: Option<crate::scanner::PackageSource>,
+    reason: String,
+    severity: crate::analysis::recommendations::RecommendationSeverity,
+    size_bytes: u64,
+    action: CleanupAction,
+}
+
+/// Structured cleanup plan printed by `clean --dry-run` in machine-readable formats
+#[derive(serde::Serialize)]
+struct CleanPlan {
+    dry_run: bool,
+    total_packages: usize,
+    total_size_bytes: u64,
+    packages: Vec<PlannedRemoval>,
+    /// Dependencies `--autoremove` would take out as well
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    autoremove: Vec<PlannedRemoval>,
+    /// Recommended packages left alone because installed packages still depend on them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    excluded: Vec<PlannedRemoval>,
+}
+
+impl CleanPlan {
+    fn new(
+        recommendations: &[crate::analysis::recommendations::Recommendation],
+        excluded: &[crate::analysis::recommendations::Recommendation],
+        packages: &[crate::scanner::Package],
+    ) -> Self {
+        let planned = |recommendations: &[crate::analysis::recommendations::Recommendation]| -> Vec<PlannedRemoval> {
+            recommendations.iter().map(|r| PlannedRemoval {
+                package: r.package.clone(),
+                source: r.find_package(packages).map(|p| p.source.clone()),
+                reason: r.reason.clone(),
+                severity: r.severity,
+                size_bytes: r.size_recoverable,
+                action: r.action,
+            }).collect()
+        };
+        let removals = planned(recommendations);
+
+        Self {
+            dry_run: true,
+            total_packages: removals.len(),
+            total_size_bytes: removals.iter().map(|p| p.size_bytes).sum(),
+            packages: removals,
+            autoremove: Vec::new(),
+            excluded: planned(excluded),
+        }
+    }
+
+    /// Add the predicted `brew autoremove` cascade to the plan
+    fn with_autoremove(mut self, cascade: &[&crate::scanner::Package]) -> Self {
+        self.autoremove = cascade.iter().map(|p| PlannedRemoval {
+            package: p.name.clone(),
+            source: Some(p.source.clone()),
+            reason: "Dependency no longer needed after this cleanup".to_string(),
+            severity: crate::analysis::recommendations::RecommendationSeverity::Safe,
+            size_bytes: p.size_bytes.unwrap_or(0),
+            action: CleanupAction::Remove,
+        }).collect();
+        self.total_packages += self.autoremove.len();
+        self.total_size_bytes += self.autoremove.iter().map(|p| p.size_bytes).sum::<u64>();
+        self
+    }
+}
+
+/// Options for `macsweep clean`
+pub struct CleanOptions {
+    pub dry_run: bool,
+    pub yes: bool,
+    /// `--source`, `--unused`, `--only-severity` and `--exclude`
+    pub filter: crate::analysis::recommendations::RecommendationFilter,
+    pub interactive: bool,
+    /// Only retry the removals that failed in the last cleanup
+    pub retry_failed: bool,
+    /// Continue the last interrupted cleanup
+    pub resume: bool,
+    /// Write the dry run's plan to this file
+    pub save_plan: Option<PathBuf>,
+    /// Remove exactly the packages in this saved plan
+    pub plan: Option<PathBuf>,
+    /// Apply plan entries whose installed version changed since the plan was saved
+    pub allow_drift: bool,
+    /// Packages with this tag are never recommended
+    pub keep_tag: String,
+    /// Also remove casks' preferences, caches and other files
+    pub zap: bool,
+    /// Leave apps' Preferences and Application Support in place
+    pub keep_config: bool,
+    /// Afterwards remove the dependencies the cleanup left unneeded, as `brew autoremove` would
+    pub autoremove: bool,
+    /// Remove packages on the protected list too
+    pub force_protected: bool,
+    /// Extra protected packages from the config file
+    pub protected_packages: Vec<String>,
+    /// When unused packages are recommended
+    pub thresholds: RecommendationConfig,
+    /// List every package of the plan instead of the largest per source
+    pub show_all: bool,
+    /// Remove the Homebrew packages this Brewfile doesn't list instead of the recommendations
+    pub not_in_brewfile: Option<PathBuf>,
+    /// Told about the cleanup once it is recorded, unless it is a dry run
+    pub hooks: crate::config::HookConfig,
+    /// How long uninstallers may run
+    pub timeouts: CommandTimeouts,
+}
+
+/// Packages of each source shown in the plan before the rest are summarized
+const PLAN_ENTRIES_PER_SOURCE: usize = 20;
+
+/// One source's part of the clean plan
+struct PlanGroup {
+    source: String,
+    /// Indices into the plan, largest first
+    entries: Vec<usize>,
+    size: u64,
+}
+
+/// The plan grouped by source, largest group first; packages no longer in `packages`
+/// go under "Other"
+fn group_plan(recommendations: &[crate::analysis::recommendations::Recommendation], packages: &[crate::scanner::Package]) -> Vec<PlanGroup> {
+    let mut groups: Vec<PlanGroup> = Vec::new();
+    for (idx, rec) in recommendations.iter().enumerate() {
+        let source = rec
+            .find_package(packages)
+            .map(|p| format!("{:?}", p.source))
+            .unwrap_or_else(|| "Other".to_string());
+        match groups.iter_mut().find(|g| g.source == source) {
+            Some(group) => {
+                group.entries.push(idx);
+                group.size += rec.size_recoverable;
+            }
+            None => groups.push(PlanGroup { source, entries: vec![idx], size: rec.size_recoverable }),
+        }
+    }
+
+    for group in &mut groups {
+        group.entries.sort_by_key(|&idx| std::cmp::Reverse(recommendations[idx].size_recoverable));
+    }
+    groups.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.source.cmp(&b.source)));
+    groups
+}
+
+fn severity_icon(severity: crate::analysis::recommendations::RecommendationSeverity) -> &'static str {
+    match severity {
+        crate::analysis::recommendations::RecommendationSeverity::Safe => "✓",
+        crate::analysis::recommendations::RecommendationSeverity::Review => "⚠",
+        crate::analysis::recommendations::RecommendationSeverity::Warning => "•",
+    }
+}
+
+fn plan_group_heading(group: &PlanGroup) -> String {
+    let noun = if group.entries.len() == 1 { "package" } else { "packages" };
+    format!("{} ({} {}, {})", group.source, group.entries.len(), noun, crate::utils::size::format_size(group.size))
+}
+
+fn plan_entry_line(rec: &crate::analysis::recommendations::Recommendation) -> String {
+    let size_str = crate::utils::size::format_size(rec.size_recoverable);
+    format!("{} {} - {} ({})", severity_icon(rec.severity), rec.package, rec.reason, size_str)
+}
+
+/// Let the user pick plan entries with `select(items, checked)`, which returns the indices of
+/// the items left checked. Each source starts with an entry standing for the whole group;
+/// when one is picked, every chosen package is shown again, checked, so single packages can
+/// be deselected. Returns the chosen plan indices in plan order.
+fn pick_plan_entries(
+    groups: &[PlanGroup],
+    recommendations: &[crate::analysis::recommendations::Recommendation],
+    mut select: impl FnMut(&[String], &[bool]) -> Result<Vec<usize>>,
+) -> Result<Vec<usize>> {
+    // `picks` holds whether each item is a whole source, and the plan indices it selects
+    let mut items: Vec<String> = Vec::new();
+    let mut picks: Vec<(bool, Vec<usize>)> = Vec::new();
+    for group in groups {
+        items.push(format!("▸ All of {}", plan_group_heading(group)));
+        picks.push((true, group.entries.clone()));
+        for &idx in &group.entries {
+            items.push(format!("    {}", plan_entry_line(&recommendations[idx])));
+            picks.push((false, vec![idx]));
+        }
+    }
+
+    let selected = select(&items, &vec![false; items.len()])?;
+    let mut chosen: Vec<usize> = selected.iter().flat_map(|&item| picks[item].1.iter().copied()).collect();
+    chosen.sort_unstable();
+    chosen.dedup();
+    if !selected.iter().any(|&item| picks[item].0) || chosen.is_empty() {
+        return Ok(chosen);
+    }
+
+    println!("\n{}", "Deselect any packages to keep (Space to toggle, Enter to confirm):".bold());
+    let items: Vec<String> = chosen.iter().map(|&idx| plan_entry_line(&recommendations[idx])).collect();
+    let kept = select(&items, &vec![true; items.len()])?;
+    Ok(kept.into_iter().map(|item| chosen[item]).collect())
+}
+
+pub fn clean(db: &Database, options: CleanOptions, format: OutputFormat) -> Result<Outcome> {
+    let CleanOptions {
+        dry_run, yes, filter, interactive, retry_failed, resume, save_plan, plan, allow_drift,
+        keep_tag, zap, keep_config, autoremove, force_protected, protected_packages, thresholds, show_all, not_in_brewfile, hooks,
+        timeouts,
+    } = options;
+    let machine = format.is_structured();
+    if machine && !dry_run {
+        anyhow::bail!("Structured output for clean requires --dry-run");
+    }
+    status!("🧹 MacSweep Cleanup\n");
+
+    let packages = api::load_packages(db)?;
+
+    if packages.is_empty() && !machine {
+        println!("No packages found. Run {} first.", "macsweep scan".cyan());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    // Generate recommendations, or take the failures of the last cleanup or what an
+    // interrupted one left
+    let incomplete = database::get_incomplete_cleanup(db.conn())?;
+    let recommendations = if let Some(ref plan_path) = plan {
+        saved_plan_removals(plan_path, &packages, allow_drift)?
+    } else if resume {
+        incomplete.as_ref().map(|c| pending_removals(c, &packages)).unwrap_or_default()
+    } else if retry_failed {
+        failed_removals(db, &packages)?
+    } else if let Some(ref path) = not_in_brewfile {
+        brewfile_removals(db, path, &packages, &keep_tag, &protected_packages)?
+    } else if packages.is_empty() {
+        Vec::new()
+    } else {
+        load_recommendations(db, &packages, &keep_tag, &protected_packages, &thresholds, &timeouts)?
+    };
+    // Filter before anything is shown, so the plan and its totals are what will run
+    let unfiltered = recommendations.len();
+    let recommendations = filter.apply(recommendations, &packages, chrono::Utc::now());
+    let removal_options = crate::cleanup::executor::RemovalOptions {
+        dry_run,
+        zap,
+        protected: crate::analysis::protected::ProtectedPackages::new(&protected_packages, packages.iter().map(|p| &p.source)),
+        force_protected,
+        timeouts,
+    };
+
+    if machine {
+        let (recommendations, excluded) = order_removals(recommendations, &packages);
+        if let Some(ref path) = save_plan {
+            save_plan_file(path, &recommendations, &packages)?;
+        }
+        let mut plan = CleanPlan::new(&recommendations, &excluded, &packages);
+        if autoremove {
+            let removed: Vec<_> = recommendations.iter()
+                .filter(|r| r.action == CleanupAction::Remove)
+                .filter_map(|r| r.find_package(&packages))
+                .collect();
+            plan = plan.with_autoremove(&crate::analysis::orphans::predict_autoremove(&packages, &removed));
+        }
+        output::print_serialized(&plan, format)?;
+        return Ok(if recommendations.is_empty() { Outcome::NothingToDo } else { Outcome::Success });
+    }
+
+    if let Some(ref cleanup) = incomplete {
+        if !resume {
+            status!(
+                "💡 The last cleanup was interrupted with {} removals left; run {} to finish it\n",
+                cleanup.pending.len(),
+                "macsweep clean --resume".cyan()
+            );
+        }
+    }
+
+    if recommendations.is_empty() {
+        if unfiltered > 0 {
+            println!("No cleanup recommendations match the given filters.");
+        } else if plan.is_some() {
+            println!("Nothing in the plan can be removed.");
+        } else if resume {
+            println!("No interrupted cleanup to resume.");
+        } else if retry_failed {
+            println!("No failed removals to retry.");
+        } else {
+            println!("{}", "No cleanup recommendations at this time. ✨".green());
+        }
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let (mut recommendations, blocked) = order_removals(recommendations, &packages);
+    if recommendations.is_empty() {
+        print_blocked(&blocked);
+        println!("{}", "Every recommended package is still needed by another package.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+    if let Some(ref path) = save_plan {
+        save_plan_file(path, &recommendations, &packages)?;
+    }
+
+    // Summary
+    let total_recoverable: u64 = recommendations.iter()
+        .map(|r| r.size_recoverable)
+        .sum();
+
+    println!("{}", "Packages to remove:".bold());
+    println!("  Total: {}", recommendations.len().to_string().yellow());
+    println!("  Potential space savings: {}\n", crate::utils::size::format_size(total_recoverable).green().bold());
+
+    // Show what will be removed, by source; long groups are cut short unless --show-all
+    let groups = group_plan(&recommendations, &packages);
+    for group in &groups {
+        println!("  {}", plan_group_heading(group).bold());
+        let shown = if show_all { group.entries.len() } else { group.entries.len().min(PLAN_ENTRIES_PER_SOURCE) };
+        for &idx in &group.entries[..shown] {
+            let rec = &recommendations[idx];
+            let size_str = crate::utils::size::format_size(rec.size_recoverable);
+            println!("    {} {} - {} ({})",
+                severity_icon(rec.severity),
+                rec.package.cyan(),
+                rec.reason,
+                size_str.yellow()
+            );
+        }
+        if shown < group.entries.len() {
+            println!("    ... and {} more (--show-all to list them)", group.entries.len() - shown);
+        }
+    }
+    println!();
+    print_blocked(&blocked);
+
+    if dry_run {
+        println!("{}", "[DRY RUN MODE] - No packages will be removed".yellow().bold());
+        println!("Run without --dry-run to actually remove packages.\n");
+    }
+
+    // Interactive mode - let user select packages
+    if interactive && !dry_run {
+        use dialoguer::MultiSelect;
+
+        println!("{}", "Select packages to remove (Space to select, Enter to confirm):".bold());
+        println!("{}", "Selecting a source's \"All of\" entry selects every package under it; you can deselect them next.".dimmed());
+        println!();
+
+        let chosen = pick_plan_entries(&groups, &recommendations, |items, defaults| {
+            Ok(MultiSelect::with_theme(output::prompt_theme().as_ref())
+                .items(items)
+                .defaults(defaults)
+                .interact()?)
+        })?;
+
+        if chosen.is_empty() {
+            println!("No packages selected. Cleanup cancelled.");
+            return Ok(Outcome::Cancelled);
+        }
+
+        // Filter recommendations to only selected ones, in plan order; a dependency picked
+        // without the package that needs it has to stay
+        let selected_recs: Vec<_> = recommendations.iter()
+            .enumerate()
+            .filter(|(idx, _)| chosen.contains(idx))
+            .map(|(_, rec)| rec.clone())
+            .collect();
+        let (selected_recs, blocked) = order_removals(selected_recs, &packages);
+        print_blocked(&blocked);
+        if selected_recs.is_empty() {
+            println!("Nothing left to remove. Cleanup cancelled.");
+            return Ok(Outcome::Cancelled);
+        }
+        recommendations = selected_recs;
+
+        let new_total: u64 = recommendations.iter()
+            .map(|r| r.size_recoverable)
+            .sum();
+
+        println!("\n{} packages selected", recommendations.len().to_string().green().bold());
+        println!("Space to recover: {}\n", crate::utils::size::format_size(new_total).green().bold());
+    }
+
+    // Apps and casks take their support files in ~/Library to the Trash with them; `--zap`
+    // leaves casks' to brew
+    let mut support_files: std::collections::HashMap<(&str, &PackageSource), Vec<crate::analysis::leftovers::Leftover>> = recommendations.iter()
+        .filter(|r| r.action == CleanupAction::Remove)
+        .filter_map(|r| r.find_package(&packages))
+        .filter(|p| !(zap && p.source == PackageSource::HomebrewCask))
+        .filter_map(|p| match crate::analysis::leftovers::support_files(p, keep_config, timeouts.get(CommandKind::Scanner)) {
+            Ok(files) if !files.is_empty() => Some(((p.name.as_str(), &p.source), files)),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("⚠️  Warning: Failed to look for {}'s support files: {}", p.name, e);
+                None
+            }
+        })
+        .collect();
+
+    if !support_files.is_empty() {
+        let total: u64 = support_files.values().flatten().map(|f| f.size_bytes).sum();
+        println!("{}", "Support files to move to the Trash:".bold());
+        for rec in &recommendations {
+            let Some(files) = rec.find_package(&packages).and_then(|p| support_files.get(&(p.name.as_str(), &p.source))) else {
+                continue;
+            };
+            println!("  {}", rec.package.cyan());
+            for file in files {
+                println!("    {} ({})", file.path.display(), crate::utils::size::format_size(file.size_bytes).yellow());
+            }
+        }
+        println!("  Total: {}", crate::utils::size::format_size(total).green().bold());
+        if keep_config {
+            println!("  Preferences and Application Support are kept (--keep-config)");
+        }
+        println!();
+
+        // The package selection doesn't cover these, so ask about them separately
+        if interactive && !dry_run && !yes {
+            let confirmed = dialoguer::Confirm::with_theme(output::prompt_theme().as_ref())
+                .with_prompt("Move these support files to the Trash too?")
+                .default(true)
+                .interact()?;
+            if !confirmed {
+                support_files.clear();
+            }
+        }
     }
 
     // Confirm before proceeding (unless --yes flag)
@@ -554,251 +1745,1185 @@ pub fn clean(dry_run: bool, yes: bool, source: Option<String>, interactive: bool
 
         if !input.trim().eq_ignore_ascii_case("y") {
             println!("Cleanup cancelled.");
-            return Ok(());
+            return Ok(Outcome::Cancelled);
+        }
+    }
+
+    if !dry_run {
+        if let Err(e) = crate::utils::interrupt::install_handler() {
+            eprintln!("⚠️  Warning: {}", e);
+        }
+    }
+
+    let request = crate::cleanup::runner::CleanupRequest {
+        recommendations: &recommendations,
+        packages: &packages,
+        support_files: &support_files,
+        options: &removal_options,
+        autoremove,
+        resuming: incomplete.as_ref().filter(|_| resume),
+    };
+    // Ctrl-C lets the removals in flight finish, then no more are started
+    let progress = CleanupProgress { dry_run, bars: output::TerminalProgress::default() };
+    let report = crate::cleanup::runner::execute_cleanup(
+        db.conn(),
+        &request,
+        &crate::utils::interrupt::interrupted,
+        &progress.bars,
+        |event| progress.show(event),
+    );
+
+    print_cleanup_summary(&report, dry_run);
+    if !dry_run && !hooks.is_empty() {
+        run_post_clean_hooks(&hooks, &report)?;
+    }
+
+    Ok(if report.is_partial() { Outcome::PartialFailure } else { Outcome::Success })
+}
+
+/// Run the `[hooks]` for a finished cleanup. A failure is only logged, unless the hooks are
+/// `required`; the cleanup itself stands either way.
+fn run_post_clean_hooks(hooks: &crate::config::HookConfig, report: &crate::cleanup::runner::CleanupReport) -> Result<()> {
+    let failures = crate::cleanup::hooks::run_post_clean(hooks, report);
+    for failure in &failures {
+        tracing::warn!(hook = failure.hook, error = %failure.error, "Post-clean hook failed");
+    }
+    if hooks.required && !failures.is_empty() {
+        let failures: Vec<String> = failures.iter().map(ToString::to_string).collect();
+        anyhow::bail!("The cleanup finished, but a required hook failed: {}", failures.join("; "));
+    }
+    Ok(())
+}
+
+/// Announce a new backup manifest
+fn print_backup_created(manifest_path: &str) {
+    let path = std::path::Path::new(manifest_path);
+    let backup_id = path.file_stem().unwrap_or_default().to_string_lossy();
+    println!("  📋 Backup created: {}", backup_id);
+    println!("     Manifest: {}", path.display());
+}
+
+/// Shows what a cleanup is doing: removals in flight as progress bars, problems above them
+struct CleanupProgress {
+    dry_run: bool,
+    bars: output::TerminalProgress,
+}
+
+impl CleanupProgress {
+    fn show(&self, event: crate::cleanup::runner::CleanupEvent) {
+        use crate::cleanup::runner::{CleanupEvent, CleanupWarning, ItemOutcome};
+
+        match event {
+            CleanupEvent::CreatingBackup => status!("\n{}", "Creating backup...".bold()),
+            CleanupEvent::BackupCreated { manifest_path } => print_backup_created(manifest_path),
+            CleanupEvent::Started => status!("\n{}", "Starting cleanup...".bold()),
+            CleanupEvent::RemovalFinished { package, action, outcome } => match outcome {
+                ItemOutcome::Removed | ItemOutcome::Cleaned => {}
+                ItemOutcome::Unverified(message) => {
+                    self.bars.println(format!("  ⚠ Could not verify removal of {}: {}", package.name, message));
+                }
+                ItemOutcome::Failed(stderr) => {
+                    let verb = match action {
+                        CleanupAction::Remove => "remove",
+                        CleanupAction::BrewCleanup => "clean up",
+                    };
+                    self.bars.println(format!("  ✗ Failed to {} {}: {}", verb, package.name, stderr));
+                }
+                ItemOutcome::Error(e) => self.bars.println(format!("  ✗ Error removing {}: {}", package.name, e)),
+            },
+            CleanupEvent::AutoremoveStarted => status!("\n{}", "Removing dependencies that are no longer needed...".bold()),
+            CleanupEvent::Autoremoved { packages } => {
+                let names: Vec<_> = packages.iter().map(|p| p.name.as_str()).collect();
+                let verb = if self.dry_run { "Would also remove" } else { "Also removed" };
+                println!("  {} {} unneeded dependencies: {}", verb, packages.len(), names.join(", ").cyan());
+            }
+            CleanupEvent::Warning(warning) => match warning {
+                CleanupWarning::Database(message) => tracing::warn!("{}", message),
+                // Removals in flight show their problems above the progress bars
+                warning if self.bars.is_active() => self.bars.println(format!("  ⚠️  Warning: {}", warning)),
+                CleanupWarning::NoBackup(message) => {
+                    eprintln!("⚠️  Warning: {}", message);
+                    eprintln!("   Proceeding without backup...");
+                }
+                warning => eprintln!("⚠️  Warning: {}", warning),
+            },
+        }
+    }
+}
+
+fn print_cleanup_summary(report: &crate::cleanup::runner::CleanupReport, dry_run: bool) {
+    println!("\n{}", "Cleanup Summary:".bold());
+    if dry_run {
+        println!("  Would remove: {}", report.removed.to_string().green());
+        if report.cleaned > 0 {
+            println!("  Would clean up stale versions of: {}", report.cleaned.to_string().green());
         }
+        if report.support_files_trashed > 0 {
+            println!("  Would trash support files: {}", report.support_files_trashed.to_string().green());
+        }
+        println!("  Would recover: {}", crate::utils::size::format_size(report.bytes_recovered).green().bold());
+        return;
+    }
+
+    println!("  Successfully removed: {}", report.removed.to_string().green());
+    if report.cleaned > 0 {
+        println!("  Stale versions cleaned up: {}", report.cleaned.to_string().green());
+    }
+    if report.support_files_trashed > 0 {
+        println!("  Support files moved to the Trash: {}", report.support_files_trashed.to_string().green());
     }
+    if report.failed > 0 {
+        println!("  Failed: {}", report.failed.to_string().red());
+    }
+    if report.unverified > 0 {
+        println!("  Reported removed but still present: {}", report.unverified.to_string().yellow());
+    }
+    if report.interrupted() {
+        println!("  Not attempted (interrupted): {}", report.not_attempted.to_string().yellow());
+    }
+    println!("  Space recovered: {}", crate::utils::size::format_size(report.bytes_recovered).green().bold());
+
+    // Show undo instructions
+    if report.interrupted() {
+        status!("\n💡 Run {} to remove the rest", "macsweep clean --resume".cyan());
+    }
+    if report.failed > 0 || report.unverified > 0 {
+        status!("\n💡 Run {} to try the failed removals again", "macsweep clean --retry-failed".cyan());
+    }
+    if let Some(log_path) = report.log_path.as_ref().filter(|_| report.failed > 0 || report.unverified > 0 || report.log_has_failures) {
+        status!("\n📄 Full uninstaller output: {}", log_path.display());
+    }
+    if report.removed > 0 && report.backup_manifest_path.is_some() {
+        status!("\n💡 Run {} to undo this cleanup", "macsweep undo".cyan());
+    }
+}
+
+#[derive(serde::Serialize)]
+struct MeasuredSize<'a> {
+    name: &'a str,
+    source: &'a PackageSource,
+    path: &'a std::path::Path,
+    size_bytes: u64,
+    previous_size_bytes: Option<u64>,
+}
+
+/// Measure `package`'s directory again (every source's, if several have one by that name) and
+/// record the new size, for the package and for the next scan. Without a package, measure every
+/// package that has no recorded size (`size --missing`), reusing cached directory sizes.
+pub fn size(db: &Database, package: Option<&str>, format: OutputFormat) -> Result<()> {
+    let packages = api::load_packages(db)?;
+    let (matches, cache): (Vec<_>, _) = match package {
+        Some(package) => {
+            let matches: Vec<_> = packages.iter().filter(|p| p.name == package).collect();
+            if matches.is_empty() {
+                anyhow::bail!("Package {} not found. Run macsweep scan first.", package);
+            }
+            (matches, crate::utils::size::SizeCache::new(std::collections::HashMap::new(), true))
+        }
+        None => {
+            let missing = packages.iter().filter(|p| p.size_bytes.is_none() && p.size_path.is_some()).collect();
+            (missing, crate::utils::size::SizeCache::new(database::get_directory_sizes(db.conn())?, false))
+        }
+    };
+
+    let mut measured = Vec::new();
+    for pkg in matches {
+        let Some(path) = &pkg.size_path else {
+            status!("{} ({:?}) has no directory to measure; rescan to update its size", pkg.name, pkg.source);
+            continue;
+        };
+        let size_bytes = cache.size_of(path).with_context(|| format!("Cannot measure {}", path.display()))?;
+        if let Some(id) = database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+            database::update_package_size(db.conn(), id, size_bytes)?;
+        }
+        measured.push(MeasuredSize { name: &pkg.name, source: &pkg.source, path, size_bytes, previous_size_bytes: pkg.size_bytes });
+    }
+    database::save_directory_sizes(db.conn(), &cache.measured())?;
+
+    if output::print_serialized(&measured, format)? {
+        return Ok(());
+    }
+    if package.is_none() && measured.is_empty() {
+        println!("Every package with a directory to measure has a recorded size.");
+    }
+    for size in &measured {
+        let previous = match size.previous_size_bytes {
+            Some(previous) if previous != size.size_bytes => format!(
+                " (was {}, {})",
+                crate::utils::size::format_size(previous),
+                crate::utils::size::format_size_delta(size.size_bytes as i64 - previous as i64)
+            ),
+            Some(_) => " (unchanged)".to_string(),
+            None => String::new(),
+        };
+        println!(
+            "📏 {} ({:?}): {}{}",
+            size.name.cyan().bold(),
+            size.source,
+            crate::utils::size::format_size(size.size_bytes).green(),
+            previous.dimmed()
+        );
+        println!("   {}", size.path.display().to_string().dimmed());
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct PackageHistory<'a> {
+    name: &'a str,
+    source: String,
+    /// Days with a recorded use in each of the last twelve months, oldest first
+    monthly_uses: Vec<crate::analysis::trends::MonthlyUsage>,
+    trend: Option<crate::analysis::trends::UsageTrend>,
+    events: Vec<database::UsageEvent>,
+}
+
+pub fn history(db: &Database, package: &str, format: OutputFormat, dates: DateDisplay) -> Result<()> {
+    let matches: Vec<_> = api::load_packages(db)?
+        .into_iter()
+        .filter(|p| p.name == package)
+        .collect();
+
+    let now = chrono::Utc::now();
+    let mut histories = Vec::new();
+    for pkg in &matches {
+        let (events, monthly) = match database::get_package_id(db.conn(), &pkg.name, &pkg.source)? {
+            Some(id) => (
+                database::get_usage_events(db.conn(), id)?,
+                database::get_monthly_usage(db.conn(), id, now - chrono::Duration::days(366))?,
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        histories.push(PackageHistory {
+            name: &pkg.name,
+            source: pkg.source.to_string(),
+            monthly_uses: crate::analysis::trends::last_twelve_months(&monthly, now),
+            trend: pkg.usage_trend,
+            events,
+        });
+    }
+
+    if format.is_structured() {
+        output::print_serialized(&histories, format)?;
+        return Ok(());
+    }
+
+    if histories.is_empty() {
+        println!("Package {} not found. Run {} first.", package.cyan(), "macsweep scan".cyan());
+        return Ok(());
+    }
+
+    for history in &histories {
+        println!("📊 Usage history for {} ({})", history.name.cyan().bold(), history.source);
+        if history.monthly_uses.iter().any(|m| m.uses > 0) {
+            print_monthly_uses(&history.monthly_uses);
+            if let Some(description) = history.trend.and_then(|t| t.describe()) {
+                println!("   {}", format!("Trend: {}", description).yellow());
+            }
+            println!();
+        }
+        if history.events.is_empty() {
+            println!("   {}", "No usage recorded".dimmed());
+        }
+        for event in &history.events {
+            println!(
+                "   {}  {:<9} {}",
+                format_date(&event.event_date, dates),
+                event.event_type,
+                event.details.as_deref().unwrap_or("").dimmed()
+            );
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Days used per month as a bar chart, scaled to the busiest month
+fn print_monthly_uses(months: &[crate::analysis::trends::MonthlyUsage]) {
+    const WIDTH: u32 = 30;
+    let busiest = months.iter().map(|m| m.uses).max().unwrap_or(0).max(1);
+
+    println!("   Days used per month:");
+    for month in months {
+        let bar = "█".repeat((month.uses * WIDTH).div_ceil(busiest) as usize);
+        println!("   {}  {:>3} {}", month.month, month.uses, bar.cyan());
+    }
+}
+
+#[derive(serde::Serialize)]
+struct StatsSummary<'a> {
+    total_packages: usize,
+    total_size_bytes: u64,
+    sources: std::collections::BTreeMap<String, usize>,
+    packages_with_usage_data: usize,
+    packages_without_usage_data: usize,
+    potential_savings_bytes: u64,
+    recommendations: &'a [crate::analysis::recommendations::Recommendation],
+}
+
+fn stats_markdown(summary: &StatsSummary) -> String {
+    use crate::utils::size::format_size;
+
+    let mut out = String::from("## Package Overview\n\n");
+    out.push_str(&output::markdown_table(&["Metric", "Value"], &[
+        vec!["Total packages".to_string(), summary.total_packages.to_string()],
+        vec!["Total size".to_string(), format_size(summary.total_size_bytes)],
+        vec!["Packages with usage data".to_string(), summary.packages_with_usage_data.to_string()],
+        vec!["Packages without usage data".to_string(), summary.packages_without_usage_data.to_string()],
+    ]));
+
+    out.push_str("\n## Source Breakdown\n\n");
+    let sources: Vec<_> = summary.sources.iter()
+        .map(|(source, count)| vec![source.clone(), count.to_string()])
+        .collect();
+    out.push_str(&output::markdown_table(&["Source", "Packages"], &sources));
+
+    out.push_str(&format!(
+        "\n## Cleanup Recommendations\n\nPotential space savings: {}\n\n",
+        format_size(summary.potential_savings_bytes)
+    ));
+    let recs: Vec<_> = summary.recommendations.iter()
+        .map(|r| vec![format!("{:?}", r.severity), r.package.clone(), r.reason.clone(), format_size(r.size_recoverable)])
+        .collect();
+    out.push_str(&output::markdown_table(&["Severity", "Package", "Reason", "Size"], &recs));
+
+    out
+}
+
+pub fn stats(
+    db: &Database,
+    keep_tag: &str,
+    protected_packages: &[String],
+    thresholds: &RecommendationConfig,
+    timeouts: &CommandTimeouts,
+    stale_after: u32,
+    format: OutputFormat,
+) -> Result<()> {
+    print_scan_freshness(db, stale_after)?;
+    let packages = api::load_packages(db)?;
+
+    if format.is_structured() || format == OutputFormat::Markdown {
+        let recommendations = load_recommendations(db, &packages, keep_tag, protected_packages, thresholds, timeouts)?;
+        let mut sources = std::collections::BTreeMap::new();
+        for package in &packages {
+            *sources.entry(package.source.as_str().to_string()).or_insert(0) += 1;
+        }
+        let with_usage = packages.iter().filter(|p| p.last_used.is_some()).count();
+
+        let summary = StatsSummary {
+            total_packages: packages.len(),
+            total_size_bytes: packages.iter().filter_map(|p| p.size_bytes).sum(),
+            sources,
+            packages_with_usage_data: with_usage,
+            packages_without_usage_data: packages.len() - with_usage,
+            potential_savings_bytes: recommendations.iter().map(|r| r.size_recoverable).sum(),
+            recommendations: &recommendations,
+        };
+        if !output::print_serialized(&summary, format)? {
+            print!("{}", stats_markdown(&summary));
+        }
+        return Ok(());
+    }
+
+    println!("📈 MacSweep Statistics\n");
+
+    if packages.is_empty() {
+        println!("No packages found. Run {} first.", "macsweep scan".cyan());
+        return Ok(());
+    }
+
+    // Overall statistics
+    println!("{}",  "═══ Package Overview ═══".cyan().bold());
+    println!("Total packages: {}", packages.len().to_string().yellow().bold());
+
+    let total_size: u64 = packages.iter()
+        .filter_map(|p| p.size_bytes)
+        .sum();
+    println!("Total size: {}", crate::utils::size::format_size(total_size).yellow().bold());
+
+    println!("\n{}",  "Source breakdown:".bold());
+    for group in ScannerRegistry::default().groups() {
+        let count = packages.iter().filter(|p| group.sources.contains(&p.source)).count();
+        if count > 0 {
+            println!("  {}: {}", group.label, count.to_string().cyan());
+        }
+    }
+
+    // Usage statistics
+    let used_count = packages.iter()
+        .filter(|p| p.last_used.is_some())
+        .count();
+    let never_used_count = packages.len() - used_count;
+
+    println!("\n{}", "═══ Usage Statistics ═══".cyan().bold());
+    println!("Packages with usage data: {}", used_count.to_string().green());
+    println!("Packages without usage data: {}", never_used_count.to_string().yellow());
+
+    // Generate cleanup recommendations
+    println!("\n{}", "═══ Cleanup Recommendations ═══".cyan().bold());
+
+    let recommendations = load_recommendations(db, &packages, keep_tag, protected_packages, thresholds, timeouts)?;
+
+    if recommendations.is_empty() {
+        println!("{}", "No cleanup recommendations at this time. ✨".green());
+        return Ok(());
+    }
+
+    let total_recoverable: u64 = recommendations.iter()
+        .map(|r| r.size_recoverable)
+        .sum();
+
+    println!("Found {} cleanup opportunities", recommendations.len().to_string().yellow().bold());
+    println!("Potential space savings: {}\n", crate::utils::size::format_size(total_recoverable).green().bold());
+
+    // Group by severity
+    let safe_recs: Vec<_> = recommendations.iter()
+        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Safe)
+        .collect();
+    let review_recs: Vec<_> = recommendations.iter()
+        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Review)
+        .collect();
+    let warning_recs: Vec<_> = recommendations.iter()
+        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Warning)
+        .collect();
+
+    if !safe_recs.is_empty() {
+        println!("{} ({})", "Safe to Remove:".green().bold(), safe_recs.len());
+        for rec in safe_recs.iter().take(5) {
+            let size_str = crate::utils::size::format_size(rec.size_recoverable);
+            println!("  • {} - {} ({})", rec.package.cyan(), rec.reason, size_str.yellow());
+        }
+        if safe_recs.len() > 5 {
+            println!("  ... and {} more", safe_recs.len() - 5);
+        }
+        println!();
+    }
+
+    if !review_recs.is_empty() {
+        println!("{} ({})", "Review Recommended:".yellow().bold(), review_recs.len());
+        for rec in review_recs.iter().take(5) {
+            let size_str = crate::utils::size::format_size(rec.size_recoverable);
+            println!("  • {} - {} ({})", rec.package.cyan(), rec.reason, size_str.yellow());
+        }
+        if review_recs.len() > 5 {
+            println!("  ... and {} more", review_recs.len() - 5);
+        }
+        println!();
+    }
+
+    if !warning_recs.is_empty() {
+        println!("{} ({})", "Consider Checking:".cyan().bold(), warning_recs.len());
+        for rec in warning_recs.iter().take(5) {
+            let size_str = crate::utils::size::format_size(rec.size_recoverable);
+            println!("  • {} - {} ({})", rec.package, rec.reason, size_str.yellow());
+        }
+        if warning_recs.len() > 5 {
+            println!("  ... and {} more", warning_recs.len() - 5);
+        }
+    }
+
+    println!("\n💡 Run {} to see orphaned packages", "macsweep list --orphaned".cyan());
+    println!("💡 Run {} to see unused packages", "macsweep list --unused 90".cyan());
+
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct ScanTrend {
+    #[serde(flatten)]
+    scan: database::ScanRecord,
+    packages_delta: Option<i64>,
+    size_delta: Option<i64>,
+}
+
+#[derive(serde::Serialize)]
+struct SourceGrowth {
+    source: String,
+    first: i64,
+    latest: i64,
+    change: i64,
+    per_week: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct StatsHistory {
+    scans: Vec<ScanTrend>,
+    cleanups: database::CleanupTotals,
+    sources: Vec<SourceGrowth>,
+}
+
+pub fn stats_history(db: &Database, count: usize, format: OutputFormat, dates: DateDisplay) -> Result<()> {
+    let scans = database::get_recent_scans(db.conn(), "full", count)?;
+    let cleanups = database::get_cleanup_totals(db.conn())?;
+
+    // Deltas against the previous scan in the window
+    let mut trends = Vec::new();
+    for (idx, scan) in scans.iter().enumerate() {
+        let prev = idx.checked_sub(1).map(|i| &scans[i]);
+        trends.push(ScanTrend {
+            packages_delta: prev.map(|p| scan.packages_found - p.packages_found),
+            size_delta: prev.and_then(|p| Some(scan.total_size_bytes? - p.total_size_bytes?)),
+            scan: scan.clone(),
+        });
+    }
+
+    // Per-source growth between the oldest and newest scan with source counts
+    let with_counts: Vec<_> = scans.iter().filter(|s| !s.source_counts.is_empty()).collect();
+    let mut sources = Vec::new();
+    if let (Some(first), Some(latest)) = (with_counts.first(), with_counts.last()) {
+        let weeks = latest.scanned_at()
+            .zip(first.scanned_at())
+            .map(|(l, f)| (l - f).num_seconds() as f64 / (7.0 * 86400.0))
+            .filter(|w| *w > 0.0);
+
+        let names: std::collections::BTreeSet<_> = first.source_counts.keys()
+            .chain(latest.source_counts.keys())
+            .collect();
+        for name in names {
+            let first_count = first.source_counts.get(name).copied().unwrap_or(0);
+            let latest_count = latest.source_counts.get(name).copied().unwrap_or(0);
+            let change = latest_count - first_count;
+            sources.push(SourceGrowth {
+                source: name.clone(),
+                first: first_count,
+                latest: latest_count,
+                change,
+                per_week: weeks.map(|w| change as f64 / w),
+            });
+        }
+    }
+
+    let history = StatsHistory { scans: trends, cleanups, sources };
+
+    if output::print_serialized(&history, format)? {
+        return Ok(());
+    }
+
+    println!("📈 MacSweep Scan History\n");
+
+    if history.scans.is_empty() {
+        println!("No scans recorded yet. Run {} first.", "macsweep scan".cyan());
+        return Ok(());
+    }
+
+    use comfy_table::{Cell, Attribute};
+
+    let mut table = output::create_table();
+    table.set_header(vec![
+        Cell::new("Scan Date").add_attribute(Attribute::Bold),
+        Cell::new("Packages").add_attribute(Attribute::Bold),
+        Cell::new("Δ").add_attribute(Attribute::Bold),
+        Cell::new("Total Size").add_attribute(Attribute::Bold),
+        Cell::new("Δ Size").add_attribute(Attribute::Bold),
+    ]);
+
+    for trend in &history.scans {
+        table.add_row(vec![
+            Cell::new(trend.scan.scanned_at()
+                .map(|dt| format_date(&dt, dates))
+                .unwrap_or_else(|| trend.scan.scan_date.clone())),
+            Cell::new(trend.scan.packages_found),
+            Cell::new(trend.packages_delta.map(|d| format!("{:+}", d)).unwrap_or_else(|| "-".to_string())),
+            Cell::new(trend.scan.total_size_bytes
+                .map(|s| crate::utils::size::format_size(s.max(0) as u64))
+                .unwrap_or_else(|| "-".to_string())),
+            Cell::new(trend.size_delta.map(crate::utils::size::format_size_delta).unwrap_or_else(|| "-".to_string())),
+        ]);
+    }
+    println!("{}", table);
+
+    if !history.sources.is_empty() {
+        let mut table = output::create_table();
+        table.set_header(vec![
+            Cell::new("Source").add_attribute(Attribute::Bold),
+            Cell::new("First").add_attribute(Attribute::Bold),
+            Cell::new("Latest").add_attribute(Attribute::Bold),
+            Cell::new("Change").add_attribute(Attribute::Bold),
+            Cell::new("Per Week").add_attribute(Attribute::Bold),
+        ]);
+        for growth in &history.sources {
+            table.add_row(vec![
+                Cell::new(&growth.source),
+                Cell::new(growth.first),
+                Cell::new(growth.latest),
+                Cell::new(format!("{:+}", growth.change)),
+                Cell::new(growth.per_week.map(|r| format!("{:+.1}", r)).unwrap_or_else(|| "-".to_string())),
+            ]);
+        }
+        println!("\n{}", "Growth by source:".bold());
+        println!("{}", table);
+    }
+
+    println!("\n{}", "Cleanups:".bold());
+    println!("  Cleanups run: {}", history.cleanups.cleanups.to_string().cyan());
+    println!("  Packages removed: {}", history.cleanups.packages_removed.to_string().cyan());
+    println!("  Space recovered: {}",
+        crate::utils::size::format_size(history.cleanups.space_recovered.max(0) as u64).green().bold());
+
+    Ok(())
+}
+
+/// Show what is installed but not in the Brewfile at `path`, and what it lists that isn't installed
+pub fn compare(db: &Database, path: &std::path::Path, format: OutputFormat) -> Result<()> {
+    let brewfile = crate::analysis::brewfile::Brewfile::load(path)?;
+    let packages = api::load_packages(db)?;
+    if packages.is_empty() {
+        anyhow::bail!("No packages found. Run macsweep scan first.");
+    }
+    let comparison = crate::analysis::brewfile::compare(&brewfile, &packages);
+
+    if output::print_serialized(&comparison, format)? {
+        return Ok(());
+    }
+
+    use comfy_table::{Attribute, Cell};
+
+    println!("📋 Compared with {}: {} entries installed\n", path.display(), comparison.matched.to_string().green());
+
+    if comparison.extra.is_empty() {
+        println!("{}", "Everything installed on request is in the Brewfile. ✨".green());
+    } else {
+        println!("{}", format!("Installed but not in the Brewfile ({}):", comparison.extra.len()).bold());
+        let mut table = output::create_table();
+        table.set_header(vec![
+            Cell::new("Name").add_attribute(Attribute::Bold),
+            Cell::new("Source").add_attribute(Attribute::Bold),
+            Cell::new("Size").add_attribute(Attribute::Bold),
+            Cell::new("Last Used").add_attribute(Attribute::Bold),
+        ]);
+        for package in &comparison.extra {
+            table.add_row(vec![
+                Cell::new(&package.name),
+                Cell::new(format!("{:?}", package.source)),
+                Cell::new(package.size_bytes.map(crate::utils::size::format_size).unwrap_or_else(|| "-".to_string())),
+                Cell::new(package.last_used.map(|d| format_days_ago(days_since(&d))).unwrap_or_else(|| "Never".to_string())),
+            ]);
+        }
+        println!("{}", table);
+    }
+
+    if !comparison.missing.is_empty() {
+        println!("\n{}", format!("In the Brewfile but not installed ({}):", comparison.missing.len()).bold());
+        for entry in &comparison.missing {
+            println!("  {} {}", format!("{:?}", entry.kind).to_lowercase().dimmed(), entry.name.yellow());
+        }
+    }
+
+    if !comparison.extra.is_empty() {
+        status!(
+            "\n💡 Run {} to remove the packages not in the Brewfile",
+            format!("macsweep clean --not-in-brewfile {} --dry-run", path.display()).cyan()
+        );
+    }
+    Ok(())
+}
+
+/// What `macsweep export` writes
+pub enum ExportKind {
+    Manifest(crate::analysis::manifests::ManifestKind),
+    /// A CycloneDX 1.5 JSON inventory of every package, dependencies included
+    Sbom,
+    /// The packages as a property list
+    Plist,
+}
+
+/// Options for `macsweep export`
+pub struct ExportOptions {
+    pub kind: ExportKind,
+    /// Leave out packages recommended for removal that have gone unused for this many days
+    pub exclude_unused: Option<u32>,
+    pub output: Option<PathBuf>,
+    /// Overwrite `output` if it already exists
+    pub force: bool,
+    /// Packages with this tag are never recommended, so never left out
+    pub keep_tag: String,
+    pub thresholds: RecommendationConfig,
+    pub protected_packages: Vec<String>,
+    /// How long brew and `ps` may take while working out the recommendations
+    pub timeouts: CommandTimeouts,
+}
+
+/// Write a manifest to reinstall the recorded packages from
+pub fn export(db: &Database, options: ExportOptions) -> Result<()> {
+    if let Some(ref path) = options.output {
+        if path.exists() && !options.force {
+            anyhow::bail!("{} already exists; use --force to overwrite it", path.display());
+        }
+    }
+
+    let mut packages = api::load_packages(db)?;
+    if packages.is_empty() {
+        anyhow::bail!("No packages found. Run macsweep scan first.");
+    }
+
+    if let Some(days) = options.exclude_unused {
+        let recommendations = load_recommendations(db, &packages, &options.keep_tag, &options.protected_packages, &options.thresholds, &options.timeouts)?;
+        let filter = crate::analysis::recommendations::RecommendationFilter { unused_days: Some(days), ..Default::default() };
+        let unused = filter.apply(recommendations, &packages, chrono::Utc::now());
+        let before = packages.len();
+        packages.retain(|p| !unused.iter().any(|r| r.is_for(p)));
+        status!("Left out {} packages recommended for removal and unused for {}+ days", before - packages.len(), days);
+    }
+
+    let manifest = match options.kind {
+        ExportKind::Manifest(kind) => crate::analysis::manifests::render(kind, &packages).into_bytes(),
+        ExportKind::Sbom => crate::analysis::sbom::render(&packages, chrono::Utc::now())?.into_bytes(),
+        ExportKind::Plist => {
+            let mut plist = Vec::new();
+            output::write_plist(&mut plist, &packages)?;
+            plist
+        }
+    };
+    match options.output {
+        Some(path) => {
+            std::fs::write(&path, manifest).with_context(|| format!("Cannot write {}", path.display()))?;
+            status!("{} Wrote {}", "✓".green(), path.display());
+        }
+        None => {
+            use std::io::Write;
+            std::io::stdout().lock().write_all(&manifest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the Prometheus metrics of the recorded packages to `path` for `export --metrics`,
+/// replacing the file if it exists
+pub fn export_metrics(
+    db: &Database,
+    path: &std::path::Path,
+    keep_tag: &str,
+    protected_packages: &[String],
+    thresholds: &RecommendationConfig,
+    timeouts: &CommandTimeouts,
+) -> Result<()> {
+    if api::load_packages(db)?.is_empty() {
+        anyhow::bail!("No packages found. Run macsweep scan first.");
+    }
+    write_metrics(db, path, keep_tag, protected_packages, thresholds, timeouts)?;
+    status!("{} Wrote {}", "✓".green(), path.display());
+    Ok(())
+}
+
+/// Write the metrics to a temporary file next to `path` and rename it into place, so the
+/// textfile collector never reads a half-written file. The temporary name doesn't end in
+/// `.prom`, which the collector would pick up.
+fn write_metrics(
+    db: &Database,
+    path: &std::path::Path,
+    keep_tag: &str,
+    protected_packages: &[String],
+    thresholds: &RecommendationConfig,
+    timeouts: &CommandTimeouts,
+) -> Result<()> {
+    let packages = api::load_packages(db)?;
+    let kept = api::kept_packages(db, keep_tag)?;
+    let recommendations = api::recommendations_excluding(&packages, thresholds, protected_packages, &kept, timeouts)?;
+    let last_scan = database::get_last_scan(db.conn(), None)?.and_then(|s| s.scanned_at());
+    let metrics = crate::analysis::metrics::render(&packages, &recommendations, last_scan);
+    use std::io::Write;
+
+    let name = path.file_name().with_context(|| format!("{} is not a file path", path.display()))?;
+    let temp = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+    let written = std::fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(metrics.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(e).with_context(|| format!("Cannot write {}", path.display()));
+    }
+    Ok(())
+}
+
+pub fn leftovers(db: &Database, clean: bool, dry_run: bool, yes: bool, format: OutputFormat, delimiter: u8) -> Result<()> {
+    let packages = api::load_packages(db)?;
+
+    if packages.is_empty() {
+        println!("No packages found. Run {} first.", "macsweep scan".cyan());
+        return Ok(());
+    }
+
+    if !packages.iter().any(|p| p.bundle_id.is_some()) {
+        eprintln!("⚠️  No bundle identifiers recorded; run {} for accurate matching", "macsweep scan".cyan());
+    }
+
+    let leftovers = crate::analysis::leftovers::find_leftovers(&packages)?;
 
-    // Create backup before cleanup
-    let backup_manifest_path = if !dry_run {
-        println!("\n{}", "Creating backup...".bold());
-        let packages_to_remove: Vec<_> = recommendations.iter()
-            .filter_map(|r| packages.iter().find(|p| p.name == r.package))
-            .cloned()
-            .collect();
+    if output::print_serialized(&leftovers, format)? {
+        return Ok(());
+    }
 
-        match crate::cleanup::backup::create_backup(&packages_to_remove) {
-            Ok(path) => Some(path),
-            Err(e) => {
-                eprintln!("⚠️  Warning: Failed to create backup: {}", e);
-                eprintln!("   Proceeding without backup...");
-                None
-            }
+    if format.is_delimited() {
+        let mut wtr = output::csv_writer(std::io::stdout(), delimiter);
+        wtr.write_record(["name", "location", "bundle_id", "size_bytes", "path"])?;
+        for leftover in &leftovers {
+            wtr.write_record([
+                &leftover.name,
+                &leftover.location,
+                leftover.bundle_id.as_deref().unwrap_or(""),
+                &leftover.size_bytes.to_string(),
+                &leftover.path.to_string_lossy(),
+            ])?;
         }
-    } else {
-        None
-    };
+        wtr.flush()?;
+        return Ok(());
+    }
 
-    // Perform cleanup
-    println!("\n{}", "Starting cleanup...".bold());
+    println!("🗂️  Leftover Application Data\n");
 
-    use indicatif::{ProgressBar, ProgressStyle};
+    if leftovers.is_empty() {
+        println!("{}", "No leftover application data found. ✨".green());
+        return Ok(());
+    }
 
-    let pb = ProgressBar::new(recommendations.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("  [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-            .unwrap()
-            .progress_chars("━━╺")
-    );
+    use comfy_table::{Cell, Color, Attribute, ContentArrangement};
 
-    let mut removed_count = 0;
-    let mut failed_count = 0;
-    let mut total_recovered: u64 = 0;
+    let mut table = output::create_table();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Name").add_attribute(Attribute::Bold),
+        Cell::new("Location").add_attribute(Attribute::Bold),
+        Cell::new("Size").add_attribute(Attribute::Bold),
+        Cell::new("Path").add_attribute(Attribute::Bold),
+    ]);
 
-    for rec in &recommendations {
-        pb.set_message(rec.package.clone());
+    for leftover in &leftovers {
+        table.add_row(vec![
+            Cell::new(&leftover.name),
+            Cell::new(&leftover.location).fg(Color::Cyan),
+            Cell::new(crate::utils::size::format_size(leftover.size_bytes)),
+            Cell::new(leftover.path.display()),
+        ]);
+    }
 
-        // Find the package
-        if let Some(package) = packages.iter().find(|p| p.name == rec.package) {
-            match crate::cleanup::executor::remove_package(package, dry_run) {
-                Ok(true) => {
-                    removed_count += 1;
-                    total_recovered += rec.size_recoverable;
-                }
-                Ok(false) => {
-                    failed_count += 1;
-                }
-                Err(e) => {
-                    pb.println(format!("  ✗ Error removing {}: {}", package.name, e));
-                    failed_count += 1;
-                }
-            }
-        }
+    let total_size: u64 = leftovers.iter().map(|l| l.size_bytes).sum();
+    println!("{}", table);
+    println!("\nTotal: {} items", leftovers.len().to_string().cyan().bold());
+    println!("Potential space savings: {}", crate::utils::size::format_size(total_size).green().bold());
 
-        pb.inc(1);
+    if !clean {
+        status!("\n💡 Run {} to move leftovers to the Trash", "macsweep leftovers --clean".cyan());
+        return Ok(());
     }
 
-    pb.finish_and_clear();
-
-    // Summary
-    println!("\n{}", "Cleanup Summary:".bold());
     if dry_run {
-        println!("  Would remove: {}", removed_count.to_string().green());
-        println!("  Would recover: {}", crate::utils::size::format_size(total_recovered).green().bold());
+        println!("\n{}", "[DRY RUN MODE] - Nothing will be moved to the Trash".yellow().bold());
+        return Ok(());
+    }
+
+    // Leftovers are Review severity: select them explicitly unless --yes was given
+    let selected: Vec<&crate::analysis::leftovers::Leftover> = if yes {
+        leftovers.iter().collect()
     } else {
-        println!("  Successfully removed: {}", removed_count.to_string().green());
-        if failed_count > 0 {
-            println!("  Failed: {}", failed_count.to_string().red());
-        }
-        println!("  Space recovered: {}", crate::utils::size::format_size(total_recovered).green().bold());
+        use dialoguer::MultiSelect;
 
-        // Record cleanup in database
-        if removed_count > 0 && backup_manifest_path.is_some() {
-            if let Err(e) = database::insert_cleanup(
-                db.conn(),
-                backup_manifest_path.as_ref().unwrap(),
-                removed_count as i64,
-                total_recovered as i64,
-            ) {
-                eprintln!("Warning: Failed to record cleanup in database: {}", e);
-            }
-        }
+        println!("\n{}", "Select leftovers to move to the Trash (Space to select, Enter to confirm):".bold());
+
+        let items: Vec<String> = leftovers.iter().map(|l| {
+            let rec = l.to_recommendation();
+            format!("⚠ {} - {} ({})", rec.package, rec.reason, crate::utils::size::format_size(rec.size_recoverable))
+        }).collect();
+
+        let picked = MultiSelect::with_theme(output::prompt_theme().as_ref())
+            .items(&items)
+            .interact()?;
 
-        // Show undo instructions
-        if removed_count > 0 {
-            println!("\n💡 Run {} to update the database", "macsweep scan".cyan());
-            if backup_manifest_path.is_some() {
-                println!("💡 Run {} to undo this cleanup", "macsweep undo".cyan());
+        picked.into_iter().map(|idx| &leftovers[idx]).collect()
+    };
+
+    if selected.is_empty() {
+        println!("Nothing selected. Cleanup cancelled.");
+        return Ok(());
+    }
+
+    let mut moved_count = 0;
+    let mut recovered: u64 = 0;
+
+    for leftover in selected {
+        match crate::cleanup::executor::move_to_trash(&leftover.path) {
+            Ok(_) => {
+                moved_count += 1;
+                recovered += leftover.size_bytes;
             }
+            Err(e) => eprintln!("  ✗ Failed to move {} to the Trash: {}", leftover.path.display(), error::chain(&e)),
         }
     }
 
+    println!("\n{}", "Cleanup Summary:".bold());
+    println!("  Moved to Trash: {}", moved_count.to_string().green());
+    println!("  Space recovered: {}", crate::utils::size::format_size(recovered).green().bold());
+
     Ok(())
 }
 
-pub fn history(package: &str) -> Result<()> {
-    println!("📊 Usage history for: {}", package);
-    // TODO: Implement history logic
-    Ok(())
+/// Options for `macsweep deps`
+pub struct DepsOptions {
+    /// A `--source` value, for packages installed from more than one source
+    pub source: Option<String>,
+    pub format: crate::analysis::graph::GraphFormat,
+    /// Levels of dependencies to follow; all of them when unset
+    pub depth: Option<usize>,
+    /// Show what depends on the package instead
+    pub reverse: bool,
+    pub output: Option<PathBuf>,
+    /// Overwrite `output` if it already exists
+    pub force: bool,
 }
 
-pub fn stats() -> Result<()> {
-    println!("📈 MacSweep Statistics\n");
+/// Write the dependency graph rooted at `package` as DOT or Mermaid
+pub fn deps(db: &Database, package: &str, options: DepsOptions) -> Result<()> {
+    if let Some(ref path) = options.output {
+        if path.exists() && !options.force {
+            anyhow::bail!("{} already exists; use --force to overwrite it", path.display());
+        }
+    }
 
-    // Load packages from database
-    let db = Database::default()?;
-    db.init()?;
+    let packages = api::load_packages(db)?;
+    let sources = options.source.as_deref().map(|s| ScannerRegistry::default().sources(s)).transpose()?;
+    let matches: Vec<_> = packages
+        .iter()
+        .filter(|p| p.name == package)
+        .filter(|p| sources.as_ref().is_none_or(|sources| sources.contains(&p.source)))
+        .collect();
 
-    let packages = database::get_packages(db.conn())?;
+    let root = match matches.as_slice() {
+        [] => anyhow::bail!("Package {} not found. Run macsweep scan first.", package),
+        [root] => *root,
+        several => {
+            let sources: Vec<_> = several.iter().map(|p| format!("{:?}", p.source)).collect();
+            anyhow::bail!("{} is installed from several sources ({}); pick one with --source", package, sources.join(", "));
+        }
+    };
 
-    if packages.is_empty() {
-        println!("No packages found. Run {} first.", "macsweep scan".cyan());
-        return Ok(());
+    let graph = crate::analysis::graph::DependencyGraph::build(&packages, root, options.depth, options.reverse);
+    let rendered = graph.render(options.format);
+
+    match options.output {
+        Some(path) => {
+            std::fs::write(&path, rendered).with_context(|| format!("Cannot write {}", path.display()))?;
+            status!("{} Wrote {} packages to {}", "✓".green(), graph.nodes.len(), path.display());
+        }
+        None => print!("{}", rendered),
     }
+    Ok(())
+}
 
-    // Overall statistics
-    println!("{}",  "═══ Package Overview ═══".cyan().bold());
-    println!("Total packages: {}", packages.len().to_string().yellow().bold());
+/// What `macsweep which` found out about a file
+#[derive(serde::Serialize)]
+struct WhichResult<'a> {
+    path: PathBuf,
+    /// Where the path leads, when it is a symlink
+    resolved: Option<PathBuf>,
+    /// None when no package claims the file
+    package: Option<ClaimingPackage<'a>>,
+    /// The file's own size, or the directory's for an app bundle
+    size_bytes: Option<u64>,
+    modified: Option<chrono::DateTime<chrono::Utc>>,
+}
 
-    let total_size: u64 = packages.iter()
-        .filter_map(|p| p.size_bytes)
-        .sum();
-    println!("Total size: {}", crate::utils::size::format_size(total_size).yellow().bold());
+#[derive(serde::Serialize)]
+struct ClaimingPackage<'a> {
+    name: String,
+    source: PackageSource,
+    matched_by: crate::analysis::owners::OwnerMatch,
+    /// Whether a scan has recorded the package; only its install location names it otherwise
+    recorded: bool,
+    version: Option<&'a str>,
+    size_bytes: Option<u64>,
+    last_used: Option<chrono::DateTime<chrono::Utc>>,
+}
 
-    // Breakdown by source
-    let homebrew_count = packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Homebrew))
-        .count();
-    let casks_count = packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::HomebrewCask))
-        .count();
-    let apps_count = packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Applications))
-        .count();
-    let npm_count = packages.iter()
-        .filter(|p| matches!(p.source, crate::scanner::PackageSource::Npm))
-        .count();
+/// Tell which package installed `target`, a program on PATH or a path; when none did, show the
+/// file's size and modification time instead
+pub fn which(db: &Database, target: &str, format: OutputFormat, dates: DateDisplay) -> Result<()> {
+    let path = if target.contains('/') {
+        std::env::current_dir()?.join(target)
+    } else {
+        which::which(target).with_context(|| format!("{} is not on PATH", target))?
+    };
+    let metadata = std::fs::symlink_metadata(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+    let resolved = path.canonicalize().ok().filter(|resolved| *resolved != path);
+
+    let packages = api::load_packages(db)?;
+    let owner = crate::analysis::owners::find_owner(&path, &packages);
+    // Sizes follow the link, to the file that takes up the space
+    let (size_bytes, modified) = match std::fs::metadata(&path) {
+        Ok(target) if target.is_dir() => (crate::utils::size::calculate_directory_size(&path).ok(), target.modified().ok()),
+        Ok(target) => (Some(target.len()), target.modified().ok()),
+        Err(_) => (None, metadata.modified().ok()),
+    };
+    let result = WhichResult {
+        resolved,
+        package: owner.map(|owner| ClaimingPackage {
+            name: owner.name,
+            source: owner.source,
+            matched_by: owner.matched_by,
+            recorded: owner.package.is_some(),
+            version: owner.package.and_then(|p| p.version.as_deref()),
+            size_bytes: owner.package.and_then(|p| p.size_bytes),
+            last_used: owner.package.and_then(|p| p.last_used),
+        }),
+        size_bytes,
+        modified: modified.map(chrono::DateTime::<chrono::Utc>::from),
+        path,
+    };
 
-    println!("\n{}",  "Source breakdown:".bold());
-    if homebrew_count > 0 {
-        println!("  Homebrew formulae: {}", homebrew_count.to_string().cyan());
+    if output::print_serialized(&result, format)? {
+        return Ok(());
     }
-    if casks_count > 0 {
-        println!("  Homebrew casks: {}", casks_count.to_string().cyan());
+
+    match &result.resolved {
+        Some(resolved) => println!("🔎 {} → {}", result.path.display().to_string().cyan().bold(), resolved.display()),
+        None => println!("🔎 {}", result.path.display().to_string().cyan().bold()),
     }
-    if apps_count > 0 {
-        println!("  Applications: {}", apps_count.to_string().cyan());
+    let Some(package) = &result.package else {
+        println!("  {}", "Not claimed by any package macsweep knows about".yellow());
+        if let Some(size_bytes) = result.size_bytes {
+            println!("  Size: {}", crate::utils::size::format_size(size_bytes));
+        }
+        if let Some(modified) = result.modified {
+            println!("  Modified: {}", format_date(&modified, dates));
+        }
+        return Ok(());
+    };
+
+    println!("  Package: {} ({:?}) - {}", package.name.green().bold(), package.source, package.matched_by.describe());
+    if !package.recorded {
+        println!("  {}", "Not recorded by any scan yet; run macsweep scan for its version, size and usage".dimmed());
+        return Ok(());
     }
-    if npm_count > 0 {
-        println!("  npm packages: {}", npm_count.to_string().cyan());
+    println!("  Version: {}", package.version.unwrap_or("-"));
+    println!("  Size: {}", package.size_bytes.map(crate::utils::size::format_size).unwrap_or_else(|| "-".to_string()));
+    match package.last_used {
+        Some(last_used) => println!("  Last used: {}", format_date(&last_used, dates)),
+        None => println!("  Last used: Never"),
     }
+    Ok(())
+}
 
-    // Usage statistics
-    let used_count = packages.iter()
-        .filter(|p| p.last_used.is_some())
-        .count();
-    let never_used_count = packages.len() - used_count;
+/// Show whether the bin directories' volumes record access times, and find symlinks in them
+/// whose targets are gone, removing them with `fix`
+pub fn doctor(db: &Database, fix: bool, dry_run: bool, yes: bool, format: OutputFormat) -> Result<Outcome> {
+    if format.is_structured() && fix && !dry_run {
+        anyhow::bail!("Structured output for doctor --fix requires --dry-run");
+    }
 
-    println!("\n{}", "═══ Usage Statistics ═══".cyan().bold());
-    println!("Packages with usage data: {}", used_count.to_string().green());
-    println!("Packages without usage data: {}", never_used_count.to_string().yellow());
+    let packages = api::load_packages(db)?;
+    let links = crate::analysis::broken_links::find_broken_links(&packages);
 
-    // Generate cleanup recommendations
-    println!("\n{}", "═══ Cleanup Recommendations ═══".cyan().bold());
+    if output::print_serialized(&links, format)? {
+        return Ok(if links.is_empty() { Outcome::NothingToDo } else { Outcome::Success });
+    }
+
+    // Access times only count as usage on volumes that update them
+    println!("🩺 Access Times\n");
+    for dir in crate::analysis::broken_links::bin_dirs() {
+        let Some(policy) = crate::usage::atime::policy_for(&dir) else {
+            continue;
+        };
+        let described = match policy {
+            crate::usage::atime::AtimePolicy::Disabled => policy.describe().yellow().to_string(),
+            _ => policy.describe().to_string(),
+        };
+        println!("  {}: {}", dir.display(), described);
+    }
+    println!();
 
-    let recommendations = crate::analysis::recommendations::generate_recommendations(&packages)?;
+    println!("🩺 Broken Symlinks\n");
 
-    if recommendations.is_empty() {
-        println!("{}", "No cleanup recommendations at this time. ✨".green());
-        return Ok(());
+    if links.is_empty() {
+        println!("{}", "No broken symlinks found. ✨".green());
+        return Ok(Outcome::NothingToDo);
     }
 
-    let total_recoverable: u64 = recommendations.iter()
-        .map(|r| r.size_recoverable)
-        .sum();
+    use comfy_table::{Cell, Color, Attribute, ContentArrangement};
 
-    println!("Found {} cleanup opportunities", recommendations.len().to_string().yellow().bold());
-    println!("Potential space savings: {}\n", crate::utils::size::format_size(total_recoverable).green().bold());
+    let mut table = output::create_table();
+    table.set_content_arrangement(ContentArrangement::Dynamic);
+    table.set_header(vec![
+        Cell::new("Link").add_attribute(Attribute::Bold),
+        Cell::new("Missing target").add_attribute(Attribute::Bold),
+        Cell::new("Package").add_attribute(Attribute::Bold),
+    ]);
+    for link in &links {
+        let package = match (&link.package, &link.source) {
+            (Some(name), Some(source)) => format!("{} ({:?})", name, source),
+            _ => "-".to_string(),
+        };
+        table.add_row(vec![
+            Cell::new(link.path.display()),
+            Cell::new(link.target.display()).fg(Color::Red),
+            Cell::new(package),
+        ]);
+    }
+    println!("{}", table);
+    println!("\nTotal: {} broken links", links.len().to_string().cyan().bold());
 
-    // Group by severity
-    let safe_recs: Vec<_> = recommendations.iter()
-        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Safe)
-        .collect();
-    let review_recs: Vec<_> = recommendations.iter()
-        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Review)
-        .collect();
-    let warning_recs: Vec<_> = recommendations.iter()
-        .filter(|r| r.severity == crate::analysis::recommendations::RecommendationSeverity::Warning)
-        .collect();
+    if !fix {
+        status!("\n💡 Run {} to remove them (undo puts them back)", "macsweep doctor --fix".cyan());
+        return Ok(Outcome::Success);
+    }
 
-    if !safe_recs.is_empty() {
-        println!("{} ({})", "Safe to Remove:".green().bold(), safe_recs.len());
-        for rec in safe_recs.iter().take(5) {
-            let size_str = crate::utils::size::format_size(rec.size_recoverable);
-            println!("  • {} - {} ({})", rec.package.cyan(), rec.reason, size_str.yellow());
-        }
-        if safe_recs.len() > 5 {
-            println!("  ... and {} more", safe_recs.len() - 5);
-        }
-        println!();
+    if dry_run {
+        println!("\n{}", "[DRY RUN MODE] - No links will be removed".yellow().bold());
+        return Ok(Outcome::Success);
     }
 
-    if !review_recs.is_empty() {
-        println!("{} ({})", "Review Recommended:".yellow().bold(), review_recs.len());
-        for rec in review_recs.iter().take(5) {
-            let size_str = crate::utils::size::format_size(rec.size_recoverable);
-            println!("  • {} - {} ({})", rec.package.cyan(), rec.reason, size_str.yellow());
-        }
-        if review_recs.len() > 5 {
-            println!("  ... and {} more", review_recs.len() - 5);
+    if !yes {
+        let confirmed = dialoguer::Confirm::with_theme(output::prompt_theme().as_ref())
+            .with_prompt(format!("Remove these {} links? Their targets are already gone", links.len()))
+            .default(false)
+            .interact()?;
+        if !confirmed {
+            println!("Cleanup cancelled.");
+            return Ok(Outcome::Cancelled);
         }
-        println!();
     }
 
-    if !warning_recs.is_empty() {
-        println!("{} ({})", "Consider Checking:".cyan().bold(), warning_recs.len());
-        for rec in warning_recs.iter().take(5) {
-            let size_str = crate::utils::size::format_size(rec.size_recoverable);
-            println!("  • {} - {} ({})", rec.package, rec.reason, size_str.yellow());
-        }
-        if warning_recs.len() > 5 {
-            println!("  ... and {} more", warning_recs.len() - 5);
+    let removed: Vec<_> = links
+        .iter()
+        .map(|l| crate::cleanup::backup::RemovedLink {
+            path: l.path.to_string_lossy().to_string(),
+            target: l.target.to_string_lossy().to_string(),
+        })
+        .collect();
+    let manifest_path = crate::cleanup::backup::create_link_backup(&removed)?;
+    print_backup_created(&manifest_path);
+
+    let mut kept = Vec::new();
+    for (link, backed_up) in links.iter().zip(&removed) {
+        if let Err(e) = crate::cleanup::executor::remove_broken_link(&link.path) {
+            eprintln!("  ✗ {}", error::chain(&e));
+            kept.push(backed_up.path.as_str());
         }
     }
+    let failed = kept.len();
+    // Links still in place come out of the backup, so undo doesn't try to recreate them
+    if !kept.is_empty() {
+        crate::cleanup::backup::remove_links_from_backup(&manifest_path, &kept)?;
+    }
 
-    println!("\n💡 Run {} to see orphaned packages", "macsweep list --orphaned".cyan());
-    println!("💡 Run {} to see unused packages", "macsweep list --unused 90".cyan());
-
-    Ok(())
-}
-
-pub fn export(output: Option<PathBuf>) -> Result<()> {
-    println!("💾 Exporting data to: {:?}", output.unwrap_or_else(|| PathBuf::from("stdout")));
-    // TODO: Implement export logic
-    Ok(())
+    println!("\n{}", "Cleanup Summary:".bold());
+    println!("  Links removed: {}", (links.len() - failed).to_string().green());
+    if failed > 0 {
+        println!("  Failed: {}", failed.to_string().red());
+        return Ok(Outcome::PartialFailure);
+    }
+    Ok(Outcome::Success)
 }
 
-pub fn undo(backup_id: Option<String>, list: bool) -> Result<()> {
+pub fn undo(
+    db: &Database,
+    backup_id: Option<String>,
+    list: bool,
+    verify: bool,
+    interactive: bool,
+    mut options: crate::cleanup::backup::RestoreOptions,
+) -> Result<Outcome> {
     if list {
         // List available backups
         println!("📋 Available Backups:\n");
@@ -807,15 +2932,35 @@ pub fn undo(backup_id: Option<String>, list: bool) -> Result<()> {
 
         if backups.is_empty() {
             println!("No backups found.");
-            return Ok(());
+            return Ok(Outcome::NothingToDo);
         }
 
+        let backup_dir = crate::cleanup::backup::backup_dir()?;
         for (idx, backup) in backups.iter().enumerate() {
-            println!("  {}. {}", idx + 1, backup.cyan());
+            let manifest_path = backup_dir.join(format!("{}.json", backup));
+            let items = match database::get_cleanup_id_by_manifest(db.conn(), &manifest_path.to_string_lossy())? {
+                Some(id) => database::get_cleanup_items(db.conn(), id)?,
+                None => Vec::new(),
+            };
+
+            let failed: Vec<_> = items.iter().filter(|i| i.failed() || i.unverified()).collect();
+            let removed = items.iter().filter(|i| i.removed()).count();
+            let pending = items.iter().filter(|i| i.pending()).count();
+            if items.is_empty() {
+                println!("  {}. {}", idx + 1, backup.cyan());
+            } else if pending > 0 {
+                println!("  {}. {} ({} removed, {} failed, {} left by interruption)", idx + 1, backup.cyan(), removed, failed.len(), pending);
+            } else {
+                println!("  {}. {} ({} removed, {} failed)", idx + 1, backup.cyan(), removed, failed.len());
+            }
+            for item in failed {
+                let error = item.error_message.as_deref().and_then(|e| e.lines().next()).unwrap_or("unknown error");
+                println!("     {} {} ({}): {}", "✗".red(), item.package, item.source, error.dimmed());
+            }
         }
 
         println!("\nRestore a backup with: {}", "macsweep undo <backup_id>".cyan());
-        return Ok(());
+        return Ok(Outcome::Success);
     }
 
     // Restore from backup
@@ -827,15 +2972,260 @@ pub fn undo(backup_id: Option<String>, list: bool) -> Result<()> {
 
         if backups.is_empty() {
             println!("No backups found to restore.");
-            return Ok(());
+            return Ok(Outcome::NothingToDo);
         }
 
         let most_recent = &backups[0];
-        println!("No backup ID specified, using most recent: {}", most_recent.cyan());
+        status!("No backup ID specified, using most recent: {}", most_recent.cyan());
         most_recent.clone()
     };
 
-    crate::cleanup::backup::restore_backup(&backup_to_restore)?;
+    if verify {
+        let verification = crate::cleanup::backup::verify_backup(&backup_to_restore)?;
+        print_verification(&verification);
+        return Ok(Outcome::Success);
+    }
+
+    if interactive {
+        use dialoguer::MultiSelect;
+
+        let entries = crate::cleanup::backup::backup_packages(&backup_to_restore)?;
+        let items: Vec<String> = entries.iter().map(|p| match p.version {
+            Some(ref version) => format!("{} {} ({})", p.name, version, p.source),
+            None => format!("{} ({})", p.name, p.source),
+        }).collect();
+
+        println!("{}", "Select packages to restore (Space to select, Enter to confirm):".bold());
+        let picked = MultiSelect::with_theme(output::prompt_theme().as_ref())
+            .items(&items)
+            .interact()?;
+        if picked.is_empty() {
+            println!("No packages selected. Undo cancelled.");
+            return Ok(Outcome::Cancelled);
+        }
+        options.packages = Some(picked.into_iter().map(|idx| entries[idx].name.clone()).collect());
+    }
+
+    let summary = crate::cleanup::backup::restore_backup(&backup_to_restore, &options, |event| {
+        // Record each package as soon as it is back, so `list` is right even if a later one hangs
+        if let crate::cleanup::backup::RestoreEvent::PackageFinished {
+            outcome: crate::cleanup::backup::RestoreOutcome::Restored { reinstalled, .. },
+            ..
+        } = &event
+        {
+            if let Err(e) = database::record_reinstalled_package(db.conn(), &reinstalled.name, &reinstalled.source, reinstalled.version.as_deref()) {
+                tracing::warn!(package = %reinstalled.name, error = %e, "Failed to record reinstalled package in database");
+            }
+        }
+        show_restore_event(event, options.dry_run);
+    })?;
+    print_restore_summary(&summary, options.dry_run);
+
+    if options.dry_run {
+        return Ok(if summary.restored > 0 { Outcome::Success } else { Outcome::NothingToDo });
+    }
+
+    // Installer output goes to the log of the cleanup being undone
+    if !summary.commands.is_empty() {
+        match crate::cleanup::log::CleanupLog::for_backup(Some(&summary.manifest_path)) {
+            Ok(mut log) => {
+                for (package, command) in &summary.commands {
+                    if let Err(e) = log.record(package, command) {
+                        eprintln!("⚠️  Warning: {}", error::chain(&e));
+                        break;
+                    }
+                }
+                if summary.failed > 0 {
+                    status!("\n📄 Full installer output: {}", log.path().display());
+                }
+            }
+            Err(e) => eprintln!("⚠️  Warning: {}", error::chain(&e)),
+        }
+    }
+
+    // Record the outcome on the cleanup this backup belongs to
+    if let Err(e) = database::update_cleanup_restore_status(db.conn(), &summary.manifest_path, summary.outcome()) {
+        tracing::warn!(error = %e, "Failed to record restore in database");
+    }
+
+    if summary.succeeded() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::PartialFailure)
+    }
+}
+
+/// Print a verification report
+fn print_verification(verification: &crate::cleanup::backup::BackupVerification) {
+    use crate::cleanup::backup::RestoreStatus;
+
+    println!("🔍 Verifying backup: {}", verification.backup_id);
+    println!("   Created: {}", verification.created_at);
+    println!("   Packages: {}", verification.packages.len());
+    if !verification.links.is_empty() {
+        println!("   Links: {}", verification.links.len());
+    }
+    println!();
+
+    for entry in &verification.packages {
+        let status = match &entry.status {
+            RestoreStatus::Restorable => "✓ restorable".to_string(),
+            RestoreStatus::AlreadyInstalled => "⚠ already installed".to_string(),
+            RestoreStatus::Impossible(reason) => format!("✗ impossible: {}", reason),
+        };
+        println!("  {} ({}) - {}", entry.package.name, entry.package.source, status);
+    }
+    for link in &verification.links {
+        let status = if link.path_in_use() { "⚠ path in use" } else { "✓ restorable" };
+        println!("  {} -> {} - {}", link.path, link.target, status);
+    }
+
+    println!("\n📊 Verification Summary:");
+    println!("   Restorable: {}", verification.count(|s| *s == RestoreStatus::Restorable));
+    println!("   Already installed: {}", verification.count(|s| *s == RestoreStatus::AlreadyInstalled));
+    println!("   Impossible: {}", verification.count(|s| matches!(s, RestoreStatus::Impossible(_))));
+}
+
+/// Show what an undo is doing: a line per package and link, ended once it is restored
+fn show_restore_event(event: crate::cleanup::backup::RestoreEvent, dry_run: bool) {
+    use crate::cleanup::backup::{LinkOutcome, RestoreEvent, RestoreOutcome};
+
+    match event {
+        RestoreEvent::Started { verification, selected, links } => {
+            if dry_run {
+                println!("🔍 Would restore from backup: {}", verification.backup_id);
+            } else {
+                println!("🔄 Restoring from backup: {}", verification.backup_id);
+            }
+            println!("   Created: {}", verification.created_at);
+            if selected < verification.packages.len() {
+                println!("   Packages: {} of {}", selected, verification.packages.len());
+            } else {
+                println!("   Packages: {}", selected);
+            }
+            if links > 0 {
+                println!("   Links: {}", links);
+            }
+            println!();
+        }
+        RestoreEvent::PackageStarted { package } => {
+            let version = package.version.as_deref().map(|v| format!(" {}", v)).unwrap_or_default();
+            if dry_run {
+                print!("  {}{} ({}): ", package.name, version, package.source);
+            } else {
+                print!("  Restoring {}{} ({})... ", package.name, version, package.source);
+            }
+        }
+        RestoreEvent::PackageFinished { outcome, .. } => match outcome {
+            RestoreOutcome::Skipped(reason) => println!("✗ Skipped: {}", reason),
+            RestoreOutcome::AlreadyInstalled => println!("⚠ Already installed"),
+            RestoreOutcome::WouldRestore(method) => println!("{}", method),
+            RestoreOutcome::Restored { reinstalled, elapsed, warnings } => {
+                let version = reinstalled.version.as_deref().unwrap_or("unknown version");
+                println!("✓ {} in {:.1}s", version, elapsed.as_secs_f64());
+                for warning in warnings {
+                    println!("    ⚠️  {}", warning);
+                }
+            }
+            RestoreOutcome::InstallFailed { elapsed, error: Some(error) } => {
+                println!("✗ Install command failed after {:.1}s: {}", elapsed.as_secs_f64(), error)
+            }
+            RestoreOutcome::InstallFailed { elapsed, error: None } => {
+                println!("✗ Install command failed after {:.1}s", elapsed.as_secs_f64())
+            }
+            RestoreOutcome::Failed { elapsed, error } => println!("✗ {} after {:.1}s", error, elapsed.as_secs_f64()),
+        },
+        RestoreEvent::LinkFinished { link, outcome } => {
+            let status = match outcome {
+                LinkOutcome::PathInUse => "⚠ Path in use".to_string(),
+                LinkOutcome::WouldRecreate => "ln -s".to_string(),
+                LinkOutcome::Recreated => "✓ Recreated".to_string(),
+                LinkOutcome::Failed(error) => format!("✗ {}", error),
+            };
+            println!("  {} -> {}: {}", link.path, link.target, status);
+        }
+    }
+}
+
+fn print_restore_summary(summary: &crate::cleanup::backup::RestoreSummary, dry_run: bool) {
+    println!("\n📊 Restore Summary:");
+    if dry_run {
+        println!("   Would restore: {}", summary.restored);
+    } else {
+        println!("   Restored: {}", summary.restored);
+    }
+    if summary.already_installed > 0 {
+        println!("   Already installed: {}", summary.already_installed);
+    }
+    if summary.skipped > 0 {
+        println!("   Skipped: {}", summary.skipped);
+    }
+    if summary.failed > 0 {
+        println!("   Failed: {}", summary.failed);
+    }
+    if summary.not_selected > 0 {
+        println!("   Not selected: {}", summary.not_selected);
+    }
+}
 
+pub fn generate_man(dir: &std::path::Path) -> Result<()> {
+    use clap::CommandFactory;
+
+    std::fs::create_dir_all(dir)?;
+    clap_mangen::generate_to(super::Cli::command(), dir)?;
+
+    println!("📖 Man pages written to {}", dir.display());
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::recommendations::{Recommendation, RecommendationSeverity};
+    use crate::scanner::{Package, PackageSource};
+
+    fn recommendation(name: &str, source: PackageSource, size: u64) -> Recommendation {
+        Recommendation {
+            package: name.to_string(),
+            source: Some(source),
+            reason: "Never used".to_string(),
+            severity: RecommendationSeverity::Review,
+            size_recoverable: size,
+            action: CleanupAction::Remove,
+        }
+    }
+
+    #[test]
+    fn test_picked_sources_packages_can_be_deselected() {
+        let recommendations = [
+            recommendation("left-pad", PackageSource::Npm, 4096),
+            recommendation("is-odd", PackageSource::Npm, 2048),
+            recommendation("httpie", PackageSource::Pipx, 9_000_000),
+        ];
+        let packages: Vec<_> = recommendations.iter().map(|r| Package::new(r.package.clone(), r.source.clone().unwrap())).collect();
+        // Pipx, then npm's left-pad and is-odd
+        let groups = group_plan(&recommendations, &packages);
+
+        // Picking all of npm, then unchecking is-odd
+        let mut prompts = Vec::new();
+        let chosen = pick_plan_entries(&groups, &recommendations, |items, checked| {
+            prompts.push((items.len(), checked.iter().all(|&c| c)));
+            Ok(match prompts.len() {
+                1 => vec![2],
+                _ => vec![0],
+            })
+        })
+        .unwrap();
+        assert_eq!(chosen, [0]);
+        assert_eq!(prompts, [(5, false), (2, true)]);
+
+        // Single packages are taken as picked, without a second prompt
+        let mut prompts = 0;
+        let chosen = pick_plan_entries(&groups, &recommendations, |_, _| {
+            prompts += 1;
+            Ok(vec![1, 4])
+        })
+        .unwrap();
+        assert_eq!((chosen, prompts), (vec![1, 2], 1));
+    }
+}
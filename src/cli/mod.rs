@@ -1,15 +1,50 @@
 // CLI module - handles command line interface
+
+/// Print a status line to stderr unless `--quiet` was given
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::cli::output::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Like `status!` but without a trailing newline
+macro_rules! status_inline {
+    ($($arg:tt)*) => {
+        if !$crate::cli::output::is_quiet() {
+            eprint!($($arg)*);
+        }
+    };
+}
+
 pub mod commands;
 pub mod output;
 
 use anyhow::Result;
 use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use crate::analysis::graph::GraphFormat;
+use crate::analysis::recommendations::{RecommendationConfig, RecommendationFilter, RecommendationSeverity};
+use crate::cleanup::backup::RestoreOptions;
+use crate::config::Config;
+use crate::storage::Database;
+use crate::scanner::Architecture;
+use crate::scanner::registry::ScannerRegistry;
+use crate::utils::date::DateDisplay;
 
 #[derive(Parser)]
 #[command(name = "macsweep")]
 #[command(about = "Mac Package Hygiene Tool - Find and clean unused packages")]
 #[command(version)]
+#[command(after_help = "\
+Exit codes:
+  0  Success
+  1  Unexpected error
+  2  Partial failure (some packages could not be scanned, removed or restored)
+  3  Nothing to do (no packages in the database, no recommendations, no backups,
+     or a scan skipped by --if-stale)
+  4  Cancelled at the confirmation prompt")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
@@ -18,34 +53,142 @@ pub struct Cli {
     #[arg(long, default_value = "table")]
     pub format: OutputFormat,
 
-    /// Verbose output
-    #[arg(short, long)]
-    pub verbose: bool,
+    /// Field separator of --format csv, e.g. ';' or tab (default: ',')
+    #[arg(long, global = true, value_name = "CHAR", value_parser = output::parse_delimiter)]
+    pub delimiter: Option<u8>,
+
+    /// Write --format plist and export --plist as a binary property list instead of XML
+    #[arg(long, global = true)]
+    pub binary_plist: bool,
+
+    /// Log more: -v for what each step is doing, -vv for debug details and how long each
+    /// scanner and the usage lookups took (RUST_LOG overrides it)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Suppress progress bars and status messages
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Disable colored output (also honors NO_COLOR and non-terminal stdout)
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// How dates are shown in tables: absolute, relative or both [default: relative, or
+    /// `dates` in the config file]
+    #[arg(long, global = true, value_enum)]
+    pub dates: Option<DateDisplay>,
+
+    /// Packages carrying this tag are never recommended for removal
+    #[arg(long, global = true, default_value = crate::api::KEEP_TAG)]
+    pub keep_tag: String,
+
+    /// Warn in list and stats when the last scan is at least this many days old
+    #[arg(long, global = true, value_name = "DAYS", default_value_t = 7)]
+    pub stale_after: u32,
+
+    /// Override recommendation thresholds for this run, e.g. warning=60,review=180,safe=365,large=500,size-only=false,intel-only=true
+    /// (days unused; large is in MB)
+    #[arg(long, global = true, value_name = "KEY=VALUE,...")]
+    pub thresholds: Option<String>,
+
+    /// Database file to use instead of the default location
+    #[arg(long, global = true, env = "MACSWEEP_DB", value_name = "FILE")]
+    pub db_path: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// Scan system for installed packages
+    #[command(after_help = "\
+Examples:
+  macsweep scan                      Scan every source and gather usage data
+  macsweep scan --source homebrew    Only rescan Homebrew formulae and casks
+  macsweep scan --source brew,npm    Only rescan Homebrew and npm
+  macsweep scan --quick              Skip usage detection for a faster scan
+  macsweep scan --if-stale 7         Only rescan if the last scan is a week old
+  macsweep scan --quick --notify     Notify when there is much more to clean up than last time")]
     Scan {
-        /// Only scan specific source
-        #[arg(long)]
-        source: Option<String>,
+        /// Only scan these sources (homebrew, npm, pip, cargo, applications); repeat or separate with commas
+        #[arg(long, value_name = "SOURCE", value_delimiter = ',')]
+        source: Vec<String>,
 
         /// Skip usage detection (faster)
         #[arg(long)]
         quick: bool,
+
+        /// Do nothing if the same kind of scan ran less than DAYS days ago
+        #[arg(long, value_name = "DAYS")]
+        if_stale: Option<u32>,
+
+        /// Scan up to N sources at once (default: all of them, or `scan_jobs` in the config file)
+        #[arg(long, short = 'j', value_name = "N")]
+        jobs: Option<std::num::NonZeroUsize>,
+
+        /// Measure every package's size again instead of reusing sizes of unmodified directories
+        #[arg(long)]
+        recalculate_sizes: bool,
+
+        /// Don't measure package sizes, keeping those recorded by earlier scans; `size --missing`
+        /// measures the packages that have none
+        #[arg(long, conflicts_with = "recalculate_sizes")]
+        no_size: bool,
+
+        /// Only scan sources changed since their last scan (Homebrew's Cellar, the npm global
+        /// prefix, Applications, cargo's install records); packages of the others stay as recorded
+        #[arg(long)]
+        changed: bool,
+
+        /// Scan every source, ignoring --changed and --if-stale
+        #[arg(long)]
+        force: bool,
+
+        /// Post a macOS notification when the space recommendations would free grew by the
+        /// `[notifications]` threshold since the last scan; meant for scheduled scans
+        #[arg(long)]
+        notify: bool,
     },
 
     /// List packages
+    #[command(after_help = "\
+Examples:
+  macsweep list --unused 90                 Packages not used in 90+ days
+  macsweep list --orphaned                  Orphaned dependencies (Homebrew, pip)
+  macsweep list --growing --limit 10        What grew the most since the last scan
+  macsweep list --sort size --limit 20      The 20 largest packages
+  macsweep list --min-size 1GB --installed-before 365d
+                                            Everything over 1 GB installed more than a year ago
+  macsweep list --name 'python@*' --never-used
+                                            Python versions nothing has used
+  macsweep --format csv list > pkgs.csv     Export the package list as CSV
+  macsweep --format jsonl list | jq -c .    Stream one JSON object per package
+  macsweep list --columns name,usage_count,is_dependency")]
     List {
-        /// Filter by source
-        #[arg(long)]
-        source: Option<String>,
+        /// Filter by source (homebrew, npm, pip, cargo, applications, or e.g. homebrewcask);
+        /// repeat or separate with commas
+        #[arg(long, value_name = "SOURCE", value_delimiter = ',')]
+        source: Vec<String>,
 
         /// Show packages unused for N days
         #[arg(long)]
         unused: Option<u32>,
 
+        /// Show only packages without any recorded use
+        #[arg(long)]
+        never_used: bool,
+
+        /// Show only packages installed before a date (2024-06-30) or at least N days ago (365d)
+        #[arg(long, value_name = "DATE|DAYS", value_parser = crate::utils::date::parse_date_or_age)]
+        installed_before: Option<chrono::DateTime<chrono::Utc>>,
+
+        /// Show only packages at least this large (e.g. 500MB, 1.5GB)
+        #[arg(long, value_name = "SIZE", value_parser = crate::utils::size::parse_size)]
+        min_size: Option<u64>,
+
+        /// Show only packages at most this large
+        #[arg(long, value_name = "SIZE", value_parser = crate::utils::size::parse_size)]
+        max_size: Option<u64>,
+
         /// Show only orphaned packages
         #[arg(long)]
         orphaned: bool,
@@ -54,13 +197,55 @@ pub enum Commands {
         #[arg(long)]
         large: bool,
 
-        /// Sort by: name, size, last_used, install_date
+        /// Sort by: name, size, last_used, install_date, usage_count
         #[arg(long, default_value = "name")]
         sort: SortField,
 
         /// Limit results
         #[arg(long)]
         limit: Option<usize>,
+
+        /// Skip the first N results (with --limit, for paging)
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Show only packages whose name contains this text, or matches it as a pattern
+        /// with * and ? (case-insensitive)
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Show only packages with this tag
+        #[arg(long)]
+        tag: Option<String>,
+
+        /// Comma-separated columns for table, Markdown and CSV output
+        /// (default: name,source,version,size,install_date,last_used)
+        #[arg(long, value_delimiter = ',')]
+        columns: Vec<output::PackageColumn>,
+
+        /// Write the list to a file (no colors or summary footer)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Overwrite the --output file if it exists
+        #[arg(long)]
+        force: bool,
+
+        /// Only show packages with a cleanup recommendation
+        #[arg(long)]
+        recommended_only: bool,
+
+        /// Also show packages that were uninstalled outside macsweep
+        #[arg(long)]
+        include_removed: bool,
+
+        /// Sort by size growth since the previous scan (adds a Growth column)
+        #[arg(long)]
+        growing: bool,
+
+        /// Show only applications built for this architecture
+        #[arg(long)]
+        arch: Option<Architecture>,
     },
 
     /// Show package details
@@ -68,7 +253,25 @@ pub enum Commands {
         package: String,
     },
 
+    /// Explain a package's recommendation: usage evidence, dependencies and thresholds
+    #[command(after_help = "\
+Examples:
+  macsweep why jq                    What each usage source found for jq
+  macsweep --format json why jq      The same, as JSON")]
+    Why {
+        package: String,
+    },
+
     /// Interactive cleanup
+    #[command(after_help = "\
+Examples:
+  macsweep clean --dry-run                       Preview what would be removed
+  macsweep clean -i                              Pick packages to remove
+  macsweep clean --source homebrew --dry-run     Preview Homebrew cleanup only
+  macsweep clean --unused 180 --exclude 'py*'    Packages unused for 180+ days, except py*
+  macsweep clean --retry-failed                  Try the last cleanup's failures again
+  macsweep clean --resume                        Finish a cleanup stopped with Ctrl-C
+  macsweep undo                                  Restore the last cleanup")]
     Clean {
         /// Dry run - show what would be removed
         #[arg(long)]
@@ -78,13 +281,117 @@ pub enum Commands {
         #[arg(long)]
         yes: bool,
 
-        /// Only clean specific source
-        #[arg(long)]
-        source: Option<String>,
+        /// Only clean these sources; repeat or separate with commas
+        #[arg(long, value_name = "SOURCE", value_delimiter = ',')]
+        source: Vec<String>,
+
+        /// Only packages unused for at least DAYS days (or never used)
+        #[arg(long, value_name = "DAYS")]
+        unused: Option<u32>,
+
+        /// Least certain recommendations to include: `safe` only, `review` (safe and review), or `warning` (all)
+        #[arg(long, value_enum, value_name = "SEVERITY")]
+        only_severity: Option<RecommendationSeverity>,
+
+        /// Leave out packages whose names match PATTERN (`*` and `?` wildcards); repeatable
+        #[arg(long, value_name = "PATTERN")]
+        exclude: Vec<String>,
 
         /// Interactive mode - select packages to remove
         #[arg(long, short)]
         interactive: bool,
+
+        /// Only retry the removals that failed in the last cleanup
+        #[arg(long)]
+        retry_failed: bool,
+
+        /// Continue the last cleanup that was interrupted, removing what it didn't get to
+        #[arg(long, conflicts_with = "retry_failed")]
+        resume: bool,
+
+        /// Write the plan to FILE so it can be reviewed and applied later with --plan
+        #[arg(long, value_name = "FILE", requires = "dry_run")]
+        save_plan: Option<PathBuf>,
+
+        /// Remove exactly the packages in a plan saved with --save-plan
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["retry_failed", "resume", "save_plan"])]
+        plan: Option<PathBuf>,
+
+        /// With --plan, also remove packages whose version changed since the plan was saved
+        #[arg(long, requires = "plan")]
+        allow_drift: bool,
+
+        /// Also remove Homebrew casks' preferences, caches and support files (`brew uninstall --zap`)
+        #[arg(long)]
+        zap: bool,
+
+        /// Keep apps' Preferences and Application Support, so settings survive a reinstall
+        #[arg(long, conflicts_with = "zap")]
+        keep_config: bool,
+
+        /// Afterwards remove the formulae the cleanup left unneeded, as `brew autoremove` would
+        #[arg(long)]
+        autoremove: bool,
+
+        /// Allow removing system-critical and other protected packages
+        #[arg(long)]
+        force_protected: bool,
+
+        /// List every package in the plan (by default each source shows its 20 largest)
+        #[arg(long)]
+        show_all: bool,
+
+        /// Remove the Homebrew packages installed on request that the Brewfile at FILE doesn't list
+        #[arg(long, value_name = "FILE", conflicts_with_all = ["retry_failed", "resume", "plan"])]
+        not_in_brewfile: Option<PathBuf>,
+    },
+
+    /// Compare the installed Homebrew packages with a Brewfile
+    #[command(after_help = "\
+Examples:
+  macsweep compare --brewfile ~/dotfiles/Brewfile
+  macsweep --format json compare --brewfile Brewfile
+  macsweep clean --not-in-brewfile ~/dotfiles/Brewfile --dry-run")]
+    Compare {
+        /// The Brewfile to compare with
+        #[arg(long, value_name = "FILE")]
+        brewfile: PathBuf,
+    },
+
+    /// Tag a package (e.g. `macsweep tag postgres work`)
+    Tag {
+        /// Package to tag (omit to list all tags)
+        package: Option<String>,
+
+        /// Tags to add (omit to show the package's tags)
+        tags: Vec<String>,
+
+        /// Remove the given tags instead of adding them
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Attach a note to a package
+    Note {
+        package: String,
+
+        /// Note text (omit to show existing notes)
+        text: Option<String>,
+
+        /// Remove all notes from the package
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Record usage macsweep can't see (IDE plugins, CI on another machine)
+    #[command(after_help = "\
+Examples:
+  macsweep mark used protoc                   Used today
+  macsweep mark used protoc --date 2024-05-01
+  macsweep mark never-used imagemagick        Forget the usage seen so far")]
+    Mark {
+        #[command(subcommand)]
+        action: MarkAction,
     },
 
     /// Show usage history for a package
@@ -92,14 +399,156 @@ pub enum Commands {
         package: String,
     },
 
+    /// Measure a package's size again, replacing the size recorded by the last scan
+    Size {
+        #[arg(required_unless_present = "missing")]
+        package: Option<String>,
+
+        /// Measure every package without a recorded size instead, e.g. after `scan --no-size`
+        #[arg(long, conflicts_with = "package")]
+        missing: bool,
+    },
+
     /// Show summary statistics
-    Stats,
+    Stats {
+        /// Show trends across the last N full scans (default 10)
+        #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+        history: Option<usize>,
+    },
 
-    /// Export data
+    /// Write manifests to reinstall the recorded packages from
+    #[command(after_help = "\
+Examples:
+  macsweep export --brewfile -o Brewfile              Homebrew formulae and casks, for brew bundle
+  macsweep export --requirements > requirements.txt   pip packages pinned to their versions
+  macsweep export --install-script --exclude-unused 180 -o reinstall.sh
+                                                      Everything but what has sat unused for 180 days
+  macsweep export --sbom -o sbom.json                 A CycloneDX inventory for security reviews
+  macsweep export --plist -o packages.plist           Every package as a property list, for MDM tools
+  macsweep export --metrics /usr/local/var/prom/macsweep.prom
+                                                      Gauges for node_exporter's textfile collector")]
+    #[command(group(clap::ArgGroup::new("manifest").required(true)))]
     Export {
+        /// A Brewfile of the Homebrew formulae and casks, for `brew bundle`
+        #[arg(long, group = "manifest")]
+        brewfile: bool,
+
+        /// A requirements file of the pip packages, for `pip install -r`
+        #[arg(long, group = "manifest")]
+        requirements: bool,
+
+        /// A shell script installing the npm global packages
+        #[arg(long, group = "manifest")]
+        npm_globals: bool,
+
+        /// A shell script installing the packages of every source, listing apps to reinstall by hand
+        #[arg(long, group = "manifest")]
+        install_script: bool,
+
+        /// A CycloneDX 1.5 JSON SBOM of every package and its dependencies
+        #[arg(long, group = "manifest")]
+        sbom: bool,
+
+        /// A property list of every package with the fields --format json shows, for MDM tools
+        #[arg(long, group = "manifest")]
+        plist: bool,
+
+        /// Prometheus gauges of package counts, sizes and reclaimable space, written to FILE;
+        /// the file is replaced atomically, so a collector never reads it half-written
+        #[arg(long, value_name = "FILE", group = "manifest", conflicts_with_all = ["output", "exclude_unused"])]
+        metrics: Option<PathBuf>,
+
+        /// Leave out packages recommended for removal that have gone unused for DAYS days
+        #[arg(long, value_name = "DAYS")]
+        exclude_unused: Option<u32>,
+
         /// Output file (default: stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Overwrite the --output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Find data left behind by uninstalled applications
+    Leftovers {
+        /// Move the selected leftovers to the Trash
+        #[arg(long)]
+        clean: bool,
+
+        /// Show what would be moved to the Trash
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Auto-confirm (dangerous!)
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Render a package's dependency graph as DOT or Mermaid
+    #[command(after_help = "\
+Examples:
+  macsweep deps ffmpeg | dot -Tsvg > ffmpeg.svg    Everything ffmpeg pulls in
+  macsweep deps openssl@3 --reverse --depth 1      What needs openssl@3 directly
+  macsweep deps node --format mermaid -o node.mmd  Mermaid for a Markdown file")]
+    Deps {
+        package: String,
+
+        /// Graph format
+        #[arg(long = "format", value_enum, default_value_t = GraphFormat::Dot)]
+        graph_format: GraphFormat,
+
+        /// Follow at most N levels of dependencies
+        #[arg(long, value_name = "N")]
+        depth: Option<usize>,
+
+        /// Show the packages that depend on it instead
+        #[arg(long)]
+        reverse: bool,
+
+        /// Only consider packages from this source (when the name exists in several)
+        #[arg(long)]
+        source: Option<String>,
+
+        /// Write the graph to a file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Overwrite the --output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Tell which package a program on PATH, or any file, came from
+    #[command(after_help = "\
+Examples:
+  macsweep which terraform                       Look the program up on PATH
+  macsweep which /usr/local/bin/protoc           Or give a path
+  macsweep --format json which terraform")]
+    Which {
+        /// A program name to look up on PATH, or a path
+        target: String,
+    },
+
+    /// Find broken symlinks in bin directories (Homebrew, ~/.local/bin, ~/.cargo/bin, ...)
+    #[command(after_help = "\
+Examples:
+  macsweep doctor                    List symlinks whose targets were deleted
+  macsweep doctor --fix --dry-run    Show what --fix would remove
+  macsweep doctor --fix              Remove them (macsweep undo recreates them)")]
+    Doctor {
+        /// Remove the broken links, recording them so undo can recreate them
+        #[arg(long)]
+        fix: bool,
+
+        /// Show what would be removed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Auto-confirm
+        #[arg(long)]
+        yes: bool,
     },
 
     /// Undo last cleanup operation
@@ -110,6 +559,46 @@ pub enum Commands {
         /// List available backups
         #[arg(long)]
         list: bool,
+
+        /// Check whether a backup can be restored without restoring it
+        #[arg(long)]
+        verify: bool,
+
+        /// Show what would be reinstalled and from where, without installing anything
+        #[arg(long, conflicts_with_all = ["list", "verify"])]
+        dry_run: bool,
+
+        /// Only restore these packages from the backup (comma-separated)
+        #[arg(long, value_name = "NAMES", value_delimiter = ',', conflicts_with_all = ["list", "verify"])]
+        packages: Option<Vec<String>>,
+
+        /// Pick the packages to restore
+        #[arg(long, short, conflicts_with_all = ["list", "verify", "packages"])]
+        interactive: bool,
+    },
+
+    /// Generate man pages into a directory
+    #[command(hide = true)]
+    GenerateMan {
+        /// Output directory for macsweep.1 and per-subcommand pages
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MarkAction {
+    /// Mark a package as used; rescans only replace this with newer usage
+    Used {
+        package: String,
+
+        /// Day it was last used, YYYY-MM-DD (default: today)
+        #[arg(long)]
+        date: Option<chrono::NaiveDate>,
+    },
+
+    /// Mark a package as never used, discarding the usage recorded so far
+    NeverUsed {
+        package: String,
     },
 }
 
@@ -117,7 +606,27 @@ pub enum Commands {
 pub enum OutputFormat {
     Table,
     Json,
+    /// JSON Lines: one compact JSON object per line
+    Jsonl,
     Csv,
+    /// Tab-separated values: CSV with tabs between the fields
+    Tsv,
+    Yaml,
+    Markdown,
+    /// An Apple property list, XML unless --binary-plist is given
+    Plist,
+}
+
+impl OutputFormat {
+    /// Formats that print a serialized document instead of a human-oriented view
+    pub fn is_structured(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Jsonl | OutputFormat::Yaml | OutputFormat::Plist)
+    }
+
+    /// CSV and TSV, which write rows of fields split by a delimiter
+    pub fn is_delimited(self) -> bool {
+        matches!(self, OutputFormat::Csv | OutputFormat::Tsv)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
@@ -129,33 +638,235 @@ pub enum SortField {
     UsageCount,
 }
 
+impl From<SortField> for crate::storage::database::PackageOrder {
+    fn from(sort: SortField) -> Self {
+        match sort {
+            SortField::Name => Self::Name,
+            SortField::Size => Self::Size,
+            SortField::LastUsed => Self::LastUsed,
+            SortField::InstallDate => Self::InstallDate,
+            SortField::UsageCount => Self::UsageCount,
+        }
+    }
+}
+
+/// What a command accomplished, mapped to the process exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    /// Some packages failed to scan, remove or restore
+    PartialFailure,
+    /// No packages in the database or nothing to clean or restore
+    NothingToDo,
+    /// The user declined the confirmation prompt
+    Cancelled,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::PartialFailure => 2,
+            Outcome::NothingToDo => 3,
+            Outcome::Cancelled => 4,
+        }
+    }
+}
+
+/// Which log events are printed: warnings by default, errors only with `--quiet`, and with
+/// `-v`, `-vv` and `-vvv` info, debug and trace. A `RUST_LOG` filter takes precedence.
+pub fn log_filter(verbose: u8, quiet: bool) -> tracing_subscriber::EnvFilter {
+    let level = match verbose {
+        0 if quiet => "error",
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
+    };
+    tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level))
+}
+
 /// Execute the CLI command
-pub fn execute(cli: Cli) -> Result<()> {
+pub fn execute(cli: Cli) -> Result<Outcome> {
+    output::init_color(cli.no_color);
+    output::set_quiet(cli.quiet);
+    if cli.binary_plist && cli.format != OutputFormat::Plist && !matches!(cli.command, Commands::Export { plist: true, .. }) {
+        anyhow::bail!("--binary-plist only applies to --format plist and export --plist");
+    }
+    output::set_binary_plist(cli.binary_plist);
+
+    let db_path = match cli.db_path {
+        Some(path) => path,
+        None => Database::default_path()?,
+    };
+    let open_db = || Database::open(&db_path);
+    let delimiter = output::delimiter(cli.format, cli.delimiter)?;
+    let dates_flag = cli.dates;
+    let dates = || -> Result<DateDisplay> {
+        Ok(match dates_flag {
+            Some(dates) => dates,
+            None => Config::load()?.dates,
+        })
+    };
+
     match cli.command {
-        Commands::Scan { source, quick } => {
-            commands::scan(source, quick)?;
+        Commands::Scan { source, quick, if_stale, jobs, recalculate_sizes, no_size, changed, force, notify } => {
+            let mut config = Config::load()?;
+            config.scan_jobs = jobs.map(std::num::NonZeroUsize::get).or(config.scan_jobs);
+            config.recommendations = recommendation_config(&config, cli.thresholds.as_deref())?;
+            let options = crate::api::ScanOptions {
+                sources: source,
+                quick,
+                recalculate_sizes,
+                skip_sizes: no_size,
+                changed_only: changed && !force,
+                db_path: Some(db_path.clone()),
+                config,
+            };
+            let if_stale = if_stale.filter(|_| !force);
+            return commands::scan(&mut open_db()?, &options, if_stale, notify, &cli.keep_tag, cli.format);
         }
-        Commands::List { source, unused, orphaned, large, sort, limit } => {
-            commands::list(source, unused, orphaned, large, sort, limit, cli.format)?;
+        Commands::List {
+            source, unused, never_used, installed_before, min_size, max_size, orphaned, large, sort, limit, offset, name, tag, columns,
+            output, force, recommended_only, include_removed, growing, arch,
+        } => {
+            let config = Config::load()?;
+            let options = commands::ListOptions {
+                source, unused, never_used, installed_before, min_size, max_size, orphaned, large, sort, limit, offset, name, tag, columns,
+                dates: dates_flag.unwrap_or(config.dates),
+                delimiter,
+                output,
+                force,
+                recommended_only,
+                keep_tag: cli.keep_tag.clone(),
+                thresholds: recommendation_config(&config, cli.thresholds.as_deref())?,
+                protected_packages: config.protected_packages,
+                include_removed,
+                stale_after: cli.stale_after,
+                growing,
+                arch,
+                timeouts: config.timeouts,
+            };
+            commands::list(&open_db()?, options, cli.format)?;
         }
         Commands::Info { package } => {
-            commands::info(&package)?;
+            commands::info(&open_db()?, &package, cli.format, dates()?)?;
+        }
+        Commands::Why { package } => {
+            let config = Config::load()?;
+            let thresholds = recommendation_config(&config, cli.thresholds.as_deref())?;
+            commands::why(&open_db()?, &package, &cli.keep_tag, &config, &thresholds, cli.format, dates_flag.unwrap_or(config.dates))?;
         }
-        Commands::Clean { dry_run, yes, source, interactive } => {
-            commands::clean(dry_run, yes, source, interactive)?;
+        Commands::Clean {
+            dry_run, yes, source, unused, only_severity, exclude, interactive, retry_failed, resume, save_plan, plan, allow_drift,
+            zap, keep_config, autoremove, force_protected, show_all, not_in_brewfile,
+        } => {
+            let config = Config::load()?;
+            let options = commands::CleanOptions {
+                dry_run, yes, interactive, retry_failed, resume, save_plan, plan, allow_drift, zap, keep_config, autoremove,
+                force_protected,
+                show_all,
+                not_in_brewfile,
+                filter: RecommendationFilter {
+                    sources: ScannerRegistry::default().sources_all(&source)?,
+                    unused_days: unused,
+                    max_severity: only_severity,
+                    exclude,
+                },
+                thresholds: recommendation_config(&config, cli.thresholds.as_deref())?,
+                protected_packages: config.protected_packages,
+                keep_tag: cli.keep_tag.clone(),
+                hooks: config.hooks,
+                timeouts: config.timeouts,
+            };
+            return commands::clean(&open_db()?, options, cli.format);
         }
+        Commands::Tag { package, tags, remove } => {
+            commands::tag(&open_db()?, package, tags, remove)?;
+        }
+        Commands::Note { package, text, remove } => {
+            commands::note(&open_db()?, &package, text, remove)?;
+        }
+        Commands::Mark { action } => match action {
+            MarkAction::Used { package, date } => commands::mark(&open_db()?, &package, true, date)?,
+            MarkAction::NeverUsed { package } => commands::mark(&open_db()?, &package, false, None)?,
+        },
         Commands::History { package } => {
-            commands::history(&package)?;
+            commands::history(&open_db()?, &package, cli.format, dates()?)?;
+        }
+        Commands::Size { package, .. } => {
+            commands::size(&open_db()?, package.as_deref(), cli.format)?;
+        }
+        Commands::Stats { history } => {
+            match history {
+                Some(count) => commands::stats_history(&open_db()?, count, cli.format, dates()?)?,
+                None => {
+                    let config = Config::load()?;
+                    let thresholds = recommendation_config(&config, cli.thresholds.as_deref())?;
+                    commands::stats(&open_db()?, &cli.keep_tag, &config.protected_packages, &thresholds, &config.timeouts, cli.stale_after, cli.format)?
+                }
+            }
+        }
+        Commands::Export { brewfile, requirements, npm_globals, install_script, sbom, plist, metrics, exclude_unused, output, force } => {
+            use crate::analysis::manifests::ManifestKind;
+            let config = Config::load()?;
+            let thresholds = recommendation_config(&config, cli.thresholds.as_deref())?;
+            if let Some(path) = metrics {
+                commands::export_metrics(&open_db()?, &path, &cli.keep_tag, &config.protected_packages, &thresholds, &config.timeouts)?;
+                return Ok(Outcome::Success);
+            }
+            let kind = match (brewfile, requirements, npm_globals, install_script, sbom) {
+                _ if plist => commands::ExportKind::Plist,
+                (true, ..) => commands::ExportKind::Manifest(ManifestKind::Brewfile),
+                (_, true, ..) => commands::ExportKind::Manifest(ManifestKind::Requirements),
+                (_, _, true, ..) => commands::ExportKind::Manifest(ManifestKind::NpmGlobals),
+                (_, _, _, true, _) => commands::ExportKind::Manifest(ManifestKind::InstallScript),
+                _ => commands::ExportKind::Sbom,
+            };
+            let options = commands::ExportOptions {
+                kind,
+                exclude_unused,
+                output,
+                force,
+                keep_tag: cli.keep_tag.clone(),
+                thresholds,
+                protected_packages: config.protected_packages,
+                timeouts: config.timeouts,
+            };
+            commands::export(&open_db()?, options)?;
+        }
+        Commands::Compare { brewfile } => {
+            commands::compare(&open_db()?, &brewfile, cli.format)?;
         }
-        Commands::Stats => {
-            commands::stats()?;
+        Commands::Leftovers { clean, dry_run, yes } => {
+            commands::leftovers(&open_db()?, clean, dry_run, yes, cli.format, delimiter)?;
         }
-        Commands::Export { output } => {
-            commands::export(output)?;
+        Commands::Deps { package, graph_format, depth, reverse, source, output, force } => {
+            let options = commands::DepsOptions { source, format: graph_format, depth, reverse, output, force };
+            commands::deps(&open_db()?, &package, options)?;
         }
-        Commands::Undo { backup_id, list } => {
-            commands::undo(backup_id, list)?;
+        Commands::Which { target } => {
+            commands::which(&open_db()?, &target, cli.format, dates()?)?;
         }
+        Commands::Doctor { fix, dry_run, yes } => {
+            return commands::doctor(&open_db()?, fix, dry_run, yes, cli.format);
+        }
+        Commands::Undo { backup_id, list, verify, dry_run, packages, interactive } => {
+            let options = RestoreOptions { packages, dry_run, timeouts: Config::load()?.timeouts };
+            return commands::undo(&open_db()?, backup_id, list, verify, interactive, options);
+        }
+        Commands::GenerateMan { dir } => {
+            commands::generate_man(&dir)?;
+        }
+    }
+    Ok(Outcome::Success)
+}
+
+/// Recommendation thresholds from the config file, with `--thresholds` applied on top
+fn recommendation_config(config: &Config, overrides: Option<&str>) -> Result<RecommendationConfig> {
+    let mut thresholds = config.recommendations.clone();
+    if let Some(overrides) = overrides {
+        thresholds.apply_overrides(overrides)?;
     }
-    Ok(())
+    Ok(thresholds)
 }
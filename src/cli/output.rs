@@ -1,12 +1,244 @@
-// Output formatting for different formats (table, JSON, CSV)
-use comfy_table::{Table, Cell, Color, Attribute};
+// Output formatting for different formats (table, JSON, CSV, TSV, YAML, Markdown, plist)
+use super::OutputFormat;
+use crate::analysis::recommendations::PackageStatus;
+use crate::progress::{ItemProgress, Phase, ProgressReporter};
+use crate::scanner::Package;
+use crate::utils::date::{format_date, DateDisplay};
+use anyhow::Result;
+use comfy_table::Table;
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(true);
+static QUIET: AtomicBool = AtomicBool::new(false);
+static BINARY_PLIST: AtomicBool = AtomicBool::new(false);
+/// The latest progress bar, which log output clears from the terminal while it is written
+static PROGRESS_BAR: Mutex<Option<indicatif::WeakProgressBar>> = Mutex::new(None);
+
+/// Suppress status messages and progress bars (`--quiet`)
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Write property lists in the binary format instead of XML (`--binary-plist`)
+pub fn set_binary_plist(binary: bool) {
+    BINARY_PLIST.store(binary, Ordering::Relaxed);
+}
+
+/// Decide once whether styled output is allowed and apply it to `colored`.
+/// Color is disabled by `--no-color`, a non-empty `NO_COLOR`, or stdout not being a terminal.
+pub fn init_color(no_color_flag: bool) {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let enabled = color_enabled(no_color_flag, no_color_env, std::io::stdout().is_terminal());
+
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+    colored::control::set_override(enabled);
+}
+
+/// Whether log output on stderr may use colors: as for `init_color`, but for stderr
+pub fn log_color_enabled(no_color_flag: bool) -> bool {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    color_enabled(no_color_flag, no_color_env, std::io::stderr().is_terminal())
+}
+
+fn color_enabled(no_color_flag: bool, no_color_env: bool, stdout_is_tty: bool) -> bool {
+    !no_color_flag && !no_color_env && stdout_is_tty
+}
+
+/// Whether tables, progress bars and text may use colors
+pub fn should_color() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
 
 pub fn create_table() -> Table {
     let mut table = Table::new();
     table.load_preset(comfy_table::presets::UTF8_FULL);
+    if !should_color() {
+        table.force_no_tty();
+    }
     table
 }
 
+/// Theme for interactive prompts, without colors when they are disabled
+pub fn prompt_theme() -> Box<dyn dialoguer::theme::Theme> {
+    if should_color() {
+        Box::new(dialoguer::theme::ColorfulTheme::default())
+    } else {
+        Box::new(dialoguer::theme::SimpleTheme)
+    }
+}
+
+/// Progress bar in the shared style, drawn on stderr; plain characters when colors are disabled
+pub fn create_progress_bar(len: u64) -> indicatif::ProgressBar {
+    if is_quiet() {
+        return indicatif::ProgressBar::hidden();
+    }
+
+    let (template, chars) = if should_color() {
+        ("  [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}", "━━╺")
+    } else {
+        ("  [{elapsed_precise}] [{bar:40}] {pos}/{len} {msg}", "=> ")
+    };
+
+    let pb = indicatif::ProgressBar::with_draw_target(Some(len), indicatif::ProgressDrawTarget::stderr());
+    pb.set_style(
+        indicatif::ProgressStyle::default_bar()
+            .template(template)
+            .unwrap()
+            .progress_chars(chars)
+    );
+    *PROGRESS_BAR.lock().unwrap_or_else(|e| e.into_inner()) = Some(pb.downgrade());
+    pb
+}
+
+/// Run `f` with the progress bars (those of a `WorkerProgress` included) cleared from the
+/// terminal, drawing them again afterwards, so what `f` prints isn't drawn over
+pub fn suspend_progress<R>(f: impl FnOnce() -> R) -> R {
+    let bar = PROGRESS_BAR.lock().unwrap_or_else(|e| e.into_inner()).as_ref().and_then(|bar| bar.upgrade());
+    match bar {
+        Some(bar) => bar.suspend(f),
+        None => f(),
+    }
+}
+
+/// Stderr for log events, written between redraws of the progress bars
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProgressAwareStderr;
+
+impl Write for ProgressAwareStderr {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        suspend_progress(|| std::io::stderr().write(buf))
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        // A log line is written in one go, so it is never split around a redraw
+        suspend_progress(|| std::io::stderr().write_all(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
+/// Progress of work spread over several workers: the shared progress bar, with a line under
+/// it per worker showing what that worker is doing
+pub struct WorkerProgress {
+    multi: indicatif::MultiProgress,
+    overall: indicatif::ProgressBar,
+    workers: Vec<indicatif::ProgressBar>,
+}
+
+impl WorkerProgress {
+    pub fn new(len: u64) -> Self {
+        let target = if is_quiet() {
+            indicatif::ProgressDrawTarget::hidden()
+        } else {
+            indicatif::ProgressDrawTarget::stderr()
+        };
+        let multi = indicatif::MultiProgress::with_draw_target(target);
+        let overall = multi.add(create_progress_bar(len));
+        Self { multi, overall, workers: Vec::new() }
+    }
+
+    /// Show what `worker` has started on
+    pub fn start(&mut self, worker: usize, message: String) {
+        while self.workers.len() <= worker {
+            let line = self.multi.add(indicatif::ProgressBar::new_spinner());
+            line.set_style(indicatif::ProgressStyle::with_template("    {msg}").unwrap());
+            self.workers.push(line);
+        }
+        self.workers[worker].set_message(message);
+    }
+
+    /// Count one item done and clear `worker`'s line
+    pub fn finish(&self, worker: usize) {
+        if let Some(line) = self.workers.get(worker) {
+            line.set_message("");
+        }
+        self.overall.inc(1);
+    }
+
+    /// Print a line above the progress bars, or on its own when they aren't drawn
+    pub fn println(&self, message: impl AsRef<str>) {
+        if self.multi.is_hidden() || self.multi.println(message.as_ref()).is_err() {
+            eprintln!("{}", message.as_ref());
+        }
+    }
+
+    pub fn finish_and_clear(&self) {
+        for line in &self.workers {
+            line.finish_and_clear();
+        }
+        self.overall.finish_and_clear();
+    }
+}
+
+/// Progress bars for the library's progress reports: a single bar for usage lookups and the
+/// per-worker view, a line for each source or package being worked on, for scans and removals
+#[derive(Default)]
+pub struct TerminalProgress {
+    bars: std::sync::Mutex<Option<PhaseBars>>,
+}
+
+enum PhaseBars {
+    Single(indicatif::ProgressBar),
+    Workers(WorkerProgress),
+}
+
+impl TerminalProgress {
+    fn bars(&self) -> std::sync::MutexGuard<'_, Option<PhaseBars>> {
+        self.bars.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a phase's bars are showing
+    pub fn is_active(&self) -> bool {
+        self.bars().is_some()
+    }
+
+    /// Print a line above the progress bars, or on its own when none are drawn
+    pub fn println(&self, message: impl AsRef<str>) {
+        match &*self.bars() {
+            Some(PhaseBars::Workers(workers)) => workers.println(message),
+            Some(PhaseBars::Single(pb)) => pb.suspend(|| eprintln!("{}", message.as_ref())),
+            None => eprintln!("{}", message.as_ref()),
+        }
+    }
+}
+
+impl ProgressReporter for TerminalProgress {
+    fn on_phase_start(&self, phase: Phase, total: u64) {
+        let bars = match phase {
+            Phase::Usage => PhaseBars::Single(create_progress_bar(total)),
+            Phase::Scan | Phase::Cleanup => PhaseBars::Workers(WorkerProgress::new(total)),
+        };
+        *self.bars() = Some(bars);
+    }
+
+    fn on_item(&self, _phase: Phase, item: ItemProgress) {
+        match (&mut *self.bars(), item) {
+            (Some(PhaseBars::Single(pb)), ItemProgress::Started { name, .. }) => pb.set_message(name.to_string()),
+            (Some(PhaseBars::Single(pb)), ItemProgress::Finished { .. }) => pb.inc(1),
+            (Some(PhaseBars::Workers(workers)), ItemProgress::Started { name, worker }) => workers.start(worker, name.to_string()),
+            (Some(PhaseBars::Workers(workers)), ItemProgress::Finished { worker, .. }) => workers.finish(worker),
+            (None, _) => {}
+        }
+    }
+
+    fn on_phase_done(&self, _phase: Phase) {
+        match self.bars().take() {
+            Some(PhaseBars::Single(pb)) => pb.finish_and_clear(),
+            Some(PhaseBars::Workers(workers)) => workers.finish_and_clear(),
+            None => {}
+        }
+    }
+}
+
 pub fn format_size(bytes: u64) -> String {
     const KB: u64 = 1024;
     const MB: u64 = KB * 1024;
@@ -32,3 +264,506 @@ pub fn format_days_ago(days: u32) -> String {
         format!("{} days ago", days)
     }
 }
+
+/// Print `value` when a serialization format (JSON, JSON Lines, YAML, plist) is selected.
+/// Returns false for human-oriented formats so the caller renders its own view.
+pub fn print_serialized<T: Serialize + ?Sized>(value: &T, format: OutputFormat) -> Result<bool> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Jsonl => {
+            // Sequences become one line per element, anything else a single line
+            let mut writer = JsonLinesWriter::stdout();
+            match serde_json::to_value(value)? {
+                serde_json::Value::Array(items) => {
+                    for item in &items {
+                        writer.write(item)?;
+                    }
+                }
+                other => writer.write(&other)?,
+            }
+        }
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        OutputFormat::Plist => write_plist(std::io::stdout().lock(), value)?,
+        OutputFormat::Table | OutputFormat::Csv | OutputFormat::Tsv | OutputFormat::Markdown => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Write `value` as a property list, XML unless `--binary-plist` was given. It holds what the
+/// JSON output does, except that timestamps are plist dates and nulls are left out, since
+/// property lists have no null.
+pub fn write_plist<T: Serialize + ?Sized, W: Write>(mut out: W, value: &T) -> Result<()> {
+    let value = plist_value(serde_json::to_value(value)?, None).unwrap_or_else(|| plist::Dictionary::new().into());
+    if BINARY_PLIST.load(Ordering::Relaxed) {
+        value.to_writer_binary(&mut out)?;
+    } else {
+        value.to_writer_xml(&mut out)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Fields holding a timestamp; only these become plist dates, so a name or version that
+/// happens to look like one stays a string
+const PLIST_DATE_KEYS: &[&str] = &[
+    "checked_at",
+    "created_at",
+    "date",
+    "event_date",
+    "install_date",
+    "last_entry_at",
+    "last_used",
+    "measured_at",
+    "modified",
+    "read_at",
+    "removed_at",
+    "scan_date",
+    "taken_at",
+    "timestamp",
+];
+
+/// The plist form of a JSON value found under `key`: RFC 3339 strings in date fields, which
+/// is how timestamps serialize, become dates, and nulls become None so their keys are dropped
+fn plist_value(value: serde_json::Value, key: Option<&str>) -> Option<plist::Value> {
+    use serde_json::Value;
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(b) => plist::Value::Boolean(b),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(u), _) => plist::Value::Integer(u.into()),
+            (_, Some(i)) => plist::Value::Integer(i.into()),
+            _ => plist::Value::Real(n.as_f64().unwrap_or_default()),
+        },
+        Value::String(s) => match chrono::DateTime::parse_from_rfc3339(&s) {
+            Ok(date) if key.is_some_and(|key| PLIST_DATE_KEYS.contains(&key)) => {
+                plist::Value::Date(std::time::SystemTime::from(date).into())
+            }
+            _ => plist::Value::String(s),
+        },
+        Value::Array(items) => plist::Value::Array(items.into_iter().filter_map(|item| plist_value(item, key)).collect()),
+        Value::Object(fields) => plist::Value::Dictionary(
+            fields.into_iter().filter_map(|(key, value)| Some((key.clone(), plist_value(value, Some(&key))?))).collect(),
+        ),
+    })
+}
+
+/// Parse `--delimiter`: a single ASCII character, or `tab` (also written `\t`)
+pub fn parse_delimiter(text: &str) -> Result<u8> {
+    match text {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ if text.len() == 1 && text.is_ascii() && !matches!(text, "\"" | "\n" | "\r") => Ok(text.as_bytes()[0]),
+        _ => anyhow::bail!("Invalid delimiter '{}'; use a single ASCII character other than a quote, or tab", text),
+    }
+}
+
+/// The field separator for `format`: tabs for TSV, and for CSV `--delimiter` or a comma
+pub fn delimiter(format: OutputFormat, requested: Option<u8>) -> Result<u8> {
+    match (format, requested) {
+        (OutputFormat::Csv, requested) => Ok(requested.unwrap_or(b',')),
+        (OutputFormat::Tsv, None | Some(b'\t')) => Ok(b'\t'),
+        (_, None) => Ok(b','),
+        (_, Some(_)) => anyhow::bail!("--delimiter only applies to --format csv"),
+    }
+}
+
+/// A CSV writer separating fields with `delimiter`. Fields holding the delimiter, quotes or
+/// line breaks are quoted as RFC 4180 describes.
+pub fn csv_writer<W: Write>(out: W, delimiter: u8) -> csv::Writer<W> {
+    csv::WriterBuilder::new().delimiter(delimiter).from_writer(out)
+}
+
+/// Writes records as JSON Lines, one compact object per line, as they are produced
+pub struct JsonLinesWriter<W: Write> {
+    out: W,
+}
+
+impl JsonLinesWriter<std::io::StdoutLock<'static>> {
+    /// Writer on stdout; stdout is line buffered so each record is visible immediately
+    pub fn stdout() -> Self {
+        Self::new(std::io::stdout().lock())
+    }
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn write<T: Serialize + ?Sized>(&mut self, record: &T) -> Result<()> {
+        serde_json::to_writer(&mut self.out, record)?;
+        self.out.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// A column of the package list, selectable with `list --columns`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PackageColumn {
+    Name,
+    Source,
+    Version,
+    Size,
+    #[value(name = "install_date", alias = "install-date")]
+    InstallDate,
+    #[value(name = "last_used", alias = "last-used")]
+    LastUsed,
+    #[value(name = "usage_count", alias = "usage-count")]
+    UsageCount,
+    #[value(name = "is_dependency", alias = "is-dependency")]
+    IsDependency,
+    #[value(name = "binary_path", alias = "binary-path")]
+    BinaryPath,
+    Status,
+    /// Size change since the previous scan
+    Growth,
+    Description,
+    /// How much evidence backs the last-used date
+    Confidence,
+}
+
+/// A package row of `list` together with its cleanup status
+#[derive(Serialize)]
+pub struct ListedPackage {
+    #[serde(flatten)]
+    pub package: Package,
+    pub status: Option<PackageStatus>,
+    /// Size change in bytes since the previous scan, when growth was requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_growth: Option<i64>,
+}
+
+impl From<Package> for ListedPackage {
+    fn from(package: Package) -> Self {
+        Self { package, status: None, size_growth: None }
+    }
+}
+
+/// Table color for a package status
+pub fn status_color(status: PackageStatus) -> comfy_table::Color {
+    match status {
+        PackageStatus::Safe => comfy_table::Color::Green,
+        PackageStatus::Review => comfy_table::Color::Yellow,
+        PackageStatus::Warning => comfy_table::Color::Red,
+        PackageStatus::Pinned => comfy_table::Color::Blue,
+        PackageStatus::Removed => comfy_table::Color::DarkGrey,
+    }
+}
+
+/// CSV and TSV columns when `--columns` is not given: the defaults plus the fields that are
+/// too long for a table but useful in a spreadsheet
+pub const DEFAULT_CSV_PACKAGE_COLUMNS: [PackageColumn; 10] = [
+    PackageColumn::Name,
+    PackageColumn::Source,
+    PackageColumn::Version,
+    PackageColumn::Size,
+    PackageColumn::InstallDate,
+    PackageColumn::LastUsed,
+    PackageColumn::UsageCount,
+    PackageColumn::IsDependency,
+    PackageColumn::BinaryPath,
+    PackageColumn::Status,
+];
+
+/// Columns shown when `--columns` is not given
+pub const DEFAULT_PACKAGE_COLUMNS: [PackageColumn; 7] = [
+    PackageColumn::Name,
+    PackageColumn::Source,
+    PackageColumn::Version,
+    PackageColumn::Size,
+    PackageColumn::InstallDate,
+    PackageColumn::LastUsed,
+    PackageColumn::Status,
+];
+
+impl PackageColumn {
+    /// Header in the table and Markdown views
+    pub fn header(self) -> &'static str {
+        match self {
+            PackageColumn::Name => "Package",
+            PackageColumn::Source => "Source",
+            PackageColumn::Version => "Version",
+            PackageColumn::Size => "Size",
+            PackageColumn::InstallDate => "Install Date",
+            PackageColumn::LastUsed => "Last Used",
+            PackageColumn::UsageCount => "Uses",
+            PackageColumn::IsDependency => "Dependency",
+            PackageColumn::BinaryPath => "Path",
+            PackageColumn::Status => "Status",
+            PackageColumn::Growth => "Growth",
+            PackageColumn::Description => "Description",
+            PackageColumn::Confidence => "Confidence",
+        }
+    }
+
+    /// Header in CSV output
+    pub fn csv_header(self) -> &'static str {
+        match self {
+            PackageColumn::Name => "name",
+            PackageColumn::Source => "source",
+            PackageColumn::Version => "version",
+            PackageColumn::Size => "size_bytes",
+            PackageColumn::InstallDate => "install_date",
+            PackageColumn::LastUsed => "last_used",
+            PackageColumn::UsageCount => "usage_count",
+            PackageColumn::IsDependency => "is_dependency",
+            PackageColumn::BinaryPath => "binary_path",
+            PackageColumn::Status => "status",
+            PackageColumn::Growth => "size_growth_bytes",
+            PackageColumn::Description => "description",
+            PackageColumn::Confidence => "usage_confidence",
+        }
+    }
+
+    /// Human-readable cell, shared by the table and Markdown views
+    pub fn cell(self, row: &ListedPackage, dates: DateDisplay) -> String {
+        let pkg = &row.package;
+        match self {
+            PackageColumn::Name => pkg.name.clone(),
+            PackageColumn::Source => format!("{:?}", pkg.source),
+            PackageColumn::Version => pkg.version.clone().unwrap_or_else(|| "-".to_string()),
+            PackageColumn::Size => pkg.size_bytes
+                .map(crate::utils::size::format_size)
+                .unwrap_or_else(|| "-".to_string()),
+            PackageColumn::InstallDate => pkg.install_date
+                .map(|dt| format_date(&dt, dates))
+                .unwrap_or_else(|| "-".to_string()),
+            PackageColumn::LastUsed => pkg.last_used
+                .map(|dt| format_date(&dt, dates))
+                .unwrap_or_else(|| "Never".to_string()),
+            PackageColumn::UsageCount => pkg.usage_count.to_string(),
+            PackageColumn::IsDependency => if pkg.is_dependency { "yes" } else { "no" }.to_string(),
+            PackageColumn::BinaryPath => pkg.binary_path
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            PackageColumn::Status => row.status
+                .map(|s| format!("{:?}", s))
+                .unwrap_or_else(|| "-".to_string()),
+            PackageColumn::Growth => row.size_growth
+                .map(crate::utils::size::format_size_delta)
+                .unwrap_or_else(|| "-".to_string()),
+            PackageColumn::Description => pkg.description.clone().unwrap_or_else(|| "-".to_string()),
+            PackageColumn::Confidence => pkg.usage_confidence.as_str().to_string(),
+        }
+    }
+
+    /// Raw value for CSV output
+    pub fn csv_value(self, row: &ListedPackage) -> String {
+        let pkg = &row.package;
+        match self {
+            PackageColumn::Name => pkg.name.clone(),
+            PackageColumn::Source => pkg.source.to_string(),
+            PackageColumn::Version => pkg.version.clone().unwrap_or_default(),
+            PackageColumn::Size => pkg.size_bytes.map(|s| s.to_string()).unwrap_or_default(),
+            PackageColumn::InstallDate => pkg.install_date.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
+            PackageColumn::LastUsed => pkg.last_used.map(|dt| dt.to_rfc3339()).unwrap_or_default(),
+            PackageColumn::UsageCount => pkg.usage_count.to_string(),
+            PackageColumn::IsDependency => pkg.is_dependency.to_string(),
+            PackageColumn::BinaryPath => pkg.binary_path
+                .as_ref()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            PackageColumn::Status => row.status.map(|s| format!("{:?}", s)).unwrap_or_default(),
+            PackageColumn::Growth => row.size_growth.map(|g| g.to_string()).unwrap_or_default(),
+            PackageColumn::Description => pkg.description.clone().unwrap_or_default(),
+            PackageColumn::Confidence => pkg.usage_confidence.as_str().to_string(),
+        }
+    }
+}
+
+/// Human-readable cells for one package
+pub fn package_table_row(row: &ListedPackage, columns: &[PackageColumn], dates: DateDisplay) -> Vec<String> {
+    columns.iter().map(|c| c.cell(row, dates)).collect()
+}
+
+/// Render a GitHub-flavored Markdown table
+pub fn markdown_table<S: AsRef<str>>(headers: &[S], rows: &[Vec<String>]) -> String {
+    let mut out = String::new();
+
+    let header: Vec<String> = headers.iter().map(|h| escape_markdown_cell(h.as_ref())).collect();
+    out.push_str(&format!("| {} |\n", header.join(" | ")));
+    out.push_str(&format!("|{}\n", " --- |".repeat(headers.len())));
+
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|c| escape_markdown_cell(c)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+
+    out
+}
+
+/// Escape characters that would break a Markdown table cell
+fn escape_markdown_cell(cell: &str) -> String {
+    cell.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::PackageSource;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_packages_markdown() {
+        let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+        wget.version = Some("1.24.5".to_string());
+        wget.size_bytes = Some(4 * 1024 * 1024);
+        wget.install_date = Utc.with_ymd_and_hms(2024, 11, 2, 10, 0, 0).single();
+
+        let mut piped = Package::new("a|b".to_string(), PackageSource::Npm);
+        piped.size_bytes = Some(512);
+
+        let rows: Vec<_> = [wget, piped]
+            .into_iter()
+            .map(|p| package_table_row(&p.into(), &DEFAULT_PACKAGE_COLUMNS, DateDisplay::Absolute))
+            .collect();
+        let headers: Vec<_> = DEFAULT_PACKAGE_COLUMNS.iter().map(|c| c.header()).collect();
+        let markdown = markdown_table(&headers, &rows);
+
+        assert_eq!(
+            markdown,
+            "| Package | Source | Version | Size | Install Date | Last Used | Status |\n\
+             | --- | --- | --- | --- | --- | --- | --- |\n\
+             | wget | Homebrew | 1.24.5 | 4.0 MB | 2024-11-02 | Never | - |\n\
+             | a\\|b | Npm | - | 512 B | - | Never | - |\n"
+        );
+    }
+
+    #[test]
+    fn test_selected_columns() {
+        let mut jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        jq.usage_count = 42;
+        jq.is_dependency = true;
+
+        let row = ListedPackage { package: jq, status: Some(PackageStatus::Review), size_growth: None };
+
+        let columns = [
+            PackageColumn::Name,
+            PackageColumn::UsageCount,
+            PackageColumn::IsDependency,
+            PackageColumn::BinaryPath,
+            PackageColumn::Status,
+        ];
+        assert_eq!(package_table_row(&row, &columns, DateDisplay::Relative), vec!["jq", "42", "yes", "-", "Review"]);
+
+        let csv: Vec<_> = columns.iter().map(|c| c.csv_value(&row)).collect();
+        assert_eq!(csv, vec!["jq", "42", "true", "", "Review"]);
+
+        let json = serde_json::to_value(&row).unwrap();
+        assert_eq!(json["name"], "jq");
+        assert_eq!(json["status"], "Review");
+    }
+
+    #[test]
+    fn test_csv_quoting_and_delimiters() {
+        let mut quoted = Package::new("say \"hi\"".to_string(), PackageSource::Npm);
+        quoted.binary_path = Some("/opt/a,b;c/bin/hi".into());
+        let multiline = Package::new("two\nlines".to_string(), PackageSource::Homebrew);
+        let rows: Vec<ListedPackage> = vec![quoted.into(), multiline.into()];
+        let columns = [PackageColumn::Name, PackageColumn::Source, PackageColumn::BinaryPath];
+
+        let write = |delimiter| {
+            let mut wtr = csv_writer(Vec::new(), delimiter);
+            wtr.write_record(columns.iter().map(|c| c.csv_header())).unwrap();
+            for row in &rows {
+                wtr.write_record(columns.iter().map(|c| c.csv_value(row))).unwrap();
+            }
+            String::from_utf8(wtr.into_inner().unwrap()).unwrap()
+        };
+
+        // Quotes are doubled and fields with a delimiter or line break are quoted (RFC 4180)
+        let csv = write(b',');
+        assert_eq!(csv, "name,source,binary_path\n\"say \"\"hi\"\"\",npm,\"/opt/a,b;c/bin/hi\"\n\"two\nlines\",homebrew,\n");
+        assert_eq!(write(b';'), "name;source;binary_path\n\"say \"\"hi\"\"\";npm;\"/opt/a,b;c/bin/hi\"\n\"two\nlines\";homebrew;\n");
+        assert_eq!(write(b'\t'), "name\tsource\tbinary_path\n\"say \"\"hi\"\"\"\tnpm\t/opt/a,b;c/bin/hi\n\"two\nlines\"\thomebrew\t\n");
+
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let names: Vec<String> = reader.records().map(|r| r.unwrap()[0].to_string()).collect();
+        assert_eq!(names, ["say \"hi\"", "two\nlines"]);
+
+        let headers: Vec<_> = DEFAULT_CSV_PACKAGE_COLUMNS.iter().map(|c| c.csv_header()).collect();
+        assert_eq!(headers.join(","), "name,source,version,size_bytes,install_date,last_used,usage_count,is_dependency,binary_path,status");
+    }
+
+    #[test]
+    fn test_delimiter_options() {
+        assert_eq!(parse_delimiter(";").unwrap(), b';');
+        assert_eq!(parse_delimiter("tab").unwrap(), b'\t');
+        assert_eq!(parse_delimiter("\\t").unwrap(), b'\t');
+        assert!(parse_delimiter("\"").is_err() && parse_delimiter("ab").is_err() && parse_delimiter("é").is_err());
+
+        assert_eq!(delimiter(OutputFormat::Csv, None).unwrap(), b',');
+        assert_eq!(delimiter(OutputFormat::Csv, Some(b'|')).unwrap(), b'|');
+        assert_eq!(delimiter(OutputFormat::Tsv, None).unwrap(), b'\t');
+        assert!(delimiter(OutputFormat::Tsv, Some(b';')).is_err());
+        assert!(delimiter(OutputFormat::Json, Some(b';')).is_err());
+    }
+
+    #[test]
+    fn test_plist_round_trip() {
+        let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+        // A version that parses as a timestamp is still a version
+        wget.version = Some("2024-11-02T10:00:00Z".to_string());
+        wget.size_bytes = Some(4 * 1024 * 1024);
+        wget.usage_count = 3;
+        wget.install_date = Utc.with_ymd_and_hms(2024, 11, 2, 10, 0, 0).single();
+        let rows = vec![ListedPackage { package: wget, status: Some(PackageStatus::Review), size_growth: None }];
+
+        let check = |bytes: &[u8]| {
+            let parsed = plist::Value::from_reader(std::io::Cursor::new(bytes)).unwrap();
+            let package = parsed.as_array().unwrap()[0].as_dictionary().unwrap();
+            assert_eq!(package["name"].as_string(), Some("wget"));
+            assert_eq!(package["source"].as_string(), Some("homebrew"));
+            assert_eq!(package["version"].as_string(), Some("2024-11-02T10:00:00Z"));
+            assert_eq!(package["size_bytes"].as_unsigned_integer(), Some(4 * 1024 * 1024));
+            assert_eq!(package["usage_count"].as_unsigned_integer(), Some(3));
+            assert_eq!(package["status"].as_string(), Some("Review"));
+            let installed = std::time::SystemTime::from(package["install_date"].as_date().unwrap());
+            assert_eq!(chrono::DateTime::<Utc>::from(installed), Utc.with_ymd_and_hms(2024, 11, 2, 10, 0, 0).unwrap());
+            // Property lists have no null
+            assert!(!package.contains_key("last_used"));
+        };
+
+        let mut xml = Vec::new();
+        write_plist(&mut xml, &rows).unwrap();
+        let text = String::from_utf8(xml.clone()).unwrap();
+        assert!(text.starts_with("<?xml"), "{}", text);
+        assert!(text.contains("<date>2024-11-02T10:00:00Z</date>"), "{}", text);
+        check(&xml);
+
+        set_binary_plist(true);
+        let mut binary = Vec::new();
+        write_plist(&mut binary, &rows).unwrap();
+        set_binary_plist(false);
+        assert!(binary.starts_with(b"bplist00"));
+        check(&binary);
+    }
+
+    #[test]
+    fn test_json_lines_writer() {
+        let mut writer = JsonLinesWriter::new(Vec::new());
+        writer.write(&Package::new("wget".to_string(), PackageSource::Homebrew)).unwrap();
+        writer.write(&Package::new("npm".to_string(), PackageSource::Npm)).unwrap();
+
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["name"], "wget");
+        assert_eq!(first["source"], "homebrew");
+    }
+
+    #[test]
+    fn test_color_enabled() {
+        assert!(color_enabled(false, false, true));
+        assert!(!color_enabled(true, false, true));
+        assert!(!color_enabled(false, true, true));
+        assert!(!color_enabled(false, false, false));
+    }
+}
@@ -0,0 +1,261 @@
+// User configuration (~/.config/macsweep/config.toml)
+use crate::analysis::recommendations::RecommendationConfig;
+use crate::utils::date::DateDisplay;
+use crate::utils::process::CommandTimeouts;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::path::{Path, PathBuf};
+
+/// Settings read from the config file; every key is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Extra shell history files, directories of history files, or globs like
+    /// `~/.local/state/zsh/history-*` (wildcards in the file name only) to read
+    pub history_paths: Vec<PathBuf>,
+    /// Projects (or folders of projects) whose package.json scripts count as using a tool
+    pub project_dirs: Vec<PathBuf>,
+    /// Packages never to recommend or remove, on top of the built-in list: a name for every
+    /// source, or `source:name` for one
+    pub protected_packages: Vec<String>,
+    /// What runs after a cleanup
+    pub hooks: HookConfig,
+    /// Where scans keep a Prometheus metrics file up to date
+    pub metrics: MetricsConfig,
+    /// Notifications posted by `scan --notify`
+    pub notifications: NotificationConfig,
+    /// When unused packages are recommended for removal
+    pub recommendations: RecommendationConfig,
+    /// How many sources a scan runs at once; all of them when unset
+    pub scan_jobs: Option<usize>,
+    /// How many scans of per-package size history are kept; 50 when unset
+    pub size_history_scans: Option<usize>,
+    /// How dates are shown in tables when `--dates` isn't given
+    pub dates: DateDisplay,
+    /// How long package managers, mdls and uninstallers may run before they are killed
+    pub timeouts: CommandTimeouts,
+}
+
+impl Config {
+    pub fn default_path() -> Result<PathBuf> {
+        let home = dirs::home_dir().context("Failed to get home directory")?;
+        Ok(home.join(".config").join("macsweep").join("config.toml"))
+    }
+
+    /// Load the config file, falling back to defaults when it doesn't exist
+    pub fn load() -> Result<Self> {
+        Self::load_from(&Self::default_path()?)
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        config.history_paths = config.history_paths.iter().map(|p| expand_home(p)).collect();
+        config.project_dirs = config.project_dirs.iter().map(|p| expand_home(p)).collect();
+        config.metrics.textfile = config.metrics.textfile.as_deref().map(expand_home);
+        config
+            .recommendations
+            .validate()
+            .with_context(|| format!("Invalid [recommendations] in config file {}", path.display()))?;
+        for entry in &config.protected_packages {
+            crate::analysis::protected::parse_entry(entry).with_context(|| format!("Invalid config file {}", path.display()))?;
+        }
+        if config.scan_jobs == Some(0) {
+            anyhow::bail!("Invalid config file {}: scan_jobs must be at least 1", path.display());
+        }
+        if config.size_history_scans == Some(0) {
+            anyhow::bail!("Invalid config file {}: size_history_scans must be at least 1", path.display());
+        }
+        config.hooks.post_clean_command = config.hooks.post_clean_command.filter(|c| !c.trim().is_empty());
+        config.hooks.validate().with_context(|| format!("Invalid [hooks] in config file {}", path.display()))?;
+        let timeouts = config.timeouts;
+        for (name, seconds) in [("scanners", timeouts.scanners), ("mdls", timeouts.mdls), ("uninstalls", timeouts.uninstalls)] {
+            if seconds == 0 {
+                anyhow::bail!("Invalid [timeouts] in config file {}: {} must be at least 1 second", path.display(), name);
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// `[hooks]`: what is told about a cleanup once it is recorded. `clean --dry-run` runs none.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookConfig {
+    /// Run with `sh -c`, with the cleanup report as JSON on stdin
+    pub post_clean_command: Option<String>,
+    /// An http(s) URL the cleanup report is POSTed to as JSON
+    pub post_clean_webhook: Option<String>,
+    /// Environment variable holding a bearer token for the webhook
+    pub webhook_token_env: Option<String>,
+    /// Seconds the command and the webhook may each take
+    pub timeout: u64,
+    /// A failed hook fails the cleanup instead of only being logged
+    pub required: bool,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self { post_clean_command: None, post_clean_webhook: None, webhook_token_env: None, timeout: 30, required: false }
+    }
+}
+
+impl HookConfig {
+    pub fn is_empty(&self) -> bool {
+        self.post_clean_command.is_none() && self.post_clean_webhook.is_none()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.timeout == 0 {
+            anyhow::bail!("timeout must be at least 1 second");
+        }
+        if let Some(url) = &self.post_clean_webhook {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                anyhow::bail!("post_clean_webhook must be an http:// or https:// URL, not '{}'", url);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `[metrics]`: a Prometheus textfile rewritten after every saved scan, as
+/// `export --metrics` writes it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// For node_exporter's textfile collector, e.g. `/usr/local/var/prom/macsweep.prom`
+    pub textfile: Option<PathBuf>,
+}
+
+/// `[notifications]`: when `scan --notify` posts a notification about new cleanup
+/// opportunities
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotificationConfig {
+    pub enabled: bool,
+    /// How much space must be reclaimable for a notification, in bytes; written as a size
+    /// such as `"500MB"` or a number of bytes
+    #[serde(deserialize_with = "deserialize_size")]
+    pub threshold: u64,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self { enabled: true, threshold: 1024 * 1024 * 1024 }
+    }
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(text) => crate::utils::size::parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Expand a leading `~` to the home directory
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_config() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+
+        // A missing file means defaults
+        assert!(Config::load_from(&path).unwrap().history_paths.is_empty());
+
+        std::fs::write(&path, "history_paths = [\"~/.bash_history.d\", \"/tmp/extra_history\"]\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.history_paths.len(), 2);
+        assert!(!config.history_paths[0].starts_with("~"));
+        assert!(config.history_paths[0].ends_with(".bash_history.d"));
+        assert_eq!(config.history_paths[1], PathBuf::from("/tmp/extra_history"));
+
+        std::fs::write(&path, "[recommendations]\nwarning_days = 60\nreview_days = 180\nsize_only = false\n").unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.recommendations.review_days, 180);
+        assert_eq!(config.recommendations.large_size_mb, 100);
+        assert!(!config.recommendations.size_only);
+
+        std::fs::write(&path, "[recommendations]\nwarning_days = 120\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("must be below the review threshold"));
+
+        std::fs::write(&path, "protected_packages = [\"homebrew:node\", \"brw:jq\"]\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid protected package 'brw:jq'"));
+
+        std::fs::write(&path, "scan_jobs = 2\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().scan_jobs, Some(2));
+        std::fs::write(&path, "scan_jobs = 0\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(err.to_string().contains("scan_jobs must be at least 1"));
+
+        std::fs::write(&path, "size_history_scans = 200\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().size_history_scans, Some(200));
+        std::fs::write(&path, "size_history_scans = 0\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(err.to_string().contains("size_history_scans must be at least 1"));
+
+        std::fs::write(&path, "dates = \"both\"\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().dates, DateDisplay::Both);
+        std::fs::write(&path, "dates = \"yesterday\"\n").unwrap();
+        assert!(Config::load_from(&path).is_err());
+
+        std::fs::write(&path, "[timeouts]\nmdls = 10\n").unwrap();
+        let timeouts = Config::load_from(&path).unwrap().timeouts;
+        assert_eq!((timeouts.scanners, timeouts.mdls, timeouts.uninstalls), (60, 10, 300));
+        std::fs::write(&path, "[timeouts]\nuninstalls = 0\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(err.to_string().contains("uninstalls must be at least 1 second"));
+
+        std::fs::write(&path, "[notifications]\nthreshold = \"500MB\"\n").unwrap();
+        let notifications = Config::load_from(&path).unwrap().notifications;
+        assert_eq!(notifications, NotificationConfig { enabled: true, threshold: 500 * 1024 * 1024 });
+        std::fs::write(&path, "[notifications]\nenabled = false\nthreshold = 4096\n").unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().notifications, NotificationConfig { enabled: false, threshold: 4096 });
+        std::fs::write(&path, "[notifications]\nthreshold = \"lots\"\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid size 'lots'"));
+
+        std::fs::write(&path, "[hooks]\npost_clean_webhook = \"https://inventory.example.com/hook\"\nrequired = true\n").unwrap();
+        let hooks = Config::load_from(&path).unwrap().hooks;
+        assert_eq!((hooks.timeout, hooks.required, hooks.is_empty()), (30, true, false));
+        std::fs::write(&path, "[hooks]\npost_clean_command = \"  \"\n").unwrap();
+        assert!(Config::load_from(&path).unwrap().hooks.is_empty());
+        std::fs::write(&path, "[hooks]\npost_clean_webhook = \"inventory.example.com\"\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("must be an http:// or https:// URL"));
+
+        std::fs::write(&path, "[metrics]\ntextfile = \"~/prom/macsweep.prom\"\n").unwrap();
+        let textfile = Config::load_from(&path).unwrap().metrics.textfile.unwrap();
+        assert_eq!(textfile, dirs::home_dir().unwrap().join("prom/macsweep.prom"));
+
+        // Typos are reported rather than silently ignored
+        std::fs::write(&path, "histroy_paths = []\n").unwrap();
+        let err = Config::load_from(&path).unwrap_err();
+        assert!(format!("{:#}", err).contains("Invalid config file"));
+    }
+}
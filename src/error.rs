@@ -0,0 +1,292 @@
+// Errors returned by the library modules (scanners, storage, usage and cleanup), typed so
+// callers can tell a missing tool from a failed command or a broken database. The CLI
+// converts them to anyhow errors for display.
+use std::fmt::Display;
+use thiserror::Error;
+
+pub type Result<T, E = MacsweepError> = std::result::Result<T, E>;
+
+/// Any error that can be parsed out of text: JSON, UTF-8, dates, numbers
+pub type ParseSource = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, Error)]
+pub enum MacsweepError {
+    /// The program a scanner or usage source reads from isn't installed
+    #[error("{program} is not installed")]
+    ScannerUnavailable { program: String },
+
+    /// An external command ran but exited with an error
+    #[error("{program} failed: {}", stderr.trim())]
+    CommandFailed { program: String, stderr: String },
+
+    /// An external command was still running after `timeout` and was killed
+    #[error("{program} timed out after {}s", timeout.as_secs())]
+    TimedOut { program: String, timeout: std::time::Duration },
+
+    #[cfg(feature = "storage")]
+    #[error("{}", context.clone().unwrap_or_else(|| source.to_string()))]
+    Database {
+        context: Option<String>,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    /// Command output, a file or a value wasn't in the expected format
+    #[error("{}", context.clone().unwrap_or_else(|| source.to_string()))]
+    Parse {
+        context: Option<String>,
+        #[source]
+        source: ParseSource,
+    },
+
+    #[error("{}", context.clone().unwrap_or_else(|| source.to_string()))]
+    Io {
+        context: Option<String>,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A `--source` value that names no scanner or package source; `suggestion` is a known
+    /// name close enough to be what was meant
+    #[error(
+        "Unknown source '{name}'{}; known sources: {}",
+        suggestion.as_ref().map(|s| format!(" (did you mean '{}'?)", s)).unwrap_or_default(),
+        known.join(", ")
+    )]
+    UnknownSource { name: String, suggestion: Option<String>, known: Vec<String> },
+
+    /// Anything else, such as a backup that doesn't exist or a plan from another version
+    #[error("{0}")]
+    Other(String),
+}
+
+impl MacsweepError {
+    /// `UnknownSource` for `name`, suggesting whichever of `candidates` it is most likely a
+    /// typo of
+    pub fn unknown_source<'a>(name: &str, known: Vec<String>, candidates: impl IntoIterator<Item = &'a str>) -> Self {
+        let typed = name.to_ascii_lowercase();
+        let suggestion = candidates
+            .into_iter()
+            .map(|candidate| (edit_distance(&typed, candidate), candidate))
+            .filter(|(distance, candidate)| (*distance <= 2 && *distance < typed.len()) || (typed.len() >= 3 && candidate.starts_with(&typed)))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate.to_string());
+        Self::UnknownSource { name: name.to_string(), suggestion, known }
+    }
+
+    /// An external command couldn't be started; a program that isn't there means the
+    /// scanner or usage source relying on it can't run
+    pub fn spawn(program: &str, context: impl Display, source: std::io::Error) -> Self {
+        if source.kind() == std::io::ErrorKind::NotFound {
+            Self::ScannerUnavailable { program: program.to_string() }
+        } else {
+            Self::Io { context: Some(context.to_string()), source }
+        }
+    }
+
+    /// Put `context` in front of the message. Command errors are left alone: they already
+    /// say what ran.
+    pub fn context(self, context: impl Display) -> Self {
+        let prefix = |old: Option<String>| {
+            Some(match old {
+                Some(old) => format!("{}: {}", context, old),
+                None => context.to_string(),
+            })
+        };
+        match self {
+            #[cfg(feature = "storage")]
+            Self::Database { context, source } => Self::Database { context: prefix(context), source },
+            Self::Parse { context, source } => Self::Parse { context: prefix(context), source },
+            Self::Io { context, source } => Self::Io { context: prefix(context), source },
+            Self::Other(message) => Self::Other(format!("{}: {}", context, message)),
+            command => command,
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+impl From<rusqlite::Error> for MacsweepError {
+    fn from(source: rusqlite::Error) -> Self {
+        Self::Database { context: None, source }
+    }
+}
+
+impl From<std::io::Error> for MacsweepError {
+    fn from(source: std::io::Error) -> Self {
+        Self::Io { context: None, source }
+    }
+}
+
+/// Errors from the modules built on anyhow (scan, analysis, config) where they reach the
+/// library's API: a `MacsweepError` nothing was added to comes back as itself, anything else
+/// becomes `Other` with its whole chain
+impl From<anyhow::Error> for MacsweepError {
+    fn from(error: anyhow::Error) -> Self {
+        let bare = error.chain().next().is_some_and(|top| top.is::<MacsweepError>());
+        let message = format!("{:#}", error);
+        match error.downcast::<MacsweepError>() {
+            Ok(error) if bare => error,
+            _ => Self::Other(message),
+        }
+    }
+}
+
+macro_rules! parse_errors {
+    ($($error:ty),*) => {
+        $(impl From<$error> for MacsweepError {
+            fn from(source: $error) -> Self {
+                Self::Parse { context: None, source: Box::new(source) }
+            }
+        })*
+    };
+}
+
+parse_errors!(
+    serde_json::Error,
+    std::string::FromUtf8Error,
+    std::num::ParseIntError,
+    chrono::ParseError
+);
+
+/// `.context(...)` and `.with_context(...)` for results and options, in the style of anyhow's
+/// trait of the same name, keeping the error's kind
+pub trait Context<T> {
+    fn context<C: Display>(self, context: C) -> Result<T>;
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T>;
+}
+
+impl<T, E: Into<MacsweepError>> Context<T> for std::result::Result<T, E> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.map_err(|e| e.into().context(context()))
+    }
+}
+
+impl<T> Context<T> for Option<T> {
+    fn context<C: Display>(self, context: C) -> Result<T> {
+        self.ok_or_else(|| MacsweepError::Other(context.to_string()))
+    }
+
+    fn with_context<C: Display, F: FnOnce() -> C>(self, context: F) -> Result<T> {
+        self.ok_or_else(|| MacsweepError::Other(context().to_string()))
+    }
+}
+
+/// How many single-character insertions, deletions and substitutions turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// `error` followed by each of its causes, like anyhow's `{:#}`; a cause is left out when
+/// the error before it already says the same, as errors without context do
+pub fn chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut previous = message.clone();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let text = cause.to_string();
+        if text != previous {
+            message.push_str(": ");
+            message.push_str(&text);
+        }
+        previous = text;
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::process::run_command_with_timeout;
+    use std::time::Duration;
+
+    #[test]
+    fn test_context_keeps_the_kind() {
+        let err = std::fs::read("/nonexistent/macsweep").context("Cannot read plan").unwrap_err();
+        assert!(matches!(err, MacsweepError::Io { ref source, .. } if source.kind() == std::io::ErrorKind::NotFound));
+        assert_eq!(err.to_string(), "Cannot read plan");
+
+        let err = serde_json::from_str::<u32>("nope").map_err(MacsweepError::from).context("Invalid plan").unwrap_err();
+        assert!(matches!(err, MacsweepError::Parse { .. }));
+        assert!(chain(&err).starts_with("Invalid plan: expected"));
+
+        let err = MacsweepError::Other("Backup not found".to_string()).context("Cannot restore");
+        assert_eq!(err.to_string(), "Cannot restore: Backup not found");
+    }
+
+    #[test]
+    fn test_errors_without_context_show_their_source() {
+        let err = MacsweepError::from(std::io::Error::other("No space left on device"));
+        assert_eq!(err.to_string(), "No space left on device");
+        assert_eq!(chain(&err), "No space left on device");
+
+        let err = MacsweepError::from(serde_json::from_str::<u32>("nope").unwrap_err());
+        assert!(err.to_string().starts_with("expected"), "{}", err);
+    }
+
+    #[test]
+    fn test_from_anyhow_keeps_the_kind_unless_context_was_added() {
+        let unknown = || MacsweepError::unknown_source("brw", vec!["brew".to_string()], ["brew"]);
+        let err = MacsweepError::from(anyhow::Error::from(unknown()));
+        assert!(matches!(err, MacsweepError::UnknownSource { .. }));
+
+        let err = MacsweepError::from(anyhow::Error::from(unknown()).context("Cannot scan"));
+        assert!(matches!(err, MacsweepError::Other(ref message) if message.starts_with("Cannot scan: Unknown source 'brw'")));
+    }
+
+    // A caller deciding what to do from the kind of error rather than its message
+    #[test]
+    fn test_callers_can_match_error_kinds() {
+        fn describe(err: &MacsweepError) -> &'static str {
+            match err {
+                MacsweepError::ScannerUnavailable { .. } => "skip this source",
+                MacsweepError::CommandFailed { .. } => "show the command's stderr",
+                MacsweepError::TimedOut { .. } => "try again later",
+                #[cfg(feature = "storage")]
+                MacsweepError::Database { .. } => "check the database location",
+                MacsweepError::Io { .. } => "check the database location",
+                MacsweepError::UnknownSource { .. } => "list the known sources",
+                MacsweepError::Parse { .. } | MacsweepError::Other(_) => "report a bug",
+            }
+        }
+
+        let missing = run_command_with_timeout("macsweep-no-such-program", &["--version"], Duration::from_secs(10)).unwrap_err();
+        assert!(matches!(missing, MacsweepError::ScannerUnavailable { ref program } if program == "macsweep-no-such-program"));
+        assert_eq!(describe(&missing), "skip this source");
+
+        let failed = run_command_with_timeout("sh", &["-c", "echo 'no such formula' >&2; exit 1"], Duration::from_secs(10)).unwrap_err();
+        match &failed {
+            MacsweepError::CommandFailed { program, stderr } => {
+                assert_eq!(program, "sh");
+                assert_eq!(stderr.trim(), "no such formula");
+            }
+            other => panic!("expected CommandFailed, got {:?}", other),
+        }
+        assert_eq!(failed.to_string(), "sh failed: no such formula");
+
+        #[cfg(feature = "storage")]
+        {
+            let dir = tempfile::TempDir::new().unwrap();
+            let blocker = dir.path().join("not-a-dir");
+            std::fs::write(&blocker, b"").unwrap();
+            let unusable = crate::storage::Database::open(&blocker.join("macsweep.db")).err().unwrap();
+            assert!(matches!(unusable, MacsweepError::Io { .. }));
+            assert_eq!(describe(&unusable), "check the database location");
+        }
+    }
+}
@@ -1,10 +1,29 @@
 // Library exports for macsweep
-// This allows the project to be used as both a binary and a library
+// This allows the project to be used as both a binary and a library. The terminal side
+// (the `cli` feature, on by default) is optional: `scanner`, `usage`, `storage` and `cleanup`
+// build the library without it.
 
+#[cfg(all(feature = "storage", feature = "usage"))]
+pub mod api;
+#[cfg(feature = "cli")]
 pub mod cli;
+#[cfg(feature = "scanner")]
+pub mod config;
+pub mod error;
+pub mod progress;
+#[cfg(all(feature = "storage", feature = "usage"))]
+pub mod scan;
+#[cfg(feature = "scanner")]
 pub mod scanner;
+#[cfg(feature = "scanner")]
 pub mod usage;
+#[cfg(feature = "scanner")]
 pub mod analysis;
+#[cfg(feature = "storage")]
 pub mod storage;
+#[cfg(feature = "cleanup")]
 pub mod cleanup;
 pub mod utils;
+
+#[cfg(all(feature = "storage", feature = "usage"))]
+pub use api::{scan_and_recommend, ScanOptions};
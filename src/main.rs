@@ -1,25 +1,26 @@
 use anyhow::Result;
 use clap::Parser;
 
-mod cli;
-mod scanner;
-mod usage;
-mod analysis;
-mod storage;
-mod cleanup;
-mod utils;
-
-use cli::Cli;
+use macsweep::cli::{self, Cli, Outcome};
 
 fn main() -> Result<()> {
-    // Initialize tracing/logging
-    tracing_subscriber::fmt::init();
-
     // Parse command line arguments
     let cli = Cli::parse();
 
-    // Execute the command
-    cli::execute(cli)?;
+    // Log to stderr, above any progress bars; closing a span logs how long it took, so -vv
+    // times each scanner and the usage lookups
+    tracing_subscriber::fmt()
+        .with_env_filter(cli::log_filter(cli.verbose, cli.quiet))
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_ansi(cli::output::log_color_enabled(cli.no_color))
+        .with_writer(|| cli::output::ProgressAwareStderr)
+        .init();
+
+    // Execute the command; errors exit with 1 via anyhow, other outcomes map to their own codes
+    let outcome = cli::execute(cli)?;
+    if outcome != Outcome::Success {
+        std::process::exit(outcome.exit_code());
+    }
 
     Ok(())
 }
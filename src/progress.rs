@@ -0,0 +1,50 @@
+// Progress reporting for long-running library calls, so the same loops can drive the CLI's
+// progress bars, a GUI, or nothing at all
+use std::fmt;
+
+/// A long-running stage of a library call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// Running the package scanners, one item per source
+    Scan,
+    /// Looking up how each package is used
+    Usage,
+    /// Carrying out cleanup actions, one item per package
+    Cleanup,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Scan => "scan",
+            Phase::Usage => "usage",
+            Phase::Cleanup => "cleanup",
+        })
+    }
+}
+
+/// Where one item of a phase has got to. Phases that work on several items at once tell
+/// them apart by `worker`, which stays below the number of items running at the same time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemProgress<'a> {
+    Started { name: &'a str, worker: usize },
+    Finished { name: &'a str, worker: usize },
+}
+
+/// Receives progress from `scan::scan_all`, `usage::aggregate_all_usage` and
+/// `cleanup::runner::execute_cleanup`. Calls may come from several threads at once.
+pub trait ProgressReporter: Sync {
+    /// `total` items are about to be processed
+    fn on_phase_start(&self, _phase: Phase, _total: u64) {}
+
+    fn on_item(&self, _phase: Phase, _item: ItemProgress) {}
+
+    /// Every item has finished, or the phase was stopped early
+    fn on_phase_done(&self, _phase: Phase) {}
+}
+
+/// Reports nothing; for library callers that don't show progress
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
@@ -0,0 +1,875 @@
+// Scanning installed packages and their usage into the database. Nothing here prints: what
+// happens is reported through `ScanEvent`s, how far it has got to a `ProgressReporter`, and
+// the outcome returned as a `ScanResult`
+use crate::api::ScanOptions;
+use crate::config::Config;
+use crate::error::MacsweepError;
+use crate::progress::{ItemProgress, Phase, ProgressReporter};
+use crate::scanner::registry::{RegisteredScanner, ScannerRegistry};
+use crate::scanner::{ChangeSignals, Package, PackageSource, ScanContext};
+use crate::storage::{database, Database};
+use crate::usage::{UsageContext, UsageInfo};
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::utils::size::SizeCache;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many scans of per-package size history are kept unless `size_history_scans` is set
+const SIZE_HISTORY_SCANS: usize = 50;
+
+/// Outcome of scanning a single source
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceScan {
+    pub source: String,
+    pub available: bool,
+    pub packages: usize,
+    pub error: Option<String>,
+    /// Why the source wasn't scanned, e.g. its Cellar is unchanged since the last scan
+    pub skipped: Option<String>,
+    /// The package sources the scanner listed in full: those it reports, less any it could
+    /// only partly scan
+    #[serde(skip)]
+    pub covers: Vec<PackageSource>,
+    /// The scanner's name in the registry
+    #[serde(skip)]
+    pub name: &'static str,
+    /// The scanner's change signals from just before it ran
+    #[serde(skip)]
+    pub signals: ChangeSignals,
+    /// Whether the scanner was skipped because a command it ran outlasted its timeout
+    #[serde(skip)]
+    pub timed_out: bool,
+}
+
+impl SourceScan {
+    /// Whether the scan went through, so packages missing from it were uninstalled
+    pub fn succeeded(&self) -> bool {
+        self.available && self.error.is_none() && self.skipped.is_none()
+    }
+}
+
+/// Everything a scan found and did
+#[derive(Debug, Clone, Default)]
+pub struct ScanResult {
+    pub packages: Vec<Package>,
+    pub sources: Vec<SourceScan>,
+    /// Packages found per source, keyed by `PackageSource::as_str`
+    pub per_source_counts: BTreeMap<String, usize>,
+    /// Problems that didn't stop the scan, e.g. a package whose usage couldn't be looked up
+    pub warnings: Vec<String>,
+    /// Packages whose usage lookup failed, by error message; they keep the usage recorded by
+    /// earlier scans
+    pub usage_failures: BTreeMap<String, Vec<String>>,
+    /// The whole scan, saving included
+    pub duration: Duration,
+    /// Running the scanners, package sizes included
+    pub scan_duration: Duration,
+    /// Package sizes taken from earlier scans because their directories were unmodified
+    pub sizes_reused: usize,
+    /// Package sizes measured by walking their directories
+    pub sizes_measured: usize,
+    /// Usage lookups, unless the scan was quick
+    pub usage_duration: Option<Duration>,
+    /// Time the usage lookups took combined, divided by the wall-clock time they took in
+    /// parallel; None when they were too quick for the ratio to mean anything
+    pub usage_speedup: Option<f64>,
+    pub saved: bool,
+    /// Previously recorded packages the scan no longer found
+    pub removed: usize,
+}
+
+impl ScanResult {
+    pub fn total_size_bytes(&self) -> u64 {
+        self.packages.iter().filter_map(|p| p.size_bytes).sum()
+    }
+
+    /// Whether a source failed to scan or the results couldn't be saved
+    pub fn has_failures(&self) -> bool {
+        !self.saved || self.sources.iter().any(|s| s.error.is_some())
+    }
+}
+
+/// What `scan` is doing, in the order things happen; how far each phase has got goes to its
+/// `ProgressReporter` instead. The per-source events come once every scanner has finished, in
+/// the order the scanners were given, however long each took.
+#[derive(Debug)]
+pub enum ScanEvent<'a> {
+    SourceUnavailable { label: &'a str },
+    SourceStarted { label: &'a str },
+    /// `unit` names what was counted, e.g. "packages" or "apps"
+    SourceScanned { label: &'a str, unit: &'a str, count: usize },
+    SourceFailed { label: &'a str, error: &'a str },
+    /// Left out of an incremental scan; its packages stay as recorded
+    SourceSkipped { label: &'a str, reason: &'a str },
+    /// A command the scanner ran outlasted its timeout; its packages stay as recorded
+    SourceTimedOut { label: &'a str, error: &'a str },
+    /// Every scanner has finished; `sizes_reused` package sizes came from the size cache
+    SourcesScanned { duration: Duration, sizes_reused: usize, sizes_measured: usize },
+    /// Every source has been scanned
+    PackagesFound { packages: &'a [Package] },
+    UsageStarted,
+    UsageFinished { duration: Duration, speedup: Option<f64> },
+    Saving,
+    Saved { removed: usize },
+    SaveFailed { error: &'a str },
+}
+
+/// What running one scanner came to
+enum ScannerOutcome {
+    Unavailable,
+    /// The change signals are as `previous` has them; the reason says which were checked
+    Unchanged(String),
+    /// With the sources listed, when not all of those the scanner covers
+    Scanned(Vec<Package>, ChangeSignals, Option<Vec<PackageSource>>),
+    TimedOut(String),
+    Failed(String),
+}
+
+/// Run `scanners`, e.g. the ones `ScannerRegistry::select` picked, created with `context`, up to
+/// `jobs` at once. Each runs on its own thread and a scanner that panics, or whose thread dies
+/// while it runs, counts as failed; the packages and events
+/// come back in the order of `scanners`. Scanners whose change signals match those in
+/// `previous`, by scanner name, are skipped.
+pub fn scan_all(
+    scanners: &[&RegisteredScanner],
+    context: &ScanContext,
+    previous: Option<&HashMap<String, ChangeSignals>>,
+    jobs: usize,
+    progress: &dyn ProgressReporter,
+    on_event: &(dyn Fn(ScanEvent) + Sync),
+) -> (Vec<Package>, Vec<SourceScan>) {
+    progress.on_phase_start(Phase::Scan, scanners.len() as u64);
+
+    let next = AtomicUsize::new(0);
+    // Each scanner's outcome goes in its own slot as soon as it is known, so a worker that
+    // dies (e.g. in the progress callback) only leaves the scanner it was on without one
+    let outcomes: Mutex<Vec<Option<ScannerOutcome>>> = Mutex::new(scanners.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, scanners.len().max(1)))
+            .map(|worker| {
+                let (next, outcomes) = (&next, &outcomes);
+                scope.spawn(move || loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(registered) = scanners.get(index) else {
+                        break;
+                    };
+                    progress.on_item(Phase::Scan, ItemProgress::Started { name: registered.label, worker });
+                    let unchanged_from = previous.and_then(|previous| previous.get(registered.name));
+                    let outcome = run_scanner(registered, context, unchanged_from);
+                    outcomes.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(outcome);
+                    progress.on_item(Phase::Scan, ItemProgress::Finished { name: registered.label, worker });
+                })
+            })
+            .collect();
+        for worker in workers {
+            if worker.join().is_err() {
+                tracing::warn!("A scan worker panicked");
+            }
+        }
+    });
+    progress.on_phase_done(Phase::Scan);
+    let outcomes = outcomes.into_inner().unwrap_or_else(|e| e.into_inner());
+
+    let mut packages = Vec::new();
+    let mut scans = Vec::new();
+    for (registered, outcome) in scanners.iter().zip(outcomes) {
+        let outcome = outcome.unwrap_or_else(|| ScannerOutcome::Failed("scan worker panicked".to_string()));
+        let (label, unit) = (registered.label, registered.unit);
+        let mut scan = SourceScan {
+            source: label.to_string(),
+            available: true,
+            packages: 0,
+            error: None,
+            skipped: None,
+            covers: registered.covers(),
+            name: registered.name,
+            signals: ChangeSignals::new(),
+            timed_out: false,
+        };
+        match outcome {
+            ScannerOutcome::Unavailable => {
+                on_event(ScanEvent::SourceUnavailable { label });
+                scan.available = false;
+            }
+            ScannerOutcome::Unchanged(reason) => {
+                on_event(ScanEvent::SourceSkipped { label, reason: &reason });
+                scan.skipped = Some(reason);
+            }
+            ScannerOutcome::Scanned(found, signals, listed) => {
+                on_event(ScanEvent::SourceStarted { label });
+                on_event(ScanEvent::SourceScanned { label, unit, count: found.len() });
+                scan.packages = found.len();
+                scan.signals = signals;
+                if let Some(listed) = listed {
+                    scan.covers.retain(|source| listed.contains(source));
+                }
+                packages.extend(found);
+            }
+            ScannerOutcome::TimedOut(error) => {
+                on_event(ScanEvent::SourceStarted { label });
+                on_event(ScanEvent::SourceTimedOut { label, error: &error });
+                scan.skipped = Some(error);
+                scan.timed_out = true;
+            }
+            ScannerOutcome::Failed(error) => {
+                on_event(ScanEvent::SourceStarted { label });
+                on_event(ScanEvent::SourceFailed { label, error: &error });
+                scan.error = Some(error);
+            }
+        }
+        scans.push(scan);
+    }
+
+    (packages, scans)
+}
+
+/// Create and run one scanner, catching a panic so the other sources are still scanned. It
+/// isn't run if its change markers exist and are as `unchanged_from` recorded them.
+fn run_scanner(registered: &RegisteredScanner, context: &ScanContext, unchanged_from: Option<&ChangeSignals>) -> ScannerOutcome {
+    let _span = tracing::debug_span!("scanner", source = registered.name).entered();
+    let run = || {
+        let scanner = registered.scanner(context);
+        if !scanner.is_available() {
+            return ScannerOutcome::Unavailable;
+        }
+        // Taken before scanning, so changes made during the scan are picked up next time
+        let markers = scanner.change_markers();
+        let signals = crate::scanner::change_signals(&markers);
+        if unchanged_from == Some(&signals) && signals.values().any(Option::is_some) {
+            let markers: Vec<_> = markers.iter().map(|marker| marker.display().to_string()).collect();
+            return ScannerOutcome::Unchanged(format!("{} unchanged since the last scan", markers.join(", ")));
+        }
+        match scanner.scan_sources() {
+            Ok((found, listed)) => {
+                tracing::info!(source = registered.name, packages = found.len(), "Scanned");
+                ScannerOutcome::Scanned(found, signals, listed)
+            }
+            Err(e @ MacsweepError::TimedOut { .. }) => ScannerOutcome::TimedOut(e.to_string()),
+            Err(e) => ScannerOutcome::Failed(e.to_string()),
+        }
+    };
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown cause".to_string());
+        ScannerOutcome::Failed(format!("scanner panicked: {}", message))
+    })
+}
+
+/// Scan the installed packages (only those of `options.sources`, if any are given), look up how
+/// they're used unless `options.quick`, and save them. Directory sizes are reused from earlier
+/// scans while the directory is unmodified, unless `options.recalculate_sizes`, and not measured
+/// at all with `options.skip_sizes`; with `options.changed_only`, whole sources whose change
+/// markers are unmodified are reused too. Errors
+/// mean a source names no known source or the database couldn't be read; a source that fails to
+/// scan or a failed save is reported in the result instead, and a source whose commands time
+/// out (after `options.config.timeouts`) is a warning.
+pub fn scan(
+    db: &mut Database,
+    options: &ScanOptions,
+    progress: &dyn ProgressReporter,
+    on_event: &(dyn Fn(ScanEvent) + Sync),
+) -> Result<ScanResult> {
+    let ScanOptions { ref sources, quick, recalculate_sizes, skip_sizes, changed_only, ref config, .. } = *options;
+    let registry = ScannerRegistry::default();
+    let scanners = registry.select_all(sources)?;
+    let recorded_as = scan_type(sources);
+    let start = Instant::now();
+    let jobs = config.scan_jobs.unwrap_or(scanners.len());
+    let previous = if changed_only { Some(database::get_source_signals(db.conn())?) } else { None };
+    let sizes = Arc::new(match skip_sizes {
+        true => SizeCache::skipping(),
+        false => SizeCache::new(database::get_directory_sizes(db.conn())?, recalculate_sizes),
+    });
+    let context = ScanContext { timeouts: config.timeouts, sizes: sizes.clone() };
+    let (packages, sources) = scan_all(&scanners, &context, previous.as_ref(), jobs, progress, on_event);
+    let measured_sizes = sizes.measured();
+    // The sizes are still right if saving the packages fails later
+    database::save_directory_sizes(db.conn(), &measured_sizes)?;
+    let mut result = ScanResult {
+        packages,
+        sources,
+        scan_duration: start.elapsed(),
+        sizes_reused: sizes.reused(),
+        sizes_measured: measured_sizes.len(),
+        ..Default::default()
+    };
+    if skip_sizes {
+        database::carry_over_sizes(db.conn(), &mut result.packages)?;
+    }
+    for source in result.sources.iter().filter(|s| s.timed_out) {
+        let error = source.skipped.as_deref().unwrap_or_default();
+        result.warnings.push(format!("{}: {}; keeping its packages from the last scan", source.source, error));
+    }
+    on_event(ScanEvent::SourcesScanned {
+        duration: result.scan_duration,
+        sizes_reused: result.sizes_reused,
+        sizes_measured: result.sizes_measured,
+    });
+    // Sources that scanned successfully and can be reconciled with the database
+    let scanned_sources: Vec<PackageSource> = result.sources.iter()
+        .filter(|s| s.succeeded())
+        .flat_map(|s| s.covers.iter().cloned())
+        .collect();
+
+    for package in &result.packages {
+        *result.per_source_counts.entry(package.source.as_str().to_string()).or_insert(0) += 1;
+    }
+    on_event(ScanEvent::PackagesFound { packages: &result.packages });
+
+    // Usage information is kept per package so the observations can be recorded as events
+    let mut usage: Vec<Option<UsageInfo>> = Vec::new();
+    let mut usage_context = None;
+    if !quick {
+        let context = gather_usage(db, config, &mut result, &mut usage, progress, on_event)?;
+        usage_context = Some(context);
+    } else {
+        database::carry_over_usage(db.conn(), &mut result.packages)?;
+    }
+
+    // Manual marks record what scans can't see, so they outlast older automatic data
+    let marks = database::get_manual_marks(db.conn())?;
+    if !marks.is_empty() {
+        for package in &mut result.packages {
+            if let Some(mark) = marks.get(&(package.name.clone(), package.source.to_string())) {
+                mark.apply(package);
+            }
+        }
+    }
+
+    // Save to database (even an empty result reconciles packages that were uninstalled)
+    if !result.packages.is_empty() || !scanned_sources.is_empty() {
+        on_event(ScanEvent::Saving);
+        let saved = tracing::debug_span!("save", packages = result.packages.len()).in_scope(|| {
+            save_packages(
+                db,
+                &result.packages,
+                &usage,
+                usage_context.as_ref(),
+                &ScanRecord {
+                    scan_type: &recorded_as,
+                    duration_ms: result.scan_duration.as_millis() as i64,
+                    sizes_measured: !skip_sizes,
+                    size_history_scans: config.size_history_scans.unwrap_or(SIZE_HISTORY_SCANS),
+                },
+                &scanned_sources,
+            )
+        });
+        match saved {
+            Ok(removed) => {
+                result.saved = true;
+                result.removed = removed;
+                // Only now that the packages are saved may the next incremental scan skip them
+                for source in result.sources.iter().filter(|s| s.succeeded()) {
+                    database::save_source_signals(db.conn(), source.name, &source.signals)?;
+                }
+                on_event(ScanEvent::Saved { removed });
+            }
+            Err(e) => {
+                let error = format!("{:#}", e);
+                on_event(ScanEvent::SaveFailed { error: &error });
+                result.warnings.push(format!("Failed to save to database: {}", error));
+            }
+        }
+    }
+
+    result.duration = start.elapsed();
+    Ok(result)
+}
+
+/// What a scan of `sources` is recorded as: "full", or the sources as given, e.g. "npm"
+pub fn scan_type(sources: &[String]) -> String {
+    if sources.is_empty() {
+        "full".to_string()
+    } else {
+        sources.join(",")
+    }
+}
+
+/// Look up how each package in `result` is used, filling in its usage fields and pushing
+/// what was observed onto `usage` in package order
+fn gather_usage(
+    db: &Database,
+    config: &Config,
+    result: &mut ScanResult,
+    usage: &mut Vec<Option<UsageInfo>>,
+    progress: &dyn ProgressReporter,
+    on_event: &(dyn Fn(ScanEvent) + Sync),
+) -> Result<UsageContext> {
+    let _span = tracing::debug_span!("usage", packages = result.packages.len()).entered();
+    let start = Instant::now();
+    on_event(ScanEvent::UsageStarted);
+
+    let history_files = database::get_history_files(db.conn())?;
+    let mut context = UsageContext::for_packages(&result.packages, config, &history_files);
+    context.recorded_spotlight = database::get_latest_usage_events(db.conn(), "spotlight")?;
+    // Shell history is only read from where the last scan stopped, so it adds to the stored totals
+    let stored = if context.history_is_complete {
+        HashMap::new()
+    } else {
+        database::get_usage_totals(db.conn())?
+    };
+
+    // Lookups run in parallel but come back in package order, so results are applied below
+    // exactly as a serial loop would
+    let lookups = crate::usage::aggregate_all_usage(&result.packages, &context, progress);
+
+    let serial_duration: Duration = lookups.iter().map(|(_, elapsed)| *elapsed).sum();
+
+    let mut failed = Vec::new();
+    for (index, (package, (lookup, _))) in result.packages.iter_mut().zip(lookups).enumerate() {
+        match lookup {
+            Ok(usage_info) => {
+                package.launches_at_login = usage_info.launches_at_login;
+                if context.history_is_complete {
+                    package.last_used = usage_info.last_used;
+                    package.usage_count = usage_info.usage_count;
+                    package.usage_confidence = usage_info.confidence;
+                } else {
+                    let key = (package.name.clone(), package.source.to_string());
+                    let (last_used, count, confidence) = stored.get(&key).copied().unwrap_or_default();
+                    (package.last_used, package.usage_count) = usage_info.accumulate(last_used, count);
+                    // Evidence from history read by earlier scans still stands
+                    package.usage_confidence = usage_info.confidence.max(confidence);
+                }
+                usage.push(Some(usage_info));
+            }
+            Err(e) => {
+                // Don't fail the scan if usage tracking fails
+                let warning = format!("Failed to get usage for {}: {}", package.name, e);
+                // The scan summarizes these at the end, so they're only logged in detail
+                tracing::debug!(package = %package.name, source = %package.source, error = %e, "Usage lookup failed");
+                result.warnings.push(warning);
+                result.usage_failures.entry(crate::error::chain(&e)).or_default().push(package.name.clone());
+                failed.push(index);
+                usage.push(None);
+            }
+        }
+    }
+    keep_recorded_usage(db, &mut result.packages, failed)?;
+
+    let duration = start.elapsed();
+    result.usage_duration = Some(duration);
+    // Below this, thread start-up dominates and the ratio means nothing
+    let speedup = (serial_duration >= Duration::from_millis(100))
+        .then(|| serial_duration.as_secs_f64() / duration.as_secs_f64().max(f64::EPSILON));
+    result.usage_speedup = speedup.map(|speedup| (speedup * 10.0).round() / 10.0);
+    on_event(ScanEvent::UsageFinished { duration, speedup });
+
+    Ok(context)
+}
+
+/// Give the packages at `indices`, whose usage lookups failed, the usage earlier scans recorded;
+/// saving what the failed lookup found (nothing) would wipe it out
+fn keep_recorded_usage(db: &Database, packages: &mut [Package], indices: Vec<usize>) -> Result<()> {
+    if indices.is_empty() {
+        return Ok(());
+    }
+    let mut failed: Vec<Package> = indices.iter().map(|&index| packages[index].clone()).collect();
+    database::carry_over_usage(db.conn(), &mut failed)?;
+    for (index, package) in indices.into_iter().zip(failed) {
+        packages[index] = package;
+    }
+    Ok(())
+}
+
+/// How a scan is recorded in the scan history
+struct ScanRecord<'a> {
+    scan_type: &'a str,
+    duration_ms: i64,
+    /// Whether the packages' sizes were measured by this scan, rather than carried over, and
+    /// so belong in their size history
+    sizes_measured: bool,
+    /// How many scans of size history to keep
+    size_history_scans: usize,
+}
+
+/// Save scan results, returning how many previously recorded packages were marked removed
+fn save_packages(
+    db: &mut Database,
+    packages: &[Package],
+    usage: &[Option<UsageInfo>],
+    usage_context: Option<&UsageContext>,
+    record: &ScanRecord,
+    scanned_sources: &[PackageSource],
+) -> Result<usize> {
+    let ScanRecord { scan_type, duration_ms, sizes_measured, size_history_scans } = *record;
+    // One transaction for the whole save: much faster, and a failed save leaves the previous scan intact
+    let tx = db.transaction()?;
+
+    // Record the scan first so package sizes can be linked to it
+    let total_size: u64 = packages.iter().filter_map(|p| p.size_bytes).sum();
+    let mut source_counts = BTreeMap::new();
+    for package in packages {
+        *source_counts.entry(package.source.to_string()).or_insert(0) += 1;
+    }
+    let scan_id = database::insert_scan(&tx, scan_type, packages.len() as i64, duration_ms, total_size as i64, &source_counts)?;
+
+    let stored = database::get_usage_totals(&tx)?;
+    let previous_last_used: Vec<_> = packages.iter()
+        .map(|p| stored.get(&(p.name.clone(), p.source.to_string())).and_then(|(last_used, ..)| *last_used))
+        .collect();
+    let package_ids = database::upsert_packages(&tx, packages, Some(scan_id).filter(|_| sizes_measured))?;
+    database::prune_size_history(&tx, size_history_scans)?;
+
+    // Record the usage observed since the previous scan
+    for (idx, package_id) in package_ids.into_iter().enumerate() {
+        if let Some(Some(info)) = usage.get(idx) {
+            record_usage_events(&tx, package_id, info, previous_last_used[idx])?;
+            database::save_usage_checks(&tx, package_id, &info.checks)?;
+        }
+    }
+
+    // Anything from a successfully scanned source that wasn't found was uninstalled elsewhere
+    let mut removed = 0;
+    for scanned in scanned_sources {
+        let seen: HashSet<&str> = packages.iter()
+            .filter(|p| p.source == *scanned)
+            .map(|p| p.name.as_str())
+            .collect();
+        removed += database::mark_removed_packages(&tx, scanned, &seen)?;
+    }
+
+    if let Some(context) = usage_context {
+        database::save_history_files(&tx, &context.history_files)?;
+        if !context.history_is_complete {
+            credit_unscanned_packages(&tx, packages, &context.history)?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// Add newly read shell history to packages this scan didn't cover (e.g. with `--source`),
+/// since that history won't be read again
+fn credit_unscanned_packages(
+    conn: &rusqlite::Connection,
+    scanned: &[Package],
+    history: &crate::usage::shell_history::HistoryIndex,
+) -> Result<()> {
+    let scanned: HashSet<(&str, &str)> = scanned.iter()
+        .map(|p| (p.name.as_str(), p.source.as_str()))
+        .collect();
+
+    for package in database::get_packages(conn)? {
+        if scanned.contains(&(package.name.as_str(), package.source.as_str())) {
+            continue;
+        }
+
+        if let Some((last_used, count, _)) = crate::usage::aggregator::shell_usage(&package, history) {
+            if let Some(id) = database::get_package_id(conn, &package.name, &package.source)? {
+                let last_used = package.last_used.map_or(last_used, |stored| stored.max(last_used));
+                database::update_package_usage(conn, id, Some(last_used), package.usage_count + count, crate::usage::UsageConfidence::High)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Store usage observations newer than `since`, so repeated scans don't replay old history.
+/// Every distinct Spotlight date is kept: Spotlight only reports the latest launch, so these
+/// observations are the only record of earlier ones.
+fn record_usage_events(
+    conn: &rusqlite::Connection,
+    package_id: i64,
+    info: &UsageInfo,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<()> {
+    for source in &info.sources {
+        if let Some(event_date) = source.event_date() {
+            let timeline = matches!(source, crate::usage::UsageSource::SpotlightMetadata { .. });
+            if timeline || since.is_none_or(|since| event_date > since) {
+                database::insert_usage_event(conn, package_id, source.event_type(), event_date, source.details())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use crate::scanner::registry::SourceGroup;
+    use crate::scanner::Scanner;
+    use crate::usage::UsageSource;
+    use std::sync::{Arc, Barrier, Mutex};
+
+    struct Fixed(&'static str, PackageSource);
+
+    impl Scanner for Fixed {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            Ok(vec![Package::new(self.0.to_string(), self.1.clone())])
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    struct Panics;
+
+    impl Scanner for Panics {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            panic!("malformed output")
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    /// Runs a command that never finishes in time
+    struct Hangs;
+
+    impl Scanner for Hangs {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            crate::utils::process::run_command_with_timeout("sleep", &["30"], Duration::from_secs(1))?;
+            Ok(Vec::new())
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    /// Only finishes once every scanner sharing the barrier is running
+    struct Waits(Arc<Barrier>, &'static str);
+
+    impl Scanner for Waits {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            self.0.wait();
+            Ok(vec![Package::new(self.1.to_string(), PackageSource::Npm)])
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    /// Lists pip packages but fails to list pipx's
+    struct PartlyScans;
+
+    impl Scanner for PartlyScans {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            self.scan_sources().map(|(packages, _)| packages)
+        }
+        fn scan_sources(&self) -> crate::error::Result<(Vec<Package>, Option<Vec<PackageSource>>)> {
+            Ok((vec![Package::new("requests".to_string(), PackageSource::Pip)], Some(vec![PackageSource::Pip])))
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+    }
+
+    /// A scanner whose packages are installed in a directory
+    struct Installs(std::path::PathBuf);
+
+    impl Scanner for Installs {
+        fn scan(&self) -> crate::error::Result<Vec<Package>> {
+            let names = std::fs::read_dir(&self.0)?.map(|e| Ok(e?.file_name().to_string_lossy().into_owned()));
+            names.map(|name| name.map(|name| Package::new(name, PackageSource::Gem))).collect()
+        }
+        fn is_available(&self) -> bool {
+            true
+        }
+        fn change_markers(&self) -> Vec<std::path::PathBuf> {
+            vec![self.0.clone()]
+        }
+    }
+
+    fn registered(name: &'static str, new: impl Fn(&ScanContext) -> Box<dyn Scanner> + Send + Sync + 'static) -> RegisteredScanner {
+        RegisteredScanner::new(name, &[], name, "packages", &[SourceGroup { sources: &[PackageSource::Gem], label: "gems" }], new)
+    }
+
+    fn describe(event: ScanEvent) -> String {
+        match event {
+            ScanEvent::SourceStarted { label } => format!("started {}", label),
+            ScanEvent::SourceScanned { label, count, .. } => format!("{} found {}", label, count),
+            ScanEvent::SourceFailed { label, error } => format!("{} failed: {}", label, error),
+            ScanEvent::SourceTimedOut { label, error } => format!("{} timed out: {}", label, error),
+            other => format!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_scanners_run_together_and_report_in_order() {
+        let barrier = Arc::new(Barrier::new(2));
+        let (first, second) = (barrier.clone(), barrier);
+        let scanners = [
+            registered("first", move |_| Box::new(Waits(first.clone(), "left-pad"))),
+            registered("second", move |_| Box::new(Waits(second.clone(), "is-odd"))),
+            registered("third", |_| Box::new(Fixed("rails", PackageSource::Gem))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        let events = Mutex::new(Vec::new());
+        let (packages, scans) = scan_all(&scanners, &ScanContext::default(), None, 2, &NoProgress, &|event| events.lock().unwrap().push(describe(event)));
+
+        let names: Vec<_> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["left-pad", "is-odd", "rails"]);
+        assert_eq!(scans.iter().map(|s| s.packages).collect::<Vec<_>>(), [1, 1, 1]);
+        assert_eq!(
+            events.into_inner().unwrap(),
+            ["started first", "first found 1", "started second", "second found 1", "started third", "third found 1"]
+        );
+    }
+
+    #[test]
+    fn test_panicking_scanner_fails_alone() {
+        let scanners = [
+            registered("broken", |_| Box::new(Panics)),
+            registered("gem", |_| Box::new(Fixed("rails", PackageSource::Gem))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        for jobs in [1, 2] {
+            let (packages, scans) = scan_all(&scanners, &ScanContext::default(), None, jobs, &NoProgress, &|_| {});
+            assert_eq!(packages.len(), 1);
+            assert_eq!(scans[0].error.as_deref(), Some("scanner panicked: malformed output"));
+            assert!(!scans[0].succeeded());
+            assert!(scans[1].succeeded());
+        }
+    }
+
+    /// Progress that panics when `0` starts, outside the scanner's own catch_unwind
+    struct PanicsOnStart(&'static str);
+
+    impl ProgressReporter for PanicsOnStart {
+        fn on_item(&self, _phase: Phase, item: ItemProgress) {
+            if matches!(item, ItemProgress::Started { name, .. } if name == self.0) {
+                panic!("progress bar gone");
+            }
+        }
+    }
+
+    #[test]
+    fn test_dead_worker_fails_only_its_scanner() {
+        let scanners = [
+            registered("first", |_| Box::new(Fixed("left-pad", PackageSource::Npm))),
+            registered("second", |_| Box::new(Fixed("rails", PackageSource::Gem))),
+            registered("third", |_| Box::new(Fixed("requests", PackageSource::Pip))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        let (packages, scans) = scan_all(&scanners, &ScanContext::default(), None, 2, &PanicsOnStart("first"), &|_| {});
+        assert_eq!(packages.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["rails", "requests"]);
+        assert_eq!(scans[0].error.as_deref(), Some("scan worker panicked"));
+        assert!(scans[1].succeeded() && scans[2].succeeded());
+        assert_eq!(scans.iter().map(|s| s.packages).collect::<Vec<_>>(), [0, 1, 1]);
+    }
+
+    #[test]
+    fn test_partial_scan_only_covers_the_sources_listed() {
+        let groups = &[SourceGroup { sources: &[PackageSource::Pip, PackageSource::Pipx], label: "pip/pipx packages" }];
+        let scanners = [
+            RegisteredScanner::new("pip", &[], "pip/pipx", "packages", groups, |_| Box::new(PartlyScans)),
+            registered("gem", |_| Box::new(Fixed("rails", PackageSource::Gem))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        let (packages, scans) = scan_all(&scanners, &ScanContext::default(), None, 2, &NoProgress, &|_| {});
+        assert_eq!(packages.len(), 2);
+        assert!(scans[0].succeeded());
+        assert_eq!(scans[0].covers, [PackageSource::Pip]);
+        assert_eq!(scans[1].covers, [PackageSource::Gem]);
+    }
+
+    #[test]
+    fn test_timed_out_scanner_is_skipped() {
+        let scanners = [
+            registered("stuck", |_| Box::new(Hangs)),
+            registered("gem", |_| Box::new(Fixed("rails", PackageSource::Gem))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        let events = Mutex::new(Vec::new());
+        let (packages, scans) = scan_all(&scanners, &ScanContext::default(), None, 2, &NoProgress, &|event| events.lock().unwrap().push(describe(event)));
+        assert_eq!(packages.len(), 1);
+        assert!(scans[0].timed_out);
+        assert!(scans[0].error.is_none());
+        assert_eq!(scans[0].skipped.as_deref(), Some("sleep timed out after 1s"));
+        assert!(!scans[0].succeeded());
+        assert_eq!(events.into_inner().unwrap()[..2], ["started stuck", "stuck timed out: sleep timed out after 1s"]);
+    }
+
+    #[test]
+    fn test_failed_usage_lookups_keep_recorded_usage() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let db = Database::open(&dir.path().join("macsweep.db")).unwrap();
+        let last_used = chrono::Utc::now() - chrono::Duration::days(12);
+        for name in ["jq", "fd"] {
+            let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+            package.last_used = Some(last_used);
+            package.usage_count = 9;
+            database::upsert_package(db.conn(), &package).unwrap();
+        }
+
+        // The lookup for jq worked and found nothing new; fd's failed
+        let mut packages = vec![
+            Package::new("jq".to_string(), PackageSource::Homebrew),
+            Package::new("fd".to_string(), PackageSource::Homebrew),
+        ];
+        keep_recorded_usage(&db, &mut packages, vec![1]).unwrap();
+        assert_eq!((packages[0].last_used, packages[0].usage_count), (None, 0));
+        assert_eq!(packages[1].last_used.map(|d| d.timestamp()), Some(last_used.timestamp()));
+        assert_eq!(packages[1].usage_count, 9);
+    }
+
+    #[test]
+    fn test_only_usage_newer_than_the_stored_last_use_is_recorded() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut db = Database::open(&dir.path().join("macsweep.db")).unwrap();
+        let now = chrono::Utc::now();
+        let mut jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        jq.last_used = Some(now - chrono::Duration::days(10));
+        database::upsert_package(db.conn(), &jq).unwrap();
+
+        // The file access predates the stored last use, so an earlier scan already counted it
+        let usage = UsageInfo {
+            sources: vec![
+                UsageSource::FileAccessTime { atime: now - chrono::Duration::days(20) },
+                UsageSource::ShellHistory { count: 1, last_used: now - chrono::Duration::days(1), command: "jq .".to_string() },
+            ],
+            ..Default::default()
+        };
+        let record = ScanRecord { scan_type: "full", duration_ms: 1, sizes_measured: false, size_history_scans: SIZE_HISTORY_SCANS };
+        save_packages(&mut db, &[jq], &[Some(usage)], None, &record, &[]).unwrap();
+
+        let id = database::get_package_id(db.conn(), "jq", &PackageSource::Homebrew).unwrap().unwrap();
+        let events = database::get_usage_events(db.conn(), id).unwrap();
+        assert_eq!(events.iter().map(|e| e.event_type.as_str()).collect::<Vec<_>>(), ["shell"]);
+    }
+
+    #[test]
+    fn test_unchanged_scanners_are_skipped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::create_dir(dir.path().join("rails")).unwrap();
+        let gems = dir.path().to_path_buf();
+        let scanners = [
+            registered("gem", move |_| Box::new(Installs(gems.clone()))),
+            registered("fixed", |_| Box::new(Fixed("left-pad", PackageSource::Npm))),
+        ];
+        let scanners: Vec<_> = scanners.iter().collect();
+
+        let (_, first) = scan_all(&scanners, &ScanContext::default(), None, 2, &NoProgress, &|_| {});
+        assert!(first[1].signals.is_empty());
+        let previous: HashMap<_, _> = first.iter().map(|s| (s.name.to_string(), s.signals.clone())).collect();
+
+        // Scanners without change markers always run
+        let (packages, again) = scan_all(&scanners, &ScanContext::default(), Some(&previous), 2, &NoProgress, &|_| {});
+        assert_eq!(packages.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["left-pad"]);
+        assert_eq!(again[0].skipped, Some(format!("{} unchanged since the last scan", dir.path().display())));
+        assert!(!again[0].succeeded());
+        assert!(again[1].succeeded());
+
+        std::fs::create_dir(dir.path().join("sinatra")).unwrap();
+        let changed = HashMap::from([("gem".to_string(), ChangeSignals::from([(dir.path().to_path_buf(), Some(0))]))]);
+        let (packages, rescanned) = scan_all(&scanners, &ScanContext::default(), Some(&changed), 2, &NoProgress, &|_| {});
+        assert!(rescanned[0].skipped.is_none());
+        assert_eq!(packages.len(), 3);
+    }
+}
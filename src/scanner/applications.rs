@@ -1,17 +1,29 @@
 // macOS Applications scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::{Context, Result};
+use super::{Architecture, Package, PackageSource, ScanContext, Scanner};
+use crate::error::Result;
+use crate::utils::size::SizeCache;
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use walkdir::WalkDir;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct ApplicationsScanner {
     scan_paths: Vec<PathBuf>,
+    sizes: Arc<SizeCache>,
+    /// How long each Info.plist lookup may take
+    timeout: Duration,
+}
+
+impl Default for ApplicationsScanner {
+    fn default() -> Self {
+        Self::new(&ScanContext::default())
+    }
 }
 
 impl ApplicationsScanner {
-    pub fn new() -> Self {
+    pub fn new(context: &ScanContext) -> Self {
         let mut scan_paths = vec![PathBuf::from("/Applications")];
 
         // Also scan user Applications if it exists
@@ -22,42 +34,16 @@ impl ApplicationsScanner {
             }
         }
 
-        Self { scan_paths }
+        Self { scan_paths, sizes: context.sizes.clone(), timeout: context.timeout() }
     }
 
-    fn get_app_version(&self, app_path: &PathBuf) -> Option<String> {
-        // Try to read version from Info.plist
-        let plist_path = app_path.join("Contents/Info.plist");
-        if !plist_path.exists() {
-            return None;
-        }
-
-        // Use defaults command to read plist
-        let output = Command::new("defaults")
-            .args(["read", &plist_path.to_string_lossy(), "CFBundleShortVersionString"])
-            .output()
-            .ok()?;
-
-        if output.status.success() {
-            let version = String::from_utf8(output.stdout).ok()?;
-            return Some(version.trim().to_string());
-        }
-
-        // Try alternative key
-        let output = Command::new("defaults")
-            .args(["read", &plist_path.to_string_lossy(), "CFBundleVersion"])
-            .output()
-            .ok()?;
-
-        if output.status.success() {
-            let version = String::from_utf8(output.stdout).ok()?;
-            return Some(version.trim().to_string());
-        }
-
-        None
+    fn get_app_version(&self, app_path: &Path) -> Option<String> {
+        // Try to read version from Info.plist, falling back to the build version
+        read_info_plist_key(app_path, "CFBundleShortVersionString", self.timeout)
+            .or_else(|| read_info_plist_key(app_path, "CFBundleVersion", self.timeout))
     }
 
-    fn get_app_name(&self, app_path: &PathBuf) -> Option<String> {
+    fn get_app_name(&self, app_path: &Path) -> Option<String> {
         // Get the app name from the .app bundle name
         app_path
             .file_stem()
@@ -86,10 +72,13 @@ impl Scanner for ApplicationsScanner {
                                 if let Some(name) = self.get_app_name(&path) {
                                     let mut package = Package::new(name, PackageSource::Applications);
                                     package.version = self.get_app_version(&path);
+                                    package.bundle_id = read_bundle_id(&path, self.timeout);
                                     package.binary_path = Some(path.clone());
+                                    package.arch = detect_architecture(&path, self.timeout);
 
                                     // Calculate size
-                                    package.size_bytes = crate::utils::size::calculate_directory_size(&path).ok();
+                                    package.size_bytes = self.sizes.size_of(&path).ok();
+                                    package.size_path = Some(path.clone());
 
                                     packages.push(package);
                                 }
@@ -98,7 +87,7 @@ impl Scanner for ApplicationsScanner {
                     }
                 }
                 Err(e) => {
-                    eprintln!("Warning: Failed to scan {}: {}", scan_path.display(), e);
+                    tracing::warn!(source = "applications", path = %scan_path.display(), error = %e, "Failed to scan applications");
                 }
             }
         }
@@ -110,6 +99,139 @@ impl Scanner for ApplicationsScanner {
         // Applications scanning is always available on macOS
         self.scan_paths.iter().any(|p| p.exists())
     }
+
+    /// Apps are installed, replaced by updaters and deleted by moving bundles in and out
+    fn change_markers(&self) -> Vec<PathBuf> {
+        self.scan_paths.clone()
+    }
+}
+
+/// Read the bundle identifier (CFBundleIdentifier) of an .app bundle
+pub fn read_bundle_id(app_path: &Path, timeout: Duration) -> Option<String> {
+    read_info_plist_key(app_path, "CFBundleIdentifier", timeout)
+}
+
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+
+/// The architecture an .app runs as, from the Mach-O header of its main executable and the
+/// `LSArchitecturePriority`/`LSRequiresNativeExecution` Info.plist keys. When the main
+/// executable is a script wrapper, the other executables in Contents/MacOS decide. Each
+/// Info.plist lookup may take `timeout`.
+pub fn detect_architecture(app_path: &Path, timeout: Duration) -> Option<Architecture> {
+    let macos = app_path.join("Contents/MacOS");
+    let executable = read_info_plist_key(app_path, "CFBundleExecutable", timeout)
+        .or_else(|| app_path.file_stem().map(|s| s.to_string_lossy().to_string()))?;
+
+    let mut cpu_types = read_macho_cpu_types(&macos.join(&executable));
+    if cpu_types.is_empty() {
+        let mut others: Vec<PathBuf> = fs::read_dir(&macos).ok()?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        others.sort();
+        for other in others {
+            for cpu_type in read_macho_cpu_types(&other) {
+                if !cpu_types.contains(&cpu_type) {
+                    cpu_types.push(cpu_type);
+                }
+            }
+        }
+    }
+
+    let priority = read_info_plist_key(app_path, "LSArchitecturePriority", timeout)
+        .map(|value| parse_plist_array(&value))
+        .unwrap_or_default();
+    let requires_native = read_info_plist_key(app_path, "LSRequiresNativeExecution", timeout).is_some_and(|v| v == "1");
+    classify_architecture(&cpu_types, &priority, requires_native)
+}
+
+/// A universal app that lists x86_64 first in `LSArchitecturePriority` opens under Rosetta
+/// unless it also sets `LSRequiresNativeExecution`
+fn classify_architecture(cpu_types: &[u32], priority: &[String], requires_native: bool) -> Option<Architecture> {
+    let intel = cpu_types.iter().any(|&t| t == CPU_TYPE_X86_64 || t == CPU_TYPE_X86);
+    let arm = cpu_types.contains(&CPU_TYPE_ARM64);
+
+    match (intel, arm) {
+        (true, true) if !requires_native && priority.first().is_some_and(|a| a == "x86_64") => Some(Architecture::X86_64),
+        (true, true) => Some(Architecture::Universal),
+        (true, false) => Some(Architecture::X86_64),
+        (false, true) => Some(Architecture::Arm64),
+        (false, false) => None,
+    }
+}
+
+/// CPU types in a Mach-O file, one for a thin binary or one per slice of a universal one;
+/// empty for anything else, such as a shell script
+fn read_macho_cpu_types(path: &Path) -> Vec<u32> {
+    let mut header = Vec::new();
+    match fs::File::open(path) {
+        Ok(file) if path.is_file() => {
+            let _ = file.take(4096).read_to_end(&mut header);
+        }
+        _ => return Vec::new(),
+    }
+    parse_macho_cpu_types(&header)
+}
+
+fn parse_macho_cpu_types(header: &[u8]) -> Vec<u32> {
+    let word = |offset: usize, big_endian: bool| {
+        let bytes: [u8; 4] = header.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+
+    match word(0, true) {
+        // Universal binary: big-endian fat_arch (or fat_arch_64) entries follow the header
+        Some(magic @ (0xcafe_babe | 0xcafe_babf)) => {
+            let entry_size = if magic == 0xcafe_babe { 20 } else { 32 };
+            let count = word(4, true).unwrap_or(0) as usize;
+            // Java class files share the 0xcafebabe magic; their "count" is a large version number
+            if count > 16 {
+                return Vec::new();
+            }
+            (0..count).filter_map(|i| word(8 + i * entry_size, true)).collect()
+        }
+        // Thin binary, little-endian on both Intel and Apple silicon
+        Some(0xcffa_edfe | 0xcefa_edfe) => word(4, false).into_iter().collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Items of an array as printed by `defaults read`, e.g. `(\n    x86_64,\n    arm64\n)`
+fn parse_plist_array(value: &str) -> Vec<String> {
+    value
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|item| item.trim().trim_matches('"').to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Read a single key from an .app bundle's Info.plist, giving `defaults` `timeout`
+fn read_info_plist_key(app_path: &Path, key: &str, timeout: Duration) -> Option<String> {
+    let plist_path = app_path.join("Contents/Info.plist");
+    if !plist_path.exists() {
+        return None;
+    }
+
+    // Use defaults command to read plist
+    let output = crate::utils::process::output(
+        Command::new("defaults").args(["read", &plist_path.to_string_lossy(), key]),
+        timeout,
+    )
+    .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8(output.stdout).ok()?;
+    let value = value.trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
 }
 
 #[cfg(test)]
@@ -118,14 +240,70 @@ mod tests {
 
     #[test]
     fn test_scanner_available() {
-        let scanner = ApplicationsScanner::new();
+        let scanner = ApplicationsScanner::default();
         println!("Applications scanner available: {}", scanner.is_available());
     }
 
+    fn thin(cpu_type: u32) -> Vec<u8> {
+        let mut header = 0xfeed_facf_u32.to_le_bytes().to_vec();
+        header.extend(cpu_type.to_le_bytes());
+        header.extend([0; 24]);
+        header
+    }
+
+    fn fat(cpu_types: &[u32]) -> Vec<u8> {
+        let mut header = 0xcafe_babe_u32.to_be_bytes().to_vec();
+        header.extend((cpu_types.len() as u32).to_be_bytes());
+        for cpu_type in cpu_types {
+            header.extend(cpu_type.to_be_bytes());
+            header.extend([0; 16]);
+        }
+        header
+    }
+
+    #[test]
+    fn test_parse_macho_headers() {
+        assert_eq!(parse_macho_cpu_types(&thin(CPU_TYPE_ARM64)), [CPU_TYPE_ARM64]);
+        assert_eq!(parse_macho_cpu_types(&fat(&[CPU_TYPE_X86_64, CPU_TYPE_ARM64])), [CPU_TYPE_X86_64, CPU_TYPE_ARM64]);
+        assert!(parse_macho_cpu_types(b"#!/bin/sh\nexec \"$0-bin\"\n").is_empty());
+        assert!(parse_macho_cpu_types(&[0xca, 0xfe]).is_empty());
+        // A Java class file: 0xcafebabe followed by a version, not a slice count
+        assert!(parse_macho_cpu_types(&[0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52]).is_empty());
+    }
+
+    #[test]
+    fn test_classify_architecture() {
+        let universal = [CPU_TYPE_X86_64, CPU_TYPE_ARM64];
+        let intel_first = vec!["x86_64".to_string(), "arm64".to_string()];
+
+        assert_eq!(classify_architecture(&universal, &[], false), Some(Architecture::Universal));
+        assert_eq!(classify_architecture(&universal, &intel_first, false), Some(Architecture::X86_64));
+        assert_eq!(classify_architecture(&universal, &intel_first, true), Some(Architecture::Universal));
+        assert_eq!(classify_architecture(&[CPU_TYPE_X86_64], &[], false), Some(Architecture::X86_64));
+        assert_eq!(classify_architecture(&[CPU_TYPE_ARM64], &[], false), Some(Architecture::Arm64));
+        assert_eq!(classify_architecture(&[], &[], false), None);
+
+        assert_eq!(parse_plist_array("(\n    x86_64,\n    arm64\n)\n"), intel_first);
+    }
+
+    #[test]
+    fn test_script_wrapper_falls_back_to_other_executables() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let app = dir.path().join("Wrapped.app");
+        let macos = app.join("Contents/MacOS");
+        fs::create_dir_all(&macos).unwrap();
+        fs::write(macos.join("Wrapped"), b"#!/bin/sh\nexec \"$(dirname \"$0\")/wrapped-bin\"\n").unwrap();
+        fs::write(macos.join("wrapped-bin"), thin(CPU_TYPE_X86_64)).unwrap();
+        assert_eq!(detect_architecture(&app, Duration::from_secs(10)), Some(Architecture::X86_64));
+
+        fs::write(macos.join("Wrapped"), fat(&[CPU_TYPE_X86_64, CPU_TYPE_ARM64])).unwrap();
+        assert_eq!(detect_architecture(&app, Duration::from_secs(10)), Some(Architecture::Universal));
+    }
+
     #[test]
     #[ignore] // Run manually
     fn test_scan_applications() {
-        let scanner = ApplicationsScanner::new();
+        let scanner = ApplicationsScanner::default();
         let packages = scanner.scan().unwrap();
         println!("Found {} applications", packages.len());
         for pkg in packages.iter().take(10) {
@@ -1,27 +1,34 @@
 // Cargo binaries scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::{Context, Result};
+use super::{Package, PackageSource, ScanContext, Scanner};
+use crate::error::{Context, Result};
+use crate::utils::process::output;
 use regex::Regex;
 use lazy_static::lazy_static;
 use std::fs;
 use std::process::Command;
+use std::time::Duration;
 
-pub struct CargoScanner;
+pub struct CargoScanner {
+    timeout: Duration,
+}
 
 lazy_static! {
     static ref CARGO_INSTALL_RE: Regex = Regex::new(r"^(\S+)\s+v([0-9.]+):").unwrap();
 }
 
+impl Default for CargoScanner {
+    fn default() -> Self {
+        Self::new(&ScanContext::default())
+    }
+}
+
 impl CargoScanner {
-    pub fn new() -> Self {
-        Self
+    pub fn new(context: &ScanContext) -> Self {
+        Self { timeout: context.timeout() }
     }
 
     fn scan_cargo_install_list(&self) -> Result<Vec<Package>> {
-        let output = Command::new("cargo")
-            .args(["install", "--list"])
-            .output()
-            .context("Failed to run cargo install --list")?;
+        let output = output(Command::new("cargo").args(["install", "--list"]), self.timeout)?;
 
         if !output.status.success() {
             return Ok(Vec::new());
@@ -52,7 +59,7 @@ impl CargoScanner {
 
     fn scan_cargo_bin_directory(&self) -> Result<Vec<Package>> {
         let home = dirs::home_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+            .context("Could not determine home directory")?;
 
         let bin_dir = home.join(".cargo/bin");
 
@@ -88,7 +95,7 @@ impl CargoScanner {
                 package.binary_path = Some(path.clone());
 
                 // Try to get version by running --version
-                package.version = get_binary_version(&path);
+                package.version = get_binary_version(&path, self.timeout);
 
                 packages.push(package);
             }
@@ -116,14 +123,14 @@ impl Scanner for CargoScanner {
         // First try cargo install --list (more reliable for version info)
         match self.scan_cargo_install_list() {
             Ok(mut packages) => all_packages.append(&mut packages),
-            Err(e) => eprintln!("Warning: Failed to scan cargo install list: {}", e),
+            Err(e) => tracing::warn!(source = "cargo", command = "cargo install --list", error = %e, "Failed to list cargo installs"),
         }
 
         // If cargo install --list returned nothing, scan the bin directory
         if all_packages.is_empty() {
             match self.scan_cargo_bin_directory() {
                 Ok(mut packages) => all_packages.append(&mut packages),
-                Err(e) => eprintln!("Warning: Failed to scan cargo bin directory: {}", e),
+                Err(e) => tracing::warn!(source = "cargo", error = %e, "Failed to scan the cargo bin directory"),
             }
         }
 
@@ -133,6 +140,14 @@ impl Scanner for CargoScanner {
     fn is_available(&self) -> bool {
         which::which("cargo").is_ok()
     }
+
+    /// `cargo install` and `cargo uninstall` rewrite both of its install records
+    fn change_markers(&self) -> Vec<std::path::PathBuf> {
+        let Some(home) = dirs::home_dir() else {
+            return Vec::new();
+        };
+        vec![home.join(".cargo/.crates.toml"), home.join(".cargo/.crates2.json")]
+    }
 }
 
 #[cfg(unix)]
@@ -151,12 +166,9 @@ fn is_executable(_path: &std::path::Path) -> bool {
     true // On non-Unix, assume everything is potentially executable
 }
 
-fn get_binary_version(path: &std::path::Path) -> Option<String> {
+fn get_binary_version(path: &std::path::Path, timeout: Duration) -> Option<String> {
     // Try running with --version flag
-    let output = Command::new(path)
-        .arg("--version")
-        .output()
-        .ok()?;
+    let output = output(Command::new(path).arg("--version"), timeout).ok()?;
 
     if output.status.success() {
         let stdout = String::from_utf8(output.stdout).ok()?;
@@ -177,14 +189,14 @@ mod tests {
 
     #[test]
     fn test_scanner_available() {
-        let scanner = CargoScanner::new();
+        let scanner = CargoScanner::default();
         println!("cargo available: {}", scanner.is_available());
     }
 
     #[test]
     #[ignore] // Run manually
     fn test_scan_cargo_binaries() {
-        let scanner = CargoScanner::new();
+        let scanner = CargoScanner::default();
         if scanner.is_available() {
             let packages = scanner.scan().unwrap();
             println!("Found {} cargo binaries", packages.len());
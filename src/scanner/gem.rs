@@ -1,9 +1,15 @@
 // Ruby gems scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::Result;
+use super::{Package, Scanner};
+use crate::error::Result;
 
 pub struct GemScanner;
 
+impl Default for GemScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GemScanner {
     pub fn new() -> Self {
         Self
@@ -12,7 +18,7 @@ impl GemScanner {
 
 impl Scanner for GemScanner {
     fn scan(&self) -> Result<Vec<Package>> {
-        let mut packages = Vec::new();
+        let packages = Vec::new();
 
         // TODO: Run `gem list` to get installed gems
         // TODO: Parse output and create Package structs
@@ -1,7 +1,8 @@
 // Generic binary scanner for /usr/local/bin, ~/.local/bin, etc.
-use super::{Package, PackageSource, Scanner};
-use anyhow::Result;
+use super::{Package, Scanner};
+use crate::error::Result;
 
+#[allow(dead_code)] // paths will be used once directory scanning is implemented
 pub struct GenericBinaryScanner {
     paths: Vec<String>,
 }
@@ -14,7 +15,7 @@ impl GenericBinaryScanner {
 
 impl Scanner for GenericBinaryScanner {
     fn scan(&self) -> Result<Vec<Package>> {
-        let mut packages = Vec::new();
+        let packages = Vec::new();
 
         // TODO: Scan specified directories for executable files
         // TODO: Create Package structs for each binary
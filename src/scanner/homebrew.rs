@@ -1,13 +1,36 @@
 // Homebrew package scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::{Context, Result};
-use chrono::{DateTime, TimeZone, Utc};
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use std::process::Command;
+use super::{Package, PackageSource, ScanContext, Scanner};
+use crate::error::{Context, Result};
+use crate::utils::process::{CommandRunner, SystemRunner};
+use crate::utils::size::SizeCache;
+use chrono::{TimeZone, Utc};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const BREW_PREFIX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where Homebrew installs itself on Apple Silicon and on Intel Macs
+const DEFAULT_PREFIXES: [&str; 2] = ["/opt/homebrew", "/usr/local"];
+
+/// The prefix the `brew` on PATH installs into (Apple Silicon vs Intel), or the first
+/// default prefix with a Cellar when brew can't be run
+pub fn active_prefix(runner: &dyn CommandRunner) -> PathBuf {
+    runner
+        .run("brew", &["--prefix"], BREW_PREFIX_TIMEOUT)
+        .map(|output| PathBuf::from(output.trim()))
+        .unwrap_or_else(|_| {
+            let cellar = DEFAULT_PREFIXES.iter().find(|prefix| Path::new(prefix).join("Cellar").is_dir());
+            PathBuf::from(cellar.unwrap_or(&DEFAULT_PREFIXES[0]))
+        })
+}
 
 pub struct HomebrewScanner {
     prefix: String,
+    runner: Box<dyn CommandRunner>,
+    timeout: Duration,
+    sizes: Arc<SizeCache>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,11 +47,15 @@ struct BrewFormula {
     versions: BrewVersions,
     #[serde(default)]
     installed: Vec<BrewInstalled>,
+    /// Version currently linked into the prefix; `None` for keg-only or unlinked formulae
+    #[serde(default)]
+    linked_keg: Option<String>,
     #[serde(default)]
     dependencies: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)] // `installed` is parsed for completeness
 struct BrewCask {
     token: String,
     #[serde(default)]
@@ -50,6 +77,10 @@ struct BrewInstalled {
     time: Option<i64>,
     #[serde(default)]
     runtime_dependencies: Vec<BrewDependency>,
+    #[serde(default)]
+    installed_as_dependency: bool,
+    #[serde(default)]
+    installed_on_request: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -57,48 +88,122 @@ struct BrewDependency {
     full_name: String,
 }
 
+/// The INSTALL_RECEIPT.json brew writes into each installed keg
+#[derive(Debug, Default, Deserialize)]
+struct BrewReceipt {
+    #[serde(default)]
+    time: Option<i64>,
+    #[serde(default)]
+    runtime_dependencies: Vec<BrewDependency>,
+    #[serde(default)]
+    installed_as_dependency: bool,
+    #[serde(default)]
+    installed_on_request: bool,
+}
+
+impl Default for HomebrewScanner {
+    fn default() -> Self {
+        Self::new(&ScanContext::default())
+    }
+}
+
 impl HomebrewScanner {
-    pub fn new() -> Self {
-        // Detect Homebrew prefix (Apple Silicon vs Intel)
-        let prefix = Self::get_brew_prefix().unwrap_or_else(|_| "/opt/homebrew".to_string());
-        Self { prefix }
+    pub fn new(context: &ScanContext) -> Self {
+        Self::with_runner(Box::new(SystemRunner), context)
     }
 
-    fn get_brew_prefix() -> Result<String> {
-        let output = Command::new("brew")
-            .args(["--prefix"])
-            .output()
-            .context("Failed to run brew --prefix")?;
+    /// A scanner running brew through `runner`
+    pub fn with_runner(runner: Box<dyn CommandRunner>, context: &ScanContext) -> Self {
+        let prefix = active_prefix(runner.as_ref()).to_string_lossy().into_owned();
+        Self { prefix, runner, timeout: context.timeout(), sizes: context.sizes.clone() }
+    }
 
-        if !output.status.success() {
-            anyhow::bail!("brew --prefix failed");
+    /// `brew`, or the prefix's own copy when it isn't on PATH, as under cron or launchd
+    fn brew_program(&self) -> String {
+        let installed = Path::new(&self.prefix).join("bin/brew");
+        if which::which("brew").is_err() && installed.exists() {
+            installed.to_string_lossy().into_owned()
+        } else {
+            "brew".to_string()
         }
-
-        Ok(String::from_utf8(output.stdout)?.trim().to_string())
     }
 
     fn get_installed_info(&self) -> Result<BrewInfo> {
-        let output = Command::new("brew")
-            .args(["info", "--json=v2", "--installed"])
-            .output()
-            .context("Failed to run brew info --json=v2 --installed")?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("brew info failed: {}", stderr);
-        }
-
-        let json = String::from_utf8(output.stdout)?;
+        let json = self.runner.run(&self.brew_program(), &["info", "--json=v2", "--installed"], self.timeout)?;
         let info: BrewInfo = serde_json::from_str(&json)
             .context("Failed to parse brew info JSON")?;
 
         Ok(info)
     }
 
-    fn calculate_formula_size(&self, name: &str) -> Option<u64> {
-        // Calculate size of formula in Cellar
-        let cellar_path = PathBuf::from(&self.prefix).join("Cellar").join(name);
-        crate::utils::size::calculate_directory_size(&cellar_path).ok()
+    /// What `brew info --installed` reports, read from the install receipts in the Cellar and
+    /// the version directories in the Caskroom. Descriptions aren't recorded there.
+    fn read_cellar(&self) -> Result<BrewInfo> {
+        let cellar = Path::new(&self.prefix).join("Cellar");
+        let mut formulae = Vec::new();
+        for (name, formula_dir) in subdirectories(&cellar).with_context(|| format!("Cannot read {}", cellar.display()))? {
+            let mut installed: Vec<BrewInstalled> = subdirectories(&formula_dir)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(version, keg)| {
+                    let receipt: BrewReceipt = std::fs::read_to_string(keg.join("INSTALL_RECEIPT.json"))
+                        .ok()
+                        .and_then(|json| serde_json::from_str(&json).ok())
+                        .unwrap_or_default();
+                    BrewInstalled {
+                        version,
+                        time: receipt.time,
+                        runtime_dependencies: receipt.runtime_dependencies,
+                        installed_as_dependency: receipt.installed_as_dependency,
+                        installed_on_request: receipt.installed_on_request,
+                    }
+                })
+                .collect();
+            if installed.is_empty() {
+                continue;
+            }
+            // Oldest first, as brew lists them
+            installed.sort_by_key(|i| i.time);
+
+            // opt/<name> links to the keg in use, e.g. ../Cellar/node/22.2.0
+            let linked_keg = std::fs::read_link(Path::new(&self.prefix).join("opt").join(&name))
+                .ok()
+                .and_then(|target| target.file_name().map(|v| v.to_string_lossy().into_owned()));
+            let stable = linked_keg.clone().unwrap_or_else(|| installed[installed.len() - 1].version.clone());
+            formulae.push(BrewFormula {
+                name,
+                desc: None,
+                versions: BrewVersions { stable },
+                installed,
+                linked_keg,
+                dependencies: Vec::new(),
+            });
+        }
+
+        let casks = subdirectories(&Path::new(&self.prefix).join("Caskroom"))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(token, cask_dir)| {
+                let versions = subdirectories(&cask_dir).ok()?;
+                let (version, _) = versions.into_iter().rfind(|(v, _)| !v.starts_with('.'))?;
+                Some(BrewCask { token, desc: None, version: version.clone(), installed: Some(version) })
+            })
+            .collect();
+
+        Ok(BrewInfo { formulae, casks })
+    }
+
+    /// `brew info`, or the Cellar when brew is missing or doesn't answer in time
+    fn installed(&self) -> Result<BrewInfo> {
+        self.get_installed_info().or_else(|e| {
+            tracing::warn!(source = "homebrew", error = %e, "brew info failed, reading the Homebrew Cellar instead");
+            self.read_cellar().map_err(|_| e)
+        })
+    }
+
+    /// The formula's directory in the Cellar, holding every installed version
+    fn formula_cellar(&self, name: &str) -> PathBuf {
+        PathBuf::from(&self.prefix).join("Cellar").join(name)
     }
 
     fn find_formula_binary(&self, name: &str) -> Option<PathBuf> {
@@ -111,13 +216,26 @@ impl HomebrewScanner {
         }
     }
 
-    fn scan_formulae(&self) -> Result<Vec<Package>> {
-        let info = self.get_installed_info()?;
+    /// Everything the formula links into bin, e.g. `magick` for imagemagick or
+    /// `python3.12` for python@3.12
+    fn formula_binaries(&self, name: &str) -> Vec<String> {
+        list_binaries(&PathBuf::from(&self.prefix).join("opt").join(name).join("bin"))
+    }
+
+    /// Size of each of `versions` in the formula's Cellar directory
+    fn calculate_versions_size(&self, name: &str, versions: &[String]) -> Option<u64> {
+        let cellar_path = self.formula_cellar(name);
+        versions.iter()
+            .map(|version| self.sizes.size_of(&cellar_path.join(version)).ok())
+            .sum()
+    }
+
+    fn scan_formulae(&self, info: &BrewInfo) -> Vec<Package> {
         let mut packages = Vec::new();
+        let mut unknown_reason = Vec::new();
 
-        for formula in info.formulae {
-            // Use the first installed version
-            let installed = formula.installed.first();
+        for formula in &info.formulae {
+            let (installed, stale_versions) = active_install(formula);
 
             let version = installed
                 .map(|i| i.version.clone())
@@ -140,25 +258,44 @@ impl HomebrewScanner {
 
             let mut package = Package::new(formula.name.clone(), PackageSource::Homebrew);
             package.version = version;
+            package.description = formula.desc.clone();
             package.install_date = install_date;
-            package.size_bytes = self.calculate_formula_size(&formula.name);
+            let cellar_path = self.formula_cellar(&formula.name);
+            package.size_bytes = self.sizes.size_of(&cellar_path).ok();
+            package.size_path = Some(cellar_path);
             package.binary_path = self.find_formula_binary(&formula.name);
+            package.binaries = self.formula_binaries(&formula.name);
             package.dependencies = dependencies;
-            package.is_dependency = false; // Will be determined later
+            // Pulled in by another formula and never asked for: what `brew autoremove` removes
+            match installed.and_then(install_reason) {
+                Some(is_dependency) => package.is_dependency = is_dependency,
+                None => unknown_reason.push(packages.len()),
+            }
+            if !stale_versions.is_empty() {
+                package.stale_bytes = self.calculate_versions_size(&formula.name, &stale_versions);
+                package.stale_versions = stale_versions;
+            }
 
             packages.push(package);
         }
 
-        Ok(packages)
+        if !unknown_reason.is_empty() {
+            match crate::analysis::orphans::get_brew_leaves(self.timeout) {
+                Ok(leaves) => mark_non_leaves_as_dependencies(&mut packages, &unknown_reason, &leaves),
+                Err(e) => tracing::warn!(source = "homebrew", command = "brew leaves", error = %e, "Failed to list Homebrew leaves"),
+            }
+        }
+
+        packages
     }
 
-    fn scan_casks(&self) -> Result<Vec<Package>> {
-        let info = self.get_installed_info()?;
+    fn scan_casks(&self, info: &BrewInfo) -> Vec<Package> {
         let mut packages = Vec::new();
 
-        for cask in info.casks {
+        for cask in &info.casks {
             let mut package = Package::new(cask.token.clone(), PackageSource::HomebrewCask);
-            package.version = Some(cask.version);
+            package.version = Some(cask.version.clone());
+            package.description = cask.desc.clone();
             // Note: Cask install time is harder to determine from JSON
             // We could parse the cask directory metadata if needed
 
@@ -167,14 +304,16 @@ impl HomebrewScanner {
                 .join(format!("{}.app", Self::guess_app_name(&cask.token)));
 
             if app_path.exists() {
+                package.bundle_id = super::applications::read_bundle_id(&app_path, self.timeout);
                 package.binary_path = Some(app_path.clone());
-                package.size_bytes = crate::utils::size::calculate_directory_size(&app_path).ok();
+                package.size_bytes = self.sizes.size_of(&app_path).ok();
+                package.size_path = Some(app_path.clone());
             }
 
             packages.push(package);
         }
 
-        Ok(packages)
+        packages
     }
 
     fn guess_app_name(token: &str) -> String {
@@ -194,33 +333,125 @@ impl HomebrewScanner {
     }
 }
 
-impl Scanner for HomebrewScanner {
-    fn scan(&self) -> Result<Vec<Package>> {
-        let mut packages = Vec::new();
+/// The install in use (the linked keg, otherwise the newest install) and the versions left
+/// behind by upgrades, which `brew cleanup` removes: those older than the stable version,
+/// but never the linked keg, and none until the stable version is installed
+fn active_install(formula: &BrewFormula) -> (Option<&BrewInstalled>, Vec<String>) {
+    let active = formula.linked_keg.as_ref()
+        .and_then(|linked| formula.installed.iter().find(|i| &i.version == linked))
+        .or_else(|| formula.installed.last());
+    let stable = &formula.versions.stable;
+    let stale = if formula.installed.iter().any(|i| &i.version == stable) {
+        formula.installed.iter()
+            .filter(|i| formula.linked_keg.as_ref() != Some(&i.version))
+            .filter(|i| compare_versions(&i.version, stable).is_lt())
+            .map(|i| i.version.clone())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    (active, stale)
+}
 
-        // Scan formulae
-        match self.scan_formulae() {
-            Ok(mut formulae) => packages.append(&mut formulae),
-            Err(e) => eprintln!("Warning: Failed to scan Homebrew formulae: {}", e),
+/// Order Homebrew versions piece by piece (`1.10` is newer than `1.9`, and the revision in
+/// `3.3.1_1` is newer than `3.3.1`)
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let pieces = |v: &str| v.split(|c: char| !c.is_ascii_alphanumeric()).map(String::from).collect::<Vec<_>>();
+    let (a, b) = (pieces(a), pieces(b));
+    for (x, y) in a.iter().zip(&b) {
+        let order = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.cmp(y),
+        };
+        if order.is_ne() {
+            return order;
         }
+    }
+    a.len().cmp(&b.len())
+}
 
-        // Scan casks
-        match self.scan_casks() {
-            Ok(mut casks) => packages.append(&mut casks),
-            Err(e) => eprintln!("Warning: Failed to scan Homebrew casks: {}", e),
-        }
+/// Whether the install receipt says the formula was pulled in as a dependency; `None` when it
+/// records neither reason, as receipts from older Homebrew versions do
+fn install_reason(installed: &BrewInstalled) -> Option<bool> {
+    if installed.installed_on_request {
+        Some(false)
+    } else if installed.installed_as_dependency {
+        Some(true)
+    } else {
+        None
+    }
+}
+
+/// Settle the formulae at `unknown` (indexes into `packages`) whose receipts don't say why
+/// they were installed: one another formula depends on, which `brew leaves` leaves out, was
+/// most likely pulled in for it
+fn mark_non_leaves_as_dependencies(packages: &mut [Package], unknown: &[usize], leaves: &[String]) {
+    for &index in unknown {
+        let package = &mut packages[index];
+        package.is_dependency = !leaves.contains(&package.name);
+    }
+}
 
+/// Names and paths of the directories in `dir`, sorted by name
+fn subdirectories(dir: &Path) -> std::io::Result<Vec<(String, PathBuf)>> {
+    let mut dirs: Vec<(String, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .map(|e| (e.file_name().to_string_lossy().into_owned(), e.path()))
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Names of the executables in `dir`, sorted; empty when it doesn't exist
+fn list_binaries(dir: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut binaries: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| !e.path().is_dir())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    binaries.sort();
+    binaries
+}
+
+impl Scanner for HomebrewScanner {
+    fn scan(&self) -> Result<Vec<Package>> {
+        // One `brew info` for both formulae and casks
+        let info = self.installed()?;
+        let mut packages = self.scan_formulae(&info);
+        packages.append(&mut self.scan_casks(&info));
         Ok(packages)
     }
 
     fn is_available(&self) -> bool {
-        which::which("brew").is_ok()
+        which::which("brew").is_ok() || Path::new(&self.prefix).join("Cellar").is_dir()
+    }
+
+    /// Installs and removals change the Cellar and the Caskroom; upgrades add a version inside
+    /// the formula's own directory, but relink it in opt
+    fn change_markers(&self) -> Vec<PathBuf> {
+        let prefix = Path::new(&self.prefix);
+        let mut markers: Vec<_> = ["Cellar", "Caskroom", "opt"].iter().map(|dir| prefix.join(dir)).collect();
+        // A cask upgrade adds a version directory under its own Caskroom entry, leaving the
+        // Caskroom itself untouched
+        if let Ok(entries) = std::fs::read_dir(prefix.join("Caskroom")) {
+            let mut casks: Vec<_> = entries.flatten().map(|e| e.path()).filter(|path| path.is_dir()).collect();
+            casks.sort();
+            markers.extend(casks);
+        }
+        markers
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_guess_app_name() {
@@ -229,9 +460,177 @@ mod tests {
         assert_eq!(HomebrewScanner::guess_app_name("alt-tab"), "Alt Tab");
     }
 
+    #[test]
+    fn test_change_markers_include_each_cask() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for cask in ["firefox", "docker"] {
+            std::fs::create_dir_all(dir.path().join("Caskroom").join(cask)).unwrap();
+        }
+        let scanner = HomebrewScanner { prefix: dir.path().to_string_lossy().into_owned(), ..HomebrewScanner::default() };
+
+        let markers: Vec<_> = scanner.change_markers().iter().map(|m| m.strip_prefix(dir.path()).unwrap().to_path_buf()).collect();
+        let markers: Vec<_> = markers.iter().map(|m| m.to_str().unwrap()).collect();
+        assert_eq!(markers, ["Cellar", "Caskroom", "opt", "Caskroom/docker", "Caskroom/firefox"]);
+    }
+
+    #[test]
+    fn test_list_binaries() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let bin = dir.path().join("opt/imagemagick/bin");
+        std::fs::create_dir_all(bin.join("subdir")).unwrap();
+        for name in ["magick", "convert", ".DS_Store"] {
+            std::fs::write(bin.join(name), b"").unwrap();
+        }
+
+        assert_eq!(list_binaries(&bin), vec!["convert", "magick"]);
+        assert!(list_binaries(&dir.path().join("opt/missing/bin")).is_empty());
+    }
+
+    #[test]
+    fn test_active_install_and_stale_versions() {
+        let info: BrewInfo = serde_json::from_str(r#"{
+            "formulae": [
+                {"name": "node", "versions": {"stable": "22.3.0"}, "linked_keg": "22.2.0",
+                 "installed": [{"version": "21.7.1"}, {"version": "22.2.0"}, {"version": "22.3.0"}]},
+                {"name": "openssl@3", "versions": {"stable": "3.3.1"}, "linked_keg": null,
+                 "installed": [{"version": "3.3.0"}, {"version": "3.3.1"}]},
+                {"name": "jq", "versions": {"stable": "1.7.1"}, "installed": [{"version": "1.7.1"}]},
+                {"name": "wget", "versions": {"stable": "1.24.5"}, "linked_keg": "1.24.0",
+                 "installed": [{"version": "1.21.4"}, {"version": "1.24.0"}]}
+            ],
+            "casks": []
+        }"#).unwrap();
+
+        let (active, stale) = active_install(&info.formulae[0]);
+        assert_eq!(active.unwrap().version, "22.2.0");
+        // The linked keg stays even though it's older than stable, and so do newer versions
+        assert_eq!(stale, ["21.7.1"]);
+
+        // Keg-only formulae aren't linked; the newest install is the one in use
+        let (active, stale) = active_install(&info.formulae[1]);
+        assert_eq!(active.unwrap().version, "3.3.1");
+        assert_eq!(stale, ["3.3.0"]);
+
+        let (active, stale) = active_install(&info.formulae[2]);
+        assert_eq!(active.unwrap().version, "1.7.1");
+        assert!(stale.is_empty());
+
+        // brew cleanup removes nothing until the stable version is installed
+        let (active, stale) = active_install(&info.formulae[3]);
+        assert_eq!(active.unwrap().version, "1.24.0");
+        assert!(stale.is_empty());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        use std::cmp::Ordering;
+        assert_eq!(compare_versions("1.9", "1.10"), Ordering::Less);
+        assert_eq!(compare_versions("3.3.1_1", "3.3.1"), Ordering::Greater);
+        assert_eq!(compare_versions("22.3.0", "22.3.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0rc1", "1.0rc2"), Ordering::Less);
+    }
+
+    #[test]
+    fn test_install_reason_and_leaves() {
+        let installed: Vec<BrewInstalled> = serde_json::from_str(r#"[
+            {"version": "1.7.1", "installed_on_request": true, "installed_as_dependency": true},
+            {"version": "6.9.9", "installed_as_dependency": true},
+            {"version": "2.1"}
+        ]"#).unwrap();
+        assert_eq!(install_reason(&installed[0]), Some(false));
+        assert_eq!(install_reason(&installed[1]), Some(true));
+        assert_eq!(install_reason(&installed[2]), None);
+
+        let mut packages: Vec<Package> = ["pcre2", "htop", "jq"].iter()
+            .map(|name| Package::new(name.to_string(), PackageSource::Homebrew))
+            .collect();
+        mark_non_leaves_as_dependencies(&mut packages, &[0, 1], &["htop".to_string(), "jq".to_string()]);
+        assert!(packages[0].is_dependency);
+        assert!(!packages[1].is_dependency);
+        assert!(!packages[2].is_dependency);
+    }
+
+    /// Answers `brew info` with canned JSON (or times out without any), recording each call
+    struct FakeBrew {
+        info: Option<&'static str>,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl CommandRunner for FakeBrew {
+        fn run(&self, program: &str, args: &[&str], timeout: Duration) -> Result<String> {
+            self.calls.lock().unwrap().push(args.join(" "));
+            self.info.map(str::to_string).ok_or(crate::error::MacsweepError::TimedOut { program: program.to_string(), timeout })
+        }
+    }
+
+    fn scanner(prefix: &Path, info: Option<&'static str>) -> (HomebrewScanner, Arc<Mutex<Vec<String>>>) {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let runner = FakeBrew { info, calls: calls.clone() };
+        let scanner = HomebrewScanner {
+            prefix: prefix.to_string_lossy().into_owned(),
+            runner: Box::new(runner),
+            timeout: Duration::from_secs(60),
+            sizes: Arc::default(),
+        };
+        (scanner, calls)
+    }
+
+    #[test]
+    fn test_formulae_and_casks_share_one_brew_info() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let (scanner, calls) = scanner(dir.path(), Some(r#"{
+            "formulae": [{"name": "jq", "versions": {"stable": "1.7.1"},
+                          "installed": [{"version": "1.7.1", "installed_on_request": true}]}],
+            "casks": [{"token": "iterm2", "version": "3.5.0"}]
+        }"#));
+
+        let packages = scanner.scan().unwrap();
+        let names: Vec<_> = packages.iter().map(|p| (p.name.as_str(), p.source.clone())).collect();
+        assert_eq!(names, [("jq", PackageSource::Homebrew), ("iterm2", PackageSource::HomebrewCask)]);
+        assert_eq!(*calls.lock().unwrap(), ["info --json=v2 --installed"]);
+    }
+
+    #[test]
+    fn test_reads_install_receipts_when_brew_times_out() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let keg = |name: &str, version: &str, receipt: &str| {
+            let keg = dir.path().join("Cellar").join(name).join(version);
+            std::fs::create_dir_all(&keg).unwrap();
+            std::fs::write(keg.join("INSTALL_RECEIPT.json"), receipt).unwrap();
+        };
+        keg("node", "21.7.1", r#"{"time": 1700000000, "installed_on_request": true}"#);
+        keg("node", "22.2.0", r#"{"time": 1710000000, "installed_on_request": true,
+                                  "runtime_dependencies": [{"full_name": "icu4c", "version": "74.2"}]}"#);
+        keg("icu4c", "74.2", r#"{"time": 1690000000, "installed_as_dependency": true}"#);
+        std::fs::create_dir_all(dir.path().join("opt")).unwrap();
+        std::os::unix::fs::symlink("../Cellar/node/21.7.1", dir.path().join("opt/node")).unwrap();
+        std::fs::create_dir_all(dir.path().join("Caskroom/firefox/126.0")).unwrap();
+        std::fs::create_dir_all(dir.path().join("Caskroom/firefox/.metadata")).unwrap();
+
+        let (scanner, calls) = scanner(dir.path(), None);
+        assert!(scanner.is_available());
+        let packages = scanner.scan().unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["info --json=v2 --installed"]);
+
+        let names: Vec<_> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["icu4c", "node", "firefox"]);
+        assert!(packages[0].is_dependency);
+
+        let node = &packages[1];
+        assert_eq!(node.version.as_deref(), Some("21.7.1"));
+        assert!(!node.is_dependency);
+        assert!(node.dependencies.is_empty());
+        // Without brew the linked keg stands in for stable, so the newer install isn't stale
+        assert!(node.stale_versions.is_empty());
+        assert_eq!(node.install_date.unwrap().timestamp(), 1700000000);
+
+        assert_eq!(packages[2].version.as_deref(), Some("126.0"));
+        assert_eq!(packages[2].source, PackageSource::HomebrewCask);
+    }
+
     #[test]
     fn test_scanner_available() {
-        let scanner = HomebrewScanner::new();
+        let scanner = HomebrewScanner::default();
         // This test will pass if brew is installed
         println!("Homebrew available: {}", scanner.is_available());
     }
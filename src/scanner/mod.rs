@@ -6,12 +6,22 @@ pub mod cargo;
 pub mod applications;
 pub mod gem;
 pub mod generic;
+pub mod plugin;
+pub mod registry;
 
+use crate::analysis::trends::UsageTrend;
+use crate::usage::UsageConfidence;
 use chrono::{DateTime, Utc};
+use crate::error::MacsweepError;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+/// Where a package was installed from. Stored, exported and serialized as the stable names
+/// of `as_str`; never as the variant names, which may change.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PackageSource {
     Homebrew,
     HomebrewCask,
@@ -25,21 +35,178 @@ pub enum PackageSource {
     Composer,
     Applications,
     LocalBin,
+    /// Reported by the plugin scanner of that name. Holds the stable name, `plugin:<name>`;
+    /// create it with `PackageSource::plugin`.
+    Plugin(String),
+}
+
+impl PackageSource {
+    pub const ALL: [PackageSource; 12] = [
+        PackageSource::Homebrew,
+        PackageSource::HomebrewCask,
+        PackageSource::MacAppStore,
+        PackageSource::Npm,
+        PackageSource::Pip,
+        PackageSource::Pipx,
+        PackageSource::Cargo,
+        PackageSource::Gem,
+        PackageSource::Go,
+        PackageSource::Composer,
+        PackageSource::Applications,
+        PackageSource::LocalBin,
+    ];
+
+    /// A plugin's source, for a name `plugin::valid_name` accepts
+    pub fn plugin(name: &str) -> Self {
+        PackageSource::Plugin(format!("{}{}", PLUGIN_PREFIX, name))
+    }
+
+    /// The name of the plugin reporting packages of this source
+    pub fn plugin_name(&self) -> Option<&str> {
+        match self {
+            PackageSource::Plugin(source) => source.strip_prefix(PLUGIN_PREFIX),
+            _ => None,
+        }
+    }
+
+    /// The stable name, used in the database, JSON, CSV, backups and plans
+    pub fn as_str(&self) -> &str {
+        match self {
+            PackageSource::Homebrew => "homebrew",
+            PackageSource::HomebrewCask => "homebrewcask",
+            PackageSource::MacAppStore => "macappstore",
+            PackageSource::Npm => "npm",
+            PackageSource::Pip => "pip",
+            PackageSource::Pipx => "pipx",
+            PackageSource::Cargo => "cargo",
+            PackageSource::Gem => "gem",
+            PackageSource::Go => "go",
+            PackageSource::Composer => "composer",
+            PackageSource::Applications => "applications",
+            PackageSource::LocalBin => "localbin",
+            PackageSource::Plugin(source) => source,
+        }
+    }
+}
+
+impl fmt::Display for PackageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const PLUGIN_PREFIX: &str = "plugin:";
+
+/// Parses the stable names, including any plugin's, and the variant names (`HomebrewCask`)
+/// that databases, backups and plans written by earlier versions hold
+impl FromStr for PackageSource {
+    type Err = MacsweepError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(name) = s.strip_prefix(PLUGIN_PREFIX).filter(|name| plugin::valid_name(name)) {
+            return Ok(PackageSource::plugin(name));
+        }
+        PackageSource::ALL
+            .into_iter()
+            .find(|source| source.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let known = PackageSource::ALL.iter().map(|source| source.as_str().to_string()).collect();
+                MacsweepError::unknown_source(s, known, PackageSource::ALL.iter().map(PackageSource::as_str))
+            })
+    }
+}
+
+impl Serialize for PackageSource {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PackageSource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// CPU architecture an application runs as
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Architecture {
+    /// Apple silicon only
+    Arm64,
+    /// Intel only, or a universal app set to open under Rosetta
+    #[serde(rename = "x86_64")]
+    #[cfg_attr(feature = "cli", value(name = "x86_64"))]
+    X86_64,
+    /// Native on both
+    Universal,
+}
+
+impl Architecture {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Architecture::Arm64 => "arm64",
+            Architecture::X86_64 => "x86_64",
+            Architecture::Universal => "universal",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "arm64" => Some(Architecture::Arm64),
+            "x86_64" => Some(Architecture::X86_64),
+            "universal" => Some(Architecture::Universal),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Package {
     pub name: String,
     pub version: Option<String>,
+    /// One-line summary from the package manager, e.g. "YAML Parser" for libyaml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     pub source: PackageSource,
     pub install_date: Option<DateTime<Utc>>,
     pub size_bytes: Option<u64>,
+    /// The directory `size_bytes` measures, e.g. the formula's Cellar directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size_path: Option<PathBuf>,
     pub binary_path: Option<PathBuf>,
+    /// Every executable the package installs, when it can be listed (imagemagick installs `magick`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binaries: Vec<String>,
+    /// macOS bundle identifier (CFBundleIdentifier) for .app bundles
+    pub bundle_id: Option<String>,
     pub is_dependency: bool,
     pub dependencies: Vec<String>,
     pub dependents: Vec<String>,
     pub last_used: Option<DateTime<Utc>>,
     pub usage_count: u32,
+    /// How much evidence backs `last_used`
+    #[serde(default)]
+    pub usage_confidence: UsageConfidence,
+    /// When a scan stopped finding this package (uninstalled outside macsweep)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub removed_at: Option<DateTime<Utc>>,
+    /// Installed versions other than the active one, which `brew cleanup` would remove
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stale_versions: Vec<String>,
+    /// Space taken by `stale_versions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_bytes: Option<u64>,
+    /// Architecture of an application's executable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Architecture>,
+    /// How often the package was used over the past year, from recorded usage events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_trend: Option<UsageTrend>,
+    /// Started at login (a login item or LaunchAgent), so always in use
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub launches_at_login: bool,
 }
 
 impl Package {
@@ -47,21 +214,120 @@ impl Package {
         Self {
             name,
             version: None,
+            description: None,
             source,
             install_date: None,
             size_bytes: None,
+            size_path: None,
             binary_path: None,
+            binaries: Vec::new(),
+            bundle_id: None,
             is_dependency: false,
             dependencies: Vec::new(),
             dependents: Vec::new(),
             last_used: None,
             usage_count: 0,
+            usage_confidence: UsageConfidence::None,
+            removed_at: None,
+            stale_versions: Vec::new(),
+            stale_bytes: None,
+            arch: None,
+            usage_trend: None,
+            launches_at_login: false,
         }
     }
 }
 
+/// What a scan hands the scanners it creates
+#[derive(Debug, Clone, Default)]
+pub struct ScanContext {
+    /// How long the commands scanners run may take; `scanners` applies
+    pub timeouts: crate::utils::process::CommandTimeouts,
+    /// Where scanners get directory sizes, reusing those of earlier scans
+    pub sizes: std::sync::Arc<crate::utils::size::SizeCache>,
+}
+
+impl ScanContext {
+    /// How long one of a scanner's commands may run
+    pub fn timeout(&self) -> std::time::Duration {
+        self.timeouts.get(crate::utils::process::CommandKind::Scanner)
+    }
+}
+
 /// Trait for package scanners
 pub trait Scanner {
-    fn scan(&self) -> anyhow::Result<Vec<Package>>;
+    fn scan(&self) -> crate::error::Result<Vec<Package>>;
     fn is_available(&self) -> bool;
+
+    /// `scan`, along with the package sources it listed when it could only list some of those
+    /// the scanner covers (pip, but not pipx). None, the default, means all of them; only the
+    /// sources listed are reconciled with the database.
+    fn scan_sources(&self) -> crate::error::Result<(Vec<Package>, Option<Vec<PackageSource>>)> {
+        Ok((self.scan()?, None))
+    }
+
+    /// Paths whose modification times change whenever packages are installed or removed, so
+    /// `scan --changed` can skip the scanner while they're untouched. Empty (the default)
+    /// means there's no such signal and the scanner always runs.
+    fn change_markers(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// Modification times of a scanner's change markers in nanoseconds, None for markers that
+/// don't exist
+pub type ChangeSignals = BTreeMap<PathBuf, Option<i64>>;
+
+/// The current `ChangeSignals` of `markers`
+pub fn change_signals(markers: &[PathBuf]) -> ChangeSignals {
+    markers.iter().map(|marker| (marker.clone(), crate::utils::size::modified_ns(marker))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_source_serializes_lowercase() {
+        assert_eq!(serde_json::to_string(&PackageSource::HomebrewCask).unwrap(), "\"homebrewcask\"");
+        assert_eq!(serde_yaml::to_string(&PackageSource::Npm).unwrap().trim(), "npm");
+        assert_eq!(
+            serde_json::to_string(&PackageSource::MacAppStore).unwrap(),
+            format!("\"{}\"", PackageSource::MacAppStore.as_str())
+        );
+    }
+
+    #[test]
+    fn test_package_source_parses_stable_and_legacy_names() {
+        for source in PackageSource::ALL {
+            assert_eq!(source.to_string().parse::<PackageSource>().unwrap(), source);
+            // What `format!("{:?}")` stored before the names were stable
+            assert_eq!(format!("{:?}", source).parse::<PackageSource>().unwrap(), source);
+        }
+        assert_eq!(serde_json::from_str::<PackageSource>("\"HomebrewCask\"").unwrap(), PackageSource::HomebrewCask);
+        assert!("homebrew-cask".parse::<PackageSource>().is_err());
+
+        let plugin = PackageSource::plugin("corp-sdk");
+        assert_eq!((plugin.as_str(), plugin.plugin_name()), ("plugin:corp-sdk", Some("corp-sdk")));
+        assert_eq!("plugin:corp-sdk".parse::<PackageSource>().unwrap(), plugin);
+        assert_eq!(serde_json::from_str::<PackageSource>("\"plugin:corp-sdk\"").unwrap(), plugin);
+        assert!("plugin:".parse::<PackageSource>().is_err());
+        assert!("plugin:../sdk".parse::<PackageSource>().is_err());
+        assert_eq!(PackageSource::Npm.plugin_name(), None);
+    }
+
+    #[test]
+    fn test_package_round_trip() {
+        let mut package = Package::new("wget".to_string(), PackageSource::Homebrew);
+        package.version = Some("1.24.5".to_string());
+        package.dependencies = vec!["openssl@3".to_string()];
+
+        let json: Package = serde_json::from_str(&serde_json::to_string(&package).unwrap()).unwrap();
+        assert_eq!(json.source, PackageSource::Homebrew);
+        assert_eq!(json.dependencies, package.dependencies);
+
+        let yaml: Package = serde_yaml::from_str(&serde_yaml::to_string(&package).unwrap()).unwrap();
+        assert_eq!(yaml.source, PackageSource::Homebrew);
+        assert_eq!(yaml.version, package.version);
+    }
 }
@@ -1,11 +1,18 @@
 // npm global package scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::{Context, Result};
-use serde::{Deserialize, Serialize};
+use super::{Package, PackageSource, ScanContext, Scanner};
+use crate::error::{Context, Result};
+use crate::utils::process::output;
+use serde::Deserialize;
 use std::collections::HashMap;
+use crate::utils::size::SizeCache;
 use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct NpmScanner;
+pub struct NpmScanner {
+    timeout: Duration,
+    sizes: Arc<SizeCache>,
+}
 
 #[derive(Debug, Deserialize)]
 struct NpmList {
@@ -13,22 +20,29 @@ struct NpmList {
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct NpmPackage {
     version: String,
     #[serde(default)]
     overridden: bool,
+    /// Only present with `--long`
+    #[serde(default)]
+    description: Option<String>,
+}
+
+impl Default for NpmScanner {
+    fn default() -> Self {
+        Self::new(&ScanContext::default())
+    }
 }
 
 impl NpmScanner {
-    pub fn new() -> Self {
-        Self
+    pub fn new(context: &ScanContext) -> Self {
+        Self { timeout: context.timeout(), sizes: context.sizes.clone() }
     }
 
     fn get_global_packages(&self) -> Result<Vec<Package>> {
-        let output = Command::new("npm")
-            .args(["list", "-g", "--depth=0", "--json"])
-            .output()
-            .context("Failed to run npm list -g")?;
+        let output = output(Command::new("npm").args(["list", "-g", "--depth=0", "--json", "--long"]), self.timeout)?;
 
         if !output.status.success() {
             // npm may return non-zero even on success with warnings
@@ -52,6 +66,7 @@ impl NpmScanner {
 
                 let mut package = Package::new(name.clone(), PackageSource::Npm);
                 package.version = Some(pkg_info.version);
+                package.description = pkg_info.description.filter(|d| !d.trim().is_empty());
 
                 // Try to find the binary path
                 package.binary_path = self.find_npm_binary(&name);
@@ -61,7 +76,8 @@ impl NpmScanner {
                     if let Some(parent) = bin_path.parent() {
                         if let Some(node_modules) = parent.parent() {
                             let pkg_path = node_modules.join("lib/node_modules").join(&name);
-                            package.size_bytes = crate::utils::size::calculate_directory_size(&pkg_path).ok();
+                            package.size_bytes = self.sizes.size_of(&pkg_path).ok();
+                            package.size_path = Some(pkg_path);
                         }
                     }
                 }
@@ -82,7 +98,7 @@ impl NpmScanner {
         // Also check common patterns for package names with @org/package format
         if package_name.contains('/') {
             // Extract the package name part after the slash
-            if let Some(bin_name) = package_name.split('/').last() {
+            if let Some(bin_name) = package_name.split('/').next_back() {
                 if let Ok(path) = which::which(bin_name) {
                     return Some(path);
                 }
@@ -101,6 +117,20 @@ impl Scanner for NpmScanner {
     fn is_available(&self) -> bool {
         which::which("npm").is_ok()
     }
+
+    fn change_markers(&self) -> Vec<std::path::PathBuf> {
+        global_modules().into_iter().collect()
+    }
+}
+
+/// The directory global packages are installed into: lib/node_modules under the global
+/// prefix, which is `NPM_CONFIG_PREFIX` or else the directory holding npm's bin
+fn global_modules() -> Option<std::path::PathBuf> {
+    let prefix = match std::env::var_os("NPM_CONFIG_PREFIX") {
+        Some(prefix) => std::path::PathBuf::from(prefix),
+        None => which::which("npm").ok()?.parent()?.parent()?.to_path_buf(),
+    };
+    Some(prefix.join("lib/node_modules"))
 }
 
 #[cfg(test)]
@@ -109,14 +139,14 @@ mod tests {
 
     #[test]
     fn test_scanner_available() {
-        let scanner = NpmScanner::new();
+        let scanner = NpmScanner::default();
         println!("npm available: {}", scanner.is_available());
     }
 
     #[test]
     #[ignore] // Run this manually as it requires npm to be installed
     fn test_scan_npm_packages() {
-        let scanner = NpmScanner::new();
+        let scanner = NpmScanner::default();
         if scanner.is_available() {
             let packages = scanner.scan().unwrap();
             println!("Found {} npm packages", packages.len());
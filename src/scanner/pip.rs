@@ -1,10 +1,15 @@
 // pip/pip3/pipx package scanner
-use super::{Package, PackageSource, Scanner};
-use anyhow::{Context, Result};
+use super::{Package, PackageSource, ScanContext, Scanner};
+use crate::error::{Context, MacsweepError, Result};
+use crate::utils::process::output;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::process::Command;
+use std::time::Duration;
 
-pub struct PipScanner;
+pub struct PipScanner {
+    timeout: Duration,
+}
 
 #[derive(Debug, Deserialize)]
 struct PipPackage {
@@ -12,20 +17,53 @@ struct PipPackage {
     version: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct PipInspect {
+    installed: Vec<PipInspectEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipInspectEntry {
+    metadata: PipMetadata,
+    /// Whether the distribution was installed on request rather than as a dependency
+    #[serde(default)]
+    requested: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipMetadata {
+    name: String,
+    #[serde(default)]
+    requires_dist: Vec<String>,
+}
+
+/// Why a distribution is installed and what it requires, from `pip inspect`
+#[derive(Debug, Default, PartialEq)]
+struct PipInstall {
+    requested: Option<bool>,
+    /// Normalized names of the distributions it requires, leaving out optional extras
+    requires: Vec<String>,
+}
+
+impl Default for PipScanner {
+    fn default() -> Self {
+        Self::new(&ScanContext::default())
+    }
+}
+
 impl PipScanner {
-    pub fn new() -> Self {
-        Self
+    pub fn new(context: &ScanContext) -> Self {
+        Self { timeout: context.timeout() }
     }
 
     fn scan_pip_executable(&self, pip_cmd: &str) -> Result<Vec<Package>> {
-        let output = Command::new(pip_cmd)
-            .args(["list", "--format=json"])
-            .output()
-            .context(format!("Failed to run {} list", pip_cmd))?;
+        let output = output(Command::new(pip_cmd).args(["list", "--format=json"]), self.timeout)?;
 
         if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            anyhow::bail!("{} list failed: {}", pip_cmd, stderr);
+            return Err(MacsweepError::CommandFailed {
+                program: format!("{} list", pip_cmd),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
         }
 
         let json = String::from_utf8(output.stdout)
@@ -35,6 +73,12 @@ impl PipScanner {
             .context(format!("Failed to parse {} list JSON", pip_cmd))?;
 
         let mut packages = Vec::new();
+        let mut summaries = self.fetch_summaries(pip_cmd, &pip_packages);
+        let mut installs = self.fetch_installs(pip_cmd);
+        // Requirements name distributions loosely (`PyYAML`, `pyyaml`); store the listed names
+        let listed_names: HashMap<String, String> = pip_packages.iter()
+            .map(|p| (normalize_name(&p.name), p.name.clone()))
+            .collect();
 
         for pip_pkg in pip_packages {
             // Skip pip and setuptools as they're base packages
@@ -44,6 +88,13 @@ impl PipScanner {
 
             let mut package = Package::new(pip_pkg.name.clone(), PackageSource::Pip);
             package.version = Some(pip_pkg.version);
+            package.description = summaries.remove(&pip_pkg.name.to_lowercase());
+            if let Some(install) = installs.remove(&normalize_name(&pip_pkg.name)) {
+                package.is_dependency = install.requested == Some(false);
+                package.dependencies = install.requires.into_iter()
+                    .map(|name| listed_names.get(&name).cloned().unwrap_or(name))
+                    .collect();
+            }
 
             // Try to find the binary path (many Python packages install console scripts)
             package.binary_path = self.find_pip_binary(&pip_pkg.name);
@@ -54,11 +105,50 @@ impl PipScanner {
         Ok(packages)
     }
 
+    /// Package summaries from a single `pip show` call, keyed by lowercase name.
+    /// Descriptions are a nice-to-have, so any failure just yields none.
+    fn fetch_summaries(&self, pip_cmd: &str, pip_packages: &[PipPackage]) -> HashMap<String, String> {
+        if pip_packages.is_empty() {
+            return HashMap::new();
+        }
+
+        let output = output(
+            Command::new(pip_cmd).arg("show").args(pip_packages.iter().map(|p| p.name.as_str())),
+            self.timeout,
+        );
+
+        match output {
+            Ok(output) => parse_pip_show_summaries(&String::from_utf8_lossy(&output.stdout)),
+            Err(e) => {
+                tracing::debug!(source = "pip", command = pip_cmd, error = %e, "Failed to run pip show");
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Install reasons and requirements keyed by normalized name; `pip inspect` needs pip
+    /// 22.2 or later, and without it packages just have no dependency information
+    fn fetch_installs(&self, pip_cmd: &str) -> HashMap<String, PipInstall> {
+        let output = match output(Command::new(pip_cmd).arg("inspect"), self.timeout) {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                tracing::debug!(source = "pip", command = pip_cmd, stderr = %String::from_utf8_lossy(&output.stderr).trim(), "pip inspect failed");
+                return HashMap::new();
+            }
+            Err(e) => {
+                tracing::debug!(source = "pip", command = pip_cmd, error = %e, "Failed to run pip inspect");
+                return HashMap::new();
+            }
+        };
+
+        parse_pip_inspect(&String::from_utf8_lossy(&output.stdout)).unwrap_or_else(|e| {
+            tracing::warn!(source = "pip", command = pip_cmd, error = %e, "Failed to parse pip inspect output");
+            HashMap::new()
+        })
+    }
+
     fn scan_pipx(&self) -> Result<Vec<Package>> {
-        let output = Command::new("pipx")
-            .args(["list", "--short"])
-            .output()
-            .context("Failed to run pipx list")?;
+        let output = output(Command::new("pipx").args(["list", "--short"]), self.timeout)?;
 
         if !output.status.success() {
             return Ok(Vec::new());
@@ -70,7 +160,7 @@ impl PipScanner {
         let mut packages = Vec::new();
 
         for line in stdout.lines() {
-            let parts: Vec<&str> = line.trim().split_whitespace().collect();
+            let parts: Vec<&str> = line.split_whitespace().collect();
             if parts.is_empty() {
                 continue;
             }
@@ -107,34 +197,111 @@ impl PipScanner {
     }
 }
 
-impl Scanner for PipScanner {
-    fn scan(&self) -> Result<Vec<Package>> {
-        let mut all_packages = Vec::new();
+/// Parse the `Name:` / `Summary:` pairs of `pip show` output (records separated by `---`)
+fn parse_pip_show_summaries(output: &str) -> HashMap<String, String> {
+    let mut summaries = HashMap::new();
+    let mut name: Option<String> = None;
+
+    for line in output.lines() {
+        if line.starts_with("---") {
+            name = None;
+        } else if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim().to_lowercase());
+        } else if let Some(value) = line.strip_prefix("Summary:") {
+            let value = value.trim();
+            if let (Some(name), false) = (name.take(), value.is_empty() || value == "UNKNOWN") {
+                summaries.insert(name, value.to_string());
+            }
+        }
+    }
+
+    summaries
+}
+
+fn parse_pip_inspect(json: &str) -> Result<HashMap<String, PipInstall>> {
+    let inspect: PipInspect = serde_json::from_str(json).context("Invalid pip inspect JSON")?;
 
-        // Try pip3 first (preferred on macOS)
-        if which::which("pip3").is_ok() {
-            match self.scan_pip_executable("pip3") {
-                Ok(mut packages) => all_packages.append(&mut packages),
-                Err(e) => eprintln!("Warning: Failed to scan pip3: {}", e),
+    Ok(inspect.installed.into_iter()
+        .map(|entry| {
+            let requires = entry.metadata.requires_dist.iter().filter_map(|r| requirement_name(r)).collect();
+            (normalize_name(&entry.metadata.name), PipInstall { requested: entry.requested, requires })
+        })
+        .collect())
+}
+
+/// The distribution a `Requires-Dist` entry names, e.g. `pysocks` for
+/// `PySocks!=1.5.7,>=1.5.6; extra == "socks"`; `None` for optional extras
+fn requirement_name(requirement: &str) -> Option<String> {
+    let (spec, marker) = requirement.split_once(';').unwrap_or((requirement, ""));
+    if marker.contains("extra") {
+        return None;
+    }
+    let name: String = spec.trim()
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect();
+    (!name.is_empty()).then(|| normalize_name(&name))
+}
+
+/// PEP 503 name normalization: lowercase, with runs of `-`, `_` and `.` as a single `-`
+fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !normalized.ends_with('-') {
+                normalized.push('-');
             }
+        } else {
+            normalized.push(c.to_ascii_lowercase());
         }
-        // Try pip if pip3 not available
-        else if which::which("pip").is_ok() {
-            match self.scan_pip_executable("pip") {
-                Ok(mut packages) => all_packages.append(&mut packages),
-                Err(e) => eprintln!("Warning: Failed to scan pip: {}", e),
+    }
+    normalized
+}
+
+impl Scanner for PipScanner {
+    fn scan(&self) -> Result<Vec<Package>> {
+        self.scan_sources().map(|(packages, _)| packages)
+    }
+
+    /// pip and pipx are listed separately; when one fails, only the other's packages are
+    /// complete, and when both fail the scan does
+    fn scan_sources(&self) -> Result<(Vec<Package>, Option<Vec<PackageSource>>)> {
+        let mut all_packages = Vec::new();
+        let mut scanned = Vec::new();
+        let mut errors = Vec::new();
+
+        // Try pip3 first (preferred on macOS), then pip
+        if let Some(pip_cmd) = ["pip3", "pip"].into_iter().find(|cmd| which::which(cmd).is_ok()) {
+            match self.scan_pip_executable(pip_cmd) {
+                Ok(mut packages) => {
+                    all_packages.append(&mut packages);
+                    scanned.push(PackageSource::Pip);
+                }
+                Err(e) => {
+                    tracing::warn!(source = "pip", command = pip_cmd, error = %e, "Failed to scan pip packages");
+                    errors.push(e);
+                }
             }
         }
 
         // Try pipx if available
         if which::which("pipx").is_ok() {
             match self.scan_pipx() {
-                Ok(mut packages) => all_packages.append(&mut packages),
-                Err(e) => eprintln!("Warning: Failed to scan pipx: {}", e),
+                Ok(mut packages) => {
+                    all_packages.append(&mut packages);
+                    scanned.push(PackageSource::Pipx);
+                }
+                Err(e) => {
+                    tracing::warn!(source = "pipx", command = "pipx", error = %e, "Failed to scan pipx packages");
+                    errors.push(e);
+                }
             }
         }
 
-        Ok(all_packages)
+        if scanned.is_empty() && !errors.is_empty() {
+            return Err(errors.remove(0));
+        }
+        Ok((all_packages, Some(scanned)))
     }
 
     fn is_available(&self) -> bool {
@@ -148,14 +315,45 @@ mod tests {
 
     #[test]
     fn test_scanner_available() {
-        let scanner = PipScanner::new();
+        let scanner = PipScanner::default();
         println!("pip/pip3/pipx available: {}", scanner.is_available());
     }
 
+    #[test]
+    fn test_parse_pip_show_summaries() {
+        let output = "Name: requests\nVersion: 2.31.0\nSummary: Python HTTP for Humans.\nHome-page: https://requests.readthedocs.io\n---\nName: PyYAML\nVersion: 6.0.1\nSummary: YAML parser and emitter for Python\n---\nName: internal-tool\nVersion: 0.1\nSummary: UNKNOWN\n";
+
+        let summaries = parse_pip_show_summaries(output);
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries["requests"], "Python HTTP for Humans.");
+        assert_eq!(summaries["pyyaml"], "YAML parser and emitter for Python");
+    }
+
+    #[test]
+    fn test_parse_pip_inspect() {
+        let json = r#"{"version": "1", "installed": [
+            {"metadata": {"name": "requests", "version": "2.31.0",
+                          "requires_dist": ["charset-normalizer<4,>=2", "idna<4,>=2.5", "PySocks!=1.5.7,>=1.5.6; extra == \"socks\""]},
+             "requested": false},
+            {"metadata": {"name": "Flask_Login", "version": "0.6.3", "requires_dist": ["Flask>=1.0.4", "Werkzeug (>=1.0.1)"]},
+             "requested": true},
+            {"metadata": {"name": "idna", "version": "3.6"}}
+        ]}"#;
+
+        let installs = parse_pip_inspect(json).unwrap();
+        assert_eq!(installs["requests"], PipInstall { requested: Some(false), requires: vec!["charset-normalizer".to_string(), "idna".to_string()] });
+        assert_eq!(installs["flask-login"].requires, ["flask", "werkzeug"]);
+        assert_eq!(installs["flask-login"].requested, Some(true));
+        assert_eq!(installs["idna"], PipInstall::default());
+
+        assert_eq!(normalize_name("Zope.Interface__x"), "zope-interface-x");
+        assert!(parse_pip_inspect("not json").is_err());
+    }
+
     #[test]
     #[ignore] // Run manually as it requires pip to be installed
     fn test_scan_pip_packages() {
-        let scanner = PipScanner::new();
+        let scanner = PipScanner::default();
         if scanner.is_available() {
             let packages = scanner.scan().unwrap();
             println!("Found {} pip packages", packages.len());
@@ -0,0 +1,265 @@
+// Plugin scanners: executables in ~/.config/macsweep/plugins that report packages macsweep
+// doesn't know about. A plugin is run with one of three subcommands:
+//
+//   is-available   exit 0 if the tool it covers is installed here
+//   scan           print one JSON object per line: {"name": ..., "version": ...,
+//                  "size_bytes": ..., "binary_path": ..., "install_date": ...}; only name is
+//                  required, install_date is RFC 3339 or YYYY-MM-DD
+//   remove NAME    uninstall the package, exiting 0 once it's gone
+use super::registry::{ScannerRegistry, SourceGroup};
+use super::{Package, PackageSource, ScanContext, Scanner};
+use crate::error::{Context, MacsweepError, Result};
+use crate::utils::process::{output, run_command_with_timeout};
+use chrono::{DateTime, NaiveDate, Utc};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// An executable in the plugins directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plugin {
+    /// The file name up to its first dot: `corp-sdk.sh` is `corp-sdk`
+    pub name: String,
+    pub path: PathBuf,
+    /// `plugin:<name>`
+    pub source: PackageSource,
+    /// Shown while scanning, e.g. "corp-sdk (plugin)"
+    pub label: String,
+    /// How its packages are counted in breakdowns, e.g. "corp-sdk packages (plugin)"
+    pub group_label: String,
+}
+
+lazy_static! {
+    /// The plugins installed when first asked for; the directory is read once per run
+    static ref INSTALLED: Vec<Plugin> = plugins_dir().map(|dir| without_builtin_names(discover(&dir))).unwrap_or_default();
+    static ref GROUPS: Vec<SourceGroup> = INSTALLED
+        .iter()
+        .map(|plugin| SourceGroup { sources: std::slice::from_ref(&plugin.source), label: &plugin.group_label })
+        .collect();
+}
+
+/// `~/.config/macsweep/plugins`, next to the config file
+pub fn plugins_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("macsweep").join("plugins"))
+}
+
+/// The plugins in the plugins directory
+pub fn installed() -> &'static [Plugin] {
+    &INSTALLED
+}
+
+/// The breakdown group of each installed plugin, in the order of `installed`
+pub fn source_groups() -> &'static [SourceGroup] {
+    &GROUPS
+}
+
+/// The installed plugin called `name`
+pub fn find(name: &str) -> Option<&'static Plugin> {
+    installed().iter().find(|plugin| plugin.name == name)
+}
+
+/// Plugin names are used in sources (`plugin:corp-sdk`) and `--source`, so they are kept to
+/// letters, digits, `-` and `_`
+pub fn valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphanumeric()) && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// The executables in `dir`, sorted by name. Hidden files, files that aren't executable and
+/// names that aren't `valid_name` are skipped, as is a second file with the same name.
+pub fn discover(dir: &Path) -> Vec<Plugin> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    let mut plugins: Vec<Plugin> = Vec::new();
+    for path in paths {
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if file_name.starts_with('.') || path.to_str().is_none() {
+            continue;
+        }
+        let executable = std::fs::metadata(&path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0);
+        if !executable {
+            tracing::debug!(path = %path.display(), "Skipping plugin that isn't an executable file");
+            continue;
+        }
+        let name = file_name.split('.').next().unwrap_or_default();
+        if !valid_name(name) {
+            tracing::warn!(path = %path.display(), "Skipping plugin; its name may only use letters, digits, - and _");
+            continue;
+        }
+        if plugins.iter().any(|p| p.name == name) {
+            tracing::warn!(path = %path.display(), plugin = name, "Skipping plugin; another plugin has the same name");
+            continue;
+        }
+        plugins.push(Plugin {
+            name: name.to_string(),
+            source: PackageSource::plugin(name),
+            label: format!("{} (plugin)", name),
+            group_label: format!("{} packages (plugin)", name),
+            path,
+        });
+    }
+    plugins
+}
+
+/// `plugins` without those `--source` couldn't tell from a built-in scanner, e.g. `brew`
+fn without_builtin_names(plugins: Vec<Plugin>) -> Vec<Plugin> {
+    let builtin = ScannerRegistry::builtin();
+    plugins
+        .into_iter()
+        .filter(|plugin| {
+            let taken = builtin.select(Some(&plugin.name)).is_ok();
+            if taken {
+                tracing::warn!(path = %plugin.path.display(), plugin = %plugin.name, "Skipping plugin named like a built-in source");
+            }
+            !taken
+        })
+        .collect()
+}
+
+/// A package as a plugin's `scan` prints it
+#[derive(Debug, Deserialize)]
+struct PluginPackage {
+    name: String,
+    version: Option<String>,
+    size_bytes: Option<u64>,
+    binary_path: Option<PathBuf>,
+    install_date: Option<String>,
+}
+
+pub struct PluginScanner {
+    plugin: Plugin,
+    timeout: Duration,
+}
+
+impl PluginScanner {
+    pub fn new(plugin: Plugin, context: &ScanContext) -> Self {
+        Self { plugin, timeout: context.timeout() }
+    }
+
+    fn program(&self) -> &str {
+        self.plugin.path.to_str().expect("plugin paths are UTF-8")
+    }
+}
+
+impl Scanner for PluginScanner {
+    fn scan(&self) -> Result<Vec<Package>> {
+        let stdout = run_command_with_timeout(self.program(), &["scan"], self.timeout)?;
+        parse_packages(&stdout, &self.plugin)
+    }
+
+    fn is_available(&self) -> bool {
+        output(Command::new(&self.plugin.path).arg("is-available"), self.timeout).is_ok_and(|o| o.status.success())
+    }
+}
+
+/// The packages of a plugin's `scan` output; blank lines are skipped, anything else that isn't
+/// a package fails the whole scan, so a broken plugin doesn't mark its packages as removed
+fn parse_packages(stdout: &str, plugin: &Plugin) -> Result<Vec<Package>> {
+    let mut packages = Vec::new();
+    for (number, line) in stdout.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let invalid = || format!("plugin {} printed an invalid package on line {}", plugin.name, number + 1);
+        let found: PluginPackage = serde_json::from_str(line).with_context(invalid)?;
+        if found.name.trim().is_empty() {
+            return Err(MacsweepError::Other(format!("{}: the name is empty", invalid())));
+        }
+        let mut package = Package::new(found.name, plugin.source.clone());
+        package.version = found.version.filter(|v| !v.is_empty());
+        package.size_bytes = found.size_bytes;
+        package.binary_path = found.binary_path;
+        package.install_date = found.install_date.as_deref().map(parse_install_date).transpose().with_context(invalid)?;
+        packages.push(package);
+    }
+    Ok(packages)
+}
+
+fn parse_install_date(text: &str) -> Result<DateTime<Utc>> {
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc());
+    }
+    Ok(DateTime::parse_from_rfc3339(text)?.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_plugin(dir: &Path, file_name: &str, script: &str, mode: u32) -> PathBuf {
+        let path = dir.join(file_name);
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_discover_plugins() {
+        let dir = TempDir::new().unwrap();
+        write_plugin(dir.path(), "corp-sdk.sh", "#!/bin/sh\n", 0o755);
+        write_plugin(dir.path(), "internal_cli", "#!/bin/sh\n", 0o755);
+        write_plugin(dir.path(), "README.md", "Plugins live here\n", 0o644);
+        write_plugin(dir.path(), ".hidden", "#!/bin/sh\n", 0o755);
+        write_plugin(dir.path(), "bad name", "#!/bin/sh\n", 0o755);
+        write_plugin(dir.path(), "corp-sdk.py", "#!/bin/sh\n", 0o755);
+        std::fs::create_dir(dir.path().join("lib")).unwrap();
+
+        let plugins = discover(dir.path());
+        let names: Vec<&str> = plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["corp-sdk", "internal_cli"]);
+        assert_eq!(plugins[0].path, dir.path().join("corp-sdk.py"));
+        assert_eq!(plugins[0].source.as_str(), "plugin:corp-sdk");
+        assert!(discover(&dir.path().join("missing")).is_empty());
+
+        write_plugin(dir.path(), "brew", "#!/bin/sh\n", 0o755);
+        write_plugin(dir.path(), "homebrewcask", "#!/bin/sh\n", 0o755);
+        let names: Vec<String> = without_builtin_names(discover(dir.path())).into_iter().map(|p| p.name).collect();
+        assert_eq!(names, ["corp-sdk", "internal_cli"]);
+
+        assert!(valid_name("sdk2") && valid_name("my_tool-x"));
+        assert!(!valid_name("") && !valid_name("-x") && !valid_name("a/b") && !valid_name("a.b"));
+    }
+
+    #[test]
+    fn test_scan_parses_json_lines() {
+        let dir = TempDir::new().unwrap();
+        let script = r#"#!/bin/sh
+case "$1" in
+  is-available) exit 0 ;;
+  scan)
+    echo '{"name": "sdk-java", "version": "21.0.2", "size_bytes": 4096, "binary_path": "/opt/sdk/bin/java", "install_date": "2024-06-30"}'
+    echo
+    echo '{"name": "sdk-go", "install_date": "2024-07-01T10:00:00+02:00", "homepage": "ignored"}'
+    ;;
+  *) exit 2 ;;
+esac
+"#;
+        write_plugin(dir.path(), "sdkman", script, 0o755);
+        let plugin = discover(dir.path()).remove(0);
+        let scanner = PluginScanner::new(plugin.clone(), &ScanContext::default());
+        assert!(scanner.is_available());
+
+        let packages = scanner.scan().unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[0].source, PackageSource::plugin("sdkman"));
+        assert_eq!((packages[0].version.as_deref(), packages[0].size_bytes), (Some("21.0.2"), Some(4096)));
+        assert_eq!(packages[0].binary_path.as_deref(), Some(Path::new("/opt/sdk/bin/java")));
+        assert_eq!(packages[0].install_date.unwrap().to_rfc3339(), "2024-06-30T00:00:00+00:00");
+        assert_eq!(packages[1].install_date.unwrap().to_rfc3339(), "2024-07-01T08:00:00+00:00");
+        assert_eq!(packages[1].version, None);
+
+        let err = parse_packages("{\"name\": \"ok\"}\nnot json\n", &plugin).unwrap_err();
+        assert!(err.to_string().starts_with("plugin sdkman printed an invalid package on line 2"), "{}", err);
+        assert!(parse_packages("{\"name\": \"x\", \"install_date\": \"last week\"}", &plugin).is_err());
+        assert!(parse_packages("{\"name\": \" \"}", &plugin).unwrap_err().to_string().ends_with("the name is empty"));
+
+        write_plugin(dir.path(), "sdkman", "#!/bin/sh\nexit 1\n", 0o755);
+        assert!(!scanner.is_available());
+    }
+}
@@ -0,0 +1,309 @@
+// The scanners macsweep knows about, with the names `--source` selects them by and how their
+// packages are shown; scan, stats and the source filters all iterate this instead of naming
+// each source themselves
+use super::applications::ApplicationsScanner;
+use super::cargo::CargoScanner;
+use super::homebrew::HomebrewScanner;
+use super::npm::NpmScanner;
+use super::pip::PipScanner;
+use super::plugin::{self, PluginScanner};
+use super::{PackageSource, ScanContext, Scanner};
+use crate::error::{MacsweepError, Result};
+
+/// Packages of one or more sources counted together in a breakdown, e.g. "pip/pipx packages"
+#[derive(Debug, Clone, Copy)]
+pub struct SourceGroup {
+    pub sources: &'static [PackageSource],
+    pub label: &'static str,
+}
+
+/// Creates a scanner for a scan
+type NewScanner = Box<dyn Fn(&ScanContext) -> Box<dyn Scanner> + Send + Sync>;
+
+/// A scanner and what the CLI shows for it
+pub struct RegisteredScanner {
+    /// Canonical `--source` name
+    pub name: &'static str,
+    /// Other names `--source` accepts, e.g. "brew" for "homebrew"
+    pub aliases: &'static [&'static str],
+    /// Shown while scanning, e.g. "npm (global)"
+    pub label: &'static str,
+    /// What a scan of it counts, "packages" or "apps"
+    pub unit: &'static str,
+    /// How its packages are broken down after a scan and in stats
+    pub groups: &'static [SourceGroup],
+    /// Scanners are created when a scan needs them, since some (Homebrew's) run a command
+    /// to set themselves up
+    new: NewScanner,
+}
+
+impl RegisteredScanner {
+    pub fn new(
+        name: &'static str,
+        aliases: &'static [&'static str],
+        label: &'static str,
+        unit: &'static str,
+        groups: &'static [SourceGroup],
+        new: impl Fn(&ScanContext) -> Box<dyn Scanner> + Send + Sync + 'static,
+    ) -> Self {
+        Self { name, aliases, label, unit, groups, new: Box::new(new) }
+    }
+
+    pub fn scanner(&self, context: &ScanContext) -> Box<dyn Scanner> {
+        (self.new)(context)
+    }
+
+    /// The package sources the scanner reports
+    pub fn covers(&self) -> Vec<PackageSource> {
+        self.groups.iter().flat_map(|g| g.sources.iter().cloned()).collect()
+    }
+
+    fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name) || self.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Every scanner, in the order scans run them
+pub struct ScannerRegistry {
+    scanners: Vec<RegisteredScanner>,
+}
+
+/// The built-in scanners, then a scanner for each installed plugin
+impl Default for ScannerRegistry {
+    fn default() -> Self {
+        let mut registry = Self::builtin();
+        for (plugin, group) in plugin::installed().iter().zip(plugin::source_groups()) {
+            registry.register(RegisteredScanner::new(
+                &plugin.name,
+                &[],
+                &plugin.label,
+                "packages",
+                std::slice::from_ref(group),
+                move |context| Box::new(PluginScanner::new(plugin.clone(), context)),
+            ));
+        }
+        registry
+    }
+}
+
+impl ScannerRegistry {
+    /// The scanners built into macsweep, without plugins
+    pub fn builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.register(RegisteredScanner::new(
+            "homebrew",
+            &["brew"],
+            "Homebrew",
+            "packages",
+            &[
+                SourceGroup { sources: &[PackageSource::Homebrew], label: "Homebrew formulae" },
+                SourceGroup { sources: &[PackageSource::HomebrewCask], label: "Homebrew casks" },
+            ],
+            |context| Box::new(HomebrewScanner::new(context)),
+        ));
+        registry.register(RegisteredScanner::new(
+            "npm",
+            &[],
+            "npm (global)",
+            "packages",
+            &[SourceGroup { sources: &[PackageSource::Npm], label: "npm global packages" }],
+            |context| Box::new(NpmScanner::new(context)),
+        ));
+        registry.register(RegisteredScanner::new(
+            "pip",
+            &["python"],
+            "pip/pipx",
+            "packages",
+            &[SourceGroup { sources: &[PackageSource::Pip, PackageSource::Pipx], label: "pip/pipx packages" }],
+            |context| Box::new(PipScanner::new(context)),
+        ));
+        registry.register(RegisteredScanner::new(
+            "cargo",
+            &["rust"],
+            "cargo",
+            "packages",
+            &[SourceGroup { sources: &[PackageSource::Cargo], label: "cargo binaries" }],
+            |context| Box::new(CargoScanner::new(context)),
+        ));
+        registry.register(RegisteredScanner::new(
+            "applications",
+            &["apps"],
+            "Applications",
+            "apps",
+            &[SourceGroup { sources: &[PackageSource::Applications], label: "Applications" }],
+            |context| Box::new(ApplicationsScanner::new(context)),
+        ));
+        registry
+    }
+
+    /// A registry without any scanners
+    pub fn empty() -> Self {
+        Self { scanners: Vec::new() }
+    }
+
+    /// Add a scanner, run after the ones already registered
+    pub fn register(&mut self, scanner: RegisteredScanner) {
+        self.scanners.push(scanner);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredScanner> {
+        self.scanners.iter()
+    }
+
+    /// Every breakdown group, in registry order
+    pub fn groups(&self) -> impl Iterator<Item = &SourceGroup> {
+        self.scanners.iter().flat_map(|s| s.groups.iter())
+    }
+
+    /// The scanners a `--source` value selects (all of them for None). Besides the scanner
+    /// names and aliases it accepts a single package source such as "homebrewcask", which
+    /// selects the scanner reporting it.
+    pub fn select(&self, source: Option<&str>) -> Result<Vec<&RegisteredScanner>> {
+        let Some(name) = source else {
+            return Ok(self.scanners.iter().collect());
+        };
+        let selected: Vec<_> = self.scanners.iter().filter(|s| s.is_named(name)).collect();
+        if !selected.is_empty() {
+            return Ok(selected);
+        }
+        let source = self.package_source(name)?;
+        Ok(self.scanners.iter().filter(|s| s.covers().contains(&source)).collect())
+    }
+
+    /// The scanners any of `names` selects, in registry order (all of them when there are no names)
+    pub fn select_all<S: AsRef<str>>(&self, names: &[S]) -> Result<Vec<&RegisteredScanner>> {
+        if names.is_empty() {
+            return self.select(None);
+        }
+        let chosen = names.iter().map(|name| self.select(Some(name.as_ref()))).collect::<Result<Vec<_>>>()?;
+        Ok(self.scanners.iter().filter(|s| chosen.iter().flatten().any(|c| std::ptr::eq(*c, *s))).collect())
+    }
+
+    /// The package sources a `--source` value means: everything its scanner reports, or
+    /// just the one package source it names
+    pub fn sources(&self, name: &str) -> Result<Vec<PackageSource>> {
+        match self.scanners.iter().find(|s| s.is_named(name)) {
+            Some(scanner) => Ok(scanner.covers()),
+            None => Ok(vec![self.package_source(name)?]),
+        }
+    }
+
+    /// The package sources any of `names` means, in registry order; None when there are no
+    /// names, i.e. every source
+    pub fn sources_all<S: AsRef<str>>(&self, names: &[S]) -> Result<Option<Vec<PackageSource>>> {
+        if names.is_empty() {
+            return Ok(None);
+        }
+        let chosen = names.iter().map(|name| self.sources(name.as_ref())).collect::<Result<Vec<_>>>()?;
+        let covered = self.scanners.iter().flat_map(|s| s.covers());
+        Ok(Some(covered.filter(|source| chosen.iter().flatten().any(|c| c == source)).collect()))
+    }
+
+    fn package_source(&self, name: &str) -> Result<PackageSource> {
+        let covered: Vec<PackageSource> = self.scanners.iter().flat_map(|s| s.covers()).collect();
+        covered.iter().find(|source| source.as_str().eq_ignore_ascii_case(name)).cloned().ok_or_else(|| {
+            let names = self.scanners.iter().flat_map(|s| std::iter::once(s.name).chain(s.aliases.iter().copied()));
+            MacsweepError::unknown_source(name, self.known_names(), names.chain(covered.iter().map(PackageSource::as_str)))
+        })
+    }
+
+    /// Names `--source` accepts, with their aliases: "homebrew (brew)"
+    pub fn known_names(&self) -> Vec<String> {
+        self.scanners
+            .iter()
+            .map(|s| match s.aliases {
+                [] => s.name.to_string(),
+                aliases => format!("{} ({})", s.name, aliases.join(", ")),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(scanners: Vec<&RegisteredScanner>) -> Vec<&str> {
+        scanners.iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn test_select_by_name_alias_or_package_source() {
+        let registry = ScannerRegistry::default();
+        assert_eq!(names(registry.select(None).unwrap()), ["homebrew", "npm", "pip", "cargo", "applications"]);
+        assert_eq!(names(registry.select(Some("brew")).unwrap()), ["homebrew"]);
+        assert_eq!(names(registry.select(Some("Python")).unwrap()), ["pip"]);
+        assert_eq!(names(registry.select(Some("homebrewcask")).unwrap()), ["homebrew"]);
+
+        let both = registry.select_all(&["apps", "brew", "homebrewcask"]).unwrap();
+        assert_eq!(names(both), ["homebrew", "applications"]);
+        assert_eq!(registry.select_all::<&str>(&[]).unwrap().len(), 5);
+
+        assert_eq!(registry.sources("brew").unwrap(), [PackageSource::Homebrew, PackageSource::HomebrewCask]);
+        assert_eq!(registry.sources("pipx").unwrap(), [PackageSource::Pipx]);
+        assert_eq!(registry.sources("apps").unwrap(), [PackageSource::Applications]);
+    }
+
+    #[test]
+    fn test_sources_match_exactly() {
+        let registry = ScannerRegistry::default();
+        assert_eq!(registry.sources("python").unwrap(), [PackageSource::Pip, PackageSource::Pipx]);
+        assert_eq!(registry.sources("HomebrewCask").unwrap(), [PackageSource::HomebrewCask]);
+        assert!(registry.sources("mac").is_err());
+        assert!(registry.sources("brewer").is_err());
+
+        assert_eq!(registry.sources_all::<&str>(&[]).unwrap(), None);
+        assert_eq!(
+            registry.sources_all(&["apps", "brew"]).unwrap().unwrap(),
+            [PackageSource::Homebrew, PackageSource::HomebrewCask, PackageSource::Applications]
+        );
+        assert_eq!(registry.sources_all(&["pipx", "python"]).unwrap().unwrap(), [PackageSource::Pip, PackageSource::Pipx]);
+        assert!(registry.sources_all(&["npm", "nmp"]).is_err());
+    }
+
+    #[test]
+    fn test_unknown_source_lists_known_ones() {
+        let err = ScannerRegistry::default().select(Some("hombrew")).err().unwrap();
+        assert!(matches!(err, MacsweepError::UnknownSource { ref name, .. } if name == "hombrew"));
+        assert_eq!(
+            err.to_string(),
+            "Unknown source 'hombrew' (did you mean 'homebrew'?); known sources: homebrew (brew), npm, pip (python), cargo (rust), applications (apps)"
+        );
+
+        let suggested = |name: &str| match ScannerRegistry::default().sources(name).unwrap_err() {
+            MacsweepError::UnknownSource { suggestion, .. } => suggestion,
+            other => panic!("expected UnknownSource, got {:?}", other),
+        };
+        assert_eq!(suggested("nmp").as_deref(), Some("npm"));
+        assert_eq!(suggested("Applicaton").as_deref(), Some("applications"));
+        assert_eq!(suggested("homebrewcsk").as_deref(), Some("homebrewcask"));
+        assert_eq!(suggested("applic").as_deref(), Some("applications"));
+        assert_eq!(suggested("golang"), None);
+    }
+
+    #[test]
+    fn test_registered_scanner_is_scanned() {
+        struct Fixed;
+        impl Scanner for Fixed {
+            fn scan(&self) -> Result<Vec<crate::scanner::Package>> {
+                Ok(vec![crate::scanner::Package::new("rails".to_string(), PackageSource::Gem)])
+            }
+            fn is_available(&self) -> bool {
+                true
+            }
+        }
+
+        let mut registry = ScannerRegistry::empty();
+        registry.register(RegisteredScanner::new(
+            "gem",
+            &["ruby"],
+            "gems",
+            "packages",
+            &[SourceGroup { sources: &[PackageSource::Gem], label: "Ruby gems" }],
+            |_| Box::new(Fixed),
+        ));
+        let selected = registry.select(Some("ruby")).unwrap();
+        assert_eq!(selected[0].scanner(&ScanContext::default()).scan().unwrap()[0].name, "rails");
+        assert_eq!(registry.groups().map(|g| g.label).collect::<Vec<_>>(), ["Ruby gems"]);
+    }
+}
@@ -1,20 +1,26 @@
 // Database operations (CRUD for packages, usage events, scans)
-use anyhow::{Context, Result};
-use rusqlite::{Connection, params};
-use crate::scanner::{Package, PackageSource};
+use crate::error::{MacsweepError, Result};
+use rusqlite::{Connection, Transaction, params};
+use crate::analysis::trends::{MonthlyUsage, UsageTrend, USE_EVENT_TYPES};
+use crate::scanner::{Architecture, ChangeSignals, Package, PackageSource};
+use crate::usage::shell_history::HistoryFileState;
+use crate::usage::{ManualMark, UsageCheck, UsageConfidence};
+use crate::utils::size::CachedSize;
 use chrono::{DateTime, Utc};
 
 /// Insert or update a package in the database
 pub fn upsert_package(conn: &Connection, package: &Package) -> Result<i64> {
-    let source_str = format!("{:?}", package.source);
+    let source_str = package.source.to_string();
     let version_str = package.version.as_deref();
     let binary_path_str = package.binary_path.as_ref().map(|p| p.to_string_lossy().to_string());
+    let size_path_str = package.size_path.as_ref().map(|p| p.to_string_lossy().to_string());
     let install_date_str = package.install_date.map(|dt| dt.to_rfc3339());
     let last_used_str = package.last_used.map(|dt| dt.to_rfc3339());
+    let stale_versions_str = Some(package.stale_versions.join(",")).filter(|s| !s.is_empty());
 
-    conn.execute(
-        "INSERT INTO packages (name, source, version, binary_path, install_date, size_bytes, is_dependency, last_used, usage_count)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO packages (name, source, version, binary_path, install_date, size_bytes, is_dependency, last_used, usage_count, bundle_id, description, usage_confidence, stale_versions, stale_bytes, arch, launches_at_login, size_path)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)
          ON CONFLICT(name, source) DO UPDATE SET
             version = excluded.version,
             binary_path = excluded.binary_path,
@@ -23,7 +29,19 @@ pub fn upsert_package(conn: &Connection, package: &Package) -> Result<i64> {
             is_dependency = excluded.is_dependency,
             last_used = excluded.last_used,
             usage_count = excluded.usage_count,
-            last_seen = CURRENT_TIMESTAMP",
+            usage_confidence = excluded.usage_confidence,
+            bundle_id = excluded.bundle_id,
+            description = COALESCE(excluded.description, packages.description),
+            stale_versions = excluded.stale_versions,
+            stale_bytes = excluded.stale_bytes,
+            arch = excluded.arch,
+            launches_at_login = excluded.launches_at_login,
+            size_path = excluded.size_path,
+            last_seen = CURRENT_TIMESTAMP,
+            removed_at = NULL
+         RETURNING id",
+    )?;
+    let package_id: i64 = stmt.query_row(
         params![
             &package.name,
             &source_str,
@@ -34,18 +52,21 @@ pub fn upsert_package(conn: &Connection, package: &Package) -> Result<i64> {
             package.is_dependency,
             last_used_str,
             package.usage_count as i64,
+            package.bundle_id,
+            package.description,
+            package.usage_confidence.as_str(),
+            stale_versions_str,
+            package.stale_bytes.map(|s| s as i64),
+            package.arch.map(|a| a.as_str()),
+            package.launches_at_login,
+            size_path_str,
         ],
-    )?;
-
-    // Get the package ID
-    let package_id: i64 = conn.query_row(
-        "SELECT id FROM packages WHERE name = ?1 AND source = ?2",
-        params![&package.name, &source_str],
         |row| row.get(0),
     )?;
 
     // Store dependencies
     store_dependencies(conn, package_id, &package.dependencies)?;
+    store_binaries(conn, package_id, &package.binaries)?;
 
     Ok(package_id)
 }
@@ -53,103 +74,480 @@ pub fn upsert_package(conn: &Connection, package: &Package) -> Result<i64> {
 /// Store package dependencies
 fn store_dependencies(conn: &Connection, package_id: i64, dependencies: &[String]) -> Result<()> {
     // First, delete existing dependencies
-    conn.execute(
-        "DELETE FROM package_dependencies WHERE package_id = ?1",
-        params![package_id],
-    )?;
+    conn.prepare_cached("DELETE FROM package_dependencies WHERE package_id = ?1")?
+        .execute(params![package_id])?;
 
     // Insert new dependencies
+    let mut insert = conn.prepare_cached(
+        "INSERT INTO package_dependencies (package_id, dependency_name) VALUES (?1, ?2)"
+    )?;
     for dep in dependencies {
-        conn.execute(
-            "INSERT INTO package_dependencies (package_id, dependency_name) VALUES (?1, ?2)",
-            params![package_id, dep],
-        )?;
+        insert.execute(params![package_id, dep])?;
     }
 
     Ok(())
 }
 
-/// Get all packages from the database
-pub fn get_packages(conn: &Connection) -> Result<Vec<Package>> {
+/// Replace the executables recorded for a package
+fn store_binaries(conn: &Connection, package_id: i64, binaries: &[String]) -> Result<()> {
+    conn.prepare_cached("DELETE FROM package_binaries WHERE package_id = ?1")?
+        .execute(params![package_id])?;
+
+    let mut insert = conn.prepare_cached(
+        "INSERT OR IGNORE INTO package_binaries (package_id, binary_name) VALUES (?1, ?2)"
+    )?;
+    for binary in binaries {
+        insert.execute(params![package_id, binary])?;
+    }
+
+    Ok(())
+}
+
+/// Insert or update many packages within the caller's transaction, returning their ids in order.
+/// Committing once instead of per row is what makes saving a full scan fast.
+/// With a `scan_id`, known sizes are also added to the package's size history.
+pub fn upsert_packages(tx: &Transaction, packages: &[Package], scan_id: Option<i64>) -> Result<Vec<i64>> {
+    packages.iter()
+        .map(|package| {
+            let package_id = upsert_package(tx, package)?;
+            if let (Some(scan_id), Some(size)) = (scan_id, package.size_bytes) {
+                tx.prepare_cached(
+                    "INSERT OR REPLACE INTO package_sizes (package_id, scan_id, size_bytes) VALUES (?1, ?2, ?3)"
+                )?
+                .execute(params![package_id, scan_id, size as i64])?;
+            }
+            Ok(package_id)
+        })
+        .collect()
+}
+
+/// A package's size as recorded by one scan
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeRecord {
+    pub scan_id: i64,
+    pub scan_date: String,
+    pub size_bytes: i64,
+}
+
+impl SizeRecord {
+    /// When the scan that recorded this size ran
+    pub fn scanned_at(&self) -> Option<DateTime<Utc>> {
+        parse_sqlite_timestamp(&self.scan_date)
+    }
+}
+
+/// The last `limit` recorded sizes of a package, oldest first
+pub fn get_size_history(conn: &Connection, package_id: i64, limit: usize) -> Result<Vec<SizeRecord>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, source, version, binary_path, install_date,
-                size_bytes, is_dependency, last_used, usage_count
-         FROM packages
-         ORDER BY name"
-    )?;
-
-    let packages = stmt.query_map([], |row| {
-        let id: i64 = row.get(0)?;
-        let name: String = row.get(1)?;
-        let source_str: String = row.get(2)?;
-        let source = parse_package_source(&source_str);
-
-        let version: Option<String> = row.get(3)?;
-        let binary_path_str: Option<String> = row.get(4)?;
-        let binary_path = binary_path_str.map(PathBuf::from);
-
-        let install_date_str: Option<String> = row.get(5)?;
-        let install_date = install_date_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        let size_bytes: Option<i64> = row.get(6)?;
-        let is_dependency: bool = row.get(7)?;
-
-        let last_used_str: Option<String> = row.get(8)?;
-        let last_used = last_used_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        let usage_count: u32 = row.get(9).unwrap_or(0);
-
-        Ok((id, Package {
-            name,
-            version,
-            source,
-            install_date,
-            size_bytes: size_bytes.map(|s| s as u64),
-            binary_path,
-            is_dependency,
-            dependencies: Vec::new(), // Will be populated below
-            dependents: Vec::new(),
-            last_used,
-            usage_count,
-        }))
-    })?;
+        "SELECT ps.scan_id, s.scan_date, ps.size_bytes FROM package_sizes ps
+         JOIN scans s ON s.id = ps.scan_id
+         WHERE ps.package_id = ?1
+         ORDER BY ps.scan_id DESC
+         LIMIT ?2"
+    )?;
+
+    let mut history = stmt.query_map(params![package_id, limit as i64], |row| {
+        Ok(SizeRecord {
+            scan_id: row.get(0)?,
+            scan_date: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            size_bytes: row.get(2)?,
+        })
+    })?
+    .collect::<Result<Vec<SizeRecord>, _>>()?;
+
+    history.reverse();
+    Ok(history)
+}
+
+/// Size change between each package's two most recent recorded sizes, keyed by (source, name).
+/// Packages with a single recorded size are left out.
+pub fn get_size_growth(conn: &Connection) -> Result<HashMap<(String, String), i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.source, p.name, g.delta FROM (
+            SELECT package_id,
+                   size_bytes - LAG(size_bytes) OVER (PARTITION BY package_id ORDER BY scan_id) AS delta,
+                   ROW_NUMBER() OVER (PARTITION BY package_id ORDER BY scan_id DESC) AS recency
+            FROM package_sizes
+         ) g
+         JOIN packages p ON p.id = g.package_id
+         WHERE g.recency = 1 AND g.delta IS NOT NULL"
+    )?;
+
+    let growth = stmt.query_map([], |row| Ok(((row.get(0)?, row.get(1)?), row.get(2)?)))?
+        .collect::<Result<HashMap<(String, String), i64>, _>>()?;
+    Ok(growth)
+}
+
+/// Drop size history older than the `keep_scans` most recent scans. Returns the rows removed.
+pub fn prune_size_history(conn: &Connection, keep_scans: usize) -> Result<usize> {
+    let removed = conn.execute(
+        "DELETE FROM package_sizes WHERE scan_id NOT IN (
+            SELECT id FROM scans ORDER BY id DESC LIMIT ?1
+         )",
+        params![keep_scans as i64],
+    )?;
+    Ok(removed)
+}
+
+/// Columns selected by the package queries, in the order `package_from_row` expects
+const PACKAGE_COLUMNS: &str = "id, name, source, version, binary_path, install_date,
+                size_bytes, is_dependency, last_used, usage_count, bundle_id, removed_at, description, usage_confidence,
+                stale_versions, stale_bytes, arch, launches_at_login, size_path";
+
+/// The order packages are loaded in; ties are broken by name
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackageOrder {
+    #[default]
+    Name,
+    /// Largest first, packages without a recorded size counting as empty
+    Size,
+    /// Most recently used first, never used last
+    LastUsed,
+    /// Newest first, unknown install dates last
+    InstallDate,
+    /// Most used first
+    UsageCount,
+}
+
+impl PackageOrder {
+    fn order_by(self) -> &'static str {
+        // RFC 3339 timestamps in UTC sort lexically
+        match self {
+            PackageOrder::Name => "name, source",
+            PackageOrder::Size => "COALESCE(size_bytes, 0) DESC, name, source",
+            PackageOrder::LastUsed => "last_used IS NULL, last_used DESC, name, source",
+            PackageOrder::InstallDate => "install_date IS NULL, install_date DESC, name, source",
+            PackageOrder::UsageCount => "usage_count DESC, name, source",
+        }
+    }
+}
+
+/// Filters, order and paging applied in SQL when loading packages; the default matches every
+/// installed package, in name order
+#[derive(Debug, Clone, Default)]
+pub struct PackageQuery {
+    /// Also return packages a scan found to be uninstalled
+    pub include_removed: bool,
+    /// Only packages from these sources
+    pub sources: Option<Vec<PackageSource>>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    /// Only packages never used or last used before this time
+    pub unused_before: Option<DateTime<Utc>>,
+    /// Only packages without any recorded use
+    pub never_used: bool,
+    /// Only packages installed before this time; those without an install date are left out
+    pub installed_before: Option<DateTime<Utc>>,
+    /// Only packages whose name matches this LIKE pattern (case-insensitive, `\` escapes);
+    /// `like_containing` builds one from plain text
+    pub name_like: Option<String>,
+    /// Only packages with this tag
+    pub tag: Option<String>,
+    pub arch: Option<Architecture>,
+    pub order: PackageOrder,
+    /// Return at most this many packages
+    pub limit: Option<usize>,
+    /// Skip this many packages first
+    pub offset: usize,
+}
+
+/// A LIKE pattern matching names that contain `text`, wildcards in it included
+pub fn like_containing(text: &str) -> String {
+    format!("%{}%", like_escape(text))
+}
+
+/// A LIKE pattern matching whole names against a shell-style `pattern`, as `glob_match` does:
+/// `*` matches any run of characters and `?` any single one
+pub fn like_glob(pattern: &str) -> String {
+    like_escape(pattern).replace('*', "%").replace('?', "_")
+}
+
+fn like_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+impl PackageQuery {
+    /// The WHERE clause and its parameters
+    fn where_clause(&self) -> (String, Vec<rusqlite::types::Value>) {
+        use rusqlite::types::Value;
+
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+
+        if !self.include_removed {
+            conditions.push("removed_at IS NULL".to_string());
+        }
+        if let Some(ref sources) = self.sources {
+            let mut placeholders = Vec::new();
+            for source in sources {
+                values.push(Value::Text(source.to_string()));
+                placeholders.push(format!("?{}", values.len()));
+            }
+            conditions.push(format!("source IN ({})", placeholders.join(", ")));
+        }
+        if let Some(min_size) = self.min_size {
+            values.push(Value::Integer(min_size as i64));
+            conditions.push(format!("size_bytes >= ?{}", values.len()));
+        }
+        if let Some(max_size) = self.max_size {
+            values.push(Value::Integer(max_size as i64));
+            conditions.push(format!("size_bytes <= ?{}", values.len()));
+        }
+        if let Some(cutoff) = self.unused_before {
+            // RFC 3339 timestamps in UTC sort lexically
+            values.push(Value::Text(cutoff.to_rfc3339()));
+            conditions.push(format!("(last_used IS NULL OR last_used < ?{})", values.len()));
+        }
+        if self.never_used {
+            conditions.push("last_used IS NULL".to_string());
+        }
+        if let Some(cutoff) = self.installed_before {
+            values.push(Value::Text(cutoff.to_rfc3339()));
+            conditions.push(format!("install_date < ?{}", values.len()));
+        }
+        if let Some(ref pattern) = self.name_like {
+            values.push(Value::Text(pattern.clone()));
+            conditions.push(format!("name LIKE ?{} ESCAPE '\\'", values.len()));
+        }
+        if let Some(ref tag) = self.tag {
+            values.push(Value::Text(tag.clone()));
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM package_tags t WHERE t.package_id = packages.id AND t.tag = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(arch) = self.arch {
+            values.push(Value::Text(arch.as_str().to_string()));
+            conditions.push(format!("arch = ?{}", values.len()));
+        }
+
+        if conditions.is_empty() {
+            (String::new(), values)
+        } else {
+            (format!("WHERE {}", conditions.join(" AND ")), values)
+        }
+    }
+
+    /// Whether the query returns only some of the installed packages (or of all recorded ones,
+    /// with `include_removed`): it's filtered or paged
+    fn narrows(&self) -> bool {
+        let everything = PackageQuery { include_removed: self.include_removed, ..Default::default() };
+        self.limit.is_some() || self.offset > 0 || self.where_clause().0 != everything.where_clause().0
+    }
+
+    /// Everything after SELECT's columns: the table, filters, order and page
+    fn selection(&self) -> (String, Vec<rusqlite::types::Value>) {
+        let (filter, values) = self.where_clause();
+        let page = match (self.limit, self.offset) {
+            (None, 0) => String::new(),
+            // SQLite needs a LIMIT before an OFFSET; -1 means none
+            (limit, offset) => format!(" LIMIT {} OFFSET {}", limit.map_or(-1, |limit| limit as i64), offset),
+        };
+        (format!("FROM packages {} ORDER BY {}{}", filter, self.order.order_by(), page), values)
+    }
+}
+
+/// Get all installed packages from the database
+pub fn get_packages(conn: &Connection) -> Result<Vec<Package>> {
+    query_packages(conn, &PackageQuery::default())
+}
+
+/// Get every package, including ones a scan found to be uninstalled
+pub fn get_packages_including_removed(conn: &Connection) -> Result<Vec<Package>> {
+    query_packages(conn, &PackageQuery { include_removed: true, ..Default::default() })
+}
 
+/// Get the packages matching `query`, in its order
+pub fn query_packages(conn: &Connection, query: &PackageQuery) -> Result<Vec<Package>> {
     let mut result = Vec::new();
-    for pkg_result in packages {
-        let (id, mut pkg) = pkg_result?;
-        pkg.dependencies = get_package_dependencies(conn, id)?;
+    for_each_package::<MacsweepError>(conn, query, |pkg| {
         result.push(pkg);
-    }
+        Ok(())
+    })?;
 
     Ok(result)
 }
 
-use std::path::PathBuf;
+/// Whether any package has been recorded at all
+pub fn has_packages(conn: &Connection, include_removed: bool) -> Result<bool> {
+    let filter = if include_removed { "" } else { "WHERE removed_at IS NULL" };
+    let found = conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM packages {})", filter),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(found)
+}
+
+/// Visit every package matching `query` in its order without loading the whole table into memory.
+/// `f` may fail with its own error type, such as the CLI's anyhow errors.
+pub fn for_each_package<E: From<MacsweepError>>(
+    conn: &Connection,
+    query: &PackageQuery,
+    mut f: impl FnMut(Package) -> Result<(), E>,
+) -> Result<(), E> {
+    let (from, values) = query.selection();
+
+    // Dependencies, binaries and usage are only loaded for the packages returned when those are
+    // a subset; otherwise reading them all is cheaper than listing the ids
+    let ids = match query.narrows() {
+        true => Some(
+            conn.prepare(&format!("SELECT id {}", from))
+                .and_then(|mut stmt| {
+                    stmt.query_map(rusqlite::params_from_iter(values.iter()), |row| row.get(0))?
+                        .collect::<rusqlite::Result<Vec<i64>>>()
+                })
+                .map_err(MacsweepError::from)?,
+        ),
+        false => None,
+    };
+    let mut dependencies = get_all_dependencies(conn, ids.as_deref())?;
+    let mut dependents = get_all_dependents(conn, ids.as_deref())?;
+    let mut binaries = get_all_binaries(conn, ids.as_deref())?;
+    let mut trends = usage_trends_of(conn, Utc::now(), ids.as_deref())?;
+
+    let mut stmt = conn.prepare(&format!("SELECT {} {}", PACKAGE_COLUMNS, from)).map_err(MacsweepError::from)?;
+    let packages = stmt
+        .query_map(rusqlite::params_from_iter(values), package_from_row)
+        .map_err(MacsweepError::from)?;
+
+    for pkg_result in packages {
+        let (id, mut pkg) = pkg_result.map_err(MacsweepError::from)?;
+        pkg.dependencies = dependencies.remove(&id).unwrap_or_default();
+        pkg.binaries = binaries.remove(&id).unwrap_or_default();
+        pkg.usage_trend = trends.remove(&id);
+        pkg.dependents = dependents
+            .remove(&(pkg.source.to_string(), pkg.name.clone()))
+            .unwrap_or_default();
+        f(pkg)?;
+    }
+
+    Ok(())
+}
+
+/// Mark installed packages of `source` that are missing from `seen` as removed.
+/// Returns how many packages were marked.
+pub fn mark_removed_packages(conn: &Connection, source: &PackageSource, seen: &HashSet<&str>) -> Result<usize> {
+    let source_str = source.to_string();
+    let mut stmt = conn.prepare("SELECT id, name FROM packages WHERE source = ?1 AND removed_at IS NULL")?;
+    let missing: Vec<i64> = stmt
+        .query_map(params![source_str], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .filter_map(|r| r.ok())
+        .filter(|(_, name)| !seen.contains(name.as_str()))
+        .map(|(id, _)| id)
+        .collect();
+
+    for id in &missing {
+        conn.execute("UPDATE packages SET removed_at = CURRENT_TIMESTAMP WHERE id = ?1", params![id])?;
+    }
 
-fn parse_package_source(s: &str) -> PackageSource {
-    match s {
-        "Homebrew" => PackageSource::Homebrew,
-        "HomebrewCask" => PackageSource::HomebrewCask,
-        "MacAppStore" => PackageSource::MacAppStore,
-        "Npm" => PackageSource::Npm,
-        "Pip" => PackageSource::Pip,
-        "Pipx" => PackageSource::Pipx,
-        "Cargo" => PackageSource::Cargo,
-        "Gem" => PackageSource::Gem,
-        "Go" => PackageSource::Go,
-        "Composer" => PackageSource::Composer,
-        "Applications" => PackageSource::Applications,
-        "LocalBin" => PackageSource::LocalBin,
-        _ => PackageSource::LocalBin, // Default fallback
+    Ok(missing.len())
+}
+
+/// Record a package reinstalled by `undo` with the version that was installed, so the next
+/// `list` shows it even before a rescan. `source` is as stored (`Homebrew`, `Npm`, ...).
+pub fn record_reinstalled_package(conn: &Connection, name: &str, source: &str, version: Option<&str>) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE packages SET version = COALESCE(?3, version), removed_at = NULL, last_seen = CURRENT_TIMESTAMP
+         WHERE name = ?1 AND source = ?2",
+        params![name, source, version],
+    )?;
+    if updated == 0 {
+        conn.execute(
+            "INSERT INTO packages (name, source, version) VALUES (?1, ?2, ?3)",
+            params![name, source, version],
+        )?;
     }
+    Ok(())
+}
+
+/// Sources that have installed packages
+pub fn get_installed_sources(conn: &Connection) -> Result<Vec<PackageSource>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT source FROM packages WHERE removed_at IS NULL ORDER BY source")?;
+    let sources = stmt.query_map([], |row| row.get::<_, String>(0))?
+        .map(|source| Ok(source?.parse().unwrap_or(PackageSource::LocalBin)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(sources)
+}
+
+/// Parse a `CURRENT_TIMESTAMP` value (`YYYY-MM-DD HH:MM:SS`, UTC)
+fn parse_sqlite_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|dt| dt.and_utc())
+}
+
+/// Build a package (and its row id) from a row selected with `PACKAGE_COLUMNS`
+fn package_from_row(row: &rusqlite::Row) -> rusqlite::Result<(i64, Package)> {
+    let id: i64 = row.get(0)?;
+    let name: String = row.get(1)?;
+    let source_str: String = row.get(2)?;
+    // Sources no longer known to this version read as LocalBin
+    let source = source_str.parse().unwrap_or(PackageSource::LocalBin);
+
+    let version: Option<String> = row.get(3)?;
+    let binary_path_str: Option<String> = row.get(4)?;
+    let binary_path = binary_path_str.map(PathBuf::from);
+
+    let install_date_str: Option<String> = row.get(5)?;
+    let install_date = install_date_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+
+    let size_bytes: Option<i64> = row.get(6)?;
+    let is_dependency: bool = row.get(7)?;
+
+    let last_used_str: Option<String> = row.get(8)?;
+    let last_used = last_used_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|dt| dt.with_timezone(&Utc));
+
+    let usage_count: u32 = row.get(9).unwrap_or(0);
+    let bundle_id: Option<String> = row.get(10)?;
+    let removed_at: Option<String> = row.get(11)?;
+    let removed_at = removed_at.and_then(|s| parse_sqlite_timestamp(&s));
+    let description: Option<String> = row.get(12)?;
+    let usage_confidence: Option<String> = row.get(13)?;
+    let stale_versions: Option<String> = row.get(14)?;
+    let stale_bytes: Option<i64> = row.get(15)?;
+    let arch: Option<String> = row.get(16)?;
+    let launches_at_login: bool = row.get(17)?;
+    let size_path: Option<String> = row.get(18)?;
+
+    Ok((id, Package {
+        name,
+        version,
+        description,
+        source,
+        install_date,
+        size_bytes: size_bytes.map(|s| s as u64),
+        size_path: size_path.map(PathBuf::from),
+        binary_path,
+        binaries: Vec::new(), // Populated by the caller
+        bundle_id,
+        is_dependency,
+        dependencies: Vec::new(), // Populated by the caller
+        dependents: Vec::new(),
+        last_used,
+        usage_count,
+        usage_confidence: usage_confidence.as_deref().map(UsageConfidence::parse).unwrap_or_default(),
+        removed_at,
+        stale_versions: stale_versions
+            .map(|s| s.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+        stale_bytes: stale_bytes.map(|s| s as u64),
+        arch: arch.as_deref().and_then(Architecture::parse),
+        usage_trend: None, // Populated by the caller
+        launches_at_login,
+    }))
 }
 
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
 /// Get dependencies for a package
 fn get_package_dependencies(conn: &Connection, package_id: i64) -> Result<Vec<String>> {
     let mut stmt = conn.prepare(
-        "SELECT dependency_name FROM package_dependencies WHERE package_id = ?1"
+        "SELECT dependency_name FROM package_dependencies WHERE package_id = ?1 ORDER BY id"
     )?;
 
     let deps = stmt.query_map(params![package_id], |row| row.get(0))?
@@ -158,17 +556,284 @@ fn get_package_dependencies(conn: &Connection, package_id: i64) -> Result<Vec<St
     Ok(deps)
 }
 
+/// Dependencies of every package, keyed by package id
+fn get_all_dependencies(conn: &Connection, ids: Option<&[i64]>) -> Result<HashMap<i64, Vec<String>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT package_id, dependency_name FROM package_dependencies WHERE {} ORDER BY id",
+        id_in("package_id", ids)
+    ))?;
+
+    let mut dependencies: HashMap<i64, Vec<String>> = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+    for row in rows {
+        let (package_id, dependency) = row?;
+        dependencies.entry(package_id).or_default().push(dependency);
+    }
+
+    Ok(dependencies)
+}
+
+/// A condition limiting `column` to `ids`, or always true for None. Ids come from the
+/// database, so they're written into the SQL rather than bound one by one.
+fn id_in(column: &str, ids: Option<&[i64]>) -> String {
+    match ids {
+        Some(ids) => {
+            let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+            format!("{} IN ({})", column, ids.join(", "))
+        }
+        None => "1".to_string(),
+    }
+}
+
+/// Executables installed by a package, in name order
+fn get_package_binaries(conn: &Connection, package_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT binary_name FROM package_binaries WHERE package_id = ?1 ORDER BY binary_name"
+    )?;
+
+    let binaries = stmt.query_map(params![package_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(binaries)
+}
+
+/// Executables of every package, keyed by package id
+fn get_all_binaries(conn: &Connection, ids: Option<&[i64]>) -> Result<HashMap<i64, Vec<String>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT package_id, binary_name FROM package_binaries WHERE {} ORDER BY binary_name",
+        id_in("package_id", ids)
+    ))?;
+
+    let mut binaries: HashMap<i64, Vec<String>> = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+    for row in rows {
+        let (package_id, binary) = row?;
+        binaries.entry(package_id).or_default().push(binary);
+    }
+
+    Ok(binaries)
+}
+
+/// Installed packages of the same source that depend on `name`, in name order
+pub fn get_package_dependents(conn: &Connection, name: &str, source: &PackageSource) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.name FROM package_dependencies d
+         JOIN packages p ON p.id = d.package_id
+         WHERE d.dependency_name = ?1 AND p.source = ?2 AND p.removed_at IS NULL
+         ORDER BY p.name"
+    )?;
+
+    let dependents = stmt.query_map(params![name, source.to_string()], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(dependents)
+}
+
+/// Reverse dependency edges for every package, keyed by (source, dependency name).
+/// Edges only connect packages of the same source, so an npm package never
+/// counts as a dependent of a Homebrew formula that happens to share its name.
+fn get_all_dependents(conn: &Connection, ids: Option<&[i64]>) -> Result<HashMap<(String, String), Vec<String>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT p.source, d.dependency_name, p.name FROM package_dependencies d
+         JOIN packages p ON p.id = d.package_id
+         WHERE p.removed_at IS NULL AND EXISTS (
+             SELECT 1 FROM packages q WHERE q.name = d.dependency_name AND q.source = p.source AND {}
+         )
+         ORDER BY p.name",
+        id_in("q.id", ids)
+    ))?;
+
+    let mut dependents: HashMap<(String, String), Vec<String>> = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+    for row in rows {
+        let (source, dependency, dependent) = row?;
+        dependents.entry((source, dependency)).or_default().push(dependent);
+    }
+
+    Ok(dependents)
+}
+
 /// Update package usage information
 pub fn update_package_usage(
     conn: &Connection,
     package_id: i64,
-    last_used: DateTime<Utc>,
+    last_used: Option<DateTime<Utc>>,
     usage_count: u32,
+    confidence: UsageConfidence,
 ) -> Result<()> {
     conn.execute(
-        "UPDATE packages SET last_used = ?1, usage_count = ?2 WHERE id = ?3",
-        params![last_used.to_rfc3339(), usage_count, package_id],
+        "UPDATE packages SET last_used = ?1, usage_count = ?2, usage_confidence = ?3 WHERE id = ?4",
+        params![last_used.map(|dt| dt.to_rfc3339()), usage_count, confidence.as_str(), package_id],
+    )?;
+    Ok(())
+}
+
+/// Last use, usage count, and the confidence in that last use
+pub type UsageTotals = (Option<DateTime<Utc>>, u32, UsageConfidence);
+
+/// Stored usage of every package, keyed by (name, source)
+pub fn get_usage_totals(conn: &Connection) -> Result<HashMap<(String, String), UsageTotals>> {
+    let mut stmt = conn.prepare("SELECT name, source, last_used, usage_count, usage_confidence FROM packages")?;
+    let totals = stmt
+        .query_map([], |row| {
+            let last_used: Option<String> = row.get(2)?;
+            let last_used = last_used
+                .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                .map(|dt| dt.with_timezone(&Utc));
+            let confidence = row.get::<_, Option<String>>(4)?.as_deref().map(UsageConfidence::parse).unwrap_or_default();
+            Ok(((row.get(0)?, row.get(1)?), (last_used, row.get::<_, Option<u32>>(3)?.unwrap_or(0), confidence)))
+        })?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(totals)
+}
+
+/// Give packages from a scan that skipped usage tracking their stored usage, so saving them
+/// refreshes versions and sizes without discarding what earlier scans found
+pub fn carry_over_usage(conn: &Connection, packages: &mut [Package]) -> Result<()> {
+    let stored = get_usage_totals(conn)?;
+    let mut stmt = conn.prepare("SELECT name, source FROM packages WHERE launches_at_login")?;
+    let at_login: HashSet<(String, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect::<rusqlite::Result<_>>()?;
+
+    for package in packages {
+        let key = (package.name.clone(), package.source.to_string());
+        if let Some((last_used, count, confidence)) = stored.get(&key) {
+            package.last_used = *last_used;
+            package.usage_count = *count;
+            package.usage_confidence = *confidence;
+        }
+        package.launches_at_login = at_login.contains(&key);
+    }
+    Ok(())
+}
+
+/// Give packages from a scan that didn't measure sizes (`scan --no-size`) their stored sizes,
+/// so saving them doesn't replace known sizes with nothing
+pub fn carry_over_sizes(conn: &Connection, packages: &mut [Package]) -> Result<()> {
+    let mut stmt = conn.prepare("SELECT name, source, size_bytes, stale_bytes FROM packages")?;
+    let stored: HashMap<(String, String), (Option<i64>, Option<i64>)> = stmt
+        .query_map([], |row| Ok(((row.get(0)?, row.get(1)?), (row.get(2)?, row.get(3)?))))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    for package in packages {
+        if let Some((size_bytes, stale_bytes)) = stored.get(&(package.name.clone(), package.source.to_string())) {
+            package.size_bytes = package.size_bytes.or(size_bytes.map(|s| s as u64));
+            package.stale_bytes = package.stale_bytes.or(stale_bytes.map(|s| s as u64));
+        }
+    }
+    Ok(())
+}
+
+/// Where each shell history file was read up to by previous scans
+pub fn get_history_files(conn: &Connection) -> Result<Vec<HistoryFileState>> {
+    let mut stmt = conn.prepare("SELECT path, inode, byte_offset, last_entry_at FROM history_files")?;
+    let files = stmt
+        .query_map([], |row| {
+            let last_entry_at: Option<String> = row.get(3)?;
+            Ok(HistoryFileState {
+                path: PathBuf::from(row.get::<_, String>(0)?),
+                inode: row.get::<_, i64>(1)? as u64,
+                offset: row.get::<_, i64>(2)? as u64,
+                last_entry_at: last_entry_at
+                    .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+                    .map(|dt| dt.with_timezone(&Utc)),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(files)
+}
+
+/// Remember how far each history file was read
+pub fn save_history_files(conn: &Connection, files: &[HistoryFileState]) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO history_files (path, inode, byte_offset, last_entry_at, parsed_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+         ON CONFLICT(path) DO UPDATE SET
+            inode = excluded.inode,
+            byte_offset = excluded.byte_offset,
+            last_entry_at = excluded.last_entry_at,
+            parsed_at = excluded.parsed_at",
+    )?;
+
+    for file in files {
+        stmt.execute(params![
+            file.path.to_string_lossy(),
+            file.inode as i64,
+            file.offset as i64,
+            file.last_entry_at.map(|dt| dt.to_rfc3339()),
+        ])?;
+    }
+    Ok(())
+}
+
+/// Directory sizes measured by earlier scans
+pub fn get_directory_sizes(conn: &Connection) -> Result<HashMap<PathBuf, CachedSize>> {
+    let mut stmt = conn.prepare("SELECT path, size_bytes, modified_ns, measured_at FROM directory_sizes")?;
+    let sizes = stmt
+        .query_map([], |row| {
+            let measured_at: String = row.get(3)?;
+            Ok((
+                PathBuf::from(row.get::<_, String>(0)?),
+                CachedSize {
+                    size_bytes: row.get::<_, i64>(1)? as u64,
+                    modified_ns: row.get(2)?,
+                    measured_at: DateTime::parse_from_rfc3339(&measured_at)
+                        .map(|dt| dt.with_timezone(&Utc))
+                        .unwrap_or_default(),
+                },
+            ))
+        })?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(sizes)
+}
+
+/// Remember newly measured directory sizes, forgetting directories that no longer exist
+pub fn save_directory_sizes(conn: &Connection, sizes: &HashMap<PathBuf, CachedSize>) -> Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR REPLACE INTO directory_sizes (path, size_bytes, modified_ns, measured_at) VALUES (?1, ?2, ?3, ?4)",
+    )?;
+    for (path, size) in sizes {
+        stmt.execute(params![path.to_string_lossy(), size.size_bytes as i64, size.modified_ns, size.measured_at.to_rfc3339()])?;
+    }
+
+    let paths: Vec<String> = conn
+        .prepare("SELECT path FROM directory_sizes")?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut delete = conn.prepare_cached("DELETE FROM directory_sizes WHERE path = ?1")?;
+    for path in paths.iter().filter(|path| !std::path::Path::new(path).exists()) {
+        delete.execute(params![path])?;
+    }
+    Ok(())
+}
+
+/// The change signals each scanner had when it last scanned successfully, by scanner name
+pub fn get_source_signals(conn: &Connection) -> Result<HashMap<String, ChangeSignals>> {
+    let mut stmt = conn.prepare("SELECT scanner, path, modified_ns FROM source_signals")?;
+    let mut signals: HashMap<String, ChangeSignals> = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?)))?;
+    for row in rows {
+        let (scanner, path, modified_ns) = row?;
+        signals.entry(scanner).or_default().insert(PathBuf::from(path), modified_ns);
+    }
+    Ok(signals)
+}
+
+/// Replace the change signals recorded for `scanner`
+pub fn save_source_signals(conn: &Connection, scanner: &str, signals: &ChangeSignals) -> Result<()> {
+    conn.execute("DELETE FROM source_signals WHERE scanner = ?1", params![scanner])?;
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO source_signals (scanner, path, modified_ns, recorded_at) VALUES (?1, ?2, ?3, ?4)",
     )?;
+    let now = Utc::now().to_rfc3339();
+    for (path, modified_ns) in signals {
+        stmt.execute(params![scanner, path.to_string_lossy(), modified_ns, now])?;
+    }
+    Ok(())
+}
+
+/// Replace a package's recorded size, e.g. after measuring it again
+pub fn update_package_size(conn: &Connection, package_id: i64, size_bytes: u64) -> Result<()> {
+    conn.execute("UPDATE packages SET size_bytes = ?2 WHERE id = ?1", params![package_id, size_bytes as i64])?;
     Ok(())
 }
 
@@ -189,123 +854,1514 @@ pub fn insert_usage_event(
     Ok(())
 }
 
-/// Record a scan
-pub fn insert_scan(
-    conn: &Connection,
-    scan_type: &str,
-    packages_found: i64,
-    duration_ms: i64,
-) -> Result<i64> {
-    conn.execute(
-        "INSERT INTO scans (scan_type, packages_found, duration_ms)
-         VALUES (?1, ?2, ?3)",
-        params![scan_type, packages_found, duration_ms],
-    )?;
-
-    Ok(conn.last_insert_rowid())
+/// A stored usage observation, as shown by `history`
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageEvent {
+    pub event_type: String,
+    pub event_date: DateTime<Utc>,
+    pub details: Option<String>,
 }
 
-/// Record a cleanup operation
-pub fn insert_cleanup(
-    conn: &Connection,
-    backup_manifest_path: &str,
-    packages_removed: i64,
-    space_recovered: i64,
-) -> Result<i64> {
-    conn.execute(
-        "INSERT INTO cleanups (backup_manifest_path, packages_removed, space_recovered)
-         VALUES (?1, ?2, ?3)",
-        params![backup_manifest_path, packages_removed, space_recovered],
+/// Every usage event of a package, newest first
+pub fn get_usage_events(conn: &Connection, package_id: i64) -> Result<Vec<UsageEvent>> {
+    let mut stmt = conn.prepare(
+        "SELECT event_type, event_date, details FROM usage_events
+         WHERE package_id = ?1
+         ORDER BY event_date DESC",
     )?;
 
-    Ok(conn.last_insert_rowid())
-}
+    let events = stmt
+        .query_map(params![package_id], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+        })?
+        .filter_map(|row| row.ok())
+        .filter_map(|(event_type, date, details)| {
+            let event_date = DateTime::parse_from_rfc3339(&date).ok()?.with_timezone(&Utc);
+            Some(UsageEvent { event_type, event_date, details })
+        })
+        .collect();
 
-/// Get package by name and source
-pub fn get_package_by_name(conn: &Connection, name: &str, source: &PackageSource) -> Result<Option<Package>> {
-    let source_str = format!("{:?}", source);
+    Ok(events)
+}
 
+/// The newest event of `event_type` for every package that has one, keyed by (name, source)
+pub fn get_latest_usage_events(conn: &Connection, event_type: &str) -> Result<HashMap<(String, String), DateTime<Utc>>> {
     let mut stmt = conn.prepare(
-        "SELECT id, name, source, version, binary_path, install_date,
-                size_bytes, is_dependency, last_used, usage_count
-         FROM packages
-         WHERE name = ?1 AND source = ?2"
-    )?;
-
-    let result = stmt.query_row(params![name, source_str], |row| {
-        let id: i64 = row.get(0)?;
-        let name: String = row.get(1)?;
-        let source_str: String = row.get(2)?;
-        let source = parse_package_source(&source_str);
-
-        let version: Option<String> = row.get(3)?;
-        let binary_path_str: Option<String> = row.get(4)?;
-        let binary_path = binary_path_str.map(PathBuf::from);
-
-        let install_date_str: Option<String> = row.get(5)?;
-        let install_date = install_date_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        let size_bytes: Option<i64> = row.get(6)?;
-        let is_dependency: bool = row.get(7)?;
-
-        let last_used_str: Option<String> = row.get(8)?;
-        let last_used = last_used_str.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
-            .map(|dt| dt.with_timezone(&Utc));
-
-        let usage_count: u32 = row.get(9).unwrap_or(0);
-
-        Ok((id, Package {
-            name,
-            version,
-            source,
-            install_date,
-            size_bytes: size_bytes.map(|s| s as u64),
-            binary_path,
-            is_dependency,
-            dependencies: Vec::new(),
-            dependents: Vec::new(),
-            last_used,
-            usage_count,
-        }))
-    });
+        "SELECT p.name, p.source, e.event_date FROM usage_events e
+         JOIN packages p ON p.id = e.package_id
+         WHERE e.event_type = ?1",
+    )?;
 
-    match result {
-        Ok((id, mut pkg)) => {
-            pkg.dependencies = get_package_dependencies(conn, id)?;
-            Ok(Some(pkg))
+    let mut latest: HashMap<(String, String), DateTime<Utc>> = HashMap::new();
+    let rows = stmt.query_map(params![event_type], |row| {
+        Ok(((row.get::<_, String>(0)?, row.get::<_, String>(1)?), row.get::<_, String>(2)?))
+    })?;
+    for row in rows {
+        let (key, date) = row?;
+        if let Ok(date) = DateTime::parse_from_rfc3339(&date) {
+            let date = date.with_timezone(&Utc);
+            latest.entry(key).and_modify(|d| *d = (*d).max(date)).or_insert(date);
         }
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(e.into()),
     }
+
+    Ok(latest)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::NamedTempFile;
-    use crate::storage::Database;
+/// SQL list of the event types that count as uses, for `IN (...)`
+fn use_event_types_sql() -> String {
+    USE_EVENT_TYPES.iter().map(|t| format!("'{}'", t)).collect::<Vec<_>>().join(", ")
+}
 
-    #[test]
-    fn test_upsert_package() {
-        let temp_file = NamedTempFile::new().unwrap();
-        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
-        db.init().unwrap();
+/// Days with a recorded use in the 30, 90 and 365 days before `now`, for every package used
+/// in the past year, keyed by package id
+pub fn get_usage_trends(conn: &Connection, now: DateTime<Utc>) -> Result<HashMap<i64, UsageTrend>> {
+    usage_trends_of(conn, now, None)
+}
 
-        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
-        package.version = Some("1.0.0".to_string());
+/// `get_usage_trends` for the packages `ids` (all of them for None)
+fn usage_trends_of(conn: &Connection, now: DateTime<Utc>, ids: Option<&[i64]>) -> Result<HashMap<i64, UsageTrend>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT package_id,
+                COUNT(DISTINCT CASE WHEN event_date >= ?1 THEN substr(event_date, 1, 10) END),
+                COUNT(DISTINCT CASE WHEN event_date >= ?2 THEN substr(event_date, 1, 10) END),
+                COUNT(DISTINCT substr(event_date, 1, 10))
+         FROM usage_events
+         WHERE event_type IN ({}) AND event_date >= ?3 AND event_date <= ?4 AND {}
+         GROUP BY package_id",
+        use_event_types_sql(),
+        id_in("package_id", ids)
+    ))?;
 
-        let id = upsert_package(db.conn(), &package).unwrap();
-        assert!(id > 0);
+    let since = |days: i64| (now - chrono::Duration::days(days)).to_rfc3339();
+    let trends = stmt
+        .query_map(params![since(30), since(90), since(365), now.to_rfc3339()], |row| {
+            Ok((row.get(0)?, UsageTrend { last_30_days: row.get(1)?, last_90_days: row.get(2)?, last_365_days: row.get(3)? }))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(trends)
+}
 
-        // Update the package
-        package.version = Some("2.0.0".to_string());
-        let id2 = upsert_package(db.conn(), &package).unwrap();
-        assert_eq!(id, id2); // Should be the same ID
+/// Days with a recorded use in each calendar month since `since`, oldest first; months
+/// without uses are left out
+pub fn get_monthly_usage(conn: &Connection, package_id: i64, since: DateTime<Utc>) -> Result<Vec<MonthlyUsage>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT substr(event_date, 1, 7) AS month, COUNT(DISTINCT substr(event_date, 1, 10))
+         FROM usage_events
+         WHERE package_id = ?1 AND event_type IN ({}) AND event_date >= ?2
+         GROUP BY month
+         ORDER BY month",
+        use_event_types_sql()
+    ))?;
 
-        // Verify version was updated
-        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap();
+    let months = stmt
+        .query_map(params![package_id, since.to_rfc3339()], |row| Ok(MonthlyUsage { month: row.get(0)?, uses: row.get(1)? }))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(months)
+}
+
+/// Replace the usage sources recorded for a package with what this scan consulted
+pub fn save_usage_checks(conn: &Connection, package_id: i64, checks: &[UsageCheck]) -> Result<()> {
+    conn.execute("DELETE FROM usage_checks WHERE package_id = ?1", params![package_id])?;
+    let checked_at = Utc::now().to_rfc3339();
+    for check in checks {
+        conn.execute(
+            "INSERT OR REPLACE INTO usage_checks (package_id, source, found, result, checked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![package_id, check.source, check.found, check.result, checked_at],
+        )?;
+    }
+    Ok(())
+}
+
+/// Usage sources the latest scan consulted for a package, in the order it checked them,
+/// with when that scan ran
+pub fn get_usage_checks(conn: &Connection, package_id: i64) -> Result<(Vec<UsageCheck>, Option<DateTime<Utc>>)> {
+    let mut stmt = conn.prepare(
+        "SELECT source, found, result, checked_at FROM usage_checks
+         WHERE package_id = ?1
+         ORDER BY rowid",
+    )?;
+
+    let mut checks = Vec::new();
+    let mut checked_at = None;
+    let rows = stmt.query_map(params![package_id], |row| {
+        Ok((UsageCheck { source: row.get(0)?, found: row.get(1)?, result: row.get(2)? }, row.get::<_, String>(3)?))
+    })?;
+    for row in rows {
+        let (check, date) = row?;
+        checked_at = DateTime::parse_from_rfc3339(&date).ok().map(|dt| dt.with_timezone(&Utc));
+        checks.push(check);
+    }
+
+    Ok((checks, checked_at))
+}
+
+/// Store a manual mark as a usage event; marking again with the same date replaces the
+/// earlier mark so it counts as the latest
+pub fn record_manual_mark(conn: &Connection, package_id: i64, mark: &ManualMark) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO usage_events (package_id, event_type, event_date, details)
+         VALUES (?1, 'manual', ?2, ?3)",
+        params![package_id, mark.date.to_rfc3339(), mark.details()],
+    )?;
+    Ok(())
+}
+
+fn manual_mark_from_row(row: &rusqlite::Row, offset: usize) -> rusqlite::Result<Option<ManualMark>> {
+    let date: String = row.get(offset)?;
+    let details: Option<String> = row.get(offset + 1)?;
+    Ok(DateTime::parse_from_rfc3339(&date)
+        .ok()
+        .map(|dt| ManualMark::from_details(details.as_deref().unwrap_or_default(), dt.with_timezone(&Utc))))
+}
+
+/// The latest manual mark of a package, if it has one
+pub fn get_manual_mark(conn: &Connection, package_id: i64) -> Result<Option<ManualMark>> {
+    let mark = conn.query_row(
+        "SELECT event_date, details FROM usage_events
+         WHERE package_id = ?1 AND event_type = 'manual'
+         ORDER BY id DESC LIMIT 1",
+        params![package_id],
+        |row| manual_mark_from_row(row, 0),
+    );
+
+    match mark {
+        Ok(mark) => Ok(mark),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The latest manual mark of every marked package, keyed by (name, source)
+pub fn get_manual_marks(conn: &Connection) -> Result<HashMap<(String, String), ManualMark>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.name, p.source, e.event_date, e.details FROM usage_events e
+         JOIN packages p ON p.id = e.package_id
+         WHERE e.event_type = 'manual'
+         ORDER BY e.id",
+    )?;
+
+    let mut marks = HashMap::new();
+    let rows = stmt.query_map([], |row| Ok(((row.get(0)?, row.get(1)?), manual_mark_from_row(row, 2)?)))?;
+    for row in rows {
+        if let (key, Some(mark)) = row? {
+            marks.insert(key, mark);
+        }
+    }
+
+    Ok(marks)
+}
+
+/// Record a scan, with aggregates used for trend statistics
+pub fn insert_scan(
+    conn: &Connection,
+    scan_type: &str,
+    packages_found: i64,
+    duration_ms: i64,
+    total_size_bytes: i64,
+    source_counts: &BTreeMap<String, i64>,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO scans (scan_type, packages_found, duration_ms, total_size_bytes, source_counts)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            scan_type,
+            packages_found,
+            duration_ms,
+            total_size_bytes,
+            serde_json::to_string(source_counts)?,
+        ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// A recorded scan
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanRecord {
+    pub id: i64,
+    pub scan_date: String,
+    pub scan_type: String,
+    pub packages_found: i64,
+    pub duration_ms: i64,
+    /// Not recorded for scans made before trend statistics existed
+    pub total_size_bytes: Option<i64>,
+    pub source_counts: BTreeMap<String, i64>,
+    /// Space the recommendations after the scan would free, and how many packages they name;
+    /// only recorded by the CLI's scans
+    pub reclaimable: Option<Reclaimable>,
+}
+
+/// Space recommendations would free
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Reclaimable {
+    pub bytes: i64,
+    pub packages: i64,
+}
+
+impl ScanRecord {
+    /// When the scan ran, if the stored date is readable
+    pub fn scanned_at(&self) -> Option<DateTime<Utc>> {
+        parse_sqlite_timestamp(&self.scan_date)
+    }
+}
+
+const SCAN_COLUMNS: &str =
+    "id, scan_date, scan_type, packages_found, duration_ms, total_size_bytes, source_counts, reclaimable_bytes, reclaimable_packages";
+
+fn scan_from_row(row: &rusqlite::Row) -> rusqlite::Result<ScanRecord> {
+    let source_counts: Option<String> = row.get(6)?;
+    Ok(ScanRecord {
+        id: row.get(0)?,
+        scan_date: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+        scan_type: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        packages_found: row.get::<_, Option<i64>>(3)?.unwrap_or(0),
+        duration_ms: row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+        total_size_bytes: row.get(5)?,
+        source_counts: source_counts
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+        reclaimable: match (row.get(7)?, row.get(8)?) {
+            (Some(bytes), Some(packages)) => Some(Reclaimable { bytes, packages }),
+            _ => None,
+        },
+    })
+}
+
+/// Record what the recommendations after scan `scan_id` would free
+pub fn set_scan_reclaimable(conn: &Connection, scan_id: i64, reclaimable: Reclaimable) -> Result<()> {
+    conn.execute(
+        "UPDATE scans SET reclaimable_bytes = ?2, reclaimable_packages = ?3 WHERE id = ?1",
+        params![scan_id, reclaimable.bytes, reclaimable.packages],
+    )?;
+    Ok(())
+}
+
+/// The reclaimable space recorded for the latest scan before `scan_id` that has one
+pub fn get_previous_reclaimable(conn: &Connection, scan_id: i64) -> Result<Option<Reclaimable>> {
+    let row = conn.query_row(
+        "SELECT reclaimable_bytes, reclaimable_packages FROM scans
+         WHERE id < ?1 AND reclaimable_bytes IS NOT NULL ORDER BY id DESC LIMIT 1",
+        params![scan_id],
+        |row| Ok(Reclaimable { bytes: row.get(0)?, packages: row.get(1)? }),
+    );
+    match row {
+        Ok(reclaimable) => Ok(Some(reclaimable)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Get the most recent scans of a given type, oldest first
+pub fn get_recent_scans(conn: &Connection, scan_type: &str, limit: usize) -> Result<Vec<ScanRecord>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM scans WHERE scan_type = ?1 ORDER BY id DESC LIMIT ?2",
+        SCAN_COLUMNS
+    ))?;
+
+    let mut scans = stmt.query_map(params![scan_type, limit as i64], scan_from_row)?
+        .collect::<Result<Vec<ScanRecord>, _>>()?;
+
+    scans.reverse();
+    Ok(scans)
+}
+
+/// Get the latest scan, of `scan_type` if given or of any type otherwise
+pub fn get_last_scan(conn: &Connection, scan_type: Option<&str>) -> Result<Option<ScanRecord>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM scans WHERE ?1 IS NULL OR scan_type = ?1 ORDER BY id DESC LIMIT 1",
+        SCAN_COLUMNS
+    ))?;
+
+    match stmt.query_row(params![scan_type], scan_from_row) {
+        Ok(scan) => Ok(Some(scan)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Cumulative totals across all recorded cleanups
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CleanupTotals {
+    pub cleanups: i64,
+    pub packages_removed: i64,
+    pub space_recovered: i64,
+}
+
+/// Sum up every recorded cleanup
+pub fn get_cleanup_totals(conn: &Connection) -> Result<CleanupTotals> {
+    let totals = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(packages_removed), 0), COALESCE(SUM(space_recovered), 0)
+         FROM cleanups",
+        [],
+        |row| Ok(CleanupTotals {
+            cleanups: row.get(0)?,
+            packages_removed: row.get(1)?,
+            space_recovered: row.get(2)?,
+        }),
+    )?;
+
+    Ok(totals)
+}
+
+/// Record a cleanup operation
+pub fn insert_cleanup(
+    conn: &Connection,
+    backup_manifest_path: &str,
+    packages_removed: i64,
+    space_recovered: i64,
+) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO cleanups (backup_manifest_path, packages_removed, space_recovered)
+         VALUES (?1, ?2, ?3)",
+        params![backup_manifest_path, packages_removed, space_recovered],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// One removal of a cleanup
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanupItem {
+    pub package: String,
+    pub source: String,
+    pub size_bytes: Option<u64>,
+    /// "pending" (not attempted yet), "removed", "cleaned" (stale versions removed, the
+    /// package stays), "failed", or "unverified" (the uninstaller succeeded but the package
+    /// is still on disk)
+    pub status: String,
+    pub error_message: Option<String>,
+    /// Exit code of the uninstaller, when one ran to completion
+    pub exit_code: Option<i32>,
+    /// How long the uninstaller took
+    pub duration_ms: Option<u64>,
+    /// "remove" to uninstall the package, "brew-cleanup" to remove only its stale versions
+    pub action: String,
+}
+
+impl CleanupItem {
+    /// Whether the item is about `package`; `source` may still be a variant name (`Npm`)
+    /// in items recorded before sources had stable names
+    pub fn is_for(&self, package: &Package) -> bool {
+        self.package == package.name && self.source.parse::<PackageSource>().is_ok_and(|s| s == package.source)
+    }
+
+    pub fn failed(&self) -> bool {
+        self.status == "failed"
+    }
+
+    pub fn removed(&self) -> bool {
+        self.status == "removed"
+    }
+
+    pub fn unverified(&self) -> bool {
+        self.status == "unverified"
+    }
+
+    pub fn pending(&self) -> bool {
+        self.status == "pending"
+    }
+}
+
+/// Record a cleanup together with the outcome of every removal it attempted
+pub fn record_cleanup(
+    conn: &Connection,
+    backup_manifest_path: &str,
+    items: &[CleanupItem],
+) -> Result<i64> {
+    let cleanup_id = start_cleanup(conn, backup_manifest_path, items)?;
+    finish_cleanup(conn, cleanup_id, "complete")?;
+    Ok(cleanup_id)
+}
+
+/// Record a cleanup before it runs, with its planned removals as "pending" items, so one cut
+/// short by Ctrl-C or a crash can be resumed
+pub fn start_cleanup(conn: &Connection, backup_manifest_path: &str, items: &[CleanupItem]) -> Result<i64> {
+    let tx = conn.unchecked_transaction()?;
+    let cleanup_id = insert_cleanup(&tx, backup_manifest_path, 0, 0)?;
+    tx.execute("UPDATE cleanups SET status = 'in_progress' WHERE id = ?1", params![cleanup_id])?;
+    add_cleanup_items(&tx, cleanup_id, items)?;
+    tx.commit()?;
+    Ok(cleanup_id)
+}
+
+/// Add items to a recorded cleanup; removed packages are marked removed right away
+pub fn add_cleanup_items(conn: &Connection, cleanup_id: i64, items: &[CleanupItem]) -> Result<()> {
+    for item in items {
+        conn.execute(
+            "INSERT INTO cleanup_items (cleanup_id, package_name, source, size_bytes, status, error_message, exit_code, duration_ms, action)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                cleanup_id,
+                item.package,
+                item.source,
+                item.size_bytes.map(|s| s as i64),
+                item.status,
+                item.error_message,
+                item.exit_code,
+                item.duration_ms.map(|d| d as i64),
+                item.action,
+            ],
+        )?;
+        if item.removed() {
+            mark_package_removed(conn, &item.package, &item.source)?;
+        }
+    }
+    Ok(())
+}
+
+/// Record the outcome of one of a cleanup's planned removals, marking the package removed
+/// as soon as it is gone
+pub fn update_cleanup_item(conn: &Connection, cleanup_id: i64, item: &CleanupItem) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let updated = tx.execute(
+        "UPDATE cleanup_items SET status = ?4, error_message = ?5, size_bytes = COALESCE(?6, size_bytes),
+            exit_code = ?7, duration_ms = ?8
+         WHERE cleanup_id = ?1 AND package_name = ?2 AND source = ?3",
+        params![
+            cleanup_id,
+            item.package,
+            item.source,
+            item.status,
+            item.error_message,
+            item.size_bytes.map(|s| s as i64),
+            item.exit_code,
+            item.duration_ms.map(|d| d as i64),
+        ],
+    )?;
+    if updated == 0 {
+        add_cleanup_items(&tx, cleanup_id, std::slice::from_ref(item))?;
+    } else if item.removed() {
+        mark_package_removed(&tx, &item.package, &item.source)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Set a cleanup's status ("complete" or "interrupted") and total up its removals
+pub fn finish_cleanup(conn: &Connection, cleanup_id: i64, status: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE cleanups SET status = ?2,
+            packages_removed = (SELECT COUNT(*) FROM cleanup_items WHERE cleanup_id = ?1 AND status = 'removed'),
+            space_recovered = (SELECT COALESCE(SUM(size_bytes), 0) FROM cleanup_items
+                               WHERE cleanup_id = ?1 AND status IN ('removed', 'cleaned'))
+         WHERE id = ?1",
+        params![cleanup_id, status],
+    )?;
+    Ok(())
+}
+
+/// A cleanup that stopped before attempting every removal it planned
+#[derive(Debug, Clone)]
+pub struct IncompleteCleanup {
+    pub id: i64,
+    pub backup_manifest_path: String,
+    pub pending: Vec<CleanupItem>,
+}
+
+/// The most recent cleanup that was interrupted (or never finished) with removals left to do
+pub fn get_incomplete_cleanup(conn: &Connection) -> Result<Option<IncompleteCleanup>> {
+    let cleanup = conn.query_row(
+        "SELECT id, backup_manifest_path FROM cleanups
+         WHERE status != 'complete' ORDER BY id DESC LIMIT 1",
+        [],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+    );
+
+    let (id, backup_manifest_path) = match cleanup {
+        Ok(cleanup) => cleanup,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let pending: Vec<_> = get_cleanup_items(conn, id)?.into_iter().filter(|i| i.pending()).collect();
+    if pending.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(IncompleteCleanup { id, backup_manifest_path, pending }))
+}
+
+/// Mark a package removed by a cleanup, so `list` stops showing it before the next scan
+pub fn mark_package_removed(conn: &Connection, name: &str, source: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE packages SET removed_at = CURRENT_TIMESTAMP
+         WHERE name = ?1 AND source = ?2 AND removed_at IS NULL",
+        params![name, source],
+    )?;
+    Ok(())
+}
+
+/// Removal attempts of a cleanup, in the order they were made
+pub fn get_cleanup_items(conn: &Connection, cleanup_id: i64) -> Result<Vec<CleanupItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT package_name, source, size_bytes, status, error_message, exit_code, duration_ms, action
+         FROM cleanup_items WHERE cleanup_id = ?1 ORDER BY id"
+    )?;
+    let items = stmt.query_map(params![cleanup_id], |row| {
+        Ok(CleanupItem {
+            package: row.get(0)?,
+            source: row.get(1)?,
+            size_bytes: row.get::<_, Option<i64>>(2)?.map(|s| s as u64),
+            status: row.get(3)?,
+            error_message: row.get(4)?,
+            exit_code: row.get(5)?,
+            duration_ms: row.get::<_, Option<i64>>(6)?.map(|d| d as u64),
+            action: row.get(7)?,
+        })
+    })?
+    .collect::<Result<Vec<CleanupItem>, _>>()?;
+    Ok(items)
+}
+
+/// Id of the most recent cleanup, if any
+pub fn get_last_cleanup_id(conn: &Connection) -> Result<Option<i64>> {
+    let id = conn.query_row("SELECT MAX(id) FROM cleanups", [], |row| row.get(0))?;
+    Ok(id)
+}
+
+/// Id of the cleanup whose backup manifest is `backup_manifest_path`
+pub fn get_cleanup_id_by_manifest(conn: &Connection, backup_manifest_path: &str) -> Result<Option<i64>> {
+    let id = conn.query_row(
+        "SELECT MAX(id) FROM cleanups WHERE backup_manifest_path = ?1",
+        params![backup_manifest_path],
+        |row| row.get(0),
+    )?;
+    Ok(id)
+}
+
+/// Record the outcome of restoring a cleanup's backup
+pub fn update_cleanup_restore_status(
+    conn: &Connection,
+    backup_manifest_path: &str,
+    restore_status: &str,
+) -> Result<usize> {
+    let updated = conn.execute(
+        "UPDATE cleanups SET restore_status = ?1 WHERE backup_manifest_path = ?2",
+        params![restore_status, backup_manifest_path],
+    )?;
+
+    Ok(updated)
+}
+
+/// Get the row IDs of every package with this name (one per source)
+pub fn find_package_ids(conn: &Connection, name: &str) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT id FROM packages WHERE name = ?1 ORDER BY source")?;
+    let ids = stmt.query_map(params![name], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+    Ok(ids)
+}
+
+/// Get the row ID of a package by name and source
+pub fn get_package_id(conn: &Connection, name: &str, source: &PackageSource) -> Result<Option<i64>> {
+    let result = conn.query_row(
+        "SELECT id FROM packages WHERE name = ?1 AND source = ?2",
+        params![name, source.to_string()],
+        |row| row.get(0),
+    );
+
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Tag a package (no-op if already tagged)
+pub fn add_tag(conn: &Connection, package_id: i64, tag: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO package_tags (package_id, tag) VALUES (?1, ?2)",
+        params![package_id, tag],
+    )?;
+    Ok(())
+}
+
+/// Remove a tag from a package, returning whether it was present
+pub fn remove_tag(conn: &Connection, package_id: i64, tag: &str) -> Result<bool> {
+    let removed = conn.execute(
+        "DELETE FROM package_tags WHERE package_id = ?1 AND tag = ?2",
+        params![package_id, tag],
+    )?;
+    Ok(removed > 0)
+}
+
+/// Get the tags on a package
+pub fn get_package_tags(conn: &Connection, package_id: i64) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT tag FROM package_tags WHERE package_id = ?1 ORDER BY tag")?;
+    let tags = stmt.query_map(params![package_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(tags)
+}
+
+/// Get every (tag, package name) pair, ordered by tag
+pub fn get_all_tags(conn: &Connection) -> Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.tag, p.name FROM package_tags t
+         JOIN packages p ON p.id = t.package_id
+         ORDER BY t.tag, p.name"
+    )?;
+    let tags = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(String, String)>, _>>()?;
+    Ok(tags)
+}
+
+/// Get the (name, source) of every package carrying a tag
+pub fn get_tagged_packages(conn: &Connection, tag: &str) -> Result<HashSet<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT p.name, p.source FROM package_tags t
+         JOIN packages p ON p.id = t.package_id
+         WHERE t.tag = ?1"
+    )?;
+    let tagged = stmt.query_map(params![tag], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashSet<(String, String)>, _>>()?;
+    Ok(tagged)
+}
+
+/// A free-form note attached to a package
+#[derive(Debug, Clone, Serialize)]
+pub struct PackageNote {
+    pub note: String,
+    pub created_at: String,
+}
+
+/// Attach a note to a package
+pub fn add_note(conn: &Connection, package_id: i64, note: &str) -> Result<i64> {
+    conn.execute(
+        "INSERT INTO package_notes (package_id, note) VALUES (?1, ?2)",
+        params![package_id, note],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Remove all notes from a package
+pub fn remove_notes(conn: &Connection, package_id: i64) -> Result<usize> {
+    let removed = conn.execute(
+        "DELETE FROM package_notes WHERE package_id = ?1",
+        params![package_id],
+    )?;
+    Ok(removed)
+}
+
+/// Get the notes on a package, oldest first
+pub fn get_package_notes(conn: &Connection, package_id: i64) -> Result<Vec<PackageNote>> {
+    let mut stmt = conn.prepare(
+        "SELECT note, created_at FROM package_notes WHERE package_id = ?1 ORDER BY id"
+    )?;
+    let notes = stmt.query_map(params![package_id], |row| {
+        Ok(PackageNote {
+            note: row.get(0)?,
+            created_at: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+        })
+    })?
+    .collect::<Result<Vec<PackageNote>, _>>()?;
+    Ok(notes)
+}
+
+/// Get package by name and source
+pub fn get_package_by_name(conn: &Connection, name: &str, source: &PackageSource) -> Result<Option<Package>> {
+    let source_str = source.to_string();
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM packages WHERE name = ?1 AND source = ?2",
+        PACKAGE_COLUMNS
+    ))?;
+
+    let result = stmt.query_row(params![name, source_str], package_from_row);
+
+    match result {
+        Ok((id, mut pkg)) => {
+            pkg.dependencies = get_package_dependencies(conn, id)?;
+            pkg.binaries = get_package_binaries(conn, id)?;
+            pkg.dependents = get_package_dependents(conn, &pkg.name, &pkg.source)?;
+            pkg.usage_trend = usage_trends_of(conn, Utc::now(), Some(&[id]))?.remove(&id);
+            Ok(Some(pkg))
+        }
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+    use crate::storage::Database;
+
+    #[test]
+    fn test_upsert_package() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.version = Some("1.0.0".to_string());
+
+        let id = upsert_package(db.conn(), &package).unwrap();
+        assert!(id > 0);
+
+        // Update the package
+        package.version = Some("2.0.0".to_string());
+        let id2 = upsert_package(db.conn(), &package).unwrap();
+        assert_eq!(id, id2); // Should be the same ID
+
+        // Verify version was updated
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap();
         assert!(retrieved.is_some());
         assert_eq!(retrieved.unwrap().version, Some("2.0.0".to_string()));
     }
+
+    #[test]
+    fn test_description_survives_rescans_without_one() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.description = Some("Test package".to_string());
+        upsert_package(db.conn(), &package).unwrap();
+        package.description = None;
+        upsert_package(db.conn(), &package).unwrap();
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(retrieved.description.as_deref(), Some("Test package"));
+    }
+
+    #[test]
+    fn test_binaries_are_replaced_on_each_save() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.binaries = vec!["tp".to_string(), "test-package".to_string()];
+        upsert_package(db.conn(), &package).unwrap();
+        package.binaries.pop();
+        upsert_package(db.conn(), &package).unwrap();
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(retrieved.binaries, vec!["tp"]);
+        assert_eq!(get_packages(db.conn()).unwrap()[0].binaries, vec!["tp"]);
+    }
+
+    #[test]
+    fn test_stale_versions_are_cleared_after_cleanup() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.stale_versions = vec!["1.0.0".to_string(), "1.5.0".to_string()];
+        package.stale_bytes = Some(4096);
+        upsert_package(db.conn(), &package).unwrap();
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(retrieved.stale_versions, vec!["1.0.0", "1.5.0"]);
+        assert_eq!(retrieved.stale_bytes, Some(4096));
+
+        package.stale_versions.clear();
+        package.stale_bytes = None;
+        upsert_package(db.conn(), &package).unwrap();
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap().unwrap();
+        assert!(retrieved.stale_versions.is_empty());
+        assert_eq!(retrieved.stale_bytes, None);
+    }
+
+    #[test]
+    fn test_architecture_is_saved() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.arch = Some(Architecture::X86_64);
+        upsert_package(db.conn(), &package).unwrap();
+        let retrieved = get_package_by_name(db.conn(), "test-package", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(retrieved.arch, Some(Architecture::X86_64));
+    }
+
+    #[test]
+    fn test_quick_scans_keep_login_items() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        // A quick scan, which doesn't read the login items, keeps what the last full one found
+        let mut package = Package::new("test-package".to_string(), PackageSource::Homebrew);
+        package.launches_at_login = true;
+        upsert_package(db.conn(), &package).unwrap();
+        let mut rescanned = vec![Package::new("test-package".to_string(), PackageSource::Homebrew)];
+        carry_over_usage(db.conn(), &mut rescanned).unwrap();
+        assert!(rescanned[0].launches_at_login);
+    }
+
+    #[test]
+    fn test_scan_history() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        assert!(get_last_scan(db.conn(), None).unwrap().is_none());
+
+        let mut counts = BTreeMap::new();
+        counts.insert("homebrew".to_string(), 10);
+        insert_scan(db.conn(), "full", 10, 100, 1000, &counts).unwrap();
+        counts.insert("homebrew".to_string(), 12);
+        insert_scan(db.conn(), "full", 12, 100, 1500, &counts).unwrap();
+        insert_scan(db.conn(), "npm", 3, 100, 10, &BTreeMap::new()).unwrap();
+
+        let scans = get_recent_scans(db.conn(), "full", 10).unwrap();
+        assert_eq!(scans.len(), 2);
+        assert!(scans[0].scanned_at().is_some());
+        assert_eq!(scans[0].packages_found, 10); // oldest first
+        assert_eq!(scans[1].total_size_bytes, Some(1500));
+        assert_eq!(scans[1].source_counts["homebrew"], 12);
+
+        assert_eq!(get_last_scan(db.conn(), None).unwrap().unwrap().scan_type, "npm");
+        assert_eq!(get_last_scan(db.conn(), Some("full")).unwrap().unwrap().packages_found, 12);
+        assert!(get_last_scan(db.conn(), Some("cargo")).unwrap().is_none());
+
+        // Reclaimable space comes from the latest earlier scan that recorded it
+        let npm = get_last_scan(db.conn(), None).unwrap().unwrap();
+        assert_eq!(npm.reclaimable, None);
+        assert_eq!(get_previous_reclaimable(db.conn(), npm.id).unwrap(), None);
+        set_scan_reclaimable(db.conn(), scans[0].id, Reclaimable { bytes: 4096, packages: 2 }).unwrap();
+        assert_eq!(get_previous_reclaimable(db.conn(), npm.id).unwrap(), Some(Reclaimable { bytes: 4096, packages: 2 }));
+        assert_eq!(get_previous_reclaimable(db.conn(), scans[0].id).unwrap(), None);
+        assert_eq!(get_recent_scans(db.conn(), "full", 10).unwrap()[0].reclaimable.unwrap().packages, 2);
+
+        insert_cleanup(db.conn(), "/tmp/a.json", 2, 300).unwrap();
+        insert_cleanup(db.conn(), "/tmp/b.json", 1, 200).unwrap();
+        let totals = get_cleanup_totals(db.conn()).unwrap();
+        assert_eq!(totals.cleanups, 2);
+        assert_eq!(totals.packages_removed, 3);
+        assert_eq!(totals.space_recovered, 500);
+    }
+
+    #[test]
+    fn test_tags_and_notes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let package = Package::new("postgres".to_string(), PackageSource::Homebrew);
+        let id = upsert_package(db.conn(), &package).unwrap();
+
+        add_tag(db.conn(), id, "work").unwrap();
+        add_tag(db.conn(), id, "work").unwrap(); // duplicate is ignored
+        add_tag(db.conn(), id, "keep").unwrap();
+        assert_eq!(get_package_tags(db.conn(), id).unwrap(), vec!["keep", "work"]);
+
+        let tagged = get_tagged_packages(db.conn(), "work").unwrap();
+        assert!(tagged.contains(&("postgres".to_string(), "homebrew".to_string())));
+
+        assert!(remove_tag(db.conn(), id, "work").unwrap());
+        assert!(!remove_tag(db.conn(), id, "work").unwrap());
+
+        add_note(db.conn(), id, "needed for work").unwrap();
+        let notes = get_package_notes(db.conn(), id).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].note, "needed for work");
+
+        // Rescanning keeps the same row, so annotations survive
+        assert_eq!(upsert_package(db.conn(), &package).unwrap(), id);
+        assert_eq!(remove_notes(db.conn(), id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_mark_removed_packages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        for name in ["firefox", "slack"] {
+            upsert_package(db.conn(), &Package::new(name.to_string(), PackageSource::HomebrewCask)).unwrap();
+        }
+        upsert_package(db.conn(), &Package::new("wget".to_string(), PackageSource::Homebrew)).unwrap();
+
+        // Only the scanned source is reconciled
+        let seen: HashSet<&str> = ["firefox"].into_iter().collect();
+        assert_eq!(mark_removed_packages(db.conn(), &PackageSource::HomebrewCask, &seen).unwrap(), 1);
+
+        let names: Vec<_> = get_packages(db.conn()).unwrap().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["firefox", "wget"]);
+
+        let all = get_packages_including_removed(db.conn()).unwrap();
+        let slack = all.iter().find(|p| p.name == "slack").unwrap();
+        assert!(slack.removed_at.is_some());
+
+        // Reinstalling clears the mark
+        upsert_package(db.conn(), &Package::new("slack".to_string(), PackageSource::HomebrewCask)).unwrap();
+        assert_eq!(get_packages(db.conn()).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_insert_usage_event_dedup() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let id = upsert_package(db.conn(), &Package::new("jq".to_string(), PackageSource::Homebrew)).unwrap();
+        let when = Utc::now();
+
+        insert_usage_event(db.conn(), id, "shell", when, Some("jq . data.json")).unwrap();
+        insert_usage_event(db.conn(), id, "shell", when, Some("jq . data.json")).unwrap();
+        insert_usage_event(db.conn(), id, "atime", when, None).unwrap();
+
+        let count: i64 = db
+            .conn()
+            .query_row("SELECT COUNT(*) FROM usage_events WHERE package_id = ?1", [id], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_usage_checks_replaced_each_scan() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let id = upsert_package(db.conn(), &Package::new("jq".to_string(), PackageSource::Homebrew)).unwrap();
+        assert_eq!(get_usage_checks(db.conn(), id).unwrap(), (Vec::new(), None));
+
+        let shell = UsageCheck::new("shell", false, "no uses across 3 history files");
+        let atime = UsageCheck::new("atime", true, "2024-01-03, low confidence");
+        save_usage_checks(db.conn(), id, &[shell.clone(), atime.clone()]).unwrap();
+        let (checks, checked_at) = get_usage_checks(db.conn(), id).unwrap();
+        assert_eq!(checks, [shell.clone(), atime]);
+        assert!(checked_at.is_some());
+
+        save_usage_checks(db.conn(), id, std::slice::from_ref(&shell)).unwrap();
+        assert_eq!(get_usage_checks(db.conn(), id).unwrap().0, [shell]);
+    }
+
+    #[test]
+    fn test_record_reinstalled_package() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut node = Package::new("node".to_string(), PackageSource::Homebrew);
+        node.version = Some("18.19.0".to_string());
+        node.usage_count = 7;
+        upsert_package(db.conn(), &node).unwrap();
+        db.conn().execute("UPDATE packages SET removed_at = CURRENT_TIMESTAMP", []).unwrap();
+
+        record_reinstalled_package(db.conn(), "node", "homebrew", Some("22.1.0")).unwrap();
+        record_reinstalled_package(db.conn(), "node@18", "homebrew", Some("18.20.4")).unwrap();
+
+        let node = get_package_by_name(db.conn(), "node", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(node.version.as_deref(), Some("22.1.0"));
+        assert_eq!(node.usage_count, 7);
+        assert!(node.removed_at.is_none());
+        let node_18 = get_package_by_name(db.conn(), "node@18", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(node_18.version.as_deref(), Some("18.20.4"));
+    }
+
+    #[test]
+    fn test_quick_scan_keeps_usage() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let last_used = Utc::now() - chrono::Duration::days(3);
+        let mut full = Package::new("jq".to_string(), PackageSource::Homebrew);
+        full.version = Some("1.6".to_string());
+        full.last_used = Some(last_used);
+        full.usage_count = 42;
+        full.usage_confidence = UsageConfidence::High;
+        upsert_package(db.conn(), &full).unwrap();
+
+        // A quick scan knows the new version and size but nothing about usage
+        let mut quick = Package::new("jq".to_string(), PackageSource::Homebrew);
+        quick.version = Some("1.7".to_string());
+        quick.size_bytes = Some(2048);
+        let mut packages = vec![quick, Package::new("fd".to_string(), PackageSource::Homebrew)];
+        carry_over_usage(db.conn(), &mut packages).unwrap();
+        for package in &packages {
+            upsert_package(db.conn(), package).unwrap();
+        }
+
+        let stored = get_package_by_name(db.conn(), "jq", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(stored.version.as_deref(), Some("1.7"));
+        assert_eq!(stored.size_bytes, Some(2048));
+        assert_eq!(stored.last_used.map(|d| d.timestamp()), Some(last_used.timestamp()));
+        assert_eq!(stored.usage_count, 42);
+        assert_eq!(stored.usage_confidence, UsageConfidence::High);
+
+        let new = get_package_by_name(db.conn(), "fd", &PackageSource::Homebrew).unwrap().unwrap();
+        assert!(new.last_used.is_none());
+    }
+
+    #[test]
+    fn test_unmeasured_scan_keeps_sizes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut measured = Package::new("Xcode".to_string(), PackageSource::Applications);
+        measured.size_bytes = Some(12 * 1024 * 1024 * 1024);
+        upsert_package(db.conn(), &measured).unwrap();
+
+        let mut packages = vec![
+            Package::new("Xcode".to_string(), PackageSource::Applications),
+            Package::new("Slack".to_string(), PackageSource::Applications),
+        ];
+        carry_over_sizes(db.conn(), &mut packages).unwrap();
+        assert_eq!(packages[0].size_bytes, measured.size_bytes);
+        assert!(packages[1].size_bytes.is_none());
+    }
+
+    #[test]
+    fn test_spotlight_timeline_keeps_distinct_dates() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let id = upsert_package(db.conn(), &Package::new("Zoom".to_string(), PackageSource::Applications)).unwrap();
+        let first = Utc::now() - chrono::Duration::days(20);
+        let second = Utc::now() - chrono::Duration::days(2);
+
+        // The same launch seen by several scans is one event; a relaunch is another
+        insert_usage_event(db.conn(), id, "spotlight", first, Some("4 uses")).unwrap();
+        insert_usage_event(db.conn(), id, "spotlight", first, Some("4 uses")).unwrap();
+        insert_usage_event(db.conn(), id, "spotlight", second, Some("5 uses")).unwrap();
+
+        let events = get_usage_events(db.conn(), id).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].details.as_deref(), Some("5 uses"));
+
+        let latest = get_latest_usage_events(db.conn(), "spotlight").unwrap();
+        let key = ("Zoom".to_string(), PackageSource::Applications.to_string());
+        assert_eq!(latest[&key].timestamp(), second.timestamp());
+        assert!(get_latest_usage_events(db.conn(), "shell").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_usage_trends_and_monthly_counts() {
+        use chrono::TimeZone;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+        let id = upsert_package(db.conn(), &Package::new("kubectl".to_string(), PackageSource::Homebrew)).unwrap();
+        let now = Utc.with_ymd_and_hms(2025, 6, 30, 12, 0, 0).unwrap();
+
+        // Daily use from about eight months ago until four months ago, then nothing
+        for days_ago in 120..240 {
+            let when = now - chrono::Duration::days(days_ago);
+            insert_usage_event(db.conn(), id, "shell", when, Some("kubectl get pods")).unwrap();
+            // Seen by Spotlight on the same day: still one day of use
+            insert_usage_event(db.conn(), id, "spotlight", when + chrono::Duration::hours(1), None).unwrap();
+        }
+        // Neither old uses nor access times count
+        insert_usage_event(db.conn(), id, "shell", now - chrono::Duration::days(400), None).unwrap();
+        insert_usage_event(db.conn(), id, "atime", now - chrono::Duration::days(5), None).unwrap();
+
+        let trend = get_usage_trends(db.conn(), now).unwrap()[&id];
+        assert_eq!(trend, UsageTrend { last_30_days: 0, last_90_days: 0, last_365_days: 120 });
+        assert!(trend.describe().unwrap().starts_with("usage dropped from ~13×/month"));
+
+        let months = get_monthly_usage(db.conn(), id, now - chrono::Duration::days(365)).unwrap();
+        assert_eq!(months.first().unwrap().month, "2024-11");
+        assert_eq!(months.last().unwrap(), &MonthlyUsage { month: "2025-03".to_string(), uses: 2 });
+        assert_eq!(months.iter().map(|m| m.uses).sum::<u32>(), 120);
+    }
+
+    #[test]
+    fn test_scheduled_runs_count_as_use() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+        let id = upsert_package(db.conn(), &Package::new("restic".to_string(), PackageSource::Homebrew)).unwrap();
+        let now = Utc::now();
+
+        for days_ago in [3, 40] {
+            insert_usage_event(db.conn(), id, "scheduled", now - chrono::Duration::days(days_ago), Some("crontab line 4")).unwrap();
+        }
+
+        let trend = get_usage_trends(db.conn(), now).unwrap()[&id];
+        assert_eq!(trend, UsageTrend { last_30_days: 1, last_90_days: 2, last_365_days: 2 });
+    }
+
+    #[test]
+    fn test_package_dependents_scoped_by_source() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+        wget.dependencies = vec!["openssl@3".to_string(), "libidn2".to_string()];
+        let mut curl = Package::new("curl".to_string(), PackageSource::Homebrew);
+        curl.dependencies = vec!["openssl@3".to_string()];
+        // Same dependency name from another ecosystem must not leak into Homebrew
+        let mut tool = Package::new("some-tool".to_string(), PackageSource::Npm);
+        tool.dependencies = vec!["libidn2".to_string()];
+
+        for package in [&wget, &curl, &tool] {
+            upsert_package(db.conn(), package).unwrap();
+        }
+        upsert_package(db.conn(), &Package::new("openssl@3".to_string(), PackageSource::Homebrew)).unwrap();
+        upsert_package(db.conn(), &Package::new("libidn2".to_string(), PackageSource::Homebrew)).unwrap();
+
+        assert_eq!(get_package_dependents(db.conn(), "openssl@3", &PackageSource::Homebrew).unwrap(), vec!["curl", "wget"]);
+        assert_eq!(get_package_dependents(db.conn(), "libidn2", &PackageSource::Homebrew).unwrap(), vec!["wget"]);
+
+        let libidn2 = get_package_by_name(db.conn(), "libidn2", &PackageSource::Homebrew).unwrap().unwrap();
+        assert_eq!(libidn2.dependents, vec!["wget"]);
+
+        let all = get_packages(db.conn()).unwrap();
+        let openssl = all.iter().find(|p| p.name == "openssl@3").unwrap();
+        assert_eq!(openssl.dependents, vec!["curl", "wget"]);
+        assert!(all.iter().find(|p| p.name == "wget").unwrap().dependents.is_empty());
+
+        // Dependents that were uninstalled no longer hold the dependency
+        let seen: HashSet<&str> = ["wget", "openssl@3", "libidn2"].into_iter().collect();
+        mark_removed_packages(db.conn(), &PackageSource::Homebrew, &seen).unwrap();
+        assert_eq!(get_package_dependents(db.conn(), "openssl@3", &PackageSource::Homebrew).unwrap(), vec!["wget"]);
+    }
+
+    #[test]
+    fn test_upsert_packages_matches_per_row() {
+        let packages: Vec<Package> = (0..500)
+            .map(|i| {
+                let mut package = Package::new(format!("pkg-{:03}", i), PackageSource::Homebrew);
+                package.version = Some(format!("1.{}", i));
+                package.size_bytes = Some(i * 1024);
+                package.last_used = Some(Utc::now());
+                package.dependencies = vec![format!("pkg-{:03}", (i + 1) % 500)];
+                package
+            })
+            .collect();
+
+        let per_row_file = NamedTempFile::new().unwrap();
+        let per_row = Database::new(per_row_file.path().to_path_buf()).unwrap();
+        per_row.init().unwrap();
+        let per_row_ids: Vec<i64> = packages.iter().map(|p| upsert_package(per_row.conn(), p).unwrap()).collect();
+
+        let batch_file = NamedTempFile::new().unwrap();
+        let mut batch = Database::new(batch_file.path().to_path_buf()).unwrap();
+        batch.init().unwrap();
+        let tx = batch.transaction().unwrap();
+        let batch_ids = upsert_packages(&tx, &packages, None).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(per_row_ids, batch_ids);
+        let as_json = |db: &Database| serde_json::to_value(get_packages(db.conn()).unwrap()).unwrap();
+        assert_eq!(as_json(&per_row), as_json(&batch));
+
+        // Re-upserting updates rows in place and returns the existing ids
+        let tx = batch.transaction().unwrap();
+        assert_eq!(upsert_packages(&tx, &packages, None).unwrap(), batch_ids);
+    }
+
+    #[test]
+    fn test_query_packages_filters() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let now = Utc::now();
+        let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+        wget.size_bytes = Some(2_000_000);
+        wget.last_used = Some(now - chrono::Duration::days(200));
+        wget.dependencies = vec!["openssl@3".to_string(), "libidn2".to_string()];
+        wget.install_date = Some(now - chrono::Duration::days(500));
+        let mut firefox = Package::new("firefox".to_string(), PackageSource::HomebrewCask);
+        firefox.size_bytes = Some(300_000_000);
+        firefox.last_used = Some(now - chrono::Duration::days(1));
+        firefox.install_date = Some(now - chrono::Duration::days(30));
+        let mut left_pad = Package::new("left-pad".to_string(), PackageSource::Npm);
+        left_pad.size_bytes = Some(4096);
+        for package in [&wget, &firefox, &left_pad] {
+            upsert_package(db.conn(), package).unwrap();
+        }
+
+        let names = |query: PackageQuery| -> Vec<String> {
+            query_packages(db.conn(), &query).unwrap().into_iter().map(|p| p.name).collect()
+        };
+
+        assert_eq!(names(PackageQuery::default()), vec!["firefox", "left-pad", "wget"]);
+        assert_eq!(names(PackageQuery { sources: Some(vec![PackageSource::Homebrew, PackageSource::HomebrewCask]), ..Default::default() }), vec!["firefox", "wget"]);
+        assert_eq!(names(PackageQuery { min_size: Some(1_000_000), ..Default::default() }), vec!["firefox", "wget"]);
+        assert_eq!(names(PackageQuery { max_size: Some(2_000_000), ..Default::default() }), vec!["left-pad", "wget"]);
+        assert_eq!(names(PackageQuery { never_used: true, ..Default::default() }), vec!["left-pad"]);
+        assert_eq!(
+            names(PackageQuery { installed_before: Some(now - chrono::Duration::days(365)), ..Default::default() }),
+            vec!["wget"]
+        );
+        assert_eq!(names(PackageQuery { name_like: Some(like_glob("*fox")), ..Default::default() }), vec!["firefox"]);
+        assert_eq!(names(PackageQuery { name_like: Some(like_glob("W?ET")), ..Default::default() }), vec!["wget"]);
+        assert_eq!(names(PackageQuery { name_like: Some(like_glob("left_*")), ..Default::default() }), Vec::<String>::new());
+        assert_eq!(
+            names(PackageQuery { unused_before: Some(now - chrono::Duration::days(90)), ..Default::default() }),
+            vec!["left-pad", "wget"]
+        );
+
+        // Loading everything at once keeps dependencies in insertion order
+        let all = get_packages(db.conn()).unwrap();
+        assert_eq!(all[2].dependencies, wget.dependencies);
+        assert!(has_packages(db.conn(), false).unwrap());
+    }
+
+    #[test]
+    fn test_only_filtered_or_paged_queries_narrow() {
+        assert!(!PackageQuery::default().narrows());
+        assert!(!PackageQuery { include_removed: true, order: PackageOrder::Size, ..Default::default() }.narrows());
+        assert!(PackageQuery { limit: Some(20), ..Default::default() }.narrows());
+        assert!(PackageQuery { offset: 20, ..Default::default() }.narrows());
+        assert!(PackageQuery { sources: Some(vec![PackageSource::Npm]), include_removed: true, ..Default::default() }.narrows());
+    }
+
+    #[test]
+    fn test_query_packages_sorts_and_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let now = Utc::now();
+        let mut python = Package::new("python@3.12".to_string(), PackageSource::Homebrew);
+        python.size_bytes = Some(80_000_000);
+        python.last_used = Some(now - chrono::Duration::days(3));
+        python.usage_count = 40;
+        let mut pyenv = Package::new("pyenv".to_string(), PackageSource::Homebrew);
+        pyenv.size_bytes = Some(2_000_000);
+        pyenv.dependencies = vec!["python@3.12".to_string()];
+        let mut jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        jq.last_used = Some(now - chrono::Duration::days(30));
+        jq.usage_count = 7;
+        for package in [&python, &pyenv, &jq] {
+            upsert_package(db.conn(), package).unwrap();
+        }
+        let jq_id = get_package_id(db.conn(), "jq", &PackageSource::Homebrew).unwrap().unwrap();
+        add_tag(db.conn(), jq_id, "keep").unwrap();
+
+        let names = |query: PackageQuery| -> Vec<String> {
+            query_packages(db.conn(), &query).unwrap().into_iter().map(|p| p.name).collect()
+        };
+        let ordered = |order: PackageOrder| names(PackageQuery { order, ..Default::default() });
+
+        assert_eq!(ordered(PackageOrder::Size), ["python@3.12", "pyenv", "jq"]);
+        // Never used goes last
+        assert_eq!(ordered(PackageOrder::LastUsed), ["python@3.12", "jq", "pyenv"]);
+        assert_eq!(ordered(PackageOrder::UsageCount), ["python@3.12", "jq", "pyenv"]);
+
+        assert_eq!(names(PackageQuery { name_like: Some(like_containing("PY")), ..Default::default() }), ["pyenv", "python@3.12"]);
+        assert!(names(PackageQuery { name_like: Some(like_containing("py_")), ..Default::default() }).is_empty());
+        assert_eq!(names(PackageQuery { tag: Some("keep".to_string()), ..Default::default() }), ["jq"]);
+
+        let page = PackageQuery { order: PackageOrder::Size, limit: Some(1), offset: 1, ..Default::default() };
+        assert_eq!(names(page.clone()), ["pyenv"]);
+        assert_eq!(names(PackageQuery { limit: None, ..page }), ["pyenv", "jq"]);
+
+        // Related rows still arrive for the page, dependents included
+        let page = query_packages(db.conn(), &PackageQuery { name_like: Some("py%".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(page[0].dependencies, ["python@3.12"]);
+        assert_eq!(page[1].dependents, ["pyenv"]);
+    }
+
+    #[test]
+    fn test_size_history_and_growth() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let mut node = Package::new("typescript".to_string(), PackageSource::Npm);
+        let mut xcode = Package::new("Xcode".to_string(), PackageSource::Applications);
+        for (node_size, xcode_size) in [(100, 1000), (150, 900), (400, 900)] {
+            node.size_bytes = Some(node_size);
+            xcode.size_bytes = Some(xcode_size);
+            let tx = db.transaction().unwrap();
+            let scan_id = insert_scan(&tx, "full", 2, 10, 0, &BTreeMap::new()).unwrap();
+            upsert_packages(&tx, &[node.clone(), xcode.clone()], Some(scan_id)).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let id = get_package_id(db.conn(), "typescript", &PackageSource::Npm).unwrap().unwrap();
+        let sizes: Vec<i64> = get_size_history(db.conn(), id, 2).unwrap().iter().map(|r| r.size_bytes).collect();
+        assert_eq!(sizes, vec![150, 400]);
+
+        let growth = get_size_growth(db.conn()).unwrap();
+        assert_eq!(growth[&("npm".to_string(), "typescript".to_string())], 250);
+        assert_eq!(growth[&("applications".to_string(), "Xcode".to_string())], 0);
+
+        assert_eq!(prune_size_history(db.conn(), 1).unwrap(), 4);
+        assert_eq!(get_size_history(db.conn(), id, 5).unwrap().len(), 1);
+        assert!(get_size_growth(db.conn()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_directory_sizes_forget_missing_directories() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let measured_at = DateTime::parse_from_rfc3339("2024-11-02T10:00:00Z").unwrap().with_timezone(&Utc);
+        let size = CachedSize { size_bytes: 4096, modified_ns: 1_700_000_000_000_000_000, measured_at };
+        let gone = dir.path().join("Uninstalled.app");
+        save_directory_sizes(db.conn(), &HashMap::from([(dir.path().to_path_buf(), size), (gone.clone(), size)])).unwrap();
+
+        let sizes = get_directory_sizes(db.conn()).unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes[dir.path()], size);
+    }
+
+    #[test]
+    fn test_source_signals_replace_earlier_ones() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let before = ChangeSignals::from([
+            (PathBuf::from("/opt/homebrew/Cellar"), Some(1_700_000_000_000_000_000)),
+            (PathBuf::from("/opt/homebrew/Caskroom"), None),
+        ]);
+        save_source_signals(db.conn(), "homebrew", &before).unwrap();
+        save_source_signals(db.conn(), "cargo", &ChangeSignals::new()).unwrap();
+        assert_eq!(get_source_signals(db.conn()).unwrap(), HashMap::from([("homebrew".to_string(), before)]));
+
+        let after = ChangeSignals::from([(PathBuf::from("/opt/homebrew/Cellar"), Some(1_800_000_000_000_000_000))]);
+        save_source_signals(db.conn(), "homebrew", &after).unwrap();
+        assert_eq!(get_source_signals(db.conn()).unwrap()["homebrew"], after);
+    }
+
+    #[test]
+    fn test_history_files_and_usage_totals() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        assert!(get_history_files(db.conn()).unwrap().is_empty());
+
+        let mut state = HistoryFileState {
+            path: PathBuf::from("/Users/me/.zsh_history"),
+            inode: 42,
+            offset: 1024,
+            last_entry_at: Some(Utc::now()),
+        };
+        save_history_files(db.conn(), std::slice::from_ref(&state)).unwrap();
+        state.offset = 2048;
+        save_history_files(db.conn(), std::slice::from_ref(&state)).unwrap();
+
+        let files = get_history_files(db.conn()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].offset, 2048);
+        assert_eq!(files[0].last_entry_at.map(|t| t.timestamp()), state.last_entry_at.map(|t| t.timestamp()));
+
+        let mut package = Package::new("jq".to_string(), PackageSource::Homebrew);
+        package.usage_count = 7;
+        package.usage_confidence = UsageConfidence::Low;
+        upsert_package(db.conn(), &package).unwrap();
+
+        let totals = get_usage_totals(db.conn()).unwrap();
+        assert_eq!(totals[&("jq".to_string(), "homebrew".to_string())], (None, 7, UsageConfidence::Low));
+        assert_eq!(get_packages(db.conn()).unwrap()[0].usage_confidence, UsageConfidence::Low);
+    }
+
+    #[test]
+    fn test_latest_manual_mark_wins() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let id = upsert_package(db.conn(), &Package::new("protoc".to_string(), PackageSource::Homebrew)).unwrap();
+        assert_eq!(get_manual_mark(db.conn(), id).unwrap(), None);
+
+        let day = |d| chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 5, d, 0, 0, 0).unwrap();
+        let used = ManualMark { used: true, date: day(1) };
+        let never = ManualMark { used: false, date: day(20) };
+        record_manual_mark(db.conn(), id, &used).unwrap();
+        record_manual_mark(db.conn(), id, &never).unwrap();
+        assert_eq!(get_manual_mark(db.conn(), id).unwrap(), Some(never));
+
+        // Re-marking an earlier date still makes it the latest mark
+        record_manual_mark(db.conn(), id, &used).unwrap();
+        assert_eq!(get_manual_mark(db.conn(), id).unwrap(), Some(used));
+        assert_eq!(get_manual_marks(db.conn()).unwrap()[&("protoc".to_string(), "homebrew".to_string())], used);
+    }
+
+    #[test]
+    fn test_record_cleanup_items() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        let item = |package: &str, error: Option<&str>| CleanupItem {
+            package: package.to_string(),
+            source: "homebrew".to_string(),
+            size_bytes: Some(100),
+            status: if error.is_some() { "failed" } else { "removed" }.to_string(),
+            error_message: error.map(str::to_string),
+            exit_code: Some(if error.is_some() { 1 } else { 0 }),
+            duration_ms: Some(1500),
+            action: "remove".to_string(),
+        };
+        let cleaned = CleanupItem {
+            status: "cleaned".to_string(),
+            action: "brew-cleanup".to_string(),
+            ..item("node", None)
+        };
+        let items = vec![item("wget", None), item("openssl@3", Some("Refusing to uninstall: required by curl")), cleaned];
+
+        assert_eq!(get_last_cleanup_id(db.conn()).unwrap(), None);
+        let id = record_cleanup(db.conn(), "/tmp/cleanup_1.json", &items).unwrap();
+        assert_eq!(get_last_cleanup_id(db.conn()).unwrap(), Some(id));
+        assert_eq!(get_cleanup_id_by_manifest(db.conn(), "/tmp/cleanup_1.json").unwrap(), Some(id));
+
+        let stored = get_cleanup_items(db.conn(), id).unwrap();
+        assert_eq!(stored.len(), 3);
+        assert!(stored[1].failed());
+        assert_eq!(stored[1].error_message.as_deref(), Some("Refusing to uninstall: required by curl"));
+        assert_eq!(stored[1].exit_code, Some(1));
+        assert_eq!(stored[1].duration_ms, Some(1500));
+        assert_eq!(stored[0].action, "remove");
+        assert_eq!(stored[2].action, "brew-cleanup");
+
+        // Only successful removals count towards the totals; cleaning up stale versions
+        // recovers space without removing the package
+        let totals = get_cleanup_totals(db.conn()).unwrap();
+        assert_eq!(totals.packages_removed, 1);
+        assert_eq!(totals.space_recovered, 200);
+    }
+
+    #[test]
+    fn test_interrupted_cleanup_is_resumable() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let db = Database::new(temp_file.path().to_path_buf()).unwrap();
+        db.init().unwrap();
+
+        for name in ["wget", "jq", "htop"] {
+            upsert_package(db.conn(), &Package::new(name.to_string(), PackageSource::Homebrew)).unwrap();
+        }
+        let item = |package: &str, status: &str| CleanupItem {
+            package: package.to_string(),
+            source: "homebrew".to_string(),
+            size_bytes: Some(100),
+            status: status.to_string(),
+            error_message: None,
+            exit_code: None,
+            duration_ms: None,
+            action: "remove".to_string(),
+        };
+
+        let id = start_cleanup(db.conn(), "/tmp/cleanup_2.json", &[item("wget", "pending"), item("jq", "pending"), item("htop", "pending")]).unwrap();
+        update_cleanup_item(db.conn(), id, &item("wget", "removed")).unwrap();
+
+        // The removal shows up straight away, before the cleanup finishes
+        let names: Vec<_> = get_packages(db.conn()).unwrap().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["htop", "jq"]);
+
+        // A cleanup that never finished (killed) is as resumable as an interrupted one
+        let incomplete = get_incomplete_cleanup(db.conn()).unwrap().unwrap();
+        assert_eq!(incomplete.id, id);
+        assert_eq!(incomplete.backup_manifest_path, "/tmp/cleanup_2.json");
+        let pending: Vec<_> = incomplete.pending.iter().map(|i| i.package.as_str()).collect();
+        assert_eq!(pending, vec!["jq", "htop"]);
+
+        finish_cleanup(db.conn(), id, "interrupted").unwrap();
+        assert_eq!(get_cleanup_totals(db.conn()).unwrap().packages_removed, 1);
+        assert!(get_incomplete_cleanup(db.conn()).unwrap().is_some());
+
+        update_cleanup_item(db.conn(), id, &item("jq", "removed")).unwrap();
+        update_cleanup_item(db.conn(), id, &item("htop", "failed")).unwrap();
+        finish_cleanup(db.conn(), id, "complete").unwrap();
+        assert!(get_incomplete_cleanup(db.conn()).unwrap().is_none());
+        assert_eq!(get_cleanup_totals(db.conn()).unwrap().packages_removed, 2);
+    }
 }
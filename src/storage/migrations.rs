@@ -1,14 +1,294 @@
 // Database schema migrations
-use anyhow::Result;
-use rusqlite::Connection;
+use crate::error::{MacsweepError, Result};
+use crate::scanner::PackageSource;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+
+/// A numbered schema change, applied once in its own transaction
+#[derive(Clone, Copy)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Connection) -> Result<()>,
+}
+
+/// Every migration in order; append new ones, never edit or reorder applied ones
+pub const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "initial schema", up: initial_schema },
+    Migration { version: 2, description: "track externally removed packages", up: add_removed_at },
+    Migration { version: 3, description: "per-scan package size history", up: create_package_sizes_table },
+    Migration { version: 4, description: "per-package cleanup outcomes", up: create_cleanup_items_table },
+    Migration { version: 5, description: "package descriptions", up: add_package_description },
+    Migration { version: 6, description: "incremental shell history reads", up: create_history_files_table },
+    Migration { version: 7, description: "executables installed by each package", up: create_package_binaries_table },
+    Migration { version: 8, description: "usage confidence", up: add_usage_confidence },
+    Migration { version: 9, description: "resumable cleanups", up: add_cleanup_status },
+    Migration { version: 10, description: "uninstaller exit codes and durations", up: add_cleanup_item_command },
+    Migration { version: 11, description: "stale formula versions", up: add_stale_versions },
+    Migration { version: 12, description: "usage sources consulted per package", up: create_usage_checks_table },
+    Migration { version: 13, description: "application architectures", up: add_package_arch },
+    Migration { version: 14, description: "apps started at login", up: add_launches_at_login },
+    Migration { version: 15, description: "stable package source names", up: stable_source_names },
+    Migration { version: 16, description: "cached directory sizes", up: create_directory_sizes_table },
+    Migration { version: 17, description: "change signals for incremental scans", up: create_source_signals_table },
+    Migration { version: 18, description: "reclaimable space per scan", up: add_scan_reclaimable },
+];
+
+/// Schema version this binary understands
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
 
 pub fn run_migrations(conn: &Connection) -> Result<()> {
+    apply_migrations(conn, MIGRATIONS)
+}
+
+/// Apply every migration newer than the database's schema version
+fn apply_migrations(conn: &Connection, migrations: &[Migration]) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER PRIMARY KEY,
+            description TEXT,
+            applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+
+    let current = schema_version(conn)?;
+    let latest = migrations.last().map(|m| m.version).unwrap_or(0);
+    if current > latest {
+        return Err(MacsweepError::Other(format!(
+            "Database schema version {} is newer than this version of macsweep supports ({}); please upgrade macsweep",
+            current,
+            latest
+        )));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let tx = conn.unchecked_transaction()?;
+        (migration.up)(&tx)
+            .map_err(|e| e.context(format!("Migration {} ({}) failed", migration.version, migration.description)))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, description) VALUES (?1, ?2)",
+            rusqlite::params![migration.version, migration.description],
+        )?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+/// Current schema version; 0 for a new database or one created before versioning
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    let version: Option<u32> = conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get(0))?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Migration 1: the schema as it was before versioning. Written to be idempotent so
+/// unversioned databases from earlier releases are brought up to date in place.
+fn initial_schema(conn: &Connection) -> Result<()> {
     create_packages_table(conn)?;
     create_package_dependencies_table(conn)?;
     create_usage_events_table(conn)?;
     create_scans_table(conn)?;
     create_cleanups_table(conn)?;
+    create_package_tags_table(conn)?;
+    create_package_notes_table(conn)?;
     create_indexes(conn)?;
+    add_column_if_missing(conn, "packages", "bundle_id", "TEXT")?;
+    add_column_if_missing(conn, "cleanups", "restore_status", "TEXT")?;
+    add_column_if_missing(conn, "scans", "total_size_bytes", "INTEGER")?;
+    add_column_if_missing(conn, "scans", "source_counts", "TEXT")?;
+    Ok(())
+}
+
+/// Migration 2: packages that disappear from a scan are marked instead of deleted
+fn add_removed_at(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN removed_at TEXT", [])?;
+    Ok(())
+}
+
+/// Migration 3: package sizes recorded at every scan, for growth tracking
+fn create_package_sizes_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE package_sizes (
+            package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            scan_id INTEGER NOT NULL REFERENCES scans(id) ON DELETE CASCADE,
+            size_bytes INTEGER NOT NULL,
+            PRIMARY KEY(package_id, scan_id)
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX idx_package_sizes_scan_id ON package_sizes(scan_id)", [])?;
+    Ok(())
+}
+
+/// Migration 4: what happened to each package a cleanup tried to remove
+fn create_cleanup_items_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE cleanup_items (
+            id INTEGER PRIMARY KEY,
+            cleanup_id INTEGER NOT NULL REFERENCES cleanups(id) ON DELETE CASCADE,
+            package_name TEXT NOT NULL,
+            source TEXT NOT NULL,
+            size_bytes INTEGER,
+            status TEXT NOT NULL,
+            error_message TEXT
+        )",
+        [],
+    )?;
+    conn.execute("CREATE INDEX idx_cleanup_items_cleanup_id ON cleanup_items(cleanup_id)", [])?;
+    Ok(())
+}
+
+/// Migration 5: summaries reported by the package managers
+fn add_package_description(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN description TEXT", [])?;
+    Ok(())
+}
+
+/// Migration 6: how far each shell history file has been read
+fn create_history_files_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE history_files (
+            path TEXT PRIMARY KEY,
+            inode INTEGER NOT NULL,
+            byte_offset INTEGER NOT NULL,
+            last_entry_at TEXT,
+            parsed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Migration 7: every binary a package installs, for matching usage beyond the package name
+fn create_package_binaries_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE package_binaries (
+            package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            binary_name TEXT NOT NULL,
+            PRIMARY KEY(package_id, binary_name)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Migration 8: which kind of evidence backs each package's last use
+fn add_usage_confidence(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN usage_confidence TEXT", [])?;
+    Ok(())
+}
+
+/// Migration 9: cleanups are recorded before they start, so an interrupted one can be resumed
+fn add_cleanup_status(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE cleanups ADD COLUMN status TEXT NOT NULL DEFAULT 'complete'", [])?;
+    Ok(())
+}
+
+fn add_cleanup_item_command(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE cleanup_items ADD COLUMN exit_code INTEGER", [])?;
+    conn.execute("ALTER TABLE cleanup_items ADD COLUMN duration_ms INTEGER", [])?;
+    Ok(())
+}
+
+fn add_stale_versions(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN stale_versions TEXT", [])?;
+    conn.execute("ALTER TABLE packages ADD COLUMN stale_bytes INTEGER", [])?;
+    conn.execute("ALTER TABLE cleanup_items ADD COLUMN action TEXT NOT NULL DEFAULT 'remove'", [])?;
+    Ok(())
+}
+
+/// Migration 12: what each usage source found for a package in the latest scan, for `why`
+fn create_usage_checks_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE usage_checks (
+            package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            source TEXT NOT NULL,
+            found BOOLEAN NOT NULL,
+            result TEXT NOT NULL,
+            checked_at TEXT NOT NULL,
+            PRIMARY KEY(package_id, source)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_package_arch(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN arch TEXT", [])?;
+    Ok(())
+}
+
+fn add_launches_at_login(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE packages ADD COLUMN launches_at_login BOOLEAN NOT NULL DEFAULT 0", [])?;
+    Ok(())
+}
+
+/// Migration 15: package sources were stored as enum variant names (`HomebrewCask`); store
+/// `PackageSource::as_str` names instead, in the tables and in each scan's per-source counts
+fn stable_source_names(conn: &Connection) -> Result<()> {
+    let stable = |name: &str| name.parse::<PackageSource>().map_or_else(|_| name.to_string(), |s| s.to_string());
+
+    for table in ["packages", "cleanup_items"] {
+        let names: Vec<String> = conn
+            .prepare(&format!("SELECT DISTINCT source FROM {}", table))?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for name in names {
+            conn.execute(&format!("UPDATE {} SET source = ?2 WHERE source = ?1", table), params![name, stable(&name)])?;
+        }
+    }
+
+    let scans: Vec<(i64, String)> = conn
+        .prepare("SELECT id, source_counts FROM scans WHERE source_counts IS NOT NULL")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    for (id, json) in scans {
+        let Ok(counts) = serde_json::from_str::<BTreeMap<String, i64>>(&json) else {
+            continue;
+        };
+        let counts: BTreeMap<String, i64> = counts.into_iter().map(|(name, count)| (stable(&name), count)).collect();
+        conn.execute("UPDATE scans SET source_counts = ?2 WHERE id = ?1", params![id, serde_json::to_string(&counts)?])?;
+    }
+    Ok(())
+}
+
+/// Migration 16: directory sizes reused by later scans while the directory is unmodified, and
+/// which directory each package's size measures
+fn create_directory_sizes_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE directory_sizes (
+            path TEXT PRIMARY KEY,
+            size_bytes INTEGER NOT NULL,
+            modified_ns INTEGER NOT NULL,
+            measured_at TEXT NOT NULL
+        )",
+        [],
+    )?;
+    conn.execute("ALTER TABLE packages ADD COLUMN size_path TEXT", [])?;
+    Ok(())
+}
+
+fn create_source_signals_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE source_signals (
+            scanner TEXT NOT NULL,
+            path TEXT NOT NULL,
+            modified_ns INTEGER,
+            recorded_at TEXT NOT NULL,
+            PRIMARY KEY (scanner, path)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Migration 18: what the recommendations after each scan would free, for `scan --notify`
+fn add_scan_reclaimable(conn: &Connection) -> Result<()> {
+    conn.execute("ALTER TABLE scans ADD COLUMN reclaimable_bytes INTEGER", [])?;
+    conn.execute("ALTER TABLE scans ADD COLUMN reclaimable_packages INTEGER", [])?;
     Ok(())
 }
 
@@ -91,6 +371,49 @@ fn create_cleanups_table(conn: &Connection) -> Result<()> {
     Ok(())
 }
 
+fn create_package_tags_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_tags (
+            id INTEGER PRIMARY KEY,
+            package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            tag TEXT NOT NULL,
+            UNIQUE(package_id, tag)
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn create_package_notes_table(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS package_notes (
+            id INTEGER PRIMARY KEY,
+            package_id INTEGER NOT NULL REFERENCES packages(id) ON DELETE CASCADE,
+            note TEXT NOT NULL,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Add a column to an existing unversioned table (CREATE IF NOT EXISTS won't touch old databases)
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|name| name.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
 fn create_indexes(conn: &Connection) -> Result<()> {
     // Index for package lookups
     conn.execute(
@@ -130,5 +453,118 @@ fn create_indexes(conn: &Connection) -> Result<()> {
         [],
     )?;
 
+    // Index for tags and notes
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_package_tags_tag ON package_tags(tag)",
+        [],
+    )?;
+
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_package_notes_package_id ON package_notes(package_id)",
+        [],
+    )?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_homepage(conn: &Connection) -> Result<()> {
+        conn.execute("ALTER TABLE packages ADD COLUMN homepage TEXT", [])?;
+        Ok(())
+    }
+
+    fn failing(conn: &Connection) -> Result<()> {
+        conn.execute("ALTER TABLE packages ADD COLUMN broken TEXT", [])?;
+        Err(MacsweepError::Other("boom".to_string()))
+    }
+
+    #[test]
+    fn test_migration_preserves_data() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), latest_version());
+
+        conn.execute("INSERT INTO packages (name, source, version) VALUES ('wget', 'Homebrew', '1.24.5')", []).unwrap();
+
+        let next = latest_version() + 1;
+        let mut migrations = MIGRATIONS.to_vec();
+        migrations.push(Migration { version: next, description: "package homepages", up: add_homepage });
+        apply_migrations(&conn, &migrations).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), next);
+
+        let (version, homepage): (String, Option<String>) = conn
+            .query_row("SELECT version, homepage FROM packages WHERE name = 'wget'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(version, "1.24.5");
+        assert_eq!(homepage, None);
+
+        // Re-running is a no-op
+        apply_migrations(&conn, &migrations).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), next);
+    }
+
+    #[test]
+    fn test_upgrade_from_v1_marks_nothing_removed() {
+        let conn = Connection::open_in_memory().unwrap();
+        apply_migrations(&conn, &MIGRATIONS[..1]).unwrap();
+        conn.execute("INSERT INTO packages (name, source) VALUES ('jq', 'Homebrew')", []).unwrap();
+
+        run_migrations(&conn).unwrap();
+        let removed_at: Option<String> = conn
+            .query_row("SELECT removed_at FROM packages WHERE name = 'jq'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(removed_at, None);
+    }
+
+    #[test]
+    fn test_upgrade_renames_package_sources() {
+        let conn = Connection::open_in_memory().unwrap();
+        apply_migrations(&conn, &MIGRATIONS[..14]).unwrap();
+        conn.execute("INSERT INTO packages (name, source) VALUES ('firefox', 'HomebrewCask'), ('odd', 'Bower')", []).unwrap();
+        conn.execute(
+            "INSERT INTO scans (scan_type, packages_found, source_counts) VALUES ('full', 3, '{\"Homebrew\":2,\"Npm\":1}')",
+            [],
+        )
+        .unwrap();
+
+        run_migrations(&conn).unwrap();
+        let sources: Vec<String> = conn
+            .prepare("SELECT source FROM packages ORDER BY name").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+        assert_eq!(sources, ["homebrewcask", "Bower"]);
+        let counts: String = conn.query_row("SELECT source_counts FROM scans", [], |row| row.get(0)).unwrap();
+        assert_eq!(counts, r#"{"homebrew":2,"npm":1}"#);
+    }
+
+    #[test]
+    fn test_failed_migration_rolls_back() {
+        let conn = Connection::open_in_memory().unwrap();
+        let migrations = [
+            Migration { version: 1, description: "initial schema", up: initial_schema },
+            Migration { version: 2, description: "broken", up: failing },
+        ];
+
+        assert!(apply_migrations(&conn, &migrations).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+
+        let has_broken: bool = conn
+            .prepare("PRAGMA table_info(packages)").unwrap()
+            .query_map([], |row| row.get::<_, String>(1)).unwrap()
+            .any(|name| name.unwrap() == "broken");
+        assert!(!has_broken);
+    }
+
+    #[test]
+    fn test_refuses_newer_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        run_migrations(&conn).unwrap();
+        conn.execute("INSERT INTO schema_version (version, description) VALUES (?1, 'future')", [latest_version() + 1]).unwrap();
+
+        let err = run_migrations(&conn).unwrap_err();
+        assert!(err.to_string().contains("newer"));
+    }
+}
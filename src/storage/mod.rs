@@ -2,10 +2,9 @@
 pub mod database;
 pub mod migrations;
 
-use anyhow::Result;
+use crate::error::{Context, Result};
 use rusqlite::Connection;
-use std::path::PathBuf;
-use dirs;
+use std::path::{Path, PathBuf};
 
 pub struct Database {
     conn: Connection,
@@ -16,14 +15,25 @@ impl Database {
     pub fn new(db_path: PathBuf) -> Result<Self> {
         // Create parent directory if it doesn't exist
         if let Some(parent) = db_path.parent() {
-            std::fs::create_dir_all(parent)?;
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Cannot create database directory {}", parent.display()))?;
         }
 
-        let conn = Connection::open(db_path)?;
+        let conn = Connection::open(&db_path)
+            .with_context(|| format!("Cannot open database {}", db_path.display()))?;
         Ok(Self { conn })
     }
 
+    /// Open the database at `db_path` and bring its schema up to date
+    pub fn open(db_path: &Path) -> Result<Self> {
+        let db = Self::new(db_path.to_path_buf())?;
+        db.init()
+            .with_context(|| format!("Cannot use database {} (is it writable?)", db_path.display()))?;
+        Ok(db)
+    }
+
     /// Create a database in the default location (~/.local/share/macsweep/macsweep.db)
+    #[allow(clippy::should_implement_trait)]
     pub fn default() -> Result<Self> {
         let db_path = Self::default_path()?;
         Self::new(db_path)
@@ -32,12 +42,12 @@ impl Database {
     /// Get the default database path
     pub fn default_path() -> Result<PathBuf> {
         let data_dir = dirs::data_local_dir()
-            .ok_or_else(|| anyhow::anyhow!("Could not determine local data directory"))?;
+            .context("Could not determine local data directory")?;
 
         Ok(data_dir.join("macsweep").join("macsweep.db"))
     }
 
-    /// Initialize the database schema
+    /// Apply any pending schema migrations; fails if the database is newer than this binary
     pub fn init(&self) -> Result<()> {
         migrations::run_migrations(&self.conn)?;
         Ok(())
@@ -49,7 +59,7 @@ impl Database {
     }
 
     /// Begin a transaction
-    pub fn transaction(&mut self) -> Result<rusqlite::Transaction> {
+    pub fn transaction(&mut self) -> Result<rusqlite::Transaction<'_>> {
         Ok(self.conn.transaction()?)
     }
 }
@@ -1,20 +1,185 @@
 // Aggregates usage information from multiple sources
-use super::{UsageInfo, UsageSource};
+use super::aliases::Aliases;
+use super::login_items::LoginItems;
+use super::scheduled_jobs::ScheduledJobs;
+use super::processes::ProcessSnapshot;
+use super::project_scripts::ProjectScripts;
+use super::shell_history::{HistoryFileState, HistoryIndex};
+use super::spotlight::SpotlightCache;
+use super::{UsageCheck, UsageConfidence, UsageInfo, UsageSource};
+use crate::config::Config;
+use crate::progress::{ItemProgress, Phase, ProgressReporter};
 use crate::scanner::{Package, PackageSource};
-use anyhow::Result;
+use crate::error::Result;
+use crate::utils::process::{CommandKind, CommandTimeouts};
 use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Data fetched once per scan and shared by every `aggregate_usage` call
+#[derive(Debug, Default)]
+pub struct UsageContext {
+    pub spotlight: SpotlightCache,
+    /// Newest Spotlight last-used date recorded by earlier scans, keyed by (name, source)
+    pub recorded_spotlight: HashMap<(String, String), DateTime<Utc>>,
+    /// Shell history written since the previous scan (all of it on the first scan)
+    pub history: HistoryIndex,
+    /// Where each history file was read up to, saved with the scan
+    pub history_files: Vec<HistoryFileState>,
+    /// Whether `history` is the whole history, so its counts replace stored ones instead of adding to them
+    pub history_is_complete: bool,
+    pub processes: ProcessSnapshot,
+    pub scripts: ProjectScripts,
+    pub login_items: LoginItems,
+    pub scheduled_jobs: ScheduledJobs,
+    /// How long the Spotlight lookups of apps missing from `spotlight` may run
+    pub timeouts: CommandTimeouts,
+}
+
+impl UsageContext {
+    /// Index shell history appended since `history_files`, snapshot running processes, read
+    /// the login items and scheduled jobs, and prefetch Spotlight metadata for every app bundle
+    /// in `packages`
+    pub fn for_packages(packages: &[Package], config: &Config, history_files: &[HistoryFileState]) -> Self {
+        let lookup_timeout = config.timeouts.get(CommandKind::Scanner);
+        let processes = ProcessSnapshot::capture(lookup_timeout).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Failed to list running processes");
+            ProcessSnapshot::default()
+        });
+
+        let delta = super::shell_history::read_new_history(&config.history_paths, history_files).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "Failed to read shell history");
+            Default::default()
+        });
+        let history = HistoryIndex::with_aliases(delta.entries, &Aliases::load());
+        let agents = super::login_items::launch_agents(lookup_timeout);
+
+        let app_paths: Vec<&Path> = packages
+            .iter()
+            .filter(|p| uses_spotlight(p))
+            .filter_map(|p| p.binary_path.as_deref())
+            .collect();
+
+        let spotlight = if app_paths.is_empty() {
+            SpotlightCache::default()
+        } else {
+            SpotlightCache::load(&app_paths, config.timeouts.get(CommandKind::Mdls)).unwrap_or_else(|e| {
+                tracing::warn!(apps = app_paths.len(), error = %e, "Failed to prefetch Spotlight metadata");
+                SpotlightCache::default()
+            })
+        };
+
+        Self {
+            spotlight,
+            recorded_spotlight: HashMap::new(),
+            history,
+            history_files: delta.files,
+            history_is_complete: delta.is_complete,
+            processes,
+            scripts: ProjectScripts::load(&config.project_dirs),
+            scheduled_jobs: ScheduledJobs::load(&agents, lookup_timeout),
+            login_items: LoginItems::load(agents, lookup_timeout),
+            timeouts: config.timeouts,
+        }
+    }
+}
+
+fn uses_spotlight(package: &Package) -> bool {
+    package.source == PackageSource::Applications || package.source == PackageSource::HomebrewCask
+}
+
+/// Names `package` can be run by: its own, its binary's (neovim installs nvim), and every
+/// other executable it installs (imagemagick installs magick and convert), without repeats
+fn command_names(package: &Package) -> Vec<String> {
+    let mut names = vec![package.name.to_lowercase()];
+    if uses_spotlight(package) {
+        return names;
+    }
+
+    let bin_name = package.binary_path.as_ref().and_then(|p| p.file_name()).map(|n| n.to_string_lossy());
+    for name in bin_name.into_iter().chain(package.binaries.iter().map(|b| b.as_str().into())) {
+        let name = name.to_lowercase();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    names
+}
+
+/// Newest use, number of uses, and newest command line for `package` in shell history,
+/// over every name it can be run by
+pub fn shell_usage(package: &Package, history: &HistoryIndex) -> Option<(DateTime<Utc>, u32, String)> {
+    if !runs_from_shell(package) {
+        return None;
+    }
+
+    history.lookup_any(&command_names(package))
+}
+
+/// Whether `package` can be run from a shell at all
+fn runs_from_shell(package: &Package) -> bool {
+    // npm packages can be run through npx by name even when no binary is on the PATH
+    package.binary_path.is_some() || !package.binaries.is_empty() || package.source == PackageSource::Npm
+}
+
+fn day(dt: DateTime<Utc>) -> String {
+    dt.format("%Y-%m-%d").to_string()
+}
+
+/// Look up every package's usage in parallel; the results come back in package order, each
+/// with how long its lookup took
+pub fn aggregate_all_usage(
+    packages: &[Package],
+    context: &UsageContext,
+    progress: &dyn ProgressReporter,
+) -> Vec<(Result<UsageInfo>, Duration)> {
+    progress.on_phase_start(Phase::Usage, packages.len() as u64);
+
+    let lookups = packages
+        .par_iter()
+        .map(|package| {
+            let worker = rayon::current_thread_index().unwrap_or(0);
+            progress.on_item(Phase::Usage, ItemProgress::Started { name: &package.name, worker });
+            let started = Instant::now();
+            let result = aggregate_usage(package, context);
+            progress.on_item(Phase::Usage, ItemProgress::Finished { name: &package.name, worker });
+            (result, started.elapsed())
+        })
+        .collect();
+
+    progress.on_phase_done(Phase::Usage);
+    lookups
+}
 
 /// Aggregate usage information from all available sources
-pub fn aggregate_usage(package: &Package) -> Result<UsageInfo> {
+pub fn aggregate_usage(package: &Package, context: &UsageContext) -> Result<UsageInfo> {
     let mut info = UsageInfo::new();
 
     // For Applications, use Spotlight metadata
-    if package.source == PackageSource::Applications || package.source == PackageSource::HomebrewCask {
+    if uses_spotlight(package) {
         if let Some(ref app_path) = package.binary_path {
-            // Get Spotlight metadata
-            match super::spotlight::get_spotlight_usage(app_path) {
+            // Prefer the prefetched metadata, falling back to a single lookup
+            let spotlight = match context.spotlight.get(app_path) {
+                Some(usage) => Ok((usage.last_used, usage.use_count)),
+                None => super::spotlight::get_spotlight_usage(app_path, context.timeouts.get(CommandKind::Mdls)),
+            };
+
+            // Spotlight loses launch dates when its index is rebuilt, so earlier observations still count
+            let recorded = context.recorded_spotlight
+                .get(&(package.name.clone(), package.source.to_string()))
+                .copied();
+
+            match spotlight {
                 Ok((last_used, use_count)) => {
-                    if let Some(dt) = last_used {
+                    info.checks.push(match (last_used.max(recorded), use_count) {
+                        (Some(dt), Some(count)) => UsageCheck::new("spotlight", true, format!("last opened {}, {} uses", day(dt), count)),
+                        (Some(dt), None) => UsageCheck::new("spotlight", true, format!("last opened {}", day(dt))),
+                        (None, _) => UsageCheck::new("spotlight", false, "no launch date in its metadata"),
+                    });
+                    if let Some(dt) = last_used.max(recorded) {
                         info.sources.push(UsageSource::SpotlightMetadata { last_used: dt });
 
                         // Update aggregated values
@@ -28,39 +193,111 @@ pub fn aggregate_usage(package: &Package) -> Result<UsageInfo> {
                     }
                 }
                 Err(e) => {
-                    eprintln!("Warning: Failed to get Spotlight metadata for {}: {}", package.name, e);
+                    tracing::warn!(package = %package.name, source = %package.source, error = %e, "Failed to get Spotlight metadata");
+                    info.checks.push(match recorded {
+                        Some(dt) => UsageCheck::new("spotlight", true, format!("lookup failed ({}); last opened {} per an earlier scan", e, day(dt))),
+                        None => UsageCheck::new("spotlight", false, format!("lookup failed: {}", e)),
+                    });
+                    if let Some(dt) = recorded {
+                        info.sources.push(UsageSource::SpotlightMetadata { last_used: dt });
+                        info.last_used = Some(dt);
+                    }
                 }
             }
         }
     }
 
+    // Daemons and helpers started by launchd never show up in shell history
+    if let Some(taken_at) = context.processes.taken_at {
+        if context.processes.is_running(package) {
+            info.checks.push(UsageCheck::new("process", true, format!("running on {}", day(taken_at))));
+            info.sources.push(UsageSource::RunningProcess { observed_at: taken_at });
+            info.last_used = Some(taken_at);
+        } else {
+            info.checks.push(UsageCheck::new("process", false, "not running during the scan"));
+        }
+    }
+
+    // Menu bar utilities and services start at login and are never "opened" again
+    if let Some(how) = context.login_items.find(package) {
+        info.checks.push(UsageCheck::new("login", true, format!("launches at login ({})", how)));
+        info.launches_at_login = true;
+    } else if !context.login_items.is_empty() {
+        info.checks.push(UsageCheck::new("login", false, "not started at login"));
+    }
+
+    // Backups and syncs run from cron or launchd never show up in shell history either
+    let mut job_names = command_names(package);
+    job_names.extend(package.binary_path.as_ref().map(|p| p.to_string_lossy().to_lowercase()));
+    if let Some(job) = context.scheduled_jobs.find(&job_names) {
+        let observed_at = context.scheduled_jobs.read_at;
+        info.checks.push(UsageCheck::new("scheduled", true, format!("run by {}: `{}`", job.id, job.command)));
+        info.sources.push(UsageSource::ScheduledJob { job: job.id.clone(), observed_at });
+        if info.last_used.is_none_or(|dt| dt < observed_at) {
+            info.last_used = Some(observed_at);
+        }
+    } else if !context.scheduled_jobs.is_empty() {
+        info.checks.push(UsageCheck::new("scheduled", false, "not run by a crontab entry or LaunchAgent"));
+    }
+
     // For CLI tools and binaries, check shell history
-    if let Some(ref bin_path) = package.binary_path {
-        // Try to find usage in shell history
-        match find_in_shell_history(&package.name, bin_path) {
-            Ok(Some((last_used, count))) => {
-                info.sources.push(UsageSource::ShellHistory {
-                    count,
-                    last_used,
-                });
-
-                // Update aggregated values
-                if info.last_used.is_none() || info.last_used.unwrap() < last_used {
-                    info.last_used = Some(last_used);
-                }
-                info.usage_count += count;
-            }
-            Ok(None) => {}
-            Err(e) => {
-                eprintln!("Warning: Failed to check shell history: {}", e);
-            }
+    let history_files = match context.history_files.len() {
+        1 => "1 history file".to_string(),
+        n => format!("{} history files", n),
+    };
+    let read = if context.history_is_complete { "" } else { " (read since the previous scan)" };
+    if let Some((last_used, count, command)) = shell_usage(package, &context.history) {
+        info.checks.push(UsageCheck::new(
+            "shell",
+            true,
+            format!("{} uses across {}{}, last `{}` on {}", count, history_files, read, command, day(last_used)),
+        ));
+        info.sources.push(UsageSource::ShellHistory {
+            count,
+            last_used,
+            command,
+        });
+
+        // Update aggregated values
+        if info.last_used.is_none() || info.last_used.unwrap() < last_used {
+            info.last_used = Some(last_used);
+        }
+        info.usage_count += count;
+    } else if runs_from_shell(package) {
+        info.checks.push(UsageCheck::new("shell", false, format!("no uses across {}{}", history_files, read)));
+    }
+
+    // Tools only ever run through `npm run` are still in use while a project's scripts call them
+    let script = command_names(package)
+        .iter()
+        .filter_map(|name| context.scripts.lookup(name))
+        .max_by_key(|script| script.modified)
+        .cloned();
+    if let Some(ref script) = script {
+        info.checks.push(UsageCheck::new(
+            "script",
+            true,
+            format!("called by {}, changed {}", script.package_json.display(), day(script.modified)),
+        ));
+    } else if !context.scripts.is_empty() {
+        info.checks.push(UsageCheck::new("script", false, "not called by any package.json script"));
+    }
+    if let Some(script) = script {
+        info.sources.push(UsageSource::ProjectScript {
+            package_json: script.package_json.display().to_string(),
+            modified: script.modified,
+        });
+
+        if info.last_used.is_none_or(|dt| dt < script.modified) {
+            info.last_used = Some(script.modified);
         }
     }
 
-    // Check file access time as fallback
+    // Check file access time as fallback, where the volume records it
     if let Some(ref bin_path) = package.binary_path {
-        match super::atime::get_binary_atime(bin_path) {
+        match super::atime::get_usage_atime(bin_path) {
             Ok(Some(atime)) => {
+                info.checks.push(UsageCheck::new("atime", true, format!("{}, low confidence", day(atime))));
                 info.sources.push(UsageSource::FileAccessTime { atime });
 
                 // Only use atime if we don't have better data
@@ -68,49 +305,289 @@ pub fn aggregate_usage(package: &Package) -> Result<UsageInfo> {
                     info.last_used = Some(atime);
                 }
             }
-            Ok(None) => {}
+            Ok(None) => info.checks.push(UsageCheck::new("atime", false, "not recorded on this volume")),
             Err(e) => {
-                eprintln!("Warning: Failed to get file access time: {}", e);
+                info.checks.push(UsageCheck::new("atime", false, format!("lookup failed: {}", e)));
+                tracing::warn!(package = %package.name, path = %bin_path.display(), error = %e, "Failed to get file access time");
             }
         }
     }
 
+    info.confidence = UsageConfidence::from_sources(&info.sources, info.last_used);
     Ok(info)
 }
 
-/// Find package usage in shell history
-fn find_in_shell_history(
-    package_name: &str,
-    _bin_path: &std::path::Path,
-) -> Result<Option<(DateTime<Utc>, u32)>> {
-    // Parse all shell histories
-    let entries = super::shell_history::parse_all_history()?;
-
-    let mut count = 0;
-    let mut last_used: Option<DateTime<Utc>> = None;
-
-    // Count occurrences and find last usage
-    for entry in entries {
-        if entry.invokes_binary(package_name) {
-            count += 1;
-            if let Some(ts) = entry.timestamp {
-                if last_used.is_none() || last_used.unwrap() < ts {
-                    last_used = Some(ts);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::usage::aliases::Aliases;
+    use crate::usage::shell_history::HistoryEntry;
+    use crate::usage::spotlight::SpotlightUsage;
+    use chrono::{TimeZone, Utc};
+    use std::sync::Mutex;
+
+    /// Records what it is told, as "phase start total", "phase +name", "phase -name", "phase done"
+    #[derive(Default)]
+    struct RecordingProgress(Mutex<Vec<String>>);
+
+    impl ProgressReporter for RecordingProgress {
+        fn on_phase_start(&self, phase: Phase, total: u64) {
+            self.0.lock().unwrap().push(format!("{} start {}", phase, total));
+        }
+
+        fn on_item(&self, phase: Phase, item: ItemProgress) {
+            let line = match item {
+                ItemProgress::Started { name, .. } => format!("{} +{}", phase, name),
+                ItemProgress::Finished { name, .. } => format!("{} -{}", phase, name),
+            };
+            self.0.lock().unwrap().push(line);
+        }
+
+        fn on_phase_done(&self, phase: Phase) {
+            self.0.lock().unwrap().push(format!("{} done", phase));
         }
     }
 
-    if count > 0 && last_used.is_some() {
-        Ok(Some((last_used.unwrap(), count)))
-    } else {
-        Ok(None)
+    #[test]
+    fn test_aggregate_all_usage_reports_progress() {
+        let packages = vec![
+            Package::new("left-pad".to_string(), PackageSource::Npm),
+            Package::new("is-odd".to_string(), PackageSource::Npm),
+        ];
+        let progress = RecordingProgress::default();
+
+        let lookups = aggregate_all_usage(&packages, &UsageContext::default(), &progress);
+        assert_eq!(lookups.len(), 2);
+
+        let events = progress.0.into_inner().unwrap();
+        assert_eq!(events.first().map(String::as_str), Some("usage start 2"));
+        assert_eq!(events.last().map(String::as_str), Some("usage done"));
+        for name in ["left-pad", "is-odd"] {
+            let started = events.iter().position(|e| *e == format!("usage +{}", name)).unwrap();
+            let finished = events.iter().position(|e| *e == format!("usage -{}", name)).unwrap();
+            assert!(started < finished);
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_login_item_check() {
+        let context = UsageContext {
+            login_items: LoginItems::new(vec![std::path::PathBuf::from("/Applications/Stats.app")], Vec::new()),
+            ..Default::default()
+        };
+        let mut package = Package::new("Stats".to_string(), PackageSource::Applications);
+        package.binary_path = Some(std::path::PathBuf::from("/Applications/Stats.app"));
+
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert!(info.launches_at_login);
+        let check = info.checks.iter().find(|c| c.source == "login").unwrap();
+        assert!(check.found);
+        assert_eq!(check.result, "launches at login (login item)");
+
+        package.binary_path = Some(std::path::PathBuf::from("/Applications/Zoom.app"));
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert!(!info.launches_at_login);
+        assert!(!info.checks.iter().find(|c| c.source == "login").unwrap().found);
+    }
+
+    #[test]
+    fn test_scheduled_job_check() {
+        use crate::usage::scheduled_jobs::ScheduledJob;
+        let context = UsageContext {
+            scheduled_jobs: ScheduledJobs::new(vec![ScheduledJob::new(
+                "crontab line 2".to_string(),
+                "/opt/homebrew/bin/restic backup ~/Documents".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let mut restic = Package::new("restic".to_string(), PackageSource::Homebrew);
+        restic.binary_path = Some(std::path::PathBuf::from("/opt/homebrew/bin/restic"));
+
+        let info = aggregate_usage(&restic, &context).unwrap();
+        let check = info.checks.iter().find(|c| c.source == "scheduled").unwrap();
+        assert!(check.found);
+        assert_eq!(check.result, "run by crontab line 2: `/opt/homebrew/bin/restic backup ~/Documents`");
+        assert_eq!(info.last_used, Some(context.scheduled_jobs.read_at));
+        assert_eq!(info.confidence, UsageConfidence::High);
+        assert!(matches!(&info.sources[0], UsageSource::ScheduledJob { job, .. } if job == "crontab line 2"));
+
+        let rclone = Package::new("rclone".to_string(), PackageSource::Homebrew);
+        let info = aggregate_usage(&rclone, &context).unwrap();
+        assert!(!info.checks.iter().find(|c| c.source == "scheduled").unwrap().found);
+        assert_eq!(info.last_used, None);
+    }
+
+    #[test]
+    fn test_shell_usage_through_alias_and_binary_name() {
+        let home = tempfile::TempDir::new().unwrap();
+        std::fs::write(home.path().join(".zshrc"), "alias vim=nvim\n").unwrap();
+        let aliases = Aliases::load_from(home.path());
+
+        let entries = vec![HistoryEntry {
+            command: "vim notes.md".to_string(),
+            timestamp: Utc.timestamp_opt(1_700_000_000, 0).single(),
+        }];
+        let context = UsageContext {
+            history: HistoryIndex::with_aliases(entries, &aliases),
+            ..Default::default()
+        };
+
+        let mut package = Package::new("neovim".to_string(), PackageSource::Homebrew);
+        package.binary_path = Some(home.path().join("bin/nvim"));
+
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert_eq!(info.usage_count, 1);
+        assert!(info.last_used.is_some());
+    }
+
+    #[test]
+    fn test_scoped_npm_package_run_through_npx_or_its_binary() {
+        let at = |secs| Utc.timestamp_opt(secs, 0).single();
+        let entries = vec![
+            HistoryEntry { command: "npx @angular/cli@17 new shop".to_string(), timestamp: at(1_700_000_000) },
+            HistoryEntry { command: "pnpm dlx @angular/cli version".to_string(), timestamp: at(1_700_000_100) },
+            HistoryEntry { command: "ng serve".to_string(), timestamp: at(1_700_000_200) },
+            HistoryEntry { command: "ng test".to_string(), timestamp: at(1_700_000_300) },
+            HistoryEntry { command: "ng build".to_string(), timestamp: at(1_700_000_400) },
+        ];
+        let context = UsageContext { history: HistoryIndex::new(entries), ..Default::default() };
+
+        // Installed without a known binary: only the npx-style runs match
+        let package = Package::new("@angular/cli".to_string(), PackageSource::Npm);
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert_eq!(info.usage_count, 2);
+        assert_eq!(info.last_used, at(1_700_000_100));
+
+        // With its `ng` binary known, runs by either name count
+        let mut package = package;
+        package.binary_path = Some(std::path::PathBuf::from("/opt/homebrew/bin/ng"));
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert_eq!(info.usage_count, 5);
+        assert_eq!(info.last_used, at(1_700_000_400));
+    }
+
+    #[test]
+    fn test_shell_usage_matches_every_installed_binary() {
+        let at = |secs| Utc.timestamp_opt(secs, 0).single();
+        let entries = vec![
+            HistoryEntry { command: "magick in.png out.jpg".to_string(), timestamp: at(1_700_000_000) },
+            HistoryEntry { command: "convert a.png b.gif".to_string(), timestamp: at(1_700_000_100) },
+            HistoryEntry { command: "python3.12 -m venv .venv".to_string(), timestamp: at(1_700_000_200) },
+            HistoryEntry { command: "convert c.png d.png && magick identify d.png".to_string(), timestamp: at(1_699_999_000) },
+        ];
+        let history = HistoryIndex::new(entries);
+
+        let mut imagemagick = Package::new("imagemagick".to_string(), PackageSource::Homebrew);
+        imagemagick.binaries = vec!["convert".to_string(), "magick".to_string(), "mogrify".to_string()];
+        let (last_used, count, command) = shell_usage(&imagemagick, &history).unwrap();
+        // The entry running both convert and magick is one use
+        assert_eq!(count, 3);
+        assert_eq!(Some(last_used), at(1_700_000_100));
+        assert_eq!(command, "convert a.png b.gif");
+
+        let mut python = Package::new("python@3.12".to_string(), PackageSource::Homebrew);
+        assert!(shell_usage(&python, &history).is_none());
+        python.binaries = vec!["pip3.12".to_string(), "python3.12".to_string()];
+        assert_eq!(shell_usage(&python, &history).unwrap().1, 1);
+    }
+
+    #[test]
+    fn test_spotlight_prefers_newest_of_recorded_and_fresh() {
+        let app_path = std::path::PathBuf::from("/Applications/Figma.app");
+        let older = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let newer = Utc.timestamp_opt(1_700_100_000, 0).unwrap();
+
+        let mut package = Package::new("Figma".to_string(), PackageSource::Applications);
+        package.binary_path = Some(app_path.clone());
+        let key = ("Figma".to_string(), "applications".to_string());
+
+        let with = |fresh: DateTime<Utc>, recorded: DateTime<Utc>| UsageContext {
+            spotlight: SpotlightCache::from_entries([(app_path.clone(), SpotlightUsage { last_used: Some(fresh), use_count: Some(3) })]),
+            recorded_spotlight: HashMap::from([(key.clone(), recorded)]),
+            ..Default::default()
+        };
+
+        assert_eq!(aggregate_usage(&package, &with(newer, older)).unwrap().last_used, Some(newer));
+        // A reindexed Spotlight reporting an older launch doesn't roll usage back
+        let info = aggregate_usage(&package, &with(older, newer)).unwrap();
+        assert_eq!(info.last_used, Some(newer));
+        assert_eq!(info.usage_count, 3);
+    }
+
+    #[test]
+    fn test_usage_context_is_shared_across_threads() {
+        // `scan` aggregates usage from a rayon pool that borrows the context
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<UsageContext>();
+    }
+
+    #[test]
+    fn test_running_process_counts_as_used_now() {
+        let observed_at = Utc::now();
+        let context = UsageContext {
+            processes: ProcessSnapshot::from_commands(["/opt/homebrew/bin/syncthing"], observed_at),
+            ..Default::default()
+        };
+
+        let mut package = Package::new("syncthing".to_string(), PackageSource::Homebrew);
+        package.binary_path = Some(std::path::PathBuf::from("/opt/homebrew/bin/syncthing"));
+
+        let info = aggregate_usage(&package, &context).unwrap();
+        assert_eq!(info.last_used, Some(observed_at));
+        assert!(matches!(info.sources[0], UsageSource::RunningProcess { .. }));
+        assert_eq!(info.confidence, UsageConfidence::High);
+    }
+
+    #[test]
+    fn test_checks_record_every_source_consulted() {
+        let file = |path: &str| HistoryFileState { path: path.into(), inode: 1, offset: 0, last_entry_at: None };
+        let context = UsageContext {
+            history: HistoryIndex::new(vec![HistoryEntry {
+                command: "jq . data.json".to_string(),
+                timestamp: Utc.timestamp_opt(1_700_000_000, 0).single(),
+            }]),
+            history_files: vec![file("/home/a/.zsh_history"), file("/home/a/.bash_history")],
+            history_is_complete: true,
+            processes: ProcessSnapshot::from_commands(["/usr/sbin/syslogd"], Utc::now()),
+            ..Default::default()
+        };
+        let sources = |info: &UsageInfo| info.checks.iter().map(|c| (c.source.clone(), c.found)).collect::<Vec<_>>();
+
+        let mut jq = Package::new("jq".to_string(), PackageSource::Homebrew);
+        jq.binary_path = Some(std::path::PathBuf::from("/nonexistent/bin/jq"));
+        let info = aggregate_usage(&jq, &context).unwrap();
+        assert_eq!(&sources(&info)[..2], [("process".to_string(), false), ("shell".to_string(), true)]);
+        assert_eq!(info.checks[1].result, "1 uses across 2 history files, last `jq . data.json` on 2023-11-14");
+        assert_eq!(info.checks[1].label(), "Shell history");
+
+        let mut yq = Package::new("yq".to_string(), PackageSource::Homebrew);
+        yq.binary_path = Some(std::path::PathBuf::from("/nonexistent/bin/yq"));
+        let info = aggregate_usage(&yq, &UsageContext { history_is_complete: false, ..context }).unwrap();
+        assert_eq!(info.checks[1].result, "no uses across 2 history files (read since the previous scan)");
+        // Neither Spotlight nor project scripts apply
+        assert!(!sources(&info).iter().any(|(source, _)| source == "spotlight" || source == "script"));
+    }
+
+    #[test]
+    fn test_accumulate_adds_shell_uses_to_stored_totals() {
+        let stored_last_used = Utc.timestamp_opt(1_700_000_000, 0).single();
+        let newer = Utc.timestamp_opt(1_700_000_500, 0).unwrap();
+
+        let mut info = UsageInfo::new();
+        info.usage_count = 3;
+        info.last_used = Some(newer);
+        info.sources.push(UsageSource::ShellHistory { count: 3, last_used: newer, command: "jq .".to_string() });
+        assert_eq!(info.accumulate(stored_last_used, 10), (Some(newer), 13));
+
+        // Nothing new in history keeps the stored values
+        let quiet = UsageInfo::new();
+        assert_eq!(quiet.accumulate(stored_last_used, 10), (stored_last_used, 10));
+
+        // Spotlight's count is already a total
+        let mut app = UsageInfo::new();
+        app.usage_count = 42;
+        assert_eq!(app.accumulate(None, 40), (None, 42));
+    }
 
     #[test]
     #[ignore] // Run manually
@@ -118,7 +595,7 @@ mod tests {
         let mut package = Package::new("git".to_string(), PackageSource::Homebrew);
         package.binary_path = Some(std::path::PathBuf::from("/usr/bin/git"));
 
-        let info = aggregate_usage(&package).unwrap();
+        let info = aggregate_usage(&package, &UsageContext::for_packages(std::slice::from_ref(&package), &Config::default(), &[])).unwrap();
         println!("Last used: {:?}", info.last_used);
         println!("Usage count: {}", info.usage_count);
         println!("Sources: {:?}", info.sources);
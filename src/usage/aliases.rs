@@ -0,0 +1,223 @@
+// Shell and git alias extraction, so `k get pods` counts as a use of kubectl
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How many alias-to-alias hops to follow (`vi` -> `vim` -> `nvim`)
+const MAX_ALIAS_DEPTH: usize = 5;
+
+lazy_static! {
+    // `alias ll='ls -la'`, `alias -g G='| grep'`, fish's `alias k kubectl`
+    static ref SHELL_ALIAS_RE: Regex =
+        Regex::new(r#"^\s*alias\s+(?:-\S+\s+)*([^\s='"]+)(?:=|\s+)(.+)$"#).unwrap();
+
+    // Functions saved by fish's `alias --save`: `function k --wraps=kubectl ...`
+    static ref FISH_WRAPS_RE: Regex =
+        Regex::new(r#"^\s*function\s+(\S+)\s.*--wraps[= ]['"]?([^'"\s]+)"#).unwrap();
+
+    // `[alias]` entries in .gitconfig: `sync = !gh repo sync`
+    static ref GIT_ALIAS_RE: Regex = Regex::new(r#"^\s*([\w-]+)\s*=\s*"?!\s*(.+?)"?\s*$"#).unwrap();
+}
+
+/// Simple static aliases, lowercased, mapped to the binary they run
+#[derive(Debug, Clone, Default)]
+pub struct Aliases {
+    shell: HashMap<String, String>,
+    /// `git <alias>` entries that shell out to another binary
+    git: HashMap<String, String>,
+}
+
+impl Aliases {
+    /// Read aliases from the current user's shell and git configuration
+    pub fn load() -> Self {
+        match dirs::home_dir() {
+            Some(home) => Self::load_from(&home),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load_from(home: &Path) -> Self {
+        let mut aliases = Self::default();
+
+        for rc in [".zshrc", ".zsh_aliases", ".bashrc", ".bash_profile", ".bash_aliases", ".config/fish/config.fish"] {
+            if let Ok(contents) = std::fs::read_to_string(home.join(rc)) {
+                aliases.parse_shell(&contents);
+            }
+        }
+
+        if let Ok(entries) = std::fs::read_dir(home.join(".config/fish/functions")) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                if entry.path().extension().is_some_and(|e| e == "fish") {
+                    if let Ok(contents) = std::fs::read_to_string(entry.path()) {
+                        aliases.parse_fish_functions(&contents);
+                    }
+                }
+            }
+        }
+
+        for gitconfig in [".gitconfig", ".config/git/config"] {
+            if let Ok(contents) = std::fs::read_to_string(home.join(gitconfig)) {
+                aliases.parse_gitconfig(&contents);
+            }
+        }
+
+        aliases
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shell.is_empty() && self.git.is_empty()
+    }
+
+    fn parse_shell(&mut self, contents: &str) {
+        for line in contents.lines() {
+            if let Some(caps) = SHELL_ALIAS_RE.captures(line) {
+                if let Some(binary) = command_binary(&caps[2]) {
+                    self.shell.insert(caps[1].to_lowercase(), binary);
+                }
+            }
+        }
+    }
+
+    fn parse_fish_functions(&mut self, contents: &str) {
+        for line in contents.lines() {
+            if let Some(caps) = FISH_WRAPS_RE.captures(line) {
+                if let Some(binary) = command_binary(&caps[2]) {
+                    self.shell.insert(caps[1].to_lowercase(), binary);
+                }
+            }
+        }
+    }
+
+    fn parse_gitconfig(&mut self, contents: &str) {
+        let mut in_alias_section = false;
+
+        for line in contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') {
+                in_alias_section = trimmed.eq_ignore_ascii_case("[alias]");
+                continue;
+            }
+
+            if !in_alias_section {
+                continue;
+            }
+
+            if let Some(caps) = GIT_ALIAS_RE.captures(line) {
+                // `!git ...` is still git; shell functions (`!f() { ... }; f`) are skipped
+                if let Some(binary) = command_binary(&caps[2]).filter(|b| b != "git") {
+                    self.git.insert(caps[1].to_lowercase(), binary);
+                }
+            }
+        }
+    }
+
+    /// Binaries run by the command-position words of a lowercased command line
+    pub fn expand(&self, cmd: &str) -> Vec<&str> {
+        let mut binaries = Vec::new();
+        if self.is_empty() {
+            return binaries;
+        }
+
+        let words: Vec<&str> = cmd.split_whitespace().map(|w| w.trim_start_matches("sudo")).collect();
+
+        for word in &words {
+            let mut name = *word;
+            for _ in 0..MAX_ALIAS_DEPTH {
+                match self.shell.get(name) {
+                    Some(binary) if binary != name && !binaries.contains(&binary.as_str()) => {
+                        binaries.push(binary.as_str());
+                        name = binary;
+                    }
+                    _ => break,
+                }
+            }
+        }
+
+        for pair in words.windows(2) {
+            if pair[0] == "git" {
+                if let Some(binary) = self.git.get(pair[1]) {
+                    binaries.push(binary.as_str());
+                }
+            }
+        }
+
+        binaries
+    }
+}
+
+/// The binary an alias value runs, lowercased: the first word after quotes, env
+/// assignments, and wrappers like `sudo`, reduced to its file name
+fn command_binary(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches(|c| c == '\'' || c == '"');
+
+    value
+        .split_whitespace()
+        .find(|w| !w.contains('=') && !matches!(*w, "sudo" | "command" | "exec" | "noglob" | "nocorrect" | "env"))
+        .filter(|w| w.chars().all(|c| c.is_alphanumeric() || "-_./@+~".contains(c)))
+        .and_then(|w| Path::new(w).file_name())
+        .map(|name| name.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_aliases_from_dotfiles() {
+        let home = TempDir::new().unwrap();
+        let root = home.path();
+        std::fs::create_dir_all(root.join(".config/fish/functions")).unwrap();
+
+        std::fs::write(root.join(".zshrc"), "\
+export PATH=$HOME/bin:$PATH
+alias k=kubectl
+alias vim='nvim'
+alias vi=vim
+alias -g G='| grep'
+alias ll=\"ls -la\"
+alias tf='AWS_PROFILE=dev /opt/homebrew/bin/terraform'
+greet() { echo hi; }
+").unwrap();
+        std::fs::write(root.join(".bash_aliases"), "alias dc='docker compose'\n").unwrap();
+        std::fs::write(root.join(".config/fish/config.fish"), "alias lg lazygit\n").unwrap();
+        std::fs::write(
+            root.join(".config/fish/functions/rgf.fish"),
+            "function rgf --wraps='rg --files' --description 'alias rgf=rg --files'\n  rg --files $argv\nend\n",
+        ).unwrap();
+        std::fs::write(root.join(".gitconfig"), "\
+[user]
+    name = Someone
+[alias]
+    co = checkout
+    sync = !gh repo sync
+    cleanup = \"!git branch --merged | xargs git branch -d\"
+    fn = \"!f() { echo $1; }; f\"
+").unwrap();
+
+        let aliases = Aliases::load_from(root);
+        assert_eq!(aliases.shell["k"], "kubectl");
+        assert_eq!(aliases.shell["vim"], "nvim");
+        assert_eq!(aliases.shell["ll"], "ls");
+        assert_eq!(aliases.shell["tf"], "terraform");
+        assert_eq!(aliases.shell["dc"], "docker");
+        assert_eq!(aliases.shell["lg"], "lazygit");
+        assert_eq!(aliases.shell["rgf"], "rg");
+        assert!(!aliases.shell.contains_key("g"));
+        assert_eq!(aliases.git.len(), 1);
+        assert_eq!(aliases.git["sync"], "gh");
+    }
+
+    #[test]
+    fn test_expand_follows_chains_and_git_aliases() {
+        let mut aliases = Aliases::default();
+        aliases.parse_shell("alias vi=vim\nalias vim=nvim\nalias k=kubectl\n");
+        aliases.parse_gitconfig("[alias]\n  sync = !gh repo sync\n");
+
+        assert_eq!(aliases.expand("vi notes.md"), vec!["vim", "nvim"]);
+        assert_eq!(aliases.expand("cat pod.yaml | sudo k apply -f -"), vec!["kubectl"]);
+        assert_eq!(aliases.expand("git sync"), vec!["gh"]);
+        assert!(aliases.expand("git status").is_empty());
+    }
+}
@@ -1,9 +1,119 @@
 // File access time detection
-use anyhow::Result;
+use crate::error::Result;
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use std::fs;
 use std::path::Path;
 
+/// How a volume maintains file access times
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AtimePolicy {
+    /// Every read updates atime
+    Updated,
+    /// `relatime`: only updated when older than the last change, or about once a day
+    Relative,
+    /// `noatime`: never updated, so atime is whatever it was when the file was written
+    Disabled,
+}
+
+impl AtimePolicy {
+    /// From the mount flags `statfs` reports
+    #[cfg(target_os = "macos")]
+    fn from_flags(flags: u32) -> Self {
+        if flags & libc::MNT_NOATIME as u32 != 0 {
+            AtimePolicy::Disabled
+        } else {
+            AtimePolicy::Updated
+        }
+    }
+
+    /// From the mount flags `statvfs` reports
+    #[cfg(target_os = "linux")]
+    fn from_flags(flags: libc::c_ulong) -> Self {
+        if flags & libc::ST_NOATIME != 0 {
+            AtimePolicy::Disabled
+        } else if flags & libc::ST_RELATIME != 0 {
+            AtimePolicy::Relative
+        } else {
+            AtimePolicy::Updated
+        }
+    }
+
+    /// What the policy means for usage, as `doctor` shows it
+    pub fn describe(self) -> &'static str {
+        match self {
+            AtimePolicy::Updated => "updated on every read",
+            AtimePolicy::Relative => "updated about once a day (relatime)",
+            AtimePolicy::Disabled => "never updated (noatime), so access times are ignored",
+        }
+    }
+}
+
+/// Policy of the volume holding `path`, from its mount flags; None when `path` doesn't exist
+/// or its volume can't be queried
+#[cfg(target_os = "macos")]
+pub fn policy_for(path: &Path) -> Option<AtimePolicy> {
+    let path = c_path(path)?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read once statfs has filled it in
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    Some(AtimePolicy::from_flags(stat.f_flags))
+}
+
+/// Policy of the volume holding `path`, from its mount flags; None when `path` doesn't exist
+/// or its volume can't be queried. Linux's `statfs` doesn't report the flags; `statvfs` does.
+#[cfg(target_os = "linux")]
+pub fn policy_for(path: &Path) -> Option<AtimePolicy> {
+    let path = c_path(path)?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is only read once statvfs has filled it in
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    Some(AtimePolicy::from_flags(stat.f_flag))
+}
+
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn c_path(path: &Path) -> Option<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(path.as_os_str().as_bytes()).ok()
+}
+
+/// Policy of the volume holding `path`; unknown on this platform
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub fn policy_for(_path: &Path) -> Option<AtimePolicy> {
+    None
+}
+
+/// Last access time of `binary_path`, if it is evidence the binary was actually used.
+/// Returns `None` on volumes mounted `noatime`, and when atime equals mtime exactly
+/// (never read since it was written, e.g. at install)
+pub fn get_usage_atime(binary_path: &Path) -> Result<Option<DateTime<Utc>>> {
+    if policy_for(binary_path) == Some(AtimePolicy::Disabled) {
+        return Ok(None);
+    }
+
+    usage_atime(binary_path)
+}
+
+fn usage_atime(binary_path: &Path) -> Result<Option<DateTime<Utc>>> {
+    if !binary_path.exists() {
+        return Ok(None);
+    }
+
+    let metadata = fs::metadata(binary_path)?;
+    match (metadata.accessed(), metadata.modified()) {
+        (Ok(accessed), Ok(modified)) if accessed == modified => Ok(None),
+        (Ok(accessed), _) => Ok(Some(accessed.into())),
+        (Err(_), _) => Ok(None),
+    }
+}
+
 /// Get the file access time (atime) for a binary
 /// Note: On macOS, atime may not be reliable as it can be disabled with noatime mount option
 pub fn get_binary_atime(binary_path: &Path) -> Result<Option<DateTime<Utc>>> {
@@ -48,6 +158,39 @@ pub fn get_binary_mtime(binary_path: &Path) -> Result<Option<DateTime<Utc>>> {
 mod tests {
     use super::*;
     use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_policy_from_mount_flags() {
+        let dir = tempfile::TempDir::new().unwrap();
+        assert!(policy_for(dir.path()).is_some());
+        assert_eq!(policy_for(&dir.path().join("missing")), None);
+
+        assert_eq!(AtimePolicy::from_flags(0), AtimePolicy::Updated);
+        #[cfg(target_os = "macos")]
+        assert_eq!(AtimePolicy::from_flags((libc::MNT_NOATIME | libc::MNT_LOCAL) as u32), AtimePolicy::Disabled);
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(AtimePolicy::from_flags(libc::ST_NOATIME | libc::ST_NODEV), AtimePolicy::Disabled);
+            assert_eq!(AtimePolicy::from_flags(libc::ST_RELATIME), AtimePolicy::Relative);
+        }
+    }
+
+    #[test]
+    fn test_atime_equal_to_mtime_is_not_usage() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("tool");
+        fs::write(&path, b"").unwrap();
+
+        let written = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_times(fs::FileTimes::new().set_accessed(written).set_modified(written)).unwrap();
+        assert_eq!(usage_atime(&path).unwrap(), None);
+
+        let read = written + Duration::from_secs(3600);
+        file.set_times(fs::FileTimes::new().set_accessed(read).set_modified(written)).unwrap();
+        assert_eq!(usage_atime(&path).unwrap(), Some(DateTime::<Utc>::from(read)));
+    }
 
     #[test]
     #[ignore] // Run manually
@@ -0,0 +1,211 @@
+// Login items and LaunchAgents: apps started at login count as in use even though they
+// never show up in Spotlight launch dates or shell history
+use crate::scanner::{Package, PackageSource};
+use crate::utils::process::run_command_with_timeout;
+use crate::error::Result;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Directories of LaunchAgent plists, relative to the home directory when not absolute
+const LAUNCH_AGENT_DIRS: &[&str] = &["Library/LaunchAgents", "/Library/LaunchAgents"];
+
+/// A LaunchAgent plist, reduced to what identifies the app or command it starts
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LaunchAgent {
+    pub label: String,
+    /// `Program`, or else the first of `ProgramArguments`
+    pub program: Option<PathBuf>,
+    /// `ProgramArguments`, the first of which is the program's name as it sees it
+    pub arguments: Vec<String>,
+    /// `AssociatedBundleIdentifiers`, which apps registering agents with SMAppService set
+    pub bundle_ids: Vec<String>,
+    /// The plist it was read from
+    pub path: PathBuf,
+}
+
+/// The enabled agents in the user's and the system's LaunchAgents directories; each plist is
+/// read with `plutil`, given `timeout`
+pub fn launch_agents(timeout: Duration) -> Vec<LaunchAgent> {
+    let home = dirs::home_dir();
+    LAUNCH_AGENT_DIRS
+        .iter()
+        .filter_map(|dir| match Path::new(dir) {
+            dir if dir.is_absolute() => Some(dir.to_path_buf()),
+            dir => home.as_ref().map(|home| home.join(dir)),
+        })
+        .flat_map(|dir| read_launch_agents(&dir, timeout).unwrap_or_default())
+        .collect()
+}
+
+/// Everything set to start at login
+#[derive(Debug, Clone, Default)]
+pub struct LoginItems {
+    /// App bundles in the user's login items
+    apps: Vec<PathBuf>,
+    agents: Vec<LaunchAgent>,
+}
+
+impl LoginItems {
+    pub fn new(apps: Vec<PathBuf>, agents: Vec<LaunchAgent>) -> Self {
+        Self { apps, agents }
+    }
+
+    /// Read the login items (through System Events), next to the `agents` read from the
+    /// LaunchAgent plists; either may be unavailable, which only means fewer apps are recognized
+    pub fn load(agents: Vec<LaunchAgent>, timeout: Duration) -> Self {
+        let script = [
+            "-e", "set AppleScript's text item delimiters to linefeed",
+            "-e", "tell application \"System Events\" to set paths to the path of every login item",
+            "-e", "paths as text",
+        ];
+        let apps = run_command_with_timeout("osascript", &script, timeout)
+            .map(|output| parse_login_items(&output))
+            .unwrap_or_else(|e| {
+                tracing::warn!(command = "osascript", error = %e, "Failed to read login items");
+                Vec::new()
+            });
+
+        Self { apps, agents }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty() && self.agents.is_empty()
+    }
+
+    /// How `package` starts at login, e.g. "login item" or "LaunchAgent com.example.helper"
+    pub fn find(&self, package: &Package) -> Option<String> {
+        let path = package.binary_path.as_deref();
+        let is_app = path.is_some_and(|p| p.extension().is_some_and(|e| e == "app"));
+
+        if is_app && self.apps.iter().any(|app| Some(app.as_path()) == path) {
+            return Some("login item".to_string());
+        }
+
+        self.agents
+            .iter()
+            .find(|agent| {
+                let runs_package = match (path, &agent.program) {
+                    // Apps usually start a helper from inside their own bundle
+                    (Some(path), Some(program)) if is_app => program.starts_with(path),
+                    (Some(path), Some(program)) => program == path,
+                    _ => false,
+                };
+                let names_package = package.bundle_id.as_ref().is_some_and(|id| {
+                    agent.bundle_ids.contains(id) || agent.label == *id || agent.label.starts_with(&format!("{}.", id))
+                });
+                // `brew services start` writes homebrew.mxcl.<formula>.plist
+                let brew_service = package.source == PackageSource::Homebrew && agent.label == format!("homebrew.mxcl.{}", package.name);
+                runs_package || names_package || brew_service
+            })
+            .map(|agent| format!("LaunchAgent {}", agent.label))
+    }
+}
+
+/// Paths printed by `osascript` for the login items, one per line; a path may itself hold ", "
+fn parse_login_items(output: &str) -> Vec<PathBuf> {
+    output
+        .lines()
+        .map(|path| path.trim_end_matches('/'))
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn read_launch_agents(dir: &Path, timeout: Duration) -> Result<Vec<LaunchAgent>> {
+    let mut agents = Vec::new();
+    for entry in fs::read_dir(dir)?.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != "plist") {
+            continue;
+        }
+        // plutil reads both XML and binary plists
+        match run_command_with_timeout("plutil", &["-convert", "json", "-o", "-", &path.to_string_lossy()], timeout) {
+            Ok(json) => agents.extend(parse_launch_agent(&json).map(|agent| LaunchAgent { path: path.clone(), ..agent })),
+            Err(e) => tracing::warn!(command = "plutil", path = %path.display(), error = %e, "Failed to read launch agent"),
+        }
+    }
+    Ok(agents)
+}
+
+/// The agent described by a plist converted to JSON; disabled agents don't start anything
+fn parse_launch_agent(json: &str) -> Option<LaunchAgent> {
+    let plist: Value = serde_json::from_str(json).ok()?;
+    if plist["Disabled"].as_bool() == Some(true) {
+        return None;
+    }
+
+    let arguments: Vec<String> = match &plist["ProgramArguments"] {
+        Value::Array(args) => args.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+    let program = plist["Program"].as_str().or(arguments.first().map(String::as_str)).map(PathBuf::from);
+    let bundle_ids = match &plist["AssociatedBundleIdentifiers"] {
+        Value::String(id) => vec![id.clone()],
+        Value::Array(ids) => ids.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+
+    Some(LaunchAgent { label: plist["Label"].as_str()?.to_string(), program, arguments, bundle_ids, path: PathBuf::new() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, bundle_id: &str) -> Package {
+        let mut package = Package::new(name.to_string(), PackageSource::Applications);
+        package.binary_path = Some(PathBuf::from(format!("/Applications/{}.app", name)));
+        package.bundle_id = Some(bundle_id.to_string());
+        package
+    }
+
+    #[test]
+    fn test_parse_login_items_and_agents() {
+        assert_eq!(
+            parse_login_items("/Applications/Ice.app\n/Applications/Stats.app/\n/Applications/Tools, Misc/Rectangle.app\n"),
+            [
+                PathBuf::from("/Applications/Ice.app"),
+                PathBuf::from("/Applications/Stats.app"),
+                PathBuf::from("/Applications/Tools, Misc/Rectangle.app"),
+            ]
+        );
+        assert!(parse_login_items("\n").is_empty());
+
+        let agent = parse_launch_agent(
+            r#"{"Label":"com.example.helper","ProgramArguments":["/Applications/Example.app/Contents/MacOS/helper","--quiet"],"AssociatedBundleIdentifiers":"com.example.app"}"#,
+        )
+        .unwrap();
+        assert_eq!(agent.program, Some(PathBuf::from("/Applications/Example.app/Contents/MacOS/helper")));
+        assert_eq!(agent.arguments, ["/Applications/Example.app/Contents/MacOS/helper", "--quiet"]);
+        assert_eq!(agent.bundle_ids, ["com.example.app"]);
+
+        assert!(parse_launch_agent(r#"{"Label":"com.example.off","Program":"/bin/true","Disabled":true}"#).is_none());
+        assert!(parse_launch_agent("not json").is_none());
+    }
+
+    #[test]
+    fn test_find_matches_apps_agents_and_services() {
+        let items = LoginItems::new(
+            vec![PathBuf::from("/Applications/Rectangle.app")],
+            vec![
+                LaunchAgent {
+                    label: "eu.exelban.Stats.SMCHelper".to_string(),
+                    program: Some(PathBuf::from("/Applications/Stats.app/Contents/Library/LoginItems/helper")),
+                    ..Default::default()
+                },
+                LaunchAgent { label: "com.jordanbaird.Ice.agent".to_string(), ..Default::default() },
+                LaunchAgent { label: "homebrew.mxcl.postgresql@16".to_string(), ..Default::default() },
+            ],
+        );
+
+        assert_eq!(items.find(&app("Rectangle", "com.knollsoft.Rectangle")).as_deref(), Some("login item"));
+        assert_eq!(items.find(&app("Stats", "eu.exelban.Stats")).as_deref(), Some("LaunchAgent eu.exelban.Stats.SMCHelper"));
+        assert_eq!(items.find(&app("Ice", "com.jordanbaird.Ice")).as_deref(), Some("LaunchAgent com.jordanbaird.Ice.agent"));
+        assert_eq!(items.find(&app("Zoom", "us.zoom.xos")), None);
+
+        let postgres = Package::new("postgresql@16".to_string(), PackageSource::Homebrew);
+        assert_eq!(items.find(&postgres).as_deref(), Some("LaunchAgent homebrew.mxcl.postgresql@16"));
+    }
+}
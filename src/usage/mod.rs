@@ -2,20 +2,213 @@
 pub mod shell_history;
 pub mod spotlight;
 pub mod atime;
+#[cfg(feature = "usage")]
 pub mod aggregator;
+pub mod aliases;
+pub mod processes;
+pub mod project_scripts;
+pub mod login_items;
+pub mod scheduled_jobs;
 
 // Re-export the main aggregator function for convenience
-pub use aggregator::aggregate_usage;
+#[cfg(feature = "usage")]
+pub use aggregator::{aggregate_all_usage, aggregate_usage, UsageContext};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UsageSource {
-    ShellHistory { count: u32, last_used: DateTime<Utc> },
+    /// `command` is the most recent matching command line
+    ShellHistory { count: u32, last_used: DateTime<Utc>, command: String },
     SpotlightMetadata { last_used: DateTime<Utc> },
     FileAccessTime { atime: DateTime<Utc> },
-    Manual,
+    /// Seen running when the scan took its process snapshot
+    RunningProcess { observed_at: DateTime<Utc> },
+    /// Run by a package.json script; dated by when that file last changed
+    ProjectScript { package_json: String, modified: DateTime<Utc> },
+    /// Run by a crontab entry or LaunchAgent, e.g. `crontab line 4`; dated by the scan that
+    /// found it scheduled
+    ScheduledJob { job: String, observed_at: DateTime<Utc> },
+    /// Recorded by hand with `macsweep mark`
+    Manual(ManualMark),
+}
+
+impl UsageSource {
+    /// How strongly this observation shows the package is really used
+    pub fn confidence(&self) -> UsageConfidence {
+        match self {
+            UsageSource::ShellHistory { .. }
+            | UsageSource::SpotlightMetadata { .. }
+            | UsageSource::RunningProcess { .. }
+            | UsageSource::ScheduledJob { .. } => UsageConfidence::High,
+            UsageSource::Manual(mark) if mark.used => UsageConfidence::High,
+            UsageSource::Manual(_) => UsageConfidence::None,
+            UsageSource::ProjectScript { .. } => UsageConfidence::Medium,
+            UsageSource::FileAccessTime { .. } => UsageConfidence::Low,
+        }
+    }
+
+    /// Event type stored in the `usage_events` table
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            UsageSource::ShellHistory { .. } => "shell",
+            UsageSource::SpotlightMetadata { .. } => "spotlight",
+            UsageSource::FileAccessTime { .. } => "atime",
+            UsageSource::RunningProcess { .. } => "process",
+            UsageSource::ProjectScript { .. } => "script",
+            UsageSource::ScheduledJob { .. } => "scheduled",
+            UsageSource::Manual(_) => "manual",
+        }
+    }
+
+    /// When the observed use happened, or when a package was marked never used
+    pub fn event_date(&self) -> Option<DateTime<Utc>> {
+        match self {
+            UsageSource::ShellHistory { last_used, .. } => Some(*last_used),
+            UsageSource::SpotlightMetadata { last_used } => Some(*last_used),
+            UsageSource::FileAccessTime { atime } => Some(*atime),
+            UsageSource::RunningProcess { observed_at } => Some(*observed_at),
+            UsageSource::ProjectScript { modified, .. } => Some(*modified),
+            UsageSource::ScheduledJob { observed_at, .. } => Some(*observed_at),
+            UsageSource::Manual(mark) => Some(mark.date),
+        }
+    }
+
+    /// Extra detail stored with the event
+    pub fn details(&self) -> Option<&str> {
+        match self {
+            UsageSource::ShellHistory { command, .. } => Some(command),
+            UsageSource::ProjectScript { package_json, .. } => Some(package_json),
+            UsageSource::ScheduledJob { job, .. } => Some(job),
+            UsageSource::Manual(mark) => Some(mark.details()),
+            _ => None,
+        }
+    }
+}
+
+/// A use (or the absence of any) that macsweep can't observe, recorded with `macsweep mark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManualMark {
+    /// Marked as used rather than as never used
+    pub used: bool,
+    /// When it was used, or when it was marked never used
+    pub date: DateTime<Utc>,
+}
+
+impl ManualMark {
+    /// `details` of the stored usage event
+    pub fn details(&self) -> &'static str {
+        if self.used { "used" } else { "never-used" }
+    }
+
+    pub fn from_details(details: &str, date: DateTime<Utc>) -> Self {
+        Self { used: details != "never-used", date }
+    }
+
+    /// Overlay the mark on automatically gathered usage, so rescans don't undo it: a use
+    /// counts unless there is newer evidence, and a never-used mark discards everything
+    /// observed before it
+    pub fn apply(&self, package: &mut crate::scanner::Package) {
+        if self.used {
+            if package.last_used.is_none_or(|last_used| last_used <= self.date) {
+                package.last_used = Some(self.date);
+                package.usage_confidence = UsageConfidence::High;
+            }
+            package.usage_count = package.usage_count.max(1);
+        } else if package.last_used.is_none_or(|last_used| last_used <= self.date) {
+            package.last_used = None;
+            package.usage_count = 0;
+            package.usage_confidence = UsageConfidence::None;
+        }
+    }
+
+    /// Whether `package`'s last-used value is this mark's doing
+    pub fn explains(&self, package: &crate::scanner::Package) -> bool {
+        if self.used {
+            package.last_used == Some(self.date)
+        } else {
+            package.last_used.is_none()
+        }
+    }
+}
+
+/// How far a package's last-used date can be trusted, from the strongest source behind it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageConfidence {
+    /// No usage observed at all
+    #[default]
+    None,
+    /// Only a file access time, which macOS often doesn't update (or updates on backup)
+    Low,
+    /// Indirect evidence, such as a project script that calls the tool
+    Medium,
+    /// Shell history, Spotlight, or a running process
+    High,
+}
+
+impl UsageConfidence {
+    /// Confidence in `last_used`: that of the source it came from, or the best of them when
+    /// several saw it on that date
+    pub fn from_sources(sources: &[UsageSource], last_used: Option<DateTime<Utc>>) -> Self {
+        sources.iter()
+            .filter(|source| last_used.is_some() && source.event_date() == last_used)
+            .map(UsageSource::confidence)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UsageConfidence::None => "none",
+            UsageConfidence::Low => "low",
+            UsageConfidence::Medium => "medium",
+            UsageConfidence::High => "high",
+        }
+    }
+
+    /// Parse a value stored by `as_str`; anything unknown means no confidence
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "low" => UsageConfidence::Low,
+            "medium" => UsageConfidence::Medium,
+            "high" => UsageConfidence::High,
+            _ => UsageConfidence::None,
+        }
+    }
+}
+
+/// A usage source consulted for a package and what it found, including nothing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCheck {
+    /// Event type of the source (`shell`, `spotlight`, `process`, `login`, `scheduled`, `script`
+    /// or `atime`)
+    pub source: String,
+    /// Whether the source showed the package being used
+    pub found: bool,
+    /// What was found, e.g. `no uses across 3 history files`
+    pub result: String,
+}
+
+impl UsageCheck {
+    pub fn new(source: &str, found: bool, result: impl Into<String>) -> Self {
+        Self { source: source.to_string(), found, result: result.into() }
+    }
+
+    /// Readable name of the source
+    pub fn label(&self) -> &str {
+        match self.source.as_str() {
+            "shell" => "Shell history",
+            "spotlight" => "Spotlight",
+            "process" => "Running processes",
+            "script" => "Project scripts",
+            "atime" => "File access time",
+            "login" => "Login items",
+            "scheduled" => "Scheduled jobs",
+            other => other,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +216,17 @@ pub struct UsageInfo {
     pub last_used: Option<DateTime<Utc>>,
     pub usage_count: u32,
     pub sources: Vec<UsageSource>,
+    pub confidence: UsageConfidence,
+    /// Every source consulted, in the order they were checked
+    pub checks: Vec<UsageCheck>,
+    /// Started at login, so in use whatever the other sources say
+    pub launches_at_login: bool,
+}
+
+impl Default for UsageInfo {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl UsageInfo {
@@ -31,6 +235,86 @@ impl UsageInfo {
             last_used: None,
             usage_count: 0,
             sources: Vec::new(),
+            confidence: UsageConfidence::None,
+            checks: Vec::new(),
+            launches_at_login: false,
         }
     }
+
+    /// Uses found in shell history, as opposed to Spotlight's absolute use count
+    pub fn shell_count(&self) -> u32 {
+        self.sources
+            .iter()
+            .map(|s| match s {
+                UsageSource::ShellHistory { count, .. } => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Add this scan's observations to the stored last use and count, when shell history
+    /// was only read from where the previous scan stopped
+    pub fn accumulate(&self, stored_last_used: Option<DateTime<Utc>>, stored_count: u32) -> (Option<DateTime<Utc>>, u32) {
+        let shell = self.shell_count();
+        // Spotlight's count is a running total, so it replaces rather than adds
+        let spotlight = self.usage_count - shell;
+
+        (self.last_used.max(stored_last_used), stored_count.max(spotlight) + shell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::{Package, PackageSource};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_manual_marks_outlast_older_automatic_usage() {
+        let day = |d| Utc.with_ymd_and_hms(2024, 5, d, 0, 0, 0).unwrap();
+        let scanned = |last_used: Option<DateTime<Utc>>| {
+            let mut package = Package::new("protoc".to_string(), PackageSource::Homebrew);
+            package.last_used = last_used;
+            package.usage_count = if last_used.is_some() { 4 } else { 0 };
+            package.usage_confidence = if last_used.is_some() { UsageConfidence::Low } else { UsageConfidence::None };
+            package
+        };
+
+        let used = ManualMark { used: true, date: day(10) };
+        let mut package = scanned(Some(day(1)));
+        used.apply(&mut package);
+        assert_eq!(package.last_used, Some(day(10)));
+        assert_eq!(package.usage_confidence, UsageConfidence::High);
+        assert!(used.explains(&package));
+
+        // Newer automatic evidence wins
+        let mut package = scanned(Some(day(20)));
+        used.apply(&mut package);
+        assert_eq!(package.last_used, Some(day(20)));
+        assert!(!used.explains(&package));
+
+        let never = ManualMark { used: false, date: day(10) };
+        let mut package = scanned(Some(day(1)));
+        never.apply(&mut package);
+        assert_eq!((package.last_used, package.usage_count), (None, 0));
+        assert!(never.explains(&package));
+
+        let mut package = scanned(Some(day(20)));
+        never.apply(&mut package);
+        assert_eq!(package.last_used, Some(day(20)));
+    }
+
+    #[test]
+    fn test_confidence_comes_from_the_newest_source() {
+        let day = |d| Utc.with_ymd_and_hms(2024, 5, d, 0, 0, 0).unwrap();
+        let sources = vec![
+            UsageSource::ShellHistory { count: 3, last_used: day(1), command: "protoc --version".to_string() },
+            UsageSource::ProjectScript { package_json: "/src/app/package.json".to_string(), modified: day(10) },
+        ];
+
+        // The script set last_used, so the older shell history doesn't vouch for it
+        assert_eq!(UsageConfidence::from_sources(&sources, Some(day(10))), UsageConfidence::Medium);
+        assert_eq!(UsageConfidence::from_sources(&sources, Some(day(1))), UsageConfidence::High);
+        assert_eq!(UsageConfidence::from_sources(&sources, None), UsageConfidence::None);
+    }
 }
@@ -0,0 +1,114 @@
+// Running processes as usage evidence, for daemons and helpers started by launchd
+use crate::scanner::Package;
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Executables running at one point in time
+#[derive(Debug, Clone, Default)]
+pub struct ProcessSnapshot {
+    /// Executable paths as reported and with symlinks resolved
+    paths: HashSet<PathBuf>,
+    /// Processes reported without a path (only their command name)
+    names: HashSet<String>,
+    pub taken_at: Option<DateTime<Utc>>,
+}
+
+impl ProcessSnapshot {
+    /// List running processes with `ps`, giving up after `timeout`
+    pub fn capture(timeout: Duration) -> Result<Self> {
+        let output = crate::utils::process::run_command_with_timeout("ps", &["-axo", "comm="], timeout)?;
+        Ok(Self::from_commands(output.lines(), Utc::now()))
+    }
+
+    pub fn from_commands<'a>(commands: impl IntoIterator<Item = &'a str>, taken_at: DateTime<Utc>) -> Self {
+        let mut snapshot = Self { taken_at: Some(taken_at), ..Self::default() };
+
+        for command in commands.into_iter().map(str::trim).filter(|c| !c.is_empty()) {
+            let path = Path::new(command);
+            if path.is_absolute() {
+                if let Ok(resolved) = path.canonicalize() {
+                    snapshot.paths.insert(resolved);
+                }
+                snapshot.paths.insert(path.to_path_buf());
+            } else {
+                snapshot.names.insert(command.to_string());
+            }
+        }
+
+        snapshot
+    }
+
+    /// Whether `package`'s binary, or anything inside its app bundle, is running
+    pub fn is_running(&self, package: &Package) -> bool {
+        let Some(ref binary_path) = package.binary_path else {
+            return false;
+        };
+
+        let mut candidates = vec![binary_path.clone()];
+        if let Ok(resolved) = binary_path.canonicalize() {
+            if resolved != *binary_path {
+                candidates.push(resolved);
+            }
+        }
+
+        candidates.iter().any(|candidate| {
+            if candidate.extension().is_some_and(|e| e == "app") {
+                // Apps run from Contents/MacOS, helpers from deeper inside the bundle
+                self.paths.iter().any(|p| p.starts_with(candidate))
+            } else {
+                self.paths.contains(candidate)
+                    || candidate
+                        .file_name()
+                        .is_some_and(|name| self.names.contains(name.to_string_lossy().as_ref()))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::PackageSource;
+
+    fn package(name: &str, source: PackageSource, binary_path: &str) -> Package {
+        let mut package = Package::new(name.to_string(), source);
+        package.binary_path = Some(PathBuf::from(binary_path));
+        package
+    }
+
+    #[test]
+    fn test_running_binaries_and_app_bundles() {
+        let snapshot = ProcessSnapshot::from_commands(
+            [
+                "/opt/homebrew/bin/syncthing",
+                "/Applications/Tailscale.app/Contents/Library/LoginItems/IPNExtension.app/Contents/MacOS/IPNExtension",
+                "karabiner_grabber",
+            ],
+            Utc::now(),
+        );
+
+        assert!(snapshot.is_running(&package("syncthing", PackageSource::Homebrew, "/opt/homebrew/bin/syncthing")));
+        assert!(snapshot.is_running(&package("Tailscale", PackageSource::Applications, "/Applications/Tailscale.app")));
+        assert!(snapshot.is_running(&package("karabiner", PackageSource::Homebrew, "/usr/local/bin/karabiner_grabber")));
+        assert!(!snapshot.is_running(&package("jq", PackageSource::Homebrew, "/opt/homebrew/bin/jq")));
+        assert!(!snapshot.is_running(&package("Tail", PackageSource::Applications, "/Applications/Tail.app")));
+        assert!(!snapshot.is_running(&Package::new("left-pad".to_string(), PackageSource::Npm)));
+    }
+
+    #[test]
+    fn test_symlinked_binary_matches_resolved_process() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let cellar = dir.path().join("Cellar/syncthing/1.27/bin/syncthing");
+        std::fs::create_dir_all(cellar.parent().unwrap()).unwrap();
+        std::fs::write(&cellar, b"").unwrap();
+        let link = dir.path().join("bin/syncthing");
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&cellar, &link).unwrap();
+
+        let snapshot = ProcessSnapshot::from_commands([cellar.to_str().unwrap()], Utc::now());
+        assert!(snapshot.is_running(&package("syncthing", PackageSource::Homebrew, link.to_str().unwrap())));
+    }
+}
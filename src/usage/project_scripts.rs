@@ -0,0 +1,108 @@
+// Tools referenced by package.json scripts, for npm globals only ever run via `npm run`
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The most recently modified package.json whose scripts run a tool
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptReference {
+    pub package_json: PathBuf,
+    pub modified: DateTime<Utc>,
+}
+
+/// Names run by the `scripts` of every package.json in the configured project directories
+#[derive(Debug, Default)]
+pub struct ProjectScripts {
+    references: HashMap<String, ScriptReference>,
+}
+
+impl ProjectScripts {
+    /// Read `package.json` in each directory and in its immediate subdirectories, so both a
+    /// single project and a folder of checkouts work
+    pub fn load(project_dirs: &[PathBuf]) -> Self {
+        let mut scripts = Self::default();
+
+        for dir in project_dirs {
+            scripts.add_package_json(&dir.join("package.json"));
+
+            if let Ok(entries) = std::fs::read_dir(dir) {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    let path = entry.path();
+                    if path.is_dir() && entry.file_name() != "node_modules" {
+                        scripts.add_package_json(&path.join("package.json"));
+                    }
+                }
+            }
+        }
+
+        scripts
+    }
+
+    fn add_package_json(&mut self, path: &Path) {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return;
+        };
+        let Some(modified) = std::fs::metadata(path).and_then(|m| m.modified()).ok().map(DateTime::<Utc>::from) else {
+            return;
+        };
+
+        let json: serde_json::Value = match serde_json::from_str(&contents) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Skipping invalid package.json");
+                return;
+            }
+        };
+
+        let Some(scripts) = json.get("scripts").and_then(|s| s.as_object()) else {
+            return;
+        };
+
+        for script in scripts.values().filter_map(|s| s.as_str()) {
+            let script = script.to_lowercase();
+            for name in super::shell_history::invoked_names(&script) {
+                let newer = self.references.get(name).is_none_or(|r| r.modified < modified);
+                if newer {
+                    self.references.insert(name.to_string(), ScriptReference { package_json: path.to_path_buf(), modified });
+                }
+            }
+        }
+    }
+
+    /// Whether no configured project runs anything
+    pub fn is_empty(&self) -> bool {
+        self.references.is_empty()
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&ScriptReference> {
+        self.references.get(&name.to_lowercase())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scripts_in_project_and_checkout_folders() {
+        let root = TempDir::new().unwrap();
+        let single = root.path().join("site");
+        let checkouts = root.path().join("code");
+        std::fs::create_dir_all(&single).unwrap();
+        std::fs::create_dir_all(checkouts.join("api")).unwrap();
+        std::fs::create_dir_all(checkouts.join("node_modules/left-pad")).unwrap();
+
+        std::fs::write(single.join("package.json"), r#"{"scripts": {"lint": "eslint . && prettier --check src", "build": "tsc -p ."}}"#).unwrap();
+        std::fs::write(checkouts.join("api/package.json"), r#"{"scripts": {"new": "npx @nestjs/cli@10 new", "test": "jest"}}"#).unwrap();
+        std::fs::write(checkouts.join("node_modules/left-pad/package.json"), r#"{"scripts": {"x": "left-pad-cli"}}"#).unwrap();
+
+        let scripts = ProjectScripts::load(&[single.clone(), checkouts.clone()]);
+        for name in ["eslint", "prettier", "tsc", "jest", "@nestjs/cli"] {
+            assert!(scripts.lookup(name).is_some(), "{}", name);
+        }
+        assert_eq!(scripts.lookup("ESLint").unwrap().package_json, single.join("package.json"));
+        assert!(scripts.lookup("left-pad-cli").is_none());
+        assert!(scripts.lookup("mocha").is_none());
+    }
+}
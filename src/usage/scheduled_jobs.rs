@@ -0,0 +1,222 @@
+// Scheduled jobs: tools run by the user's crontab or personal LaunchAgents, such as backups
+// and syncs, count as in use though they never show up in shell history
+use super::login_items::LaunchAgent;
+use super::shell_history::invoked_names;
+use crate::utils::process::run_command_with_timeout;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A job that runs a command line on a schedule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledJob {
+    /// Where it is set up, e.g. `crontab line 4` or `LaunchAgent com.me.backup`
+    pub id: String,
+    pub command: String,
+    /// Lowercased names the command runs, as shell history matches them, plus the paths and
+    /// file names of the executables they resolve to
+    names: HashSet<String>,
+}
+
+impl ScheduledJob {
+    pub fn new(id: String, command: String) -> Self {
+        let mut names: HashSet<String> = invoked_names(&command.to_lowercase()).into_iter().map(str::to_string).collect();
+        // `/usr/local/bin/rb` may be a link into the package that installed it
+        for name in invoked_names(&command) {
+            if let Some(target) = resolve(name) {
+                names.extend(target.file_name().map(|n| n.to_string_lossy().to_lowercase()));
+                names.insert(target.to_string_lossy().to_lowercase());
+            }
+        }
+        Self { id, command, names }
+    }
+
+    fn runs(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+/// The executable `name` runs: a path as written, or else the first match on `PATH`, with
+/// symlinks followed
+fn resolve(name: &str) -> Option<PathBuf> {
+    let path = if name.contains('/') { PathBuf::from(name) } else { which::which(name).ok()? };
+    std::fs::canonicalize(path).ok()
+}
+
+/// Every job in the user's crontab and personal LaunchAgents
+#[derive(Debug, Clone, Default)]
+pub struct ScheduledJobs {
+    jobs: Vec<ScheduledJob>,
+    /// When the jobs were read, which is when their packages were last known to be in use
+    pub read_at: DateTime<Utc>,
+}
+
+impl ScheduledJobs {
+    pub fn new(jobs: Vec<ScheduledJob>) -> Self {
+        Self { jobs, read_at: Utc::now() }
+    }
+
+    /// Read `crontab -l` and the `agents` in ~/Library/LaunchAgents; agents elsewhere belong
+    /// to apps, which the login items account for; `crontab -l` is given `timeout`
+    pub fn load(agents: &[LaunchAgent], timeout: Duration) -> Self {
+        let mut jobs = match run_command_with_timeout("crontab", &["-l"], timeout) {
+            Ok(crontab) => parse_crontab(&crontab),
+            // `crontab -l` fails when there is no crontab
+            Err(e) => {
+                tracing::debug!(command = "crontab", error = %e, "No crontab read");
+                Vec::new()
+            }
+        };
+        if let Some(dir) = dirs::home_dir().map(|home| home.join("Library/LaunchAgents")) {
+            jobs.extend(agents.iter().filter(|agent| agent.path.starts_with(&dir)).filter_map(agent_job));
+        }
+        Self::new(jobs)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// The first job running any of `names` (lowercased command names or binary paths)
+    pub fn find(&self, names: &[String]) -> Option<&ScheduledJob> {
+        self.jobs.iter().find(|job| names.iter().any(|name| job.runs(name)))
+    }
+}
+
+/// The jobs of a crontab, named by their line
+fn parse_crontab(crontab: &str) -> Vec<ScheduledJob> {
+    crontab
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| Some(ScheduledJob::new(format!("crontab line {}", i + 1), crontab_command(line)?)))
+        .collect()
+}
+
+/// The command of a crontab line, after its five time fields or `@daily`-style schedule.
+/// Blank lines, comments and `NAME=value` settings have none.
+fn crontab_command(line: &str) -> Option<String> {
+    let line = line.trim();
+    let is_setting = line
+        .split_once('=')
+        .is_some_and(|(name, _)| !name.trim().is_empty() && name.trim().chars().all(|c| c.is_ascii_alphanumeric() || c == '_'));
+    if line.is_empty() || line.starts_with('#') || is_setting {
+        return None;
+    }
+
+    let fields = if line.starts_with('@') { 1 } else { 5 };
+    let mut rest = line;
+    for _ in 0..fields {
+        rest = rest.split_once(char::is_whitespace)?.1.trim_start();
+    }
+
+    // An unescaped % ends the command; the text after it is the command's standard input
+    let mut command = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.clone().next() == Some('%') => command.extend(chars.next()),
+            '%' => break,
+            c => command.push(c),
+        }
+    }
+    let command = command.trim();
+    (!command.is_empty()).then(|| command.to_string())
+}
+
+/// The command line an agent runs: the script of a `sh -c` wrapper, or else its program and
+/// arguments
+fn agent_job(agent: &LaunchAgent) -> Option<ScheduledJob> {
+    let program = agent.program.as_deref()?;
+    let mut words = vec![program.to_string_lossy().into_owned()];
+    words.extend(agent.arguments.iter().skip(1).cloned());
+
+    let is_shell = |path: &Path| path.file_name().is_some_and(|n| ["sh", "bash", "zsh"].iter().any(|shell| n == *shell));
+    let command = match words.as_slice() {
+        [_, flag, script, ..] if is_shell(program) && matches!(flag.as_str(), "-c" | "-lc") => script.clone(),
+        _ => words.join(" "),
+    };
+    Some(ScheduledJob::new(format!("LaunchAgent {}", agent.label), command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(label: &str, program: Option<&str>, arguments: &[&str]) -> LaunchAgent {
+        LaunchAgent {
+            label: label.to_string(),
+            program: program.or(arguments.first().copied()).map(PathBuf::from),
+            arguments: arguments.iter().map(|a| a.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_crontab_commands() {
+        let crontab = "\
+# m h dom mon dow command
+SHELL=/bin/bash
+PATH = /opt/homebrew/bin:/usr/bin
+
+0 3 * * * /opt/homebrew/bin/restic backup ~/Documents >> ~/restic.log 2>&1
+*/15 9-17 * * 1-5 cd ~/notes && rclone sync . remote:notes
+@reboot sleep 60; /usr/bin/env syncthing -no-browser
+30 2 1 * * mail -s report me@example.com%Backup done\\% of the time%
+";
+        let jobs = parse_crontab(crontab);
+        let commands: Vec<(&str, &str)> = jobs.iter().map(|j| (j.id.as_str(), j.command.as_str())).collect();
+        assert_eq!(
+            commands,
+            [
+                ("crontab line 5", "/opt/homebrew/bin/restic backup ~/Documents >> ~/restic.log 2>&1"),
+                ("crontab line 6", "cd ~/notes && rclone sync . remote:notes"),
+                ("crontab line 7", "sleep 60; /usr/bin/env syncthing -no-browser"),
+                ("crontab line 8", "mail -s report me@example.com"),
+            ]
+        );
+        assert!(crontab_command("0 3 * * *").is_none());
+        assert_eq!(crontab_command("0 3 * * * printf '100\\%'").as_deref(), Some("printf '100%'"));
+
+        let scheduled = ScheduledJobs::new(jobs);
+        assert_eq!(scheduled.find(&["restic".to_string()]).map(|j| j.id.as_str()), Some("crontab line 5"));
+        assert_eq!(scheduled.find(&["/opt/homebrew/bin/restic".to_string()]).map(|j| j.id.as_str()), Some("crontab line 5"));
+        assert_eq!(scheduled.find(&["rclone".to_string()]).map(|j| j.id.as_str()), Some("crontab line 6"));
+        assert!(scheduled.find(&["syncthing".to_string()]).is_some());
+        // Arguments aren't commands
+        assert!(scheduled.find(&["remote:notes".to_string()]).is_none());
+        assert!(scheduled.find(&["report".to_string()]).is_none());
+    }
+
+    #[test]
+    fn test_launch_agent_jobs() {
+        let direct = agent_job(&agent("com.me.backup", None, &["/opt/homebrew/bin/restic", "backup", "--quiet"])).unwrap();
+        assert_eq!((direct.id.as_str(), direct.command.as_str()), ("LaunchAgent com.me.backup", "/opt/homebrew/bin/restic backup --quiet"));
+        assert!(direct.runs("restic"));
+
+        let wrapped = agent_job(&agent("com.me.sync", None, &["/bin/zsh", "-lc", "rclone sync ~/Photos remote: | tee -a ~/sync.log"])).unwrap();
+        assert_eq!(wrapped.command, "rclone sync ~/Photos remote: | tee -a ~/sync.log");
+        assert!(wrapped.runs("rclone") && wrapped.runs("tee") && !wrapped.runs("zsh"));
+
+        // With Program set, the first argument is only the name the program sees
+        let named = agent_job(&agent("com.me.mirror", Some("/usr/local/bin/mirror-tool"), &["mirror", "--once"])).unwrap();
+        assert_eq!(named.command, "/usr/local/bin/mirror-tool --once");
+        assert!(named.runs("mirror-tool") && !named.runs("mirror"));
+
+        assert!(agent_job(&agent("com.me.empty", None, &[])).is_none());
+    }
+
+    #[test]
+    fn test_symlinked_commands_resolve_to_their_target() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let target = dir.path().join("Cellar/rclone/1.66.0/bin/rclone");
+        std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+        std::fs::write(&target, "").unwrap();
+        let link = dir.path().join("rc");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let job = ScheduledJob::new("crontab line 1".to_string(), format!("{} sync a b", link.display()));
+        assert!(job.runs("rc") && job.runs("rclone"));
+        assert!(job.runs(&std::fs::canonicalize(&target).unwrap().to_string_lossy().to_lowercase()));
+    }
+}
@@ -1,11 +1,14 @@
 // Shell history parser (zsh, bash, fish)
-use anyhow::{Context, Result};
+use crate::error::{Context, Result};
+use super::aliases::Aliases;
 use chrono::{DateTime, TimeZone, Utc};
 use lazy_static::lazy_static;
+use rayon::prelude::*;
 use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct HistoryEntry {
@@ -22,23 +25,23 @@ impl HistoryEntry {
             .map(|s| s.to_string())
     }
 
-    /// Check if this command invokes a specific binary/package
+    /// Check if this command invokes a specific binary/package: as the command of any part of
+    /// a pipeline or chain, by name or by path, or through a package runner like `npx`
     pub fn invokes_binary(&self, binary_name: &str) -> bool {
         let cmd = self.command.to_lowercase();
         let bin = binary_name.to_lowercase();
 
-        // Check if the command starts with the binary name
-        if cmd.starts_with(&bin) {
-            return true;
+        // App names with spaces can't be split into words, so they match the start of a command
+        if bin.contains(char::is_whitespace) {
+            return simple_commands(&cmd).iter().any(|segment| {
+                segment
+                    .trim_start()
+                    .strip_prefix(bin.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+            });
         }
 
-        // Check if it's used in a pipe or chain
-        let words: Vec<&str> = cmd.split_whitespace().collect();
-        words.iter().any(|w| {
-            // Remove common prefixes
-            let word = w.trim_start_matches("sudo");
-            word == bin || word.starts_with(&format!("{}/", bin))
-        })
+        invoked_names(&cmd).contains(bin.as_str())
     }
 }
 
@@ -46,23 +49,49 @@ lazy_static! {
     static ref ZSH_HISTORY_RE: Regex = Regex::new(r"^: (\d+):0;(.*)$").unwrap();
 }
 
+/// zsh's escape byte: the byte after it is stored XOR 32
+const ZSH_META: u8 = 0x83;
+
+/// Undo zsh's metafication, which escapes bytes 0x83 and up (so most non-ASCII text)
+fn unmetafy(line: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(line.len());
+    let mut iter = line.iter();
+
+    while let Some(&b) = iter.next() {
+        if b == ZSH_META {
+            if let Some(&next) = iter.next() {
+                bytes.push(next ^ 32);
+            }
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    bytes
+}
+
 /// Parse zsh history file (~/.zsh_history)
-/// Format: `: timestamp:0;command`
+/// Format: `: timestamp:0;command`, metafied
 pub fn parse_zsh_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
     if !history_path.exists() {
         return Ok(Vec::new());
     }
 
-    let file = File::open(history_path)
+    let contents = std::fs::read(history_path)
         .context(format!("Failed to open zsh history: {:?}", history_path))?;
 
-    let reader = BufReader::new(file);
+    Ok(parse_zsh_bytes(&contents))
+}
+
+fn parse_zsh_bytes(contents: &[u8]) -> Vec<HistoryEntry> {
     let mut entries = Vec::new();
     let mut current_command = String::new();
     let mut current_timestamp: Option<DateTime<Utc>> = None;
 
-    for line_result in reader.lines() {
-        let line = line_result?;
+    // Metafied bytes never include a newline, so lines can be split before decoding;
+    // each line is decoded on its own so one bad entry can't break the rest
+    for raw_line in contents.split(|&b| b == b'\n') {
+        let line = String::from_utf8_lossy(&unmetafy(raw_line)).into_owned();
 
         // Check if this is a new entry
         if let Some(caps) = ZSH_HISTORY_RE.captures(&line) {
@@ -79,12 +108,20 @@ pub fn parse_zsh_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
             let timestamp_num: i64 = timestamp_str.parse().unwrap_or(0);
             current_timestamp = Utc.timestamp_opt(timestamp_num, 0).single();
             current_command = caps[2].to_string();
-        } else {
-            // Continuation of previous command (multiline)
+        } else if current_command.ends_with('\\') {
+            // Continuation of previous command (zsh ends each embedded line with a backslash)
+            current_command.push('\n');
+            current_command.push_str(&line);
+        } else if !line.trim().is_empty() {
+            // Plain entry, written without EXTENDED_HISTORY
             if !current_command.is_empty() {
-                current_command.push('\n');
-                current_command.push_str(&line);
+                entries.push(HistoryEntry {
+                    command: current_command.trim().to_string(),
+                    timestamp: current_timestamp,
+                });
             }
+            current_timestamp = None;
+            current_command = line;
         }
     }
 
@@ -96,7 +133,7 @@ pub fn parse_zsh_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
         });
     }
 
-    Ok(entries)
+    entries
 }
 
 /// Parse bash history file (~/.bash_history)
@@ -107,20 +144,22 @@ pub fn parse_bash_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
         return Ok(Vec::new());
     }
 
-    let file = File::open(history_path)
+    let contents = std::fs::read(history_path)
         .context(format!("Failed to open bash history: {:?}", history_path))?;
 
-    let reader = BufReader::new(file);
+    Ok(parse_bash_bytes(&contents))
+}
+
+fn parse_bash_bytes(contents: &[u8]) -> Vec<HistoryEntry> {
     let mut entries = Vec::new();
     let mut current_timestamp: Option<DateTime<Utc>> = None;
 
-    for line_result in reader.lines() {
-        let line = line_result?;
+    for line in decode_lines(contents) {
 
         // Check if this is a timestamp line (starts with #)
-        if line.starts_with('#') {
+        if let Some(rest) = line.strip_prefix('#') {
             // Try to parse as timestamp
-            if let Ok(timestamp_num) = line[1..].trim().parse::<i64>() {
+            if let Ok(timestamp_num) = rest.trim().parse::<i64>() {
                 current_timestamp = Utc.timestamp_opt(timestamp_num, 0).single();
                 continue;
             }
@@ -136,57 +175,105 @@ pub fn parse_bash_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
         }
     }
 
-    Ok(entries)
+    entries
 }
 
 /// Parse fish history file (~/.local/share/fish/fish_history)
-/// Format: YAML-like
-/// ```
+/// Format: YAML-like, with `when:` and `paths:` both optional
+/// ```text
 /// - cmd: ls -la
 ///   when: 1234567890
+///   paths:
+///     - /some/path
 /// ```
 pub fn parse_fish_history(history_path: &Path) -> Result<Vec<HistoryEntry>> {
     if !history_path.exists() {
         return Ok(Vec::new());
     }
 
-    let file = File::open(history_path)
+    let contents = std::fs::read(history_path)
         .context(format!("Failed to open fish history: {:?}", history_path))?;
 
-    let reader = BufReader::new(file);
+    Ok(parse_fish_bytes(&contents))
+}
+
+fn parse_fish_bytes(contents: &[u8]) -> Vec<HistoryEntry> {
     let mut entries = Vec::new();
-    let mut current_command: Option<String> = None;
-    let mut current_timestamp: Option<DateTime<Utc>> = None;
+    let mut current: Option<HistoryEntry> = None;
+    let mut in_paths = false;
+
+    for line in decode_lines(contents) {
+        if let Some(cmd) = line.strip_prefix("- cmd:") {
+            // A new entry ends the previous one, whatever fields it had
+            entries.extend(current.take());
+            current = Some(HistoryEntry { command: unescape_fish(cmd.trim()), timestamp: None });
+            in_paths = false;
+            continue;
+        }
 
-    for line_result in reader.lines() {
-        let line = line_result?;
         let trimmed = line.trim();
+        if in_paths && trimmed.starts_with("- ") {
+            // An entry of the `paths:` list
+            continue;
+        }
+        in_paths = false;
 
-        if let Some(cmd) = trimmed.strip_prefix("- cmd:") {
-            // New entry
-            current_command = Some(cmd.trim().to_string());
-        } else if let Some(when) = trimmed.strip_prefix("when:") {
-            // Timestamp for current entry
+        let Some(entry) = current.as_mut() else {
+            continue;
+        };
+        if let Some(when) = trimmed.strip_prefix("when:") {
             if let Ok(timestamp_num) = when.trim().parse::<i64>() {
-                current_timestamp = Utc.timestamp_opt(timestamp_num, 0).single();
+                entry.timestamp = Utc.timestamp_opt(timestamp_num, 0).single();
             }
+        } else if trimmed == "paths:" {
+            in_paths = true;
+        }
+    }
 
-            // If we have both command and timestamp, save the entry
-            if let Some(cmd) = current_command.take() {
-                entries.push(HistoryEntry {
-                    command: cmd,
-                    timestamp: current_timestamp,
-                });
-                current_timestamp = None;
+    entries.extend(current);
+    entries
+}
+
+/// Undo fish's escaping of history commands (`\\` for a backslash, `\n` for a newline)
+fn unescape_fish(cmd: &str) -> String {
+    let mut unescaped = String::with_capacity(cmd.len());
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
             }
+            None => unescaped.push('\\'),
         }
     }
+    unescaped
+}
 
-    Ok(entries)
+/// Lines decoded one at a time, so invalid UTF-8 only spoils its own line
+fn decode_lines(contents: &[u8]) -> impl Iterator<Item = String> + '_ {
+    contents
+        .split(|&b| b == b'\n')
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned())
 }
 
-/// Get the default history path for the current shell
+/// Get the default history path for the current shell, preferring `$HISTFILE`
 pub fn get_default_history_path() -> Option<std::path::PathBuf> {
+    default_history_path(histfile_from_env())
+}
+
+/// `get_default_history_path`, with `histfile` standing in for `$HISTFILE`
+fn default_history_path(histfile: Option<PathBuf>) -> Option<PathBuf> {
+    if histfile.is_some() {
+        return histfile;
+    }
+
     let home = dirs::home_dir()?;
 
     // Try to detect shell from SHELL environment variable
@@ -196,7 +283,7 @@ pub fn get_default_history_path() -> Option<std::path::PathBuf> {
         } else if shell.contains("bash") {
             return Some(home.join(".bash_history"));
         } else if shell.contains("fish") {
-            return Some(home.join(".local/share/fish/fish_history"));
+            return Some(fish_history_path(&home, xdg_data_home_from_env()));
         }
     }
 
@@ -211,7 +298,7 @@ pub fn get_default_history_path() -> Option<std::path::PathBuf> {
         return Some(bash);
     }
 
-    let fish = home.join(".local/share/fish/fish_history");
+    let fish = fish_history_path(&home, xdg_data_home_from_env());
     if fish.exists() {
         return Some(fish);
     }
@@ -219,51 +306,588 @@ pub fn get_default_history_path() -> Option<std::path::PathBuf> {
     None
 }
 
-/// Parse all available shell history files
-pub fn parse_all_history() -> Result<Vec<HistoryEntry>> {
-    let mut all_entries = Vec::new();
+/// `$XDG_DATA_HOME`, when set; fish keeps its history there instead of ~/.local/share
+fn xdg_data_home_from_env() -> Option<PathBuf> {
+    std::env::var_os("XDG_DATA_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+fn fish_history_path(home: &Path, xdg_data_home: Option<PathBuf>) -> PathBuf {
+    xdg_data_home
+        .unwrap_or_else(|| home.join(".local/share"))
+        .join("fish/fish_history")
+}
+
+/// `$HISTFILE`, when set (it is usually only exported by the interactive shell itself)
+fn histfile_from_env() -> Option<PathBuf> {
+    std::env::var_os("HISTFILE")
+        .filter(|v| !v.is_empty())
+        .map(|v| crate::config::expand_home(Path::new(&v)))
+}
+
+/// Every existing history file to read: `$HISTFILE`, the standard zsh/bash/fish locations
+/// (fish's under `$XDG_DATA_HOME` too) and the timestamped rotated copies of `$HISTFILE` and
+/// the zsh and bash histories, zsh's per-session files from macOS Terminal, then `extra_paths`
+/// (files, directories, or globs with `*` and `?` in the file name)
+pub fn history_files(
+    home: &Path,
+    histfile: Option<PathBuf>,
+    xdg_data_home: Option<PathBuf>,
+    extra_paths: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = histfile.into_iter().collect();
+    candidates.push(home.join(".zsh_history"));
+    candidates.push(home.join(".bash_history"));
+    // Entries repeated across files are only dropped when they carry a timestamp, so a copy
+    // without timestamps would count everything it shares with the main history twice
+    let rotated: Vec<PathBuf> = candidates.iter()
+        .flat_map(|path| rotated_copies(path))
+        .filter(|path| is_timestamped(path))
+        .collect();
+    candidates.extend(rotated);
+    if xdg_data_home.is_some() {
+        candidates.push(fish_history_path(home, xdg_data_home));
+    }
+    candidates.push(fish_history_path(home, None));
+    candidates.extend(files_in(&home.join(".zsh_sessions"), |p| p.extension().is_some_and(|e| e == "history")));
+
+    for path in extra_paths {
+        let pattern = path.file_name().map(|n| n.to_string_lossy()).filter(|n| n.contains(['*', '?']));
+        match (pattern, path.parent()) {
+            (Some(pattern), Some(dir)) => candidates.extend(files_in(dir, |p| {
+                p.file_name().is_some_and(|n| crate::utils::glob::glob_match(&pattern, &n.to_string_lossy()))
+            })),
+            _ if path.is_dir() => candidates.extend(files_in(path, |_| true)),
+            _ => candidates.push(path.clone()),
+        }
+    }
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in candidates {
+        if path.is_file() && !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
 
+/// Older copies of the history file `path` next to it, as left by rotation or backups:
+/// `.zsh_history.1`, `.zsh_history.bak`, `.zsh_history-20240601`, `.zsh_history.2024-06-01.old`.
+/// Compressed copies can't be read and are skipped.
+fn rotated_copies(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name().and_then(|n| n.to_str())) else {
+        return Vec::new();
+    };
+    files_in(dir, |p| {
+        p.file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix(name))
+            .is_some_and(is_rotation_suffix)
+    })
+}
+
+/// Whether `suffix` is what rotation appends to a file name: a separator, then numbers and
+/// words like `bak`, split by `.`, `-` or `_`
+fn is_rotation_suffix(suffix: &str) -> bool {
+    let Some(rest) = suffix.strip_prefix(['.', '-', '_']) else {
+        return false;
+    };
+    rest.split(['.', '-', '_']).all(|part| {
+        (!part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())) || matches!(part, "bak" | "backup" | "old" | "orig" | "save")
+    })
+}
+
+/// Files directly inside `dir` accepted by `filter`, sorted by name
+fn files_in(dir: &Path, filter: impl Fn(&Path) -> bool) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.path())
+                .filter(|p| p.is_file() && filter(p))
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HistoryFormat {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+fn first_line(path: &Path) -> Vec<u8> {
+    let mut line = Vec::new();
+    if let Ok(file) = File::open(path) {
+        let _ = BufReader::new(file).read_until(b'\n', &mut line);
+    }
+    line
+}
+
+/// Whether the history file starts with a timestamp: zsh's EXTENDED_HISTORY `: <time>:0;`
+/// or the `#<time>` line bash writes with HISTTIMEFORMAT set
+fn is_timestamped(path: &Path) -> bool {
+    let line = String::from_utf8_lossy(&first_line(path)).trim_end().to_string();
+    ZSH_HISTORY_RE.is_match(&line) || line.strip_prefix('#').is_some_and(|time| time.parse::<i64>().is_ok())
+}
+
+/// Guess a history file's format from its first line, since custom locations carry no hint
+fn detect_format(path: &Path) -> HistoryFormat {
+    let first_line = first_line(path);
+    if ZSH_HISTORY_RE.is_match(String::from_utf8_lossy(&first_line).trim_end()) {
+        HistoryFormat::Zsh
+    } else if first_line.starts_with(b"- cmd:") {
+        HistoryFormat::Fish
+    } else if path.file_name().is_some_and(|n| n.to_string_lossy().contains("zsh")) || path.extension().is_some_and(|e| e == "history") {
+        // zsh without EXTENDED_HISTORY writes plain, but still metafied, lines
+        HistoryFormat::Zsh
+    } else {
+        HistoryFormat::Bash
+    }
+}
+
+fn parse_history_file(path: &Path) -> Result<Vec<HistoryEntry>> {
+    match detect_format(path) {
+        HistoryFormat::Zsh => parse_zsh_history(path),
+        HistoryFormat::Bash => parse_bash_history(path),
+        HistoryFormat::Fish => parse_fish_history(path),
+    }
+}
+
+fn parse_history_bytes(format: HistoryFormat, contents: &[u8]) -> Vec<HistoryEntry> {
+    match format {
+        HistoryFormat::Zsh => parse_zsh_bytes(contents),
+        HistoryFormat::Bash => parse_bash_bytes(contents),
+        HistoryFormat::Fish => parse_fish_bytes(contents),
+    }
+}
+
+/// How far a history file was read, kept between scans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryFileState {
+    pub path: PathBuf,
+    pub inode: u64,
+    /// Byte offset just past the last complete line read
+    pub offset: u64,
+    /// Newest entry timestamp seen in the file
+    pub last_entry_at: Option<DateTime<Utc>>,
+}
+
+/// History entries written since the previous scan
+#[derive(Debug, Default)]
+pub struct HistoryDelta {
+    pub entries: Vec<HistoryEntry>,
+    /// Where each file was read up to, to be stored with the scan
+    pub files: Vec<HistoryFileState>,
+    /// No file had been read before, so `entries` is the whole history rather than an addition
+    pub is_complete: bool,
+}
+
+/// Read only what was appended to each history file since `previous`
+pub fn read_new_history(extra_paths: &[PathBuf], previous: &[HistoryFileState]) -> Result<HistoryDelta> {
+    let home = match dirs::home_dir() {
+        Some(h) => h,
+        None => return Ok(HistoryDelta::default()),
+    };
+
+    Ok(read_history_files(&history_files(&home, histfile_from_env(), xdg_data_home_from_env(), extra_paths), previous))
+}
+
+/// Read each file from where `previous` left it. A file renamed by rotation is found by its
+/// inode and carries on from there. A file never read before that holds entries timestamped
+/// before the last read, such as a copy made by `copytruncate` or a backup, may repeat what
+/// was already counted, so then all history is read again as a complete delta, which drops
+/// the repeats.
+fn read_history_files(files: &[PathBuf], previous: &[HistoryFileState]) -> HistoryDelta {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut delta = HistoryDelta { is_complete: previous.is_empty(), ..HistoryDelta::default() };
+    let last_read = previous.iter().filter_map(|p| p.last_entry_at).max();
+
+    let results: Vec<_> = files
+        .par_iter()
+        .map(|path| {
+            let inode = std::fs::metadata(path).map(|m| m.ino()).ok();
+            let state = previous
+                .iter()
+                .find(|p| p.path == *path)
+                .or_else(|| previous.iter().find(|p| Some(p.inode) == inode));
+            (path, state.is_none(), read_file_since(path, state))
+        })
+        .collect();
+
+    for (path, unseen, result) in results {
+        match result {
+            Ok((entries, state)) => {
+                let earlier = |e: &HistoryEntry| e.timestamp.zip(last_read).is_some_and(|(ts, last)| ts <= last);
+                if unseen && entries.iter().any(earlier) {
+                    tracing::info!(path = %path.display(), "Found older shell history; reading all history again");
+                    return read_history_files(files, &[]);
+                }
+                delta.entries.extend(entries);
+                delta.files.push(state);
+            }
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Failed to read shell history"),
+        }
+    }
+
+    sort_and_dedupe(&mut delta.entries);
+    delta
+}
+
+/// Parse the complete lines appended to `path` since `previous`, reparsing the whole file
+/// when it was rotated (new inode) or truncated (shorter than the stored offset)
+fn read_file_since(path: &Path, previous: Option<&HistoryFileState>) -> Result<(Vec<HistoryEntry>, HistoryFileState)> {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::unix::fs::MetadataExt;
+
+    let mut file = File::open(path)?;
+    let metadata = file.metadata()?;
+    let resume = previous.filter(|p| p.inode == metadata.ino() && p.offset <= metadata.len());
+    let start = resume.map_or(0, |p| p.offset);
+
+    let mut contents = Vec::new();
+    file.seek(SeekFrom::Start(start))?;
+    file.read_to_end(&mut contents)?;
+
+    // A line still being written is picked up by the next scan
+    let complete = contents.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    contents.truncate(complete);
+
+    let mut entries = parse_history_bytes(detect_format(path), &contents);
+    let newest = entries.iter().filter_map(|e| e.timestamp).max();
+
+    if let (None, Some(previous)) = (resume, previous) {
+        // Everything up to the newest entry seen before was already counted; entries
+        // without a timestamp can't be told apart, so they are skipped
+        entries.retain(|e| e.timestamp.is_some_and(|ts| previous.last_entry_at.is_none_or(|last| ts > last)));
+    }
+
+    let state = HistoryFileState {
+        path: path.to_path_buf(),
+        inode: metadata.ino(),
+        offset: start + complete as u64,
+        last_entry_at: newest.max(previous.and_then(|p| p.last_entry_at)),
+    };
+
+    Ok((entries, state))
+}
+
+/// Parse all available shell history files, plus any configured `extra_paths`
+pub fn parse_all_history(extra_paths: &[PathBuf]) -> Result<Vec<HistoryEntry>> {
     let home = match dirs::home_dir() {
         Some(h) => h,
-        None => return Ok(all_entries),
+        None => return Ok(Vec::new()),
     };
 
-    // Try zsh
-    let zsh_path = home.join(".zsh_history");
-    if let Ok(entries) = parse_zsh_history(&zsh_path) {
-        all_entries.extend(entries);
+    Ok(parse_history_files(&history_files(&home, histfile_from_env(), xdg_data_home_from_env(), extra_paths)))
+}
+
+fn parse_history_files(files: &[PathBuf]) -> Vec<HistoryEntry> {
+    let mut all_entries = Vec::new();
+
+    let results: Vec<_> = files.par_iter().map(|path| (path, parse_history_file(path))).collect();
+    for (path, result) in results {
+        match result {
+            Ok(entries) => all_entries.extend(entries),
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Failed to read shell history"),
+        }
+    }
+
+    sort_and_dedupe(&mut all_entries);
+    all_entries
+}
+
+/// Newest first, dropping timestamped entries seen in more than one file
+fn sort_and_dedupe(all_entries: &mut Vec<HistoryEntry>) {
+    // Timestamped entries are ordered by command within the same second, so repeats end up
+    // next to each other; entries without a timestamp go last, in the order they were read
+    all_entries.sort_by(|a, b| match (a.timestamp, b.timestamp) {
+        (Some(a_time), Some(b_time)) => b_time.cmp(&a_time).then_with(|| a.command.cmp(&b.command)),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    // Terminal's session files and rotated copies repeat what is in the main history
+    all_entries.dedup_by(|a, b| a.timestamp.is_some() && a.timestamp == b.timestamp && a.command == b.command);
+}
+
+/// Shell history indexed by command, so each package lookup is a single hash probe
+///
+/// Keys are every string `binary_name` for which [`HistoryEntry::invokes_binary`] would
+/// match (see [`invoked_names`]), plus the binaries behind any shell or git aliases used.
+#[derive(Debug, Default)]
+pub struct HistoryIndex {
+    entries: Vec<HistoryEntry>,
+    stats: HashMap<String, CommandStats>,
+}
+
+#[derive(Debug, Default)]
+struct CommandStats {
+    /// Indices of the entries that run it
+    entries: Vec<usize>,
+    /// Newest timestamp and the index of its entry
+    last: Option<(DateTime<Utc>, usize)>,
+}
+
+impl HistoryIndex {
+    pub fn new(entries: Vec<HistoryEntry>) -> Self {
+        Self::with_aliases(entries, &Aliases::default())
+    }
+
+    pub fn with_aliases(entries: Vec<HistoryEntry>, aliases: &Aliases) -> Self {
+        let mut stats: HashMap<String, CommandStats> = HashMap::new();
+
+        for (i, entry) in entries.iter().enumerate() {
+            let cmd = entry.command.to_lowercase();
+            let mut keys = invoked_names(&cmd);
+            keys.extend(aliases.expand(&cmd));
+
+            for key in keys {
+                let stat = match stats.get_mut(key) {
+                    Some(stat) => stat,
+                    None => stats.entry(key.to_string()).or_default(),
+                };
+                stat.entries.push(i);
+                if let Some(ts) = entry.timestamp {
+                    if stat.last.is_none_or(|(last, _)| last < ts) {
+                        stat.last = Some((ts, i));
+                    }
+                }
+            }
+        }
+
+        Self { entries, stats }
+    }
+
+    /// Newest use, number of uses and the newest matching command line for `binary_name`
+    pub fn lookup(&self, binary_name: &str) -> Option<(DateTime<Utc>, u32, String)> {
+        let bin = binary_name.to_lowercase();
+
+        // Names with spaces (app names) can only match as a command prefix, which isn't indexed
+        if bin.is_empty() || bin.contains(char::is_whitespace) {
+            return self.scan(binary_name);
+        }
+
+        let stat = self.stats.get(&bin)?;
+        let (ts, i) = stat.last?;
+        Some((ts, stat.entries.len() as u32, self.entries[i].command.clone()))
+    }
+
+    /// Like [`lookup`](Self::lookup) over several names, counting an entry that runs more
+    /// than one of them (`convert a.png b.png && magick identify b.png`) once
+    pub fn lookup_any(&self, binary_names: &[String]) -> Option<(DateTime<Utc>, u32, String)> {
+        let mut seen = HashSet::new();
+        let mut last_used: Option<(DateTime<Utc>, usize)> = None;
+
+        for name in binary_names {
+            let bin = name.to_lowercase();
+            let scanned: Vec<usize>;
+            let matches: &[usize] = if bin.is_empty() || bin.contains(char::is_whitespace) {
+                scanned = (0..self.entries.len()).filter(|&i| self.entries[i].invokes_binary(name)).collect();
+                &scanned
+            } else {
+                self.stats.get(&bin).map_or(&[], |stat| &stat.entries)
+            };
+
+            for &i in matches.iter().filter(|&&i| seen.insert(i)) {
+                if let Some(ts) = self.entries[i].timestamp {
+                    if last_used.is_none_or(|(last, _)| last < ts) {
+                        last_used = Some((ts, i));
+                    }
+                }
+            }
+        }
+
+        last_used.map(|(ts, i)| (ts, seen.len() as u32, self.entries[i].command.clone()))
     }
 
-    // Try bash
-    let bash_path = home.join(".bash_history");
-    if let Ok(entries) = parse_bash_history(&bash_path) {
-        all_entries.extend(entries);
+    fn scan(&self, binary_name: &str) -> Option<(DateTime<Utc>, u32, String)> {
+        let mut count = 0;
+        let mut last_used: Option<(DateTime<Utc>, &str)> = None;
+
+        for entry in self.entries.iter().filter(|e| e.invokes_binary(binary_name)) {
+            count += 1;
+            if let Some(ts) = entry.timestamp {
+                if last_used.is_none_or(|(last, _)| last < ts) {
+                    last_used = Some((ts, &entry.command));
+                }
+            }
+        }
+
+        last_used.map(|(ts, command)| (ts, count, command.to_string()))
     }
+}
+
+/// Commands that run the command after them, with the options that take a value
+const WRAPPERS: &[(&str, &[&str])] = &[
+    ("sudo", &["-u", "-g", "-h", "-p", "-c", "-r", "-t", "-U", "-C"]),
+    ("doas", &["-u", "-c"]),
+    ("env", &["-u", "-c", "-s", "-S", "-C"]),
+    ("command", &[]),
+    ("builtin", &[]),
+    ("exec", &["-a"]),
+    ("nohup", &[]),
+    ("time", &[]),
+    ("noglob", &[]),
+    ("nocorrect", &[]),
+    ("nice", &["-n"]),
+    ("xargs", &["-i", "-I", "-n", "-l", "-L", "-P", "-s", "-d", "-e", "-E"]),
+    ("watch", &["-n", "-d"]),
+];
+
+/// Names a command line runs: the command of each part of a pipeline or `;`/`&&`/`||`
+/// chain (after `VAR=x` assignments, and including wrappers like `sudo` or `env`), both as
+/// written and by file name, plus packages run through `npx`, `bunx`, `pnpm dlx`,
+/// `yarn dlx`, or `npm exec`
+pub(crate) fn invoked_names(cmd: &str) -> HashSet<&str> {
+    let mut keys = HashSet::new();
 
-    // Try fish
-    let fish_path = home.join(".local/share/fish/fish_history");
-    if let Ok(entries) = parse_fish_history(&fish_path) {
-        all_entries.extend(entries);
+    for segment in simple_commands(cmd) {
+        let (commands, args) = command_words(segment);
+
+        for word in &commands {
+            keys.insert(*word);
+            // `./bin/tool` and `/opt/homebrew/bin/tool` run `tool`; `@scope/pkg` is a package name
+            if !word.starts_with('@') {
+                keys.extend(word.rsplit('/').next());
+            }
+        }
+
+        let runner_args = match (commands.last().copied(), args.first().copied()) {
+            (Some("npx" | "pnpx" | "bunx"), _) => &args[..],
+            (Some("pnpm" | "yarn"), Some("dlx")) | (Some("npm"), Some("exec")) | (Some("bun"), Some("x")) => &args[1..],
+            _ => continue,
+        };
+        keys.extend(runner_packages(runner_args));
     }
 
-    // Sort by timestamp (newest first)
-    all_entries.sort_by(|a, b| {
-        match (a.timestamp, b.timestamp) {
-            (Some(a_time), Some(b_time)) => b_time.cmp(&a_time),
-            (Some(_), None) => std::cmp::Ordering::Less,
-            (None, Some(_)) => std::cmp::Ordering::Greater,
-            (None, None) => std::cmp::Ordering::Equal,
+    keys.remove("");
+    keys
+}
+
+/// Split a command line at `|`, `||`, `&&`, `;` and `&` outside of quotes
+fn simple_commands(cmd: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    let mut start = 0;
+
+    for (i, c) in cmd.char_indices() {
+        if escaped {
+            escaped = false;
+        } else {
+            match (quote, c) {
+                (Some('\''), '\'') => quote = None,
+                (Some('\''), _) => {}
+                (_, '\\') => escaped = true,
+                (None, '\'' | '"') => quote = Some(c),
+                (Some('"'), '"') => quote = None,
+                // `2>&1` and `&>` are redirections, not separators
+                (None, '&') if matches!(prev, '>' | '<') || cmd[i + 1..].starts_with('>') => {}
+                (None, '|' | ';' | '&' | '\n') => {
+                    segments.push(&cmd[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
         }
+        prev = c;
+    }
+    segments.push(&cmd[start..]);
+
+    segments.retain(|s| !s.trim().is_empty());
+    segments
+}
+
+/// The words of a simple command that name programs (wrappers and the command they run)
+/// and the arguments that follow
+fn command_words(segment: &str) -> (Vec<&str>, Vec<&str>) {
+    let mut words = segment.split_whitespace().map(|w| {
+        // Subshells and command substitutions: `(cd app && make)`, `$(brew --prefix)`
+        w.strip_prefix("$(")
+            .unwrap_or(w)
+            .trim_start_matches(['(', '{', '`'])
+            .trim_end_matches([')', '`'])
+            .trim_matches(['"', '\''])
     });
+    let mut commands = Vec::new();
+    let mut wrapper_options: Option<&[&str]> = None;
+
+    while let Some(word) = words.next() {
+        if word.is_empty() || is_assignment(word) {
+            continue;
+        }
+        if let Some(options) = wrapper_options {
+            if word.starts_with('-') {
+                if options.contains(&word) {
+                    words.next();
+                }
+                continue;
+            }
+        }
 
-    Ok(all_entries)
+        commands.push(word);
+        // Wrappers may be given by path, as cron jobs often do: `/usr/bin/env restic`
+        let name = word.rsplit('/').next().unwrap_or(word);
+        match WRAPPERS.iter().find(|(wrapper, _)| *wrapper == name) {
+            Some((_, options)) => wrapper_options = Some(options),
+            None => break,
+        }
+    }
+
+    (commands, words.collect())
+}
+
+/// `VAR=value`, as used before a command or after `env`
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        name.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+/// Packages named in a package runner's arguments: `-p`/`--package` values, or else the
+/// first non-flag argument, without any `@version`
+fn runner_packages<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let mut packages = Vec::new();
+    let mut iter = args.iter();
+
+    while let Some(arg) = iter.next() {
+        if matches!(*arg, "-p" | "--package") {
+            packages.extend(iter.next().map(|p| strip_version(p)));
+        } else if let Some(package) = arg.strip_prefix("--package=") {
+            packages.push(strip_version(package));
+        } else if !arg.starts_with('-') {
+            if packages.is_empty() {
+                packages.push(strip_version(arg));
+            }
+            break;
+        }
+    }
+
+    packages
+}
+
+/// `eslint@8` -> `eslint`, `@angular/cli@17.1` -> `@angular/cli`
+fn strip_version(spec: &str) -> &str {
+    spec.char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '@')
+        .map_or(spec, |(i, _)| &spec[..i])
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Write;
-    use tempfile::NamedTempFile;
+    use tempfile::{NamedTempFile, TempDir};
 
     #[test]
     fn test_parse_zsh_history() {
@@ -280,6 +904,292 @@ mod tests {
         assert!(entries[0].timestamp.is_some());
     }
 
+    #[test]
+    fn test_parse_zsh_history_metafied() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // "🎉" is F0 9F 8E 89; zsh stores 9F, 8E and 89 as 0x83 followed by the byte XOR 32
+        temp_file.write_all(b": 1700000000:0;git commit -m \"party \xf0\x83\xbf\x83\xae\x83\xa9\"\n").unwrap();
+        temp_file.write_all(": 1700000010:0;echo café\n".as_bytes()).unwrap();
+        // Invalid UTF-8 after unmetafying only spoils its own entry
+        temp_file.write_all(b": 1700000020:0;echo \xff\xfe\n").unwrap();
+        temp_file.write_all(b": 1700000030:0;rg needle\n").unwrap();
+
+        let entries = parse_zsh_history(temp_file.path()).unwrap();
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec![
+            "git commit -m \"party 🎉\"",
+            "echo café",
+            "echo \u{fffd}\u{fffd}",
+            "rg needle",
+        ]);
+        assert!(entries.iter().all(|e| e.timestamp.is_some()));
+    }
+
+    #[test]
+    fn test_parse_zsh_history_multiline() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(b": 1700000000:0;for f in *.txt; do\\\n  echo \"\xe2\x83\xa6\x83\xb2 $f\"\\\ndone\n").unwrap();
+        temp_file.write_all(b": 1700000010:0;jq . data.json\n").unwrap();
+
+        let entries = parse_zsh_history(temp_file.path()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "for f in *.txt; do\\\n  echo \"\u{2192} $f\"\\\ndone");
+        assert_eq!(entries[1].command, "jq . data.json");
+    }
+
+    #[test]
+    fn test_unmetafy() {
+        assert_eq!(unmetafy(b"plain"), b"plain");
+        assert_eq!(unmetafy(b"a\x83\xa3b"), b"a\x83b");
+        // A dangling escape at the end is dropped
+        assert_eq!(unmetafy(b"a\x83"), b"a");
+    }
+
+    #[test]
+    fn test_history_files_resolution() {
+        let home = TempDir::new().unwrap();
+        let root = home.path();
+        let write = |rel: &str, contents: &str| {
+            let path = root.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let histfile = write(".config/zsh/history", ": 1700000000:0;kubectl get pods\n");
+        let zsh = write(".zsh_history", ": 1700000100:0;git status\n");
+        let session = write(".zsh_sessions/ABC-123.history", "nvim notes.md\n");
+        write(".zsh_sessions/ABC-123.session", "echo not history\n");
+        let bash_a = write(".bash_history.d/work", "#1700000200\nterraform plan\n");
+        let bash_b = write(".bash_history.d/personal", "cargo build\n");
+        let fish = write("elsewhere/fish_history", "- cmd: fd pattern\n  when: 1700000300\n");
+
+        let extra = vec![root.join(".bash_history.d"), fish.clone(), root.join("missing_history")];
+        let files = history_files(root, Some(histfile.clone()), None, &extra);
+        assert_eq!(files, vec![histfile.clone(), zsh.clone(), session, bash_b, bash_a, fish]);
+
+        // $HISTFILE pointing at a standard location is read once
+        assert_eq!(history_files(root, Some(zsh.clone()), None, &[]), vec![zsh, root.join(".zsh_sessions/ABC-123.history")]);
+
+        let commands: Vec<String> = parse_history_files(&files).into_iter().map(|e| e.command).collect();
+        for expected in ["kubectl get pods", "git status", "nvim notes.md", "terraform plan", "cargo build", "fd pattern"] {
+            assert!(commands.contains(&expected.to_string()), "missing {}", expected);
+        }
+        assert_eq!(commands[0], "fd pattern");
+    }
+
+    #[test]
+    fn test_rotated_and_globbed_history_files() {
+        let home = TempDir::new().unwrap();
+        let root = home.path();
+        let write = |rel: &str, contents: &str| {
+            let path = root.join(rel);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let zsh = write(".zsh_history", ": 1700000300:0;git status\n");
+        let numbered = write(".zsh_history.1", ": 1700000200:0;terraform plan\n: 1700000300:0;git status\n");
+        let backup = write(".zsh_history.bak", ": 1600000000:0;ansible-playbook site.yml\n");
+        let dated = write(".zsh_history-20240601", ": 1650000000:0;helm list\n");
+        write(".zsh_history.2.gz", "compressed");
+        write(".zsh_history.LOCK", "");
+        write(".zsh_history_notes", "not history\n");
+        let bash = write(".bash_history.2024-05-01.old", "#1600000100\npacker build\n");
+        // Without timestamps its repeats of the main history couldn't be told apart
+        write(".bash_history.bak", "packer build\nls\n");
+        let archived = write("archive/zsh-2023-q4", ": 1690000000:0;vagrant up\n");
+        write("archive/notes.txt", "not history\n");
+
+        let files = history_files(root, None, None, &[root.join("archive/zsh-*")]);
+        assert_eq!(files, vec![zsh, dated, numbered, backup, bash, archived]);
+        assert!(is_rotation_suffix(".1") && is_rotation_suffix("-20240601") && is_rotation_suffix(".2024-06-01.bak"));
+        assert!(!is_rotation_suffix(".gz") && !is_rotation_suffix(".1.gz") && !is_rotation_suffix("") && !is_rotation_suffix(".LOCK"));
+
+        // The copy of `git status` in the rotated file is the same use
+        let entries = parse_history_files(&files);
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["git status", "terraform plan", "vagrant up", "helm list", "packer build", "ansible-playbook site.yml"]
+        );
+    }
+
+    #[test]
+    fn test_parse_fish_history_shapes() {
+        // Plain entries, entries with a paths block, and entries from fish versions without `when:`
+        let contents = r#"- cmd: ls -la
+  when: 1700000000
+- cmd: cp notes.md /tmp/backup
+  when: 1700000100
+  paths:
+    - notes.md
+    - /tmp/backup
+- cmd: make
+- cmd: echo "a: b" && git log --format=%h:%s
+  when: 1700000200
+- cmd: printf 'one\ntwo' | rg 'C:\\\\Users'
+  when: 1700000300
+  paths:
+    - C:\\Users
+- cmd: kubectl get pods
+"#;
+
+        let entries = parse_fish_bytes(contents.as_bytes());
+        let summary: Vec<(&str, Option<i64>)> = entries
+            .iter()
+            .map(|e| (e.command.as_str(), e.timestamp.map(|t| t.timestamp())))
+            .collect();
+        assert_eq!(summary, vec![
+            ("ls -la", Some(1700000000)),
+            ("cp notes.md /tmp/backup", Some(1700000100)),
+            ("make", None),
+            ("echo \"a: b\" && git log --format=%h:%s", Some(1700000200)),
+            ("printf 'one\ntwo' | rg 'C:\\\\Users'", Some(1700000300)),
+            ("kubectl get pods", None),
+        ]);
+    }
+
+    #[test]
+    fn test_unescape_fish() {
+        assert_eq!(unescape_fish(r"echo a\nb"), "echo a\nb");
+        assert_eq!(unescape_fish(r"rg 'C:\\Users'"), r"rg 'C:\Users'");
+        assert_eq!(unescape_fish(r"grep \d trailing\"), r"grep \d trailing\");
+    }
+
+    #[test]
+    fn test_fish_history_under_xdg_data_home() {
+        let home = TempDir::new().unwrap();
+        let root = home.path();
+        let xdg = root.join("xdg");
+        std::fs::create_dir_all(xdg.join("fish")).unwrap();
+        std::fs::write(xdg.join("fish/fish_history"), "- cmd: fd pattern\n").unwrap();
+        std::fs::create_dir_all(root.join(".local/share/fish")).unwrap();
+        std::fs::write(root.join(".local/share/fish/fish_history"), "- cmd: rg todo\n").unwrap();
+
+        assert_eq!(
+            history_files(root, None, Some(xdg.clone()), &[]),
+            vec![xdg.join("fish/fish_history"), root.join(".local/share/fish/fish_history")]
+        );
+        assert_eq!(history_files(root, None, None, &[]), vec![root.join(".local/share/fish/fish_history")]);
+    }
+
+    #[test]
+    fn test_read_file_since_only_parses_appended_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".zsh_history");
+        std::fs::write(&path, ": 1700000000:0;git status\n: 1700000010:0;jq .\n").unwrap();
+
+        let (entries, state) = read_file_since(&path, None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(state.offset, std::fs::metadata(&path).unwrap().len());
+
+        // Only the appended entry is new; a half-written line waits for the next scan
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b": 1700000020:0;rg todo\n: 1700000030:0;fd").unwrap();
+        let (entries, next) = read_file_since(&path, Some(&state)).unwrap();
+        assert_eq!(entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), vec!["rg todo"]);
+        assert_eq!(next.last_entry_at, Utc.timestamp_opt(1_700_000_020, 0).single());
+
+        file.write_all(b" -e rs\n").unwrap();
+        let (entries, next) = read_file_since(&path, Some(&next)).unwrap();
+        assert_eq!(entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), vec!["fd -e rs"]);
+
+        // Nothing new
+        let (entries, _) = read_file_since(&path, Some(&next)).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_read_file_since_rotation_and_truncation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".zsh_history");
+        std::fs::write(&path, ": 1700000000:0;git status\n: 1700000010:0;jq .\n").unwrap();
+        let (_, state) = read_file_since(&path, None).unwrap();
+
+        // zsh trims the history by writing a new file and renaming it over the old one
+        let rewritten = dir.path().join(".zsh_history.new");
+        std::fs::write(&rewritten, ": 1700000010:0;jq .\n: 1700000020:0;kubectl get pods\n").unwrap();
+        std::fs::rename(&rewritten, &path).unwrap();
+
+        let (entries, rotated) = read_file_since(&path, Some(&state)).unwrap();
+        assert_eq!(entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), vec!["kubectl get pods"]);
+        assert_ne!(rotated.inode, state.inode);
+
+        // Truncated in place: the stored offset is past the end, so the file is reparsed
+        std::fs::write(&path, ": 1700000030:0;brew upgrade\n").unwrap();
+        let (entries, truncated) = read_file_since(&path, Some(&rotated)).unwrap();
+        assert_eq!(entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), vec!["brew upgrade"]);
+        assert_eq!(truncated.offset, std::fs::metadata(&path).unwrap().len());
+    }
+
+    #[test]
+    fn test_read_history_files_is_complete_only_without_state() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".bash_history");
+        std::fs::write(&path, "ls\ncargo build\n").unwrap();
+
+        let first = read_history_files(std::slice::from_ref(&path), &[]);
+        assert!(first.is_complete);
+        assert_eq!(first.entries.len(), 2);
+
+        let second = read_history_files(std::slice::from_ref(&path), &first.files);
+        assert!(!second.is_complete);
+        assert!(second.entries.is_empty());
+    }
+
+    #[test]
+    fn test_read_history_files_after_rotation() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(".zsh_history");
+        let rotated = dir.path().join(".zsh_history.1");
+        std::fs::write(&path, ": 1700000000:0;git status\n: 1700000010:0;jq .\n").unwrap();
+        let first = read_history_files(std::slice::from_ref(&path), &[]);
+
+        // logrotate renames the file and starts a new one: the renamed file is known by its
+        // inode, so only the new entries are read
+        std::fs::rename(&path, &rotated).unwrap();
+        std::fs::write(&path, ": 1700000020:0;rg todo\n").unwrap();
+        let files = [path.clone(), rotated.clone()];
+        let renamed = read_history_files(&files, &first.files);
+        assert!(!renamed.is_complete);
+        assert_eq!(renamed.entries.iter().map(|e| e.command.as_str()).collect::<Vec<_>>(), vec!["rg todo"]);
+        assert_eq!(read_history_files(&files, &renamed.files).entries.len(), 0);
+
+        // copytruncate leaves a new file repeating entries already counted, so everything is
+        // read again and the repeats are dropped
+        let copy = dir.path().join(".zsh_history.2");
+        std::fs::copy(&path, &copy).unwrap();
+        std::fs::write(&path, ": 1700000030:0;fd -e rs\n").unwrap();
+        let files = [path, rotated, copy];
+        let copied = read_history_files(&files, &renamed.files);
+        assert!(copied.is_complete);
+        let commands: Vec<&str> = copied.entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["fd -e rs", "rg todo", "jq .", "git status"]);
+        assert_eq!(copied.files.len(), 3);
+    }
+
+    #[test]
+    fn test_default_history_path_prefers_histfile() {
+        let home = TempDir::new().unwrap();
+        let histfile = home.path().join("custom_history");
+
+        assert_eq!(default_history_path(Some(histfile.clone())), Some(histfile));
+    }
+
+    #[test]
+    fn test_session_duplicates_are_merged() {
+        let home = TempDir::new().unwrap();
+        let main = home.path().join(".zsh_history");
+        let session = home.path().join("s.history");
+        std::fs::write(&main, ": 1700000000:0;git push\n: 1700000050:0;git pull\n").unwrap();
+        std::fs::write(&session, ": 1700000000:0;git push\n").unwrap();
+
+        let entries = parse_history_files(&[main, session]);
+        assert_eq!(entries.len(), 2);
+    }
+
     #[test]
     fn test_invokes_binary() {
         let entry = HistoryEntry {
@@ -296,6 +1206,167 @@ mod tests {
         assert!(entry2.invokes_binary("npm"));
     }
 
+    #[test]
+    fn test_invokes_binary_matches_whole_commands() {
+        let invokes = |command: &str, binary: &str| {
+            HistoryEntry { command: command.to_string(), timestamp: None }.invokes_binary(binary)
+        };
+
+        // Short names no longer match longer commands that start with them
+        assert!(!invokes("gol --help", "go"));
+        assert!(!invokes("got clone https://example.com/repo", "go"));
+        assert!(!invokes("go build ./...", "gol"));
+        assert!(!invokes("goland .", "go"));
+        assert!(!invokes("github-cli pr list", "git"));
+        assert!(!invokes("nodemon server.js", "node"));
+        assert!(invokes("go test ./...", "go"));
+
+        // Wrappers, options and assignments before the command
+        assert!(invokes("sudo -E npm install -g pnpm", "npm"));
+        assert!(invokes("sudo -u admin brew upgrade", "brew"));
+        assert!(!invokes("sudo -u admin brew upgrade", "admin"));
+        assert!(invokes("VAR=1 git push", "git"));
+        assert!(invokes("env -u HOME RUST_LOG=debug cargo run", "cargo"));
+        assert!(invokes("env -u HOME RUST_LOG=debug cargo run", "env"));
+        assert!(invokes("/usr/bin/env RESTIC_REPOSITORY=/backup restic snapshots", "restic"));
+        assert!(invokes("watch -n 2 kubectl get pods", "kubectl"));
+
+        // Paths match by file name, but only whole names
+        assert!(invokes("./local-git status", "local-git"));
+        assert!(!invokes("./local-git status", "git"));
+        assert!(invokes("/opt/homebrew/bin/git log", "git"));
+        assert!(invokes("node_modules/.bin/eslint .", "eslint"));
+
+        // Every command of a pipeline or chain, but not their arguments
+        assert!(invokes("cat log|jq . && git push; make", "jq"));
+        assert!(invokes("cat log|jq . && git push; make", "git"));
+        assert!(invokes("cat log|jq . && git push; make", "make"));
+        assert!(invokes("false || (cd app && make)", "make"));
+        assert!(invokes("sleep 5 & open -a Safari", "open"));
+        assert!(invokes("cargo build 2>&1 | less", "less"));
+        assert!(!invokes("cargo build 2>&1 | less", "1"));
+        assert!(!invokes("brew install jq", "jq"));
+        assert!(!invokes("git commit -m \"go; make it faster\"", "make"));
+
+        // Multi-word app names match the start of a command
+        assert!(invokes("visual studio code --help", "Visual Studio Code"));
+        assert!(!invokes("visual studio coder", "Visual Studio Code"));
+    }
+
+    #[test]
+    fn test_history_index_matches_invokes_binary() {
+        let commands = [
+            "git status",
+            "gitk --all",
+            "sudo npm install -g typescript",
+            "cat log | jq .",
+            "  rg foo",
+            "node_modules/.bin/eslint .",
+            "Visual Studio Code --help",
+            "brew upgrade",
+        ];
+        let entries: Vec<HistoryEntry> = commands
+            .iter()
+            .enumerate()
+            .map(|(i, c)| HistoryEntry {
+                command: c.to_string(),
+                timestamp: Utc.timestamp_opt(1_700_000_000 + i as i64, 0).single(),
+            })
+            .collect();
+        let index = HistoryIndex::new(entries.clone());
+
+        for name in ["git", "gi", "gitk", "npm", "jq", "rg", "node_modules", "eslint", "visual studio code", "Brew", "wget"] {
+            let expected: Vec<_> = entries.iter().filter(|e| e.invokes_binary(name)).collect();
+            let found = index.lookup(name);
+
+            assert_eq!(found.as_ref().map(|(_, count, _)| *count as usize), Some(expected.len()).filter(|n| *n > 0), "{}", name);
+            if let Some((ts, _, command)) = found {
+                let newest = expected.iter().max_by_key(|e| e.timestamp).unwrap();
+                assert_eq!(Some(ts), newest.timestamp);
+                assert_eq!(command, newest.command);
+            }
+        }
+    }
+
+    #[test]
+    fn test_history_index_expands_aliases() {
+        let home = TempDir::new().unwrap();
+        std::fs::write(home.path().join(".zshrc"), "alias k=kubectl\nalias vim=nvim\n").unwrap();
+        std::fs::write(home.path().join(".gitconfig"), "[alias]\n  sync = !gh repo sync\n").unwrap();
+        let aliases = Aliases::load_from(home.path());
+
+        let entries: Vec<HistoryEntry> = ["k get pods", "k logs -f web", "vim todo.md", "git sync", "kubectl version"]
+            .iter()
+            .enumerate()
+            .map(|(i, c)| HistoryEntry {
+                command: c.to_string(),
+                timestamp: Utc.timestamp_opt(1_700_000_000 + i as i64, 0).single(),
+            })
+            .collect();
+        let index = HistoryIndex::with_aliases(entries, &aliases);
+
+        let (_, count, command) = index.lookup("kubectl").unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(command, "kubectl version");
+        assert_eq!(index.lookup("neovim"), None);
+        assert_eq!(index.lookup("nvim").unwrap().1, 1);
+        assert_eq!(index.lookup("gh").unwrap().2, "git sync");
+    }
+
+    #[test]
+    fn test_history_index_package_runners() {
+        let entries: Vec<HistoryEntry> = [
+            "npx eslint .",
+            "npx -y @angular/cli@17 new shop",
+            "pnpm dlx create-vite@latest app",
+            "yarn dlx prettier --check .",
+            "bunx --bun tsc --noEmit",
+            "npm exec --package=@biomejs/biome -- biome check",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| HistoryEntry {
+            command: c.to_string(),
+            timestamp: Utc.timestamp_opt(1_700_000_000 + i as i64, 0).single(),
+        })
+        .collect();
+        let index = HistoryIndex::new(entries);
+
+        for name in ["eslint", "@angular/cli", "create-vite", "prettier", "tsc", "@biomejs/biome"] {
+            assert_eq!(index.lookup(name).map(|(_, count, _)| count), Some(1), "{}", name);
+        }
+        assert!(index.lookup("latest").is_none());
+    }
+
+    #[test]
+    fn test_strip_version() {
+        assert_eq!(strip_version("eslint"), "eslint");
+        assert_eq!(strip_version("eslint@8.57.0"), "eslint");
+        assert_eq!(strip_version("@angular/cli"), "@angular/cli");
+        assert_eq!(strip_version("@angular/cli@17"), "@angular/cli");
+    }
+
+    #[test]
+    fn test_history_index_lookup_is_a_single_probe() {
+        // Synthetic 50k-entry history over 500 distinct tools
+        let entries: Vec<HistoryEntry> = (0..50_000)
+            .map(|i| HistoryEntry {
+                command: format!("tool{:03} --flag {} | grep x", i % 500, i),
+                timestamp: Utc.timestamp_opt(1_700_000_000 + i, 0).single(),
+            })
+            .collect();
+        let index = HistoryIndex::new(entries);
+
+        // Counts and last use are worked out once per command, not on each lookup
+        assert!(index.stats.len() <= 502, "{} keys", index.stats.len());
+        for i in [0, 42, 499] {
+            let tool = format!("tool{:03}", i);
+            assert_eq!(index.stats[&tool].entries.len(), 100);
+            assert_eq!(index.lookup(&tool), index.scan(&tool));
+        }
+        assert_eq!(index.lookup("tool500"), None);
+    }
+
     #[test]
     fn test_base_command() {
         let entry = HistoryEntry {
@@ -1,27 +1,33 @@
 // macOS Spotlight metadata for GUI apps
-use anyhow::{Context, Result};
+use crate::error::{Context, MacsweepError, Result};
+use crate::utils::process::output;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use lazy_static::lazy_static;
 use regex::Regex;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
+
+/// Paths per `mdls` invocation, to stay well below the argument length limit
+const MDLS_BATCH_SIZE: usize = 200;
 
 lazy_static! {
-    // Pattern for parsing mdls datetime: "2026-01-18 21:35:48 +0000"
+    // Pattern for parsing mdls datetime: "2026-01-18 21:35:48 +0000", with or without the
+    // `kMDItemLastUsedDate = ` prefix (`-raw` leaves it out)
     static ref MDLS_DATETIME_RE: Regex =
-        Regex::new(r"(\d{4})-(\d{2})-(\d{2}) (\d{2}):(\d{2}):(\d{2})").unwrap();
+        Regex::new(r"(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2})(?:\s+([+-]\d{4}))?").unwrap();
 
     // Pattern for extracting numeric values
     static ref MDLS_NUMBER_RE: Regex = Regex::new(r"=\s*(\d+)").unwrap();
 }
 
 /// Get the last used date for an application from Spotlight metadata
-pub fn get_spotlight_last_used(app_path: &Path) -> Result<Option<DateTime<Utc>>> {
-    let output = Command::new("mdls")
-        .args(["-name", "kMDItemLastUsedDate", "-raw"])
-        .arg(app_path)
-        .output()
-        .context("Failed to run mdls command")?;
+pub fn get_spotlight_last_used(app_path: &Path, timeout: Duration) -> Result<Option<DateTime<Utc>>> {
+    let output = output(
+        Command::new("mdls").args(["-name", "kMDItemLastUsedDate", "-raw"]).arg(app_path),
+        timeout,
+    )?;
 
     if !output.status.success() {
         return Ok(None);
@@ -34,12 +40,8 @@ pub fn get_spotlight_last_used(app_path: &Path) -> Result<Option<DateTime<Utc>>>
 }
 
 /// Get the usage count for an application from Spotlight metadata
-pub fn get_spotlight_use_count(app_path: &Path) -> Result<Option<u32>> {
-    let output = Command::new("mdls")
-        .args(["-name", "kMDItemUseCount"])
-        .arg(app_path)
-        .output()
-        .context("Failed to run mdls command")?;
+pub fn get_spotlight_use_count(app_path: &Path, timeout: Duration) -> Result<Option<u32>> {
+    let output = output(Command::new("mdls").args(["-name", "kMDItemUseCount"]).arg(app_path), timeout)?;
 
     if !output.status.success() {
         return Ok(None);
@@ -59,12 +61,12 @@ pub fn get_spotlight_use_count(app_path: &Path) -> Result<Option<u32>> {
 }
 
 /// Get both last used date and use count in one call (more efficient)
-pub fn get_spotlight_usage(app_path: &Path) -> Result<(Option<DateTime<Utc>>, Option<u32>)> {
-    let output = Command::new("mdls")
-        .args(["-name", "kMDItemLastUsedDate", "-name", "kMDItemUseCount"])
-        .arg(app_path)
-        .output()
-        .context("Failed to run mdls command")?;
+pub fn get_spotlight_usage(app_path: &Path, timeout: Duration) -> Result<(Option<DateTime<Utc>>, Option<u32>)> {
+    let _permit = crate::utils::process::command_permit();
+    let output = output(
+        Command::new("mdls").args(["-name", "kMDItemLastUsedDate", "-name", "kMDItemUseCount"]).arg(app_path),
+        timeout,
+    )?;
 
     if !output.status.success() {
         return Ok((None, None));
@@ -83,30 +85,109 @@ pub fn get_spotlight_usage(app_path: &Path) -> Result<(Option<DateTime<Utc>>, Op
     Ok((last_used, use_count))
 }
 
+/// Spotlight usage metadata for a single application
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpotlightUsage {
+    pub last_used: Option<DateTime<Utc>>,
+    pub use_count: Option<u32>,
+}
+
+/// Spotlight metadata for many applications, fetched up front with batched `mdls` calls
+#[derive(Debug, Default)]
+pub struct SpotlightCache {
+    entries: HashMap<PathBuf, SpotlightUsage>,
+}
+
+impl SpotlightCache {
+    /// Look up usage for all `paths`, killing a batch's `mdls` after `timeout`; batches that
+    /// fail or time out are left out so callers can fall back to [`get_spotlight_usage`]
+    pub fn load(paths: &[&Path], timeout: Duration) -> Result<Self> {
+        let mut entries = HashMap::new();
+
+        for batch in paths.chunks(MDLS_BATCH_SIZE) {
+            let output = match output(
+                Command::new("mdls").args(["-raw", "-name", "kMDItemLastUsedDate", "-name", "kMDItemUseCount"]).args(batch),
+                timeout,
+            ) {
+                Ok(output) => output,
+                Err(e @ MacsweepError::TimedOut { .. }) => {
+                    tracing::warn!(command = "mdls", apps = batch.len(), error = %e, "Batched Spotlight lookup timed out, looking the apps up one by one");
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            match parse_mdls_raw_batch(&stdout, batch.len()) {
+                Some(usages) => {
+                    entries.extend(batch.iter().map(|p| p.to_path_buf()).zip(usages));
+                }
+                None => {
+                    tracing::warn!(command = "mdls", apps = batch.len(), "Unexpected mdls output, looking the apps up one by one");
+                }
+            }
+        }
+
+        Ok(Self { entries })
+    }
+
+    pub fn from_entries(entries: impl IntoIterator<Item = (PathBuf, SpotlightUsage)>) -> Self {
+        Self { entries: entries.into_iter().collect() }
+    }
+
+    pub fn get(&self, app_path: &Path) -> Option<SpotlightUsage> {
+        self.entries.get(app_path).copied()
+    }
+}
+
+/// Parse `mdls -raw` output for `count` files queried for last-used date and use count.
+///
+/// Raw values are NUL-separated, two per file in argument order; None if the output
+/// doesn't line up (e.g. a file vanished mid-scan).
+fn parse_mdls_raw_batch(output: &str, count: usize) -> Option<Vec<SpotlightUsage>> {
+    let mut values: Vec<&str> = output.split('\0').collect();
+    if values.len() == count * 2 + 1 && values.last() == Some(&"") {
+        values.pop();
+    }
+    if values.len() != count * 2 {
+        return None;
+    }
+
+    Some(
+        values
+            .chunks(2)
+            .map(|pair| SpotlightUsage {
+                last_used: parse_mdls_datetime(pair[0]).ok().flatten(),
+                use_count: pair[1].trim().parse().ok(),
+            })
+            .collect(),
+    )
+}
+
 fn parse_mdls_datetime(output: &str) -> Result<Option<DateTime<Utc>>> {
     // Check if the value is "(null)"
     if output.contains("(null)") {
         return Ok(None);
     }
 
-    // Try to parse datetime from the output
-    if let Some(caps) = MDLS_DATETIME_RE.captures(output) {
-        let year: i32 = caps[1].parse()?;
-        let month: u32 = caps[2].parse()?;
-        let day: u32 = caps[3].parse()?;
-        let hour: u32 = caps[4].parse()?;
-        let minute: u32 = caps[5].parse()?;
-        let second: u32 = caps[6].parse()?;
+    let Some(caps) = MDLS_DATETIME_RE.captures(output) else {
+        return Ok(None);
+    };
 
-        let naive_date = chrono::NaiveDate::from_ymd_opt(year, month, day)
-            .ok_or_else(|| anyhow::anyhow!("Invalid date"))?;
-        let naive_time = chrono::NaiveTime::from_hms_opt(hour, minute, second)
-            .ok_or_else(|| anyhow::anyhow!("Invalid time"))?;
-        let naive_dt = NaiveDateTime::new(naive_date, naive_time);
-        return Ok(Some(DateTime::from_naive_utc_and_offset(naive_dt, Utc)));
-    }
+    let parsed = match caps.get(2) {
+        Some(offset) => {
+            let text = format!("{} {}", &caps[1], offset.as_str());
+            DateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S %z")
+                .with_context(|| format!("Invalid mdls date {}", text))?
+                .with_timezone(&Utc)
+        }
+        // Without an offset the value is UTC, which is what mdls prints by default
+        None => NaiveDateTime::parse_from_str(&caps[1], "%Y-%m-%d %H:%M:%S")
+            .with_context(|| format!("Invalid mdls date {}", &caps[1]))?
+            .and_utc(),
+    };
 
-    Ok(None)
+    Ok(Some(parsed))
 }
 
 #[cfg(test)]
@@ -134,12 +215,55 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_parse_mdls_raw_null_and_empty() {
+        assert!(parse_mdls_datetime("(null)").unwrap().is_none());
+        assert!(parse_mdls_datetime("").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_mdls_offsets() {
+        use chrono::TimeZone;
+
+        // 23:30 in San Francisco is already the next day in UTC
+        let pacific = parse_mdls_datetime("kMDItemLastUsedDate = 2026-01-18 23:30:00 -0800").unwrap();
+        assert_eq!(pacific, Some(Utc.with_ymd_and_hms(2026, 1, 19, 7, 30, 0).unwrap()));
+
+        let tokyo = parse_mdls_datetime("2026-01-18 01:15:00 +0900").unwrap();
+        assert_eq!(tokyo, Some(Utc.with_ymd_and_hms(2026, 1, 17, 16, 15, 0).unwrap()));
+
+        let raw = parse_mdls_datetime("2026-01-18 21:35:48 +0000").unwrap();
+        assert_eq!(raw, Some(Utc.with_ymd_and_hms(2026, 1, 18, 21, 35, 48).unwrap()));
+
+        let no_offset = parse_mdls_datetime("2026-01-18 21:35:48").unwrap();
+        assert_eq!(no_offset, raw);
+
+        assert!(parse_mdls_datetime("2026-02-30 10:00:00 +0000").is_err());
+    }
+
+    #[test]
+    fn test_parse_mdls_raw_batch() {
+        let output = "2026-01-18 21:35:48 +0000\x001033\x00(null)\x00(null)\x002025-06-01 08:00:00 +0000\x00(null)";
+        let usages = parse_mdls_raw_batch(output, 3).unwrap();
+
+        assert_eq!(usages.len(), 3);
+        assert!(usages[0].last_used.is_some());
+        assert_eq!(usages[0].use_count, Some(1033));
+        assert_eq!(usages[1], SpotlightUsage::default());
+        assert!(usages[2].last_used.is_some());
+        assert_eq!(usages[2].use_count, None);
+
+        // Trailing separator is tolerated, a short read is not
+        assert_eq!(parse_mdls_raw_batch("(null)\x005\x00", 1).unwrap()[0].use_count, Some(5));
+        assert!(parse_mdls_raw_batch("(null)\x005", 2).is_none());
+    }
+
     #[test]
     #[ignore] // Run manually on macOS
     fn test_get_spotlight_usage() {
         let app_path = Path::new("/Applications/Arc.app");
         if app_path.exists() {
-            let (last_used, use_count) = get_spotlight_usage(app_path).unwrap();
+            let (last_used, use_count) = get_spotlight_usage(app_path, Duration::from_secs(5)).unwrap();
             println!("Last used: {:?}", last_used);
             println!("Use count: {:?}", use_count);
         }
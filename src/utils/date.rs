@@ -1,29 +1,142 @@
 // Date formatting utilities
-use chrono::{DateTime, Utc, Local};
+use crate::error::{MacsweepError, Result};
+use chrono::{DateTime, Utc, Local, NaiveDate};
+
+/// How dates such as last used and install date are displayed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DateDisplay {
+    /// Calendar date, e.g. `2024-11-02`
+    Absolute,
+    /// Age, e.g. `3 months ago`
+    #[default]
+    Relative,
+    /// Both, e.g. `2024-11-02 (3 months ago)`
+    Both,
+}
 
 pub fn format_datetime(dt: &DateTime<Utc>) -> String {
     let local: DateTime<Local> = DateTime::from(*dt);
     local.format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+/// Format a date for display according to `mode`
+pub fn format_date(dt: &DateTime<Utc>, mode: DateDisplay) -> String {
+    format_date_at(dt, Utc::now(), mode)
+}
+
+fn format_date_at(dt: &DateTime<Utc>, now: DateTime<Utc>, mode: DateDisplay) -> String {
+    let absolute = dt.format("%Y-%m-%d").to_string();
+    let days = days_between(dt, now);
+
+    match mode {
+        DateDisplay::Absolute => absolute,
+        DateDisplay::Relative => format_days_ago(days),
+        DateDisplay::Both => format!("{} ({})", absolute, format_days_ago(days).to_lowercase()),
+    }
+}
+
+/// Parse a point in time given as a date (`2024-06-30`, midnight UTC) or as an age in days
+/// (`365`, `365d`, `365 days`), counted back from now
+pub fn parse_date_or_age(text: &str) -> Result<DateTime<Utc>> {
+    parse_date_or_age_at(text, Utc::now())
+}
+
+fn parse_date_or_age_at(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc());
+    }
+    let days = text
+        .strip_suffix("days")
+        .or_else(|| text.strip_suffix("day"))
+        .or_else(|| text.strip_suffix('d'))
+        .unwrap_or(text)
+        .trim_end();
+    days.parse::<u32>()
+        .ok()
+        .and_then(|days| now.checked_sub_signed(chrono::Duration::days(days.into())))
+        .ok_or_else(|| MacsweepError::Other(format!("Invalid date '{}'; use e.g. 2024-06-30 or 365d", text)))
+}
+
 pub fn days_since(dt: &DateTime<Utc>) -> u32 {
-    let now = Utc::now();
-    let duration = now.signed_duration_since(*dt);
-    duration.num_days() as u32
+    days_between(dt, Utc::now())
+}
+
+/// Whole days from `dt` to `now`; dates slightly in the future (clock skew) count as today
+fn days_between(dt: &DateTime<Utc>, now: DateTime<Utc>) -> u32 {
+    now.signed_duration_since(*dt).num_days().max(0) as u32
 }
 
 pub fn format_days_ago(days: u32) -> String {
-    if days == 0 {
-        "Today".to_string()
-    } else if days == 1 {
-        "Yesterday".to_string()
-    } else if days < 7 {
-        format!("{} days ago", days)
-    } else if days < 30 {
-        format!("{} weeks ago", days / 7)
-    } else if days < 365 {
-        format!("{} months ago", days / 30)
+    let (count, unit) = match days {
+        0 => return "Today".to_string(),
+        1 => return "Yesterday".to_string(),
+        2..=6 => (days, "day"),
+        7..=29 => (days / 7, "week"),
+        30..=364 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+
+    if count == 1 {
+        format!("1 {} ago", unit)
     } else {
-        format!("{} years ago", days / 365)
+        format!("{} {}s ago", count, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_parse_date_or_age() {
+        let now = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+        let midnight = Utc.with_ymd_and_hms(2024, 6, 30, 0, 0, 0).unwrap();
+        assert_eq!(parse_date_or_age_at("2024-06-30", now).unwrap(), midnight);
+        for age in ["365", "365d", "365 days", " 365days "] {
+            assert_eq!(parse_date_or_age_at(age, now).unwrap(), now - Duration::days(365), "{:?}", age);
+        }
+        assert_eq!(parse_date_or_age_at("1 day", now).unwrap(), now - Duration::days(1));
+        for invalid in ["", "last year", "2024-13-01", "-5d", "d"] {
+            assert!(parse_date_or_age_at(invalid, now).is_err(), "{:?} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_days_ago_buckets() {
+        assert_eq!(format_days_ago(0), "Today");
+        assert_eq!(format_days_ago(1), "Yesterday");
+        assert_eq!(format_days_ago(6), "6 days ago");
+        assert_eq!(format_days_ago(7), "1 week ago");
+        assert_eq!(format_days_ago(29), "4 weeks ago");
+        assert_eq!(format_days_ago(30), "1 month ago");
+        assert_eq!(format_days_ago(364), "12 months ago");
+        assert_eq!(format_days_ago(365), "1 year ago");
+        assert_eq!(format_days_ago(800), "2 years ago");
+    }
+
+    #[test]
+    fn test_format_date_modes() {
+        let now = Utc.with_ymd_and_hms(2025, 2, 10, 12, 0, 0).unwrap();
+        let installed = now - Duration::days(100);
+
+        assert_eq!(format_date_at(&installed, now, DateDisplay::Absolute), "2024-11-02");
+        assert_eq!(format_date_at(&installed, now, DateDisplay::Relative), "3 months ago");
+        assert_eq!(format_date_at(&installed, now, DateDisplay::Both), "2024-11-02 (3 months ago)");
+        assert_eq!(format_date_at(&now, now, DateDisplay::Both), "2025-02-10 (today)");
+    }
+
+    #[test]
+    fn test_days_between_near_day_boundaries() {
+        let now = Utc.with_ymd_and_hms(2025, 2, 10, 0, 5, 0).unwrap();
+
+        assert_eq!(days_between(&(now - Duration::hours(23) - Duration::minutes(59)), now), 0);
+        assert_eq!(days_between(&(now - Duration::hours(24)), now), 1);
+        assert_eq!(days_between(&(now - Duration::days(30) + Duration::seconds(1)), now), 29);
+        assert_eq!(days_between(&(now - Duration::days(30)), now), 30);
+        assert_eq!(days_between(&(now + Duration::minutes(10)), now), 0);
     }
 }
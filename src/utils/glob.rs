@@ -0,0 +1,53 @@
+// Shell-style wildcard matching for package name patterns
+/// Whether `text` matches `pattern`, where `*` matches any run of characters and `?` any
+/// single character; case-insensitive, like package names
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Greedy matching with backtracking to the last `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("python*", "python@3.11"));
+        assert!(glob_match("python*", "Python"));
+        assert!(glob_match("node", "node"));
+        assert!(!glob_match("node", "node@18"));
+        assert!(glob_match("*-cli", "@angular/cli-cli"));
+        assert!(glob_match("lib?aml", "libyaml"));
+        assert!(glob_match("*yaml*", "libyaml"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("?", ""));
+    }
+}
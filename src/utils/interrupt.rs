@@ -0,0 +1,28 @@
+// Ctrl-C handling for operations that should stop between steps rather than mid-step
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static HANDLER: Once = Once::new();
+
+/// Catch Ctrl-C so the caller can stop at its next check of `interrupted`; a second
+/// Ctrl-C exits immediately
+pub fn install_handler() -> Result<()> {
+    let mut result = Ok(());
+    HANDLER.call_once(|| {
+        result = ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                std::process::exit(130);
+            }
+            eprintln!("\nInterrupted: finishing the current step (press Ctrl-C again to quit now)");
+        })
+        .context("Failed to install Ctrl-C handler");
+    });
+    result
+}
+
+/// Whether Ctrl-C has been pressed since the handler was installed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -2,8 +2,12 @@
 pub mod size;
 pub mod date;
 pub mod process;
+#[cfg(feature = "cli")]
+pub mod interrupt;
+pub mod glob;
+pub mod notify;
 
 // Re-export commonly used utilities
 pub use size::calculate_directory_size;
 pub use date::format_datetime;
-pub use process::run_command;
+pub use process::run_command_with_timeout;
@@ -0,0 +1,27 @@
+// macOS user notifications, posted through osascript
+use super::process::run_command_with_timeout;
+use crate::error::Result;
+use std::time::Duration;
+
+/// Post a notification to Notification Center, giving osascript `timeout`
+pub fn post(title: &str, message: &str, timeout: Duration) -> Result<()> {
+    let script = format!("display notification {} with title {}", applescript_string(message), applescript_string(title));
+    run_command_with_timeout("osascript", &["-e", &script], timeout)?;
+    Ok(())
+}
+
+/// A double-quoted AppleScript string
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_applescript_string() {
+        assert_eq!(applescript_string("4.2 GB"), "\"4.2 GB\"");
+        assert_eq!(applescript_string(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+    }
+}
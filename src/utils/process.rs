@@ -1,24 +1,291 @@
 // Process/command execution utilities
-use anyhow::{Context, Result};
-use std::process::Command;
+use crate::error::{Context, MacsweepError, Result};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::io::{Read, Write};
+use std::process::{Command, ExitStatus, Output, Stdio};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
-pub fn run_command(program: &str, args: &[&str]) -> Result<String> {
-    let output = Command::new(program)
-        .args(args)
-        .output()
-        .context(format!("Failed to execute: {} {:?}", program, args))?;
+/// Most external commands run at once, so parallel usage gathering can't fork a process per package
+pub const MAX_CONCURRENT_COMMANDS: usize = 4;
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("Command failed: {}", stderr);
+lazy_static! {
+    static ref COMMAND_SLOTS: Semaphore = Semaphore::new(MAX_CONCURRENT_COMMANDS);
+}
+
+/// A counting semaphore; `acquire` blocks until one of the slots is free
+struct Semaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Semaphore {
+    fn new(slots: usize) -> Self {
+        Self { available: Mutex::new(slots), released: Condvar::new() }
+    }
+
+    fn acquire(&self) -> CommandPermit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|e| e.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|e| e.into_inner());
+        }
+        *available -= 1;
+        CommandPermit(self)
+    }
+}
+
+/// A slot for running one external command, given back when dropped
+pub struct CommandPermit<'a>(&'a Semaphore);
+
+impl Drop for CommandPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Wait for a slot to run an external command; hold the permit until the command exits
+pub fn command_permit() -> CommandPermit<'static> {
+    COMMAND_SLOTS.acquire()
+}
+
+/// What an external command is for, which decides how long it may run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Package managers and the other commands a scan runs, such as `brew info` or `ps`
+    Scanner,
+    /// Spotlight metadata lookups
+    Mdls,
+    /// Uninstallers, and the installs that undo a cleanup
+    Uninstall,
+}
+
+/// How many seconds each kind of command may run before it is killed; `[timeouts]` in the
+/// config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CommandTimeouts {
+    pub scanners: u64,
+    pub mdls: u64,
+    pub uninstalls: u64,
+}
+
+impl Default for CommandTimeouts {
+    fn default() -> Self {
+        Self { scanners: 60, mdls: 5, uninstalls: 300 }
     }
+}
 
-    let stdout = String::from_utf8(output.stdout)
-        .context("Failed to parse command output as UTF-8")?;
+impl CommandTimeouts {
+    pub fn get(&self, kind: CommandKind) -> Duration {
+        Duration::from_secs(match kind {
+            CommandKind::Scanner => self.scanners,
+            CommandKind::Mdls => self.mdls,
+            CommandKind::Uninstall => self.uninstalls,
+        })
+    }
+}
+
+/// Everything a command printed, and how it exited
+#[derive(Debug)]
+pub struct CommandOutput {
+    /// None when the command was killed for running past its deadline
+    pub status: Option<ExitStatus>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Run `command` to completion with its output captured, like `Command::output`, killing it if
+/// it is still running after `timeout`; errors mean it couldn't be started
+pub fn output_within(command: &mut Command, timeout: Duration) -> std::io::Result<CommandOutput> {
+    run_within(command, None, timeout)
+}
+
+/// `output_within`, with `input` written to the command's stdin
+pub fn output_with_input(command: &mut Command, input: &[u8], timeout: Duration) -> std::io::Result<CommandOutput> {
+    run_within(command, Some(input.to_vec()), timeout)
+}
+
+fn run_within(command: &mut Command, input: Option<Vec<u8>>, timeout: Duration) -> std::io::Result<CommandOutput> {
+    use std::os::unix::process::CommandExt;
+
+    let started = Instant::now();
+    let stdin = if input.is_some() { Stdio::piped() } else { Stdio::null() };
+    // In a process group of its own, so a timeout also kills what it started (brew is a shell
+    // script running Ruby) and Ctrl-C in the terminal doesn't stop an uninstaller halfway
+    let mut child = command.process_group(0).stdin(stdin).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    // Written on its own thread too; a command that doesn't read it all just closes the pipe
+    if let (Some(input), Some(mut pipe)) = (input, child.stdin.take()) {
+        std::thread::spawn(move || pipe.write_all(&input));
+    }
+
+    // Drained on their own threads so a program with a lot to say can't block on a full pipe
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            // SAFETY: killpg only sends a signal; the group is the child's own, led by its pid
+            unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    match status {
+        Some(status) => tracing::debug!(command = %command_line(command), code = ?status.code(), elapsed_ms, "Command finished"),
+        None => tracing::debug!(command = %command_line(command), elapsed_ms, "Command timed out and was killed"),
+    }
 
-    Ok(stdout)
+    // Children that left the group may still hold the pipes open; keep what arrived
+    let collect = |pipe: mpsc::Receiver<Vec<u8>>| match status {
+        Some(_) => pipe.recv().unwrap_or_default(),
+        None => pipe.recv_timeout(Duration::from_millis(500)).unwrap_or_default(),
+    };
+    Ok(CommandOutput { status, stdout: collect(stdout), stderr: collect(stderr) })
+}
+
+/// `output_within`, for callers that check the exit status themselves. A command still running
+/// after `timeout` is killed and reported as `TimedOut`.
+pub fn output(command: &mut Command, timeout: Duration) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = output_within(command, timeout)
+        .map_err(|e| MacsweepError::spawn(&program, format!("Failed to run {}", command_line(command)), e))?;
+    match output.status {
+        Some(status) => Ok(Output { status, stdout: output.stdout, stderr: output.stderr }),
+        None => Err(MacsweepError::TimedOut { program, timeout }),
+    }
+}
+
+/// Program and arguments, space-separated
+pub fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run a quick lookup such as `ps` or `crontab -l`, returning its stdout; it fails if it exits
+/// with an error, and the program is killed if it is still running after `timeout`
+pub fn run_command_with_timeout(program: &str, args: &[&str], timeout: Duration) -> Result<String> {
+    let _permit = command_permit();
+    let output = output_within(Command::new(program).args(args), timeout)
+        .map_err(|e| MacsweepError::spawn(program, format!("Failed to execute: {} {:?}", program, args), e))?;
+
+    let Some(status) = output.status else {
+        return Err(MacsweepError::TimedOut { program: program.to_string(), timeout });
+    };
+    if !status.success() {
+        return Err(MacsweepError::CommandFailed {
+            program: program.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    String::from_utf8(output.stdout).context("Failed to parse command output as UTF-8")
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        let _ = sender.send(output);
+    });
+    receiver
+}
+
+/// Runs the external commands a scanner depends on, so tests can stand in for the real programs
+pub trait CommandRunner {
+    fn run(&self, program: &str, args: &[&str], timeout: Duration) -> Result<String>;
+}
+
+/// Runs commands for real
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str], timeout: Duration) -> Result<String> {
+        run_command_with_timeout(program, args, timeout)
+    }
 }
 
 pub fn command_exists(program: &str) -> bool {
     which::which(program).is_ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_output_with_input() {
+        let output = output_with_input(Command::new("sh").args(["-c", "tr a-z A-Z"]), b"report", Duration::from_secs(5)).unwrap();
+        assert!(output.status.unwrap().success());
+        assert_eq!(output.stdout, b"REPORT");
+
+        // Input the command never reads doesn't hold it up
+        let output = output_with_input(Command::new("true").arg("ignored"), &[b'x'; 1 << 20], Duration::from_secs(5)).unwrap();
+        assert!(output.status.unwrap().success());
+    }
+
+    #[test]
+    fn test_semaphore_bounds_concurrency() {
+        let slots = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let _permit = slots.acquire();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(*slots.available.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_run_command_with_timeout() {
+        let output = run_command_with_timeout("sh", &["-c", "echo formulae"], Duration::from_secs(10)).unwrap();
+        assert_eq!(output.trim(), "formulae");
+
+        let start = Instant::now();
+        let err = run_command_with_timeout("sh", &["-c", "sleep 5"], Duration::from_millis(200)).unwrap_err();
+        assert!(matches!(err, MacsweepError::TimedOut { ref program, .. } if program == "sh"));
+        assert!(start.elapsed() < Duration::from_secs(4));
+
+        let err = run_command_with_timeout("sh", &["-c", "echo broken >&2; exit 3"], Duration::from_secs(10)).unwrap_err();
+        assert_eq!(err.to_string(), "sh failed: broken");
+    }
+
+    #[test]
+    fn test_timeout_kills_what_the_command_started() {
+        let output = output_within(Command::new("sh").args(["-c", "sleep 30 & echo $!; wait"]), Duration::from_millis(300)).unwrap();
+        assert!(output.status.is_none());
+
+        // The sleep held stdout open; killed along with sh, it lets everything printed through
+        let sleep: libc::pid_t = String::from_utf8(output.stdout).unwrap().trim().parse().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while unsafe { libc::kill(sleep, 0) } == 0 && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert_ne!(unsafe { libc::kill(sleep, 0) }, 0);
+    }
+}
@@ -1,20 +1,105 @@
 // Disk size calculation utilities
-use anyhow::Result;
-use std::path::Path;
+use crate::error::{MacsweepError, Result};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
 use walkdir::WalkDir;
 
+/// Total size of the files under `path`, walking its top-level entries in parallel
 pub fn calculate_directory_size(path: &Path) -> Result<u64> {
-    let mut total_size = 0;
+    let entries: Vec<PathBuf> = match std::fs::read_dir(path) {
+        Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+        Err(_) => return Ok(files_size(path)),
+    };
+    Ok(entries.par_iter().map(|entry| files_size(entry)).sum())
+}
+
+fn files_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_root_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// A directory's measured size, trusted until the directory's own modification time changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedSize {
+    pub size_bytes: u64,
+    /// The directory's modification time when it was measured, in nanoseconds since the epoch
+    pub modified_ns: i64,
+    pub measured_at: DateTime<Utc>,
+}
 
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            if let Ok(metadata) = entry.metadata() {
-                total_size += metadata.len();
+/// Directory sizes kept from earlier scans. `size_of` answers from it for directories whose
+/// top-level modification time hasn't changed; a scan hands it to its scanners.
+#[derive(Debug, Default)]
+pub struct SizeCache {
+    entries: Mutex<HashMap<PathBuf, CachedSize>>,
+    /// Sizes measured since the cache was loaded, to be saved
+    measured: Mutex<HashMap<PathBuf, CachedSize>>,
+    /// Measure every directory again (`--recalculate-sizes`)
+    recalculate: bool,
+    /// Measure nothing (`scan --no-size`), leaving every size unknown
+    skip: bool,
+    reused: AtomicUsize,
+}
+
+impl SizeCache {
+    pub fn new(entries: HashMap<PathBuf, CachedSize>, recalculate: bool) -> Self {
+        Self { entries: Mutex::new(entries), recalculate, ..Default::default() }
+    }
+
+    /// A cache that measures nothing: every `size_of` fails, so packages are left without a size
+    pub fn skipping() -> Self {
+        Self { skip: true, ..Default::default() }
+    }
+
+    /// `path`'s size, measured only if it isn't cached or has been modified since
+    pub fn size_of(&self, path: &Path) -> Result<u64> {
+        if self.skip {
+            return Err(MacsweepError::Other(format!("Not measuring {} in this scan", path.display())));
+        }
+        let Some(modified_ns) = modified_ns(path) else {
+            return calculate_directory_size(path);
+        };
+        if !self.recalculate {
+            let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(cached) = entries.get(path).filter(|cached| cached.modified_ns == modified_ns) {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                return Ok(cached.size_bytes);
             }
         }
+
+        let size_bytes = calculate_directory_size(path)?;
+        let entry = CachedSize { size_bytes, modified_ns, measured_at: Utc::now() };
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).insert(path.to_path_buf(), entry);
+        self.measured.lock().unwrap_or_else(|e| e.into_inner()).insert(path.to_path_buf(), entry);
+        Ok(size_bytes)
+    }
+
+    /// How many sizes came from the cache
+    pub fn reused(&self) -> usize {
+        self.reused.load(Ordering::Relaxed)
     }
 
-    Ok(total_size)
+    /// The sizes measured while the cache was in use, to save for the next scan
+    pub fn measured(&self) -> HashMap<PathBuf, CachedSize> {
+        self.measured.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// When `path` was last modified, in nanoseconds since the epoch
+pub fn modified_ns(path: &Path) -> Option<i64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as i64)
 }
 
 pub fn format_size(bytes: u64) -> String {
@@ -33,6 +118,35 @@ pub fn format_size(bytes: u64) -> String {
     }
 }
 
+/// Parse a size as `format_size` shows it, or as typed: `1024`, `500MB`, `500 mb`, `1.5GB`,
+/// `2KiB`. Units are binary (1 KB is 1024 bytes); a bare number is bytes.
+pub fn parse_size(text: &str) -> Result<u64> {
+    let invalid = || MacsweepError::Other(format!("Invalid size '{}'; use e.g. 500MB, 1.5GB or 1024", text));
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        "t" | "tb" | "tib" => 1024 * 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(invalid());
+    }
+    Ok(bytes.round() as u64)
+}
+
+/// Signed size change, e.g. `+1.5 MB` or `-200 B`
+pub fn format_size_delta(delta: i64) -> String {
+    let sign = if delta < 0 { "-" } else { "+" };
+    format!("{}{}", sign, format_size(delta.unsigned_abs()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -67,6 +181,63 @@ mod tests {
         assert_eq!(format_size(1536 * 1024 * 1024), "1.5 GB");
     }
 
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("500mb").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("500MB").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("1.5GB").unwrap(), 1536 * 1024 * 1024);
+        assert_eq!(parse_size(" 1.5 GB ").unwrap(), 1536 * 1024 * 1024);
+        assert_eq!(parse_size("2KiB").unwrap(), 2048);
+        assert_eq!(parse_size("10k").unwrap(), 10240);
+        assert_eq!(parse_size("1TB").unwrap(), 1 << 40);
+        assert_eq!(parse_size("12 B").unwrap(), 12);
+        // What format_size prints reads back
+        assert_eq!(parse_size(&format_size(5 * 1024 * 1024)).unwrap(), 5 * 1024 * 1024);
+
+        for invalid in ["", "GB", "1.5.2GB", "-1MB", "500 megs", "1e3", "99999999999PB", "1 GB extra"] {
+            assert!(parse_size(invalid).is_err(), "{:?} should not parse", invalid);
+        }
+        assert_eq!(parse_size("lots").unwrap_err().to_string(), "Invalid size 'lots'; use e.g. 500MB, 1.5GB or 1024");
+    }
+
+    #[test]
+    fn test_size_cache_reuses_unmodified_directories() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let app = dir.path().join("Slack.app");
+        std::fs::create_dir_all(app.join("Contents/Frameworks")).unwrap();
+        std::fs::write(app.join("Contents/Frameworks/Electron"), vec![0u8; 3000]).unwrap();
+        std::fs::write(app.join("Info.plist"), vec![0u8; 100]).unwrap();
+        assert_eq!(calculate_directory_size(&app).unwrap(), 3100);
+
+        let modified_ns = modified_ns(&app).unwrap();
+        let stale = CachedSize { size_bytes: 42, modified_ns, measured_at: Utc::now() };
+        let cache = SizeCache::new(HashMap::from([(app.clone(), stale)]), false);
+        assert_eq!(cache.size_of(&app).unwrap(), 42);
+        assert_eq!(cache.reused(), 1);
+        assert!(cache.measured().is_empty());
+
+        // A different modification time means the directory changed
+        let moved = CachedSize { modified_ns: modified_ns - 1, ..stale };
+        let cache = SizeCache::new(HashMap::from([(app.clone(), moved)]), false);
+        assert_eq!(cache.size_of(&app).unwrap(), 3100);
+        assert_eq!(cache.measured()[&app].size_bytes, 3100);
+
+        let cache = SizeCache::new(HashMap::from([(app.clone(), stale)]), true);
+        assert_eq!(cache.size_of(&app).unwrap(), 3100);
+        assert_eq!(cache.reused(), 0);
+    }
+
+    #[test]
+    fn test_skipping_cache_measures_nothing() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("Info.plist"), vec![0u8; 100]).unwrap();
+        let cache = SizeCache::skipping();
+        assert!(cache.size_of(dir.path()).is_err());
+        assert!(cache.measured().is_empty());
+    }
+
     #[test]
     fn test_calculate_directory_size_nonexistent() {
         let path = Path::new("/nonexistent/path");
@@ -0,0 +1,1561 @@
+// Integration tests for the macsweep command line
+use assert_cmd::Command;
+use predicates::prelude::PredicateBooleanExt;
+use macsweep::scanner::{Package, PackageSource};
+use macsweep::storage::{database, Database};
+use tempfile::TempDir;
+
+/// Database used by `macsweep(&home)`, never the real user database
+fn db_path(home: &TempDir) -> std::path::PathBuf {
+    home.path().join("data/macsweep/macsweep.db")
+}
+
+/// Run macsweep against an isolated home/data directory
+fn macsweep(home: &TempDir) -> Command {
+    let mut cmd = Command::cargo_bin("macsweep").unwrap();
+    cmd.env("HOME", home.path())
+        .env("XDG_DATA_HOME", home.path().join("data"))
+        .env("MACSWEEP_DB", db_path(home));
+    cmd
+}
+
+/// Store a package that has gone unused long enough to be recommended for removal
+fn seed_unused_package(home: &TempDir) {
+    let db = Database::open(&db_path(home)).unwrap();
+
+    let mut package = Package::new("left-pad".to_string(), PackageSource::Npm);
+    package.size_bytes = Some(4096);
+    package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+    database::upsert_package(db.conn(), &package).unwrap();
+}
+
+/// Scans of an empty sandbox find nothing, which exits with "nothing to do"
+fn scan_finished(status: std::process::ExitStatus) -> bool {
+    matches!(status.code(), Some(0) | Some(3))
+}
+
+#[test]
+fn test_scan_json_is_parseable() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--format", "json", "scan", "--quick", "--source", "applications"])
+        .output()
+        .unwrap();
+
+    assert!(scan_finished(output.status));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json["sources"].is_array());
+    assert!(json["total_packages"].is_u64());
+    assert!(json["duration_ms"].is_u64());
+    assert!(json["warnings"].is_array());
+}
+
+#[test]
+fn test_clean_dry_run_json_is_parseable() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--format", "json", "clean", "--dry-run"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["dry_run"], true);
+    assert_eq!(json["total_packages"], 0);
+    assert_eq!(json["packages"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn test_clean_json_requires_dry_run() {
+    let home = TempDir::new().unwrap();
+    macsweep(&home)
+        .args(["--format", "json", "clean", "--yes"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_piped_output_has_no_ansi_codes() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["list"])
+        .env_remove("NO_COLOR")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains('\u{1b}'));
+}
+
+#[test]
+fn test_scan_status_goes_to_stderr() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--format", "json", "scan", "--quick", "--source", "applications"])
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Scanning packages"));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Scanning packages"));
+}
+
+#[test]
+fn test_unknown_source_is_rejected() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    macsweep(&home)
+        .args(["scan", "--quick", "--source", "hombrew"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Unknown source 'hombrew' (did you mean 'homebrew'?); known sources: homebrew (brew), npm, pip (python)"));
+    macsweep(&home).args(["list", "--source", "nmp"]).assert().code(1);
+
+    // Aliases resolve to every source of their scanner
+    let output = macsweep(&home).args(["--format", "json", "list", "--source", "NPM"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["name"], "left-pad");
+    let output = macsweep(&home).args(["--format", "json", "list", "--source", "brew"]).output().unwrap();
+    assert_eq!(serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap(), serde_json::json!([]));
+}
+
+#[test]
+fn test_list_name_size_and_age_filters() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let now = chrono::Utc::now();
+    for (name, source, size, installed_days_ago, used) in [
+        ("Xcode-beta", PackageSource::Applications, 12 << 30, 400, false),
+        ("Docker", PackageSource::Applications, 2 << 30, 30, false),
+        ("python@3.11", PackageSource::Homebrew, 80 << 20, 700, true),
+        ("python@3.12", PackageSource::Homebrew, 90 << 20, 700, false),
+        ("jq", PackageSource::Homebrew, 1 << 20, 900, true),
+    ] {
+        let mut package = Package::new(name.to_string(), source);
+        package.size_bytes = Some(size);
+        package.install_date = Some(now - chrono::Duration::days(installed_days_ago));
+        package.last_used = used.then(|| now - chrono::Duration::days(3));
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+    drop(db);
+
+    let names = |args: &[&str]| -> Vec<String> {
+        let output = macsweep(&home).args(["--format", "json", "list", "--sort", "name"]).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        json.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(names(&["--min-size", "1GB", "--installed-before", "365d"]), ["Xcode-beta"]);
+    assert_eq!(names(&["--min-size", "1.5gb", "--max-size", "10 GB"]), ["Docker"]);
+    assert_eq!(names(&["--name", "python@*", "--never-used"]), ["python@3.12"]);
+    assert_eq!(names(&["--name", "PYTHON@3.1?", "--source", "brew"]), ["python@3.11", "python@3.12"]);
+    assert_eq!(names(&["--max-size", "1048576", "--installed-before", "2099-01-01"]), ["jq"]);
+
+    macsweep(&home)
+        .args(["list", "--min-size", "lots"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains("Invalid size 'lots'"));
+}
+
+#[test]
+fn test_export_manifests() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+    let db = Database::open(&db_path(&home)).unwrap();
+    for (name, source) in [
+        ("jq", PackageSource::Homebrew),
+        ("firefox", PackageSource::HomebrewCask),
+        ("requests", PackageSource::Pip),
+        ("left-pad", PackageSource::Pipx),
+    ] {
+        let mut package = Package::new(name.to_string(), source);
+        package.version = Some("1.0".to_string());
+        package.last_used = Some(chrono::Utc::now());
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+    drop(db);
+
+    macsweep(&home).args(["export", "--brewfile"]).assert().success().stdout("brew \"jq\"\ncask \"firefox\"\n");
+    macsweep(&home).args(["export", "--requirements"]).assert().success().stdout("requests==1.0\n");
+
+    // left-pad has sat unused for 200 days and is recommended for removal
+    let output = macsweep(&home).args(["export", "--npm-globals"]).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("npm install -g left-pad\n"));
+    macsweep(&home)
+        .args(["export", "--install-script", "--exclude-unused", "180"])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::contains("brew install jq")
+                .and(predicates::str::contains("npm install -g left-pad").not())
+                // Only the unused npm package is left out, not the pipx one of the same name
+                .and(predicates::str::contains("pipx install left-pad")),
+        )
+        .stderr(predicates::str::contains("Left out 1 packages"));
+
+    let brewfile = home.path().join("Brewfile");
+    macsweep(&home).args(["export", "--brewfile", "-o"]).arg(&brewfile).assert().success();
+    assert_eq!(std::fs::read_to_string(&brewfile).unwrap(), "brew \"jq\"\ncask \"firefox\"\n");
+    macsweep(&home).args(["export", "--brewfile", "-o"]).arg(&brewfile).assert().code(1);
+
+    // One manifest at a time, and one is needed
+    macsweep(&home).args(["export"]).assert().code(2);
+    macsweep(&home).args(["export", "--brewfile", "--requirements"]).assert().code(2);
+}
+
+#[test]
+fn test_export_sbom_is_valid_cyclonedx() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+    wget.version = Some("1.24.5".to_string());
+    wget.description = Some("Internet file retriever".to_string());
+    wget.dependencies = vec!["openssl@3".to_string(), "libidn2".to_string()];
+    let mut openssl = Package::new("openssl@3".to_string(), PackageSource::Homebrew);
+    openssl.version = Some("3.3.1".to_string());
+    openssl.is_dependency = true;
+    let mut cli = Package::new("@vue/cli".to_string(), PackageSource::Npm);
+    cli.version = Some("5.0.8".to_string());
+    let mut slack = Package::new("Slack".to_string(), PackageSource::Applications);
+    slack.bundle_id = Some("com.tinyspeck.slackmacgap".to_string());
+    let httpie = Package::new("httpie".to_string(), PackageSource::Pip);
+    let httpie_pipx = Package::new("httpie".to_string(), PackageSource::Pipx);
+    for package in [&wget, &openssl, &cli, &slack, &httpie, &httpie_pipx] {
+        database::upsert_package(db.conn(), package).unwrap();
+    }
+    drop(db);
+
+    let sbom = home.path().join("sbom.json");
+    macsweep(&home).args(["export", "--sbom", "--output"]).arg(&sbom).assert().success();
+    let bom: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&sbom).unwrap()).unwrap();
+    let schema: serde_json::Value = serde_json::from_str(include_str!("fixtures/bom-1.5.schema.json")).unwrap();
+    let validator = jsonschema::draft7::new(&schema).unwrap();
+    let errors: Vec<String> = validator.iter_errors(&bom).map(|e| format!("{}: {}", e.instance_path(), e)).collect();
+    assert_eq!(errors, Vec::<String>::new());
+    // The validator does catch mistakes
+    let mut broken = bom.clone();
+    broken["components"][0]["type"] = serde_json::json!("formula");
+    assert_eq!(validator.iter_errors(&broken).count(), 1);
+
+    assert_eq!((bom["bomFormat"].as_str(), bom["specVersion"].as_str()), (Some("CycloneDX"), Some("1.5")));
+    let component = |name: &str, source: &str| {
+        bom["components"].as_array().unwrap().iter().find(|c| c["name"] == name && c["properties"][0]["value"] == source).cloned().unwrap()
+    };
+    assert_eq!(component("wget", "homebrew")["purl"], "pkg:brew/wget@1.24.5");
+    assert_eq!(component("@vue/cli", "npm")["purl"], "pkg:npm/%40vue/cli@5.0.8");
+    assert_eq!(component("Slack", "applications")["purl"], "pkg:generic/com.tinyspeck.slackmacgap");
+    // A pip and a pipx package of the same name share a purl but not a bom-ref
+    assert_eq!(component("httpie", "pip")["purl"], component("httpie", "pipx")["purl"]);
+    assert_ne!(component("httpie", "pip")["bom-ref"], component("httpie", "pipx")["bom-ref"]);
+
+    // Every dependency points at a component; libidn2 isn't installed
+    let refs: Vec<&serde_json::Value> = bom["components"].as_array().unwrap().iter().map(|c| &c["bom-ref"]).collect();
+    let wget_deps = bom["dependencies"].as_array().unwrap().iter().find(|d| d["ref"] == component("wget", "homebrew")["bom-ref"]).unwrap();
+    assert_eq!(wget_deps["dependsOn"], serde_json::json!([component("openssl@3", "homebrew")["bom-ref"]]));
+    for dependency in bom["dependencies"].as_array().unwrap() {
+        assert!(refs.contains(&&dependency["ref"]));
+        assert!(dependency["dependsOn"].as_array().unwrap().iter().all(|r| refs.contains(&r)));
+    }
+}
+
+#[test]
+fn test_export_metrics_and_refresh_after_scan() {
+    let home = TempDir::new().unwrap();
+    let prom = home.path().join("prom/macsweep.prom");
+    std::fs::create_dir_all(prom.parent().unwrap()).unwrap();
+    macsweep(&home).args(["export", "--metrics"]).arg(&prom).assert().code(1);
+
+    seed_unused_package(&home);
+    macsweep(&home).args(["export", "--metrics"]).arg(&prom).assert().success();
+    let metrics = std::fs::read_to_string(&prom).unwrap();
+    assert!(metrics.contains("# TYPE macsweep_packages_total gauge\nmacsweep_packages_total{source=\"npm\"} 1\n"), "{}", metrics);
+    assert!(metrics.contains("macsweep_package_size_bytes_total{source=\"npm\"} 4096\n"));
+    assert!(metrics.contains("macsweep_reclaimable_bytes{severity=\"review\"} 4096\n"));
+    // Nothing has been scanned yet; the file is replaced without --force
+    assert!(!metrics.contains("macsweep_last_scan_timestamp_seconds"));
+    macsweep(&home).args(["export", "--metrics"]).arg(&prom).assert().success();
+    macsweep(&home).args(["export", "--metrics"]).arg(&prom).args(["-o", "other.prom"]).assert().code(2);
+
+    // With [metrics] set, every saved scan rewrites the file
+    let config = home.path().join(".config/macsweep/config.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, format!("[metrics]\ntextfile = \"{}\"\n", prom.display())).unwrap();
+    std::fs::create_dir_all(home.path().join("Applications")).unwrap();
+    let output = macsweep(&home).args(["scan", "--quick", "--source", "applications"]).output().unwrap();
+    assert!(scan_finished(output.status), "{}", String::from_utf8_lossy(&output.stderr));
+    let metrics = std::fs::read_to_string(&prom).unwrap();
+    assert!(metrics.contains("\nmacsweep_last_scan_timestamp_seconds "), "{}", metrics);
+    // Only the metrics file is left behind
+    assert_eq!(std::fs::read_dir(prom.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_compare_with_brewfile() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    for (name, source, size) in [
+        ("jq", PackageSource::Homebrew, 1024),
+        ("htop", PackageSource::Homebrew, 4096),
+        ("slack", PackageSource::HomebrewCask, 300_000_000),
+        ("left-pad", PackageSource::Npm, 2048),
+    ] {
+        let mut package = Package::new(name.to_string(), source);
+        package.size_bytes = Some(size);
+        package.last_used = Some(chrono::Utc::now());
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+    drop(db);
+    macsweep(&home).args(["tag", "slack", "keep"]).assert().success();
+    let brewfile = home.path().join("Brewfile");
+    std::fs::write(&brewfile, "tap \"homebrew/bundle\"\nbrew \"jq\"\nbrew \"wget\", args: [\"HEAD\"]\ncask \"firefox\"\n").unwrap();
+
+    let output = macsweep(&home).args(["--format", "json", "compare", "--brewfile"]).arg(&brewfile).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names = |section: &str| -> Vec<String> {
+        json[section].as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(names("extra"), ["htop", "slack"]);
+    assert_eq!(names("missing"), ["wget", "firefox"]);
+    assert_eq!(json["missing"][1]["kind"], "cask");
+    assert_eq!(json["matched"], 1);
+
+    macsweep(&home)
+        .args(["compare", "--brewfile"])
+        .arg(&brewfile)
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Installed but not in the Brewfile (2)").and(predicates::str::contains("In the Brewfile but not installed (2)")));
+
+    // Recently used, so nothing is recommended, but the Brewfile decides; slack is kept by its tag
+    let output = macsweep(&home).args(["--format", "json", "clean", "--dry-run", "--not-in-brewfile"]).arg(&brewfile).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let planned: Vec<&str> = json["packages"].as_array().unwrap().iter().map(|p| p["package"].as_str().unwrap()).collect();
+    assert_eq!(planned, ["htop"]);
+
+    macsweep(&home).args(["compare", "--brewfile"]).arg(home.path().join("missing")).assert().code(1);
+}
+
+#[test]
+fn test_scan_notify_posts_when_reclaimable_space_is_over_the_threshold() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = TempDir::new().unwrap();
+    let seed = |name: &str, size: u64| {
+        let db = Database::open(&db_path(&home)).unwrap();
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.size_bytes = Some(size);
+        package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(400));
+        database::upsert_package(db.conn(), &package).unwrap();
+    };
+    let config = home.path().join(".config/macsweep/config.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "[notifications]\nthreshold = \"2MB\"\n").unwrap();
+    // An empty ~/Applications gives the scan a source to save, leaving the seeded formulae alone
+    std::fs::create_dir_all(home.path().join("Applications")).unwrap();
+
+    // A stand-in for osascript that records its arguments, or fails when asked to
+    let bin = home.path().join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let log = home.path().join("notifications");
+    let script = format!(
+        "#!/bin/sh\n[ -e \"{0}.fail\" ] && exit 1\nprintf '%s\\n' \"$@\" >> \"{0}\"\n",
+        log.display()
+    );
+    std::fs::write(bin.join("osascript"), script).unwrap();
+    std::fs::set_permissions(bin.join("osascript"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+    let scan = |notify: bool| {
+        let mut cmd = macsweep(&home);
+        cmd.env("PATH", &path).args(["scan", "--quick", "--source", "applications"]);
+        if notify {
+            cmd.arg("--notify");
+        }
+        let output = cmd.output().unwrap();
+        assert!(scan_finished(output.status), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let notifications = || std::fs::read_to_string(&log).unwrap_or_default();
+
+    // 1 MB is under the threshold
+    seed("fortune", 1024 * 1024);
+    scan(true);
+    assert_eq!(notifications(), "");
+
+    seed("cowsay", 2 * 1024 * 1024);
+    scan(true);
+    assert_eq!(
+        notifications(),
+        "-e\ndisplay notification \"3.0 MB reclaimable across 2 packages (+2.0 MB since the last scan)\" with title \"macsweep\"\n"
+    );
+
+    // Nothing new to clean up, and without --notify nothing is posted or recorded
+    std::fs::write(&log, "").unwrap();
+    scan(true);
+    seed("sl", 2 * 1024 * 1024);
+    scan(false);
+    assert_eq!(notifications(), "");
+    seed("figlet", 8 * 1024 * 1024);
+    scan(true);
+    assert!(notifications().contains("\"13.0 MB reclaimable across 4 packages (+10.0 MB since the last scan)\""), "{}", notifications());
+
+    // Failing to post is only a warning
+    std::fs::write(home.path().join("notifications.fail"), "").unwrap();
+    seed("lolcat", 4 * 1024 * 1024);
+    assert!(scan(true).contains("couldn't post a notification"));
+}
+
+#[test]
+fn test_source_takes_several_values() {
+    let home = TempDir::new().unwrap();
+    seed_snapshot_packages(&home);
+    let names = |args: &[&str]| -> Vec<String> {
+        let output = macsweep(&home).args(["--format", "json", "list", "--sort", "name"]).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        json.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect()
+    };
+
+    assert_eq!(names(&["--source", "python"]), ["httpie"]);
+    assert_eq!(names(&["--source", "brew,pipx"]), ["httpie"]);
+    assert_eq!(names(&["--source", "npm", "--source", "python"]), ["httpie", "is-odd", "left-pad"]);
+
+    // One mistyped value fails the whole command rather than being skipped
+    macsweep(&home)
+        .args(["clean", "--dry-run", "--source", "npm,pyhton"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Unknown source 'pyhton' (did you mean 'python'?)"));
+}
+
+#[test]
+fn test_quiet_suppresses_status() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--quiet", "--format", "json", "scan", "--quick", "--source", "applications"])
+        .output()
+        .unwrap();
+
+    assert!(scan_finished(output.status));
+    assert!(output.stderr.is_empty());
+    serde_json::from_slice::<serde_json::Value>(&output.stdout).unwrap();
+}
+
+#[test]
+fn test_verbose_raises_log_level() {
+    let home = TempDir::new().unwrap();
+    std::fs::create_dir_all(home.path().join("Applications")).unwrap();
+    let stderr = |args: &[&str], rust_log: Option<&str>| {
+        let mut cmd = macsweep(&home);
+        cmd.env_remove("RUST_LOG").args(args).args(["scan", "--quick", "--source", "applications"]);
+        if let Some(filter) = rust_log {
+            cmd.env("RUST_LOG", filter);
+        }
+        let output = cmd.output().unwrap();
+        assert!(scan_finished(output.status));
+        String::from_utf8(output.stderr).unwrap()
+    };
+
+    let quiet = stderr(&[], None);
+    assert!(!quiet.contains(" INFO ") && !quiet.contains("DEBUG"), "{}", quiet);
+    let info = stderr(&["-v"], None);
+    assert!(info.contains("Scanned source=\"applications\" packages=0"), "{}", info);
+    assert!(!info.contains("DEBUG"), "{}", info);
+
+    // -vv times each phase, and logs are plain text when stderr isn't a terminal
+    let debug = stderr(&["-vv"], None);
+    assert!(debug.contains("scanner{source=\"applications\"}: macsweep::scan: close time.busy="), "{}", debug);
+    assert!(debug.contains("save{packages=0}"), "{}", debug);
+    assert!(!debug.contains('\x1b'));
+
+    // RUST_LOG takes precedence over -v
+    assert!(!stderr(&["-vv"], Some("off")).contains("DEBUG"));
+    assert!(stderr(&[], Some("macsweep=debug")).contains("DEBUG"));
+}
+
+#[test]
+fn test_scan_jsonl_is_single_line() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--quiet", "--format", "jsonl", "scan", "--quick", "--source", "applications"])
+        .output()
+        .unwrap();
+
+    assert!(scan_finished(output.status));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    serde_json::from_str::<serde_json::Value>(stdout.trim()).unwrap();
+}
+
+#[test]
+fn test_list_rejects_unknown_column() {
+    let home = TempDir::new().unwrap();
+    macsweep(&home)
+        .args(["list", "--columns", "name,colour"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("usage_count"));
+}
+
+#[test]
+fn test_exit_code_nothing_to_do() {
+    let home = TempDir::new().unwrap();
+    macsweep(&home).args(["clean", "--dry-run"]).assert().code(3);
+    macsweep(&home).args(["undo"]).assert().code(3);
+}
+
+#[test]
+fn test_exit_code_cancelled() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    macsweep(&home)
+        .args(["clean"])
+        .write_stdin("n\n")
+        .assert()
+        .code(4)
+        .stdout(predicates::str::contains("left-pad"));
+}
+
+#[test]
+fn test_post_clean_hooks() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let mut package = Package::new("fortune".to_string(), PackageSource::Homebrew);
+    package.size_bytes = Some(4096);
+    package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(400));
+    database::upsert_package(db.conn(), &package).unwrap();
+    drop(db);
+    let config = home.path().join(".config/macsweep/config.toml");
+    std::fs::create_dir_all(config.parent().unwrap()).unwrap();
+    std::fs::write(&config, "[hooks]\npost_clean_command = \"cat > \\\"$HOME/report.json\\\"\"\n").unwrap();
+    // Without brew on PATH the removal fails, which the report records like any other outcome
+    let clean = |args: &[&str]| macsweep(&home).env("PATH", "/usr/bin:/bin").args(["clean"]).args(args).output().unwrap();
+
+    assert_eq!(clean(&["--dry-run"]).status.code(), Some(0));
+    assert!(!home.path().join("report.json").exists());
+
+    let output = clean(&["--yes"]);
+    assert_eq!(output.status.code(), Some(2), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(home.path().join("report.json")).unwrap()).unwrap();
+    assert_eq!((report["attempted"].as_u64(), report["failed"].as_u64()), (Some(1), Some(1)));
+    assert_eq!(report["items"][0]["package"], "fortune");
+    assert_eq!(report["items"][0]["status"], "failed");
+
+    // A failing hook is only logged, unless the hooks are required
+    std::fs::write(&config, "[hooks]\npost_clean_command = \"exit 7\"\n").unwrap();
+    let output = clean(&["--yes", "--retry-failed"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Post-clean hook failed"));
+    std::fs::write(&config, "[hooks]\npost_clean_command = \"exit 7\"\nrequired = true\n").unwrap();
+    let output = clean(&["--yes", "--retry-failed"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("a required hook failed: post_clean_command: exit 7 failed: exit status: 7"));
+}
+
+#[test]
+fn test_plugin_scanner() {
+    let home = TempDir::new().unwrap();
+    let plugins = home.path().join(".config/macsweep/plugins");
+    std::fs::create_dir_all(&plugins).unwrap();
+    std::fs::copy("tests/fixtures/plugins/corp-tools", plugins.join("corp-tools")).unwrap();
+    let state = home.path().join("corp-tools.txt");
+    let scan = || {
+        let output = macsweep(&home).args(["scan", "--quick", "--source", "corp-tools"]).output().unwrap();
+        assert!(scan_finished(output.status), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let listed = || -> Vec<(String, String)> {
+        let output = macsweep(&home).args(["--format", "json", "list", "--source", "corp-tools"]).output().unwrap();
+        let packages: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let name = |p: &serde_json::Value| p["name"].as_str().unwrap().to_string();
+        packages.as_array().unwrap().iter().map(|p| (name(p), p["source"].as_str().unwrap().to_string())).collect()
+    };
+
+    // Without its state file the fixture's tool isn't installed
+    assert!(scan().contains("corp-tools (plugin) (not installed)"));
+
+    std::fs::write(&state, "deployer 2.1.0 524288000
+sdk-cli 0.9.1 1024
+").unwrap();
+    scan();
+    let source = "plugin:corp-tools".to_string();
+    assert_eq!(listed(), [("deployer".to_string(), source.clone()), ("sdk-cli".to_string(), source.clone())]);
+
+    // The 500 MB package without usage data is recommended, and the plugin removes it
+    macsweep(&home)
+        .args(["clean", "--dry-run", "--source", "corp-tools"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("deployer").and(predicates::str::contains("sdk-cli").not()));
+    macsweep(&home).args(["clean", "--yes", "--source", "corp-tools"]).assert().success();
+    assert_eq!(std::fs::read_to_string(&state).unwrap(), "sdk-cli 0.9.1 1024\n");
+    scan();
+    assert_eq!(listed(), [("sdk-cli".to_string(), source)]);
+
+    // A plugin printing something other than packages fails its scan but keeps its packages
+    std::fs::write(plugins.join("corp-tools"), "#!/bin/sh\necho 'not json'\n").unwrap();
+    assert!(scan().contains("printed an invalid package on line 1"));
+    assert_eq!(listed().len(), 1);
+}
+
+#[test]
+fn test_exit_code_success() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    macsweep(&home).args(["clean", "--dry-run"]).assert().code(0);
+    macsweep(&home).args(["list"]).assert().code(0);
+}
+
+#[test]
+fn test_list_tsv_and_delimiter() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    let output = macsweep(&home).args(["--format", "tsv", "list"]).output().unwrap();
+    assert!(output.status.success());
+    let tsv = String::from_utf8(output.stdout).unwrap();
+    let mut lines = tsv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("name\tsource\tversion\tsize_bytes\tinstall_date\tlast_used\tusage_count\tis_dependency\tbinary_path\tstatus")
+    );
+    assert!(lines.next().unwrap().starts_with("left-pad\tnpm\t"));
+
+    let path = home.path().join("packages.csv");
+    macsweep(&home)
+        .args(["--format", "csv", "--delimiter", ";", "list", "--columns", "name,source,size", "--output"])
+        .arg(&path)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "name;source;size_bytes\nleft-pad;npm;4096\n");
+
+    macsweep(&home)
+        .args(["--format", "json", "--delimiter", ";", "list"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--delimiter only applies to --format csv"));
+    macsweep(&home)
+        .args(["--format", "csv", "--delimiter", "ab", "list"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Invalid delimiter 'ab'"));
+}
+
+#[test]
+fn test_plist_output() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+    let parse = |bytes: &[u8]| plist::Value::from_reader(std::io::Cursor::new(bytes.to_vec())).unwrap();
+
+    let output = macsweep(&home).args(["--format", "plist", "list"]).output().unwrap();
+    assert!(output.status.success());
+    let list = parse(&output.stdout);
+    let package = list.as_array().unwrap()[0].as_dictionary().unwrap();
+    assert_eq!(package["name"].as_string(), Some("left-pad"));
+    assert!(package["last_used"].as_date().is_some());
+
+    let output = macsweep(&home).args(["--format", "plist", "info", "left-pad"]).output().unwrap();
+    assert!(output.status.success());
+    let info = parse(&output.stdout);
+    assert_eq!(info.as_array().unwrap()[0].as_dictionary().unwrap()["size_bytes"].as_unsigned_integer(), Some(4096));
+
+    let path = home.path().join("packages.plist");
+    macsweep(&home).args(["--binary-plist", "export", "--plist", "--output"]).arg(&path).assert().success();
+    let bytes = std::fs::read(&path).unwrap();
+    assert!(bytes.starts_with(b"bplist00"));
+    assert_eq!(parse(&bytes).as_array().unwrap()[0].as_dictionary().unwrap()["source"].as_string(), Some("npm"));
+
+    macsweep(&home)
+        .args(["--binary-plist", "list"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("--binary-plist only applies to --format plist"));
+}
+
+#[test]
+fn test_list_output_file() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+    let path = home.path().join("packages.csv");
+
+    macsweep(&home)
+        .args(["--format", "csv", "list", "--output"])
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(predicates::str::contains("Wrote 1 packages"));
+
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert!(csv.starts_with("name,source,version"));
+    assert!(csv.contains("left-pad"));
+
+    // Existing files are only replaced with --force
+    macsweep(&home).args(["list", "--output"]).arg(&path).assert().failure();
+    macsweep(&home).args(["list", "--force", "--output"]).arg(&path).assert().success();
+
+    let table = std::fs::read_to_string(&path).unwrap();
+    assert!(table.contains("left-pad"));
+    assert!(!table.contains('\u{1b}'));
+    assert!(!table.contains("Total:"));
+}
+
+#[test]
+fn test_list_status_and_recommended_only() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "list", "--recommended-only"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["name"], "left-pad");
+    assert_eq!(json[0]["status"], "Review");
+
+    // Pinned packages are listed but never count as recommended
+    macsweep(&home).args(["tag", "left-pad", "keep"]).assert().success();
+
+    let output = macsweep(&home).args(["--format", "json", "list"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["status"], "Pinned");
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "list", "--recommended-only"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn test_thresholds_override() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    let status = |thresholds: &str| {
+        let output = macsweep(&home)
+            .args(["--format", "json", "--thresholds", thresholds, "list"])
+            .output()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        json[0]["status"].clone()
+    };
+    assert_eq!(status("safe=180"), "Safe");
+    assert_eq!(status("warning=100,review=365"), "Warning");
+    assert_eq!(status("warning=300,review=365"), serde_json::Value::Null);
+
+    macsweep(&home)
+        .args(["--thresholds", "review=10", "list"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("must be below the review threshold"));
+}
+
+#[test]
+fn test_why_explains_recommendation() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+    let db = Database::open(&db_path(&home)).unwrap();
+    let id = database::get_package_id(db.conn(), "left-pad", &PackageSource::Npm).unwrap().unwrap();
+    let checks = [macsweep::usage::UsageCheck::new("shell", false, "no uses across 3 history files")];
+    database::save_usage_checks(db.conn(), id, &checks).unwrap();
+
+    let output = macsweep(&home).args(["--format", "json", "why", "left-pad"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["usage_checks"][0]["result"], "no uses across 3 history files");
+    assert_eq!(json[0]["orphaned"], false);
+    assert_eq!(json[0]["thresholds"]["review_days"], 90);
+    assert_eq!(json[0]["recommendation"]["severity"], "Review");
+
+    macsweep(&home)
+        .args(["--thresholds", "warning=300,review=365", "why", "left-pad"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("✗ Shell history: no uses across 3 history files"))
+        .stdout(predicates::str::contains("Warning after 300 days unused, Review after 365"))
+        .stdout(predicates::str::contains("no recommendation (used within the warning threshold)"));
+}
+
+#[test]
+fn test_doctor_reports_broken_links() {
+    let home = TempDir::new().unwrap();
+    let bin = home.path().join(".local/bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::os::unix::fs::symlink("/nonexistent/pipx/venvs/httpie/bin/http", bin.join("http")).unwrap();
+
+    let output = macsweep(&home).args(["--format", "json", "doctor"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let link = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|l| l["path"] == bin.join("http").to_str().unwrap())
+        .unwrap();
+    assert_eq!(link["package"], "httpie");
+    assert_eq!(link["source"], "pipx");
+
+    macsweep(&home)
+        .args(["--format", "json", "doctor", "--fix"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("requires --dry-run"));
+
+    macsweep(&home).args(["doctor", "--fix", "--yes"]).assert().success();
+    assert!(bin.join("http").symlink_metadata().is_err());
+    let backups = home.path().join("Library/Application Support/macsweep/backups");
+    let manifest = std::fs::read_dir(&backups).unwrap().next().unwrap().unwrap().path();
+    let manifest: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(manifest).unwrap()).unwrap();
+    assert_eq!(manifest["links"][0]["path"], bin.join("http").to_str().unwrap());
+}
+
+#[test]
+fn test_deps_graph() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    for (name, dependencies) in [("wget", vec!["openssl@3", "libidn2"]), ("openssl@3", vec!["ca-certificates"])] {
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.dependencies = dependencies.into_iter().map(String::from).collect();
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+
+    macsweep(&home)
+        .args(["deps", "wget"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\"wget\" -> \"openssl@3\";"))
+        .stdout(predicates::str::contains("\"openssl@3\" -> \"ca-certificates\";"))
+        .stdout(predicates::str::contains("\"libidn2\" [label=\"libidn2\\nnot installed\", style=dashed];"));
+
+    let output = home.path().join("graph.mmd");
+    macsweep(&home)
+        .args(["deps", "openssl@3", "--reverse", "--format", "mermaid", "--output"])
+        .arg(&output)
+        .assert()
+        .success();
+    let mermaid = std::fs::read_to_string(&output).unwrap();
+    assert!(mermaid.contains("n1 --> n0"));
+    assert!(mermaid.contains("n1[\"wget<br/>"));
+
+    macsweep(&home).args(["deps", "curl"]).assert().failure().stderr(predicates::str::contains("not found"));
+}
+
+#[test]
+fn test_mark_used_and_never_used() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    macsweep(&home).args(["mark", "used", "left-pad", "--date", "2999-01-01"]).assert().failure();
+    macsweep(&home).args(["mark", "used", "right-pad"]).assert().failure();
+
+    macsweep(&home).args(["mark", "used", "left-pad"]).assert().success();
+    let output = macsweep(&home).args(["--format", "json", "info", "left-pad"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["manual_mark"]["used"], true);
+    assert_eq!(json[0]["usage_confidence"], "high");
+
+    let output = macsweep(&home).args(["--format", "json", "list", "--recommended-only"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 0);
+
+    macsweep(&home).args(["mark", "never-used", "left-pad"]).assert().success();
+    macsweep(&home)
+        .args(["info", "left-pad"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Last used: Never (marked manually)"));
+}
+
+#[test]
+fn test_db_path_flag_overrides_env() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    // A fresh database in a directory that doesn't exist yet is created on demand
+    let other = home.path().join("work/nested/work.db");
+    let output = macsweep(&home)
+        .args(["--format", "json", "list", "--db-path"])
+        .arg(&other)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("left-pad"));
+    assert!(other.exists());
+
+    // MACSWEEP_DB still points at the seeded database
+    let output = macsweep(&home).args(["--format", "json", "list"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["name"], "left-pad");
+}
+
+#[test]
+fn test_db_path_unusable_location() {
+    let home = TempDir::new().unwrap();
+    let blocker = home.path().join("not-a-dir");
+    std::fs::write(&blocker, b"").unwrap();
+
+    macsweep(&home)
+        .args(["list", "--db-path"])
+        .arg(blocker.join("macsweep.db"))
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("Cannot create database directory"));
+}
+
+#[test]
+fn test_scan_staleness() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+    let db = Database::open(&db_path(&home)).unwrap();
+    database::insert_scan(db.conn(), "full", 1, 10, 4096, &Default::default()).unwrap();
+
+    macsweep(&home)
+        .args(["scan", "--if-stale", "7"])
+        .assert()
+        .code(3)
+        .stderr(predicates::str::contains("skipping"));
+
+    macsweep(&home)
+        .args(["list"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Data from scan today"));
+
+    db.conn()
+        .execute("UPDATE scans SET scan_date = datetime('now', '-10 days')", [])
+        .unwrap();
+    macsweep(&home)
+        .args(["stats"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("Data from scan 1 week ago — consider rescanning"));
+}
+
+#[test]
+fn test_changed_scan_keeps_a_skipped_sources_packages() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = TempDir::new().unwrap();
+    let prefix = home.path().join("npm");
+    std::fs::create_dir_all(prefix.join("lib/node_modules/left-pad")).unwrap();
+    // A stand-in for npm listing whatever the `listing` file holds
+    let bin = home.path().join("bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    let listing = home.path().join("listing.json");
+    std::fs::write(bin.join("npm"), format!("#!/bin/sh
+cat \"{}\"\n", listing.display())).unwrap();
+    std::fs::set_permissions(bin.join("npm"), std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap_or_default());
+    let scan = |args: &[&str]| {
+        let output = macsweep(&home)
+            .env("PATH", &path)
+            .env("NPM_CONFIG_PREFIX", &prefix)
+            .args(["scan", "--quick", "--source", "npm"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(scan_finished(output.status), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    };
+    let listed = || {
+        let output = macsweep(&home).args(["--format", "json", "list", "--source", "npm"]).output().unwrap();
+        let packages: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        packages.as_array().unwrap().iter().map(|p| p["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+    };
+
+    std::fs::write(&listing, r#"{"dependencies": {"left-pad": {"version": "1.3.0"}}}"#).unwrap();
+    scan(&[]);
+    assert_eq!(listed(), ["left-pad"]);
+
+    // npm would now list nothing, but node_modules is untouched so npm isn't asked
+    std::fs::write(&listing, "{}").unwrap();
+    assert!(scan(&["--changed"]).contains("unchanged since the last scan"));
+    assert_eq!(listed(), ["left-pad"]);
+
+    scan(&[]);
+    macsweep(&home).args(["list", "--source", "npm"]).assert().stdout(predicates::str::contains("left-pad").not());
+}
+
+#[test]
+fn test_list_growing() {
+    let home = TempDir::new().unwrap();
+    let mut db = Database::open(&db_path(&home)).unwrap();
+
+    let mut small = Package::new("tsc".to_string(), PackageSource::Npm);
+    let mut big = Package::new("Xcode".to_string(), PackageSource::Applications);
+    for (small_size, big_size) in [(100, 5000), (900, 5100)] {
+        small.size_bytes = Some(small_size);
+        big.size_bytes = Some(big_size);
+        let tx = db.transaction().unwrap();
+        let scan_id = database::insert_scan(&tx, "full", 2, 10, 0, &Default::default()).unwrap();
+        database::upsert_packages(&tx, &[small.clone(), big.clone()], Some(scan_id)).unwrap();
+        tx.commit().unwrap();
+    }
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "list", "--growing"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["name"], "tsc");
+    assert_eq!(json[0]["size_growth"], 800);
+    assert_eq!(json[1]["size_growth"], 100);
+
+    macsweep(&home)
+        .args(["list", "--growing"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Growth"))
+        .stdout(predicates::str::contains("+800 B"));
+}
+
+#[test]
+fn test_list_pages_by_size() {
+    let home = TempDir::new().unwrap();
+    seed_snapshot_packages(&home);
+
+    let names = |args: &[&str]| -> Vec<String> {
+        let output = macsweep(&home).args(["--format", "jsonl", "list"]).args(args).output().unwrap();
+        String::from_utf8(output.stdout).unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(names(&["--large"]), ["httpie", "left-pad", "is-odd"]);
+    assert_eq!(names(&["--large", "--limit", "1", "--offset", "1"]), ["left-pad"]);
+    assert_eq!(names(&["--name", "PAD"]), ["left-pad"]);
+    // Paged after the recommendation filter, which runs once packages are loaded
+    assert_eq!(names(&["--sort", "size", "--recommended-only", "--offset", "2"]), ["is-odd"]);
+}
+
+#[test]
+fn test_retry_failed_and_undo_list() {
+    let home = TempDir::new().unwrap();
+    seed_unused_package(&home);
+
+    macsweep(&home)
+        .args(["clean", "--retry-failed", "--dry-run"])
+        .assert()
+        .code(3)
+        .stdout(predicates::str::contains("No failed removals to retry"));
+
+    let backups = home.path().join("Library/Application Support/macsweep/backups");
+    std::fs::create_dir_all(&backups).unwrap();
+    let manifest = backups.join("cleanup_20250101_120000.json");
+    std::fs::write(&manifest, r#"{"backup_id":"cleanup_20250101_120000","created_at":"2025-01-01T12:00:00Z","packages":[]}"#).unwrap();
+
+    let db = Database::open(&db_path(&home)).unwrap();
+    let failed = database::CleanupItem {
+        package: "left-pad".to_string(),
+        source: "Npm".to_string(),
+        size_bytes: Some(4096),
+        status: "failed".to_string(),
+        error_message: Some("npm ERR! EACCES: permission denied".to_string()),
+        exit_code: Some(243),
+        duration_ms: Some(800),
+        action: "remove".to_string(),
+    };
+    database::record_cleanup(db.conn(), &manifest.to_string_lossy(), &[failed]).unwrap();
+
+    macsweep(&home)
+        .args(["clean", "--retry-failed", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("left-pad"))
+        .stdout(predicates::str::contains("Retry failed removal: npm ERR! EACCES"));
+
+    macsweep(&home)
+        .args(["undo", "--list"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("(0 removed, 1 failed)"))
+        .stdout(predicates::str::contains("left-pad (Npm): npm ERR! EACCES"));
+}
+
+#[test]
+fn test_clean_dry_run_predicts_autoremove() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+
+    let mut ffmpeg = Package::new("ffmpeg".to_string(), PackageSource::Homebrew);
+    ffmpeg.size_bytes = Some(4096);
+    ffmpeg.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+    ffmpeg.dependencies = vec!["x264".to_string()];
+    database::upsert_package(db.conn(), &ffmpeg).unwrap();
+
+    let mut x264 = Package::new("x264".to_string(), PackageSource::Homebrew);
+    x264.size_bytes = Some(1024);
+    x264.is_dependency = true;
+    x264.last_used = Some(chrono::Utc::now());
+    database::upsert_package(db.conn(), &x264).unwrap();
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "clean", "--dry-run", "--autoremove"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["packages"][0]["package"], "ffmpeg");
+    assert_eq!(json["autoremove"][0]["package"], "x264");
+    assert_eq!(json["total_size_bytes"], 5120);
+
+    // Without the flag nothing cascades
+    let output = macsweep(&home).args(["--format", "json", "clean", "--dry-run"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(json.get("autoremove").is_none());
+}
+
+#[test]
+fn test_clean_dry_run_plans_the_recommended_source() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    for (source, days) in [(PackageSource::Homebrew, 1), (PackageSource::HomebrewCask, 200)] {
+        let mut package = Package::new("docker".to_string(), source);
+        package.size_bytes = Some(4096);
+        package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(days));
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+
+    let output = macsweep(&home).args(["--format", "json", "clean", "--dry-run"]).output().unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let planned = json["packages"].as_array().unwrap();
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0]["source"], "homebrewcask");
+}
+
+#[test]
+fn test_save_and_apply_plan() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let plan = home.path().join("plan.json");
+
+    for (name, version) in [("jq", "1.7.1"), ("wget", "1.21.4")] {
+        let mut package = Package::new(name.to_string(), PackageSource::Homebrew);
+        package.version = Some(version.to_string());
+        package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+
+    macsweep(&home)
+        .args(["clean", "--dry-run", "--save-plan", plan.to_str().unwrap()])
+        .assert()
+        .success();
+    let saved: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&plan).unwrap()).unwrap();
+    assert_eq!(saved["schema_version"], 1);
+    assert_eq!(saved["macsweep_version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(saved["packages"].as_array().unwrap().len(), 2);
+
+    // wget is upgraded after the plan was reviewed
+    let mut wget = Package::new("wget".to_string(), PackageSource::Homebrew);
+    wget.version = Some("1.24.5".to_string());
+    wget.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+    database::upsert_package(db.conn(), &wget).unwrap();
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "clean", "--dry-run", "--plan", plan.to_str().unwrap()])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let names: Vec<_> = json["packages"].as_array().unwrap().iter().map(|p| p["package"].as_str().unwrap()).collect();
+    assert_eq!(names, ["jq"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("version changed from 1.21.4 to 1.24.5"));
+
+    let output = macsweep(&home)
+        .args(["--format", "json", "clean", "--dry-run", "--plan", plan.to_str().unwrap(), "--allow-drift"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["total_packages"], 2);
+
+    // Saving needs a dry run
+    macsweep(&home).args(["clean", "--save-plan", plan.to_str().unwrap()]).assert().failure();
+}
+
+#[test]
+fn test_selective_and_dry_run_undo() {
+    let home = TempDir::new().unwrap();
+    let trash = home.path().join(".Trash");
+    let apps = home.path().join("Applications");
+    std::fs::create_dir_all(trash.join("Foo.app")).unwrap();
+    std::fs::create_dir_all(trash.join("Bar.app")).unwrap();
+    std::fs::create_dir_all(&apps).unwrap();
+
+    let backups = home.path().join("Library/Application Support/macsweep/backups");
+    std::fs::create_dir_all(&backups).unwrap();
+    let app = |name: &str| serde_json::json!({
+        "name": name,
+        "source": "Applications",
+        "version": "2.0",
+        "binary_path": apps.join(format!("{}.app", name)),
+        "size_bytes": 1024,
+        "trashed_path": trash.join(format!("{}.app", name)),
+    });
+    let manifest = serde_json::json!({
+        "backup_id": "cleanup_20250101_120000",
+        "created_at": "2025-01-01T12:00:00Z",
+        "packages": [app("Foo"), app("Bar")],
+    });
+    std::fs::write(backups.join("cleanup_20250101_120000.json"), manifest.to_string()).unwrap();
+
+    macsweep(&home)
+        .args(["undo", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Would restore: 2"))
+        .stdout(predicates::str::contains(format!("move {} back to", trash.join("Foo.app").display())));
+    assert!(trash.join("Foo.app").exists());
+
+    macsweep(&home)
+        .args(["undo", "--packages", "nope"])
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Not in backup cleanup_20250101_120000: nope"));
+
+    macsweep(&home)
+        .args(["undo", "--packages", "foo"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("✓ 2.0 in"))
+        .stdout(predicates::str::contains("Not selected: 1"));
+    assert!(apps.join("Foo.app").exists());
+    assert!(trash.join("Bar.app").exists());
+
+    // The restored app is listed without a rescan
+    macsweep(&home)
+        .args(["list"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Foo"))
+        .stdout(predicates::str::contains("Bar").not());
+}
+
+#[test]
+fn test_clean_plan_grouped_by_source() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    for idx in 0..22 {
+        let mut package = Package::new(format!("npm-tool-{:02}", idx), PackageSource::Npm);
+        package.size_bytes = Some(1024 * (idx + 1));
+        package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+    let mut wheel = Package::new("old-wheel".to_string(), PackageSource::Pip);
+    wheel.size_bytes = Some(1024);
+    wheel.last_used = Some(chrono::Utc::now() - chrono::Duration::days(200));
+    database::upsert_package(db.conn(), &wheel).unwrap();
+
+    let output = macsweep(&home).args(["clean", "--dry-run"]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let npm = stdout.find("Npm (22 packages, 253.0 KB)").unwrap();
+    let pip = stdout.find("Pip (1 package, 1.0 KB)").unwrap();
+    assert!(npm < pip);
+    // Largest first within the group, cut off after 20
+    assert!(stdout.find("npm-tool-21").unwrap() < stdout.find("npm-tool-20").unwrap());
+    assert!(!stdout.contains("npm-tool-00"));
+    assert!(stdout.contains("... and 2 more (--show-all to list them)"));
+
+    macsweep(&home)
+        .args(["clean", "--dry-run", "--show-all"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("npm-tool-00").and(predicates::str::contains("more (--show-all").not()));
+}
+
+/// Store packages unused for 400 days, one per source given
+fn seed_snapshot_packages(home: &TempDir) {
+    let db = Database::open(&db_path(home)).unwrap();
+    for (name, source, size) in [("left-pad", PackageSource::Npm, 4096), ("is-odd", PackageSource::Npm, 2048), ("httpie", PackageSource::Pipx, 9_000_000)] {
+        let mut package = Package::new(name.to_string(), source);
+        package.size_bytes = Some(size);
+        package.last_used = Some(chrono::Utc::now() - chrono::Duration::days(400));
+        database::upsert_package(db.conn(), &package).unwrap();
+    }
+}
+
+#[test]
+fn test_snapshot_clean_dry_run() {
+    let home = TempDir::new().unwrap();
+    seed_snapshot_packages(&home);
+
+    let output = macsweep(&home).args(["clean", "--dry-run"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "\
+Packages to remove:
+  Total: 3
+  Potential space savings: 8.6 MB
+
+  Pipx (1 package, 8.6 MB)
+    ⚠ httpie - Not used in 400 days (~13 months) (8.6 MB)
+  Npm (2 packages, 6.0 KB)
+    ⚠ left-pad - Not used in 400 days (~13 months) (4.0 KB)
+    ⚠ is-odd - Not used in 400 days (~13 months) (2.0 KB)
+
+[DRY RUN MODE] - No packages will be removed
+Run without --dry-run to actually remove packages.
+
+
+Cleanup Summary:
+  Would remove: 3
+  Would recover: 8.6 MB
+"
+    );
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "🧹 MacSweep Cleanup\n\n\nStarting cleanup...\n");
+}
+
+#[test]
+fn test_snapshot_clean_without_packages() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home).args(["clean"]).output().unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "No packages found. Run macsweep scan first.\n");
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "🧹 MacSweep Cleanup\n\n");
+}
+
+/// A backup of three apps, one still in the Trash, one whose Trash was emptied and one
+/// reinstalled since, and of a broken link; `undo` snapshots replace `{home}` with the home
+fn seed_snapshot_backup(home: &TempDir) {
+    let root = home.path();
+    std::fs::create_dir_all(root.join(".Trash/Foo.app")).unwrap();
+    std::fs::create_dir_all(root.join("Applications/Baz.app")).unwrap();
+    std::fs::create_dir_all(root.join("bin")).unwrap();
+    let app = |name: &str, version: Option<&str>, trashed: bool| {
+        let mut app = serde_json::json!({
+            "name": name,
+            "source": "Applications",
+            "version": version,
+            "binary_path": root.join(format!("Applications/{}.app", name)),
+            "size_bytes": 1024,
+        });
+        if trashed {
+            app["trashed_path"] = serde_json::json!(root.join(format!(".Trash/{}.app", name)));
+        }
+        app
+    };
+    let manifest = serde_json::json!({
+        "backup_id": "cleanup_20250101_120000",
+        "created_at": "2025-01-01T12:00:00Z",
+        "packages": [app("Foo", Some("2.0"), true), app("Bar", None, true), app("Baz", Some("1.1"), false)],
+        "links": [{"path": root.join("bin/http"), "target": root.join("gone/http")}],
+    });
+    let backups = root.join("Library/Application Support/macsweep/backups");
+    std::fs::create_dir_all(&backups).unwrap();
+    std::fs::write(backups.join("cleanup_20250101_120000.json"), manifest.to_string()).unwrap();
+}
+
+#[test]
+fn test_snapshot_undo() {
+    let home = TempDir::new().unwrap();
+    seed_snapshot_backup(&home);
+    let undo = |args: &[&str]| {
+        let output = macsweep(&home).args(["undo", "cleanup_20250101_120000"]).args(args).output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap().replace(home.path().to_str().unwrap(), "{home}");
+        (output.status.code(), stdout)
+    };
+
+    assert_eq!(
+        undo(&["--verify"]),
+        (
+            Some(0),
+            "\
+🔍 Verifying backup: cleanup_20250101_120000
+   Created: 2025-01-01T12:00:00Z
+   Packages: 3
+   Links: 1
+
+  Foo (applications) - ✓ restorable
+  Bar (applications) - ✗ impossible: moved to Trash and the Trash was emptied
+  Baz (applications) - ⚠ already installed
+  {home}/bin/http -> {home}/gone/http - ✓ restorable
+
+📊 Verification Summary:
+   Restorable: 1
+   Already installed: 1
+   Impossible: 1
+"
+            .to_string()
+        )
+    );
+
+    assert_eq!(
+        undo(&["--dry-run"]),
+        (
+            Some(0),
+            "\
+🔍 Would restore from backup: cleanup_20250101_120000
+   Created: 2025-01-01T12:00:00Z
+   Packages: 3
+   Links: 1
+
+  Foo 2.0 (applications): move {home}/.Trash/Foo.app back to {home}/Applications/Foo.app
+  Bar (applications): ✗ Skipped: moved to Trash and the Trash was emptied
+  Baz 1.1 (applications): ⚠ Already installed
+  {home}/bin/http -> {home}/gone/http: ln -s
+
+📊 Restore Summary:
+   Would restore: 2
+   Already installed: 1
+   Skipped: 1
+"
+            .to_string()
+        )
+    );
+
+    // Only how long the move took varies
+    let (code, stdout) = undo(&[]);
+    let (before, after) = stdout.split_once("✓ 2.0 in ").unwrap();
+    assert_eq!(code, Some(2));
+    assert_eq!(
+        before,
+        "\
+🔄 Restoring from backup: cleanup_20250101_120000
+   Created: 2025-01-01T12:00:00Z
+   Packages: 3
+   Links: 1
+
+  Restoring Foo 2.0 (applications)... "
+    );
+    assert_eq!(
+        after.split_once('\n').unwrap().1,
+        "  Restoring Bar (applications)... ✗ Skipped: moved to Trash and the Trash was emptied
+  Restoring Baz 1.1 (applications)... ⚠ Already installed
+  {home}/bin/http -> {home}/gone/http: ✓ Recreated
+
+📊 Restore Summary:
+   Restored: 2
+   Already installed: 1
+   Skipped: 1
+"
+    );
+}
+
+#[test]
+fn test_size_measures_the_package_again() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let app = home.path().join("Slack.app");
+    std::fs::create_dir_all(app.join("Contents")).unwrap();
+    std::fs::write(app.join("Contents/Slack"), vec![0u8; 2048]).unwrap();
+    let mut package = Package::new("Slack".to_string(), PackageSource::Applications);
+    package.size_bytes = Some(1024);
+    package.size_path = Some(app.clone());
+    database::upsert_package(db.conn(), &package).unwrap();
+
+    let output = macsweep(&home).args(["--format", "json", "size", "Slack"]).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json[0]["size_bytes"], 2048);
+    assert_eq!(json[0]["previous_size_bytes"], 1024);
+    assert_eq!(json[0]["source"], "applications");
+
+    assert_eq!(database::get_packages(db.conn()).unwrap()[0].size_bytes, Some(2048));
+    assert_eq!(database::get_directory_sizes(db.conn()).unwrap()[&app].size_bytes, 2048);
+
+    macsweep(&home).args(["size", "nope"]).assert().code(1).stderr(predicates::str::contains("Package nope not found"));
+}
+
+#[test]
+fn test_which_names_the_owning_package() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let cellar = home.path().join("homebrew/Cellar/terraform/1.7.0/bin");
+    std::fs::create_dir_all(&cellar).unwrap();
+    std::fs::write(cellar.join("terraform"), vec![0u8; 512]).unwrap();
+    let bin = home.path().join("homebrew/bin");
+    std::fs::create_dir_all(&bin).unwrap();
+    std::os::unix::fs::symlink(cellar.join("terraform"), bin.join("terraform")).unwrap();
+    std::fs::write(bin.join("mystery"), vec![0u8; 64]).unwrap();
+
+    let mut terraform = Package::new("terraform".to_string(), PackageSource::Homebrew);
+    terraform.version = Some("1.7.0".to_string());
+    database::upsert_package(db.conn(), &terraform).unwrap();
+
+    let output = macsweep(&home).args(["--format", "json", "which"]).arg(bin.join("terraform")).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json["package"]["name"], "terraform");
+    assert_eq!(json["package"]["source"], "homebrew");
+    assert_eq!(json["package"]["matched_by"], "install-location");
+    assert_eq!(json["package"]["version"], "1.7.0");
+    assert_eq!(json["size_bytes"], 512);
+
+    macsweep(&home)
+        .arg("which")
+        .arg(bin.join("mystery"))
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("Not claimed by any package").and(predicates::str::contains("Size: 64 B")));
+    macsweep(&home)
+        .args(["which", "macsweep-no-such-program"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("macsweep-no-such-program is not on PATH"));
+}
+
+#[test]
+fn test_size_missing_fills_in_unmeasured_packages() {
+    let home = TempDir::new().unwrap();
+    let db = Database::open(&db_path(&home)).unwrap();
+    let app = home.path().join("Blender.app");
+    std::fs::create_dir_all(app.join("Contents")).unwrap();
+    std::fs::write(app.join("Contents/Blender"), vec![0u8; 4096]).unwrap();
+    let mut unmeasured = Package::new("Blender".to_string(), PackageSource::Applications);
+    unmeasured.size_path = Some(app.clone());
+    database::upsert_package(db.conn(), &unmeasured).unwrap();
+    seed_unused_package(&home);
+
+    // A never-used package without a size can't be judged by the large-package rule
+    macsweep(&home)
+        .args(["clean", "--dry-run"])
+        .assert()
+        .success()
+        .stderr(predicates::str::contains("1 never-used packages have no recorded size"));
+    macsweep(&home)
+        .args(["why", "Blender"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("no recorded size to judge it by"));
+
+    let output = macsweep(&home).args(["--format", "json", "size", "--missing"]).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["name"], "Blender");
+    assert_eq!(json[0]["size_bytes"], 4096);
+
+    macsweep(&home)
+        .args(["size", "--missing"])
+        .assert()
+        .success()
+        .stdout("Every package with a directory to measure has a recorded size.\n");
+    macsweep(&home).args(["scan", "--no-size", "--recalculate-sizes"]).assert().code(2);
+}
+
+#[test]
+fn test_snapshot_scan_json_fields() {
+    let home = TempDir::new().unwrap();
+    let output = macsweep(&home)
+        .args(["--format", "json", "scan", "--quick", "--source", "npm"])
+        .output()
+        .unwrap();
+
+    assert!(scan_finished(output.status));
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    // Keys come back sorted
+    let fields: Vec<_> = json.as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(
+        fields,
+        [
+            "duration_ms", "removed", "saved", "scan_duration_ms", "sizes_measured", "sizes_reused", "source_counts", "sources",
+            "total_packages", "total_size_bytes", "usage_duration_ms", "usage_speedup", "warnings",
+        ]
+    );
+    let source_fields: Vec<_> = json["sources"][0].as_object().unwrap().keys().map(String::as_str).collect();
+    assert_eq!(source_fields, ["available", "error", "packages", "skipped", "source"]);
+}